@@ -0,0 +1,260 @@
+// src/api.rs
+// JSON REST API of serve mode - lets scripts and other apps use the library
+//
+// Every route lives under /api:
+// - GET  /api                       -> library name, book count, version
+// - GET  /api/books?q=&offset=&limit= -> books matching a filter expression
+//                                      (same syntax as the TUI filter), by title
+// - GET  /api/books/<id>            -> everything stored about a book
+// - GET  /api/books/<id>/file       -> the EPUB file
+// - POST /api/scan                  -> rescans the scan roots, saves the library
+//
+// A book's id is its position in the library, as in the download links of
+// the web page; a scan can change them. Errors are answered as
+// {"error": "..."} with a 4xx status.
+
+use crate::book::{Book, ReadingStatus};
+use crate::export::ExportedBook;
+use crate::filter::Filter;
+use crate::metrics::Metrics;
+use crate::profile::Profile;
+use crate::server::{ascii_filename, header};
+use serde::Serialize;
+use std::fs::File;
+use std::io;
+use tiny_http::{Method, Request, Response};
+
+/// Path prefix of the API
+pub const API_BASE: &str = "/api";
+
+/// Books per page of /api/books when no `limit` is given
+const DEFAULT_LIMIT: usize = 100;
+
+/// What GET /api answers
+#[derive(Serialize)]
+struct LibraryInfo<'a> {
+    library: &'a str,
+    books: usize,
+    version: &'a str,
+}
+
+/// A page of GET /api/books
+#[derive(Serialize)]
+struct BookPage {
+    /// Books matching the filter, in all
+    total: usize,
+
+    /// Index of the first book of this page among the matching ones
+    offset: usize,
+
+    books: Vec<BookSummary>,
+}
+
+/// A book in a list: the fields a list shows
+#[derive(Serialize)]
+struct BookSummary {
+    id: usize,
+    title: String,
+    authors: Vec<String>,
+    series: Option<String>,
+    series_index: Option<f32>,
+    status: Option<ReadingStatus>,
+    rating: Option<u8>,
+    starred: bool,
+    tags: Vec<String>,
+
+    /// Path of the EPUB file on the API
+    download: String,
+}
+
+/// What GET /api/books/<id> answers: the book as the JSON export has it
+#[derive(Serialize)]
+struct BookDetails {
+    id: usize,
+
+    #[serde(flatten)]
+    book: ExportedBook,
+
+    /// Path of the EPUB file on the API
+    download: String,
+}
+
+/// What POST /api/scan answers
+#[derive(Serialize)]
+struct ScanResult {
+    /// Books in the library after the scan
+    books: usize,
+
+    /// Books found that weren't in the library
+    added: usize,
+
+    /// Books whose files are gone
+    removed: usize,
+}
+
+/// Answers a request for an /api route
+///
+/// # Arguments
+/// * `request` - The request (its path starts with API_BASE)
+/// * `path` - Its path, without the query
+/// * `query` - Its query string ("q=tolkien&limit=10")
+/// * `profile` - The library profile being served (scans use its roots)
+/// * `books` - The books of the library
+/// * `metrics` - Counts the downloads, scans and errors
+pub fn respond(
+    request: Request,
+    path: &str,
+    query: &str,
+    profile: &Profile,
+    books: &mut Vec<Book>,
+    metrics: &mut Metrics,
+) -> io::Result<()> {
+    let route = path.strip_prefix(API_BASE).unwrap_or("").trim_end_matches('/');
+    let parts: Vec<&str> = route.split('/').filter(|part| !part.is_empty()).collect();
+    let get = *request.method() == Method::Get;
+    let book_id = |id: &str| id.parse::<usize>().ok().filter(|id| *id < books.len());
+
+    match parts.as_slice() {
+        [] if get => json(
+            request,
+            &LibraryInfo {
+                library: &profile.name,
+                books: books.len(),
+                version: env!("CARGO_PKG_VERSION"),
+            },
+        ),
+        ["books"] if get => match list(books, query) {
+            Ok(page) => json(request, &page),
+            Err(e) => error(request, 400, &e),
+        },
+        ["books", id] if get => match book_id(id) {
+            Some(id) => json(
+                request,
+                &BookDetails {
+                    id,
+                    book: ExportedBook::from_book(&books[id]),
+                    download: download_path(id),
+                },
+            ),
+            None => not_found(request, metrics, "no such book"),
+        },
+        ["books", id, "file"] if get => match book_id(id) {
+            Some(id) => file(request, &books[id], metrics),
+            None => not_found(request, metrics, "no such book"),
+        },
+        ["scan"] if *request.method() == Method::Post => {
+            let started = std::time::Instant::now();
+            let result = scan(profile, books);
+            metrics.scan(started.elapsed(), books);
+            json(request, &result)
+        }
+        ["scan"] => error(request, 405, "use POST"),
+        _ => not_found(request, metrics, "no such route"),
+    }
+}
+
+/// Lists the books matching the `q` filter of a query, a page at a time
+fn list(books: &[Book], query: &str) -> Result<BookPage, String> {
+    let param = |name: &str| crate::server::query_param(query, name);
+    let filter = Filter::parse(&param("q").and_then(crate::server::form_decode).unwrap_or_default())?;
+    let offset = param("offset").and_then(|o| o.parse().ok()).unwrap_or(0);
+    let limit = param("limit").and_then(|l| l.parse().ok()).unwrap_or(DEFAULT_LIMIT);
+
+    // Archived books only when the filter asks for them, as in the TUI
+    let mut ids: Vec<usize> = (0..books.len())
+        .filter(|&i| filter.mentions_archived() || !books[i].user.archived)
+        .filter(|&i| filter.matches(&books[i]))
+        .collect();
+    // Collated as in the TUI, each title keyed once
+    ids.sort_by_cached_key(|&i| crate::sort::title_key(books[i].display_title()));
+
+    Ok(BookPage {
+        total: ids.len(),
+        offset,
+        books: ids
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|id| {
+                let book = &books[id];
+                BookSummary {
+                    id,
+                    title: book.display_title().to_string(),
+                    authors: book.authors().to_vec(),
+                    series: book.meta.series.clone(),
+                    series_index: book.meta.series_index,
+                    status: book.user.status,
+                    rating: book.user.rating,
+                    starred: book.user.starred,
+                    tags: book.user.tags.clone(),
+                    download: download_path(id),
+                }
+            })
+            .collect(),
+    })
+}
+
+/// Rescans every scan root of the profile and saves the library
+fn scan(profile: &Profile, books: &mut Vec<Book>) -> ScanResult {
+    let before: std::collections::HashSet<_> = books.iter().map(|book| book.path.clone()).collect();
+    for root in &profile.settings.scan_paths {
+        profile.rescan_root(books, root, false);
+    }
+    let after: std::collections::HashSet<_> = books.iter().map(|book| &book.path).collect();
+    let added = after.iter().filter(|path| !before.contains(**path)).count();
+    let removed = before.iter().filter(|path| !after.contains(path)).count();
+
+    if let Err(e) = crate::database::save(&profile.database_path(), books) {
+        tracing::error!(library = %profile.name, error = %e, "cannot save library database");
+    }
+    tracing::info!(books = books.len(), added, removed, "library rescanned from the API");
+    ScanResult {
+        books: books.len(),
+        added,
+        removed,
+    }
+}
+
+/// Path of a book's file on the API
+fn download_path(id: usize) -> String {
+    format!("{}/books/{}/file", API_BASE, id)
+}
+
+/// Sends a book's EPUB file
+fn file(request: Request, book: &Book, metrics: &mut Metrics) -> io::Result<()> {
+    match File::open(&book.path) {
+        Ok(file) => {
+            metrics.download(file.metadata().map(|m| m.len()).unwrap_or(0));
+            let disposition = format!("attachment; filename=\"{}\"", ascii_filename(&book.name));
+            let response = Response::from_file(file)
+                .with_header(header("Content-Type", "application/epub+zip")?)
+                .with_header(header("Content-Disposition", &disposition)?);
+            request.respond(response)
+        }
+        Err(e) => {
+            tracing::warn!(path = %book.path.display(), error = %e, "cannot open book for download");
+            not_found(request, metrics, "the file of this book can't be read")
+        }
+    }
+}
+
+/// Answers with a value as JSON
+fn json<T: Serialize>(request: Request, value: &T) -> io::Result<()> {
+    let body = serde_json::to_string_pretty(value).map_err(io::Error::other)?;
+    request.respond(Response::from_string(body).with_header(header("Content-Type", "application/json")?))
+}
+
+/// Answers 404 with `{"error": "..."}`, counting it
+fn not_found(request: Request, metrics: &mut Metrics, message: &str) -> io::Result<()> {
+    metrics.not_found();
+    error(request, 404, message)
+}
+
+/// Answers with an error status and `{"error": "..."}`
+fn error(request: Request, status: u16, message: &str) -> io::Result<()> {
+    let body = serde_json::to_string(&serde_json::json!({ "error": message })).map_err(io::Error::other)?;
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json")?);
+    request.respond(response)
+}
@@ -0,0 +1,335 @@
+// src/audio.rs
+// Listening to books that come with narration - EPUB audiobooks and
+// read-aloud EPUBs (media overlays, see epub.rs) - with mpv ([audio] player)
+//
+// - The audio files, in reading order (as the spine's SMIL overlays play
+//   them, else as the manifest lists them), are copied into the cache
+//   directory once and given to the player as a playlist, starting where
+//   listening stopped.
+// - The player runs in the background and is controlled through its JSON
+//   IPC socket (--input-ipc-server): pause, seek, next and previous track,
+//   and where it is - kept with the book (track and second) next to the
+//   reader's position, and synced like it.
+// - Closing the app stops it.
+
+use crate::book::ListeningPosition;
+use crate::epub;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+/// Folder of the cache directory the audio of books is copied to
+const CACHE_DIR: &str = "narration";
+
+/// How long the player is given to open its socket after starting
+const START_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long a reply of the player is waited for
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// What the player is doing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Status {
+    /// Index of the audio file playing
+    pub track: usize,
+
+    /// Seconds into it
+    pub seconds: f64,
+
+    /// Its length in seconds (None until the player knows it)
+    pub duration: Option<f64>,
+
+    pub paused: bool,
+}
+
+/// A player playing the narration of a book, in the background
+pub struct Player {
+    /// The book
+    pub book: PathBuf,
+
+    /// Number of audio files of the book
+    pub tracks: usize,
+
+    child: Child,
+
+    /// Where the player listens for commands
+    socket: PathBuf,
+}
+
+/// Copies the audio of a book into the cache (once), in reading order
+///
+/// # Returns
+/// The audio files, or an error if the book has none
+pub fn narration(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(io::Error::other)?;
+    let entries = audio_entries(&mut archive)?;
+    if entries.is_empty() {
+        return Err(io::Error::other("the book has no narration"));
+    }
+
+    let key = format!("{:x}", Sha256::digest(path.to_string_lossy().as_bytes()));
+    let folder = crate::paths::cache_dir().join(CACHE_DIR).join(&key[..16]);
+    std::fs::create_dir_all(&folder)?;
+    let mut files = Vec::new();
+    for (i, name) in entries.iter().enumerate() {
+        let mut entry = archive.by_name(name).map_err(io::Error::other)?;
+        let file_name = name.rsplit('/').next().unwrap_or(name);
+        let file = folder.join(format!("{:03}-{}", i + 1, file_name));
+        if std::fs::metadata(&file).map(|m| m.len()).ok() != Some(entry.size()) {
+            let mut audio = Vec::new();
+            entry.read_to_end(&mut audio)?;
+            std::fs::write(&file, audio)?;
+        }
+        files.push(file);
+    }
+    Ok(files)
+}
+
+/// The audio files of an EPUB, as paths inside the archive: those its
+/// overlays play, in the spine's order, else the manifest's audio items
+fn audio_entries(archive: &mut zip::ZipArchive<File>) -> io::Result<Vec<String>> {
+    let (opf_path, opf) = epub::read_package(archive)?;
+    let (items, _) = epub::read_manifest(&opf)?;
+    let overlays = media_overlays(&opf)?;
+    let by_id: HashMap<&str, &epub::Item> = items.iter().map(|item| (item.id.as_str(), item)).collect();
+
+    let mut entries: Vec<String> = Vec::new();
+    for (id, _) in epub::read_spine(&opf)? {
+        let Some(smil) = overlays.get(&id).and_then(|overlay| by_id.get(overlay.as_str())) else {
+            continue;
+        };
+        let smil_path = epub::entry_path(&opf_path, &smil.href);
+        let Ok(smil) = epub::read_entry(archive, &smil_path) else {
+            continue;
+        };
+        for source in audio_sources(&smil)? {
+            let entry = epub::entry_path(&smil_path, &source);
+            if !entries.contains(&entry) {
+                entries.push(entry);
+            }
+        }
+    }
+    if entries.is_empty() {
+        entries = items
+            .iter()
+            .filter(|item| item.media_type.starts_with("audio/"))
+            .map(|item| epub::entry_path(&opf_path, &item.href))
+            .collect();
+    }
+    Ok(entries)
+}
+
+/// The media overlay of each manifest item that has one (item id -> overlay id)
+fn media_overlays(opf: &str) -> io::Result<HashMap<String, String>> {
+    let mut overlays = HashMap::new();
+    let mut reader = Reader::from_str(opf);
+    loop {
+        match reader.read_event().map_err(io::Error::other)? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"item" => {
+                if let (Some(id), Some(overlay)) = (epub::attribute(&e, b"id"), epub::attribute(&e, b"media-overlay")) {
+                    overlays.insert(id, overlay);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(overlays)
+}
+
+/// The audio files a SMIL overlay plays (`<audio src="...">`), in order
+fn audio_sources(smil: &str) -> io::Result<Vec<String>> {
+    let mut sources: Vec<String> = Vec::new();
+    let mut reader = Reader::from_str(smil);
+    loop {
+        match reader.read_event().map_err(io::Error::other)? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"audio" => {
+                if let Some(source) = epub::attribute(&e, b"src") {
+                    if sources.last() != Some(&source) {
+                        sources.push(source);
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(sources)
+}
+
+impl Player {
+    /// Starts playing a book's audio files
+    ///
+    /// # Arguments
+    /// * `command` - The player, with its options, e.g. "mpv --volume=80"
+    /// * `book` - The book they're the narration of
+    /// * `files` - Its audio files, in order (see `narration`)
+    /// * `from` - Where listening stopped last time (None = from the start)
+    pub fn start(command: &str, book: &Path, files: &[PathBuf], from: Option<&ListeningPosition>) -> io::Result<Self> {
+        let mut parts = command.split_whitespace();
+        let program = parts.next().unwrap_or("mpv");
+        let socket = socket_path();
+        let _ = std::fs::remove_file(&socket);
+
+        let mut player = Command::new(program);
+        player
+            .args(parts)
+            .arg("--no-video")
+            .arg("--no-terminal")
+            .arg(format!("--input-ipc-server={}", socket.display()));
+        if let Some(from) = from.filter(|from| from.track < files.len()) {
+            player
+                .arg(format!("--playlist-start={}", from.track))
+                .arg(format!("--start={}", from.seconds));
+        }
+        let child = player
+            .arg("--")
+            .args(files)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("cannot run {}: {}", program, e)))?;
+
+        let mut player = Self {
+            book: book.to_path_buf(),
+            tracks: files.len(),
+            child,
+            socket,
+        };
+        // The socket comes up once the player has started
+        let started = std::time::Instant::now();
+        while connect(&player.socket).is_err() {
+            if !player.is_running() {
+                return Err(io::Error::other(format!("{} stopped at once", program)));
+            }
+            if started.elapsed() > START_TIMEOUT {
+                return Err(io::Error::other(format!("{} doesn't answer on its socket", program)));
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        tracing::info!(book = %book.display(), tracks = files.len(), player = program, "listening started");
+        Ok(player)
+    }
+
+    /// Asks the player what it's doing
+    pub fn status(&self) -> io::Result<Status> {
+        let track = self.get("playlist-pos")?.as_u64().unwrap_or(0) as usize;
+        Ok(Status {
+            track,
+            // Not known yet between two files
+            seconds: self.get("time-pos").ok().and_then(|seconds| seconds.as_f64()).unwrap_or(0.0),
+            duration: self.get("duration").ok().and_then(|seconds| seconds.as_f64()),
+            paused: self.get("pause")?.as_bool().unwrap_or(false),
+        })
+    }
+
+    /// Pauses, or goes on after a pause
+    pub fn toggle_pause(&self) -> io::Result<()> {
+        self.request(json!(["cycle", "pause"])).map(drop)
+    }
+
+    /// Goes forward (or back, if negative) some seconds
+    pub fn seek(&self, seconds: i64) -> io::Result<()> {
+        self.request(json!(["seek", seconds, "relative"])).map(drop)
+    }
+
+    /// Goes to the next (or previous) audio file
+    pub fn skip(&self, forward: bool) -> io::Result<()> {
+        let command = if forward { "playlist-next" } else { "playlist-prev" };
+        self.request(json!([command])).map(drop)
+    }
+
+    /// Whether the player is still running (it stops at the end of the
+    /// last file, or when closed)
+    pub fn is_running(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    /// Stops the player
+    pub fn stop(mut self) {
+        self.quit();
+    }
+
+    fn quit(&mut self) {
+        if self.is_running() && self.request(json!(["quit"])).is_err() {
+            let _ = self.child.kill();
+        }
+        let _ = self.child.wait();
+        let _ = std::fs::remove_file(&self.socket);
+    }
+
+    /// A property of the player, e.g. "time-pos"
+    fn get(&self, property: &str) -> io::Result<Value> {
+        self.request(json!(["get_property", property]))
+    }
+
+    /// Sends a command to the player and waits for its reply (events the
+    /// player sends meanwhile are skipped)
+    fn request(&self, command: Value) -> io::Result<Value> {
+        let mut connection = connect(&self.socket)?;
+        let mut line = json!({ "command": command, "request_id": 1 }).to_string();
+        line.push('\n');
+        connection.write_all(line.as_bytes())?;
+        for line in BufReader::new(connection).lines() {
+            let reply: Value = serde_json::from_str(&line?).map_err(io::Error::other)?;
+            if reply.get("request_id") != Some(&json!(1)) {
+                continue;
+            }
+            return match reply["error"].as_str() {
+                Some("success") => Ok(reply["data"].clone()),
+                error => Err(io::Error::other(error.unwrap_or("no reply").to_string())),
+            };
+        }
+        Err(io::Error::other("the player closed its socket"))
+    }
+}
+
+impl Drop for Player {
+    /// A player left behind would go on playing after the app is gone
+    fn drop(&mut self) {
+        if self.is_running() {
+            self.quit();
+        }
+    }
+}
+
+impl Status {
+    /// e.g. "Track 3/12  4:05 / 12:30", "(paused)" after it when it is
+    pub fn summary(&self, tracks: usize) -> String {
+        let time = |seconds: f64| format!("{}:{:02}", seconds as u64 / 60, seconds as u64 % 60);
+        let duration = self.duration.map(|d| format!(" / {}", time(d))).unwrap_or_default();
+        let paused = if self.paused { "  (paused)" } else { "" };
+        format!("Track {}/{}  {}{}{}", self.track + 1, tracks, time(self.seconds), duration, paused)
+    }
+}
+
+/// Where this app's player listens for commands
+fn socket_path() -> PathBuf {
+    let name = format!("funkhunt-player-{}", std::process::id());
+    if cfg!(windows) {
+        PathBuf::from(format!(r"\\.\pipe\{}", name))
+    } else {
+        std::env::temp_dir().join(format!("{}.sock", name))
+    }
+}
+
+#[cfg(unix)]
+fn connect(socket: &Path) -> io::Result<std::os::unix::net::UnixStream> {
+    let stream = std::os::unix::net::UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+    Ok(stream)
+}
+
+/// mpv's socket is a named pipe on Windows
+#[cfg(windows)]
+fn connect(socket: &Path) -> io::Result<File> {
+    std::fs::OpenOptions::new().read(true).write(true).open(socket)
+}
@@ -0,0 +1,109 @@
+// src/authors.rs
+// Author name normalization - finds spellings of the same author
+// ("Tolkien, J.R.R." vs "J. R. R. Tolkien") so they can be merged
+
+use crate::book::Book;
+use std::collections::{BTreeMap, HashMap};
+
+/// Spellings of what is probably one author
+#[derive(Debug, Clone)]
+pub struct AuthorGroup {
+    /// Different spellings with how many books use each, most used first
+    pub spellings: Vec<(String, usize)>,
+}
+
+/// Reduces an author name to a comparison key
+///
+/// - "Last, First" is turned around to "First Last"
+/// - punctuation becomes spaces, so "J.R.R." and "J. R. R." both give "j r r"
+/// - letters are lowercased and spaces collapsed
+///
+/// # Examples
+/// "Tolkien, J.R.R." and "J. R. R. Tolkien" both give "j r r tolkien"
+pub fn author_key(name: &str) -> String {
+    // "Tolkien, J.R.R." -> "J.R.R. Tolkien" (only for a single comma,
+    // "Smith, Jr., John" is left alone rather than guessed at)
+    let name = match name.split_once(',') {
+        Some((last, first)) if !first.contains(',') && !first.trim().is_empty() => {
+            format!("{} {}", first.trim(), last.trim())
+        }
+        _ => name.to_string(),
+    };
+
+    name.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Finds authors written in more than one way across the library
+///
+/// Uses the authors as shown (canonical names where set), so authors
+/// that were merged before don't come up again.
+///
+/// # Returns
+/// One group per author with several spellings, sorted by the most used spelling
+pub fn find_variants(books: &[Book]) -> Vec<AuthorGroup> {
+    // key -> spelling -> number of books
+    let mut by_key: HashMap<String, BTreeMap<String, usize>> = HashMap::new();
+    for book in books {
+        for author in book.authors() {
+            *by_key
+                .entry(author_key(author))
+                .or_default()
+                .entry(author.clone())
+                .or_default() += 1;
+        }
+    }
+
+    let mut groups: Vec<AuthorGroup> = by_key
+        .into_values()
+        .filter(|spellings| spellings.len() > 1)
+        .map(|spellings| {
+            let mut spellings: Vec<(String, usize)> = spellings.into_iter().collect();
+            // Most used spelling first - it's the likely canonical one
+            spellings.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            AuthorGroup { spellings }
+        })
+        .collect();
+
+    groups.sort_by(|a, b| a.spellings[0].0.to_lowercase().cmp(&b.spellings[0].0.to_lowercase()));
+    groups
+}
+
+/// Replaces author spellings with their canonical name on every book that uses them
+///
+/// The result is stored as the book's canonical authors; the metadata read
+/// from the file stays untouched.
+///
+/// # Arguments
+/// * `books` - The books of the library (modified in place)
+/// * `aliases` - Spelling -> canonical name
+///
+/// # Returns
+/// How many books changed
+pub fn apply_aliases(books: &mut [Book], aliases: &BTreeMap<String, String>) -> usize {
+    let mut changed = 0;
+
+    for book in books {
+        let current = book.authors().to_vec();
+        let mut canonical: Vec<String> = Vec::with_capacity(current.len());
+        for author in &current {
+            let name = aliases.get(author).unwrap_or(author);
+            // Two spellings of one author may both be listed - keep one
+            if !canonical.contains(name) {
+                canonical.push(name.clone());
+            }
+        }
+
+        if canonical != current {
+            book.user.authors = canonical;
+            changed += 1;
+        }
+    }
+
+    changed
+}
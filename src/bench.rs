@@ -0,0 +1,189 @@
+// src/bench.rs
+// Benchmark of the scanner (`funkhunt bench PATH`), so a change that makes
+// scanning slower shows in numbers
+//
+// The EPUBs of PATH are found once (the walk of a scan), then three stages
+// are timed with each thread count asked for, the files shared out between
+// the threads:
+// - scanning: what a scan does with a file (metadata, language, readability)
+// - metadata: only reading the package (epub::read_metadata)
+// - hashing: the content hash of the file
+// Each measure is the best of a few runs - the first one also fills the
+// disk cache. Nothing is written, the library is left alone.
+
+use crate::profile::FolderSettings;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Runs of each measure (the best one counts) unless asked otherwise
+pub const DEFAULT_RUNS: usize = 3;
+
+/// What is timed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Scanning,
+    Metadata,
+    Hashing,
+}
+
+/// How long a stage took with some threads
+#[derive(Debug, Clone)]
+pub struct Timing {
+    pub stage: Stage,
+    pub threads: usize,
+
+    /// The best run
+    pub time: Duration,
+}
+
+/// Results of a benchmark
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub root: PathBuf,
+
+    /// EPUBs found
+    pub files: usize,
+
+    /// Their total size
+    pub bytes: u64,
+
+    /// How long finding them took (one walk)
+    pub walk: Duration,
+
+    /// Every stage with every thread count, in that order
+    pub timings: Vec<Timing>,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 3] = [Stage::Scanning, Stage::Metadata, Stage::Hashing];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Stage::Scanning => "Scanning",
+            Stage::Metadata => "Metadata",
+            Stage::Hashing => "Hashing",
+        }
+    }
+
+    /// Does the stage's work on one file (its result thrown away)
+    fn run(self, path: &Path, settings: &FolderSettings) {
+        match self {
+            Stage::Scanning => drop(std::hint::black_box(crate::scanner::book_from_file(path, settings))),
+            Stage::Metadata => drop(std::hint::black_box(crate::epub::read_metadata(path))),
+            Stage::Hashing => drop(std::hint::black_box(crate::hash::content_hash(path))),
+        }
+    }
+}
+
+/// Thread counts tried unless asked otherwise: 1, 2, 4... up to the
+/// number of processors (which is tried too)
+pub fn default_threads() -> Vec<usize> {
+    let processors = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let mut threads: Vec<usize> = std::iter::successors(Some(1), |n| Some(n * 2))
+        .take_while(|&n| n < processors)
+        .collect();
+    threads.push(processors);
+    threads
+}
+
+/// Benchmarks the scanner on a folder
+///
+/// # Arguments
+/// * `root` - The folder (its EPUBs, recursively)
+/// * `threads` - The thread counts to try
+/// * `runs` - How many times each measure is taken (the best one counts)
+/// * `progress` - Told each measure before it's taken, e.g. for a status line
+///
+/// # Returns
+/// The report (without timings if the folder has no EPUBs)
+pub fn run(root: &Path, threads: &[usize], runs: usize, mut progress: impl FnMut(Stage, usize)) -> Report {
+    let settings = FolderSettings::default();
+    let started = Instant::now();
+    let files = crate::scanner::epub_files(root, &settings);
+    let walk = started.elapsed();
+    let bytes = files.iter().filter_map(|file| std::fs::metadata(file).ok()).map(|m| m.len()).sum();
+
+    let mut timings = Vec::new();
+    for stage in Stage::ALL.into_iter().filter(|_| !files.is_empty()) {
+        for &count in threads {
+            progress(stage, count);
+            let time = (0..runs.max(1))
+                .map(|_| timed(&files, count, |file| stage.run(file, &settings)))
+                .min()
+                .unwrap_or_default();
+            timings.push(Timing { stage, threads: count, time });
+        }
+    }
+
+    Report {
+        root: root.to_path_buf(),
+        files: files.len(),
+        bytes,
+        walk,
+        timings,
+    }
+}
+
+/// Times some threads working through files, each taking the next one
+/// left until none is
+fn timed(files: &[PathBuf], threads: usize, work: impl Fn(&Path) + Sync) -> Duration {
+    let next = AtomicUsize::new(0);
+    let started = Instant::now();
+    std::thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| {
+                while let Some(file) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    work(file);
+                }
+            });
+        }
+    });
+    started.elapsed()
+}
+
+impl Report {
+    /// The report as a table, a line per row: files per second, MB per
+    /// second and the speed-up over the fewest threads tried, per stage
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!(
+                "{}: {} EPUBs, {}",
+                self.root.display(),
+                self.files,
+                crate::transfer::human_bytes(self.bytes as f64)
+            ),
+            format!("Finding the files: {}", duration_text(self.walk)),
+            String::new(),
+            format!("{:<10} {:>7} {:>10} {:>10} {:>9} {:>9}", "Stage", "Threads", "Time", "Files/s", "MB/s", "Speed-up"),
+        ];
+        for stage in Stage::ALL {
+            let timings: Vec<&Timing> = self.timings.iter().filter(|timing| timing.stage == stage).collect();
+            let Some(first) = timings.first() else {
+                continue;
+            };
+            for timing in &timings {
+                let seconds = timing.time.as_secs_f64().max(f64::EPSILON);
+                lines.push(format!(
+                    "{:<10} {:>7} {:>10} {:>10.1} {:>9.1} {:>8.2}x",
+                    stage.label(),
+                    timing.threads,
+                    duration_text(timing.time),
+                    self.files as f64 / seconds,
+                    self.bytes as f64 / (1 << 20) as f64 / seconds,
+                    first.time.as_secs_f64() / seconds,
+                ));
+            }
+        }
+        lines
+    }
+}
+
+/// e.g. "850 ms", "12.40 s"
+fn duration_text(time: Duration) -> String {
+    if time < Duration::from_secs(1) {
+        format!("{} ms", time.as_millis())
+    } else {
+        format!("{:.2} s", time.as_secs_f64())
+    }
+}
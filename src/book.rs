@@ -1,11 +1,14 @@
 // src/book.rs
 // Data model for an EPUB book and methods to interact with it
 
+use crate::readability::Readability;
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
 /// Represents a single EPUB book in our collection
 /// #[derive(Clone)] allows us to create copies of Book structs when needed
-#[derive(Clone)]
+/// Serialize/Deserialize let the library database store books as JSON
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Book {
     /// Display name of the book (usually the filename)
     pub name: String,
@@ -13,6 +16,386 @@ pub struct Book {
     /// Full filesystem path to the EPUB file
     /// PathBuf is like String but for file paths, handles OS differences automatically
     pub path: PathBuf,
+
+    /// Metadata read from the EPUB package (title, authors, series...)
+    /// #[serde(default)] lets older databases without this field still load
+    #[serde(default)]
+    pub meta: Metadata,
+
+    /// Data curated by the user (tags, rating...) - never read from the file itself
+    #[serde(default)]
+    pub user: UserData,
+
+    /// SHA-256 of the file content, computed on demand and cached
+    /// (see `ensure_hash`) - identifies the book even after it moves
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+
+    /// Language guessed from the book's text when it was scanned, e.g. "es"
+    /// (None = not sure, or not guessed - see language.rs)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detected_language: Option<String>,
+
+    /// How hard its text reads, measured when it was scanned (see readability.rs)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub readability: Option<Readability>,
+
+    /// When the book entered the library (seconds since the Unix epoch)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub added: Option<u64>,
+
+    /// When the book was last opened from FunkHunt (seconds since the Unix epoch)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opened: Option<u64>,
+
+    /// When the user data (or metadata) last changed (seconds since the Unix epoch)
+    /// Stamped when the library is saved (see `database::stamp_changes`);
+    /// two-way sync keeps the newer side
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+}
+
+/// Everything the user adds on top of a book's own metadata
+/// Default gives a book with no tags and no rating
+/// PartialEq lets us tell whether the user touched a book at all
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UserData {
+    /// Free-form tags, e.g. "fantasy" or "to-lend"
+    pub tags: Vec<String>,
+
+    /// Rating from 1 to 5 stars (None = not rated)
+    pub rating: Option<u8>,
+
+    /// Named collections (shelves) the book belongs to, e.g. "Summer reading"
+    /// Independent of where the file lives on disk
+    pub collections: Vec<String>,
+
+    /// Marked as a favorite
+    pub starred: bool,
+
+    /// Where the user is with this book (None = no status set)
+    pub status: Option<ReadingStatus>,
+
+    /// Hidden from the normal list (the file is left alone)
+    pub archived: bool,
+
+    /// Canonical author names chosen when merging spellings
+    /// (empty = use the authors from the file's metadata)
+    pub authors: Vec<String>,
+
+    /// Places marked in the built-in reader, in reading order
+    pub bookmarks: Vec<Bookmark>,
+
+    /// Passages highlighted in the built-in reader, in reading order
+    pub highlights: Vec<Highlight>,
+
+    /// Where reading stopped in the built-in reader (None = never read there)
+    pub position: Option<ReadingPosition>,
+
+    /// Where listening to its narration stopped (None = never listened to)
+    pub listening: Option<ListeningPosition>,
+
+    /// Language set by hand (None = the one declared or detected)
+    pub language: Option<String>,
+}
+
+/// Where reading a book stopped in the built-in reader
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReadingPosition {
+    /// Index of the chapter (as reader::Document has them)
+    pub chapter: usize,
+
+    /// Index of the paragraph (block) at the top of the page
+    pub block: usize,
+
+    /// How far into the book the page was, in percent
+    #[serde(default)]
+    pub percent: u8,
+
+    /// When the book was last read (seconds since 1970)
+    #[serde(default)]
+    pub read_at: u64,
+}
+
+/// Where listening to a book's narration stopped (see audio.rs)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ListeningPosition {
+    /// Index of the audio file, in reading order
+    pub track: usize,
+
+    /// Seconds into it
+    pub seconds: u32,
+
+    /// Number of audio files of the book
+    #[serde(default)]
+    pub tracks: usize,
+
+    /// When the book was last listened to (seconds since 1970)
+    #[serde(default)]
+    pub listened_at: u64,
+}
+
+/// A place in a book, marked from the built-in reader
+/// The position is a paragraph, so it doesn't depend on the terminal's width
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    /// Index of the chapter (as reader::Document has them)
+    pub chapter: usize,
+
+    /// Index of the paragraph (block) in the chapter
+    pub block: usize,
+
+    /// Name given to the place (empty = none)
+    #[serde(default)]
+    pub label: String,
+
+    /// When it was set (seconds since 1970)
+    #[serde(default)]
+    pub created: u64,
+}
+
+/// A passage highlighted in the built-in reader, with where it is
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Highlight {
+    /// Index of the chapter (as reader::Document has them)
+    pub chapter: usize,
+
+    /// Index of the paragraph (block) the passage starts in
+    pub block: usize,
+
+    /// Title of the chapter when it was highlighted (empty = unknown)
+    #[serde(default)]
+    pub chapter_title: String,
+
+    /// The passage (paragraphs separated by newlines)
+    pub text: String,
+
+    /// When it was highlighted (seconds since 1970)
+    #[serde(default)]
+    pub created: u64,
+}
+
+/// Reading status of a book
+/// Stored as "to-read", "reading", "finished" or "abandoned"
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReadingStatus {
+    /// On the list, not started
+    ToRead,
+    /// Currently being read
+    Reading,
+    /// Read to the end
+    Finished,
+    /// Started, then given up
+    Abandoned,
+}
+
+impl ReadingStatus {
+    /// All statuses, in workflow order
+    pub const ALL: [ReadingStatus; 4] = [
+        ReadingStatus::ToRead,
+        ReadingStatus::Reading,
+        ReadingStatus::Finished,
+        ReadingStatus::Abandoned,
+    ];
+
+    /// Human-readable name, e.g. "To Read"
+    pub fn label(self) -> &'static str {
+        match self {
+            ReadingStatus::ToRead => "To Read",
+            ReadingStatus::Reading => "Reading",
+            ReadingStatus::Finished => "Finished",
+            ReadingStatus::Abandoned => "Abandoned",
+        }
+    }
+
+    /// Name used in files and filters, e.g. "to-read"
+    pub fn key(self) -> &'static str {
+        match self {
+            ReadingStatus::ToRead => "to-read",
+            ReadingStatus::Reading => "reading",
+            ReadingStatus::Finished => "finished",
+            ReadingStatus::Abandoned => "abandoned",
+        }
+    }
+
+    /// One-character badge for the book list
+    pub fn badge(self) -> char {
+        match self {
+            ReadingStatus::ToRead => '○',
+            ReadingStatus::Reading => '◐',
+            ReadingStatus::Finished => '●',
+            ReadingStatus::Abandoned => '✗',
+        }
+    }
+
+    /// Next step when cycling: no status -> To Read -> ... -> Abandoned -> no status
+    pub fn cycle(status: Option<ReadingStatus>) -> Option<ReadingStatus> {
+        match status {
+            None => Some(ReadingStatus::ToRead),
+            Some(ReadingStatus::ToRead) => Some(ReadingStatus::Reading),
+            Some(ReadingStatus::Reading) => Some(ReadingStatus::Finished),
+            Some(ReadingStatus::Finished) => Some(ReadingStatus::Abandoned),
+            Some(ReadingStatus::Abandoned) => None,
+        }
+    }
+}
+
+/// Descriptive metadata of a book, as found in the EPUB's OPF package
+/// Default gives empty metadata (used when the EPUB can't be parsed)
+/// PartialEq lets a sync tell whether it was edited on either side
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Metadata {
+    /// Title of the book (dc:title)
+    pub title: Option<String>,
+
+    /// Authors, in the order listed in the package (dc:creator)
+    pub authors: Vec<String>,
+
+    /// Language code, e.g. "en" or "es" (dc:language)
+    pub language: Option<String>,
+
+    /// Publisher name (dc:publisher)
+    pub publisher: Option<String>,
+
+    /// Publication date as written in the package (dc:date)
+    pub published: Option<String>,
+
+    /// Blurb / description (dc:description)
+    pub description: Option<String>,
+
+    /// Subjects / genres (dc:subject)
+    pub subjects: Vec<String>,
+
+    /// ISBN digits, if the package has one
+    pub isbn: Option<String>,
+
+    /// Other unique identifier (UUID, URI...) when there's no ISBN
+    pub identifier: Option<String>,
+
+    /// Series name, e.g. "The Lord of the Rings"
+    pub series: Option<String>,
+
+    /// Position within the series (can be fractional, e.g. 1.5)
+    pub series_index: Option<f32>,
+
+    /// Pages laid out at a fixed size (EPUB3 pre-paginated rendition, as
+    /// comics and picture books are), not reflowable text
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub fixed_layout: bool,
+
+    /// Has media overlays: recorded narration synchronized with the text (read-aloud)
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub media_overlays: bool,
+
+    /// Accessibility metadata (EPUB3 schema.org and conformance properties)
+    #[serde(skip_serializing_if = "Accessibility::is_empty")]
+    pub accessibility: Accessibility,
+}
+
+/// Accessibility metadata of an EPUB package: how its content can be
+/// perceived, what helps reading it, what may harm, and the accessibility
+/// standard it claims to meet (EPUB Accessibility 1.0 / 1.1)
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Accessibility {
+    /// Ways the content is perceived, e.g. "textual", "visual" (schema:accessMode)
+    pub access_modes: Vec<String>,
+
+    /// Modes enough to read all of it, e.g. "textual" or "textual,visual"
+    /// (schema:accessModeSufficient)
+    pub sufficient_modes: Vec<String>,
+
+    /// e.g. "alternativeText", "tableOfContents" (schema:accessibilityFeature)
+    pub features: Vec<String>,
+
+    /// e.g. "none", "noFlashingHazard", "motionSimulation" (schema:accessibilityHazard)
+    pub hazards: Vec<String>,
+
+    /// Human-readable summary (schema:accessibilitySummary)
+    pub summary: Option<String>,
+
+    /// Standard it claims to meet, a URL or a name (dcterms:conformsTo)
+    pub conforms_to: Option<String>,
+
+    /// Who checked that (a11y:certifiedBy)
+    pub certified_by: Option<String>,
+}
+
+impl Accessibility {
+    /// Whether the package says nothing about accessibility
+    pub fn is_empty(&self) -> bool {
+        *self == Accessibility::default()
+    }
+
+    /// Whether the book claims to meet an accessibility standard
+    pub fn conforms(&self) -> bool {
+        self.conforms_to.is_some()
+    }
+
+    /// Whether the book says it has no hazards ("none", or only "noXxxHazard")
+    pub fn hazard_free(&self) -> bool {
+        !self.hazards.is_empty() && self.hazards.iter().all(|hazard| hazard == "none" || hazard.starts_with("no"))
+    }
+
+    /// The hazards the book says it has (not "none" or "noXxxHazard")
+    pub fn declared_hazards(&self) -> impl Iterator<Item = &str> {
+        self.hazards
+            .iter()
+            .map(String::as_str)
+            .filter(|hazard| *hazard != "none" && !hazard.starts_with("no"))
+    }
+
+    /// Name of the standard it meets: EPUB Accessibility 1.0's URLs are
+    /// spelled out, e.g. "EPUB Accessibility 1.0 - WCAG 2.0 Level AA"
+    pub fn conformance(&self) -> Option<String> {
+        let claim = self.conforms_to.as_deref()?;
+        let level = match claim.rsplit_once('#') {
+            Some((url, level)) if url.contains("idpf.org/epub/a11y/accessibility") => level,
+            _ => return Some(claim.to_string()),
+        };
+        let level = match level {
+            "wcag-a" => "A",
+            "wcag-aa" => "AA",
+            "wcag-aaa" => "AAA",
+            _ => return Some(claim.to_string()),
+        };
+        Some(format!("EPUB Accessibility 1.0 - WCAG 2.0 Level {}", level))
+    }
+
+    /// One line for the details, e.g. "EPUB Accessibility 1.1 - WCAG 2.1
+    /// Level AA (certified by X); readable as textual; features:
+    /// alternativeText, tableOfContents; hazards: none" (None if the
+    /// package says nothing)
+    pub fn describe(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        match (self.conformance(), &self.certified_by) {
+            (Some(standard), Some(certifier)) => parts.push(format!("{} (certified by {})", standard, certifier)),
+            (Some(standard), None) => parts.push(standard),
+            _ => {}
+        }
+        if !self.sufficient_modes.is_empty() {
+            parts.push(format!("readable as {}", self.sufficient_modes.join(" or ")));
+        } else if !self.access_modes.is_empty() {
+            parts.push(format!("content {}", self.access_modes.join(", ")));
+        }
+        if !self.features.is_empty() {
+            parts.push(format!("features: {}", self.features.join(", ")));
+        }
+        if !self.hazards.is_empty() {
+            parts.push(format!("hazards: {}", self.hazards.join(", ")));
+        }
+        (!parts.is_empty()).then(|| parts.join("; "))
+    }
+}
+
+impl UserData {
+    /// Whether the user hasn't added anything to this book
+    pub fn is_empty(&self) -> bool {
+        *self == UserData::default()
+    }
 }
 
 impl Book {
@@ -26,43 +409,240 @@ impl Book {
     /// A new Book struct
     pub fn new(name: String, path: PathBuf) -> Self {
         // Self { name, path } is shorthand for Self { name: name, path: path }
-        Self { name, path }
+        Self {
+            name,
+            path,
+            meta: Metadata::default(),
+            user: UserData::default(),
+            hash: None,
+            detected_language: None,
+            readability: None,
+            added: Some(unix_now()),
+            opened: None,
+            modified: None,
+        }
+    }
+
+    /// Language of the book's text: the one set by hand, else the one its
+    /// package declares, unless its text was found to be in another one (or
+    /// it declares none)
+    pub fn language(&self) -> Option<&str> {
+        if let Some(language) = &self.user.language {
+            return Some(language);
+        }
+        match (self.meta.language.as_deref(), self.detected_language.as_deref()) {
+            (Some(declared), Some(detected)) if crate::language::primary(declared) == detected => Some(declared),
+            (_, Some(detected)) => Some(detected),
+            (declared, None) => declared,
+        }
+    }
+
+    /// Gets the content hash of the book, computing it on first use
+    ///
+    /// # Returns
+    /// The hex SHA-256 of the file, or None if the file can't be read
+    pub fn ensure_hash(&mut self) -> Option<&str> {
+        if self.hash.is_none() {
+            self.hash = crate::hash::content_hash(&self.path).ok();
+        }
+        self.hash.as_deref()
+    }
+
+    /// What's written into the EPUB file when its metadata is edited (see
+    /// `epub::write_metadata`): the title, the authors as shown and the series
+    pub fn written_metadata(&self) -> Metadata {
+        Metadata {
+            title: self.meta.title.clone(),
+            authors: self.authors().to_vec(),
+            series: self.meta.series.clone(),
+            series_index: self.meta.series_index,
+            ..Metadata::default()
+        }
+    }
+
+    /// Whether the book's package wasn't read (yet): a placeholder of a
+    /// scan, or a file whose package has no title - its metadata is empty,
+    /// not edited, and never overwrites another copy's
+    pub fn is_placeholder(&self) -> bool {
+        self.meta.title.is_none()
+    }
+
+    /// Title to show in the UI - the package title, or the filename if there is none
+    pub fn display_title(&self) -> &str {
+        self.meta.title.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Authors of the book - the canonical names if authors were merged,
+    /// otherwise as listed in the package
+    pub fn authors(&self) -> &[String] {
+        if self.user.authors.is_empty() {
+            &self.meta.authors
+        } else {
+            &self.user.authors
+        }
+    }
+
+    /// Authors joined for display, or "Unknown" if the package lists none
+    pub fn display_authors(&self) -> String {
+        if self.authors().is_empty() {
+            "Unknown".to_string()
+        } else {
+            self.authors().join(", ")
+        }
     }
 
     /// Gets metadata about the book for display in the UI
     ///
     /// # Returns
-    /// A formatted string with title, path, and file size
+    /// A formatted string with title, authors, series, path, and file size
     pub fn get_metadata(&self) -> String {
-        // Try to read file metadata (size, permissions, etc.)
-        match std::fs::metadata(&self.path) {
-            Ok(meta) => {
+        // Remote books (sftp://..., davs://...) aren't looked up on the server on every redraw
+        let size = if crate::remote::is_remote(&self.path) {
+            "on the server".to_string()
+        } else {
+            // Try to read file metadata (size, permissions, etc.)
+            match std::fs::metadata(&self.path) {
                 // Convert file size from bytes to kilobytes
-                let size_kb = meta.len() / 1024;
-
-                // Format a nice display string with multiple lines
-                format!(
-                    "Title: {}\n\nPath: {}\n\nSize: {} KB",
-                    self.name,
-                    self.path.display(), // .display() formats path correctly for current OS
-                    size_kb
-                )
+                Ok(meta) => format!("{} KB", meta.len() / 1024),
+                // If reading metadata fails, return an error message
+                Err(_) => return "Error reading metadata".to_string(),
             }
-            // If reading metadata fails, return an error message
-            Err(_) => "Error reading metadata".to_string(),
-        }
+        };
+
+        // Series line only when the book belongs to one
+        let series = match (&self.meta.series, self.meta.series_index) {
+            (Some(name), Some(index)) => format!("\n\nSeries: {} #{}", name, index),
+            (Some(name), None) => format!("\n\nSeries: {}", name),
+            _ => String::new(),
+        };
+
+        // Language line only when it's known; a guess that overrides the package says so
+        let language = match (self.language(), self.meta.language.as_deref()) {
+            (Some(language), _) if self.user.language.is_some() => format!("\n\nLanguage: {} (set by hand)", language),
+            (Some(language), Some(declared)) if language != declared => {
+                format!("\n\nLanguage: {} (detected, the book says {})", language, declared)
+            }
+            (Some(language), None) => format!("\n\nLanguage: {} (detected)", language),
+            (Some(language), Some(_)) => format!("\n\nLanguage: {}", language),
+            (None, _) => String::new(),
+        };
+
+        // Accessibility lines only when the package has accessibility metadata
+        let accessibility = match (self.meta.accessibility.describe(), &self.meta.accessibility.summary) {
+            (Some(description), Some(summary)) => format!("\n\nAccessibility: {}\n\n{}", description, summary),
+            (Some(description), None) => format!("\n\nAccessibility: {}", description),
+            (None, Some(summary)) => format!("\n\nAccessibility: {}", summary),
+            (None, None) => String::new(),
+        };
+
+        // Format line only for books e-ink readers may not show well
+        let format = match (self.meta.fixed_layout, self.meta.media_overlays) {
+            (true, true) => "\n\nFormat: fixed layout, read-aloud (media overlays)",
+            (true, false) => "\n\nFormat: fixed layout",
+            (false, true) => "\n\nFormat: read-aloud (media overlays)",
+            (false, false) => "",
+        };
+
+        let readability = match self.readability {
+            Some(r) => format!(
+                "\n\nReadability: {} (grade {:.1}, {:.0} words per sentence)",
+                r.level(),
+                r.grade,
+                r.words_per_sentence
+            ),
+            None => String::new(),
+        };
+
+        // Tags and rating lines only when the user set them
+        let tags = if self.user.tags.is_empty() {
+            String::new()
+        } else {
+            format!("\n\nTags: {}", self.user.tags.join(", "))
+        };
+        let rating = match self.user.rating {
+            Some(stars) => format!("\n\nRating: {}", stars_text(stars)),
+            None => String::new(),
+        };
+        let status = match self.user.status {
+            Some(status) => format!("\n\nStatus: {}", status.label()),
+            None => String::new(),
+        };
+        // Library dates: when it was added, when it was last opened
+        let added = match self.added {
+            Some(secs) => format!("\n\nAdded: {}", date_text(secs)),
+            None => String::new(),
+        };
+        // Where listening to the narration stopped, e.g. "track 3 of 12, at 4:05"
+        let listening = match &self.user.listening {
+            Some(at) => format!(
+                "\n\nListening: track {} of {}, at {}:{:02}",
+                at.track + 1,
+                at.tracks.max(at.track + 1),
+                at.seconds / 60,
+                at.seconds % 60
+            ),
+            None => String::new(),
+        };
+        let archived = if self.user.archived { "\n\nArchived" } else { "" };
+        let opened = match self.opened {
+            Some(secs) => format!("\n\nLast opened: {}", date_text(secs)),
+            None => String::new(),
+        };
+        let collections = if self.user.collections.is_empty() {
+            String::new()
+        } else {
+            format!("\n\nCollections: {}", self.user.collections.join(", "))
+        };
+
+        // Format a nice display string with multiple lines
+        format!(
+            "Title: {}\n\nAuthors: {}{}{}{}{}{}{}{}{}{}{}{}{}\n\nPath: {}\n\nSize: {}{}",
+            self.display_title(),
+            self.display_authors(),
+            series,
+            language,
+            format,
+            readability,
+            accessibility,
+            status,
+            tags,
+            rating,
+            collections,
+            listening,
+            archived,
+            opened,
+            self.path.display(), // .display() formats path correctly for current OS
+            size,
+            added
+        )
     }
 
-    /// Opens the book using the system's default EPUB viewer
+    /// Opens the book using the configured viewer or the system's default EPUB viewer
     ///
-    /// Uses different commands depending on the operating system:
+    /// Without a configured viewer, uses different commands depending on the operating system:
     /// - Linux: xdg-open
     /// - macOS: open
     /// - Windows: cmd /C start
     ///
+    /// # Arguments
+    /// * `viewer` - Optional viewer command from the settings (the path is appended to it)
+    ///
     /// # Returns
     /// Ok(()) if the command was spawned successfully, Err otherwise
-    pub fn open(&self) -> std::io::Result<()> {
+    pub fn open(&self, viewer: Option<&str>) -> std::io::Result<()> {
+        // A configured viewer command wins over the system default
+        // "ebook-viewer --detach" -> program "ebook-viewer", args ["--detach", <path>]
+        if let Some(command) = viewer {
+            let mut parts = command.split_whitespace();
+            if let Some(program) = parts.next() {
+                std::process::Command::new(program)
+                    .args(parts)
+                    .arg(&self.path)
+                    .spawn()?;
+                return Ok(());
+            }
+        }
+
         // Conditional compilation: these #[cfg] attributes make code compile only on specific OS
 
         // On Linux, use xdg-open to open with default application
@@ -103,3 +683,23 @@ impl Book {
         Ok(())
     }
 }
+
+/// Renders a 1-5 rating as filled/empty stars, e.g. "★★★☆☆"
+pub fn stars_text(stars: u8) -> String {
+    let filled = stars.min(5) as usize;
+    format!("{}{}", "★".repeat(filled), "☆".repeat(5 - filled))
+}
+
+/// Current time in seconds since the Unix epoch
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Formats seconds since the Unix epoch as a date, e.g. "2024-05-01" (UTC)
+pub fn date_text(secs: u64) -> String {
+    // "2024-05-01T12:30:00Z" -> "2024-05-01"
+    crate::opds::rfc3339(secs)[..10].to_string()
+}
@@ -0,0 +1,540 @@
+// src/calibre.rs
+// Calibre content server client - browse a Calibre library over the
+// network, download its books into FunkHunt and add ours to it
+//
+// Uses the server's JSON interface (the one its web app uses):
+// - GET  /ajax/library-info                 -> the libraries it serves
+// - GET  /ajax/search/<library>?num=&offset= -> ids of the books
+// - GET  /ajax/books/<library>?ids=1,2,3    -> their metadata
+// - GET  /get/EPUB/<id>/<library>           -> the EPUB file
+// - POST /cdb/add-book/0/<dup>/<file>/<library> -> adds a book (needs a user
+//   with write access, or --enable-local-write on the same machine)
+//
+// Servers with users ask for a login: Calibre uses HTTP digest over plain
+// HTTP and basic over HTTPS, both are answered.
+
+use crate::settings::CalibreSettings;
+use crate::transfer::{self, Chunk, Progress};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How long to wait for the server before giving up
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Book ids asked for per search request
+const PAGE_SIZE: usize = 500;
+
+/// Books whose metadata is asked for per request (the ids go in the URL)
+const METADATA_BATCH: usize = 100;
+
+/// A book of the Calibre library
+#[derive(Debug, Clone)]
+pub struct CalibreBook {
+    /// Id of the book in the Calibre library
+    pub id: u64,
+
+    /// Title
+    pub title: String,
+
+    /// Authors
+    pub authors: Vec<String>,
+
+    /// Series name, if any
+    pub series: Option<String>,
+
+    /// Size of the EPUB file (None if Calibre doesn't say)
+    pub size: Option<u64>,
+
+    /// Whether Calibre has the book as EPUB (others can't be downloaded)
+    pub has_epub: bool,
+}
+
+impl CalibreBook {
+    /// File name a download is saved as, e.g. "Dune - Frank Herbert.epub"
+    pub fn file_name(&self) -> String {
+        let name = match self.authors.first() {
+            Some(author) => format!("{} - {}.epub", self.title, author),
+            None => format!("{}.epub", self.title),
+        };
+        match crate::organize::sanitize(&name) {
+            name if name.is_empty() => format!("calibre-{}.epub", self.id),
+            name => name,
+        }
+    }
+}
+
+/// The books of a Calibre library
+#[derive(Debug, Clone)]
+pub struct Library {
+    /// Name of the library, e.g. "Calibre Library"
+    pub name: String,
+
+    /// Its books, sorted by title
+    pub books: Vec<CalibreBook>,
+}
+
+/// What /ajax/library-info answers
+#[derive(Deserialize)]
+struct LibraryInfo {
+    /// Library id -> name
+    library_map: BTreeMap<String, String>,
+
+    /// Id of the library shown by default
+    default_library: Option<String>,
+}
+
+/// What /ajax/search answers
+#[derive(Deserialize)]
+struct SearchResult {
+    /// How many books match in all
+    total_num: usize,
+
+    /// The ids of this page
+    book_ids: Vec<u64>,
+}
+
+/// Metadata of a book, as /ajax/books answers it
+#[derive(Deserialize)]
+struct BookMetadata {
+    #[serde(default)]
+    title: String,
+
+    #[serde(default)]
+    authors: Vec<String>,
+
+    #[serde(default)]
+    series: Option<String>,
+
+    /// Formats as upper case extensions, e.g. ["EPUB", "MOBI"]
+    #[serde(default)]
+    formats: Vec<String>,
+
+    /// Details per format, keyed by lower case extension
+    #[serde(default)]
+    format_metadata: BTreeMap<String, FormatMetadata>,
+}
+
+/// Details of one format of a book
+#[derive(Deserialize)]
+struct FormatMetadata {
+    #[serde(default)]
+    size: Option<u64>,
+}
+
+/// What /cdb/add-book answers
+#[derive(Deserialize)]
+struct AddedBook {
+    /// Id of the new book (missing when it was a duplicate)
+    #[serde(default)]
+    book_id: Option<u64>,
+
+    /// Books of the library the new one duplicates (then nothing was added)
+    #[serde(default)]
+    duplicates: Option<serde_json::Value>,
+}
+
+/// How the server asked us to log in
+#[derive(Debug, Clone)]
+enum Challenge {
+    /// User name and password in the clear (base64)
+    Basic,
+
+    /// Digest of the password with a nonce of the server
+    Digest(Digest),
+}
+
+/// A digest login challenge
+#[derive(Debug, Clone)]
+struct Digest {
+    /// Protection space, e.g. "calibre"
+    realm: String,
+
+    /// Server nonce the answers are computed with
+    nonce: String,
+
+    /// Opaque value to send back as is
+    opaque: Option<String>,
+
+    /// Whether "qop=auth" is asked for
+    qop: bool,
+
+    /// Requests answered with this nonce so far
+    count: u32,
+}
+
+/// A Calibre content server
+pub struct Calibre {
+    /// HTTP client (keeps connections open between requests)
+    agent: ureq::Agent,
+
+    /// "http://host:port", without a trailing slash
+    base: String,
+
+    /// Id of the library used, e.g. "Calibre_Library"
+    library: String,
+
+    /// Name of that library
+    library_name: String,
+
+    /// User name and password, if configured
+    login: Option<(String, String)>,
+
+    /// Last login challenge of the server (None = none asked yet)
+    challenge: Mutex<Option<Challenge>>,
+}
+
+impl Calibre {
+    /// Connects to the server from [calibre] and picks the library
+    ///
+    /// # Arguments
+    /// * `settings` - The [calibre] settings (URL, library, login)
+    pub fn connect(settings: &CalibreSettings) -> Result<Self, String> {
+        let url = settings.url.as_deref().ok_or("set [calibre] url in config.toml first")?;
+        let login = match (&settings.user, &settings.password) {
+            (Some(user), Some(password)) => Some((user.clone(), password.clone())),
+            _ => None,
+        };
+        let mut calibre = Self {
+            // No overall timeout: a big book (or a paused transfer) takes a while
+            agent: ureq::AgentBuilder::new().timeout_connect(TIMEOUT).timeout_read(TIMEOUT).build(),
+            base: url.trim_end_matches('/').to_string(),
+            library: String::new(),
+            library_name: String::new(),
+            login,
+            challenge: Mutex::new(None),
+        };
+
+        let info: LibraryInfo = calibre.get_json("/ajax/library-info")?;
+        let wanted = settings.library.clone().or(info.default_library);
+        // The library may be given by id ("Calibre_Library") or by name
+        let (id, name) = info
+            .library_map
+            .iter()
+            .find(|(id, name)| wanted.as_deref().is_some_and(|w| w == id.as_str() || w == name.as_str()))
+            .or_else(|| info.library_map.iter().next())
+            .ok_or("the server has no library")?;
+        calibre.library = id.clone();
+        calibre.library_name = name.clone();
+        Ok(calibre)
+    }
+
+    /// Lists the books of the library
+    pub fn books(&self) -> Result<Library, String> {
+        let mut ids = Vec::new();
+        loop {
+            let search: SearchResult = self.get_json(&format!(
+                "/ajax/search/{}?num={}&offset={}&sort=title&sort_order=asc",
+                self.library,
+                PAGE_SIZE,
+                ids.len()
+            ))?;
+            let done = search.book_ids.is_empty();
+            ids.extend(search.book_ids);
+            if done || ids.len() >= search.total_num {
+                break;
+            }
+        }
+
+        let mut books = Vec::with_capacity(ids.len());
+        for batch in ids.chunks(METADATA_BATCH) {
+            let list: Vec<String> = batch.iter().map(|id| id.to_string()).collect();
+            let metadata: BTreeMap<String, Option<BookMetadata>> =
+                self.get_json(&format!("/ajax/books/{}?ids={}", self.library, list.join(",")))?;
+            // Kept in the order of the search (by title)
+            for id in batch {
+                let Some(Some(book)) = metadata.get(&id.to_string()) else {
+                    continue;
+                };
+                books.push(CalibreBook {
+                    id: *id,
+                    title: book.title.clone(),
+                    authors: book.authors.clone(),
+                    series: book.series.clone(),
+                    size: book.format_metadata.get("epub").and_then(|f| f.size),
+                    has_epub: book.formats.iter().any(|f| f.eq_ignore_ascii_case("epub")),
+                });
+            }
+        }
+
+        tracing::info!(server = %self.base, library = %self.library, books = books.len(), "Calibre library listed");
+        Ok(Library {
+            name: self.library_name.clone(),
+            books,
+        })
+    }
+
+    /// Downloads the EPUB of a book into a folder
+    ///
+    /// The file is fetched in ranges, and resumes after a failure (see
+    /// `transfer::fetch_chunked`).
+    ///
+    /// # Arguments
+    /// * `book` - The book
+    /// * `folder` - Where the file goes (a free name is picked)
+    /// * `progress` - The transfer the download is shown as
+    ///
+    /// # Returns
+    /// Path of the downloaded file
+    pub fn download(&self, book: &CalibreBook, folder: &Path, progress: &Progress) -> Result<PathBuf, String> {
+        let path = format!("/get/EPUB/{}/{}", book.id, self.library);
+        std::fs::create_dir_all(folder).map_err(|e| e.to_string())?;
+        let partial = crate::peer::partial_path(folder, &book.file_name(), &format!("{}{}", self.base, path));
+        transfer::fetch_chunked(&partial, book.size, progress, |start, end| {
+            let response = self.get_range(&path, start, end)?;
+            let (start, total) = Chunk::position(&response);
+            Ok(Chunk {
+                tag: Chunk::tag(&response),
+                reader: response.into_reader(),
+                start,
+                total,
+            })
+        })?;
+
+        let dest = crate::peer::free_path(folder, &book.file_name());
+        transfer::complete(&partial, &dest, book.size, None)?;
+        Ok(dest)
+    }
+
+    /// Adds a book file to the library
+    ///
+    /// # Arguments
+    /// * `source` - The EPUB file
+    /// * `duplicates` - Add it even if Calibre has the same book already
+    /// * `progress` - The transfer the upload is shown as
+    ///
+    /// # Returns
+    /// Id of the new book in the Calibre library
+    pub fn upload(&self, source: &Path, duplicates: bool, progress: &Progress) -> Result<u64, String> {
+        let file = File::open(source).map_err(|e| format!("{}: {}", source.display(), e))?;
+        let size = file.metadata().map_err(|e| e.to_string())?.len();
+        progress.set_total(size);
+        let name = source.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let path = format!(
+            "/cdb/add-book/0/{}/{}/{}",
+            if duplicates { "y" } else { "n" },
+            crate::opds::percent_encode(&name),
+            self.library
+        );
+
+        // The body can't be sent twice: the login challenge (if any) was
+        // answered when connecting
+        let added: AddedBook = self
+            .request("POST", &path)
+            .set("Content-Length", &size.to_string())
+            .send(progress.reader(file))
+            .map_err(http_error)?
+            .into_json()
+            .map_err(|e| e.to_string())?;
+        match (added.book_id, added.duplicates) {
+            (Some(id), _) => {
+                tracing::info!(book = %name, id, "book added to Calibre");
+                Ok(id)
+            }
+            (None, Some(_)) => Err("already in the Calibre library".to_string()),
+            (None, None) => Err("the server added nothing".to_string()),
+        }
+    }
+
+    /// Asks the server for JSON
+    fn get_json<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T, String> {
+        self.get(path)?.into_json().map_err(|e| e.to_string())
+    }
+
+    /// Sends a GET request, logging in when the server asks to
+    ///
+    /// # Arguments
+    /// * `path` - Path and query, e.g. "/ajax/library-info"
+    fn get(&self, path: &str) -> Result<ureq::Response, String> {
+        self.get_with(path, None)
+    }
+
+    /// Asks for the bytes `start..=end` of a file
+    fn get_range(&self, path: &str, start: u64, end: u64) -> Result<ureq::Response, String> {
+        self.get_with(path, Some(format!("bytes={}-{}", start, end)))
+    }
+
+    /// Sends a GET request with an optional Range header, logging in when
+    /// the server asks to
+    fn get_with(&self, path: &str, range: Option<String>) -> Result<ureq::Response, String> {
+        let request = |path: &str| match &range {
+            Some(range) => self.request("GET", path).set("Range", range),
+            None => self.request("GET", path),
+        };
+        match request(path).call() {
+            Ok(response) => Ok(response),
+            // A first (or stale) challenge: answer it once
+            Err(ureq::Error::Status(401, response)) if self.login.is_some() => {
+                let fresh = response.header("WWW-Authenticate").and_then(parse_challenge);
+                if fresh.is_none() {
+                    return Err(status_error(401));
+                }
+                if let Ok(mut challenge) = self.challenge.lock() {
+                    *challenge = fresh;
+                }
+                request(path).call().map_err(http_error)
+            }
+            Err(e) => Err(http_error(e)),
+        }
+    }
+
+    /// Starts a request with the login header
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        let request = self.agent.request(method, &format!("{}{}", self.base, path));
+        match self.authorization(method, path) {
+            Some(auth) => request.set("Authorization", &auth),
+            None => request,
+        }
+    }
+
+    /// Value of the Authorization header for a request (None = no login)
+    ///
+    /// Before the server asked, the password only goes out over HTTPS
+    /// (Calibre asks for basic logins there, for digest ones over HTTP).
+    fn authorization(&self, method: &str, uri: &str) -> Option<String> {
+        let (user, password) = self.login.as_ref()?;
+        let basic = || format!("Basic {}", STANDARD.encode(format!("{}:{}", user, password)));
+        let mut guard = self.challenge.lock().ok()?;
+        let challenge = match guard.as_mut() {
+            Some(Challenge::Digest(digest)) => digest,
+            Some(Challenge::Basic) => return Some(basic()),
+            None if self.base.starts_with("https:") => return Some(basic()),
+            None => return None,
+        };
+
+        challenge.count += 1;
+        let ha1 = md5_hex(format!("{}:{}:{}", user, challenge.realm, password).as_bytes());
+        let ha2 = md5_hex(format!("{}:{}", method, uri).as_bytes());
+        let nc = format!("{:08x}", challenge.count);
+        let cnonce = md5_hex(format!("{}:{}:{}", crate::book::unix_now(), uri, challenge.count).as_bytes());
+        let mut header = format!(
+            "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", algorithm=MD5",
+            user, challenge.realm, challenge.nonce, uri
+        );
+        if challenge.qop {
+            let response = md5_hex(format!("{}:{}:{}:{}:auth:{}", ha1, challenge.nonce, nc, cnonce, ha2).as_bytes());
+            header.push_str(&format!(", qop=auth, nc={}, cnonce=\"{}\", response=\"{}\"", nc, cnonce, response));
+        } else {
+            let response = md5_hex(format!("{}:{}:{}", ha1, challenge.nonce, ha2).as_bytes());
+            header.push_str(&format!(", response=\"{}\"", response));
+        }
+        if let Some(opaque) = &challenge.opaque {
+            header.push_str(&format!(", opaque=\"{}\"", opaque));
+        }
+        Some(header)
+    }
+}
+
+/// Says what went wrong with a request
+fn http_error(error: ureq::Error) -> String {
+    match error {
+        ureq::Error::Status(code, _) => status_error(code),
+        other => other.to_string(),
+    }
+}
+
+/// Says what an HTTP error status means for a Calibre server
+fn status_error(code: u16) -> String {
+    match code {
+        401 => "login refused - check [calibre] user and password".to_string(),
+        403 => "not allowed - the Calibre user needs write access (or start the server with --enable-local-write)"
+            .to_string(),
+        404 => "not found on the server".to_string(),
+        code => format!("the server answered {}", code),
+    }
+}
+
+/// Reads a login challenge, e.g. `Basic realm="calibre"` or
+/// `Digest realm="calibre", nonce="...", qop="auth", algorithm="MD5"`
+///
+/// # Returns
+/// None for schemes we can't answer
+fn parse_challenge(header: &str) -> Option<Challenge> {
+    let (scheme, params) = header.trim().split_once(' ').unwrap_or((header.trim(), ""));
+    if scheme.eq_ignore_ascii_case("basic") {
+        return Some(Challenge::Basic);
+    }
+    if !scheme.eq_ignore_ascii_case("digest") {
+        return None;
+    }
+
+    // key=value or key="value, with commas", separated by commas
+    let mut values = BTreeMap::new();
+    let mut rest = params.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_lowercase();
+        let after = after.trim_start();
+        let (value, next) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+            }
+            None => {
+                let end = after.find(',').unwrap_or(after.len());
+                (after[..end].trim(), &after[end..])
+            }
+        };
+        values.insert(key, value.to_string());
+        rest = next.trim_start().trim_start_matches(',');
+    }
+
+    Some(Challenge::Digest(Digest {
+        realm: values.get("realm").cloned().unwrap_or_default(),
+        nonce: values.get("nonce")?.clone(),
+        opaque: values.get("opaque").cloned(),
+        qop: values
+            .get("qop")
+            .is_some_and(|qop| qop.split(',').any(|q| q.trim() == "auth")),
+        count: 0,
+    }))
+}
+
+/// MD5 of some bytes, as lower case hex (digest logins need it)
+fn md5_hex(data: &[u8]) -> String {
+    const SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+
+    // Constants: the integer part of |sin(i + 1)| * 2^32
+    let k: Vec<u32> = (0..64).map(|i| ((i as f64 + 1.0).sin().abs() * 4_294_967_296.0) as u32).collect();
+    let mut state: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+
+    // Padding: 0x80, zeros, then the length in bits (little endian)
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    for block in message.chunks(64) {
+        let words: Vec<u32> = block
+            .chunks(4)
+            .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+            .collect();
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(k[i])
+                .wrapping_add(words[g])
+                .rotate_left(SHIFTS[(i / 16) * 4 + i % 4]);
+            (a, d, c) = (d, c, b);
+            b = b.wrapping_add(rotated);
+        }
+        for (s, v) in state.iter_mut().zip([a, b, c, d]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+
+    state.iter().flat_map(|s| s.to_le_bytes()).map(|b| format!("{:02x}", b)).collect()
+}
@@ -1,86 +1,698 @@
 // src/config.rs
 // Configuration and command-line argument parsing
+//
+// funkhunt [OPTIONS] [COMMAND] [ARGUMENTS] [COMMAND OPTIONS]
+//
+// The command is the first argument that isn't an option (`tui`, the
+// terminal interface, when there is none - so `funkhunt ~/Books` still opens
+// it). Every command has its own options, which go after it; the global
+// options (--library, --config, --log-level, --help) go anywhere. The flags
+// older versions had instead of commands (`--export FILE`, `--opds-port PORT`,
+// `--import-calibre DIR`...) are still understood.
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::convert::Format;
+use crate::duplicates::Resolution;
+use crate::exit::ErrorFormat;
+use crate::extract::TextFormat;
+use crate::fonts::Action as FontAction;
+use crate::listing::ListFormat;
+use crate::filter::Filter;
+use crate::profile::DEFAULT_PROFILE;
+use crate::sort::SortOrder;
+
+/// Flags of older versions, and the command (with its options) each one is now
+const LEGACY_FLAGS: [(&str, &[&str]); 8] = [
+    ("--export", &["export"]),
+    ("--export-userdata", &["export", "--userdata"]),
+    ("--export-highlights", &["export", "--highlights"]),
+    ("--import-calibre", &["import", "--calibre"]),
+    ("--import-goodreads", &["import", "--goodreads"]),
+    ("--import-userdata", &["import", "--userdata"]),
+    ("--opds-port", &["serve", "--port"]),
+    ("--convert", &["convert"]),
+];
 
 /// Application configuration parsed from command-line arguments
 /// #[derive(Debug)] allows pretty-printing the struct for debugging
 #[derive(Debug)]
 pub struct Config {
-    /// List of paths to scan for EPUB files
+    /// What to do (`tui` unless another command was given)
+    pub command: Command,
+
+    /// Folders to scan, for the commands that open the library with them
+    /// (tui, scan, serve, daemon): they become the library's scan roots
     pub scan_paths: Vec<PathBuf>,
 
-    /// Whether user requested help (--help or -h)
+    /// Files the other commands work on, in order (e.g. `join`'s OUTPUT, then its BOOKs)
+    pub files: Vec<PathBuf>,
+
+    /// Name of the library profile to open (`--library NAME` or FUNKHUNT_LIBRARY)
+    pub library: String,
+
+    /// Configuration file to use instead of the default one (`--config FILE` or FUNKHUNT_CONFIG)
+    pub settings_path: Option<PathBuf>,
+
+    /// What `export` writes (`--userdata`, `--highlights`; default the library as JSON)
+    pub export: ExportKind,
+
+    /// What `import` reads (`--calibre`, `--goodreads` or `--userdata`)
+    pub import: Option<ImportKind>,
+
+    /// How `list` writes the books (`--json`, `--csv`; default `--plain`)
+    pub list_format: ListFormat,
+
+    /// Filter expression (see filter.rs) of the books `list` shows, made of its
+    /// `--author`, `--tag`, `--ext` and `--min-size` options (all must match)
+    pub list_filter: String,
+
+    /// Order `list` shows the books in (`--sort ORDER`, default library order)
+    pub list_sort: SortOrder,
+
+    /// Whether `stats` writes JSON instead of a table (`--json`)
+    pub stats_json: bool,
+
+    /// What `dedupe` does with the extra copies of identical files (`--apply delete|hardlink`;
+    /// None = only print them)
+    pub dedupe_apply: Option<Resolution>,
+
+    /// Thread counts `bench` tries (`--threads N,N...`; empty = bench::default_threads())
+    pub bench_threads: Vec<usize>,
+
+    /// Runs of each of `bench`'s measures (`--runs N`, default bench::DEFAULT_RUNS)
+    pub bench_runs: Option<usize>,
+
+    /// Port for `serve` and `daemon` (`--port PORT`, default `server::DEFAULT_PORT`)
+    pub port: Option<u16>,
+
+    /// Format of `extract`'s text (`--format txt|md`, default plain text)
+    pub extract_format: Option<TextFormat>,
+
+    /// What `fonts` does to the fonts instead of listing them (`--strip` or `--subset`)
+    pub fonts_action: Option<FontAction>,
+
+    /// Title of `join`'s anthology (`--title TITLE`, default the output's file name)
+    pub join_title: Option<String>,
+
+    /// Format `convert` converts to (`--to FORMAT`, default EPUB)
+    pub convert_to: Option<Format>,
+
+    /// Other library to merge into the profile (`tui --merge FILE`), if requested
+    pub merge: Option<PathBuf>,
+
+    /// Library file to sync the profile with (`tui --sync FILE|URL`), if requested
+    pub sync: Option<PathBuf>,
+
+    /// Most verbose log level written to the log file (`--log-level LEVEL`)
+    pub log_level: String,
+
+    /// How a failure is written to stderr (`--errors text|json`, see exit.rs)
+    pub errors: ErrorFormat,
+
+    /// Where the time spent scanning, drawing... is written (`--profile FILE`,
+    /// builds with the `profiling` feature, see profiling.rs), if requested
+    pub profile: Option<PathBuf>,
+
+    /// Whether user requested help (--help or -h, or `help [COMMAND]`)
     pub show_help: bool,
+
+    /// What's wrong with the command line (None = nothing), shown with the usage
+    pub error: Option<String>,
+}
+
+/// The commands, each with its own arguments and options
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Command {
+    /// The terminal interface
+    #[default]
+    Tui,
+    /// Scan the library's folders and save the library, without the interface
+    Scan,
+    /// Print the books of the library
+    List,
+    /// Write the library, its user data or its highlights to a file
+    Export,
+    /// Bring books or user data in from Calibre, Goodreads or a user-data file
+    Import,
+    /// Serve the library over HTTP
+    Serve,
+    /// Run the watcher, the server, peers and sync without the interface
+    Daemon,
+    /// Print the books that look like duplicates
+    Dedupe,
+    /// Print the library's totals
+    Stats,
+    /// Write the text of a book to stdout
+    Extract,
+    /// Make a book smaller
+    Optimize,
+    /// List, strip or subset the fonts embedded in a book
+    Fonts,
+    /// Split an omnibus into its parts
+    Split,
+    /// Merge books into an anthology
+    Join,
+    /// Convert a file with ebook-convert
+    Convert,
+    /// Time the scanner on a folder
+    Bench,
+}
+
+/// What `export` writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportKind {
+    /// The library as JSON
+    #[default]
+    Library,
+    /// Tags and ratings keyed by content hash (`--userdata`)
+    UserData,
+    /// The passages highlighted in the reader, as notes (`--highlights`)
+    Highlights,
+}
+
+/// What `import` reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportKind {
+    /// A Calibre library folder (`--calibre`)
+    Calibre,
+    /// A Goodreads CSV export (`--goodreads`)
+    Goodreads,
+    /// A file written by `export --userdata` (`--userdata`)
+    UserData,
+}
+
+impl Command {
+    /// Every command, in the order the help lists them
+    pub const ALL: [Command; 16] = [
+        Command::Tui,
+        Command::Scan,
+        Command::List,
+        Command::Export,
+        Command::Import,
+        Command::Serve,
+        Command::Daemon,
+        Command::Dedupe,
+        Command::Stats,
+        Command::Extract,
+        Command::Optimize,
+        Command::Fonts,
+        Command::Split,
+        Command::Join,
+        Command::Convert,
+        Command::Bench,
+    ];
+
+    /// Name typed on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::Tui => "tui",
+            Command::Scan => "scan",
+            Command::List => "list",
+            Command::Export => "export",
+            Command::Import => "import",
+            Command::Serve => "serve",
+            Command::Daemon => "daemon",
+            Command::Dedupe => "dedupe",
+            Command::Stats => "stats",
+            Command::Extract => "extract",
+            Command::Optimize => "optimize",
+            Command::Fonts => "fonts",
+            Command::Split => "split",
+            Command::Join => "join",
+            Command::Convert => "convert",
+            Command::Bench => "bench",
+        }
+    }
+
+    /// The command a name is for, e.g. "serve"
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|command| command.name() == name)
+    }
+
+    /// Arguments and options after the command's name, for its usage line
+    fn synopsis(self) -> &'static str {
+        match self {
+            Command::Tui => "[PATH...] [--merge FILE] [--sync FILE|URL]",
+            Command::Scan => "[PATH...]",
+            Command::List => "[PATH...] [--json | --csv | --plain] [--author NAME] [--tag TAG] [--ext EXT] [--min-size SIZE] [--sort ORDER]",
+            Command::Dedupe => "[PATH...] [--apply delete|hardlink]",
+            Command::Stats => "[--json]",
+            Command::Export => "[--userdata | --highlights] [FILE]",
+            Command::Import => "--calibre DIR | --goodreads FILE | --userdata FILE",
+            Command::Serve | Command::Daemon => "[PATH...] [--port PORT]",
+            Command::Extract => "BOOK [--format txt|md]",
+            Command::Optimize | Command::Split => "BOOK",
+            Command::Fonts => "BOOK [--strip | --subset]",
+            Command::Join => "OUTPUT BOOK... [--title TITLE]",
+            Command::Convert => "FILE [--to FORMAT]",
+            Command::Bench => "PATH [--threads N,N...] [--runs N]",
+        }
+    }
+
+    /// e.g. "fonts BOOK [--strip | --subset]"
+    fn usage(self) -> String {
+        format!("{} {}", self.name(), self.synopsis()).trim_end().to_string()
+    }
+
+    /// What the command does, for the help
+    fn about(self) -> &'static [&'static str] {
+        match self {
+            Command::Tui => &[
+                "The terminal interface (the default command). PATHs become the library's",
+                "folders, scanned anew. --merge FILE opens it with a merge of another library",
+                "(its library.json or an export) to review, choosing between conflicting",
+                "ratings, statuses...; --sync FILE|URL syncs both ways with a library.json",
+                "(local or e.g. sftp://...): newer user data wins, edits on both sides are",
+                "settled in the app, remote books missing on one side are added to it",
+            ],
+            Command::Scan => &[
+                "Scan the library's folders (PATHs become them) and save the library, without",
+                "the interface: new files are added, books whose files are gone removed, the",
+                "user data of the others kept",
+            ],
+            Command::List => &[
+                "Print the books of the library, or of the PATHs (scanned, the library left",
+                "alone): title, authors and file separated by tabs (--plain), or their",
+                "metadata - file, title, authors, series, language, publisher, date, ISBN,",
+                "tags, status, size - as a JSON array (--json) or CSV (--csv). --author NAME",
+                "(part of it), --tag TAG, --ext EXT and --min-size SIZE (e.g. 1mb) keep the",
+                "books the app's filters author: tag: ext: size:>= find, archived books left",
+                "out as there; --sort orders them by title, author, rating, added, opened or",
+                "series",
+            ],
+            Command::Export => &[
+                "Write the library as JSON to FILE (default: -, stdout); --userdata: tags and",
+                "ratings keyed by content hash (portable), --highlights: the passages",
+                "highlighted in the reader as notes (Org for a .org FILE, else Markdown)",
+            ],
+            Command::Import => &[
+                "Import books, tags, series and ratings from a Calibre library; apply ratings",
+                "and shelves (as tags) from a Goodreads CSV export; or reattach the tags and",
+                "ratings of an `export --userdata` file to the matching files",
+            ],
+            Command::Serve => &[
+                "Serve the library over HTTP: a web page to search it, see the covers,",
+                "download books and set their reading status, plus the OPDS catalog (for",
+                "ereader apps), a JSON API under /api and Prometheus metrics on /metrics",
+                "(default port 8080)",
+            ],
+            Command::Daemon => &[
+                "Run without the TUI (e.g. as a systemd service): watches the folders and the",
+                "inbox, serves HTTP as `serve` does, answers peers, accepts books offered by",
+                "paired peers and applies their syncs; logs to the log file. A TUI elsewhere",
+                "uses it as a peer",
+            ],
+            Command::Dedupe => &[
+                "Print the identical files of the library, or of the PATHs (found by content",
+                "hash), a group per paragraph, then the books that look like the same work",
+                "(same title and author, or ISBN). --apply delete deletes the extra copies of",
+                "identical files (the one kept first: the first with tags, rating...), their",
+                "tags and ratings merged into it, --apply hardlink makes them hardlinks of it.",
+                "Both files are hashed again first; a copy whose user data differs is kept.",
+                "Books of the library found in the PATHs are updated in it the same way.",
+            ],
+            Command::Stats => &[
+                "Print the library's totals - books by format, authors, size of the files,",
+                "the largest ones - as a table, or as JSON (--json). The statistics screen",
+                "(w) shows the same",
+            ],
+            Command::Extract => &[
+                "Write the text of an EPUB to stdout, as plain text (a line per paragraph) or",
+                "Markdown",
+            ],
+            Command::Optimize => &[
+                "Make an EPUB smaller: images recompressed without loss (their metadata",
+                "dropped), files nothing refers to removed, all compressed at the best level.",
+                "Prints the size saved",
+            ],
+            Command::Fonts => &[
+                "List the fonts embedded in an EPUB with their sizes; --strip removes them,",
+                "--subset keeps only the characters the book uses (TrueType fonts). The book",
+                "is backed up first",
+            ],
+            Command::Split => &[
+                "Split an omnibus EPUB into a book per top-level entry of its table of",
+                "contents, written next to it",
+            ],
+            Command::Join => &[
+                "Merge EPUBs into one anthology, with a table of contents of the books and",
+                "theirs (title: OUTPUT's name by default)",
+            ],
+            Command::Convert => &[
+                "Convert FILE with Calibre's ebook-convert to epub (default: added to the",
+                "library's first folder), azw3, mobi or pdf (written next to FILE)",
+            ],
+            Command::Bench => &[
+                "Time the scanner on the EPUBs of PATH - scanning, reading metadata, hashing -",
+                "with each thread count of --threads (default 1, 2, 4... up to the number of",
+                "processors), the best of --runs runs (default 3), and print files and MB per",
+                "second. Nothing is written",
+            ],
+        }
+    }
+
+    /// The options the command takes (the global ones aside)
+    fn options(self) -> &'static [&'static str] {
+        match self {
+            Command::Tui => &["--merge", "--sync"],
+            Command::List => &["--json", "--csv", "--plain", "--author", "--tag", "--ext", "--min-size", "--sort"],
+            Command::Dedupe => &["--apply"],
+            Command::Stats => &["--json"],
+            Command::Export => &["--userdata", "--highlights"],
+            Command::Import => &["--calibre", "--goodreads", "--userdata"],
+            Command::Serve | Command::Daemon => &["--port"],
+            Command::Extract => &["--format"],
+            Command::Fonts => &["--strip", "--subset"],
+            Command::Join => &["--title"],
+            Command::Convert => &["--to"],
+            Command::Bench => &["--threads", "--runs"],
+            _ => &[],
+        }
+    }
+
+    /// Whether the command's arguments are folders to scan (the library's
+    /// scan roots) rather than files it works on
+    pub fn takes_folders(self) -> bool {
+        matches!(self, Command::Tui | Command::Scan | Command::Serve | Command::Daemon)
+    }
+
+    /// Fewest and most files the command takes (None = no limit)
+    fn files(self) -> (usize, Option<usize>) {
+        match self {
+            Command::Tui | Command::Scan | Command::Serve | Command::Daemon => (0, None),
+            Command::List | Command::Dedupe => (0, None),
+            Command::Stats => (0, Some(0)),
+            Command::Export => (0, Some(1)),
+            Command::Import | Command::Extract | Command::Optimize | Command::Fonts | Command::Split | Command::Convert | Command::Bench => {
+                (1, Some(1))
+            }
+            Command::Join => (3, None),
+        }
+    }
 }
 
 impl Config {
     /// Parses command-line arguments and returns a Config struct
     ///
     /// # Examples of valid command lines:
-    /// - `funkhunt` - No paths, starts with empty library
-    /// - `funkhunt ~/Books` - Scans ~/Books for EPUBs
+    /// - `funkhunt` - No paths, opens the default library
+    /// - `funkhunt ~/Books` - Scans ~/Books for EPUBs (same as `funkhunt tui ~/Books`)
     /// - `funkhunt ~/Books ~/Documents/EPUBs` - Scans multiple paths
-    /// - `funkhunt -h` or `funkhunt --help` - Shows help and exits
+    /// - `funkhunt --library work` - Opens the library profile named "work"
+    /// - `funkhunt --config test.toml` - Uses test.toml instead of the default config file
+    /// - `funkhunt scan` - Rescans the library's folders and saves it, without the TUI
+    /// - `funkhunt list` - Prints the books of the library
+    /// - `funkhunt list ~/Downloads --json` - Scans a folder and prints its books' metadata as JSON
+    /// - `funkhunt list --author tolkien --ext epub --sort series` - Prints some books of the library, in an order
+    /// - `funkhunt export books.json` - Writes the library as JSON and exits (`--export books.json` too)
+    /// - `funkhunt import --calibre ~/Calibre` - Imports a Calibre library and exits
+    /// - `funkhunt import --goodreads export.csv` - Applies Goodreads ratings/shelves and exits
+    /// - `funkhunt export --userdata mine.json` - Writes tags/ratings keyed by content hash
+    /// - `funkhunt import --userdata mine.json` - Reattaches them to files wherever they are now
+    /// - `funkhunt export --highlights notes.md` - Writes the reader's highlights as Markdown (or Org: notes.org)
+    /// - `funkhunt dedupe` - Prints the books that look like duplicates
+    /// - `funkhunt stats` - Prints the library's totals
+    /// - `funkhunt extract book.epub --format md` - Writes the text of a book as Markdown to stdout
+    /// - `funkhunt optimize book.epub` - Makes a book smaller (images recompressed, unused files removed)
+    /// - `funkhunt fonts book.epub --subset` - Keeps only the characters a book uses of its fonts (`--strip` removes them)
+    /// - `funkhunt split omnibus.epub` - Splits an omnibus into a book per top-level entry of its contents
+    /// - `funkhunt join stories.epub a.epub b.epub --title Stories` - Merges books into an anthology
+    /// - `funkhunt convert book.mobi` - Converts a book to EPUB with ebook-convert and adds it to the library
+    /// - `funkhunt convert book.epub --to azw3` - Converts a book to AZW3, written next to it
+    /// - `funkhunt tui --merge other/library.json` - Opens the library with a merge of the other one to review
+    /// - `funkhunt tui --sync sftp://nas/funkhunt/library.json` - Syncs the library both ways with that file
+    /// - `funkhunt dedupe ~/Downloads --apply hardlink` - Makes the identical files of a folder hardlinks of one another
+    /// - `funkhunt serve --port 8080` - Serves the library over HTTP (web page, downloads, OPDS, JSON API, metrics)
+    /// - `funkhunt bench ~/Books --threads 1,4` - Times scanning, metadata and hashing with 1 and 4 threads
+    /// - `funkhunt daemon` - Runs the watcher, HTTP server, peers and sync without the TUI (e.g. under systemd)
+    /// - `funkhunt -h`, `funkhunt --help` or `funkhunt help [COMMAND]` - Shows help and exits
     ///
     /// # Returns
-    /// A Config struct with parsed arguments
+    /// A Config struct with parsed arguments (`error` says what's wrong, if anything)
     pub fn from_args() -> Self {
+        // Environment variables give the defaults, flags override them
+        let env = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+
+        // Start from defaults and fill in as we walk the arguments
+        let mut config = Self {
+            command: Command::Tui,
+            scan_paths: Vec::new(),
+            files: Vec::new(),
+            library: env("FUNKHUNT_LIBRARY").unwrap_or_else(|| DEFAULT_PROFILE.to_string()),
+            settings_path: env("FUNKHUNT_CONFIG").map(PathBuf::from),
+            export: ExportKind::Library,
+            import: None,
+            list_format: ListFormat::Plain,
+            list_filter: String::new(),
+            list_sort: SortOrder::Library,
+            stats_json: false,
+            dedupe_apply: None,
+            bench_threads: Vec::new(),
+            bench_runs: None,
+            port: None,
+            extract_format: None,
+            fonts_action: None,
+            join_title: None,
+            convert_to: None,
+            merge: None,
+            sync: None,
+            log_level: env("FUNKHUNT_LOG_LEVEL").unwrap_or_else(|| "info".to_string()),
+            errors: ErrorFormat::Text,
+            profile: None,
+            show_help: false,
+            error: None,
+        };
         // Get all command-line arguments except the first one (which is the program name)
-        // std::env::args() returns an iterator of Strings
-        let args: Vec<String> = std::env::args().skip(1).collect();
-
-        // If no arguments provided, return config with empty paths
-        if args.is_empty() {
-            Self {
-                scan_paths: Vec::new(),
-                show_help: false,
+        config.parse(legacy_args(std::env::args().skip(1).collect()));
+        config
+    }
+
+    /// The paths given on the command line that must exist: the folders to
+    /// scan (on this computer) and the files the command reads, not the ones
+    /// it writes (`export`'s FILE, `join`'s OUTPUT)
+    pub fn input_paths(&self) -> Vec<&Path> {
+        let files: &[PathBuf] = match self.command {
+            Command::Export => &[],
+            Command::Join => self.files.get(1..).unwrap_or_default(),
+            _ => &self.files,
+        };
+        self.scan_paths
+            .iter()
+            .filter(|path| !crate::remote::is_remote(path))
+            .chain(files)
+            .map(PathBuf::as_path)
+            .collect()
+    }
+
+    /// Walks the arguments (legacy flags already turned into commands)
+    fn parse(&mut self, args: Vec<String>) {
+        let mut args = args.into_iter();
+        let mut command: Option<Command> = None;
+        let mut arguments: Vec<PathBuf> = Vec::new();
+
+        while let Some(arg) = args.next() {
+            let result = match arg.as_str() {
+                // Help flag - nothing else matters
+                "-h" | "--help" => {
+                    self.show_help = true;
+                    Ok(())
+                }
+
+                // Global options: `--library NAME` (or `-l NAME`, `--library=NAME`),
+                // `--config FILE` (or `-c FILE`), `--log-level LEVEL`, `--errors FORMAT`,
+                // `--profile FILE`
+                "--library" | "-l" => value(&mut args, &arg).map(|name| self.library = name),
+                _ if arg.starts_with("--library=") => {
+                    self.library = arg["--library=".len()..].to_string();
+                    Ok(())
+                }
+                "--config" | "-c" => value(&mut args, &arg).map(|file| self.settings_path = Some(PathBuf::from(file))),
+                "--log-level" => value(&mut args, &arg).map(|level| self.log_level = level),
+                "--errors" => value(&mut args, &arg).and_then(|format| {
+                    self.errors = ErrorFormat::from_name(&format).ok_or(format!("--errors: '{}' is not text or json", format))?;
+                    Ok(())
+                }),
+                "--profile" => value(&mut args, &arg).map(|file| self.profile = Some(PathBuf::from(file))),
+
+                // The command's options, after it (a lone "-" is a file: stdout)
+                _ if arg.starts_with('-') && arg != "-" => {
+                    let command = command.unwrap_or_default();
+                    if command.options().contains(&arg.as_str()) {
+                        self.option(command, &arg, &mut args)
+                    } else {
+                        Err(unknown_option(&arg, command))
+                    }
+                }
+
+                // `help [COMMAND]`
+                "help" if command.is_none() => {
+                    self.show_help = true;
+                    command = args.next().as_deref().and_then(Command::from_name);
+                    Ok(())
+                }
+
+                // The first other argument may be the command
+                _ if command.is_none() => {
+                    match Command::from_name(&arg) {
+                        Some(named) => command = Some(named),
+                        None => {
+                            command = Some(Command::Tui);
+                            arguments.push(PathBuf::from(arg));
+                        }
+                    }
+                    Ok(())
+                }
+
+                // Anything else is a path: a folder to scan or a file to work on
+                _ => {
+                    arguments.push(PathBuf::from(arg));
+                    Ok(())
+                }
+            };
+            // The first mistake is the one shown
+            if let Err(e) = result {
+                self.error.get_or_insert(e);
             }
         }
-        // If user requested help, set show_help flag
-        else if args.contains(&"-h".to_string()) || args.contains(&"--help".to_string()) {
-            Self {
-                scan_paths: Vec::new(),
-                show_help: true,
-            }
+
+        self.command = command.unwrap_or_default();
+        let (fewest, most) = self.command.files();
+        if !self.show_help && (arguments.len() < fewest || most.is_some_and(|most| arguments.len() > most)) {
+            self.error.get_or_insert(format!("wrong number of arguments - usage: funkhunt {}", self.command.usage()));
+        }
+        if self.command == Command::Import && self.import.is_none() && !self.show_help {
+            self.error.get_or_insert("import needs --calibre, --goodreads or --userdata".to_string());
         }
-        // Otherwise, treat all arguments as paths to scan
-        else {
-            // Convert each String argument into a PathBuf
-            let scan_paths: Vec<PathBuf> = args.into_iter().map(PathBuf::from).collect();
-
-            Self {
-                scan_paths,
-                show_help: false,
+        // A bad --min-size (or a quote in a name) is told now, not after the scan
+        if let Err(e) = Filter::parse(&self.list_filter) {
+            self.error.get_or_insert(format!("{} (in {})", e, self.list_filter));
+        }
+        // Exported user data is read back from a file, not a pipe
+        if self.command == Command::Export && self.export == ExportKind::UserData && arguments.is_empty() && !self.show_help {
+            self.error.get_or_insert("export --userdata needs a FILE".to_string());
+        }
+        if self.command.takes_folders() {
+            self.scan_paths = arguments;
+        } else {
+            self.files = arguments;
+        }
+    }
+
+    /// Applies an option of the command
+    fn option(&mut self, command: Command, option: &str, args: &mut impl Iterator<Item = String>) -> Result<(), String> {
+        match option {
+            "--merge" => self.merge = Some(PathBuf::from(value(args, option)?)),
+            "--sync" => self.sync = Some(PathBuf::from(value(args, option)?)),
+            "--userdata" if command == Command::Import => self.import = Some(ImportKind::UserData),
+            "--userdata" => self.export = ExportKind::UserData,
+            "--highlights" => self.export = ExportKind::Highlights,
+            "--json" if command == Command::Stats => self.stats_json = true,
+            "--json" => self.list_format = ListFormat::Json,
+            "--csv" => self.list_format = ListFormat::Csv,
+            "--plain" => self.list_format = ListFormat::Plain,
+            "--calibre" => self.import = Some(ImportKind::Calibre),
+            "--goodreads" => self.import = Some(ImportKind::Goodreads),
+            // The filter terms the app would be given, quoted for the spaces in names
+            "--author" => self.filter_term(format!("author:\"{}\"", value(args, option)?)),
+            "--tag" => self.filter_term(format!("tag:\"{}\"", value(args, option)?)),
+            "--ext" => self.filter_term(format!("ext:{}", value(args, option)?)),
+            "--min-size" => self.filter_term(format!("size:>={}", value(args, option)?)),
+            "--sort" => {
+                let order = value(args, option)?;
+                self.list_sort = SortOrder::from_name(&order)
+                    .ok_or(format!("--sort: '{}' is not title, author, rating, added, opened, series or library", order))?;
+            }
+            "--apply" => {
+                let resolution = value(args, option)?;
+                self.dedupe_apply = Some(Resolution::from_name(&resolution).ok_or(format!("--apply: '{}' is not delete or hardlink", resolution))?);
             }
+            "--threads" => {
+                let counts = value(args, option)?;
+                self.bench_threads = counts
+                    .split(',')
+                    .map(|count| count.trim().parse().ok().filter(|&count: &usize| count > 0))
+                    .collect::<Option<_>>()
+                    .ok_or(format!("--threads: '{}' is not a list of thread counts, e.g. 1,2,4", counts))?;
+            }
+            "--runs" => {
+                let runs = value(args, option)?;
+                self.bench_runs = Some(runs.parse().ok().filter(|&runs: &usize| runs > 0).ok_or(format!("--runs: '{}' is not a number of runs", runs))?);
+            }
+            "--port" => {
+                let port = value(args, option)?;
+                self.port = Some(port.parse().map_err(|_| format!("--port: '{}' is not a port number", port))?);
+            }
+            "--format" => {
+                let format = value(args, option)?;
+                self.extract_format = Some(TextFormat::from_name(&format).ok_or(format!("--format: '{}' is not txt or md", format))?);
+            }
+            "--strip" => self.fonts_action = Some(FontAction::Strip),
+            "--subset" => self.fonts_action = Some(FontAction::Subset),
+            "--title" => self.join_title = Some(value(args, option)?),
+            "--to" => {
+                let format = value(args, option)?;
+                self.convert_to = Some(Format::from_name(&format).ok_or(format!("--to: '{}' is not epub, azw3, mobi or pdf", format))?);
+            }
+            _ => return Err(unknown_option(option, command)),
         }
+        Ok(())
     }
 
-    /// Scans all configured paths and returns all found books
-    ///
-    /// # Returns
-    /// A Vec<Book> containing all EPUB files found in all scan_paths
-    pub fn scan_all_paths(&self) -> Vec<crate::book::Book> {
-        use crate::scanner::scan_epubs;
-
-        // Accumulator for all books across all paths
-        let mut all_books = Vec::new();
-
-        // Scan each path and add results to accumulator
-        for path in &self.scan_paths {
-            let mut books = scan_epubs(path);
-            // append() moves all elements from books into all_books
-            all_books.append(&mut books);
+    /// Adds a term to `list`'s filter
+    fn filter_term(&mut self, term: String) {
+        if !self.list_filter.is_empty() {
+            self.list_filter.push(' ');
         }
+        self.list_filter.push_str(&term);
+    }
+}
+
+/// Turns the flags of older versions into the commands they are now:
+/// `--export FILE` is `export FILE`, moved in front of the other arguments
+fn legacy_args(args: Vec<String>) -> Vec<String> {
+    let Some(at) = args.iter().position(|arg| LEGACY_FLAGS.iter().any(|(flag, _)| flag == arg)) else {
+        return args;
+    };
+    let (_, command) = LEGACY_FLAGS.iter().find(|(flag, _)| *flag == args[at]).copied().unwrap_or_default();
+    let mut rewritten: Vec<String> = command.iter().map(|arg| arg.to_string()).collect();
+    rewritten.extend(args.get(at + 1).cloned());
+    rewritten.extend(args[..at].iter().chain(args.iter().skip(at + 2)).cloned());
+    rewritten
+}
+
+/// The value following an option, e.g. the port after `--port`
+fn value(args: &mut impl Iterator<Item = String>, option: &str) -> Result<String, String> {
+    args.next().ok_or(format!("{} needs a value", option))
+}
 
-        all_books
+/// What's wrong with an option the command doesn't take
+fn unknown_option(option: &str, command: Command) -> String {
+    let commands: Vec<&str> = Command::ALL
+        .into_iter()
+        .filter(|other| other.options().contains(&option))
+        .map(Command::name)
+        .collect();
+    if commands.is_empty() {
+        format!("unknown option {}", option)
+    } else {
+        format!("{} is an option of {}, not of {} (it goes after the command)", option, commands.join(", "), command.name())
+    }
+}
+
+/// Prints the usage of one command: its arguments, options and what it does
+pub fn show_command_usage(command: Command) {
+    println!("Usage: funkhunt [OPTIONS] {}\n", command.usage());
+    for line in command.about() {
+        println!("  {}", line);
     }
 }
 
 /// Prints usage information to stdout
 ///
 /// Shows the user how to use the application, including:
-/// - Available command-line options
+/// - The commands, with their arguments and options
+/// - The global options
 /// - Example usage
 /// - In-app keyboard controls
 pub fn show_usage() {
@@ -88,18 +700,134 @@ pub fn show_usage() {
     println!("=============================\n");
 
     // Command-line usage
-    println!("Usage: funkhunt [PATH...]");
-    println!("       funkhunt -h | --help\n");
+    println!("Usage: funkhunt [OPTIONS] [COMMAND] [ARGUMENTS] [COMMAND OPTIONS]");
+    println!("       funkhunt help [COMMAND]\n");
+
+    // Commands, with what they do
+    println!("Commands:");
+    for command in Command::ALL {
+        println!("  {}", command.usage());
+        for line in command.about() {
+            println!("      {}", line);
+        }
+    }
+    println!();
+
+    // Options
+    println!("Options (before or after the command):");
+    println!("  -c, --config FILE           Use FILE instead of the default config.toml");
+    println!("  -l, --library NAME          Open the named library profile (default: \"default\")");
+    println!("      --log-level LEVEL       error, warn, info (default), debug or trace");
+    println!("      --errors json           Write errors to stderr as a line of JSON: error (its kind),");
+    println!("                              code and message");
+    println!("      --profile FILE          Write the time spent scanning, reading books, drawing and");
+    println!("                              handling keys to FILE as folded stacks, for a flame graph");
+    println!("                              (builds with `cargo build --features profiling`)");
+    println!("  -h, --help                  Show this help (after a command: that command's)");
+    println!("  The flags of older versions still work: --export FILE, --export-userdata FILE,");
+    println!("  --export-highlights FILE, --import-calibre DIR, --import-goodreads FILE,");
+    println!("  --import-userdata FILE, --opds-port PORT (serve) and --convert FILE\n");
+
+    // Exit codes (see exit.rs)
+    println!("Exit codes:");
+    println!("  0 done, 1 input/output error, 2 wrong command line, 3 no books found (list,");
+    println!("  scan, stats, dedupe, bench), 4 a path given doesn't exist, 5 the server failed\n");
+
+    // Environment variables
+    println!("Environment:");
+    println!("  FUNKHUNT_CONFIG             Same as --config");
+    println!("  FUNKHUNT_LIBRARY            Same as --library");
+    println!("  FUNKHUNT_LOG_LEVEL          Same as --log-level");
+    println!("  FUNKHUNT_CONFIG_DIR, FUNKHUNT_DATA_DIR, FUNKHUNT_CACHE_DIR");
+    println!("                              Override where config, libraries and caches live");
+    println!("  FUNKHUNT_<SECTION>_<KEY>    Override a config.toml value, e.g. FUNKHUNT_THEME_HEADER=red\n");
+
+    // Inbox
+    println!("Inbox:");
+    println!("  Set [inbox] folder in config.toml (e.g. ~/Downloads): new EPUBs appearing there");
+    println!("  are imported while funkhunt runs, moved into the library (organize = true uses");
+    println!("  the [organize] template)\n");
+
+    // Remote folders
+    println!("Remote folders:");
+    println!("  A scan root can be on a server: funkhunt sftp://me@nas.local/srv/books");
+    println!("  or, for WebDAV (Nextcloud...): davs://cloud.example.com/remote.php/dav/files/me/Books");
+    println!("  or in an S3 bucket (AWS, MinIO...): s3://my-books/library");
+    println!("  Metadata is read over the connection; books are downloaded to the cache when");
+    println!("  opened. Logins go in config.toml: [remote.\"nas.local\"] user, port, identity");
+    println!("  (SFTP key file; default: the SSH agent) or password (WebDAV app password);");
+    println!("  for S3 buckets: endpoint, region, access_key, secret_key\n");
+
+    // Peers
+    println!("Peers:");
+    println!("  Set [peers] enabled = true in config.toml to announce this library on the local");
+    println!("  network (mDNS) and find other FunkHunt instances. Syncing with a peer (y on the");
+    println!("  peers screen) copies the books and user data the other side is missing\n");
 
     // Usage examples
     println!("Examples:");
-    println!("  funkhunt                    # Start with empty library");
+    println!("  funkhunt                    # Open the default library");
     println!("  funkhunt ~/Books            # Start with specific folder");
+    println!("  funkhunt --library work     # Open the \"work\" library");
+    println!("  funkhunt scan               # Rescan the library's folders, e.g. from cron");
+    println!("  funkhunt list --csv         # The library's books and their metadata as CSV, for scripts");
+    println!("  funkhunt export lib.json    # Back up the library as JSON");
+    println!("  funkhunt serve --port 8080  # Download books from any browser on the LAN (or KOReader)");
+    println!("  funkhunt help fonts         # Show the help of the fonts command");
     println!("  funkhunt -h                 # Show this help\n");
 
     // Keyboard controls inside the app
-    println!("In-app controls:");
-    println!("  a          : Add folder from within the app");
+    println!("In-app controls (letters can be changed in config.toml, reloaded live):");
+    println!("  a          : Add folder from within the app (or paste a folder's path into the terminal)");
+    println!("  f          : Folder settings (read-only, auto-watch, sidecar files, edited");
+    println!("               metadata written into the EPUBs, excludes, default tags)");
+    println!("  c          : Show a collection or the recently added/opened views");
+    println!("               (n: new, m: save filters as smart collection, d: delete)");
+    println!("  + / -      : Add selected book to a collection / remove it from the shown one");
+    println!("  s / S      : Star the selected book / show only starred books");
+    println!("  r / R      : Cycle reading status / filter by reading status");
+    println!("  1-5 / 0    : Rate the selected book / clear its rating");
+    println!("  o          : Change the order of the book list (library, title, author, rating,");
+    println!("               date added, last opened)");
+    println!("  /          : Filter the list as you type, e.g. status:unread tag:fantasy size:<1mb");
+    println!("               Terms: words, title: author: tag: series: lang: collection: ext: status:");
+    println!("               starred archived rating:>=4 size:<1mb grade:<=6 added:<7d opened:any");
+    println!("               modified:<1d,");
+    println!("               accessible feature:alttext hazard:none is:fixed is:read-aloud,");
+    println!("               -term to exclude, \"quotes\" for spaces");
+    println!("  x / X      : Archive the selected book / show archived books");
+    println!("  e          : Show the series of the selected book in reading order");
+    println!("  v          : Mark / unmark the selected book");
+    println!("  E          : Bulk edit the marked books (tags, status, authors, series)");
+    println!("  b          : Edit the selected book's title, authors, series, tags and language (Tab:");
+    println!("               next field, Enter: save - checked first)");
+    println!("  K          : Convert the marked books to AZW3, MOBI or PDF (with ebook-convert)");
+    println!("  W          : Write the text of the marked books to files ([extract] folder and format)");
+    println!("  I          : Set the cover of the selected book: an image file, a URL, or (empty)");
+    println!("               Open Library's cover for its ISBN - embedded into the EPUB");
+    println!("  Z          : Optimize the marked books: images recompressed, unused files removed");
+    println!("  t          : Fonts embedded in the selected book, with their sizes; s strips them, u keeps");
+    println!("               only the characters the book uses (the book is backed up first)");
+    println!("  B / J      : Split the selected omnibus into a book per part / merge the marked");
+    println!("               books into an anthology (its title asked first)");
+    println!("  i          : Show the first pages of the selected book in place of its details (again: hide)");
+    println!("  y / Y      : Quotes captured from books (y in the reader quotes a passage) / quote");
+    println!("               sentences of the selected book's description");
+    println!("  n          : Listen to the selected audiobook or read-aloud book with mpv ([audio] player),");
+    println!("               from where listening stopped (Space: pause, ←→: seek, < >: file, s: stop)");
+    println!("  u / U      : Undo / redo the last edit, removal, move or rename");
+    println!("  m          : Look the selected book up online and review the changes");
+    println!("  H          : Health report (missing, empty or unreadable files)");
+    println!("  w          : Statistics: books by format, authors, size, largest files (as `stats`)");
+    println!("  d          : Books that look like duplicates (same title and author, or ISBN),");
+    println!("               compared side by side (metadata, contents, size) to keep one");
+    println!("  O          : Organize files into the [organize] template (preview first)");
+    println!("  N          : Rename the marked (or shown) books by the rename template (preview first)");
+    println!("  P          : Peers on the local network (Enter: browse a peer's books,");
+    println!("               s: send the marked books, y: sync both ways, d: forget)");
+    println!("  A          : Merge author spellings (\"Tolkien, J.R.R.\" = \"J. R. R. Tolkien\")");
+    println!("  p          : Switch library profile");
+    println!("  L          : Show recent log messages");
     println!("  ↑/↓        : Navigate book list");
     println!("  Enter      : Open selected book");
     println!("  q          : Quit application");
@@ -0,0 +1,162 @@
+// src/convert.rs
+// Format conversion with Calibre's ebook-convert - a book of the library
+// turned into AZW3, MOBI or PDF (written next to it), or a MOBI, AZW3, PDF...
+// turned into an EPUB that joins the library (`--convert FILE`)
+//
+// The converter is [devices] converter, the one copies to Kindles use too.
+// It prints its progress as it goes ("34% Running transforms on e-book..."),
+// so a conversion shows in the transfer list like a download; cancelling it
+// stops the converter. A conversion can't be paused.
+
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// How often a running conversion looks whether it was cancelled
+const CANCEL_POLL: Duration = Duration::from_millis(100);
+
+/// Formats books are converted to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Epub,
+
+    /// Kindle Format 8 (Kindles since 2011)
+    Azw3,
+
+    /// Mobipocket (older Kindles)
+    Mobi,
+
+    Pdf,
+}
+
+impl Format {
+    pub const ALL: [Format; 4] = [Format::Epub, Format::Azw3, Format::Mobi, Format::Pdf];
+
+    /// Formats the books of the library (EPUBs) can be converted to
+    pub const TARGETS: [Format; 3] = [Format::Azw3, Format::Mobi, Format::Pdf];
+
+    /// The format named by an extension, e.g. "azw3" (any case)
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| format.extension().eq_ignore_ascii_case(name))
+    }
+
+    /// Extension of the files in this format, e.g. "azw3"
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Epub => "epub",
+            Format::Azw3 => "azw3",
+            Format::Mobi => "mobi",
+            Format::Pdf => "pdf",
+        }
+    }
+
+    /// What the format is called on screen
+    pub fn label(self) -> &'static str {
+        match self {
+            Format::Epub => "EPUB",
+            Format::Azw3 => "AZW3",
+            Format::Mobi => "MOBI",
+            Format::Pdf => "PDF",
+        }
+    }
+
+    /// What the format is good for, shown next to it when choosing one
+    pub fn description(self) -> &'static str {
+        match self {
+            Format::Epub => "most e-readers and apps",
+            Format::Azw3 => "Kindles since 2011",
+            Format::Mobi => "older Kindles",
+            Format::Pdf => "printing, fixed pages",
+        }
+    }
+}
+
+/// The file a conversion writes: the source's name with the format's
+/// extension, in `folder`
+pub fn output_path(source: &Path, folder: &Path, format: Format) -> PathBuf {
+    let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+    folder.join(format!("{}.{}", stem, format.extension()))
+}
+
+/// Converts a book with the converter (the output's extension says to what)
+///
+/// # Arguments
+/// * `command` - Converter command, e.g. "ebook-convert" (input and output paths are appended)
+/// * `source` - The book to convert
+/// * `output` - The file to write (removed if the conversion fails)
+/// * `progress` - Called with the percentage done, whenever it's printed
+/// * `cancelled` - Asked while the converter runs; it's stopped once this says yes
+pub fn run(
+    command: &str,
+    source: &Path,
+    output: &Path,
+    mut progress: impl FnMut(u8),
+    cancelled: impl Fn() -> bool,
+) -> Result<(), String> {
+    // "ebook-convert --some-flag" -> program "ebook-convert", args ["--some-flag", <in>, <out>]
+    let mut parts = command.split_whitespace();
+    let program = parts.next().ok_or("no converter configured")?;
+    let mut child = Command::new(program)
+        .args(parts)
+        .arg(source)
+        .arg(output)
+        // ebook-convert is Python: unbuffered, its progress comes as it's made
+        .env("PYTHONUNBUFFERED", "1")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("cannot run {} ({}) - install Calibre, or set [devices] converter", program, e))?;
+
+    // Lines are read on a thread of their own, so a cancel isn't stuck
+    // behind a long silent step
+    let (lines, received) = mpsc::channel();
+    if let Some(stdout) = child.stdout.take() {
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if lines.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    // The last line that isn't progress is usually the reason of a failure
+    let mut last = String::new();
+    loop {
+        if cancelled() {
+            let _ = child.kill();
+            let _ = child.wait();
+            let _ = std::fs::remove_file(output);
+            return Err("cancelled".to_string());
+        }
+        match received.recv_timeout(CANCEL_POLL) {
+            Ok(line) => match percent(&line) {
+                Some(done) => progress(done),
+                None if !line.trim().is_empty() => last = line.trim().to_string(),
+                None => {}
+            },
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    let status = child.wait().map_err(|e| e.to_string())?;
+    if !status.success() || !output.is_file() {
+        let _ = std::fs::remove_file(output);
+        return Err(if last.is_empty() {
+            format!("{} failed ({})", program, status)
+        } else {
+            format!("{} failed: {}", program, last)
+        });
+    }
+    Ok(())
+}
+
+/// The percentage a progress line of ebook-convert starts with ("34% Running...")
+fn percent(line: &str) -> Option<u8> {
+    let (number, _) = line.trim_start().split_once('%')?;
+    number.parse::<u8>().ok().map(|done| done.min(100))
+}
@@ -0,0 +1,73 @@
+// src/cover.rs
+// New covers for books - an image file, an image on the web, or the cover
+// Open Library has for the book's ISBN (I in the app), embedded into the
+// EPUB by epub::write_cover
+
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Open Library's covers by ISBN ("{isbn}-L.jpg" is the large one;
+/// default=false answers 404 instead of a blank image when there's none)
+const OPEN_LIBRARY_COVERS: &str = "https://covers.openlibrary.org/b/isbn";
+
+/// Open Library asks clients to identify themselves
+const USER_AGENT: &str = concat!("FunkHunt/", env!("CARGO_PKG_VERSION"), " (cover download)");
+
+/// How long to wait for an image's server before giving up
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Largest image taken, in bytes
+const MAX_SIZE: u64 = 20 * 1024 * 1024;
+
+/// Reads the image a cover comes from
+///
+/// # Arguments
+/// * `source` - Path of an image file ("~" is the home folder), an http(s)
+///   URL, or "" for the cover of the book's ISBN on Open Library
+/// * `isbn` - The book's ISBN, if known
+///
+/// # Returns
+/// The image, or why there's none
+pub fn load(source: &str, isbn: Option<&str>) -> Result<Vec<u8>, String> {
+    let source = source.trim();
+    if source.is_empty() {
+        let isbn = isbn.ok_or("the book has no ISBN to find its cover - give a file or a URL")?;
+        return download(&format!("{}/{}-L.jpg?default=false", OPEN_LIBRARY_COVERS, isbn))
+            .map_err(|e| format!("Open Library: {}", e));
+    }
+    if source.starts_with("http://") || source.starts_with("https://") {
+        return download(source);
+    }
+
+    let path = match (source.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(source),
+    };
+    std::fs::read(&path).map_err(|e| format!("cannot read {} ({})", path.display(), e))
+}
+
+/// Downloads an image
+fn download(url: &str) -> Result<Vec<u8>, String> {
+    let response = ureq::get(url)
+        .timeout(TIMEOUT)
+        .set("User-Agent", USER_AGENT)
+        .call()
+        .map_err(|e| match e {
+            ureq::Error::Status(404, _) => "no such image".to_string(),
+            ureq::Error::Status(code, _) => format!("the server answered {}", code),
+            other => other.to_string(),
+        })?;
+
+    let mut image = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_SIZE + 1)
+        .read_to_end(&mut image)
+        .map_err(|e| e.to_string())?;
+    if image.len() as u64 > MAX_SIZE {
+        return Err(format!("the image is over {} MB", MAX_SIZE / 1024 / 1024));
+    }
+    tracing::info!(url, bytes = image.len(), "cover downloaded");
+    Ok(image)
+}
@@ -0,0 +1,182 @@
+// src/daemon.rs
+// Headless mode (`funkhunt daemon`) - everything but the interface, for a
+// library server running as a service (systemd, a NAS...)
+//
+// One loop does what the TUI's event loop does between two keys: watched
+// folders and the inbox are rescanned, the HTTP server of serve mode answers
+// (web page, OPDS, JSON API, metrics), peers are announced and answered,
+// transfers finish, webhooks are sent and the library is autosaved. What
+// needs someone to ask goes by rules instead:
+// - books offered by paired peers are accepted, other offers declined
+// - pairing needs the PIN shown on both screens, so peers asking to pair
+//   are turned away (403)
+// - peers asking for a book that needs an approval are refused
+// A TUI on another machine (or under another profile) uses the daemon as a
+// peer: it browses its books, downloads them and offers it books. Syncing
+// user data with it needs a pairing, made from the TUI of the daemon's
+// machine before the daemon starts (it reads the trusted peers the TUI
+// stored, once). Everything is logged to the log file (see logging.rs);
+// status messages the TUI would show are logged too.
+//
+// The process has no signal handling: stopping it (SIGTERM) loses at most
+// the edits since the last autosave, so keep [autosave] interval short.
+
+use crate::peer::PeerServer;
+use crate::profile::Profile;
+use crate::server::LibraryServer;
+use crate::session::Autosave;
+use crate::settings::{Settings, SettingsWatcher};
+use crate::tui::TuiState;
+use crate::webhooks::Webhooks;
+use crate::{discovery, fold, remote, sort, trust};
+use std::io;
+use std::time::{Duration, Instant};
+
+/// How long the loop waits for an HTTP request before its other work
+const TICK: Duration = Duration::from_millis(200);
+
+/// Runs the library without the TUI until the process is stopped
+///
+/// # Arguments
+/// * `profile` - The library profile to run
+/// * `state` - Application state holding the books and settings (never drawn)
+/// * `settings_watcher` - Watches config.toml, whose edits apply live
+/// * `port` - TCP port of the HTTP server
+pub fn run(profile: Profile, mut state: TuiState, mut settings_watcher: SettingsWatcher, port: u16) -> io::Result<()> {
+    let mut server = LibraryServer::start(port, &state.books)?;
+    let mut autosave = Autosave::new(state.settings.autosave.interval);
+    let mut folder_watcher = crate::folder_watcher_for(&profile, &state.settings);
+    let mut webhooks = Webhooks::new(&state.settings.webhooks, &profile.name, &state.books);
+
+    state.peers = discovery::load_known();
+    state.trusted = trust::load_trusted();
+    let mut discovery = crate::start_discovery(&profile, &state);
+    let mut peer_server = start_peer_server(&profile, &state);
+
+    println!("Running library '{}' ({} books) without the TUI", profile.name, state.books.len());
+    crate::server::print_urls(port);
+    match &peer_server {
+        Some(_) => println!("Peers:        port {}", state.settings.peers.port),
+        None => println!("Peers:        disabled ([peers] enabled = false)"),
+    }
+    println!("Logging to {}", crate::paths::log_dir().display());
+    tracing::info!(port, library = %profile.name, books = state.books.len(), "daemon started");
+
+    loop {
+        // Apply config.toml edits live
+        if settings_watcher.changed() {
+            match Settings::load(settings_watcher.path()) {
+                Ok(settings) => {
+                    tracing::info!(path = %settings_watcher.path().display(), "config reloaded");
+                    let peers_changed = settings.peers != state.settings.peers;
+                    remote::configure(&settings.remote);
+                    fold::configure(&settings.search);
+                    sort::configure(&settings.sort);
+                    state.settings = settings;
+                    autosave.set_interval(state.settings.autosave.interval);
+                    webhooks.configure(&state.settings.webhooks);
+                    folder_watcher = crate::folder_watcher_for(&profile, &state.settings);
+                    if peers_changed {
+                        drop(discovery.take());
+                        drop(peer_server.take());
+                        discovery = crate::start_discovery(&profile, &state);
+                        peer_server = start_peer_server(&profile, &state);
+                    }
+                }
+                // Keep the previous settings when the new file doesn't parse
+                Err(e) => tracing::warn!(error = %e, "config reload failed"),
+            }
+        }
+
+        // Rescan watched folders whose files changed (once they've settled)
+        for root in folder_watcher.ready_roots() {
+            let started = Instant::now();
+            if state.settings.inbox.folder.as_ref() == Some(&root) {
+                crate::import_inbox(&profile, &mut state);
+            } else {
+                profile.rescan_root(&mut state.books, &root, false);
+                crate::save_profile(&profile, &state.books);
+                tracing::info!(path = %root.display(), books = state.books.len(), "watched folder rescanned");
+            }
+            server.scanned(started.elapsed(), &state.books);
+        }
+
+        // Peers that appeared or left, and our own book count
+        if let Some(discovery) = discovery.as_mut() {
+            if discovery.poll() {
+                state.peers = discovery.peers();
+                if let Err(e) = discovery::save_known(&state.peers) {
+                    tracing::warn!(error = %e, "cannot remember peers");
+                }
+            }
+            if state.books.len() != discovery.book_count() {
+                discovery.set_book_count(state.books.len());
+                if let Some(server) = &peer_server {
+                    server.publish(&state.books);
+                }
+            }
+        }
+
+        if let Some(peers) = &peer_server {
+            // Offers: nobody to ask, so only paired peers are trusted with them
+            for incoming in peers.take_offers() {
+                if incoming.secret.is_some() {
+                    crate::accept_offer(&profile, &mut state, &incoming, &[]);
+                } else {
+                    tracing::info!(from = %incoming.offer.from, "offer declined: peer isn't paired");
+                }
+            }
+
+            // Approvals need someone at the screen (so do pairings, which
+            // the peer server turns away)
+            for request in peers.take_access_requests() {
+                tracing::info!(peer = %request.peer, book = %request.title, "access refused: approvals need the TUI");
+                peers.answer_access(&request, false);
+            }
+
+            // User data settled by peers that synced with us
+            for push in peers.take_sync_pushes() {
+                let from = push.from.clone();
+                let updated = state.apply_sync_push(push);
+                tracing::info!(from = %from, updated, "sync push applied");
+            }
+        }
+
+        // Downloads that ended: add their books to the library
+        for finished in state.transfers.poll() {
+            crate::finish_transfer(&profile, &mut state, &mut webhooks, finished);
+        }
+
+        // Requests of the web page, OPDS readers, API scripts and Prometheus
+        // (only the first one is waited for: the rest of the loop runs every tick)
+        if server.answer(&profile, &mut state.books, TICK)? {
+            while server.answer(&profile, &mut state.books, Duration::ZERO)? {}
+        }
+
+        // Books added, removed or finished since the last pass
+        webhooks.check(&state.books);
+
+        // Periodic autosave
+        if autosave.due() {
+            crate::autosave_now(&profile, &mut state);
+            autosave.saved();
+            webhooks.compare(&state.books);
+            if let Some(server) = &peer_server {
+                server.publish(&state.books);
+            }
+        }
+
+        // What the TUI would have shown in its status bar
+        if let Some(message) = state.status_message.take() {
+            tracing::info!(message = %message, "status");
+        }
+    }
+}
+
+/// Starts answering other peers, if peers are enabled - pairings refused:
+/// nobody is there to compare the PINs
+fn start_peer_server(profile: &Profile, state: &TuiState) -> Option<PeerServer> {
+    let server = crate::start_peer_server(profile, state)?;
+    server.refuse_pairing();
+    Some(server)
+}
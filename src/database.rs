@@ -0,0 +1,290 @@
+// src/database.rs
+// Library database - persists the books of a profile between sessions
+//
+// The file carries a schema version. Older files are migrated step by step
+// when they're opened (after a backup copy is made); files written by a
+// newer FunkHunt are refused instead of being silently mangled.
+//
+// What was last loaded from or saved to each file is kept in memory, so
+// stamping the changes of a save doesn't read the whole file back (unless
+// something else wrote it since).
+
+use crate::book::{Book, UserData};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+/// Schema version written by this build
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Migrations, in order: MIGRATIONS[0] turns a v1 file into v2, and so on
+/// Each one edits the raw JSON, so it doesn't depend on today's Book struct
+const MIGRATIONS: &[fn(&mut Value)] = &[migrate_v1_to_v2, migrate_v2_to_v3];
+
+/// The books of each database file as last loaded or saved
+static SAVED: OnceLock<Mutex<HashMap<PathBuf, Snapshot>>> = OnceLock::new();
+
+/// The books of a database file as last loaded or saved
+struct Snapshot {
+    /// Size and modification time of the file then (it changed if they did)
+    file: (u64, SystemTime),
+
+    /// The books, by path
+    books: HashMap<PathBuf, Book>,
+}
+
+/// On-disk layout of the database file
+#[derive(Default, Serialize, Deserialize)]
+struct DatabaseFile {
+    /// Schema version the file was written with
+    /// (files from before versioning have none - that's version 1)
+    #[serde(default = "first_version")]
+    version: u32,
+
+    /// All books known to the library
+    #[serde(default)]
+    books: Vec<Book>,
+}
+
+/// Version of files written before the schema was versioned
+fn first_version() -> u32 {
+    1
+}
+
+/// Loads the books stored in a database file, migrating old files
+///
+/// # Arguments
+/// * `path` - Path of the database file
+///
+/// # Returns
+/// * `Ok(books)` - The stored books (empty if the file doesn't exist yet)
+/// * `Err` - The file can't be read or parsed, a migration failed, or the file
+///   was written by a newer version of FunkHunt
+pub fn load(path: &Path) -> io::Result<Vec<Book>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        // A new library simply has no database yet
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let (books, version) = decode(path, &text)?;
+
+    if version < SCHEMA_VERSION {
+        // Keep the original around in case a migration went wrong
+        let backup = backup_path(path, version);
+        std::fs::copy(path, &backup)?;
+        tracing::info!(path = %path.display(), backup = %backup.display(), from = version, to = SCHEMA_VERSION, "migrated database");
+
+        // Write the migrated file right away, so the migration only runs once
+        save(path, &books)?;
+    } else {
+        remember(path, &books);
+    }
+
+    Ok(books)
+}
+
+/// Reads the books of another library's database file without touching it
+///
+/// Old files are migrated in memory only (no backup, nothing written).
+///
+/// # Arguments
+/// * `path` - Path of the database file
+pub fn read(path: &Path) -> io::Result<Vec<Book>> {
+    let text = std::fs::read_to_string(path)?;
+    decode(path, &text).map(|(books, _)| books)
+}
+
+/// Parses a database file that was read some other way (e.g. from a server)
+///
+/// # Arguments
+/// * `path` - Where the text came from, for error messages
+/// * `text` - Content of the database file
+pub fn from_text(path: &Path, text: &str) -> io::Result<Vec<Book>> {
+    decode(path, text).map(|(books, _)| books)
+}
+
+/// Writes books in the database format, as text
+pub fn to_text(books: &[Book]) -> io::Result<String> {
+    let file = DatabaseFile {
+        version: SCHEMA_VERSION,
+        books: books.to_vec(),
+    };
+    serde_json::to_string_pretty(&file).map_err(io::Error::other)
+}
+
+/// Stamps `modified` on the books whose user data (or metadata) differs from the saved
+/// database, and carries the saved stamps over to the others
+///
+/// # Arguments
+/// * `path` - The database file the books were last saved to
+/// * `books` - The books of the library (modified in place)
+pub fn stamp_changes(path: &Path, books: &mut [Book]) {
+    let mut snapshots = SAVED.get_or_init(Mutex::default).lock().unwrap_or_else(|e| e.into_inner());
+    let current = file_of(path).is_some_and(|file| snapshots.get(path).is_some_and(|saved| saved.file == file));
+    if !current {
+        // Never loaded or saved here, or written by something else since
+        let Ok(saved) = read(path) else {
+            // Nothing saved yet: nothing to compare with
+            return;
+        };
+        let Some(snapshot) = snapshot_of(path, &saved) else {
+            return;
+        };
+        snapshots.insert(path.to_path_buf(), snapshot);
+    }
+    let Some(saved) = snapshots.get(path) else {
+        return;
+    };
+    let now = crate::book::unix_now();
+
+    for book in books.iter_mut() {
+        let Some(old) = saved.books.get(&book.path) else {
+            continue;
+        };
+        // A placeholder read since got its package's metadata, and the
+        // canonical names of its authors - neither is an edit
+        let filled = old.is_placeholder() && old.user == UserData { authors: old.user.authors.clone(), ..book.user.clone() };
+        if filled || (old.user == book.user && old.meta == book.meta) {
+            book.modified = book.modified.max(old.modified);
+        } else if book.modified <= old.modified {
+            // Edited since the last save (a newer stamp came with a sync and is kept)
+            book.modified = Some(now);
+        }
+    }
+}
+
+/// Parses the text of a database file, migrating it to the current schema
+///
+/// # Returns
+/// The books and the schema version the file was written with
+fn decode(path: &Path, text: &str) -> io::Result<(Vec<Book>, u32)> {
+    let mut value: Value = serde_json::from_str(text).map_err(|e| invalid(path, e))?;
+    let version = value
+        .get("version")
+        .and_then(Value::as_u64)
+        .map(|v| v as u32)
+        .unwrap_or_else(first_version);
+
+    // Never guess at a format we don't know
+    if version > SCHEMA_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} uses database schema v{}, but this FunkHunt only supports up to v{} - please upgrade FunkHunt",
+                path.display(),
+                version,
+                SCHEMA_VERSION
+            ),
+        ));
+    }
+
+    if version < SCHEMA_VERSION {
+        for migration in &MIGRATIONS[version.saturating_sub(1) as usize..] {
+            migration(&mut value);
+        }
+        if let Some(object) = value.as_object_mut() {
+            object.insert("version".to_string(), Value::from(SCHEMA_VERSION));
+        }
+    }
+
+    let file: DatabaseFile = serde_json::from_value(value).map_err(|e| invalid(path, e))?;
+    Ok((file.books, version))
+}
+
+/// Saves books into a database file
+///
+/// Writes to a temporary file first and renames it over the old one,
+/// so a crash mid-write never leaves a half-written database behind.
+///
+/// # Arguments
+/// * `path` - Path of the database file
+/// * `books` - The books to store
+pub fn save(path: &Path, books: &[Book]) -> io::Result<()> {
+    // Stamp what changed since the last save before the old file is gone
+    let mut books = books.to_vec();
+    stamp_changes(path, &mut books);
+    let json = to_text(&books)?;
+
+    // Write next to the real file, then atomically replace it
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, json)?;
+    std::fs::rename(&tmp_path, path)?;
+    remember(path, &books);
+    Ok(())
+}
+
+/// Keeps the books just loaded from or saved to a database file
+fn remember(path: &Path, books: &[Book]) {
+    if let Some(snapshot) = snapshot_of(path, books) {
+        let mut snapshots = SAVED.get_or_init(Mutex::default).lock().unwrap_or_else(|e| e.into_inner());
+        snapshots.insert(path.to_path_buf(), snapshot);
+    }
+}
+
+/// The books of a database file, with the file as it is now (None if it
+/// can't be looked at)
+fn snapshot_of(path: &Path, books: &[Book]) -> Option<Snapshot> {
+    Some(Snapshot {
+        file: file_of(path)?,
+        books: books.iter().map(|book| (book.path.clone(), book.clone())).collect(),
+    })
+}
+
+/// Size and modification time of a file
+fn file_of(path: &Path) -> Option<(u64, SystemTime)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+/// Where the pre-migration copy of a database goes: `library.json.v1.bak`
+fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".v{}.bak", version));
+    path.with_file_name(name)
+}
+
+/// Wraps a JSON error into an io::Error that names the file
+fn invalid(path: &Path, e: serde_json::Error) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), e),
+    )
+}
+
+/// v1 -> v2: introduces the `version` field itself
+///
+/// The book layout is unchanged; this step exists so every later
+/// migration can rely on the file being versioned.
+fn migrate_v1_to_v2(_value: &mut Value) {}
+
+/// v2 -> v3: books get an `added` timestamp
+///
+/// Books that were in the library before this version get the modification
+/// time of their file, the best guess of when they were acquired.
+fn migrate_v2_to_v3(value: &mut Value) {
+    let Some(books) = value.get_mut("books").and_then(Value::as_array_mut) else {
+        return;
+    };
+
+    for book in books.iter_mut().filter_map(Value::as_object_mut) {
+        if book.contains_key("added") {
+            continue;
+        }
+        let modified = book
+            .get("path")
+            .and_then(Value::as_str)
+            .and_then(|path| std::fs::metadata(path).ok())
+            .and_then(|meta| meta.modified().ok())
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+        if let Some(secs) = modified {
+            book.insert("added".to_string(), Value::from(secs));
+        }
+    }
+}
@@ -0,0 +1,279 @@
+// src/device.rs
+// E-readers plugged in over USB - Kindles and Kobos show up as mass
+// storage; books copied into their documents folder appear on the device
+//
+// Devices are found by looking at the mounted volumes (/media/<user>,
+// /run/media/<user>, /Volumes, drive letters) for the folders each kind
+// of reader has. Folders listed in [devices] mounts count as e-readers too.
+//
+// Kindles don't read EPUB: books sent to one are converted first with an
+// external converter (Calibre's ebook-convert by default, see [devices]).
+//
+// Every book sent is recorded in a manifest on the device itself
+// (.funkhunt-sent.json in its documents folder), so we know what's
+// already there - whichever computer sent it.
+
+use crate::book::Book;
+use crate::transfer::Progress;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Manifest file in the documents folder of a device
+const MANIFEST_FILE: &str = ".funkhunt-sent.json";
+
+/// Kind of e-reader, which says where books go and what format they need
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeviceKind {
+    /// Amazon Kindle ("documents" folder, no EPUB)
+    Kindle,
+
+    /// Kobo (any folder, reads EPUB)
+    Kobo,
+
+    /// A folder from [devices] mounts (reads EPUB)
+    Other,
+}
+
+impl DeviceKind {
+    /// Name for the device list
+    pub fn label(&self) -> &str {
+        match self {
+            DeviceKind::Kindle => "Kindle",
+            DeviceKind::Kobo => "Kobo",
+            DeviceKind::Other => "e-reader",
+        }
+    }
+}
+
+/// An e-reader found mounted
+#[derive(Debug, Clone)]
+pub struct Device {
+    /// Volume name, e.g. "Kindle" or "KOBOeReader"
+    pub name: String,
+
+    /// What kind of reader it is
+    pub kind: DeviceKind,
+
+    /// Where it's mounted
+    pub root: PathBuf,
+
+    /// Folder books are copied to
+    pub documents: PathBuf,
+
+    /// What was sent to the device so far
+    pub manifest: Manifest,
+}
+
+/// The books sent to a device, keyed by their file name on the device
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    /// One entry per file
+    pub files: BTreeMap<String, SentBook>,
+}
+
+/// A book sent to a device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SentBook {
+    /// Title, for the record
+    pub title: String,
+
+    /// File name in the library the copy was made from
+    pub source: String,
+
+    /// Content hash of the library file, if known
+    pub hash: Option<String>,
+
+    /// When it was sent (seconds since 1970)
+    pub sent: u64,
+}
+
+impl Manifest {
+    /// Reads the manifest of a documents folder; files deleted on the
+    /// device are dropped from it
+    pub fn load(documents: &Path) -> Self {
+        let mut manifest: Self = std::fs::read_to_string(documents.join(MANIFEST_FILE))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        manifest.files.retain(|name, _| documents.join(name).is_file());
+        manifest
+    }
+
+    /// Writes the manifest into a documents folder
+    pub fn save(&self, documents: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        std::fs::write(documents.join(MANIFEST_FILE), json)
+    }
+
+    /// Whether a book of the library is on the device (same content, or
+    /// else same file name)
+    pub fn contains(&self, book: &Book) -> bool {
+        self.files.values().any(|sent| match (&sent.hash, &book.hash) {
+            (Some(theirs), Some(ours)) => theirs == ours,
+            _ => sent.source == book.name,
+        })
+    }
+
+    /// Records a book copied to the device
+    ///
+    /// # Arguments
+    /// * `file` - File name on the device
+    /// * `book` - The library book it was made from
+    pub fn record(&mut self, file: &str, book: &Book) {
+        self.files.insert(
+            file.to_string(),
+            SentBook {
+                title: book.display_title().to_string(),
+                source: book.name.clone(),
+                hash: book.hash.clone(),
+                sent: crate::book::unix_now(),
+            },
+        );
+    }
+}
+
+impl Device {
+    /// How many books of a library are on the device
+    pub fn count_of(&self, books: &[Book]) -> usize {
+        books.iter().filter(|book| self.manifest.contains(book)).count()
+    }
+}
+
+/// Finds the e-readers mounted right now
+///
+/// # Arguments
+/// * `extra` - Folders to treat as e-readers (from [devices] mounts), used
+///   when they exist
+pub fn detect(extra: &[PathBuf]) -> Vec<Device> {
+    let mut devices: Vec<Device> = mount_points().iter().filter_map(|root| identify(root)).collect();
+    for folder in extra.iter().filter(|folder| folder.is_dir()) {
+        if devices.iter().any(|device| &device.root == folder) {
+            continue;
+        }
+        devices.push(device(folder, DeviceKind::Other, folder.clone()));
+    }
+    devices
+}
+
+/// Tells what kind of e-reader a volume is, if it is one
+fn identify(root: &Path) -> Option<Device> {
+    if root.join("documents").is_dir() && root.join("system").is_dir() {
+        return Some(device(root, DeviceKind::Kindle, root.join("documents")));
+    }
+    if root.join(".kobo").is_dir() {
+        return Some(device(root, DeviceKind::Kobo, root.to_path_buf()));
+    }
+    None
+}
+
+/// Describes a device, reading its manifest
+fn device(root: &Path, kind: DeviceKind, documents: PathBuf) -> Device {
+    Device {
+        name: root
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| root.display().to_string()),
+        kind,
+        root: root.to_path_buf(),
+        manifest: Manifest::load(&documents),
+        documents,
+    }
+}
+
+/// The mounted volumes removable devices may be on
+fn mount_points() -> Vec<PathBuf> {
+    // Drive letters: every one is a volume of its own
+    if cfg!(target_os = "windows") {
+        return ('D'..='Z')
+            .map(|letter| PathBuf::from(format!("{}:\\", letter)))
+            .filter(|root| root.is_dir())
+            .collect();
+    }
+
+    // Folders the volumes are mounted in
+    let mut parents = Vec::new();
+    if cfg!(target_os = "macos") {
+        parents.push(PathBuf::from("/Volumes"));
+    } else {
+        if let Ok(user) = std::env::var("USER") {
+            parents.push(PathBuf::from("/media").join(&user));
+            parents.push(PathBuf::from("/run/media").join(&user));
+        }
+        parents.push(PathBuf::from("/media"));
+        parents.push(PathBuf::from("/mnt"));
+    }
+
+    parents
+        .iter()
+        .filter_map(|parent| std::fs::read_dir(parent).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect()
+}
+
+/// Converting books before they're copied (for readers without EPUB)
+#[derive(Debug, Clone)]
+pub struct Conversion {
+    /// Converter command, e.g. "ebook-convert" (input and output paths are appended)
+    pub command: String,
+
+    /// Format to convert to, as a file extension, e.g. "azw3"
+    pub format: String,
+}
+
+/// Copies a book into a device's documents folder, converting it first if asked
+///
+/// # Arguments
+/// * `source` - The library file
+/// * `documents` - The device's documents folder
+/// * `conversion` - Conversion to do first (None = copy the EPUB as is)
+/// * `progress` - The transfer the copy is shown as
+///
+/// # Returns
+/// Path of the file on the device
+pub fn send(source: &Path, documents: &Path, conversion: Option<&Conversion>, progress: &Progress) -> Result<PathBuf, String> {
+    // Converted into the cache first; the copy to the (slow) device is what's tracked
+    let converted = conversion.map(|conversion| convert(source, conversion, progress)).transpose()?;
+    let file = converted.as_deref().unwrap_or(source);
+    let name = file.file_name().ok_or_else(|| format!("{}: no file name", file.display()))?;
+    let dest = documents.join(name);
+
+    let copy = || -> io::Result<()> {
+        let input = File::open(file)?;
+        progress.set_total(input.metadata()?.len());
+        let mut output = File::create(&dest)?;
+        io::copy(&mut progress.reader(input), &mut output)?;
+        // Unplugging too early would lose what's still in the cache
+        output.sync_all()
+    };
+    let result = copy();
+    if let Some(converted) = &converted {
+        let _ = std::fs::remove_file(converted);
+    }
+    if let Err(e) = result {
+        // Don't leave half a book on the device
+        let _ = std::fs::remove_file(&dest);
+        return Err(e.to_string());
+    }
+    Ok(dest)
+}
+
+/// Converts a book with an external converter (Calibre's ebook-convert),
+/// stopped if the copy is cancelled meanwhile
+///
+/// # Returns
+/// Path of the converted file, in the cache
+fn convert(source: &Path, conversion: &Conversion, progress: &Progress) -> Result<PathBuf, String> {
+    let dir = crate::paths::cache_dir().join("convert");
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let stem = source.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let output = dir.join(format!("{}.{}", stem, conversion.format));
+
+    crate::convert::run(&conversion.command, source, &output, |_| {}, || progress.is_cancelled())?;
+    Ok(output)
+}
@@ -0,0 +1,493 @@
+// src/dictionary.rs
+// Local dictionaries for the built-in reader - words looked up in dictd and
+// StarDict files, e.g. the FreeDict dictionaries packaged by most Linux
+// distributions
+//
+// A dictionary is found from its index file in the folders of
+// [dictionary] paths (or the usual system folders when none are set):
+// - dictd: name.index (a sorted text file, "word<TAB>offset<TAB>length",
+//   numbers in base 64) with name.dict or name.dict.dz
+// - StarDict: name.ifo (name, entry format) with name.idx or name.idx.gz
+//   (word\0, offset and length as 32-bit big-endian numbers) and name.dict
+//   or name.dict.dz
+// .dz files are dictzip: gzip split into chunks that can be inflated on
+// their own, so an entry is read without inflating the whole dictionary.
+//
+// Lookups read the index files from start to end, which takes a few
+// milliseconds for the usual dictionary; nothing is kept in memory between
+// two lookups. A word is found whatever its case.
+//
+// A dictionary's language is read from its name, as FreeDict's say it:
+// "deu-eng" or "German-English" is a dictionary of German words. Words of
+// a book in a known language are only looked up in the dictionaries of
+// that language (and the ones whose language isn't known), if there are any.
+
+use flate2::read::GzDecoder;
+use flate2::{Decompress, FlushDecompress};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Folders searched when [dictionary] paths is empty
+const SYSTEM_FOLDERS: [&str; 3] = ["/usr/share/dictd", "/usr/share/stardict/dic", "~/.stardict/dic"];
+
+/// How deep into the folders dictionaries are looked for
+const MAX_DEPTH: usize = 3;
+
+/// Digits of the numbers of dictd index files
+const DICTD_DIGITS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Most bytes an entry is read to (a broken index could ask for gigabytes)
+const MAX_ENTRY: u64 = 1 << 20;
+
+/// An entry found in a dictionary
+#[derive(Debug, Clone)]
+pub struct Definition {
+    /// Name of the dictionary, e.g. "German-English FreeDict Dictionary"
+    pub dictionary: String,
+
+    /// The word as the dictionary writes it
+    pub headword: String,
+
+    /// The entry, as plain text
+    pub text: String,
+}
+
+/// Format of a dictionary
+enum Kind {
+    Dictd,
+
+    /// With the `sametypesequence` of its .ifo (None = every field says its type)
+    StarDict(Option<String>),
+}
+
+/// A dictionary's files
+struct Dictionary {
+    name: String,
+
+    /// Language of its words, e.g. "de" (None = not told by its name)
+    language: Option<String>,
+
+    kind: Kind,
+    index: PathBuf,
+    data: PathBuf,
+}
+
+/// Looks a word up in every dictionary found (of the language, if some are)
+///
+/// # Arguments
+/// * `word` - The word, in any case
+/// * `paths` - Folders (or index files) of the dictionaries (empty = SYSTEM_FOLDERS)
+/// * `language` - Language of the word, e.g. "de" or "en-GB" (None = unknown)
+///
+/// # Returns
+/// The entries found, dictionary after dictionary, or an error if there is
+/// no dictionary at all
+pub fn look_up(word: &str, paths: &[PathBuf], language: Option<&str>) -> io::Result<Vec<Definition>> {
+    let mut dictionaries = find(paths);
+    if dictionaries.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no dictionary found: install dictd or StarDict dictionaries, or set [dictionary] paths",
+        ));
+    }
+
+    if let Some(language) = language.map(crate::language::primary) {
+        if dictionaries.iter().any(|d| d.language.as_deref() == Some(language.as_str())) {
+            dictionaries.retain(|d| d.language.is_none() || d.language.as_deref() == Some(language.as_str()));
+        }
+    }
+
+    let word = word.to_lowercase();
+    let mut definitions = Vec::new();
+    for dictionary in &dictionaries {
+        match dictionary.look_up(&word) {
+            Ok(found) => definitions.extend(found),
+            Err(e) => tracing::warn!(dictionary = %dictionary.index.display(), error = %e, "cannot read dictionary"),
+        }
+    }
+    Ok(definitions)
+}
+
+/// The dictionaries in some folders (or index files)
+fn find(paths: &[PathBuf]) -> Vec<Dictionary> {
+    let folders: Vec<PathBuf> = if paths.is_empty() {
+        SYSTEM_FOLDERS.iter().map(|folder| expand_home(Path::new(folder))).collect()
+    } else {
+        paths.iter().map(|path| expand_home(path)).collect()
+    };
+
+    let mut dictionaries = Vec::new();
+    for folder in folders {
+        for entry in walkdir::WalkDir::new(&folder).max_depth(MAX_DEPTH).sort_by_file_name() {
+            let Ok(entry) = entry else {
+                continue;
+            };
+            let path = entry.path();
+            let dictionary = match path.extension().and_then(|e| e.to_str()) {
+                Some("index") => dictd(path),
+                Some("ifo") => stardict(path),
+                _ => None,
+            };
+            dictionaries.extend(dictionary);
+        }
+    }
+    dictionaries
+}
+
+/// A dictd dictionary from its .index file (None without its .dict)
+fn dictd(index: &Path) -> Option<Dictionary> {
+    let data = sibling(index, &["dict", "dict.dz"])?;
+    let name = index.file_stem()?.to_string_lossy().to_string();
+    Some(Dictionary {
+        language: source_language(&name),
+        name,
+        kind: Kind::Dictd,
+        index: index.to_path_buf(),
+        data,
+    })
+}
+
+/// A StarDict dictionary from its .ifo file (None without its .idx and .dict)
+fn stardict(ifo: &Path) -> Option<Dictionary> {
+    let info = std::fs::read_to_string(ifo).ok()?;
+    let value = |key: &str| {
+        info.lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+            .map(|value| value.trim().to_string())
+    };
+    // 64-bit offsets are only for dictionaries over 4 GB
+    if value("idxoffsetbits").is_some_and(|bits| bits != "32") {
+        tracing::debug!(path = %ifo.display(), "StarDict dictionary with 64-bit offsets skipped");
+        return None;
+    }
+    let stem = ifo.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let name = value("bookname").unwrap_or_else(|| stem.clone());
+    Some(Dictionary {
+        language: source_language(&stem).or_else(|| source_language(&name)),
+        name,
+        kind: Kind::StarDict(value("sametypesequence")),
+        index: sibling(ifo, &["idx", "idx.gz"])?,
+        data: sibling(ifo, &["dict", "dict.dz"])?,
+    })
+}
+
+/// Language of the words of a dictionary, from its name: the first of two
+/// languages in a row, e.g. "de" for "freedict-deu-eng" or "German-English
+/// FreeDict Dictionary"
+fn source_language(name: &str) -> Option<String> {
+    let words: Vec<&str> = name.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).collect();
+    words
+        .windows(2)
+        .find(|pair| pair.iter().all(|word| crate::language::is_known(word)))
+        .map(|pair| crate::language::primary(pair[0]))
+}
+
+/// The first existing file named like another one with one of some extensions
+fn sibling(path: &Path, extensions: &[&str]) -> Option<PathBuf> {
+    extensions
+        .iter()
+        .map(|extension| path.with_extension(extension))
+        .find(|candidate| candidate.is_file())
+}
+
+/// A path with a leading "~" replaced by the home folder
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+impl Dictionary {
+    /// The entries of a word (lowercase) in this dictionary
+    fn look_up(&self, word: &str) -> io::Result<Vec<Definition>> {
+        let found = match self.kind {
+            Kind::Dictd => self.dictd_entries(word)?,
+            Kind::StarDict(_) => self.stardict_entries(word)?,
+        };
+
+        let mut definitions = Vec::new();
+        for (headword, offset, length) in found {
+            let data = read_data(&self.data, offset, length.min(MAX_ENTRY))?;
+            let text = match &self.kind {
+                Kind::Dictd => String::from_utf8_lossy(&data).trim().to_string(),
+                Kind::StarDict(types) => stardict_text(&data, types.as_deref()),
+            };
+            definitions.push(Definition {
+                dictionary: self.name.clone(),
+                headword,
+                text,
+            });
+        }
+        Ok(definitions)
+    }
+
+    /// Headword, offset and length of the entries of a word in a dictd index
+    fn dictd_entries(&self, word: &str) -> io::Result<Vec<(String, u64, u64)>> {
+        let mut found = Vec::new();
+        for line in BufReader::new(File::open(&self.index)?).split(b'\n') {
+            let line = line?;
+            let line = String::from_utf8_lossy(&line);
+            let mut fields = line.trim_end_matches('\r').split('\t');
+            let (Some(headword), Some(offset), Some(length)) = (fields.next(), fields.next(), fields.next()) else {
+                continue;
+            };
+            if headword.to_lowercase() == word {
+                if let (Some(offset), Some(length)) = (dictd_number(offset), dictd_number(length)) {
+                    found.push((headword.to_string(), offset, length));
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Headword, offset and length of the entries of a word in a StarDict index
+    fn stardict_entries(&self, word: &str) -> io::Result<Vec<(String, u64, u64)>> {
+        let file = File::open(&self.index)?;
+        let mut index = Vec::new();
+        if self.index.extension().is_some_and(|e| e == "gz") {
+            GzDecoder::new(file).read_to_end(&mut index)?;
+        } else {
+            BufReader::new(file).read_to_end(&mut index)?;
+        }
+
+        let mut found = Vec::new();
+        let mut rest = index.as_slice();
+        while let Some(end) = rest.iter().position(|&b| b == 0) {
+            if rest.len() < end + 9 {
+                break;
+            }
+            let headword = String::from_utf8_lossy(&rest[..end]);
+            let number = |at: usize| u32::from_be_bytes([rest[at], rest[at + 1], rest[at + 2], rest[at + 3]]) as u64;
+            if headword.to_lowercase() == word {
+                found.push((headword.to_string(), number(end + 1), number(end + 5)));
+            }
+            rest = &rest[end + 9..];
+        }
+        Ok(found)
+    }
+}
+
+/// A number of a dictd index ("B0" = 52)
+fn dictd_number(text: &str) -> Option<u64> {
+    text.bytes().try_fold(0u64, |number, digit| {
+        let value = DICTD_DIGITS.iter().position(|&d| d == digit)? as u64;
+        number.checked_mul(64)?.checked_add(value)
+    })
+}
+
+/// Reads bytes of a dictionary's data file (plain, or dictzip)
+fn read_data(path: &Path, offset: u64, length: u64) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    if path.extension().is_none_or(|e| e != "dz") {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut data = Vec::new();
+        file.take(length).read_to_end(&mut data)?;
+        return Ok(data);
+    }
+
+    match dictzip_chunks(&mut file)? {
+        Some(chunks) => chunks.read(&mut file, offset, length),
+        // Plain gzip: inflated up to the entry
+        None => {
+            file.seek(SeekFrom::Start(0))?;
+            let mut inflated = GzDecoder::new(file);
+            io::copy(&mut (&mut inflated).take(offset), &mut io::sink())?;
+            let mut data = Vec::new();
+            inflated.take(length).read_to_end(&mut data)?;
+            Ok(data)
+        }
+    }
+}
+
+/// The chunks of a dictzip file, from its gzip header
+struct Chunks {
+    /// Bytes of text per chunk (inflated)
+    length: u64,
+
+    /// Where each chunk starts in the file, and its size in the file
+    chunks: Vec<(u64, usize)>,
+}
+
+/// Reads the chunk table of a dictzip file (None if it's plain gzip)
+fn dictzip_chunks(file: &mut File) -> io::Result<Option<Chunks>> {
+    const FHCRC: u8 = 2;
+    const FEXTRA: u8 = 4;
+    const FNAME: u8 = 8;
+    const FCOMMENT: u8 = 16;
+
+    let mut reader = BufReader::new(&mut *file);
+    let mut header = [0u8; 10];
+    reader.read_exact(&mut header)?;
+    if header[..3] != [0x1f, 0x8b, 8] {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a gzip file"));
+    }
+    let flags = header[3];
+    let mut start = header.len() as u64;
+    let mut table = None;
+
+    if flags & FEXTRA != 0 {
+        let mut length = [0u8; 2];
+        reader.read_exact(&mut length)?;
+        let mut extra = vec![0u8; u16::from_le_bytes(length) as usize];
+        reader.read_exact(&mut extra)?;
+        start += 2 + extra.len() as u64;
+
+        // Subfields: two id bytes, a length, the data; dictzip's is "RA":
+        // version, chunk length, chunk count, then the size of every chunk
+        let mut rest = extra.as_slice();
+        while rest.len() >= 4 {
+            let size = u16::from_le_bytes([rest[2], rest[3]]) as usize;
+            let data = &rest[4..(4 + size).min(rest.len())];
+            if &rest[..2] == b"RA" && data.len() >= 6 {
+                let field = |i: usize| u16::from_le_bytes([data[2 * i], data[2 * i + 1]]);
+                let count = field(2) as usize;
+                if data.len() >= 6 + 2 * count {
+                    table = Some((field(1) as u64, (0..count).map(|i| field(3 + i) as usize).collect::<Vec<_>>()));
+                }
+            }
+            rest = &rest[(4 + size).min(rest.len())..];
+        }
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let mut text = Vec::new();
+            start += reader.read_until(0, &mut text)? as u64;
+        }
+    }
+    if flags & FHCRC != 0 {
+        start += 2;
+    }
+
+    Ok(table.map(|(length, sizes)| {
+        let mut chunks = Vec::with_capacity(sizes.len());
+        for size in sizes {
+            chunks.push((start, size));
+            start += size as u64;
+        }
+        Chunks { length, chunks }
+    }))
+}
+
+impl Chunks {
+    /// Reads bytes of the text, inflating only the chunks they're in
+    fn read(&self, file: &mut File, offset: u64, length: u64) -> io::Result<Vec<u8>> {
+        if self.length == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "dictzip chunks of no length"));
+        }
+        let first = (offset / self.length) as usize;
+        let last = ((offset + length).saturating_sub(1) / self.length) as usize;
+
+        let mut text = Vec::new();
+        for &(start, size) in self.chunks.iter().take(last + 1).skip(first) {
+            let mut compressed = vec![0u8; size];
+            file.seek(SeekFrom::Start(start))?;
+            file.read_exact(&mut compressed)?;
+
+            // Every chunk ends with a full flush: it inflates on its own
+            let mut inflated = Vec::with_capacity(self.length as usize);
+            Decompress::new(false)
+                .decompress_vec(&compressed, &mut inflated, FlushDecompress::Sync)
+                .map_err(io::Error::other)?;
+            text.extend_from_slice(&inflated);
+        }
+
+        let skip = (offset - first as u64 * self.length) as usize;
+        Ok(text.into_iter().skip(skip).take(length as usize).collect())
+    }
+}
+
+/// Text of a StarDict entry
+///
+/// # Arguments
+/// * `data` - The entry
+/// * `types` - The dictionary's `sametypesequence` (None = every field of the
+///   entry starts with its type)
+fn stardict_text(data: &[u8], types: Option<&str>) -> String {
+    let mut fields = Vec::new();
+    match types {
+        // The types are given once: the fields follow each other, the last
+        // one running to the end of the entry
+        Some(types) => {
+            let mut rest = data;
+            let types: Vec<char> = types.chars().collect();
+            for (i, kind) in types.iter().enumerate() {
+                let (field, next) = stardict_field(rest, *kind, i == types.len() - 1);
+                fields.push((*kind, field));
+                rest = next;
+            }
+        }
+        None => {
+            let mut rest = data;
+            while let Some((&kind, next)) = rest.split_first() {
+                let (field, next) = stardict_field(next, kind as char, false);
+                fields.push((kind as char, field));
+                rest = next;
+            }
+        }
+    }
+
+    fields
+        .into_iter()
+        .filter_map(|(kind, field)| {
+            let text = String::from_utf8_lossy(field);
+            match kind {
+                // Plain text, phonetics, Chinese YinBiao or Japanese KANA
+                'm' | 't' | 'y' | 'l' => Some(text.trim().to_string()),
+                // HTML, Pango markup, XDXF, Powerword
+                'h' | 'g' | 'x' | 'k' => Some(markup_text(&text)),
+                // Pictures, sounds... (the uppercase types) can't be shown
+                _ => None,
+            }
+        })
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Splits the first field of a StarDict entry off
+///
+/// Lowercase types are text ending with \0 (or the entry, for the last
+/// field of a sametypesequence); uppercase ones start with their size.
+fn stardict_field(data: &[u8], kind: char, last: bool) -> (&[u8], &[u8]) {
+    if kind.is_ascii_uppercase() {
+        if last || data.len() < 4 {
+            return (data, &[]);
+        }
+        let size = (u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize).min(data.len() - 4);
+        return (&data[4..4 + size], &data[4 + size..]);
+    }
+    if last {
+        return (data, &[]);
+    }
+    match data.iter().position(|&b| b == 0) {
+        Some(end) => (&data[..end], &data[end + 1..]),
+        None => (data, &[]),
+    }
+}
+
+/// Plain text of an entry written in HTML (or another markup): tags
+/// dropped, line breaks and paragraphs kept
+fn markup_text(markup: &str) -> String {
+    let mut text = String::with_capacity(markup.len());
+    let mut rest = markup;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            rest = &rest[start..];
+            break;
+        };
+        let tag = rest[start + 1..start + end].trim_start_matches('/').to_ascii_lowercase();
+        let name = tag.split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or("");
+        if matches!(name, "br" | "p" | "div" | "li" | "tr") {
+            text.push('\n');
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+
+    let text = crate::reader::unescape(&text);
+    let lines: Vec<&str> = text.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+    lines.join("\n")
+}
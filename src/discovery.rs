@@ -0,0 +1,270 @@
+// src/discovery.rs
+// LAN peer discovery - running instances announce themselves over mDNS/DNS-SD
+// ("_funkhunt._tcp.local.") and list the other instances they hear about
+//
+// The mDNS daemon runs on its own thread; the main loop polls for news the
+// same way it polls the folder watcher, so nothing here ever blocks.
+// Peers seen once are remembered (peers.json in the data directory) and
+// shown as offline until they announce themselves again.
+
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// DNS-SD service type announced and browsed for
+pub const SERVICE_TYPE: &str = "_funkhunt._tcp.local.";
+
+/// File (inside the data directory) remembering the peers seen so far
+const KNOWN_PEERS_FILE: &str = "peers.json";
+
+/// Another FunkHunt instance on the network
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Peer {
+    /// Full DNS-SD name, unique on the network (e.g. "Living room._funkhunt._tcp.local.")
+    pub id: String,
+
+    /// Name the peer announces, e.g. "Living room"
+    pub name: String,
+
+    /// Library profile the peer has open
+    pub library: String,
+
+    /// Where the peer can be reached (None until an address was resolved)
+    pub address: Option<SocketAddr>,
+
+    /// Number of books the peer shares
+    pub books: usize,
+
+    /// Instance id the peer announces (the key of its pairing; None for
+    /// versions without pairing)
+    #[serde(default)]
+    pub instance: Option<String>,
+
+    /// Whether the peer is still announcing itself (never stored: a
+    /// remembered peer is offline until heard from again)
+    #[serde(skip)]
+    pub online: bool,
+
+    /// When the peer was last heard from
+    pub last_seen: SystemTime,
+}
+
+/// Announces this instance and collects the peers found on the network
+pub struct Discovery {
+    /// The mDNS daemon (shut down when dropped)
+    daemon: ServiceDaemon,
+
+    /// Events of the browse for other instances
+    events: mdns_sd::Receiver<ServiceEvent>,
+
+    /// Our own announcement (re-registered when the book count changes)
+    info: ServiceInfo,
+
+    /// Book count announced last
+    books: usize,
+
+    /// Peers heard about, keyed by their full name
+    peers: BTreeMap<String, Peer>,
+}
+
+impl Discovery {
+    /// Starts announcing this instance and browsing for others
+    ///
+    /// # Arguments
+    /// * `name` - Name shown to other peers
+    /// * `library` - Name of the open library profile
+    /// * `port` - Port other peers connect to
+    /// * `books` - Number of books in the library
+    /// * `known` - Peers remembered from earlier sessions (listed as offline)
+    ///
+    /// # Returns
+    /// The running discovery, or None if mDNS isn't available (logged)
+    pub fn start(name: &str, library: &str, port: u16, books: usize, known: Vec<Peer>) -> Option<Self> {
+        let started = (|| {
+            let daemon = ServiceDaemon::new()?;
+            let info = announcement(name, &crate::trust::local_id(), library, port, books)?;
+            daemon.register(info.clone())?;
+            let events = daemon.browse(SERVICE_TYPE)?;
+            Ok::<_, mdns_sd::Error>((daemon, events, info))
+        })();
+
+        match started {
+            Ok((daemon, events, info)) => {
+                tracing::info!(name, port, "announcing on the local network");
+                Some(Self {
+                    daemon,
+                    events,
+                    info,
+                    books,
+                    peers: known.into_iter().map(|peer| (peer.id.clone(), peer)).collect(),
+                })
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "cannot start peer discovery");
+                None
+            }
+        }
+    }
+
+    /// Collects news from the network
+    ///
+    /// Call regularly (e.g. on every loop iteration); never blocks.
+    ///
+    /// # Returns
+    /// Whether the peer list changed
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                ServiceEvent::ServiceResolved(info) => {
+                    // We hear our own announcement too
+                    if info.get_fullname() == self.info.get_fullname() {
+                        continue;
+                    }
+                    let peer = peer_from(&info);
+                    tracing::info!(peer = %peer.name, address = ?peer.address, "peer found");
+                    self.peers.insert(peer.id.clone(), peer);
+                    changed = true;
+                }
+                ServiceEvent::ServiceRemoved(_, fullname) => {
+                    if let Some(peer) = self.peers.get_mut(&fullname) {
+                        tracing::info!(peer = %peer.name, "peer left");
+                        peer.online = false;
+                        changed = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        changed
+    }
+
+    /// The peers heard about, sorted by full name
+    pub fn peers(&self) -> Vec<Peer> {
+        self.peers.values().cloned().collect()
+    }
+
+    /// Forgets a peer (it comes back if it announces itself again)
+    pub fn forget(&mut self, id: &str) {
+        self.peers.remove(id);
+    }
+
+    /// Book count announced last
+    pub fn book_count(&self) -> usize {
+        self.books
+    }
+
+    /// Announces a new book count (nothing is sent if it didn't change)
+    pub fn set_book_count(&mut self, books: usize) {
+        if books == self.books {
+            return;
+        }
+        self.books = books;
+
+        let info = self.info.clone();
+        let library = info.get_property_val_str("library").unwrap_or_default().to_string();
+        let name = info.get_property_val_str("name").unwrap_or_default().to_string();
+        let instance = info.get_property_val_str("instance").unwrap_or_default().to_string();
+        // Registering the same name again replaces the announcement
+        match announcement(&name, &instance, &library, info.get_port(), books).and_then(|info| {
+            self.daemon.register(info.clone())?;
+            Ok(info)
+        }) {
+            Ok(info) => self.info = info,
+            Err(e) => tracing::warn!(error = %e, "cannot update the announcement"),
+        }
+    }
+}
+
+impl Drop for Discovery {
+    /// Says goodbye, so other peers see us go offline right away
+    fn drop(&mut self) {
+        let _ = self.daemon.unregister(self.info.get_fullname());
+        let _ = self.daemon.shutdown();
+    }
+}
+
+/// Loads the peers remembered from earlier sessions (all offline)
+pub fn load_known() -> Vec<Peer> {
+    std::fs::read_to_string(known_peers_path())
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+/// Remembers the peers for the next sessions
+pub fn save_known(peers: &[Peer]) -> io::Result<()> {
+    let path = known_peers_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let json = serde_json::to_string_pretty(peers).map_err(io::Error::other)?;
+    std::fs::write(path, json)
+}
+
+/// Where the known peers are stored
+fn known_peers_path() -> PathBuf {
+    crate::paths::data_dir().join(KNOWN_PEERS_FILE)
+}
+
+/// Name of this computer, used when no peer name is configured
+pub fn host_name() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "funkhunt".to_string())
+}
+
+/// Builds our DNS-SD announcement
+///
+/// The name, instance id, library and book count travel as TXT properties.
+fn announcement(name: &str, instance: &str, library: &str, port: u16, books: usize) -> Result<ServiceInfo, mdns_sd::Error> {
+    let host = format!("{}.local.", host_name().replace(['.', ' '], "-"));
+    let books = books.to_string();
+    let properties = [
+        ("name", name),
+        ("instance", instance),
+        ("library", library),
+        ("books", books.as_str()),
+        ("version", env!("CARGO_PKG_VERSION")),
+    ];
+    // No fixed address: the daemon announces every address of this machine
+    Ok(ServiceInfo::new(SERVICE_TYPE, name, &host, "", port, &properties[..])?.enable_addr_auto())
+}
+
+/// Reads a peer from its resolved announcement
+fn peer_from(info: &ServiceInfo) -> Peer {
+    let fullname = info.get_fullname().to_string();
+    let instance = fullname.strip_suffix(SERVICE_TYPE).unwrap_or(&fullname).trim_end_matches('.');
+
+    Peer {
+        name: info
+            .get_property_val_str("name")
+            .unwrap_or(instance)
+            .to_string(),
+        library: info.get_property_val_str("library").unwrap_or_default().to_string(),
+        // Prefer IPv4: link-local IPv6 addresses need a scope to be usable
+        address: info
+            .get_addresses()
+            .iter()
+            .min_by_key(|ip| ip.is_ipv6())
+            .map(|ip| SocketAddr::new(*ip, info.get_port())),
+        books: info
+            .get_property_val_str("books")
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(0),
+        instance: info.get_property_val_str("instance").map(str::to_string),
+        online: true,
+        last_seen: SystemTime::now(),
+        id: fullname,
+    }
+}
@@ -0,0 +1,430 @@
+// src/duplicates.rs
+// Suspected duplicates - books of the library that look like the same work
+// in different files (a re-download, another edition), and the side-by-side
+// comparison of two of them that helps pick the one to keep
+//
+// Two books are suspected duplicates when they have the same ISBN, or the
+// same title (folded, without what follows a ':', '(' or '[', so "Dune
+// (Deluxe Edition)" is "dune") and the same first author (see
+// authors::author_key). Books without a title are never suspected.
+//
+// The comparison reads both files: their metadata, size, content hash,
+// number of words and chapters, and their tables of contents lined up so
+// the entries only one of them has stand out.
+//
+// Identical files - the same bytes twice, whatever their metadata says - are
+// found by content hash (only files of the same size are hashed). `funkhunt
+// dedupe --apply` deletes the extra copies (their user data merged into
+// the copy kept), or makes them hardlinks of the one kept so they take no
+// room. Both files are hashed again first.
+
+use crate::book::Book;
+use crate::reader::Document;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Two books of the library that look like the same work
+#[derive(Debug, Clone)]
+pub struct Pair {
+    /// Title of the first one, for the list
+    pub title: String,
+
+    pub left: PathBuf,
+    pub right: PathBuf,
+}
+
+/// Files of the library with the same content
+#[derive(Debug, Clone)]
+pub struct Identical {
+    /// Their content hash
+    pub hash: String,
+
+    /// Size of each one, in bytes
+    pub size: u64,
+
+    /// The files, the one to keep first
+    pub paths: Vec<PathBuf>,
+}
+
+/// What becomes of the extra copies of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Deleted
+    Delete,
+    /// Replaced by hardlinks of the copy kept (same folder tree, no room taken)
+    Hardlink,
+}
+
+/// A line of a comparison: a field of both books, or an entry of their
+/// tables of contents
+#[derive(Debug, Clone)]
+pub struct Row {
+    /// Name of the field ("" for the entries of the tables of contents)
+    pub label: &'static str,
+
+    pub left: String,
+    pub right: String,
+
+    /// Whether the two sides differ
+    pub differs: bool,
+}
+
+/// Two books side by side
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub left: PathBuf,
+    pub right: PathBuf,
+
+    /// Their fields, then their tables of contents (after a "Contents" row)
+    pub rows: Vec<Row>,
+}
+
+/// What is read from a book's file for a comparison
+struct Side {
+    size: Option<u64>,
+    hash: Option<String>,
+
+    /// Words and chapters of its text (None if it can't be read)
+    text: Option<(usize, usize)>,
+
+    /// Entries of its table of contents, indented by depth
+    contents: Vec<String>,
+}
+
+impl Resolution {
+    /// The resolution a name is for: "delete" or "hardlink"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "delete" => Some(Resolution::Delete),
+            "hardlink" => Some(Resolution::Hardlink),
+            _ => None,
+        }
+    }
+}
+
+/// Finds the suspected duplicates of a library
+///
+/// # Returns
+/// Every pair of books that look like the same work, in library order
+pub fn suspected(books: &[Book]) -> Vec<Pair> {
+    // Books sharing a key are in one group (a book can join two groups'
+    // worth of books through its two keys, so groups are merged)
+    let mut group_of: Vec<usize> = (0..books.len()).collect();
+    let mut first_with: HashMap<String, usize> = HashMap::new();
+    for (i, book) in books.iter().enumerate() {
+        for key in keys(book) {
+            match first_with.get(&key) {
+                Some(&other) => {
+                    let (a, b) = (root(&group_of, other), root(&group_of, i));
+                    group_of[b.max(a)] = b.min(a);
+                }
+                None => {
+                    first_with.insert(key, i);
+                }
+            }
+        }
+    }
+
+    let mut pairs = Vec::new();
+    for i in 0..books.len() {
+        for j in i + 1..books.len() {
+            if root(&group_of, i) == root(&group_of, j) {
+                pairs.push(Pair {
+                    title: books[i].display_title().to_string(),
+                    left: books[i].path.clone(),
+                    right: books[j].path.clone(),
+                });
+            }
+        }
+    }
+    pairs
+}
+
+/// Finds the files of a library that have the same content, hashing those
+/// of the same size (the hashes are kept in the books)
+///
+/// Files that already are hardlinks of one another count as one. The copy to
+/// keep is the first one with user data (tags, rating...), else the first one.
+///
+/// # Returns
+/// A group per content, in library order
+pub fn identical(books: &mut [Book]) -> Vec<Identical> {
+    // Only files sharing their size can be the same
+    let mut by_size: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (i, book) in books.iter().enumerate() {
+        if crate::remote::is_remote(&book.path) {
+            continue;
+        }
+        if let Ok(metadata) = std::fs::metadata(&book.path) {
+            by_size.entry(metadata.len()).or_default().push(i);
+        }
+    }
+
+    let mut groups: Vec<(usize, Identical)> = Vec::new();
+    let mut group_of: HashMap<String, usize> = HashMap::new();
+    let mut sizes: Vec<(u64, Vec<usize>)> = by_size.into_iter().filter(|(_, books)| books.len() > 1).collect();
+    sizes.sort_by_key(|(_, books)| books[0]);
+    for (size, indices) in sizes {
+        for i in indices {
+            let Some(hash) = books[i].ensure_hash().map(str::to_string) else {
+                continue;
+            };
+            let book = &books[i];
+            match group_of.get(&hash) {
+                Some(&group) => {
+                    let (first, group) = &mut groups[group];
+                    if group.paths.iter().any(|path| same_file(path, &book.path)) {
+                        continue;
+                    }
+                    // The first copy with user data is kept
+                    if book.user != Default::default() && books[*first].user == Default::default() {
+                        *first = i;
+                        group.paths.insert(0, book.path.clone());
+                    } else {
+                        group.paths.push(book.path.clone());
+                    }
+                }
+                None => {
+                    group_of.insert(hash.clone(), groups.len());
+                    groups.push((
+                        i,
+                        Identical {
+                            hash,
+                            size,
+                            paths: vec![book.path.clone()],
+                        },
+                    ));
+                }
+            }
+        }
+    }
+    groups.into_iter().map(|(_, group)| group).filter(|group| group.paths.len() > 1).collect()
+}
+
+/// Gets rid of an extra copy of a file, once both files were hashed again
+/// (the hashes kept in the library may be from before a file changed)
+///
+/// # Arguments
+/// * `copy` - The extra copy
+/// * `kept` - The copy kept (same content)
+/// * `hash` - The content both were found to have
+/// * `resolution` - Delete the copy, or make it a hardlink of the one kept
+///
+/// # Errors
+/// When either file doesn't have that content anymore (nothing is done)
+pub fn resolve(copy: &Path, kept: &Path, hash: &str, resolution: Resolution) -> io::Result<()> {
+    for path in [kept, copy] {
+        if crate::hash::content_hash(path)? != hash {
+            let text = format!("{} changed since it was hashed - scan the library again", path.display());
+            return Err(io::Error::new(io::ErrorKind::InvalidData, text));
+        }
+    }
+    match resolution {
+        Resolution::Delete => std::fs::remove_file(copy),
+        Resolution::Hardlink => {
+            // Linked beside it, then moved over it: the copy is never missing
+            let name = copy.file_name().unwrap_or_default().to_string_lossy();
+            let link = copy.with_file_name(format!(".{}.funkhunt-link", name));
+            let _ = std::fs::remove_file(&link);
+            std::fs::hard_link(kept, &link)?;
+            std::fs::rename(&link, copy).inspect_err(|_| {
+                let _ = std::fs::remove_file(&link);
+            })
+        }
+    }
+}
+
+/// Whether two paths are the same file (hardlinks of each other)
+#[cfg(unix)]
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (std::fs::metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn same_file(_: &Path, _: &Path) -> bool {
+    false
+}
+
+/// Compares two books, reading their files
+pub fn compare(left: &Book, right: &Book) -> Comparison {
+    let (a, b) = (read_side(&left.path), read_side(&right.path));
+    let mut rows = Vec::new();
+    let mut row = |label: &'static str, left: String, right: String| {
+        let differs = left != right;
+        rows.push(Row {
+            label,
+            left,
+            right,
+            differs,
+        });
+    };
+    let text = |value: &Option<String>| value.clone().unwrap_or_default();
+
+    row("File", file_name(&left.path), file_name(&right.path));
+    row("Title", text(&left.meta.title), text(&right.meta.title));
+    row("Authors", left.meta.authors.join(", "), right.meta.authors.join(", "));
+    row("Series", series(left), series(right));
+    row("Language", text(&left.meta.language), text(&right.meta.language));
+    row("Publisher", text(&left.meta.publisher), text(&right.meta.publisher));
+    row("Published", text(&left.meta.published), text(&right.meta.published));
+    row("ISBN", text(&left.meta.isbn), text(&right.meta.isbn));
+    row("Identifier", text(&left.meta.identifier), text(&right.meta.identifier));
+    row("Subjects", left.meta.subjects.join(", "), right.meta.subjects.join(", "));
+    row("Description", description(left), description(right));
+    row("Size", size_text(a.size), size_text(b.size));
+    row("Words", count_text(a.text.map(|(words, _)| words)), count_text(b.text.map(|(words, _)| words)));
+    row("Chapters", count_text(a.text.map(|(_, chapters)| chapters)), count_text(b.text.map(|(_, chapters)| chapters)));
+    let same = a.hash.is_some() && a.hash == b.hash;
+    let content = |hash: &Option<String>| match hash {
+        _ if same => "identical".to_string(),
+        Some(hash) => hash.chars().take(12).collect(),
+        None => "unreadable".to_string(),
+    };
+    row("Content", content(&a.hash), content(&b.hash));
+
+    row(
+        "Contents",
+        format!("{} entries", a.contents.len()),
+        format!("{} entries", b.contents.len()),
+    );
+    for (left, right) in line_up(&a.contents, &b.contents) {
+        let differs = left.is_none() || right.is_none();
+        rows.push(Row {
+            label: "",
+            left: left.unwrap_or_default(),
+            right: right.unwrap_or_default(),
+            differs,
+        });
+    }
+
+    Comparison {
+        left: left.path.clone(),
+        right: right.path.clone(),
+        rows,
+    }
+}
+
+/// The keys a book is grouped by: its ISBN, and its title with its first author
+fn keys(book: &Book) -> Vec<String> {
+    let mut keys = Vec::new();
+    if let Some(isbn) = &book.meta.isbn {
+        keys.push(format!("isbn:{}", isbn));
+    }
+    if let Some(title) = &book.meta.title {
+        let title = title.split([':', '(', '[']).next().unwrap_or_default();
+        let title: Vec<String> = crate::fold::fold(title)
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_string)
+            .collect();
+        if !title.is_empty() {
+            let author = book.authors().first().map(|a| crate::authors::author_key(a)).unwrap_or_default();
+            keys.push(format!("title:{}|{}", title.join(" "), author));
+        }
+    }
+    keys
+}
+
+/// The first book of the group a book is in
+fn root(group_of: &[usize], mut i: usize) -> usize {
+    while group_of[i] != i {
+        i = group_of[i];
+    }
+    i
+}
+
+/// Reads what a comparison shows of a file
+fn read_side(path: &Path) -> Side {
+    let document = Document::open(path).ok();
+    Side {
+        size: std::fs::metadata(path).ok().map(|metadata| metadata.len()),
+        hash: crate::hash::content_hash(path).ok(),
+        text: document.as_ref().map(|document| {
+            let words = document
+                .chapters
+                .iter()
+                .map(|chapter| chapter.text().split_whitespace().count())
+                .sum();
+            (words, document.chapters.len())
+        }),
+        contents: document
+            .map(|document| {
+                document
+                    .contents
+                    .iter()
+                    .map(|entry| format!("{}{}", "  ".repeat(entry.depth), entry.title))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    }
+}
+
+/// Lines up two tables of contents: the entries both have (whatever their
+/// case and accents) side by side, the others against a blank
+fn line_up(left: &[String], right: &[String]) -> Vec<(Option<String>, Option<String>)> {
+    let key = |entry: &String| crate::fold::fold(entry.trim());
+    let (a, b): (Vec<String>, Vec<String>) = (left.iter().map(key).collect(), right.iter().map(key).collect());
+
+    // Longest common subsequence, from the ends
+    let mut common = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            common[i][j] = if a[i] == b[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut rows = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            rows.push((Some(left[i].clone()), Some(right[j].clone())));
+            i += 1;
+            j += 1;
+        } else if j == b.len() || (i < a.len() && common[i + 1][j] >= common[i][j + 1]) {
+            rows.push((Some(left[i].clone()), None));
+            i += 1;
+        } else {
+            rows.push((None, Some(right[j].clone())));
+            j += 1;
+        }
+    }
+    rows
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().to_string()
+}
+
+/// "Dune Chronicles #1", or "" for a book of no series
+fn series(book: &Book) -> String {
+    match (&book.meta.series, book.meta.series_index) {
+        (Some(name), Some(index)) => format!("{} #{}", name, index),
+        (Some(name), None) => name.clone(),
+        _ => String::new(),
+    }
+}
+
+/// Length of the description, which is too long to show whole
+fn description(book: &Book) -> String {
+    match &book.meta.description {
+        Some(text) => format!("{} characters", text.chars().count()),
+        None => String::new(),
+    }
+}
+
+fn size_text(size: Option<u64>) -> String {
+    size.map(|bytes| format!("{} KB", bytes / 1024)).unwrap_or_else(|| "missing".to_string())
+}
+
+fn count_text(count: Option<usize>) -> String {
+    count.map(|count| count.to_string()).unwrap_or_else(|| "unreadable".to_string())
+}
@@ -0,0 +1,777 @@
+// src/epub.rs
+// Reads metadata (title, authors, series...) from the OPF package inside an EPUB file
+// (the text itself is read by reader.rs), and writes edited titles, authors
+// and series, and new covers, back into it
+//
+// A write rewrites the whole archive into a temporary file next to the book,
+// every entry but the package copied as it is (still compressed), then puts
+// it in the book's place in one rename: the book is never half written.
+// (optimize.rs repacks books the same way.)
+
+use crate::book::{Accessibility, Metadata};
+use crate::graphics::ImageFormat;
+use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+/// Id of the EPUB3 series collection written into packages
+const SERIES_ID: &str = "funkhunt-series";
+
+/// Id of the cover image item added to packages that have none
+const COVER_ID: &str = "funkhunt-cover";
+
+/// Apple Books' display options, where its fixed-layout books say so
+const APPLE_DISPLAY_OPTIONS: &str = "META-INF/com.apple.ibooks.display-options.xml";
+
+/// Reads the package metadata of an EPUB file
+///
+/// An EPUB is a ZIP archive. `META-INF/container.xml` points to the OPF
+/// package document, whose `<metadata>` element holds the Dublin Core fields.
+///
+/// # Arguments
+/// * `path` - Path to the EPUB file
+///
+/// # Returns
+/// The parsed Metadata, or an error if the file is not a readable EPUB
+#[cfg_attr(feature = "profiling", tracing::instrument(level = "trace", name = "metadata", skip_all))]
+pub fn read_metadata(path: &Path) -> io::Result<Metadata> {
+    read_metadata_from(File::open(path)?)
+}
+
+/// Reads the package metadata of an EPUB from any seekable source
+/// (e.g. a file on a remote server - only the parts needed are read)
+pub fn read_metadata_from<R: Read + Seek>(reader: R) -> io::Result<Metadata> {
+    let mut archive = zip::ZipArchive::new(reader).map_err(io::Error::other)?;
+    let (_, opf) = read_package(&mut archive)?;
+    let mut meta = parse_opf_metadata(&opf)?;
+    // Apple's fixed layout: <option name="fixed-layout">true</option>
+    if !meta.fixed_layout {
+        meta.fixed_layout = read_entry(&mut archive, APPLE_DISPLAY_OPTIONS)
+            .is_ok_and(|options| options.replace(' ', "").contains("name=\"fixed-layout\">true<"));
+    }
+    Ok(meta)
+}
+
+/// Reads the cover image of an EPUB file
+///
+/// The cover is the manifest item marked `properties="cover-image"` (EPUB3),
+/// else the one named by `<meta name="cover" content="..."/>` (EPUB2), else
+/// the first image with "cover" in its id or file name.
+///
+/// # Returns
+/// The image and its media type (e.g. "image/jpeg"), or None if the book has no cover
+pub fn read_cover(path: &Path) -> io::Result<Option<(Vec<u8>, String)>> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(io::Error::other)?;
+    let (opf_path, opf) = read_package(&mut archive)?;
+    let (items, cover_id) = read_manifest(&opf)?;
+    let Some(cover) = cover_item(&items, cover_id.as_deref()) else {
+        return Ok(None);
+    };
+
+    let mut entry = archive.by_name(&entry_path(&opf_path, &cover.href)).map_err(io::Error::other)?;
+    let mut image = Vec::new();
+    entry.read_to_end(&mut image)?;
+    Ok(Some((image, cover.media_type.clone())))
+}
+
+/// An item of a package's manifest
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub id: String,
+
+    /// Link to the file, relative to the package
+    pub href: String,
+
+    pub media_type: String,
+
+    /// Space-separated EPUB3 properties, e.g. "nav" or "cover-image"
+    pub properties: String,
+}
+
+/// Reads the manifest items of a package, and the id its EPUB2 cover meta names
+pub fn read_manifest(opf: &str) -> io::Result<(Vec<Item>, Option<String>)> {
+    let mut items = Vec::new();
+    let mut cover_id = None;
+    let mut reader = Reader::from_str(opf);
+    loop {
+        match reader.read_event().map_err(io::Error::other)? {
+            Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                b"item" => items.push(Item {
+                    id: attribute(&e, b"id").unwrap_or_default(),
+                    href: attribute(&e, b"href").unwrap_or_default(),
+                    media_type: attribute(&e, b"media-type").unwrap_or_default(),
+                    properties: attribute(&e, b"properties").unwrap_or_default(),
+                }),
+                b"meta" if attribute(&e, b"name").as_deref() == Some("cover") => cover_id = attribute(&e, b"content"),
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok((items, cover_id))
+}
+
+/// Reads the spine of a package: the ids of its documents in reading
+/// order, each with whether it's linear (`linear="no"` is usually notes)
+pub fn read_spine(opf: &str) -> io::Result<Vec<(String, bool)>> {
+    let mut spine = Vec::new();
+    let mut reader = Reader::from_str(opf);
+    loop {
+        match reader.read_event().map_err(io::Error::other)? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"itemref" => {
+                if let Some(id) = attribute(&e, b"idref") {
+                    spine.push((id, attribute(&e, b"linear").as_deref() != Some("no")));
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(spine)
+}
+
+/// The cover image among the manifest items (see `read_cover`)
+pub fn cover_item<'a>(items: &'a [Item], cover_id: Option<&str>) -> Option<&'a Item> {
+    let images = || items.iter().filter(|item| item.media_type.starts_with("image/"));
+    images()
+        .find(|item| item.properties.split_whitespace().any(|p| p == "cover-image"))
+        .or_else(|| images().find(|item| cover_id == Some(item.id.as_str())))
+        .or_else(|| images().find(|item| item.id.to_lowercase().contains("cover") || item.href.to_lowercase().contains("cover")))
+}
+
+/// Finds the OPF package document through `META-INF/container.xml`
+///
+/// # Returns
+/// Its path inside the archive and its content
+pub fn read_package<R: Read + Seek>(archive: &mut zip::ZipArchive<R>) -> io::Result<(String, String)> {
+    let container = read_entry(archive, "META-INF/container.xml")?;
+    let opf_path = find_rootfile(&container).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "container.xml has no rootfile")
+    })?;
+    let opf = read_entry(archive, &opf_path)?;
+    Ok((opf_path, opf))
+}
+
+/// Path inside the archive of a link found in one of its files
+///
+/// # Arguments
+/// * `base` - Path of the file the link is in, e.g. "OEBPS/content.opf"
+/// * `href` - The link, relative to it, e.g. "../images/cover.jpg" (a
+///   "#fragment" is dropped)
+pub fn entry_path(base: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let href = crate::opds::percent_decode(href).unwrap_or_else(|| href.to_string());
+    let mut parts: Vec<&str> = base.split('/').collect();
+    parts.pop();
+    for part in href.split('/') {
+        match part {
+            ".." => {
+                parts.pop();
+            }
+            "." | "" => {}
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+/// Reads a file inside the ZIP archive as a UTF-8 string
+pub fn read_entry<R: Read + Seek>(archive: &mut zip::ZipArchive<R>, name: &str) -> io::Result<String> {
+    let mut entry = archive.by_name(name).map_err(io::Error::other)?;
+    let mut text = String::new();
+    entry.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Extracts the `full-path` of the first `<rootfile>` in container.xml
+fn find_rootfile(container: &str) -> Option<String> {
+    let mut reader = Reader::from_str(container);
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.local_name().as_ref() == b"rootfile" => {
+                return attribute(&e, b"full-path");
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            _ => {}
+        }
+    }
+}
+
+/// Parses the `<metadata>` section of an OPF package document
+///
+/// Understands the Dublin Core elements plus the series conventions used
+/// by Calibre (`calibre:series` meta) and EPUB3 (`belongs-to-collection`),
+/// and the accessibility properties (schema.org's, conformance and
+/// certification) written the EPUB3 way or as EPUB2 `<meta>`s. A book is
+/// fixed-layout when most of its spine is pre-paginated (the package's
+/// `rendition:layout`, unless an itemref says otherwise, or Kindle's
+/// `fixed-layout` meta), and read-aloud when an item has a media overlay.
+fn parse_opf_metadata(opf: &str) -> io::Result<Metadata> {
+    let mut reader = Reader::from_str(opf);
+    reader.trim_text(true);
+
+    let mut meta = Metadata::default();
+
+    // Name of the element whose text we're currently reading (if any)
+    let mut current: Option<Vec<u8>> = None;
+    // Attributes of interest on the current element
+    let mut is_isbn_scheme = false;
+    let mut is_collection = false;
+    let mut is_series_index = false;
+    // Property of the current <meta> element (EPUB3)
+    let mut property = String::new();
+    // Layout of the pages without their own, and pages (spine items) of each layout
+    let mut fixed_by_default = false;
+    let (mut fixed_pages, mut reflowable_pages, mut pages) = (0, 0, 0);
+
+    loop {
+        match reader.read_event().map_err(io::Error::other)? {
+            // Text-bearing element: remember which one we're in
+            Event::Start(e) => {
+                let name = e.local_name().as_ref().to_vec();
+                is_isbn_scheme = attribute(&e, b"scheme")
+                    .map(|s| s.eq_ignore_ascii_case("isbn"))
+                    .unwrap_or(false);
+
+                // EPUB3 series: <meta property="belongs-to-collection">Name</meta>
+                property = attribute(&e, b"property").unwrap_or_default();
+                is_collection = property == "belongs-to-collection";
+                is_series_index = property == "group-position";
+
+                current = Some(name);
+            }
+
+            // Calibre series: <meta name="calibre:series" content="Name"/>
+            Event::Empty(e) if e.local_name().as_ref() == b"meta" => {
+                let name = attribute(&e, b"name").unwrap_or_default();
+                let content = attribute(&e, b"content");
+                match name.as_str() {
+                    "calibre:series" => meta.series = content,
+                    "calibre:series_index" => {
+                        meta.series_index = content.and_then(|c| c.parse().ok());
+                    }
+                    "fixed-layout" => fixed_by_default = content.as_deref() == Some("true"),
+                    name => {
+                        if let Some(content) = content {
+                            add_accessibility(&mut meta.accessibility, name, content.trim().to_string());
+                        }
+                    }
+                }
+            }
+
+            // Read-aloud items (<item media-overlay="smil1"/>, or the SMIL
+            // file itself) and the pages' own layouts
+            Event::Empty(e)
+                if e.local_name().as_ref() == b"item"
+                    && (attribute(&e, b"media-overlay").is_some()
+                        || attribute(&e, b"media-type").as_deref() == Some("application/smil+xml")) =>
+            {
+                meta.media_overlays = true;
+            }
+            Event::Empty(e) if e.local_name().as_ref() == b"itemref" => {
+                let properties = attribute(&e, b"properties").unwrap_or_default();
+                pages += 1;
+                for property in properties.split_whitespace() {
+                    match property {
+                        "rendition:layout-pre-paginated" => fixed_pages += 1,
+                        "rendition:layout-reflowable" => reflowable_pages += 1,
+                        _ => {}
+                    }
+                }
+            }
+
+            // EPUB3 conformance: <link rel="dcterms:conformsTo" href="..."/>
+            Event::Empty(e) if e.local_name().as_ref() == b"link" => {
+                let rel = attribute(&e, b"rel").unwrap_or_default();
+                if let Some(href) = attribute(&e, b"href") {
+                    add_accessibility(&mut meta.accessibility, &rel, href);
+                }
+            }
+
+            // Element text - store it in the matching field
+            Event::Text(t) => {
+                let text = t.unescape().map_err(io::Error::other)?.trim().to_string();
+                if text.is_empty() {
+                    continue;
+                }
+
+                match current.as_deref() {
+                    Some(b"title") if meta.title.is_none() => meta.title = Some(text),
+                    Some(b"creator") => meta.authors.push(text),
+                    Some(b"language") if meta.language.is_none() => meta.language = Some(text),
+                    Some(b"publisher") if meta.publisher.is_none() => meta.publisher = Some(text),
+                    Some(b"description") if meta.description.is_none() => {
+                        meta.description = Some(text)
+                    }
+                    Some(b"date") if meta.published.is_none() => meta.published = Some(text),
+                    Some(b"subject") => meta.subjects.push(text),
+                    Some(b"identifier") => {
+                        // Prefer identifiers that are (or look like) ISBNs
+                        if is_isbn_scheme || looks_like_isbn(&text) {
+                            meta.isbn = Some(normalize_isbn(&text));
+                        } else if meta.identifier.is_none() {
+                            meta.identifier = Some(text);
+                        }
+                    }
+                    Some(b"meta") if is_collection && meta.series.is_none() => {
+                        meta.series = Some(text)
+                    }
+                    Some(b"meta") if is_series_index && meta.series_index.is_none() => {
+                        meta.series_index = text.parse().ok()
+                    }
+                    Some(b"meta") if property == "rendition:layout" => fixed_by_default = text == "pre-paginated",
+                    Some(b"meta") => add_accessibility(&mut meta.accessibility, &property, text),
+                    _ => {}
+                }
+            }
+
+            // Leaving an element - stop collecting text
+            Event::End(_) => current = None,
+
+            // The manifest and spine are read too, for the layout and overlays
+            Event::Eof => break,
+
+            _ => {}
+        }
+    }
+
+    if fixed_by_default {
+        fixed_pages = pages - reflowable_pages;
+    }
+    meta.fixed_layout = fixed_pages * 2 > pages || (pages == 0 && fixed_by_default);
+    Ok(meta)
+}
+
+/// Stores an accessibility property of a package, if it's one
+///
+/// # Arguments
+/// * `property` - The property, e.g. "schema:accessMode" (any other is ignored)
+/// * `value` - Its value, e.g. "textual"
+fn add_accessibility(accessibility: &mut Accessibility, property: &str, value: String) {
+    match property {
+        "schema:accessMode" => accessibility.access_modes.push(value),
+        "schema:accessModeSufficient" => accessibility.sufficient_modes.push(value),
+        "schema:accessibilityFeature" => accessibility.features.push(value),
+        "schema:accessibilityHazard" => accessibility.hazards.push(value),
+        "schema:accessibilitySummary" if accessibility.summary.is_none() => accessibility.summary = Some(value),
+        "dcterms:conformsTo" if accessibility.conforms_to.is_none() => accessibility.conforms_to = Some(value),
+        "a11y:certifiedBy" if accessibility.certified_by.is_none() => accessibility.certified_by = Some(value),
+        _ => {}
+    }
+}
+
+/// Writes the title, authors and series of a book into its EPUB's package
+///
+/// Those elements of the package are replaced (or added), everything else
+/// is kept. No title or no authors leave the file's own; no series removes
+/// the file's. The series is written the Calibre way and, in EPUB3
+/// packages, as a collection too.
+///
+/// # Arguments
+/// * `path` - The EPUB file (replaced by the new one)
+/// * `meta` - The metadata to write
+pub fn write_metadata(path: &Path, meta: &Metadata) -> io::Result<()> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(io::Error::other)?;
+    let (opf_path, opf) = read_package(&mut archive)?;
+    let opf = rewrite_opf(&opf, meta)?;
+    replace_entries(path, &mut archive, &[(&opf_path, opf.as_bytes())])
+}
+
+/// Embeds a cover image (PNG, JPEG or GIF) into an EPUB
+///
+/// The book's cover image (see `read_cover`) gets the new image, in its
+/// place so the cover page shows it too; a book without one gets a new
+/// manifest item. Either way the package names it the way of both
+/// versions: `<meta name="cover"/>` for EPUB2 readers and, in EPUB3
+/// packages, `properties="cover-image"`.
+///
+/// # Arguments
+/// * `path` - The EPUB file (replaced by the new one)
+/// * `image` - The image file's content
+pub fn write_cover(path: &Path, image: &[u8]) -> io::Result<()> {
+    let media_type = match crate::graphics::probe(image) {
+        Some((ImageFormat::Png, _)) => "image/png",
+        Some((ImageFormat::Jpeg, _)) => "image/jpeg",
+        Some((ImageFormat::Gif, _)) => "image/gif",
+        None => return Err(io::Error::new(io::ErrorKind::InvalidData, "not a PNG, JPEG or GIF image")),
+    };
+    let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(io::Error::other)?;
+    let (opf_path, opf) = read_package(&mut archive)?;
+    let (items, cover_id) = read_manifest(&opf)?;
+
+    let (id, href, added) = match cover_item(&items, cover_id.as_deref()) {
+        Some(item) => (item.id.clone(), item.href.clone(), false),
+        None => {
+            // "cover.jpg" next to the package, unless the name is taken
+            let extension = media_type.trim_start_matches("image/").replace("jpeg", "jpg");
+            let href = ["cover", COVER_ID]
+                .iter()
+                .map(|name| format!("{}.{}", name, extension))
+                .find(|href| !archive.file_names().any(|name| name == entry_path(&opf_path, href)))
+                .ok_or_else(|| io::Error::other("no free name for the cover image"))?;
+            (COVER_ID.to_string(), href, true)
+        }
+    };
+    let cover = Item {
+        id,
+        href,
+        media_type: media_type.to_string(),
+        properties: String::new(),
+    };
+    let opf = rewrite_opf_cover(&opf, &cover, added)?;
+    let image_path = entry_path(&opf_path, &cover.href);
+    replace_entries(path, &mut archive, &[(&opf_path, opf.as_bytes()), (&image_path, image)])
+}
+
+/// Writes an EPUB anew with some entries replaced (those not in it are
+/// added at the end), everything else copied as it is
+///
+/// # Arguments
+/// * `path` - The EPUB file
+/// * `archive` - The EPUB, open
+/// * `entries` - Path inside the archive and new content of each entry
+fn replace_entries(path: &Path, archive: &mut zip::ZipArchive<File>, entries: &[(&str, &[u8])]) -> io::Result<()> {
+    let tmp_path = temp_path(path);
+    let mut write = || -> io::Result<()> {
+        let mut writer = zip::ZipWriter::new(File::create(&tmp_path)?);
+        let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        let write_entry = |writer: &mut zip::ZipWriter<File>, name: &str, content: &[u8]| -> io::Result<()> {
+            writer.start_file(name, options).map_err(io::Error::other)?;
+            writer.write_all(content)
+        };
+
+        for i in 0..archive.len() {
+            let entry = archive.by_index_raw(i).map_err(io::Error::other)?;
+            match entries.iter().find(|(name, _)| *name == entry.name()) {
+                Some((name, content)) => write_entry(&mut writer, name, content)?,
+                None => writer.raw_copy_file(entry).map_err(io::Error::other)?,
+            }
+        }
+        for (name, content) in entries {
+            if !archive.file_names().any(|existing| existing == *name) {
+                write_entry(&mut writer, name, content)?;
+            }
+        }
+        writer.finish().map_err(io::Error::other)?;
+        Ok(())
+    };
+    if let Err(e) = write() {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+    put_in_place(&tmp_path, path)
+}
+
+/// Where a new version of an EPUB is written before it replaces the book:
+/// "Dune.epub" -> ".Dune.epub.funkhunt-tmp", in the same folder so the
+/// rename can't cross file systems
+pub fn temp_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".funkhunt-tmp");
+    path.with_file_name(name)
+}
+
+/// Puts a new version of an EPUB (written to `temp_path`) in the book's
+/// place (or where a new book goes), in one rename, once it's on disk and
+/// reads as an EPUB (else it's removed and the book stays as it was)
+pub fn put_in_place(tmp_path: &Path, path: &Path) -> io::Result<()> {
+    let check = || -> io::Result<()> {
+        File::open(tmp_path)?.sync_all()?;
+        read_metadata(tmp_path)?;
+        // A new book (e.g. a part of a split) gets the usual permissions
+        match std::fs::metadata(path) {
+            Ok(book) => std::fs::set_permissions(tmp_path, book.permissions()),
+            Err(_) => Ok(()),
+        }
+    };
+    if let Err(e) = check() {
+        let _ = std::fs::remove_file(tmp_path);
+        return Err(e);
+    }
+    std::fs::rename(tmp_path, path)
+}
+
+/// An OPF package document with the title, authors and series of `meta`
+/// (see `write_metadata`)
+fn rewrite_opf(opf: &str, meta: &Metadata) -> io::Result<String> {
+    // First pass: the package's version, the prefix of its Dublin Core
+    // elements and the ids of the elements replaced (their refinements go too)
+    let mut epub3 = false;
+    let mut dc_prefix = "dc".to_string();
+    let mut replaced_ids = HashSet::new();
+    let mut reader = Reader::from_str(opf);
+    loop {
+        match reader.read_event().map_err(io::Error::other)? {
+            Event::Start(e) | Event::Empty(e) => {
+                let name = String::from_utf8_lossy(e.name().as_ref()).to_string();
+                if e.local_name().as_ref() == b"package" {
+                    epub3 = attribute(&e, b"version").is_some_and(|v| v.starts_with('3'));
+                }
+                if let Some((prefix, "title" | "creator")) = name.split_once(':') {
+                    dc_prefix = prefix.to_string();
+                }
+                if replaces(&e, meta, &HashSet::new()) {
+                    replaced_ids.extend(attribute(&e, b"id"));
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    // Second pass: copied event by event, the replaced elements skipped and
+    // the new ones written where the first old one was (or at the end)
+    let mut reader = Reader::from_str(opf);
+    let mut writer = Writer::new(Vec::new());
+    let (mut title_written, mut authors_written) = (meta.title.is_none(), meta.authors.is_empty());
+    let mut skipping = 0;
+    loop {
+        let event = reader.read_event().map_err(io::Error::other)?;
+        if skipping > 0 {
+            match event {
+                Event::Start(_) => skipping += 1,
+                Event::End(_) => skipping -= 1,
+                Event::Eof => break,
+                _ => {}
+            }
+            continue;
+        }
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) if replaces(e, meta, &replaced_ids) => {
+                if matches!(event, Event::Start(_)) {
+                    skipping = 1;
+                }
+                match e.local_name().as_ref() {
+                    b"title" if !title_written => {
+                        write_element(&mut writer, &format!("{}:title", dc_prefix), &[], meta.title.as_deref().unwrap_or_default())?;
+                        title_written = true;
+                    }
+                    b"creator" if !authors_written => {
+                        write_authors(&mut writer, &dc_prefix, &meta.authors)?;
+                        authors_written = true;
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(ref e) if e.local_name().as_ref() == b"metadata" => {
+                let adding = !title_written || !authors_written || meta.series.is_some();
+                if !title_written {
+                    write_element(&mut writer, &format!("{}:title", dc_prefix), &[], meta.title.as_deref().unwrap_or_default())?;
+                }
+                if !authors_written {
+                    write_authors(&mut writer, &dc_prefix, &meta.authors)?;
+                }
+                write_series(&mut writer, meta, epub3)?;
+                // The closing tag back on a line of its own
+                if adding {
+                    writer.write_event(Event::Text(BytesText::new("\n  "))).map_err(io::Error::other)?;
+                }
+                writer.write_event(event.clone()).map_err(io::Error::other)?;
+            }
+            Event::Eof => break,
+            event => writer.write_event(event).map_err(io::Error::other)?,
+        }
+    }
+    String::from_utf8(writer.into_inner()).map_err(io::Error::other)
+}
+
+/// Whether an element of a package is one `write_metadata` replaces: the
+/// title and authors when `meta` has some, and whatever says the series
+/// (or refines an element with one of `replaced_ids`)
+fn replaces(element: &BytesStart, meta: &Metadata, replaced_ids: &HashSet<String>) -> bool {
+    match element.local_name().as_ref() {
+        b"title" => meta.title.is_some(),
+        b"creator" => !meta.authors.is_empty(),
+        b"meta" => {
+            let name = attribute(element, b"name").unwrap_or_default();
+            let property = attribute(element, b"property").unwrap_or_default();
+            let refines = attribute(element, b"refines").unwrap_or_default();
+            name == "calibre:series"
+                || name == "calibre:series_index"
+                || property == "belongs-to-collection"
+                || refines.strip_prefix('#').is_some_and(|id| replaced_ids.contains(id))
+        }
+        _ => false,
+    }
+}
+
+/// Writes an element with text, on a line of its own in <metadata>
+fn write_element(writer: &mut Writer<Vec<u8>>, name: &str, attributes: &[(&str, &str)], text: &str) -> io::Result<()> {
+    let mut start = BytesStart::new(name);
+    start.extend_attributes(attributes.iter().copied());
+    writer.write_event(Event::Text(BytesText::new("\n    "))).map_err(io::Error::other)?;
+    writer.write_event(Event::Start(start)).map_err(io::Error::other)?;
+    writer.write_event(Event::Text(BytesText::new(text))).map_err(io::Error::other)?;
+    writer.write_event(Event::End(BytesEnd::new(name))).map_err(io::Error::other)?;
+    Ok(())
+}
+
+/// Writes the authors as creators
+fn write_authors(writer: &mut Writer<Vec<u8>>, dc_prefix: &str, authors: &[String]) -> io::Result<()> {
+    for author in authors {
+        write_element(writer, &format!("{}:creator", dc_prefix), &[], author)?;
+    }
+    Ok(())
+}
+
+/// Writes the series: Calibre's metas, and an EPUB3 collection if `epub3`
+fn write_series(writer: &mut Writer<Vec<u8>>, meta: &Metadata, epub3: bool) -> io::Result<()> {
+    let Some(series) = &meta.series else {
+        return Ok(());
+    };
+    let index = meta.series_index.map(|index| index.to_string());
+
+    let mut calibre = vec![("calibre:series", series.as_str())];
+    calibre.extend(index.as_deref().map(|index| ("calibre:series_index", index)));
+    for (name, content) in calibre {
+        let mut element = BytesStart::new("meta");
+        element.extend_attributes([("name", name), ("content", content)]);
+        writer.write_event(Event::Text(BytesText::new("\n    "))).map_err(io::Error::other)?;
+        writer.write_event(Event::Empty(element)).map_err(io::Error::other)?;
+    }
+
+    if epub3 {
+        let refines = format!("#{}", SERIES_ID);
+        write_element(writer, "meta", &[("property", "belongs-to-collection"), ("id", SERIES_ID)], series)?;
+        write_element(writer, "meta", &[("refines", &refines), ("property", "collection-type")], "series")?;
+        if let Some(index) = &index {
+            write_element(writer, "meta", &[("refines", &refines), ("property", "group-position")], index)?;
+        }
+    }
+    Ok(())
+}
+
+/// An OPF package document naming `cover` as its cover image, added to
+/// the manifest if `added` (see `write_cover`)
+fn rewrite_opf_cover(opf: &str, cover: &Item, added: bool) -> io::Result<String> {
+    let mut reader = Reader::from_str(opf);
+    let mut writer = Writer::new(Vec::new());
+    let mut epub3 = false;
+    let mut meta_written = false;
+    let mut skipping = 0;
+    loop {
+        let event = reader.read_event().map_err(io::Error::other)?;
+        if skipping > 0 {
+            match event {
+                Event::Start(_) => skipping += 1,
+                Event::End(_) => skipping -= 1,
+                Event::Eof => break,
+                _ => {}
+            }
+            continue;
+        }
+        match event {
+            Event::Start(ref e) if e.local_name().as_ref() == b"package" => {
+                epub3 = attribute(e, b"version").is_some_and(|v| v.starts_with('3'));
+                writer.write_event(event.clone()).map_err(io::Error::other)?;
+            }
+            // The cover's item: its media type, and the EPUB3 property
+            Event::Start(ref e) | Event::Empty(ref e)
+                if !added && e.local_name().as_ref() == b"item" && attribute(e, b"id").as_deref() == Some(cover.id.as_str()) =>
+            {
+                let mut item = BytesStart::new(String::from_utf8_lossy(e.name().as_ref()).to_string());
+                for attr in e.attributes().flatten() {
+                    if !matches!(attr.key.local_name().as_ref(), b"media-type" | b"properties") {
+                        item.push_attribute(attr);
+                    }
+                }
+                item.push_attribute(("media-type", cover.media_type.as_str()));
+                let properties = cover_properties(&attribute(e, b"properties").unwrap_or_default(), epub3);
+                if !properties.is_empty() {
+                    item.push_attribute(("properties", properties.as_str()));
+                }
+                let item = match event {
+                    Event::Start(_) => Event::Start(item),
+                    _ => Event::Empty(item),
+                };
+                writer.write_event(item).map_err(io::Error::other)?;
+            }
+            // The EPUB2 cover meta, pointing to the cover
+            Event::Start(ref e) | Event::Empty(ref e)
+                if e.local_name().as_ref() == b"meta" && attribute(e, b"name").as_deref() == Some("cover") =>
+            {
+                if matches!(event, Event::Start(_)) {
+                    skipping = 1;
+                }
+                if !meta_written {
+                    writer.write_event(Event::Empty(cover_meta(&cover.id))).map_err(io::Error::other)?;
+                    meta_written = true;
+                }
+            }
+            Event::End(ref e) if added && e.local_name().as_ref() == b"manifest" => {
+                let mut item = BytesStart::new("item");
+                item.extend_attributes([
+                    ("id", cover.id.as_str()),
+                    ("href", cover.href.as_str()),
+                    ("media-type", cover.media_type.as_str()),
+                ]);
+                if epub3 {
+                    item.push_attribute(("properties", "cover-image"));
+                }
+                writer.write_event(Event::Text(BytesText::new("\n    "))).map_err(io::Error::other)?;
+                writer.write_event(Event::Empty(item)).map_err(io::Error::other)?;
+                writer.write_event(Event::Text(BytesText::new("\n  "))).map_err(io::Error::other)?;
+                writer.write_event(event.clone()).map_err(io::Error::other)?;
+            }
+            Event::End(ref e) if !meta_written && e.local_name().as_ref() == b"metadata" => {
+                writer.write_event(Event::Text(BytesText::new("\n    "))).map_err(io::Error::other)?;
+                writer.write_event(Event::Empty(cover_meta(&cover.id))).map_err(io::Error::other)?;
+                writer.write_event(Event::Text(BytesText::new("\n  "))).map_err(io::Error::other)?;
+                writer.write_event(event.clone()).map_err(io::Error::other)?;
+                meta_written = true;
+            }
+            Event::Eof => break,
+            event => writer.write_event(event).map_err(io::Error::other)?,
+        }
+    }
+    String::from_utf8(writer.into_inner()).map_err(io::Error::other)
+}
+
+/// `<meta name="cover" content="{id}"/>`
+fn cover_meta(id: &str) -> BytesStart<'_> {
+    let mut meta = BytesStart::new("meta");
+    meta.extend_attributes([("name", "cover"), ("content", id)]);
+    meta
+}
+
+/// The properties of the cover's item: its own, with "cover-image" in
+/// EPUB3 packages
+fn cover_properties(properties: &str, epub3: bool) -> String {
+    let mut properties: Vec<&str> = properties.split_whitespace().collect();
+    if epub3 && !properties.contains(&"cover-image") {
+        properties.push("cover-image");
+    }
+    properties.join(" ")
+}
+
+/// Gets the unescaped value of an attribute by its local name
+pub fn attribute(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|attr| attr.key.local_name().as_ref() == name)
+        .and_then(|attr| attr.unescape_value().ok())
+        .map(|value| value.to_string())
+}
+
+/// Checks whether an identifier looks like an ISBN-10 or ISBN-13
+/// (e.g. "urn:isbn:9780261103573" or "978-0-261-10357-3")
+fn looks_like_isbn(text: &str) -> bool {
+    let digits = normalize_isbn(text);
+    (digits.len() == 10 || digits.len() == 13) && text.to_ascii_lowercase().contains("isbn")
+        || (digits.len() == 13 && (digits.starts_with("978") || digits.starts_with("979")))
+}
+
+/// Strips an ISBN down to its digits (keeping a trailing X check digit)
+pub fn normalize_isbn(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_ascii_digit() || *c == 'X' || *c == 'x')
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
@@ -0,0 +1,119 @@
+// src/exit.rs
+// How a command ends when it fails: an exit code per cause, and the error
+// written to stderr - as text, or as a line of JSON (`--errors json`) so
+// scripts can tell the causes apart without reading the message
+//
+//   0  done
+//   1  input/output error (a file that can't be read or written, the terminal...)
+//   2  wrong command line (unknown command or option, missing argument...)
+//   3  no books found (`list` matched none, `scan`, `stats`, `dedupe`, `bench` found none)
+//   4  a path given on the command line doesn't exist
+//   5  the server (`serve`, `daemon`) couldn't start, or stopped on an error
+//
+// e.g. {"code":4,"error":"invalid-path","message":"/media/usb: no such file or folder"}
+
+use crate::config::Command;
+use serde_json::json;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Why a command failed
+#[derive(Debug)]
+pub enum Failure {
+    /// Reading or writing failed
+    Io(io::Error),
+
+    /// The command line is wrong (what's wrong, and the command it was for)
+    Usage(String, Command),
+
+    /// Nothing to work on, or nothing matched (what was looked for)
+    NoBooks(String),
+
+    /// A path given on the command line doesn't exist
+    InvalidPath(PathBuf),
+
+    /// The HTTP server failed
+    Server(io::Error),
+}
+
+/// How failures are written to stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorFormat {
+    /// `funkhunt: message`, with a hint when the command line is wrong
+    #[default]
+    Text,
+    /// `{"code": code, "error": kind, "message": message}` on one line
+    Json,
+}
+
+impl ErrorFormat {
+    /// The format a name is for: "text" or "json"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(ErrorFormat::Text),
+            "json" => Some(ErrorFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+impl Failure {
+    /// The process' exit code
+    pub fn code(&self) -> i32 {
+        match self {
+            Failure::Io(_) => 1,
+            Failure::Usage(..) => 2,
+            Failure::NoBooks(_) => 3,
+            Failure::InvalidPath(_) => 4,
+            Failure::Server(_) => 5,
+        }
+    }
+
+    /// Name of the cause, for JSON errors, e.g. "invalid-path"
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Failure::Io(_) => "io",
+            Failure::Usage(..) => "usage",
+            Failure::NoBooks(_) => "no-books",
+            Failure::InvalidPath(_) => "invalid-path",
+            Failure::Server(_) => "server",
+        }
+    }
+
+    /// Writes the failure to stderr
+    pub fn report(&self, format: ErrorFormat) {
+        match format {
+            ErrorFormat::Text => {
+                eprintln!("funkhunt: {}", self);
+                if let Failure::Usage(_, command) = self {
+                    match command {
+                        Command::Tui => eprintln!("Try `funkhunt --help`"),
+                        command => eprintln!("Try `funkhunt help {}`", command.name()),
+                    }
+                }
+            }
+            ErrorFormat::Json => {
+                eprintln!("{}", json!({ "error": self.kind(), "code": self.code(), "message": self.to_string() }));
+            }
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Io(e) => write!(f, "{}", e),
+            Failure::Usage(message, _) | Failure::NoBooks(message) => write!(f, "{}", message),
+            Failure::InvalidPath(path) => write!(f, "{}: no such file or folder", path.display()),
+            Failure::Server(e) => write!(f, "server: {}", e),
+        }
+    }
+}
+
+/// Whatever fails reading or writing (`?` on an io::Result)
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Self {
+        Failure::Io(e)
+    }
+}
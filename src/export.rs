@@ -0,0 +1,106 @@
+// src/export.rs
+// Structured JSON export of a library - an interchange format for other tools and for backups
+
+use crate::book::Book;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Value of the `format` field, lets other tools recognize our exports
+pub const EXPORT_FORMAT: &str = "funkhunt-library";
+
+/// Version of the export layout - bump when fields change incompatibly
+pub const EXPORT_VERSION: u32 = 1;
+
+/// Top-level document of a library export
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LibraryExport {
+    /// Always EXPORT_FORMAT
+    pub format: String,
+
+    /// Layout version (EXPORT_VERSION at the time of writing)
+    pub version: u32,
+
+    /// Name of the library profile that was exported
+    pub library: String,
+
+    /// When the export was made (seconds since the Unix epoch)
+    pub exported_at: u64,
+
+    /// Every book of the library
+    pub books: Vec<ExportedBook>,
+}
+
+/// A single book inside an export
+///
+/// The Book itself is flattened into the object, so everything the
+/// database stores about a book (metadata and user data) is exported,
+/// plus a few facts read from the file on disk.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedBook {
+    /// All stored fields of the book
+    #[serde(flatten)]
+    pub book: Book,
+
+    /// File size in bytes (None if the file couldn't be read)
+    pub size_bytes: Option<u64>,
+
+    /// Last modification time of the file (seconds since the Unix epoch)
+    pub modified: Option<u64>,
+}
+
+impl LibraryExport {
+    /// Builds an export document from the books of a library
+    ///
+    /// # Arguments
+    /// * `library` - Name of the library profile
+    /// * `books` - The books to export
+    pub fn new(library: &str, books: &[Book]) -> Self {
+        Self {
+            format: EXPORT_FORMAT.to_string(),
+            version: EXPORT_VERSION,
+            library: library.to_string(),
+            exported_at: unix_seconds(SystemTime::now()).unwrap_or(0),
+            books: books.iter().map(ExportedBook::from_book).collect(),
+        }
+    }
+
+    /// Writes the export as pretty-printed JSON
+    ///
+    /// # Arguments
+    /// * `dest` - Output file, or `-` to write to stdout
+    pub fn write_to(&self, dest: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+
+        if dest == Path::new("-") {
+            // `-` is the usual convention for "standard output"
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(json.as_bytes())?;
+            stdout.write_all(b"\n")
+        } else {
+            std::fs::write(dest, json)
+        }
+    }
+}
+
+impl ExportedBook {
+    /// Wraps a book together with the facts read from its file
+    pub fn from_book(book: &Book) -> Self {
+        // A missing file just leaves the file facts empty
+        let meta = std::fs::metadata(&book.path).ok();
+
+        Self {
+            book: book.clone(),
+            size_bytes: meta.as_ref().map(|m| m.len()),
+            modified: meta
+                .and_then(|m| m.modified().ok())
+                .and_then(unix_seconds),
+        }
+    }
+}
+
+/// Converts a SystemTime to whole seconds since the Unix epoch
+fn unix_seconds(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
@@ -0,0 +1,164 @@
+// src/extract.rs
+// Text of a book for other tools - the chapters of an EPUB written out as
+// plain text or Markdown, to grep, quote or feed to something else
+// (`funkhunt extract BOOK --format md`, or W in the app)
+//
+// The text is the reader's (see reader.rs): what reads well in a terminal.
+// Plain text is a line per paragraph, blank lines between them; Markdown
+// keeps the headings (the first of a chapter as #, the others as ##), bold
+// and italic text, quotes, list items, preformatted text and separators.
+// Images are their alt text, as in the reader.
+
+use crate::reader::{BlockKind, Document, Span};
+use serde::Deserialize;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How the text is written
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextFormat {
+    #[default]
+    Text,
+
+    Markdown,
+}
+
+impl TextFormat {
+    /// The format a name asks for: "txt" or "text", "md" or "markdown" (any case)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "txt" | "text" => Some(TextFormat::Text),
+            "md" | "markdown" => Some(TextFormat::Markdown),
+            _ => None,
+        }
+    }
+
+    /// Extension of the files in this format, e.g. "md"
+    pub fn extension(self) -> &'static str {
+        match self {
+            TextFormat::Text => "txt",
+            TextFormat::Markdown => "md",
+        }
+    }
+}
+
+/// The text of a book in a format
+pub fn render(document: &Document, format: TextFormat) -> String {
+    let mut chapters = Vec::new();
+    for chapter in &document.chapters {
+        let mut blocks: Vec<String> = Vec::new();
+        let mut first_heading = true;
+        let mut previous = None;
+        for block in &chapter.blocks {
+            let text = match format {
+                TextFormat::Text => block.text(),
+                TextFormat::Markdown => markdown(&block.spans),
+            };
+            let text = text.trim_end();
+            if text.trim().is_empty() && block.kind != BlockKind::Rule {
+                continue;
+            }
+
+            let text = match (format, block.kind) {
+                (TextFormat::Text, BlockKind::Quote) => prefix_lines(text, "    "),
+                (TextFormat::Text, BlockKind::ListItem) => format!("- {}", text.trim()),
+                (TextFormat::Text, BlockKind::Rule) => "* * *".to_string(),
+                (TextFormat::Text, BlockKind::Preformatted) => text.to_string(),
+                (TextFormat::Text, _) => text.trim().to_string(),
+                (TextFormat::Markdown, BlockKind::Heading) => {
+                    let level = if first_heading { "#" } else { "##" };
+                    first_heading = false;
+                    format!("{} {}", level, text.trim().replace('\n', " "))
+                }
+                (TextFormat::Markdown, BlockKind::Quote) => prefix_lines(text.trim(), "> "),
+                (TextFormat::Markdown, BlockKind::ListItem) => format!("- {}", text.trim()),
+                // The spans of preformatted text, unescaped
+                (TextFormat::Markdown, BlockKind::Preformatted) => format!("```\n{}\n```", block.text().trim_end()),
+                (TextFormat::Markdown, BlockKind::Rule) => "---".to_string(),
+                (TextFormat::Markdown, BlockKind::Paragraph) => text.trim().to_string(),
+            };
+            // The items of a list go line after line
+            match blocks.last_mut() {
+                Some(last) if block.kind == BlockKind::ListItem && previous == Some(BlockKind::ListItem) => {
+                    last.push('\n');
+                    last.push_str(&text);
+                }
+                _ => blocks.push(text),
+            }
+            previous = Some(block.kind);
+        }
+        if !blocks.is_empty() {
+            chapters.push(blocks.join("\n\n"));
+        }
+    }
+
+    let separator = match format {
+        TextFormat::Text => "\n\n\n",
+        TextFormat::Markdown => "\n\n",
+    };
+    let mut out = chapters.join(separator);
+    out.push('\n');
+    out
+}
+
+/// Spans as Markdown: bold and italic marked, characters Markdown reads as
+/// markup escaped
+fn markdown(spans: &[Span]) -> String {
+    let mut out = String::new();
+    for span in spans {
+        let escaped: String = span
+            .text
+            .chars()
+            .flat_map(|c| match c {
+                '\\' | '*' | '_' | '`' => vec!['\\', c],
+                c => vec![c],
+            })
+            .collect();
+        let marks = match (span.style.bold, span.style.italic) {
+            (true, true) => "***",
+            (true, false) => "**",
+            (false, true) => "*",
+            (false, false) => "",
+        };
+        // "**word** " and not "**word **", which isn't bold
+        let core = escaped.trim();
+        if marks.is_empty() || core.is_empty() {
+            out.push_str(&escaped);
+            continue;
+        }
+        let start = escaped.len() - escaped.trim_start().len();
+        let end = escaped.trim_end().len();
+        out.push_str(&escaped[..start]);
+        out.push_str(&format!("{}{}{}", marks, core, marks));
+        out.push_str(&escaped[end..]);
+    }
+    out
+}
+
+/// Every line of a text starting with a prefix
+fn prefix_lines(text: &str, prefix: &str) -> String {
+    text.lines().map(|line| format!("{}{}", prefix, line.trim())).collect::<Vec<_>>().join("\n")
+}
+
+/// Writes the text of a book to a file named after it in a folder
+///
+/// # Arguments
+/// * `book` - The EPUB file
+/// * `title` - Title of the book (the file's name)
+/// * `folder` - Where the text goes (created if needed)
+/// * `format` - Plain text or Markdown
+///
+/// # Returns
+/// The file written
+pub fn export_book(book: &Path, title: &str, folder: &Path, format: TextFormat) -> io::Result<PathBuf> {
+    let document = Document::open(book)?;
+    std::fs::create_dir_all(folder)?;
+    let name: String = title
+        .chars()
+        .map(|c| if c.is_alphanumeric() || " -_.,'()".contains(c) { c } else { '_' })
+        .collect();
+    let dest = folder.join(format!("{}.{}", name.trim(), format.extension()));
+    std::fs::write(&dest, render(&document, format))?;
+    Ok(dest)
+}
@@ -0,0 +1,362 @@
+// src/feeds.rs
+// RSS and Atom feeds of new books, e.g. Standard Ebooks' new releases
+//
+// The feeds listed in [feeds] urls are fetched when the "New from feeds"
+// screen opens. Both kinds are read:
+// - RSS 2.0: <item> with <title>, <guid>, <pubDate>, <dc:creator> (or
+//   <author>) and <enclosure url="..." type="application/epub+zip"/>
+// - Atom (OPDS catalogs are Atom too): <entry> with <id>, <title>,
+//   <updated>, <author><name> and <link href="..." type="application/epub+zip"/>
+//
+// Entries without an EPUB link are listed, but can't be downloaded.
+//
+// The ids of the entries seen so far are kept in feeds.json in the data
+// directory; the other entries are the new ones.
+
+use crate::transfer::{self, Chunk, Progress};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Where the ids of the entries seen so far are stored (in the data directory)
+const SEEN_FILE: &str = "feeds.json";
+
+/// How long to wait for a feed's server before giving up
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// MIME type of EPUB files
+const EPUB_TYPE: &str = "application/epub+zip";
+
+/// A book announced by a feed
+#[derive(Debug, Clone)]
+pub struct FeedEntry {
+    /// Title of the feed it comes from
+    pub feed: String,
+
+    /// Id of the entry (guid / id, or else its link)
+    pub id: String,
+
+    /// Title of the book
+    pub title: String,
+
+    /// Authors
+    pub authors: Vec<String>,
+
+    /// Day it was published or updated, as "YYYY-MM-DD"
+    pub date: Option<String>,
+
+    /// Address of the EPUB file (None = the entry has none)
+    pub epub: Option<String>,
+
+    /// Size of the EPUB file, when the feed says
+    pub size: Option<u64>,
+}
+
+impl FeedEntry {
+    /// File name a download is saved as, e.g. "Dune - Frank Herbert.epub"
+    pub fn file_name(&self) -> String {
+        let name = match self.authors.first() {
+            Some(author) => format!("{} - {}.epub", self.title, author),
+            None => format!("{}.epub", self.title),
+        };
+        match crate::organize::sanitize(&name) {
+            name if name.is_empty() => "feed-book.epub".to_string(),
+            name => name,
+        }
+    }
+}
+
+/// A fetched feed
+#[derive(Debug, Clone)]
+pub struct Feed {
+    /// Title of the feed (its address when it has none)
+    pub title: String,
+
+    /// Its entries, in the feed's order
+    pub entries: Vec<FeedEntry>,
+}
+
+/// Ids of the feed entries seen so far
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Seen {
+    /// Ids of the entries (see `FeedEntry::id`)
+    pub ids: BTreeSet<String>,
+}
+
+impl Seen {
+    /// Loads the ids stored by earlier sessions
+    pub fn load() -> Self {
+        std::fs::read_to_string(seen_path())
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    /// Stores the ids for the next sessions
+    pub fn save(&self) -> io::Result<()> {
+        let path = seen_path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        std::fs::write(path, json)
+    }
+}
+
+/// Where the seen ids are stored
+fn seen_path() -> PathBuf {
+    crate::paths::data_dir().join(SEEN_FILE)
+}
+
+/// Fetches and reads a feed
+///
+/// # Arguments
+/// * `url` - Address of the RSS or Atom feed
+pub fn fetch(url: &str) -> Result<Feed, String> {
+    let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+    let xml = agent
+        .get(url)
+        .call()
+        .map_err(|e| match e {
+            ureq::Error::Status(code, _) => format!("the server answered {}", code),
+            other => other.to_string(),
+        })?
+        .into_string()
+        .map_err(|e| e.to_string())?;
+    let feed = parse(&xml, url)?;
+    tracing::info!(feed = %feed.title, url = %url, entries = feed.entries.len(), "feed fetched");
+    Ok(feed)
+}
+
+/// Reads an RSS 2.0 or Atom document
+///
+/// # Arguments
+/// * `xml` - The document
+/// * `url` - Where it was fetched from (relative links are resolved against it)
+pub fn parse(xml: &str, url: &str) -> Result<Feed, String> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+
+    let mut title: Option<String> = None;
+    let mut entries = Vec::new();
+    // The <item> / <entry> being read (None = in the feed's own header)
+    let mut entry: Option<FeedEntry> = None;
+    // Name of the element whose text we're currently reading, and whether
+    // it's inside an <author>
+    let mut current: Option<Vec<u8>> = None;
+    let mut in_author = false;
+
+    loop {
+        match reader.read_event().map_err(|e| format!("not a valid feed: {}", e))? {
+            Event::Start(e) => {
+                let name = e.local_name().as_ref().to_vec();
+                match name.as_slice() {
+                    b"item" | b"entry" => {
+                        entry = Some(FeedEntry {
+                            feed: String::new(),
+                            id: String::new(),
+                            title: String::new(),
+                            authors: Vec::new(),
+                            date: None,
+                            epub: None,
+                            size: None,
+                        })
+                    }
+                    b"author" => in_author = true,
+                    _ => {}
+                }
+                if let Some(entry) = entry.as_mut() {
+                    read_link(&e, url, entry);
+                }
+                current = Some(name);
+            }
+
+            // <enclosure .../> and <link .../> carry their data in attributes
+            Event::Empty(e) => {
+                if let Some(entry) = entry.as_mut() {
+                    read_link(&e, url, entry);
+                }
+            }
+
+            Event::Text(t) => {
+                let text = t.unescape().map_err(|e| e.to_string())?.trim().to_string();
+                store_text(current.as_deref(), in_author, text, &mut title, entry.as_mut());
+            }
+            Event::CData(t) => {
+                let text = String::from_utf8_lossy(&t).trim().to_string();
+                store_text(current.as_deref(), in_author, text, &mut title, entry.as_mut());
+            }
+
+            Event::End(e) => {
+                match e.local_name().as_ref() {
+                    b"item" | b"entry" => {
+                        if let Some(mut done) = entry.take() {
+                            if done.id.is_empty() {
+                                done.id = done.epub.clone().unwrap_or_else(|| done.title.clone());
+                            }
+                            entries.push(done);
+                        }
+                    }
+                    b"author" => in_author = false,
+                    _ => {}
+                }
+                current = None;
+            }
+
+            Event::Eof => break,
+
+            _ => {}
+        }
+    }
+
+    if title.is_none() && entries.is_empty() {
+        return Err("not an RSS or Atom feed".to_string());
+    }
+    let title = title.unwrap_or_else(|| url.to_string());
+    for entry in &mut entries {
+        entry.feed = title.clone();
+    }
+    Ok(Feed { title, entries })
+}
+
+/// Stores the text of an element in the feed's title or the current entry
+fn store_text(element: Option<&[u8]>, in_author: bool, text: String, title: &mut Option<String>, entry: Option<&mut FeedEntry>) {
+    if text.is_empty() {
+        return;
+    }
+    let Some(entry) = entry else {
+        if element == Some(b"title") && title.is_none() {
+            *title = Some(text);
+        }
+        return;
+    };
+
+    match element {
+        Some(b"title") if entry.title.is_empty() => entry.title = text,
+        Some(b"guid") | Some(b"id") if entry.id.is_empty() => entry.id = text,
+        // Atom: <author><name>...</name></author>; RSS: <dc:creator>, or
+        // <author>mail@example.com (Name)</author>
+        Some(b"name") if in_author => entry.authors.push(text),
+        Some(b"creator") => entry.authors.push(text),
+        Some(b"author") => {
+            let name = match (text.find('('), text.rfind(')')) {
+                (Some(start), Some(end)) if start < end => text[start + 1..end].trim().to_string(),
+                _ => text,
+            };
+            entry.authors.push(name);
+        }
+        Some(b"pubDate") | Some(b"updated") | Some(b"published") | Some(b"date") if entry.date.is_none() => {
+            entry.date = day_of(&text)
+        }
+        _ => {}
+    }
+}
+
+/// Picks up the EPUB link of an entry from an <enclosure> or <link> element
+fn read_link(element: &BytesStart, base: &str, entry: &mut FeedEntry) {
+    let (link, size) = match element.local_name().as_ref() {
+        b"enclosure" => (crate::epub::attribute(element, b"url"), crate::epub::attribute(element, b"length")),
+        b"link" => (crate::epub::attribute(element, b"href"), crate::epub::attribute(element, b"length")),
+        _ => return,
+    };
+    let is_epub = crate::epub::attribute(element, b"type").is_some_and(|t| t.starts_with(EPUB_TYPE));
+    let Some(link) = link.filter(|_| is_epub && entry.epub.is_none()) else {
+        return;
+    };
+    entry.epub = Some(resolve(base, &link));
+    entry.size = size.and_then(|size| size.parse().ok()).filter(|size| *size > 0);
+}
+
+/// Makes a link of a feed absolute, e.g. "/ebooks/x.epub" against
+/// "https://standardebooks.org/feeds/rss" -> "https://standardebooks.org/ebooks/x.epub"
+fn resolve(base: &str, link: &str) -> String {
+    if link.contains("://") {
+        return link.to_string();
+    }
+    let scheme_end = base.find("://").map(|i| i + 3).unwrap_or(0);
+    let scheme = &base[..scheme_end];
+    let rest = &base[scheme_end..];
+    let host = &rest[..rest.find('/').unwrap_or(rest.len())];
+    if let Some(path) = link.strip_prefix("//") {
+        return format!("{}{}", scheme, path);
+    }
+    if link.starts_with('/') {
+        return format!("{}{}{}", scheme, host, link);
+    }
+    // Relative to the folder of the feed
+    let folder = base.split(['?', '#']).next().unwrap_or(base);
+    match folder.rfind('/').filter(|i| *i >= scheme_end) {
+        Some(i) => format!("{}/{}", &folder[..i], link),
+        None => format!("{}/{}", folder, link),
+    }
+}
+
+/// Reads the day of a feed date, either RFC 3339 ("2026-10-14T18:03:51Z")
+/// or RFC 822 ("Tue, 14 Oct 2026 18:03:51 +0000")
+///
+/// # Returns
+/// The day as "YYYY-MM-DD"
+fn day_of(text: &str) -> Option<String> {
+    const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+    let day = text.get(..10).filter(|d| {
+        d.bytes().enumerate().all(|(i, b)| if i == 4 || i == 7 { b == b'-' } else { b.is_ascii_digit() })
+    });
+    if let Some(day) = day {
+        return Some(day.to_string());
+    }
+
+    // "Tue, 14 Oct 2026 ..." -> ["14", "Oct", "2026"]
+    let words: Vec<&str> = text.split_once(',').map(|(_, rest)| rest).unwrap_or(text).split_whitespace().collect();
+    let [day, month, year, ..] = words.as_slice() else {
+        return None;
+    };
+    let day: u32 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|m| month.to_lowercase().starts_with(m))? + 1;
+    let year: u32 = year.parse().ok()?;
+    Some(format!("{:04}-{:02}-{:02}", year, month, day))
+}
+
+/// Downloads the EPUB of an entry into a folder
+///
+/// The file is fetched in ranges, and resumes after a failure (see
+/// `transfer::fetch_chunked`).
+///
+/// # Arguments
+/// * `entry` - The entry
+/// * `folder` - Where the file goes (a free name is picked)
+/// * `progress` - The transfer the download is shown as
+///
+/// # Returns
+/// Path of the downloaded file
+pub fn download(entry: &FeedEntry, folder: &Path, progress: &Progress) -> Result<PathBuf, String> {
+    let url = entry.epub.as_deref().ok_or("the feed has no EPUB for this book")?;
+    let agent = ureq::AgentBuilder::new().timeout_connect(TIMEOUT).timeout_read(TIMEOUT).build();
+    std::fs::create_dir_all(folder).map_err(|e| e.to_string())?;
+    let partial = crate::peer::partial_path(folder, &entry.file_name(), url);
+    transfer::fetch_chunked(&partial, entry.size, progress, |start, end| {
+        let response = agent
+            .get(url)
+            .set("Range", &format!("bytes={}-{}", start, end))
+            .call()
+            .map_err(|e| match e {
+                ureq::Error::Status(code, _) => format!("the server answered {}", code),
+                other => other.to_string(),
+            })?;
+        let (start, total) = Chunk::position(&response);
+        Ok(Chunk {
+            tag: Chunk::tag(&response),
+            reader: response.into_reader(),
+            start,
+            total,
+        })
+    })?;
+
+    let dest = crate::peer::free_path(folder, &entry.file_name());
+    transfer::complete(&partial, &dest, entry.size, None)?;
+    Ok(dest)
+}
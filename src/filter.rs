@@ -0,0 +1,544 @@
+// src/filter.rs
+// Filter expressions for the book list, e.g. `status:unread tag:fantasy size:<1mb`
+//
+// A filter is a list of terms separated by spaces; a book must match all of them.
+// - `word`              title, authors or file name contain "word"
+// - `title:x` `author:x` `tag:x` `series:x` `lang:x` `collection:x`
+//                       that field contains x (`lang:` is the language the
+//                       book's text was found to be in, else its package's)
+// - `ext:epub`          file extension (any case)
+// - `status:reading`    reading status (to-read, reading, finished, abandoned,
+//                       none, or unread = to-read or none)
+// - `starred`           starred books only
+// - `archived`          archived books (they're hidden unless a filter asks for them)
+// - `is:fixed`          fixed-layout books (comics, picture books...)
+// - `is:read-aloud`     books with media overlays (narration synchronized
+//                       with the text); `-is:fixed -is:read-aloud` leaves them out
+// - `accessible`        books that claim to meet an accessibility standard
+//                       (EPUB Accessibility, in their package)
+// - `feature:x`         an accessibility feature of the book contains x,
+//                       e.g. `feature:alttext` (alternativeText)
+// - `hazard:none`       books that say they have no hazards; `hazard:x`:
+//                       books with a hazard containing x (`-hazard:flashing`)
+// - `rating:>=4`        rating compared with <, <=, >, >= or = (unrated = 0)
+// - `size:<1mb`         file size compared with a number of b, kb, mb or gb
+// - `grade:<=6`         readability grade level, rounded (books never
+//                       measured don't match)
+// - `added:<7d`         time since the book was added, in d(ays) or w(eeks)
+// - `opened:<30d`       time since it was last opened (`opened:any` = ever opened)
+// - `modified:<1d`      time since its file was last modified
+// - `-term`             books that do NOT match the term
+// Values with spaces go in double quotes: `tag:"science fiction"`.
+// Text is compared folded (see fold.rs): "garcia" finds "García".
+//
+// The book list runs filters against its index (see query.rs), which
+// finds the books of plain words and `title:`, `author:`, `tag:`, `size:`
+// and `modified:` terms by itself and keeps what the others compare;
+// without one, every book is read as it is.
+
+use crate::book::{unix_now, Book, ReadingStatus};
+use crate::fold::fold;
+use crate::query::Facts;
+use std::cmp::Ordering;
+use std::ops::Bound;
+use std::time::UNIX_EPOCH;
+
+/// A parsed filter expression
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// All terms must match (an empty filter matches every book)
+    terms: Vec<Term>,
+}
+
+/// One term of a filter, possibly negated
+#[derive(Debug, Clone)]
+struct Term {
+    /// Whether the term was written with a leading '-'
+    negated: bool,
+
+    /// What the term tests
+    test: Test,
+}
+
+/// The tests a term can perform
+#[derive(Debug, Clone)]
+enum Test {
+    /// Title, authors or file name contain the (folded) text
+    Text(String),
+    /// Title contains the text
+    Title(String),
+    /// One of the authors contains the text
+    Author(String),
+    /// One of the tags contains the text
+    Tag(String),
+    /// Series name contains the text
+    Series(String),
+    /// Language starts with the text ("en" matches "en-GB")
+    Language(String),
+    /// Book is in a collection whose name contains the text
+    Collection(String),
+    /// File extension is the text (lowercase, without the dot)
+    Extension(String),
+    /// Reading status is one of these (None = no status)
+    Status(Vec<Option<ReadingStatus>>),
+    /// Book is starred
+    Starred,
+    /// Book is archived
+    Archived,
+    /// Book has a fixed layout
+    FixedLayout,
+    /// Book has media overlays (read-aloud)
+    MediaOverlays,
+    /// Book claims to meet an accessibility standard
+    Accessible,
+    /// One of the accessibility features contains the text (case and
+    /// punctuation ignored)
+    Feature(String),
+    /// Book has a hazard containing the text (None = says it has none)
+    Hazard(Option<String>),
+    /// Rating (0 = unrated) compares to a number
+    Rating(Comparison, u64),
+    /// File size in bytes compares to a number
+    Size(Comparison, u64),
+    /// Readability grade level (rounded) compares to a number
+    Grade(Comparison, u64),
+    /// Seconds since the book was added compare to a number
+    AddedAgo(Comparison, u64),
+    /// Seconds since the book was last opened compare to a number
+    /// (None = opened at all, whenever that was)
+    OpenedAgo(Option<(Comparison, u64)>),
+    /// Seconds since the book's file was last modified compare to a number
+    ModifiedAgo(Comparison, u64),
+}
+
+/// A term the book index answers by itself (see query.rs): which books
+/// have an author, a tag or a word (of their title, file name or authors)
+/// containing a text, or a size or a modification time in a range
+#[derive(Debug, Clone, Copy)]
+pub enum Lookup<'a> {
+    /// Folded word
+    Word(&'a str),
+    /// Folded text
+    Author(&'a str),
+    /// Folded text
+    Tag(&'a str),
+    /// Bytes
+    Size(Bound<u64>, Bound<u64>),
+    /// Unix time
+    Modified(Bound<u64>, Bound<u64>),
+}
+
+/// Comparison operators for numeric terms
+#[derive(Debug, Clone, Copy)]
+enum Comparison {
+    /// `<`
+    Less,
+    /// `<=`
+    LessOrEqual,
+    /// `=` (or no operator)
+    Equal,
+    /// `>=`
+    GreaterOrEqual,
+    /// `>`
+    Greater,
+}
+
+impl Filter {
+    /// Parses a filter expression
+    ///
+    /// # Arguments
+    /// * `text` - The expression, e.g. `status:unread tag:fantasy size:<1mb`
+    ///
+    /// # Returns
+    /// The parsed filter, or a human-readable reason the expression is invalid
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let terms = split_terms(text)?
+            .iter()
+            .map(|word| parse_term(word))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { terms })
+    }
+
+    /// Whether the filter has no terms (matches everything)
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Whether the filter asks about archiving (`archived` or `-archived`),
+    /// in which case archived books must not be hidden before it runs
+    pub fn mentions_archived(&self) -> bool {
+        self.terms.iter().any(|term| matches!(term.test, Test::Archived))
+    }
+
+    /// Checks whether a book matches every term
+    pub fn matches(&self, book: &Book) -> bool {
+        self.terms
+            .iter()
+            .all(|term| term.test.matches(book, None) != term.negated)
+    }
+
+    /// Checks whether a book matches every term, with what its index entry
+    /// keeps of it (folded texts, its file's size and time)
+    pub fn matches_indexed(&self, book: &Book, facts: &Facts) -> bool {
+        self.terms
+            .iter()
+            .all(|term| term.test.matches(book, Some(facts)) != term.negated)
+    }
+
+    /// The terms the book index can look up (those not negated): a book
+    /// that matches the filter is among the books of each
+    pub fn lookups(&self) -> Vec<Lookup<'_>> {
+        let now = unix_now();
+        self.terms
+            .iter()
+            .filter(|term| !term.negated)
+            .filter_map(|term| match &term.test {
+                // The books with a word containing its longest word are a
+                // few more than those containing it
+                Test::Text(text) | Test::Title(text) => {
+                    crate::query::words(text).max_by_key(|word| word.len()).map(Lookup::Word)
+                }
+                Test::Author(text) => Some(Lookup::Author(text)),
+                Test::Tag(text) => Some(Lookup::Tag(text)),
+                Test::Size(comparison, value) => {
+                    let (from, to) = comparison.bounds(*value);
+                    Some(Lookup::Size(from, to))
+                }
+                // Less time ago = later: the bounds swap
+                Test::ModifiedAgo(comparison, value) => {
+                    let (from, to) = comparison.bounds(*value);
+                    let time = |ago: Bound<u64>| match ago {
+                        Bound::Included(ago) => Bound::Included(now.saturating_sub(ago)),
+                        Bound::Excluded(ago) => Bound::Excluded(now.saturating_sub(ago)),
+                        Bound::Unbounded => Bound::Unbounded,
+                    };
+                    Some(Lookup::Modified(time(to), time(from)))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Test {
+    /// Runs the test against a book (with its index entry, if it has one)
+    fn matches(&self, book: &Book, facts: Option<&Facts>) -> bool {
+        match self {
+            Test::Text(text) => match facts {
+                Some(facts) => {
+                    facts.title.contains(text.as_str())
+                        || facts.name.contains(text.as_str())
+                        || facts.authors.iter().any(|a| a.contains(text.as_str()))
+                }
+                None => {
+                    contains(book.display_title(), text)
+                        || contains(&book.name, text)
+                        || book.authors().iter().any(|a| contains(a, text))
+                }
+            },
+            Test::Title(text) => match facts {
+                Some(facts) => facts.title.contains(text.as_str()),
+                None => contains(book.display_title(), text),
+            },
+            Test::Author(text) => match facts {
+                Some(facts) => facts.authors.iter().any(|a| a.contains(text.as_str())),
+                None => book.authors().iter().any(|a| contains(a, text)),
+            },
+            Test::Tag(text) => match facts {
+                Some(facts) => facts.tags.iter().any(|t| t.contains(text.as_str())),
+                None => book.user.tags.iter().any(|t| contains(t, text)),
+            },
+            Test::Series(text) => book.meta.series.as_deref().is_some_and(|s| contains(s, text)),
+            Test::Language(text) => book.language().is_some_and(|l| l.to_lowercase().starts_with(text.as_str())),
+            Test::Collection(text) => book.user.collections.iter().any(|c| contains(c, text)),
+            Test::Extension(text) => book
+                .path
+                .extension()
+                .is_some_and(|extension| extension.to_string_lossy().to_lowercase() == *text),
+            Test::Status(statuses) => statuses.contains(&book.user.status),
+            Test::Starred => book.user.starred,
+            Test::Archived => book.user.archived,
+            Test::FixedLayout => book.meta.fixed_layout,
+            Test::MediaOverlays => book.meta.media_overlays,
+            Test::Accessible => book.meta.accessibility.conforms(),
+            Test::Feature(text) => book.meta.accessibility.features.iter().any(|f| compact(f).contains(text.as_str())),
+            Test::Hazard(None) => book.meta.accessibility.hazard_free(),
+            Test::Hazard(Some(text)) => book.meta.accessibility.declared_hazards().any(|h| compact(h).contains(text.as_str())),
+            Test::Rating(comparison, value) => {
+                comparison.holds(book.user.rating.unwrap_or(0) as u64, *value)
+            }
+            Test::Size(comparison, value) => match facts {
+                Some(facts) => facts.size.is_some_and(|size| comparison.holds(size, *value)),
+                None => std::fs::metadata(&book.path)
+                    .map(|meta| comparison.holds(meta.len(), *value))
+                    .unwrap_or(false),
+            },
+            Test::Grade(comparison, value) => book
+                .readability
+                .is_some_and(|r| comparison.holds(r.grade.round() as u64, *value)),
+            Test::AddedAgo(comparison, value) => book
+                .added
+                .is_some_and(|added| comparison.holds(unix_now().saturating_sub(added), *value)),
+            Test::OpenedAgo(None) => book.opened.is_some(),
+            Test::OpenedAgo(Some((comparison, value))) => book
+                .opened
+                .is_some_and(|opened| comparison.holds(unix_now().saturating_sub(opened), *value)),
+            Test::ModifiedAgo(comparison, value) => {
+                let modified = match facts {
+                    Some(facts) => facts.modified,
+                    None => modified_time(&book.path),
+                };
+                modified.is_some_and(|modified| comparison.holds(unix_now().saturating_sub(modified), *value))
+            }
+        }
+    }
+}
+
+/// When a file was last modified, as a unix time (None for a file that
+/// can't be read, or on a server)
+pub fn modified_time(path: &std::path::Path) -> Option<u64> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    modified.duration_since(UNIX_EPOCH).ok().map(|since| since.as_secs())
+}
+
+impl Comparison {
+    /// Whether `left <op> right` is true
+    fn holds(self, left: u64, right: u64) -> bool {
+        let ordering = left.cmp(&right);
+        match self {
+            Comparison::Less => ordering == Ordering::Less,
+            Comparison::LessOrEqual => ordering != Ordering::Greater,
+            Comparison::Equal => ordering == Ordering::Equal,
+            Comparison::GreaterOrEqual => ordering != Ordering::Less,
+            Comparison::Greater => ordering == Ordering::Greater,
+        }
+    }
+
+    /// The values `x` for which `x <op> value` is true, as a range
+    fn bounds(self, value: u64) -> (Bound<u64>, Bound<u64>) {
+        match self {
+            Comparison::Less => (Bound::Unbounded, Bound::Excluded(value)),
+            Comparison::LessOrEqual => (Bound::Unbounded, Bound::Included(value)),
+            Comparison::Equal => (Bound::Included(value), Bound::Included(value)),
+            Comparison::GreaterOrEqual => (Bound::Included(value), Bound::Unbounded),
+            Comparison::Greater => (Bound::Excluded(value), Bound::Unbounded),
+        }
+    }
+}
+
+/// Splits an expression at spaces, keeping "quoted values" together (quotes removed)
+fn split_terms(text: &str) -> Result<Vec<String>, String> {
+    let mut terms = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    for c in text.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    terms.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+
+    if in_quotes {
+        return Err("missing closing quote".to_string());
+    }
+    if !current.is_empty() {
+        terms.push(current);
+    }
+    Ok(terms)
+}
+
+/// Parses a single term like `-tag:fantasy` or `size:<1mb`
+fn parse_term(word: &str) -> Result<Term, String> {
+    // A leading '-' negates the term (a lone "-" is just text)
+    let (negated, word) = match word.strip_prefix('-') {
+        Some(rest) if !rest.is_empty() => (true, rest),
+        _ => (false, word),
+    };
+
+    let test = match word.split_once(':') {
+        None if word.eq_ignore_ascii_case("starred") => Test::Starred,
+        None if word.eq_ignore_ascii_case("archived") => Test::Archived,
+        None if word.eq_ignore_ascii_case("accessible") => Test::Accessible,
+        None => Test::Text(fold(word)),
+        Some((field, value)) => {
+            let text = fold(value);
+            let value = value.to_lowercase();
+            match field.to_lowercase().as_str() {
+                "title" => Test::Title(text),
+                "author" => Test::Author(text),
+                "tag" => Test::Tag(text),
+                "series" => Test::Series(text),
+                "lang" | "language" => Test::Language(value),
+                "collection" => Test::Collection(text),
+                "ext" => Test::Extension(value.trim_start_matches('.').to_string()),
+                "is" if value == "starred" => Test::Starred,
+                "is" if value == "archived" => Test::Archived,
+                "is" if value == "fixed" => Test::FixedLayout,
+                "is" if value == "read-aloud" || value == "readaloud" => Test::MediaOverlays,
+                "is" if value == "accessible" => Test::Accessible,
+                "feature" => Test::Feature(compact(&value).replace("alttext", "alternativetext")),
+                "hazard" if value == "none" => Test::Hazard(None),
+                "hazard" => Test::Hazard(Some(compact(&value))),
+                "status" => Test::Status(parse_status(&value)?),
+                "rating" => {
+                    let (comparison, number) = parse_comparison(&value);
+                    let stars = number
+                        .parse()
+                        .map_err(|_| format!("invalid rating: '{}'", number))?;
+                    Test::Rating(comparison, stars)
+                }
+                "size" => {
+                    let (comparison, number) = parse_comparison(&value);
+                    Test::Size(comparison, parse_size(number)?)
+                }
+                "grade" => {
+                    let (comparison, number) = parse_comparison(&value);
+                    let grade = number
+                        .parse()
+                        .map_err(|_| format!("invalid grade: '{}'", number))?;
+                    Test::Grade(comparison, grade)
+                }
+                "added" => {
+                    let (comparison, number) = parse_comparison(&value);
+                    Test::AddedAgo(comparison, parse_age(number)?)
+                }
+                "opened" if value == "any" => Test::OpenedAgo(None),
+                "opened" => {
+                    let (comparison, number) = parse_comparison(&value);
+                    Test::OpenedAgo(Some((comparison, parse_age(number)?)))
+                }
+                "modified" => {
+                    let (comparison, number) = parse_comparison(&value);
+                    Test::ModifiedAgo(comparison, parse_age(number)?)
+                }
+                _ => return Err(format!("unknown filter: '{}:'", field)),
+            }
+        }
+    };
+
+    Ok(Term { negated, test })
+}
+
+/// A name without case and punctuation, e.g. "alternativeText" -> "alternativetext"
+fn compact(name: &str) -> String {
+    name.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+/// Parses a status name into the statuses it stands for
+fn parse_status(value: &str) -> Result<Vec<Option<ReadingStatus>>, String> {
+    Ok(match value {
+        "to-read" | "toread" => vec![Some(ReadingStatus::ToRead)],
+        "reading" => vec![Some(ReadingStatus::Reading)],
+        "finished" | "read" => vec![Some(ReadingStatus::Finished)],
+        "abandoned" => vec![Some(ReadingStatus::Abandoned)],
+        "none" => vec![None],
+        "unread" => vec![None, Some(ReadingStatus::ToRead)],
+        _ => return Err(format!("unknown status: '{}'", value)),
+    })
+}
+
+/// Splits a leading comparison operator from a value ("<=4" -> (LessOrEqual, "4"))
+/// A value without operator compares for equality
+fn parse_comparison(value: &str) -> (Comparison, &str) {
+    // Two-character operators must be tried first
+    let operators = [
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+        ("=", Comparison::Equal),
+    ];
+    for (operator, comparison) in operators {
+        if let Some(rest) = value.strip_prefix(operator) {
+            return (comparison, rest);
+        }
+    }
+    (Comparison::Equal, value)
+}
+
+/// Parses a size like "1mb", "500kb" or "2048" (bytes) into bytes
+fn parse_size(value: &str) -> Result<u64, String> {
+    let units = [("gb", 1 << 30), ("mb", 1 << 20), ("kb", 1 << 10), ("b", 1)];
+    let (number, multiplier) = units
+        .iter()
+        .find_map(|(unit, multiplier)| value.strip_suffix(unit).map(|n| (n, *multiplier)))
+        .unwrap_or((value, 1));
+
+    // Allow fractions like "1.5mb"
+    number
+        .trim()
+        .parse::<f64>()
+        .map(|n| (n * multiplier as f64) as u64)
+        .map_err(|_| format!("invalid size: '{}'", value))
+}
+
+/// Parses an age like "7d" or "2w" into seconds
+fn parse_age(value: &str) -> Result<u64, String> {
+    let (number, unit_secs) = if let Some(days) = value.strip_suffix('d') {
+        (days, 86_400)
+    } else if let Some(weeks) = value.strip_suffix('w') {
+        (weeks, 7 * 86_400)
+    } else {
+        return Err(format!("invalid age: '{}' (use e.g. 7d or 2w)", value));
+    };
+
+    // The filter is parsed as it's typed: a huge age is an error, not an overflow
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit_secs))
+        .ok_or_else(|| format!("invalid age: '{}'", value))
+}
+
+/// Case- and accent-insensitive "contains" (the needle is already folded)
+fn contains(haystack: &str, needle: &str) -> bool {
+    crate::fold::contains(haystack, needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("2048"), Ok(2048));
+        assert_eq!(parse_size("500kb"), Ok(500 * 1024));
+        assert_eq!(parse_size("1.5kb"), Ok(1536));
+        assert_eq!(parse_size("1mb"), Ok(1 << 20));
+        assert_eq!(parse_size("2gb"), Ok(2 << 30));
+        assert!(parse_size("big").is_err());
+        assert!(parse_size("1tb").is_err());
+    }
+
+    #[test]
+    fn ages() {
+        assert_eq!(parse_age("7d"), Ok(7 * 86_400));
+        assert_eq!(parse_age("2w"), Ok(14 * 86_400));
+        assert_eq!(parse_age("0d"), Ok(0));
+        assert!(parse_age("3m").is_err());
+        assert!(parse_age("-1d").is_err());
+        assert!(parse_age("d").is_err());
+    }
+
+    #[test]
+    fn ages_too_big_are_errors() {
+        assert!(parse_age("99999999999999999d").is_err());
+        assert!(parse_age("99999999999999999999999d").is_err());
+        assert!(Filter::parse("added:<99999999999999999d").is_err());
+    }
+
+    #[test]
+    fn comparisons() {
+        assert!(matches!(parse_comparison("<=4"), (Comparison::LessOrEqual, "4")));
+        assert!(matches!(parse_comparison(">1mb"), (Comparison::Greater, "1mb")));
+        assert!(matches!(parse_comparison("=3"), (Comparison::Equal, "3")));
+        assert!(matches!(parse_comparison("3"), (Comparison::Equal, "3")));
+    }
+}
@@ -0,0 +1,140 @@
+// src/fold.rs
+// Text folded for searching - the filter of the book list (and the web
+// page's, the API's and smart collections', which use it too) and the
+// reader's jump to the words of a full-text search compare folded text,
+// so "garcia marquez" finds "García Márquez" and "ETRE" finds "être"
+//
+// Folding lowercases, takes the accents and other marks off letters
+// (precomposed or combining) and spells ligatures out ("æ" -> "ae", "ß" ->
+// "ss"). With [search] transliterate, Cyrillic and Greek letters are also
+// written in Latin ones, so "tolstoy" finds "Толстой".
+//
+// The full-text index (see fulltext.rs) ignores case and accents by itself
+// (SQLite's unicode61 tokenizer) but doesn't transliterate.
+//
+// The orders of the book list compare folded text first too (see sort.rs).
+
+use crate::settings::SearchSettings;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether Cyrillic and Greek are written in Latin letters ([search] transliterate)
+static TRANSLITERATE: AtomicBool = AtomicBool::new(false);
+
+/// Letters with marks, and what they fold to (lowercase only: text is
+/// lowercased first)
+const MARKED: &[(&str, &str)] = &[
+    ("àáâãäåāăąǎȁȃȧạảấầẩẫậắằẳẵặ", "a"),
+    ("çćĉċč", "c"),
+    ("ďđð", "d"),
+    ("èéêëēĕėęěȅȇẹẻẽếềểễệ", "e"),
+    ("ĝğġģǧ", "g"),
+    ("ĥħ", "h"),
+    ("ìíîïĩīĭįıǐȉȋịỉ", "i"),
+    ("ĵ", "j"),
+    ("ķǩ", "k"),
+    ("ĺļľŀł", "l"),
+    ("ñńņňŉ", "n"),
+    ("òóôõöøōŏőǒȍȏọỏốồổỗộớờởỡợơ", "o"),
+    ("ŕŗř", "r"),
+    ("śŝşšș", "s"),
+    ("ţťŧț", "t"),
+    ("ùúûüũūŭůűųǔưụủứừửữự", "u"),
+    ("ŵ", "w"),
+    ("ýÿŷỳỹỵ", "y"),
+    ("źżž", "z"),
+    ("ß", "ss"),
+    ("æ", "ae"),
+    ("œ", "oe"),
+    ("þ", "th"),
+    ("ĳ", "ij"),
+    ("ά", "α"),
+    ("έ", "ε"),
+    ("ή", "η"),
+    ("ίϊΐ", "ι"),
+    ("ό", "ο"),
+    ("ύϋΰ", "υ"),
+    ("ώ", "ω"),
+    ("ς", "σ"),
+    ("ё", "е"),
+];
+
+/// Cyrillic and Greek letters (lowercase, without marks) in Latin letters
+const LATIN: &[(char, &str)] = &[
+    ('а', "a"), ('б', "b"), ('в', "v"), ('г', "g"), ('д', "d"), ('е', "e"), ('ж', "zh"), ('з', "z"),
+    ('и', "i"), ('й', "y"), ('к', "k"), ('л', "l"), ('м', "m"), ('н', "n"), ('о', "o"), ('п', "p"),
+    ('р', "r"), ('с', "s"), ('т', "t"), ('у', "u"), ('ф', "f"), ('х', "kh"), ('ц', "ts"), ('ч', "ch"),
+    ('ш', "sh"), ('щ', "shch"), ('ъ', ""), ('ы', "y"), ('ь', ""), ('э', "e"), ('ю', "yu"), ('я', "ya"),
+    ('і', "i"), ('ї', "yi"), ('є', "ye"), ('ґ', "g"), ('ў', "u"), ('ј', "j"), ('љ', "lj"), ('њ', "nj"),
+    ('ћ', "c"), ('ђ', "dj"), ('џ', "dz"),
+    ('α', "a"), ('β', "v"), ('γ', "g"), ('δ', "d"), ('ε', "e"), ('ζ', "z"), ('η', "i"), ('θ', "th"),
+    ('ι', "i"), ('κ', "k"), ('λ', "l"), ('μ', "m"), ('ν', "n"), ('ξ', "x"), ('ο', "o"), ('π', "p"),
+    ('ρ', "r"), ('σ', "s"), ('τ', "t"), ('υ', "y"), ('φ', "f"), ('χ', "ch"), ('ψ', "ps"), ('ω', "o"),
+];
+
+/// Uses new search settings (called when config.toml is loaded)
+pub fn configure(settings: &SearchSettings) {
+    TRANSLITERATE.store(settings.transliterate, Ordering::Relaxed);
+}
+
+/// Folds a text for comparing: lowercase, without marks, ligatures spelled
+/// out (and in Latin letters with [search] transliterate)
+pub fn fold(text: &str) -> String {
+    fold_letters(text, TRANSLITERATE.load(Ordering::Relaxed))
+}
+
+/// Folds a text without ever transliterating it - for the orders of the
+/// book list (see sort.rs), which keep each script together
+pub fn unmarked(text: &str) -> String {
+    fold_letters(text, false)
+}
+
+/// Folds a text, in Latin letters if asked
+fn fold_letters(text: &str, transliterate: bool) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars().flat_map(char::to_lowercase) {
+        // Combining marks, e.g. the accent of a decomposed "é"
+        if ('\u{300}'..='\u{36f}').contains(&c) {
+            continue;
+        }
+        match MARKED.iter().find(|(marked, _)| marked.contains(c)) {
+            Some((_, plain)) => push_letters(&mut folded, plain, transliterate),
+            None => push_letters(&mut folded, c.encode_utf8(&mut [0; 4]), transliterate),
+        }
+    }
+    folded
+}
+
+/// Whether a text has a (folded) text in it, whatever their case and marks
+pub fn contains(haystack: &str, folded_needle: &str) -> bool {
+    fold(haystack).contains(folded_needle)
+}
+
+/// Appends letters without marks, in Latin letters if asked
+fn push_letters(folded: &mut String, letters: &str, transliterate: bool) {
+    for c in letters.chars() {
+        match LATIN.iter().find(|(letter, _)| *letter == c).filter(|_| transliterate) {
+            Some((_, latin)) => folded.push_str(latin),
+            None => folded.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marks_and_ligatures_go() {
+        assert_eq!(unmarked("García Márquez"), "garcia marquez");
+        assert_eq!(unmarked("ÊTRE"), "etre");
+        assert_eq!(unmarked("Straße Œuvre"), "strasse oeuvre");
+        // Decomposed: e and a combining acute accent
+        assert_eq!(unmarked("e\u{301}cole"), "ecole");
+    }
+
+    #[test]
+    fn unmarked_never_transliterates() {
+        assert_eq!(unmarked("Толстой"), "толстой");
+        assert_eq!(fold_letters("Толстой", true), "tolstoy");
+    }
+}
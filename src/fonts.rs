@@ -0,0 +1,622 @@
+// src/fonts.rs
+// Fonts embedded in EPUBs - listed with their sizes (`funkhunt fonts BOOK`,
+// or t in the app), and taken out of books they make too big:
+// - Stripping removes the font files, their manifest items, the @font-face
+//   rules naming them and their obfuscation entries (encryption.xml); the
+//   reading app's own fonts are used instead.
+// - Subsetting keeps the fonts but empties the outlines of the characters
+//   the book never uses (e.g. the Cyrillic and Greek of a font in an English
+//   book). Glyph numbers don't change, so kerning and ligatures keep working:
+//   glyphs no character maps to (ligatures, alternates) are kept, and so are
+//   ASCII, Latin-1, general punctuation, presentation forms (ligatures,
+//   Arabic shaping) and both cases of every letter used (CSS can change the
+//   case). Only TrueType fonts can be subset; CFF (most .otf), WOFF and
+//   obfuscated fonts are left as they are.
+//
+// The book is copied into the backups folder of the data directory first
+// (e.g. "Dune-1718000000.epub"), then repacked next to itself (see
+// epub::temp_path) like optimize.rs does.
+
+use crate::epub;
+use quick_xml::events::Event;
+use quick_xml::{Reader, Writer};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Folder of the data directory the books are copied to before their fonts go
+const BACKUP_DIR: &str = "backups";
+
+/// Extensions of font files
+const FONT_EXTENSIONS: [&str; 4] = ["ttf", "otf", "woff", "woff2"];
+
+/// Extensions of the files the characters a book uses are looked for in
+const TEXT_EXTENSIONS: [&str; 7] = ["xhtml", "html", "htm", "ncx", "svg", "opf", "css"];
+
+/// Characters a subset font always keeps (ASCII, Latin-1, general
+/// punctuation, alphabetic and Arabic presentation forms)
+const KEPT_RANGES: [(u32, u32); 5] = [(0x20, 0x7E), (0xA0, 0xFF), (0x2000, 0x206F), (0xFB00, 0xFDFF), (0xFE70, 0xFEFF)];
+
+/// A font embedded in a book
+#[derive(Debug, Clone)]
+pub struct Font {
+    /// Path inside the archive
+    pub path: String,
+
+    /// Family of its @font-face rule, with its weight and style, e.g.
+    /// "Garamond bold italic" (None if no rule names it)
+    pub family: Option<String>,
+
+    /// Size in bytes, uncompressed
+    pub size: u64,
+}
+
+impl Font {
+    /// Kind of font, from its extension
+    pub fn kind(&self) -> &'static str {
+        match self.path.rsplit('.').next().unwrap_or_default().to_lowercase().as_str() {
+            "ttf" => "TrueType",
+            "otf" => "OpenType",
+            "woff" => "WOFF",
+            "woff2" => "WOFF2",
+            _ => "Font",
+        }
+    }
+}
+
+/// How fonts are taken out of a book
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Remove the fonts
+    Strip,
+
+    /// Keep only the characters the book uses
+    Subset,
+}
+
+/// What was done to a book's fonts
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// Size of the book before, in bytes
+    pub before: u64,
+
+    /// Size of the book after, in bytes
+    pub after: u64,
+
+    /// Fonts removed or subset
+    pub fonts: usize,
+
+    /// Copy of the book as it was
+    pub backup: PathBuf,
+}
+
+impl Report {
+    /// e.g. "3 fonts removed, 2.1 MB -> 400.0 KB (saved 81%)"
+    pub fn summary(&self, action: Action) -> String {
+        format!(
+            "{} fonts {}, {} -> {} (saved {}%)",
+            self.fonts,
+            if action == Action::Strip { "removed" } else { "subset" },
+            crate::transfer::human_bytes(self.before as f64),
+            crate::transfer::human_bytes(self.after as f64),
+            self.before.saturating_sub(self.after) * 100 / self.before.max(1)
+        )
+    }
+}
+
+/// Lists the fonts embedded in an EPUB
+///
+/// # Returns
+/// The fonts, by path inside the archive (empty if it has none)
+pub fn list(path: &Path) -> io::Result<Vec<Font>> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(io::Error::other)?;
+    let (opf_path, opf) = epub::read_package(&mut archive)?;
+    let families = families(&mut archive)?;
+    let fonts = font_entries(&mut archive, &opf_path, &opf)?
+        .into_iter()
+        .map(|name| Font {
+            size: archive.by_name(&name).map(|entry| entry.size()).unwrap_or(0),
+            family: families.get(&name).cloned(),
+            path: name,
+        })
+        .collect();
+    Ok(fonts)
+}
+
+/// Strips or subsets the fonts of an EPUB, after a backup copy
+///
+/// # Arguments
+/// * `path` - The EPUB file (replaced by the slimmer one)
+/// * `action` - Whether the fonts are removed or subset
+pub fn slim(path: &Path, action: Action) -> io::Result<Report> {
+    let before = std::fs::metadata(path)?.len();
+    let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(io::Error::other)?;
+    let (opf_path, opf) = epub::read_package(&mut archive)?;
+    let fonts = font_entries(&mut archive, &opf_path, &opf)?;
+    if fonts.is_empty() {
+        return Err(io::Error::other("the book has no embedded fonts"));
+    }
+    let (removed, used): (HashSet<String>, HashSet<char>) = match action {
+        Action::Strip => (fonts.iter().cloned().collect(), HashSet::new()),
+        Action::Subset => (HashSet::new(), used_chars(&mut archive)?),
+    };
+    let opf = crate::optimize::without_items(&opf, &opf_path, &removed)?;
+
+    let backup = backup(path)?;
+    let tmp_path = epub::temp_path(path);
+    let mut changed = 0;
+    let mut write = || -> io::Result<()> {
+        let mut writer = zip::ZipWriter::new(File::create(&tmp_path)?);
+        for i in 0..archive.len() {
+            let entry = archive.by_index_raw(i).map_err(io::Error::other)?;
+            let name = entry.name().to_string();
+            if entry.is_dir() || removed.contains(&name) {
+                continue;
+            }
+            let rewritten = name == opf_path
+                || (action == Action::Strip && (name.to_lowercase().ends_with(".css") || name == "META-INF/encryption.xml"))
+                || (action == Action::Subset && fonts.contains(&name));
+            if !rewritten {
+                writer.raw_copy_file(entry).map_err(io::Error::other)?;
+                continue;
+            }
+            let options = zip::write::FileOptions::default()
+                .compression_method(zip::CompressionMethod::Deflated)
+                .last_modified_time(entry.last_modified());
+            drop(entry);
+
+            let mut content = Vec::new();
+            archive.by_index(i).map_err(io::Error::other)?.read_to_end(&mut content)?;
+            let content = if name == opf_path {
+                opf.as_bytes().to_vec()
+            } else if name == "META-INF/encryption.xml" {
+                match without_encryption_of(&String::from_utf8_lossy(&content), &removed)? {
+                    Some(encryption) => encryption.into_bytes(),
+                    // Only fonts were obfuscated
+                    None => continue,
+                }
+            } else if action == Action::Strip {
+                without_font_faces(&String::from_utf8_lossy(&content), &name, &removed).into_bytes()
+            } else {
+                match subset(&content, &used) {
+                    Some(smaller) => {
+                        changed += 1;
+                        smaller
+                    }
+                    None => content,
+                }
+            };
+            writer.start_file(name.as_str(), options).map_err(io::Error::other)?;
+            writer.write_all(&content)?;
+        }
+        writer.finish().map_err(io::Error::other)?;
+        Ok(())
+    };
+    let written = write().and_then(|()| match action {
+        Action::Subset if changed == 0 => Err(io::Error::other("no font could be subset (only TrueType fonts can)")),
+        _ => Ok(()),
+    });
+    if let Err(e) = written.and_then(|()| epub::put_in_place(&tmp_path, path)) {
+        let _ = std::fs::remove_file(&tmp_path);
+        let _ = std::fs::remove_file(&backup);
+        return Err(e);
+    }
+
+    let report = Report {
+        before,
+        after: std::fs::metadata(path)?.len(),
+        fonts: if action == Action::Strip { fonts.len() } else { changed },
+        backup,
+    };
+    tracing::info!(
+        path = %path.display(),
+        ?action,
+        fonts = report.fonts,
+        before,
+        after = report.after,
+        backup = %report.backup.display(),
+        "book fonts slimmed"
+    );
+    Ok(report)
+}
+
+/// The font files of an EPUB (by extension, or media type in the manifest)
+fn font_entries(archive: &mut zip::ZipArchive<File>, opf_path: &str, opf: &str) -> io::Result<Vec<String>> {
+    let (items, _) = epub::read_manifest(opf)?;
+    let listed: HashSet<String> = items
+        .iter()
+        .filter(|item| item.media_type.contains("font") || item.media_type.contains("opentype"))
+        .map(|item| epub::entry_path(opf_path, &item.href))
+        .collect();
+    let mut fonts: Vec<String> = archive
+        .file_names()
+        .filter(|name| {
+            let extension = name.rsplit('.').next().unwrap_or_default().to_lowercase();
+            FONT_EXTENSIONS.contains(&extension.as_str()) || listed.contains(*name)
+        })
+        .map(str::to_string)
+        .collect();
+    fonts.sort();
+    Ok(fonts)
+}
+
+/// Copies a book into the backups folder
+fn backup(path: &Path) -> io::Result<PathBuf> {
+    let folder = crate::paths::data_dir().join(BACKUP_DIR);
+    std::fs::create_dir_all(&folder)?;
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    let backup = folder.join(format!("{}-{}.{}", stem, crate::book::unix_now(), extension));
+    std::fs::copy(path, &backup)?;
+    Ok(backup)
+}
+
+/// Descriptors of a @font-face rule: lowercase names, values without quotes
+type Descriptors = Vec<(String, String)>;
+
+/// The @font-face rules of a stylesheet: where each is, and its descriptors
+fn font_faces(css: &str) -> Vec<(std::ops::Range<usize>, Descriptors)> {
+    // Lowercasing ASCII keeps the positions
+    let lower = css.to_ascii_lowercase();
+    let mut rules = Vec::new();
+    let mut at = 0;
+    while let Some(start) = lower[at..].find("@font-face").map(|i| at + i) {
+        let Some(end) = lower[start..].find('}').map(|i| start + i + 1) else {
+            break;
+        };
+        let body = css[start..end].split_once('{').map(|(_, body)| body).unwrap_or_default();
+        let descriptors = body
+            .trim_end_matches('}')
+            .split(';')
+            .filter_map(|descriptor| descriptor.split_once(':'))
+            .map(|(name, value)| (name.trim().to_lowercase(), value.trim().trim_matches(['"', '\'']).to_string()))
+            .collect();
+        rules.push((start..end, descriptors));
+        at = end;
+    }
+    rules
+}
+
+/// The files a @font-face rule's `src` links to, as paths inside the archive
+fn sources(descriptors: &[(String, String)], css_path: &str) -> Vec<String> {
+    descriptors
+        .iter()
+        .filter(|(name, _)| name == "src")
+        .flat_map(|(_, value)| value.split("url(").skip(1))
+        .filter_map(|url| url.split(')').next())
+        .map(|url| epub::entry_path(css_path, url.trim().trim_matches(['"', '\''])))
+        .collect()
+}
+
+/// The family each font file is the @font-face of, e.g. "Garamond bold italic"
+fn families(archive: &mut zip::ZipArchive<File>) -> io::Result<HashMap<String, String>> {
+    let mut families = HashMap::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(io::Error::other)?;
+        if !entry.name().to_lowercase().ends_with(".css") {
+            continue;
+        }
+        let css_path = entry.name().to_string();
+        let mut css = Vec::new();
+        entry.read_to_end(&mut css)?;
+        for (_, descriptors) in font_faces(&String::from_utf8_lossy(&css)) {
+            let value = |name: &str| descriptors.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str());
+            let Some(family) = value("font-family") else {
+                continue;
+            };
+            let mut family = family.to_string();
+            match value("font-weight") {
+                Some("bold" | "700") => family.push_str(" bold"),
+                Some(weight) if weight != "normal" && weight != "400" => family.push_str(&format!(" {}", weight)),
+                _ => {}
+            }
+            if let Some(style @ ("italic" | "oblique")) = value("font-style") {
+                family.push_str(&format!(" {}", style));
+            }
+            for source in sources(&descriptors, &css_path) {
+                families.insert(source, family.clone());
+            }
+        }
+    }
+    Ok(families)
+}
+
+/// A stylesheet without the @font-face rules of removed fonts
+fn without_font_faces(css: &str, css_path: &str, removed: &HashSet<String>) -> String {
+    let mut kept = String::with_capacity(css.len());
+    let mut at = 0;
+    for (range, descriptors) in font_faces(css) {
+        if sources(&descriptors, css_path).iter().any(|source| removed.contains(source)) {
+            kept.push_str(&css[at..range.start]);
+            at = range.end;
+        }
+    }
+    kept.push_str(&css[at..]);
+    kept
+}
+
+/// encryption.xml without the entries of removed files (None if none is left)
+fn without_encryption_of(xml: &str, removed: &HashSet<String>) -> io::Result<Option<String>> {
+    let mut reader = Reader::from_str(xml);
+    let mut writer = Writer::new(Vec::new());
+    // Events of the EncryptedData element being read, and whether it's of a removed file
+    let mut held: Option<(Vec<Event>, bool)> = None;
+    let mut kept = 0;
+    loop {
+        let event = reader.read_event().map_err(io::Error::other)?;
+        if let Event::Eof = event {
+            break;
+        }
+        let Some((events, of_removed)) = &mut held else {
+            match event {
+                Event::Start(ref e) if e.local_name().as_ref() == b"EncryptedData" => held = Some((vec![event], false)),
+                event => writer.write_event(event).map_err(io::Error::other)?,
+            }
+            continue;
+        };
+        if let Event::Start(ref e) | Event::Empty(ref e) = event {
+            if e.local_name().as_ref() == b"CipherReference" {
+                *of_removed = epub::attribute(e, b"URI").is_some_and(|uri| removed.contains(&epub::entry_path("", &uri)));
+            }
+        }
+        let end = matches!(event, Event::End(ref e) if e.local_name().as_ref() == b"EncryptedData");
+        events.push(event);
+        if end {
+            if let Some((events, false)) = held.take() {
+                kept += 1;
+                for event in events {
+                    writer.write_event(event).map_err(io::Error::other)?;
+                }
+            }
+        }
+    }
+    if kept == 0 {
+        return Ok(None);
+    }
+    String::from_utf8(writer.into_inner()).map(Some).map_err(io::Error::other)
+}
+
+/// The characters the text of a book uses (numeric character references
+/// included), in both cases
+fn used_chars(archive: &mut zip::ZipArchive<File>) -> io::Result<HashSet<char>> {
+    let mut used = HashSet::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(io::Error::other)?;
+        let extension = entry.name().rsplit('.').next().unwrap_or_default().to_lowercase();
+        if !TEXT_EXTENSIONS.contains(&extension.as_str()) {
+            continue;
+        }
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        let text = String::from_utf8_lossy(&content);
+        used.extend(text.chars());
+        // "&#233;" and "&#xE9;"
+        for reference in text.split("&#").skip(1) {
+            let number = reference.split(';').next().unwrap_or_default();
+            let code = match number.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => number.parse().ok(),
+            };
+            used.extend(code.and_then(char::from_u32));
+        }
+    }
+    let cased: Vec<char> = used.iter().flat_map(|c| c.to_uppercase().chain(c.to_lowercase())).collect();
+    used.extend(cased);
+    Ok(used)
+}
+
+/// A TrueType font with the outlines of the characters not used emptied
+/// (None if it isn't a TrueType font it understands, or nothing is left out)
+fn subset(font: &[u8], used: &HashSet<char>) -> Option<Vec<u8>> {
+    let tables = tables(font)?;
+    let table = |tag: &[u8; 4]| tables.iter().find(|(t, _)| t == tag).map(|(_, data)| *data);
+    let (head, maxp, loca, glyf) = (table(b"head")?, table(b"maxp")?, table(b"loca")?, table(b"glyf")?);
+    let long = u16_at(head, 50)? == 1;
+    let count = u16_at(maxp, 4)? as usize;
+    let offsets: Vec<usize> = (0..=count)
+        .map(|i| match long {
+            true => u32_at(loca, i * 4).map(|offset| offset as usize),
+            false => u16_at(loca, i * 2).map(|offset| offset as usize * 2),
+        })
+        .collect::<Option<_>>()?;
+    let glyph = |i: usize| glyf.get(offsets[i]..offsets[i + 1]);
+
+    // Glyphs of the characters left out, unless a kept character maps to them too
+    let (mut emptied, mut kept) = (HashSet::new(), HashSet::new());
+    for (code, mapped) in character_map(table(b"cmap")?)? {
+        let used = char::from_u32(code).is_some_and(|c| used.contains(&c))
+            || KEPT_RANGES.iter().any(|(first, last)| (first..=last).contains(&&code));
+        if used {
+            kept.insert(mapped);
+        } else {
+            emptied.insert(mapped);
+        }
+    }
+    emptied.retain(|glyph| *glyph != 0 && (*glyph as usize) < count && !kept.contains(glyph));
+
+    // The parts of the composite glyphs kept are kept
+    let mut pending: Vec<u16> = (0..count as u16).filter(|glyph| !emptied.contains(glyph)).collect();
+    while let Some(whole) = pending.pop() {
+        for part in components(glyph(whole as usize)?) {
+            if emptied.remove(&part) {
+                pending.push(part);
+            }
+        }
+    }
+    if emptied.is_empty() {
+        return None;
+    }
+
+    // New outlines (short offsets count 2-byte words)
+    let align = if long { 4 } else { 2 };
+    let (mut new_glyf, mut new_loca) = (Vec::new(), Vec::new());
+    for i in 0..=count {
+        match long {
+            true => new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes()),
+            false => new_loca.extend_from_slice(&u16::try_from(new_glyf.len() / 2).ok()?.to_be_bytes()),
+        }
+        if i < count && !emptied.contains(&(i as u16)) {
+            new_glyf.extend_from_slice(glyph(i)?);
+            new_glyf.resize(new_glyf.len().next_multiple_of(align), 0);
+        }
+    }
+
+    // A signature doesn't hold for the changed font
+    let tables = tables
+        .iter()
+        .filter(|(tag, _)| tag != b"DSIG")
+        .map(|(tag, data)| match tag {
+            b"glyf" => (*tag, new_glyf.clone()),
+            b"loca" => (*tag, new_loca.clone()),
+            _ => (*tag, data.to_vec()),
+        })
+        .collect();
+    let out = write_font(&font[..4], tables);
+    (out.len() < font.len()).then_some(out)
+}
+
+/// The tables of a TrueType font (None for other fonts, e.g. CFF or WOFF)
+fn tables(font: &[u8]) -> Option<Vec<([u8; 4], &[u8])>> {
+    let version = font.get(..4)?;
+    if version != [0, 1, 0, 0] && version != b"true" {
+        return None;
+    }
+    (0..u16_at(font, 4)? as usize)
+        .map(|i| {
+            let record = 12 + i * 16;
+            let tag: [u8; 4] = font.get(record..record + 4)?.try_into().ok()?;
+            let (offset, length) = (u32_at(font, record + 8)? as usize, u32_at(font, record + 12)? as usize);
+            Some((tag, font.get(offset..offset.checked_add(length)?)?))
+        })
+        .collect()
+}
+
+/// The characters of a font's Unicode character map, with their glyphs
+/// (None for fonts without one, e.g. symbol fonts)
+fn character_map(cmap: &[u8]) -> Option<Vec<(u32, u16)>> {
+    // The full Unicode map (format 12) if there is one, else the BMP's (format 4)
+    let mut best: Option<(u16, &[u8])> = None;
+    for i in 0..u16_at(cmap, 2)? as usize {
+        let (platform, encoding) = (u16_at(cmap, 4 + i * 8)?, u16_at(cmap, 6 + i * 8)?);
+        let subtable = cmap.get(u32_at(cmap, 8 + i * 8)? as usize..)?;
+        let format = u16_at(subtable, 0)?;
+        let unicode = platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10));
+        if unicode && (format == 4 || format == 12) && best.is_none_or(|(best, _)| format > best) {
+            best = Some((format, subtable));
+        }
+    }
+
+    let (format, subtable) = best?;
+    let mut map = Vec::new();
+    if format == 12 {
+        for group in 0..u32_at(subtable, 12)? as usize {
+            let at = 16 + group * 12;
+            let (first, last, glyph) = (u32_at(subtable, at)?, u32_at(subtable, at + 4)?, u32_at(subtable, at + 8)?);
+            for code in first..=last.min(0x10FFFF) {
+                map.push((code, glyph.wrapping_add(code - first) as u16));
+            }
+        }
+        return Some(map);
+    }
+
+    let segments = u16_at(subtable, 6)? as usize / 2;
+    let (ends, starts, deltas, ranges) = (14, 16 + segments * 2, 16 + segments * 4, 16 + segments * 6);
+    for segment in 0..segments {
+        let (first, last) = (u16_at(subtable, starts + segment * 2)?, u16_at(subtable, ends + segment * 2)?);
+        let delta = u16_at(subtable, deltas + segment * 2)?;
+        let range_at = ranges + segment * 2;
+        let range = u16_at(subtable, range_at)? as usize;
+        for code in first..=last {
+            if code == 0xFFFF {
+                break;
+            }
+            let glyph = match range {
+                0 => code.wrapping_add(delta),
+                _ => match u16_at(subtable, range_at + range + (code - first) as usize * 2)? {
+                    0 => 0,
+                    glyph => glyph.wrapping_add(delta),
+                },
+            };
+            if glyph != 0 {
+                map.push((code as u32, glyph));
+            }
+        }
+    }
+    Some(map)
+}
+
+/// The glyphs a composite glyph is made of (none for a simple glyph)
+fn components(glyph: &[u8]) -> Vec<u16> {
+    let mut parts = Vec::new();
+    if glyph.len() < 10 || glyph[0] & 0x80 == 0 {
+        return parts;
+    }
+    let mut at = 10;
+    while let (Some(flags), Some(part)) = (u16_at(glyph, at), u16_at(glyph, at + 2)) {
+        parts.push(part);
+        // Arguments (words or bytes), then the scale: one, x and y, or a 2x2 matrix
+        at += 4 + if flags & 0x0001 != 0 { 4 } else { 2 };
+        at += match flags {
+            f if f & 0x0008 != 0 => 2,
+            f if f & 0x0040 != 0 => 4,
+            f if f & 0x0080 != 0 => 8,
+            _ => 0,
+        };
+        if flags & 0x0020 == 0 {
+            break;
+        }
+    }
+    parts
+}
+
+/// A TrueType font made of tables (in the order given, which is the
+/// original's), with their checksums and the head's checksum adjustment
+fn write_font(version: &[u8], mut tables: Vec<([u8; 4], Vec<u8>)>) -> Vec<u8> {
+    let count = tables.len();
+    let power = 1usize << (usize::BITS - 1 - count.max(1).leading_zeros());
+    let mut font = version.to_vec();
+    for value in [count, power * 16, power.trailing_zeros() as usize, count * 16 - power * 16] {
+        font.extend_from_slice(&(value as u16).to_be_bytes());
+    }
+
+    let mut offset = 12 + count * 16;
+    let mut head_at = None;
+    for (tag, data) in &mut tables {
+        if tag == b"head" && data.len() >= 12 {
+            data[8..12].fill(0);
+            head_at = Some(offset);
+        }
+        font.extend_from_slice(tag);
+        font.extend_from_slice(&checksum(data).to_be_bytes());
+        font.extend_from_slice(&(offset as u32).to_be_bytes());
+        font.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        offset += data.len().next_multiple_of(4);
+    }
+    for (_, data) in &tables {
+        font.extend_from_slice(data);
+        font.resize(font.len().next_multiple_of(4), 0);
+    }
+    if let Some(at) = head_at {
+        let adjustment = 0xB1B0_AFBAu32.wrapping_sub(checksum(&font));
+        font[at + 8..at + 12].copy_from_slice(&adjustment.to_be_bytes());
+    }
+    font
+}
+
+/// Sum of the big-endian 32-bit words of a table (zero-padded)
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+fn u16_at(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
@@ -0,0 +1,314 @@
+// src/fulltext.rs
+// Full-text index of the library - the text of every chapter of every book
+// in an SQLite FTS5 table (fulltext.sqlite in the profile directory), so a
+// search of the books' text answers in milliseconds instead of reading
+// every file
+//
+// The index is kept up to date by a thread of its own: it reads the books
+// whose file changed since they were indexed (size or modification time),
+// forgets the ones that left the library and leaves the others alone, so
+// only the first update of a big library takes long. An update runs when
+// the library is opened, when its number of books changes and at every
+// autosave. Books on remote servers aren't indexed.
+//
+// Searches are words, each matching as a prefix ("tolk" finds "Tolkien"),
+// or "quoted phrases"; a chapter matches when it has all of them. Hits come
+// best first, with an extract of the text around the words.
+
+use crate::book::Book;
+use crate::reader::Document;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Most hits a search returns
+const MAX_HITS: usize = 200;
+
+/// Marks around the words found in the extract of a hit
+pub const MATCH_START: char = '\u{1}';
+pub const MATCH_END: char = '\u{2}';
+
+/// How long a connection waits for the other one's write to end
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A chapter whose text matches a search
+#[derive(Debug, Clone)]
+pub struct Hit {
+    /// File of the book
+    pub path: PathBuf,
+
+    /// Index of the chapter in the book (as reader::Document has them)
+    pub chapter: usize,
+
+    /// First heading of the chapter, if it has one
+    pub chapter_title: Option<String>,
+
+    /// Text around the words found, which are between MATCH_START and MATCH_END
+    pub extract: String,
+}
+
+/// Where an update of the index is
+#[derive(Debug, Default)]
+struct Progress {
+    /// Books looked at
+    done: AtomicUsize,
+
+    /// Books to look at
+    total: AtomicUsize,
+
+    finished: AtomicBool,
+}
+
+/// The full-text index of a library
+pub struct FullText {
+    /// The index file
+    path: PathBuf,
+
+    /// Connection the searches use (None if the index can't be opened)
+    connection: Option<Connection>,
+
+    /// The running update (None = none is running)
+    update: Option<Arc<Progress>>,
+
+    /// Number of books when the last update started
+    book_count: usize,
+
+    /// An update was asked for while one was running
+    pending: bool,
+}
+
+impl FullText {
+    /// Opens (or creates) the index of a library and brings it up to date
+    /// in the background
+    ///
+    /// # Arguments
+    /// * `path` - The index file (Profile::fulltext_path)
+    /// * `books` - The books of the library
+    pub fn open(path: PathBuf, books: &[Book]) -> Self {
+        let connection = match connect(&path) {
+            Ok(connection) => Some(connection),
+            Err(e) => {
+                tracing::error!(path = %path.display(), error = %e, "cannot open full-text index");
+                None
+            }
+        };
+        let mut index = Self {
+            path,
+            connection,
+            update: None,
+            book_count: books.len(),
+            pending: false,
+        };
+        index.update(books);
+        index
+    }
+
+    /// Updates the index if the number of books changed since the last
+    /// update (cheap: only counts otherwise), or if an update was asked for
+    /// while another one ran
+    pub fn check(&mut self, books: &[Book]) {
+        if self.finished() && (self.pending || books.len() != self.book_count) {
+            self.update(books);
+        }
+    }
+
+    /// Brings the index up to date with the library, in the background
+    /// (once the running update ends, if one is running)
+    pub fn update(&mut self, books: &[Book]) {
+        if self.connection.is_none() {
+            return;
+        }
+        if !self.finished() {
+            self.pending = true;
+            return;
+        }
+        self.pending = false;
+        self.book_count = books.len();
+
+        let files: Vec<PathBuf> = books
+            .iter()
+            .filter(|book| !crate::remote::is_remote(&book.path))
+            .map(|book| book.path.clone())
+            .collect();
+        let progress = Arc::new(Progress::default());
+        progress.total.store(files.len(), Ordering::Relaxed);
+        self.update = Some(progress.clone());
+
+        let path = self.path.clone();
+        std::thread::spawn(move || {
+            let started = std::time::Instant::now();
+            match index_books(&path, &files, &progress) {
+                Ok(read) => tracing::info!(books = files.len(), read, took = ?started.elapsed(), "full-text index updated"),
+                Err(e) => tracing::error!(path = %path.display(), error = %e, "cannot update full-text index"),
+            }
+            progress.finished.store(true, Ordering::Relaxed);
+        });
+    }
+
+    /// Whether no update is running
+    fn finished(&self) -> bool {
+        self.update
+            .as_ref()
+            .is_none_or(|progress| progress.finished.load(Ordering::Relaxed))
+    }
+
+    /// Books looked at and to look at by the running update (None if none is running)
+    pub fn indexing(&self) -> Option<(usize, usize)> {
+        let progress = self.update.as_ref().filter(|_| !self.finished())?;
+        Some((progress.done.load(Ordering::Relaxed), progress.total.load(Ordering::Relaxed)))
+    }
+
+    /// Finds the chapters whose text has every word (or phrase) of a query,
+    /// best first
+    ///
+    /// # Returns
+    /// The hits (MAX_HITS at most), or a message saying why the query can't run
+    pub fn search(&self, query: &str) -> Result<Vec<Hit>, String> {
+        let connection = self.connection.as_ref().ok_or("the full-text index can't be opened (see the log)")?;
+        let expression = match_expression(query);
+        if expression.is_empty() {
+            return Err("type words to look for".to_string());
+        }
+
+        let sql = format!(
+            "SELECT path, chapter, title, snippet(chapters, 3, '{}', '{}', '…', 24)
+             FROM chapters WHERE chapters MATCH ?1 ORDER BY rank LIMIT ?2",
+            MATCH_START, MATCH_END
+        );
+        let run = || -> rusqlite::Result<Vec<Hit>> {
+            let mut statement = connection.prepare(&sql)?;
+            let rows = statement.query_map(params![expression, MAX_HITS as i64], |row| {
+                Ok(Hit {
+                    path: PathBuf::from(row.get::<_, String>(0)?),
+                    chapter: row.get::<_, i64>(1)? as usize,
+                    chapter_title: row.get(2)?,
+                    extract: row.get(3)?,
+                })
+            })?;
+            rows.collect()
+        };
+        run().map_err(|e| e.to_string())
+    }
+}
+
+/// Words of a query, for the reader to find in a chapter (folded, see
+/// fold.rs; phrases split into their words)
+pub fn query_words(query: &str) -> Vec<String> {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(crate::fold::fold)
+        .collect()
+}
+
+/// The FTS5 query for what was typed: every word a quoted prefix, every
+/// "phrase" kept whole, so no character of the query is FTS5 syntax
+fn match_expression(query: &str) -> String {
+    let mut terms = Vec::new();
+    for (i, part) in query.split('"').enumerate() {
+        if i % 2 == 1 {
+            // Inside quotes: one phrase
+            if !part.trim().is_empty() {
+                terms.push(format!("\"{}\"", part.trim()));
+            }
+        } else {
+            terms.extend(part.split_whitespace().map(|word| format!("\"{}\"*", word)));
+        }
+    }
+    terms.join(" ")
+}
+
+/// Opens the index file, creating its tables if needed
+fn connect(path: &Path) -> rusqlite::Result<Connection> {
+    let connection = Connection::open(path)?;
+    connection.busy_timeout(BUSY_TIMEOUT)?;
+    // Searches read while the update thread writes
+    connection.pragma_update(None, "journal_mode", "WAL")?;
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS files (
+             path TEXT PRIMARY KEY,
+             size INTEGER NOT NULL,
+             modified INTEGER NOT NULL
+         );
+         CREATE VIRTUAL TABLE IF NOT EXISTS chapters
+             USING fts5(path UNINDEXED, chapter UNINDEXED, title, text);",
+    )?;
+    Ok(connection)
+}
+
+/// Size and modification time of a file (None if it can't be read)
+fn file_stamp(path: &Path) -> Option<(i64, i64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some((metadata.len() as i64, modified as i64))
+}
+
+/// Brings the index up to date with a list of book files (what the update
+/// thread runs)
+///
+/// # Returns
+/// How many books were read, or the error that stopped the update
+fn index_books(path: &Path, files: &[PathBuf], progress: &Progress) -> rusqlite::Result<usize> {
+    let mut connection = connect(path)?;
+    let mut read = 0;
+
+    for file in files {
+        progress.done.fetch_add(1, Ordering::Relaxed);
+        let key = file.to_string_lossy().to_string();
+        let Some((size, modified)) = file_stamp(file) else {
+            continue;
+        };
+        let indexed: Option<(i64, i64)> = connection
+            .query_row("SELECT size, modified FROM files WHERE path = ?1", [&key], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()?;
+        if indexed == Some((size, modified)) {
+            continue;
+        }
+
+        // A book that can't be read is remembered too, so it isn't read again
+        // until its file changes
+        let chapters = match Document::open(file) {
+            Ok(document) => document.chapters,
+            Err(e) => {
+                tracing::debug!(path = %file.display(), error = %e, "book not indexed");
+                Vec::new()
+            }
+        };
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM chapters WHERE path = ?1", [&key])?;
+        for (c, chapter) in chapters.iter().enumerate() {
+            transaction.execute(
+                "INSERT INTO chapters (path, chapter, title, text) VALUES (?1, ?2, ?3, ?4)",
+                params![key, c as i64, chapter.title, chapter.text()],
+            )?;
+        }
+        transaction.execute(
+            "INSERT OR REPLACE INTO files (path, size, modified) VALUES (?1, ?2, ?3)",
+            params![key, size, modified],
+        )?;
+        transaction.commit()?;
+        read += 1;
+    }
+
+    // Books that left the library
+    let current: HashSet<String> = files.iter().map(|file| file.to_string_lossy().to_string()).collect();
+    let indexed: Vec<String> = connection
+        .prepare("SELECT path FROM files")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    let gone: Vec<&String> = indexed.iter().filter(|path| !current.contains(*path)).collect();
+    if !gone.is_empty() {
+        let transaction = connection.transaction()?;
+        for path in gone {
+            transaction.execute("DELETE FROM chapters WHERE path = ?1", [path])?;
+            transaction.execute("DELETE FROM files WHERE path = ?1", [path])?;
+        }
+        transaction.commit()?;
+    }
+    Ok(read)
+}
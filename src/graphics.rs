@@ -0,0 +1,276 @@
+// src/graphics.rs
+// Pictures in the terminal - the images of a book drawn by the terminal
+// itself, with the kitty graphics protocol or iTerm2's inline images
+// (WezTerm, Konsole, mintty... speak one or the other)
+//
+// The protocol is [reader] images in config.toml, "auto" by default: kitty's
+// in kitty and Ghostty, iTerm2's in iTerm2 and WezTerm, none elsewhere (nor
+// in tmux and screen, which don't pass the images on). Without one, an
+// image is its alt text.
+//
+// Images are sent as the EPUB has them: kitty only takes PNGs that way, so
+// other formats stay alt text there; iTerm2 takes PNG, JPEG and GIF. Sixel
+// terminals would need the pixels decoded, which FunkHunt doesn't do.
+//
+// ratatui knows nothing of the pictures: when the ones on screen change,
+// the screen is cleared and drawn anew, then the pictures are drawn over
+// the blank lines the reader left for them.
+
+use base64::Engine;
+use ratatui::layout::Rect;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Size of a character cell assumed to size pictures, in pixels
+const CELL: (u32, u32) = (8, 16);
+
+/// Most rows a picture takes
+const MAX_ROWS: usize = 20;
+
+/// Base64 characters sent per kitty escape sequence (the protocol's limit)
+const KITTY_CHUNK: usize = 4096;
+
+/// Which graphics protocol to use ([reader] images)
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageSetting {
+    /// The one the terminal is known to speak, if any
+    #[default]
+    Auto,
+
+    Kitty,
+
+    /// iTerm2 inline images
+    Iterm,
+
+    /// Alt text only
+    Off,
+}
+
+impl ImageSetting {
+    /// The protocol to draw pictures with (None = alt text only)
+    pub fn protocol(self) -> Option<Protocol> {
+        match self {
+            ImageSetting::Auto => Protocol::detect(),
+            ImageSetting::Kitty => Some(Protocol::Kitty),
+            ImageSetting::Iterm => Some(Protocol::Iterm),
+            ImageSetting::Off => None,
+        }
+    }
+}
+
+/// A way to draw pictures in the terminal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    Kitty,
+    Iterm,
+}
+
+impl Protocol {
+    /// The protocol of the terminal we run in, from its environment
+    /// variables (None if unknown, or behind tmux or screen)
+    pub fn detect() -> Option<Self> {
+        let var = |name: &str| std::env::var(name).unwrap_or_default();
+        if std::env::var_os("TMUX").is_some() || var("TERM").starts_with("screen") || var("TERM").starts_with("tmux") {
+            return None;
+        }
+        if std::env::var_os("KITTY_WINDOW_ID").is_some() || var("TERM") == "xterm-kitty" || var("TERM_PROGRAM") == "ghostty" {
+            Some(Protocol::Kitty)
+        } else if ["iTerm.app", "WezTerm"].contains(&var("TERM_PROGRAM").as_str()) || var("LC_TERMINAL") == "iTerm2" {
+            Some(Protocol::Iterm)
+        } else {
+            None
+        }
+    }
+
+    /// Whether images of a format can be drawn
+    pub fn shows(self, format: ImageFormat) -> bool {
+        match self {
+            Protocol::Kitty => format == ImageFormat::Png,
+            Protocol::Iterm => true,
+        }
+    }
+}
+
+/// Formats of the images of EPUBs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Gif,
+}
+
+/// Format and size (width, height in pixels) of an image, from its first
+/// bytes (None if it isn't a PNG, JPEG or GIF, or is cut before its size)
+pub fn probe(bytes: &[u8]) -> Option<(ImageFormat, (u32, u32))> {
+    let be16 = |at: usize| Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32);
+    let be32 = |at: usize| Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+    let le16 = |at: usize| Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32);
+
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        // The IHDR chunk comes first
+        return Some((ImageFormat::Png, (be32(16)?, be32(20)?)));
+    }
+    if bytes.starts_with(b"GIF8") {
+        return Some((ImageFormat::Gif, (le16(6)?, le16(8)?)));
+    }
+    if bytes.starts_with(&[0xFF, 0xD8]) {
+        // Segments up to a start of frame (SOF0 to SOF15 but DHT, JPG and DAC)
+        let mut at = 2;
+        while *bytes.get(at)? == 0xFF {
+            let marker = *bytes.get(at + 1)?;
+            if (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker) {
+                return Some((ImageFormat::Jpeg, (be16(at + 7)?, be16(at + 5)?)));
+            }
+            at += 2 + be16(at + 2)? as usize;
+        }
+    }
+    None
+}
+
+/// Columns and rows a picture takes: its size in cells, shrunk to fit
+/// `width` columns and MAX_ROWS rows
+pub fn cells((width_px, height_px): (u32, u32), width: usize) -> (usize, usize) {
+    let mut columns = (width_px.div_ceil(CELL.0) as usize).clamp(1, width.max(1));
+    let mut rows = ((height_px as usize * columns * CELL.0 as usize) / (width_px.max(1) as usize * CELL.1 as usize)).max(1);
+    if rows > MAX_ROWS {
+        columns = (columns * MAX_ROWS / rows).max(1);
+        rows = MAX_ROWS;
+    }
+    (columns, rows)
+}
+
+/// A picture drawn on the screen
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    /// Path of the image inside the EPUB
+    pub href: String,
+
+    /// Cells it covers
+    pub area: Rect,
+}
+
+/// The pictures on the screen, and the images of the book they come from
+pub struct Pictures {
+    protocol: Option<Protocol>,
+
+    /// What's drawn now, and the size of the screen it was drawn on
+    shown: Vec<Placement>,
+    screen: Rect,
+
+    /// Images read from `book`, by path inside the EPUB
+    book: PathBuf,
+    images: HashMap<String, Vec<u8>>,
+}
+
+impl Pictures {
+    pub fn new(protocol: Option<Protocol>) -> Self {
+        Self {
+            protocol,
+            shown: Vec::new(),
+            screen: Rect::default(),
+            book: PathBuf::new(),
+            images: HashMap::new(),
+        }
+    }
+
+    /// Changes the protocol (after a config reload)
+    pub fn set_protocol(&mut self, protocol: Option<Protocol>) {
+        self.protocol = protocol;
+    }
+
+    /// Whether the screen must be cleared and drawn anew before showing
+    /// these pictures (it must when other pictures are on it)
+    pub fn changed(&self, placements: &[Placement], screen: Rect) -> bool {
+        self.protocol.is_some() && (self.shown != placements || (!self.shown.is_empty() && self.screen != screen))
+    }
+
+    /// Forgets the pictures on the screen, which the caller clears (kitty
+    /// keeps pictures over a cleared screen, so they're deleted first)
+    pub fn clear(&mut self, out: &mut impl Write) -> io::Result<()> {
+        if self.protocol == Some(Protocol::Kitty) && !self.shown.is_empty() {
+            out.write_all(b"\x1b_Ga=d,d=a,q=2\x1b\\")?;
+            out.flush()?;
+        }
+        self.shown.clear();
+        Ok(())
+    }
+
+    /// Draws pictures of a book over the screen drawn by ratatui (those
+    /// already shown aren't drawn again)
+    ///
+    /// # Arguments
+    /// * `out` - The terminal
+    /// * `book` - The EPUB the images are in
+    /// * `placements` - Where they go
+    /// * `screen` - Size of the screen
+    pub fn show(&mut self, out: &mut impl Write, book: &Path, placements: &[Placement], screen: Rect) -> io::Result<()> {
+        let Some(protocol) = self.protocol else {
+            return Ok(());
+        };
+        if self.shown == placements {
+            return Ok(());
+        }
+        if self.book != book {
+            self.book = book.to_path_buf();
+            self.images.clear();
+        }
+
+        for placement in placements {
+            if !self.images.contains_key(&placement.href) {
+                let bytes = read_image(book, &placement.href).unwrap_or_else(|e| {
+                    tracing::debug!(path = %book.display(), image = %placement.href, error = %e, "cannot read image");
+                    Vec::new()
+                });
+                self.images.insert(placement.href.clone(), bytes);
+            }
+            let bytes = &self.images[&placement.href];
+            if bytes.is_empty() {
+                continue;
+            }
+
+            let area = placement.area;
+            write!(out, "\x1b7\x1b[{};{}H", area.y + 1, area.x + 1)?;
+            let data = base64::engine::general_purpose::STANDARD.encode(bytes);
+            match protocol {
+                Protocol::Kitty => {
+                    let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+                    for (i, chunk) in chunks.iter().enumerate() {
+                        let more = u8::from(i + 1 < chunks.len());
+                        if i == 0 {
+                            write!(out, "\x1b_Ga=T,f=100,q=2,C=1,c={},r={},m={};", area.width, area.height, more)?;
+                        } else {
+                            write!(out, "\x1b_Gm={};", more)?;
+                        }
+                        out.write_all(chunk)?;
+                        out.write_all(b"\x1b\\")?;
+                    }
+                }
+                Protocol::Iterm => write!(
+                    out,
+                    "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1:{}\x07",
+                    bytes.len(),
+                    area.width,
+                    area.height,
+                    data
+                )?,
+            }
+            out.write_all(b"\x1b8")?;
+        }
+        out.flush()?;
+        self.shown = placements.to_vec();
+        self.screen = screen;
+        Ok(())
+    }
+}
+
+/// Reads an image of an EPUB
+fn read_image(book: &Path, href: &str) -> io::Result<Vec<u8>> {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(book)?).map_err(io::Error::other)?;
+    let mut entry = archive.by_name(href).map_err(io::Error::other)?;
+    let mut bytes = Vec::new();
+    entry.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
@@ -0,0 +1,25 @@
+// src/hash.rs
+// Content hashing - identifies a book by its bytes, independent of where the file lives
+
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// Computes the SHA-256 hash of a file's content
+///
+/// # Arguments
+/// * `path` - The file to hash
+///
+/// # Returns
+/// The hash as a lowercase hex string (64 characters)
+#[cfg_attr(feature = "profiling", tracing::instrument(level = "trace", name = "hash", skip_all))]
+pub fn content_hash(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+
+    // Stream the file through the hasher - no need to load it into memory
+    io::copy(&mut file, &mut hasher)?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
@@ -0,0 +1,183 @@
+// src/health.rs
+// Library health check - finds books whose files are missing, empty,
+// unreadable or blocked by permissions
+
+use crate::book::Book;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// What is wrong with a book's file
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// The file isn't where the library says it is
+    Missing,
+    /// The file has no content (often an interrupted copy)
+    Empty,
+    /// We're not allowed to read the file
+    PermissionDenied,
+    /// The file can't be read or isn't a valid EPUB (with the reason)
+    Unreadable(String),
+}
+
+impl Problem {
+    /// Short description for the report, e.g. "missing"
+    pub fn label(&self) -> String {
+        match self {
+            Problem::Missing => "missing".to_string(),
+            Problem::Empty => "empty file".to_string(),
+            Problem::PermissionDenied => "permission denied".to_string(),
+            Problem::Unreadable(reason) => format!("unreadable: {}", reason),
+        }
+    }
+}
+
+/// A book with a problem
+#[derive(Debug, Clone)]
+pub struct Issue {
+    /// Path of the book as stored in the library
+    pub path: PathBuf,
+
+    /// What is wrong with it
+    pub problem: Problem,
+}
+
+/// Checks the file of every book
+///
+/// Only the zip directory of each EPUB is read, so the check stays quick
+/// even for big libraries.
+///
+/// # Returns
+/// One issue per book with a problem, in library order
+pub fn check(books: &[Book]) -> Vec<Issue> {
+    books
+        .iter()
+        // Files on a server are only read when opened
+        .filter(|book| !crate::remote::is_remote(&book.path))
+        .filter_map(|book| {
+            check_file(book).map(|problem| Issue {
+                path: book.path.clone(),
+                problem,
+            })
+        })
+        .collect()
+}
+
+/// Checks one book's file (None = healthy)
+fn check_file(book: &Book) -> Option<Problem> {
+    let metadata = match std::fs::metadata(&book.path) {
+        Ok(metadata) => metadata,
+        Err(e) => return Some(io_problem(e)),
+    };
+    if metadata.len() == 0 {
+        return Some(Problem::Empty);
+    }
+
+    let file = match File::open(&book.path) {
+        Ok(file) => file,
+        Err(e) => return Some(io_problem(e)),
+    };
+    match zip::ZipArchive::new(file) {
+        Ok(_) => None,
+        Err(e) => Some(Problem::Unreadable(format!("not a valid EPUB ({})", e))),
+    }
+}
+
+/// Turns an I/O error into the matching problem
+fn io_problem(error: io::Error) -> Problem {
+    match error.kind() {
+        io::ErrorKind::NotFound => Problem::Missing,
+        io::ErrorKind::PermissionDenied => Problem::PermissionDenied,
+        _ => Problem::Unreadable(error.to_string()),
+    }
+}
+
+/// A book whose file was found again at another path
+#[derive(Debug, Clone)]
+pub struct Relocation {
+    /// Path stored in the library (the file is gone)
+    pub from: PathBuf,
+
+    /// Path of a file with the same content
+    pub to: PathBuf,
+}
+
+/// Searches the scan roots for files with the same content as missing books
+///
+/// Only books whose hash was recorded while the file still existed can be
+/// found. Candidate files are EPUBs that aren't in the library yet, or that
+/// a rescan added as a fresh book with no tags, rating or status.
+///
+/// # Arguments
+/// * `books` - The books of the library
+/// * `roots` - Folders to search
+///
+/// # Returns
+/// One relocation per missing book that was found
+pub fn find_moved(books: &[Book], roots: &[PathBuf]) -> Vec<Relocation> {
+    // Hash -> stored path of every missing book with a known hash
+    let mut wanted: HashMap<&str, &PathBuf> = books
+        .iter()
+        .filter(|book| !book.path.exists() && !crate::remote::is_remote(&book.path))
+        .filter_map(|book| book.hash.as_deref().map(|hash| (hash, &book.path)))
+        .collect();
+    if wanted.is_empty() {
+        return Vec::new();
+    }
+
+    // Books with user data are never a candidate - they belong to someone already
+    let taken: HashSet<&Path> = books
+        .iter()
+        .filter(|book| !book.user.is_empty())
+        .map(|book| book.path.as_path())
+        .collect();
+
+    let mut found = Vec::new();
+    for root in roots {
+        for entry in WalkDir::new(root).into_iter().filter_map(Result::ok) {
+            let path = entry.path();
+            if !entry.file_type().is_file() || !crate::scanner::is_epub(path) || taken.contains(path) {
+                continue;
+            }
+
+            let Ok(hash) = crate::hash::content_hash(path) else {
+                continue;
+            };
+            if let Some(from) = wanted.remove(hash.as_str()) {
+                found.push(Relocation {
+                    from: from.clone(),
+                    to: path.to_path_buf(),
+                });
+                if wanted.is_empty() {
+                    return found;
+                }
+            }
+        }
+    }
+    found
+}
+
+/// Points books to the files they were found at
+///
+/// A fresh entry a rescan created for the new path is dropped, so the book
+/// isn't listed twice.
+///
+/// # Returns
+/// How many books were repointed
+pub fn apply_relocations(books: &mut Vec<Book>, relocations: &[Relocation]) -> usize {
+    let mut moved = 0;
+    for relocation in relocations {
+        books.retain(|book| book.path != relocation.to);
+        if let Some(book) = books.iter_mut().find(|book| book.path == relocation.from) {
+            tracing::info!(from = %relocation.from.display(), to = %relocation.to.display(), "book relocated by content hash");
+            book.path = relocation.to.clone();
+            if let Some(name) = relocation.to.file_name() {
+                book.name = name.to_string_lossy().to_string();
+            }
+            moved += 1;
+        }
+    }
+    moved
+}
@@ -0,0 +1,206 @@
+// src/import/calibre.rs
+// Imports books from a Calibre library by reading its metadata.db (SQLite)
+
+use crate::book::{Book, Metadata, UserData};
+use rusqlite::{Connection, OpenFlags};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the database file at the root of every Calibre library
+const CALIBRE_DB: &str = "metadata.db";
+
+/// One EPUB file row of the Calibre `books` + `data` tables
+struct CalibreRow {
+    /// Calibre's book id (key for all the link tables)
+    id: i64,
+    title: String,
+    /// Book folder, relative to the library root ("Author/Title (12)")
+    folder: String,
+    /// File name without extension ("Title - Author")
+    file_stem: String,
+    series_index: Option<f64>,
+    pubdate: Option<String>,
+    uuid: Option<String>,
+}
+
+/// Reads every EPUB of a Calibre library
+///
+/// Titles, authors, tags, series, ratings, languages, publisher, ISBN and
+/// comments are carried over. Formats other than EPUB are skipped.
+///
+/// # Arguments
+/// * `library_dir` - The Calibre library folder (the one containing metadata.db)
+///
+/// # Returns
+/// The books found, or an error if metadata.db can't be opened or read
+pub fn import(library_dir: &Path) -> io::Result<Vec<Book>> {
+    // Absolute root so the stored file paths work from anywhere
+    let root = std::fs::canonicalize(library_dir)?;
+
+    // Read-only: we never touch the Calibre database
+    let conn = Connection::open_with_flags(root.join(CALIBRE_DB), OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(io::Error::other)?;
+
+    read_books(&conn, &root).map_err(io::Error::other)
+}
+
+/// Queries all EPUB books and their linked data
+fn read_books(conn: &Connection, root: &Path) -> rusqlite::Result<Vec<Book>> {
+    let mut stmt = conn.prepare(
+        "SELECT b.id, b.title, b.path, d.name, b.series_index, b.pubdate, b.uuid
+         FROM books b JOIN data d ON d.book = b.id
+         WHERE upper(d.format) = 'EPUB'
+         ORDER BY b.sort",
+    )?;
+
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(CalibreRow {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                folder: row.get(2)?,
+                file_stem: row.get(3)?,
+                series_index: row.get(4)?,
+                pubdate: row.get(5)?,
+                uuid: row.get(6)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut books = Vec::with_capacity(rows.len());
+    for row in rows {
+        books.push(build_book(conn, root, row)?);
+    }
+
+    Ok(books)
+}
+
+/// Builds a Book from a row plus the link tables (authors, tags, series...)
+fn build_book(conn: &Connection, root: &Path, row: CalibreRow) -> rusqlite::Result<Book> {
+    // Calibre stores files as {root}/{book folder}/{name}.epub
+    let path: PathBuf = root.join(&row.folder).join(format!("{}.epub", row.file_stem));
+    let name = format!("{}.epub", row.file_stem);
+
+    let authors = strings(
+        conn,
+        "SELECT a.name FROM authors a JOIN books_authors_link l ON l.author = a.id
+         WHERE l.book = ?1 ORDER BY l.id",
+        row.id,
+    )?;
+    let tags = strings(
+        conn,
+        "SELECT t.name FROM tags t JOIN books_tags_link l ON l.tag = t.id
+         WHERE l.book = ?1 ORDER BY t.name",
+        row.id,
+    )?;
+    let series = strings(
+        conn,
+        "SELECT s.name FROM series s JOIN books_series_link l ON l.series = s.id
+         WHERE l.book = ?1",
+        row.id,
+    )?
+    .into_iter()
+    .next();
+    let language = strings(
+        conn,
+        "SELECT g.lang_code FROM languages g JOIN books_languages_link l ON l.lang_code = g.id
+         WHERE l.book = ?1 ORDER BY l.item_order",
+        row.id,
+    )?
+    .into_iter()
+    .next();
+    let publisher = strings(
+        conn,
+        "SELECT p.name FROM publishers p JOIN books_publishers_link l ON l.publisher = p.id
+         WHERE l.book = ?1",
+        row.id,
+    )?
+    .into_iter()
+    .next();
+    let isbn = strings(
+        conn,
+        "SELECT val FROM identifiers WHERE book = ?1 AND lower(type) = 'isbn'",
+        row.id,
+    )?
+    .into_iter()
+    .next();
+    let description = strings(conn, "SELECT text FROM comments WHERE book = ?1", row.id)?
+        .into_iter()
+        .next()
+        .map(|html| strip_html(&html));
+
+    // Calibre ratings are 0-10 (half stars); 0 means "not rated"
+    let rating: Option<i64> = conn
+        .query_row(
+            "SELECT r.rating FROM ratings r JOIN books_ratings_link l ON l.rating = r.id
+             WHERE l.book = ?1",
+            [row.id],
+            |r| r.get(0),
+        )
+        .ok();
+    let rating = rating
+        .filter(|r| *r > 0)
+        .map(|r| ((r + 1) / 2).clamp(1, 5) as u8);
+
+    // Calibre uses year 101 as its "no date" placeholder
+    let published = row
+        .pubdate
+        .filter(|date| !date.starts_with("0101"))
+        .map(|date| date.chars().take(10).collect());
+
+    let mut book = Book::new(name, path);
+    book.meta = Metadata {
+        title: Some(row.title),
+        authors,
+        language,
+        publisher,
+        published,
+        description,
+        subjects: Vec::new(),
+        isbn: isbn.map(|i| crate::epub::normalize_isbn(&i)),
+        identifier: row.uuid.map(|uuid| format!("urn:uuid:{}", uuid)),
+        // The series index is only meaningful when the book is in a series
+        series_index: series.as_ref().and(row.series_index.map(|i| i as f32)),
+        series,
+        fixed_layout: false,
+        media_overlays: false,
+        accessibility: Default::default(),
+    };
+    book.user = UserData {
+        tags,
+        rating,
+        ..Default::default()
+    };
+
+    Ok(book)
+}
+
+/// Runs a query with a single book-id parameter and collects the first column
+fn strings(conn: &Connection, sql: &str, book_id: i64) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare_cached(sql)?;
+    let values = stmt.query_map([book_id], |r| r.get(0))?;
+    values.collect()
+}
+
+/// Removes HTML tags from Calibre comments, keeping paragraphs as blank lines
+fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+
+    for c in html.replace("</p>", "\n\n").replace("<br>", "\n").chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    text.replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&nbsp;", " ")
+        .trim()
+        .to_string()
+}
@@ -0,0 +1,174 @@
+// src/import/goodreads.rs
+// Imports reading data from a Goodreads library export (goodreads_library_export.csv)
+
+use super::ImportSummary;
+use crate::book::{Book, ReadingStatus};
+use crate::epub::normalize_isbn;
+use serde::Deserialize;
+use std::io;
+use std::path::Path;
+
+/// The columns we use from a Goodreads export row
+/// (the export has many more, csv + serde simply ignore them)
+#[derive(Debug, Deserialize)]
+struct GoodreadsRow {
+    #[serde(rename = "Title")]
+    title: String,
+
+    #[serde(rename = "Author", default)]
+    author: String,
+
+    /// Written as `="0261103571"` so spreadsheets keep the leading zero
+    #[serde(rename = "ISBN", default)]
+    isbn: String,
+
+    #[serde(rename = "ISBN13", default)]
+    isbn13: String,
+
+    /// 0 means "not rated"
+    #[serde(rename = "My Rating", default)]
+    my_rating: u8,
+
+    /// Comma-separated custom shelves
+    #[serde(rename = "Bookshelves", default)]
+    bookshelves: String,
+
+    /// read / currently-reading / to-read
+    #[serde(rename = "Exclusive Shelf", default)]
+    exclusive_shelf: String,
+}
+
+/// Applies a Goodreads export to the books of a library
+///
+/// Rows are matched to local books by ISBN first, then by title (and
+/// author when the local book has one). Matched books get the Goodreads
+/// rating, a reading status from the read-status shelf, and have the
+/// custom shelves added as tags.
+/// Rows without a local book are only counted.
+///
+/// # Arguments
+/// * `csv_path` - Path to the exported CSV file
+/// * `library` - The books of the library (modified in place)
+///
+/// # Returns
+/// Summary with `updated` (matched rows) and `unmatched` counts
+pub fn import(csv_path: &Path, library: &mut [Book]) -> io::Result<ImportSummary> {
+    let mut reader = csv::Reader::from_path(csv_path).map_err(io::Error::other)?;
+    let mut summary = ImportSummary::default();
+
+    for row in reader.deserialize::<GoodreadsRow>() {
+        let row = row.map_err(io::Error::other)?;
+
+        match find_book(library, &row) {
+            Some(book) => {
+                apply_row(book, &row);
+                summary.updated += 1;
+            }
+            None => summary.unmatched += 1,
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Finds the local book a Goodreads row refers to
+fn find_book<'a>(library: &'a mut [Book], row: &GoodreadsRow) -> Option<&'a mut Book> {
+    // ISBNs of the row, all as ISBN-13 so 10/13 digit forms compare equal
+    let row_isbns: Vec<String> = [&row.isbn, &row.isbn13]
+        .iter()
+        .filter_map(|raw| isbn13(&normalize_isbn(raw)))
+        .collect();
+
+    if let Some(i) = library.iter().position(|book| {
+        book.meta
+            .isbn
+            .as_deref()
+            .and_then(isbn13)
+            .map(|isbn| row_isbns.contains(&isbn))
+            .unwrap_or(false)
+    }) {
+        return library.get_mut(i);
+    }
+
+    // Fall back to the title, checked against the author when we know it
+    let title = normalize_title(&row.title);
+    let author = normalize_title(&row.author);
+    let i = library.iter().position(|book| {
+        normalize_title(book.display_title()) == title
+            && (book.authors().is_empty()
+                || book.authors().iter().any(|a| normalize_title(a) == author))
+    })?;
+    library.get_mut(i)
+}
+
+/// Copies rating and shelves from a row into a book
+fn apply_row(book: &mut Book, row: &GoodreadsRow) {
+    if (1..=5).contains(&row.my_rating) {
+        book.user.rating = Some(row.my_rating);
+    }
+
+    // The read-status shelf becomes the reading status
+    // (custom exclusive shelves like "on-hold" are kept as tags instead)
+    if let Some(status) = shelf_status(row.exclusive_shelf.trim()) {
+        book.user.status = Some(status);
+    }
+
+    // Every other shelf becomes a tag
+    let shelves = std::iter::once(row.exclusive_shelf.as_str())
+        .chain(row.bookshelves.split(','))
+        .map(str::trim)
+        .filter(|shelf| !shelf.is_empty() && shelf_status(shelf).is_none());
+
+    for shelf in shelves {
+        if !book.user.tags.iter().any(|t| t == shelf) {
+            book.user.tags.push(shelf.to_string());
+        }
+    }
+}
+
+/// Maps Goodreads' built-in read-status shelves to a reading status
+fn shelf_status(shelf: &str) -> Option<ReadingStatus> {
+    match shelf {
+        "to-read" => Some(ReadingStatus::ToRead),
+        "currently-reading" => Some(ReadingStatus::Reading),
+        "read" => Some(ReadingStatus::Finished),
+        _ => None,
+    }
+}
+
+/// Converts ISBN digits to ISBN-13 (ISBN-13 is returned unchanged)
+///
+/// # Returns
+/// None if the input is not a 10 or 13 character ISBN
+fn isbn13(isbn: &str) -> Option<String> {
+    match isbn.len() {
+        13 => Some(isbn.to_string()),
+        10 => {
+            // "978" + first 9 digits, then recompute the check digit
+            let body = format!("978{}", &isbn[..9]);
+            let sum: u32 = body
+                .chars()
+                .filter_map(|c| c.to_digit(10))
+                .enumerate()
+                .map(|(i, d)| if i % 2 == 0 { d } else { d * 3 })
+                .sum();
+            Some(format!("{}{}", body, (10 - sum % 10) % 10))
+        }
+        _ => None,
+    }
+}
+
+/// Lowercases a title and keeps only letters and digits, dropping the
+/// series suffix Goodreads adds, e.g. "Dune (Dune, #1)" -> "dune"
+fn normalize_title(title: &str) -> String {
+    let without_series = match title.rfind(" (") {
+        Some(pos) if title.ends_with(')') => &title[..pos],
+        _ => title,
+    };
+
+    without_series
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
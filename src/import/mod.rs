@@ -0,0 +1,77 @@
+// src/import/mod.rs
+// Importers that bring books and curation from other tools into a FunkHunt library
+
+pub mod calibre;
+pub mod goodreads;
+
+use crate::book::Book;
+use std::path::Path;
+
+/// Outcome of merging imported books into a library
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    /// Books that were not in the library yet
+    pub added: usize,
+
+    /// Books already in the library whose data was updated
+    pub updated: usize,
+
+    /// Imported records that matched no book of the library
+    pub unmatched: usize,
+}
+
+/// Merges imported books into a library
+///
+/// Books are matched by file path. A matching book takes the imported
+/// metadata, gains the imported tags and keeps its own rating unless the
+/// import has one. Unmatched books are appended to the library.
+///
+/// # Arguments
+/// * `library` - The books of the library (modified in place)
+/// * `imported` - Books produced by an importer
+///
+/// # Returns
+/// How many books were added and updated
+pub fn merge_into(library: &mut Vec<Book>, imported: Vec<Book>) -> ImportSummary {
+    let mut summary = ImportSummary::default();
+
+    for new_book in imported {
+        match library.iter_mut().find(|b| same_file(&b.path, &new_book.path)) {
+            Some(existing) => {
+                existing.meta = new_book.meta;
+
+                // Union of tags, keeping the existing order first
+                for tag in new_book.user.tags {
+                    if !existing.user.tags.contains(&tag) {
+                        existing.user.tags.push(tag);
+                    }
+                }
+
+                if new_book.user.rating.is_some() {
+                    existing.user.rating = new_book.user.rating;
+                }
+
+                summary.updated += 1;
+            }
+            None => {
+                library.push(new_book);
+                summary.added += 1;
+            }
+        }
+    }
+
+    summary
+}
+
+/// Checks whether two paths point to the same file
+/// (equal as written, or equal once resolved to absolute paths)
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    if a == b {
+        return true;
+    }
+
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
@@ -0,0 +1,78 @@
+// src/inbox.rs
+// Inbox folder - EPUBs that appear in it (e.g. ~/Downloads) are imported
+// into the library, optionally moved into the library's folder structure
+
+use crate::book::Book;
+use crate::organize::{self, Move};
+use crate::profile::FolderSettings;
+use crate::settings::InboxSettings;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Imports the new EPUBs of the inbox folder
+///
+/// Only the top level of the inbox is looked at, so a big Downloads folder
+/// isn't walked. Files already in the library are left alone; a file whose
+/// destination is taken stays in the inbox.
+///
+/// # Arguments
+/// * `settings` - Inbox settings (the folder must be set)
+/// * `library_root` - Scan root the books are moved into
+/// * `folder_settings` - Settings of that root (default tags)
+/// * `template` - Organize template, used when `settings.organize` is on
+/// * `books` - The books of the library
+///
+/// # Returns
+/// The imported books (already at their new path), or an error if the
+/// inbox can't be read
+pub fn import(
+    settings: &InboxSettings,
+    library_root: &Path,
+    folder_settings: &FolderSettings,
+    template: &str,
+    books: &[Book],
+) -> io::Result<Vec<Book>> {
+    let Some(inbox) = &settings.folder else {
+        return Ok(Vec::new());
+    };
+
+    let mut imported = Vec::new();
+    for entry in fs::read_dir(inbox)? {
+        let path = entry?.path();
+        if !path.is_file() || !crate::scanner::is_epub(&path) || books.iter().any(|b| b.path == path) {
+            continue;
+        }
+
+        let mut book = crate::scanner::book_from_file(&path, folder_settings);
+        if settings.move_files {
+            let relative = if settings.organize {
+                organize::render(template, &book)
+            } else {
+                path.file_name().unwrap_or_default().into()
+            };
+            let to = library_root.join(relative);
+            if to.exists() {
+                tracing::warn!(path = %path.display(), destination = %to.display(), "inbox file not imported, destination exists");
+                continue;
+            }
+
+            let planned = Move {
+                from: path.clone(),
+                to: to.clone(),
+                root: inbox.clone(),
+                conflict: None,
+            };
+            if let Err(e) = organize::execute(&[planned]) {
+                tracing::warn!(path = %path.display(), error = %e, "cannot move inbox file");
+                continue;
+            }
+            organize::repoint(std::slice::from_mut(&mut book), &path, &to);
+        }
+
+        tracing::info!(path = %book.path.display(), "imported from inbox");
+        imported.push(book);
+    }
+
+    Ok(imported)
+}
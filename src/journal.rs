@@ -0,0 +1,172 @@
+// src/journal.rs
+// Operation journal - remembers what library operations changed so they can
+// be undone and redone (for the current session only)
+
+use crate::book::Book;
+use crate::organize::{self, Move};
+use std::io;
+
+/// How many operations are remembered; the oldest are forgotten first
+const MAX_OPERATIONS: usize = 100;
+
+/// A library operation that can be undone
+#[derive(Debug, Clone)]
+pub enum Operation {
+    /// Books were edited (tags, status, metadata, path...)
+    Edit {
+        /// What was done, e.g. "bulk edit of 3 books"
+        description: String,
+        /// The books as they were
+        before: Vec<Book>,
+        /// The books as they are now (same order as `before`)
+        after: Vec<Book>,
+    },
+
+    /// Books were removed from the library (their files were not touched)
+    Remove {
+        description: String,
+        /// The removed books with their position in the library
+        books: Vec<(usize, Book)>,
+    },
+
+    /// Files were moved or renamed on disk
+    Move {
+        description: String,
+        /// The moves that were done
+        moves: Vec<Move>,
+    },
+}
+
+impl Operation {
+    /// What the operation did, e.g. "rename of 12 files"
+    pub fn description(&self) -> &str {
+        match self {
+            Operation::Edit { description, .. }
+            | Operation::Remove { description, .. }
+            | Operation::Move { description, .. } => description,
+        }
+    }
+
+    /// Reverts the operation on the library
+    fn revert(&self, books: &mut Vec<Book>) -> io::Result<()> {
+        match self {
+            Operation::Edit { before, after, .. } => {
+                replace_books(books, after, before);
+                Ok(())
+            }
+            Operation::Remove { books: removed, .. } => {
+                // Back to where they were, lowest position first
+                for (index, book) in removed {
+                    books.insert((*index).min(books.len()), book.clone());
+                }
+                Ok(())
+            }
+            Operation::Move { moves, .. } => {
+                organize::rollback(moves);
+                for moved in moves {
+                    organize::repoint(books, &moved.to, &moved.from);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Does the operation again after it was reverted
+    fn reapply(&self, books: &mut Vec<Book>) -> io::Result<()> {
+        match self {
+            Operation::Edit { before, after, .. } => {
+                replace_books(books, before, after);
+                Ok(())
+            }
+            Operation::Remove { books: removed, .. } => {
+                books.retain(|book| !removed.iter().any(|(_, r)| r.path == book.path));
+                Ok(())
+            }
+            Operation::Move { moves, .. } => {
+                // All or nothing, like the first time
+                let done = organize::execute(moves)?;
+                for moved in &done {
+                    organize::repoint(books, &moved.from, &moved.to);
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Replaces books by path: each book matching `old[i]` becomes `new[i]`
+///
+/// Matched by path because the list may have been rescanned or re-sorted
+/// since; an edit that changed a path (relocation) is found by its old path.
+fn replace_books(books: &mut [Book], old: &[Book], new: &[Book]) {
+    for (old, new) in old.iter().zip(new) {
+        if let Some(book) = books.iter_mut().find(|b| b.path == old.path) {
+            *book = new.clone();
+        }
+    }
+}
+
+/// Undo and redo stacks of the session
+#[derive(Debug, Default)]
+pub struct Journal {
+    /// Done operations, newest last
+    undo: Vec<Operation>,
+
+    /// Undone operations, newest last (cleared by any new operation)
+    redo: Vec<Operation>,
+}
+
+impl Journal {
+    /// Remembers a new operation
+    ///
+    /// Whatever was undone before can't be redone anymore.
+    pub fn record(&mut self, operation: Operation) {
+        self.redo.clear();
+        self.undo.push(operation);
+        if self.undo.len() > MAX_OPERATIONS {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Undoes the newest operation
+    ///
+    /// # Returns
+    /// * `None` - Nothing to undo
+    /// * `Some(Ok(description))` - What was undone
+    /// * `Some(Err(e))` - Undoing failed (the operation stays undoable)
+    pub fn undo(&mut self, books: &mut Vec<Book>) -> Option<io::Result<String>> {
+        let operation = self.undo.pop()?;
+        Some(match operation.revert(books) {
+            Ok(()) => {
+                let description = operation.description().to_string();
+                self.redo.push(operation);
+                Ok(description)
+            }
+            Err(e) => {
+                self.undo.push(operation);
+                Err(e)
+            }
+        })
+    }
+
+    /// Redoes the newest undone operation
+    ///
+    /// # Returns
+    /// * `None` - Nothing to redo
+    /// * `Some(Ok(description))` - What was redone
+    /// * `Some(Err(e))` - Redoing failed (the operation stays redoable)
+    pub fn redo(&mut self, books: &mut Vec<Book>) -> Option<io::Result<String>> {
+        let operation = self.redo.pop()?;
+        Some(match operation.reapply(books) {
+            Ok(()) => {
+                let description = operation.description().to_string();
+                self.undo.push(operation);
+                Ok(description)
+            }
+            Err(e) => {
+                self.redo.push(operation);
+                Err(e)
+            }
+        })
+    }
+}
@@ -0,0 +1,348 @@
+// src/language.rs
+// Language of a book's text, for books whose package has no dc:language or
+// a wrong one (many tools write "en" whatever the book is in)
+//
+// A sample of the text is read from a third of the way into the spine
+// (past the front matter) and its language guessed:
+// - by its script when most letters aren't Latin: Cyrillic (Ukrainian,
+//   Bulgarian, Serbian or Russian by their own letters), Greek, Hebrew,
+//   Arabic (Persian by its own letters), Devanagari, Thai, Hangul, kana
+//   (Japanese) or Han alone (Chinese)
+// - else by its most common words, for a score of languages written in the
+//   Latin script
+// A guess is only made with enough text and a clear winner, so a book of
+// one language keeps what its package says. Scanning stores the guess with
+// the book (`Book::detected_language`); `Book::language` decides which one
+// the filter and the reader (hyphenation, dictionaries) use.
+
+use crate::epub;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// Characters of text sampled from a book
+const SAMPLE_CHARS: usize = 20_000;
+
+/// Fewest words a Latin-script sample needs for a guess
+const MIN_WORDS: usize = 100;
+
+/// Fewest letters a sample needs for a guess by script
+const MIN_LETTERS: usize = 200;
+
+/// Share of a sample's words the winning language must know (in percent)
+const MIN_SHARE: usize = 10;
+
+/// How many times more words the winner must know than the runner-up
+const MIN_LEAD: f32 = 1.5;
+
+/// The languages told apart by their words: code, ISO 639-2 codes, English
+/// name, and their most common words (short words shared by many of them
+/// still count - the words only one language has decide)
+const LANGUAGES: &[(&str, &[&str], &str, &[&str])] = &[
+    ("en", &["eng"], "english", &[
+        "the", "and", "of", "to", "a", "in", "that", "is", "was", "he", "for", "it", "with", "as", "his", "on",
+        "be", "at", "by", "had", "not", "are", "but", "from", "or", "have", "she", "they", "which", "you",
+        "were", "her", "would", "there", "their", "what", "been", "this", "could", "when",
+    ]),
+    ("es", &["spa"], "spanish", &[
+        "de", "la", "que", "el", "en", "y", "a", "los", "se", "del", "las", "un", "por", "con", "no", "una",
+        "su", "para", "es", "al", "lo", "como", "más", "pero", "sus", "le", "ya", "o", "fue", "este", "ha",
+        "sí", "porque", "esta", "cuando", "muy", "sin", "sobre", "también", "había", "estaba", "hasta", "yo",
+        "él", "ella", "eso", "nos", "pues",
+    ]),
+    ("fr", &["fre", "fra"], "french", &[
+        "de", "la", "le", "et", "les", "des", "en", "un", "du", "une", "que", "est", "pour", "qui", "dans",
+        "a", "par", "plus", "pas", "au", "sur", "ne", "se", "ce", "il", "sont", "mais", "elle", "avec", "je",
+        "vous", "nous", "on", "était", "avait", "cette", "aux", "ses", "son", "sa", "lui", "tout", "comme",
+        "bien", "où",
+    ]),
+    ("de", &["ger", "deu"], "german", &[
+        "der", "die", "und", "in", "den", "von", "zu", "das", "mit", "sich", "des", "auf", "für", "ist", "im",
+        "dem", "nicht", "ein", "eine", "als", "auch", "es", "an", "er", "hat", "aus", "bei", "sie", "nach",
+        "wird", "war", "ich", "aber", "noch", "wie", "einen", "dass", "doch", "wenn", "schon", "mir", "hatte",
+        "nur", "so",
+    ]),
+    ("it", &["ita"], "italian", &[
+        "di", "e", "il", "la", "che", "a", "per", "un", "in", "è", "non", "una", "del", "della", "le", "si",
+        "da", "con", "i", "lo", "gli", "al", "ma", "come", "anche", "più", "nel", "sono", "era", "alla", "mi",
+        "io", "lui", "lei", "aveva", "questo", "quando", "perché", "ci", "ha", "se", "suo", "sua", "cosa",
+    ]),
+    ("pt", &["por"], "portuguese", &[
+        "de", "a", "o", "que", "e", "do", "da", "em", "um", "para", "é", "com", "não", "uma", "os", "no",
+        "se", "na", "por", "mais", "as", "dos", "como", "mas", "ao", "ele", "das", "à", "seu", "sua", "ou",
+        "quando", "muito", "nos", "já", "eu", "também", "só", "pelo", "pela", "era", "ela", "isso", "foi",
+        "estava", "você", "então",
+    ]),
+    ("nl", &["dut", "nld"], "dutch", &[
+        "de", "en", "van", "ik", "te", "dat", "die", "in", "een", "hij", "het", "niet", "zijn", "is", "was",
+        "op", "aan", "met", "als", "voor", "had", "er", "maar", "om", "hem", "dan", "zou", "of", "wat", "mijn",
+        "men", "dit", "zo", "door", "over", "ze", "zich", "bij", "ook", "tot", "je", "mij", "uit", "der",
+        "nog", "wel", "geen",
+    ]),
+    ("sv", &["swe"], "swedish", &[
+        "och", "i", "att", "det", "som", "en", "på", "är", "av", "för", "med", "till", "den", "har", "de",
+        "inte", "om", "ett", "han", "men", "var", "jag", "sig", "från", "vi", "så", "kan", "man", "när",
+        "år", "hon", "nu", "hade", "skulle", "efter", "upp", "vara", "honom", "hennes",
+    ]),
+    ("da", &["dan"], "danish", &[
+        "og", "i", "at", "det", "er", "en", "til", "på", "de", "med", "af", "for", "den", "som", "ikke",
+        "der", "var", "han", "et", "sig", "jeg", "har", "hun", "men", "fra", "havde", "ham", "hans", "vi",
+        "kan", "eller", "også", "nu", "over", "efter", "mig", "blev", "hvor", "skulle", "jo",
+    ]),
+    ("no", &["nor", "nob", "nno", "nb", "nn"], "norwegian", &[
+        "og", "i", "det", "på", "som", "er", "en", "til", "å", "han", "av", "for", "med", "at", "var", "de",
+        "ikke", "den", "har", "jeg", "om", "et", "men", "så", "seg", "hun", "hadde", "fra", "vi", "du",
+        "kan", "da", "ble", "ut", "skal", "meg", "deg", "noe", "hva", "være", "etter", "ham", "når", "nå",
+    ]),
+    ("fi", &["fin"], "finnish", &[
+        "ja", "on", "ei", "oli", "se", "että", "hän", "mutta", "kuin", "niin", "en", "ole", "hänen", "minä",
+        "sen", "joka", "vain", "jo", "nyt", "kun", "tai", "sitä", "mitä", "ovat", "olen", "siitä", "sinä",
+        "myös", "jos", "vielä", "jotka", "olla", "koska", "mikä", "sitten", "vaan", "kaikki", "tämä",
+    ]),
+    ("pl", &["pol"], "polish", &[
+        "i", "w", "nie", "na", "się", "z", "jest", "że", "do", "to", "jak", "ale", "co", "o", "tak", "po",
+        "od", "za", "jego", "go", "już", "tylko", "mnie", "czy", "był", "była", "jej", "ze", "tym", "przez",
+        "mu", "ja", "on", "ona", "są", "może", "gdy", "bo", "tego", "jeszcze", "tu", "kiedy", "sobie",
+    ]),
+    ("cs", &["cze", "ces"], "czech", &[
+        "a", "se", "na", "je", "v", "že", "to", "s", "z", "do", "o", "jsem", "ale", "k", "by", "jak", "tak",
+        "za", "jako", "už", "co", "byl", "od", "jen", "ve", "mu", "si", "mi", "jsou", "jeho", "které",
+        "který", "ještě", "když", "bylo", "byla", "tom", "něco", "nebo", "také",
+    ]),
+    ("hu", &["hun"], "hungarian", &[
+        "a", "az", "és", "hogy", "nem", "is", "egy", "meg", "de", "volt", "csak", "már", "ki", "el", "ha",
+        "még", "mint", "van", "azt", "ez", "vagy", "mert", "most", "aztán", "így", "sem", "pedig", "neki",
+        "minden", "nagyon", "akkor", "itt", "ott", "lesz", "kell", "amikor", "ahogy", "aki", "ami",
+    ]),
+    ("ro", &["rum", "ron"], "romanian", &[
+        "și", "în", "de", "la", "a", "să", "nu", "pe", "cu", "că", "o", "un", "se", "din", "mai", "este",
+        "ce", "fost", "era", "pentru", "dar", "care", "lui", "sau", "ca", "am", "îi", "el", "ea", "al",
+        "acest", "după", "când", "cum", "foarte", "îl", "sunt", "avea", "fi",
+    ]),
+    ("tr", &["tur"], "turkish", &[
+        "ve", "bir", "bu", "da", "de", "için", "ile", "ne", "çok", "daha", "gibi", "ama", "o", "en", "kadar",
+        "sonra", "her", "var", "olan", "ben", "sen", "onu", "diye", "değil", "şey", "mi", "mı", "olarak",
+        "yok", "ki", "bana", "hiç", "nasıl", "şimdi", "neden", "onun", "bunu", "ise",
+    ]),
+    ("ca", &["cat"], "catalan", &[
+        "de", "la", "i", "el", "que", "a", "en", "les", "per", "amb", "del", "es", "un", "una", "els", "no",
+        "va", "al", "com", "més", "però", "hi", "ho", "seu", "seva", "ja", "molt", "quan", "aquest",
+        "aquesta", "també", "tot", "era", "perquè", "sense", "fins", "havia", "li", "ens", "jo",
+    ]),
+    ("la", &["lat"], "latin", &[
+        "et", "in", "est", "non", "ad", "cum", "quod", "qui", "ut", "sed", "quae", "enim", "esse", "quam",
+        "ab", "per", "ex", "sunt", "autem", "atque", "nec", "etiam", "si", "eius", "hoc", "quoque", "neque",
+        "erat", "inter", "sibi", "ac", "eum", "tamen", "quid", "vel", "nam", "ille", "ita",
+    ]),
+];
+
+/// Languages told apart by their script (code, ISO 639-2 codes, English
+/// name) - the code is also what `detect` answers for their script
+const SCRIPT_LANGUAGES: &[(&str, &[&str], &str)] = &[
+    ("ru", &["rus"], "russian"),
+    ("uk", &["ukr"], "ukrainian"),
+    ("bg", &["bul"], "bulgarian"),
+    ("sr", &["srp"], "serbian"),
+    ("el", &["gre", "ell"], "greek"),
+    ("he", &["heb"], "hebrew"),
+    ("ar", &["ara"], "arabic"),
+    ("fa", &["per", "fas"], "persian"),
+    ("hi", &["hin"], "hindi"),
+    ("th", &["tha"], "thai"),
+    ("ko", &["kor"], "korean"),
+    ("ja", &["jpn"], "japanese"),
+    ("zh", &["chi", "zho"], "chinese"),
+];
+
+/// Scripts of letters, for the languages told apart by their script
+#[derive(Clone, Copy, PartialEq)]
+enum Script {
+    Latin,
+    Cyrillic,
+    Greek,
+    Hebrew,
+    Arabic,
+    Devanagari,
+    Thai,
+    Hangul,
+    Kana,
+    Han,
+}
+
+/// Guesses the language of a text
+///
+/// # Returns
+/// The language's two-letter code, or None if the text is too short or
+/// the guess isn't sure enough
+#[cfg_attr(feature = "profiling", tracing::instrument(level = "trace", name = "language", skip_all))]
+pub fn detect(text: &str) -> Option<&'static str> {
+    let mut scripts: Vec<(Script, usize)> = Vec::new();
+    let mut letters = 0;
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        letters += 1;
+        if let Some(script) = script(c) {
+            match scripts.iter_mut().find(|(s, _)| *s == script) {
+                Some((_, count)) => *count += 1,
+                None => scripts.push((script, 1)),
+            }
+        }
+    }
+    if letters < MIN_LETTERS {
+        return None;
+    }
+    let count = |script: Script| scripts.iter().find(|(s, _)| *s == script).map_or(0, |(_, count)| *count);
+    let has = |letters: &str| text.chars().any(|c| letters.contains(c));
+
+    // Japanese mixes kana and Han, Chinese is Han alone
+    if count(Script::Kana) * 20 > letters {
+        return Some("ja");
+    }
+    let (main, main_count) = scripts.iter().copied().max_by_key(|(_, count)| *count)?;
+    if main_count * 2 < letters {
+        return None;
+    }
+    match main {
+        Script::Latin => detect_by_words(text),
+        Script::Cyrillic if has("їєґ") => Some("uk"),
+        Script::Cyrillic if has("јљњћђџ") => Some("sr"),
+        Script::Cyrillic if !has("ыэё") && has("ъ") => Some("bg"),
+        Script::Cyrillic => Some("ru"),
+        Script::Greek => Some("el"),
+        Script::Hebrew => Some("he"),
+        Script::Arabic if has("پچژگ") => Some("fa"),
+        Script::Arabic => Some("ar"),
+        Script::Devanagari => Some("hi"),
+        Script::Thai => Some("th"),
+        Script::Hangul => Some("ko"),
+        Script::Kana => Some("ja"),
+        Script::Han => Some("zh"),
+    }
+}
+
+/// The two-letter code of a language as a package writes it: "en-GB",
+/// "eng" and "English" all give "en" (a code not known here is only
+/// lowercased, up to its first '-' or '_')
+pub fn primary(language: &str) -> String {
+    let language = language.trim().to_lowercase();
+    let code = language.split(['-', '_']).next().unwrap_or_default();
+    let known = LANGUAGES
+        .iter()
+        .map(|(code, others, name, _)| (*code, *others, *name))
+        .chain(SCRIPT_LANGUAGES.iter().copied())
+        .find(|(two, others, name)| *two == code || others.contains(&code) || *name == language);
+    match known {
+        Some((two, _, _)) => two.to_string(),
+        None => code.to_string(),
+    }
+}
+
+/// Whether a word names a language known here, by its code (two letters
+/// or ISO 639-2) or its English name, e.g. "de", "deu" or "German"
+pub fn is_known(word: &str) -> bool {
+    let word = word.to_lowercase();
+    LANGUAGES
+        .iter()
+        .map(|(code, others, name, _)| (*code, *others, *name))
+        .chain(SCRIPT_LANGUAGES.iter().copied())
+        .any(|(two, others, name)| two == word || others.contains(&word.as_str()) || name == word)
+}
+
+/// Guess for a text in the Latin script, from its most common words
+fn detect_by_words(text: &str) -> Option<&'static str> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    if words.len() < MIN_WORDS {
+        return None;
+    }
+
+    let mut scores: Vec<(&str, usize)> = LANGUAGES
+        .iter()
+        .map(|(code, _, _, common)| (*code, words.iter().filter(|word| common.contains(&word.as_str())).count()))
+        .collect();
+    scores.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+    let (best, score) = scores[0];
+    let runner_up = scores[1].1;
+    let sure = score * 100 >= words.len() * MIN_SHARE && score as f32 >= runner_up as f32 * MIN_LEAD;
+    sure.then_some(best)
+}
+
+/// Script of a letter (None for the scripts not told apart here)
+fn script(c: char) -> Option<Script> {
+    let script = match c as u32 {
+        0x0041..=0x024F | 0x1E00..=0x1EFF => Script::Latin,
+        0x0400..=0x052F => Script::Cyrillic,
+        0x0370..=0x03FF | 0x1F00..=0x1FFF => Script::Greek,
+        0x0590..=0x05FF => Script::Hebrew,
+        0x0600..=0x06FF | 0x0750..=0x077F | 0xFB50..=0xFDFF | 0xFE70..=0xFEFF => Script::Arabic,
+        0x0900..=0x097F => Script::Devanagari,
+        0x0E00..=0x0E7F => Script::Thai,
+        0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => Script::Hangul,
+        0x3040..=0x30FF | 0x31F0..=0x31FF | 0xFF66..=0xFF9F => Script::Kana,
+        0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF => Script::Han,
+        _ => return None,
+    };
+    Some(script)
+}
+
+/// Text of a book to guess its language from (and to measure how hard it
+/// reads, see readability.rs): the documents of the spine from a third of
+/// the way in, then the ones before, up to SAMPLE_CHARS characters
+#[cfg_attr(feature = "profiling", tracing::instrument(level = "trace", name = "text sample", skip_all))]
+pub fn sample(path: &Path) -> io::Result<String> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(io::Error::other)?;
+    let (opf_path, opf) = epub::read_package(&mut archive)?;
+    let (items, _) = epub::read_manifest(&opf)?;
+    let documents: Vec<String> = epub::read_spine(&opf)?
+        .into_iter()
+        .filter(|(_, linear)| *linear)
+        .filter_map(|(idref, _)| items.iter().find(|item| item.id == idref))
+        .map(|item| epub::entry_path(&opf_path, &item.href))
+        .collect();
+
+    let start = documents.len() / 3;
+    let mut text = String::new();
+    for href in documents[start..].iter().chain(&documents[..start]) {
+        if text.chars().count() >= SAMPLE_CHARS {
+            break;
+        }
+        if let Ok(xhtml) = epub::read_entry(&mut archive, href) {
+            push_text(&mut text, &xhtml);
+        }
+    }
+    Ok(text)
+}
+
+/// Appends the text of an XHTML document (its body's, scripts and styles
+/// left out) to a sample, up to the first error of malformed markup
+fn push_text(text: &mut String, xhtml: &str) {
+    let mut reader = Reader::from_str(xhtml);
+    let mut skipped = 0usize;
+    let mut in_body = false;
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => match e.local_name().as_ref() {
+                b"body" => in_body = true,
+                b"script" | b"style" => skipped += 1,
+                _ => {}
+            },
+            Ok(Event::End(e)) => {
+                if matches!(e.local_name().as_ref(), b"script" | b"style") {
+                    skipped = skipped.saturating_sub(1);
+                }
+            }
+            Ok(Event::Text(t)) if in_body && skipped == 0 => {
+                if let Ok(t) = t.unescape() {
+                    text.push_str(&t);
+                    text.push(' ');
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,135 @@
+// src/listing.rs
+// The library listed for scripts (`funkhunt list`), without the TUI
+//
+// A book per line as tab-separated text (title, authors, file), or its
+// metadata as CSV (a header, then a row per book) or as a JSON array - what
+// the scanner found and read from the packages, in fields a script can use
+// as they are.
+
+use crate::book::Book;
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Columns of the CSV listing, in order (the fields of ListedBook)
+const COLUMNS: [&str; 12] = [
+    "path",
+    "title",
+    "authors",
+    "series",
+    "series_index",
+    "language",
+    "publisher",
+    "published",
+    "isbn",
+    "tags",
+    "status",
+    "size_bytes",
+];
+
+/// How `list` writes the books
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ListFormat {
+    /// Title, authors and file, separated by tabs (`--plain`)
+    #[default]
+    Plain,
+    /// A JSON array of objects (`--json`)
+    Json,
+    /// CSV with a header row (`--csv`)
+    Csv,
+}
+
+/// A book as listed
+#[derive(Debug, Serialize)]
+struct ListedBook<'a> {
+    path: &'a Path,
+    title: &'a str,
+    authors: &'a [String],
+    series: Option<&'a str>,
+    series_index: Option<f32>,
+    /// The one set by hand, declared or detected (see `Book::language`)
+    language: Option<&'a str>,
+    publisher: Option<&'a str>,
+    published: Option<&'a str>,
+    isbn: Option<&'a str>,
+    tags: &'a [String],
+    /// e.g. "to-read"
+    status: Option<&'static str>,
+    /// None for books on a server, or whose file can't be read
+    size_bytes: Option<u64>,
+}
+
+impl<'a> ListedBook<'a> {
+    fn from_book(book: &'a Book) -> Self {
+        // Books on a server aren't looked up there for their size
+        let size_bytes = if crate::remote::is_remote(&book.path) {
+            None
+        } else {
+            std::fs::metadata(&book.path).ok().map(|m| m.len())
+        };
+        Self {
+            path: &book.path,
+            title: book.display_title(),
+            authors: book.authors(),
+            series: book.meta.series.as_deref(),
+            series_index: book.meta.series_index,
+            language: book.language(),
+            publisher: book.meta.publisher.as_deref(),
+            published: book.meta.published.as_deref(),
+            isbn: book.meta.isbn.as_deref(),
+            tags: &book.user.tags,
+            status: book.user.status.map(|status| status.key()),
+            size_bytes,
+        }
+    }
+
+    /// Its CSV row, in COLUMNS' order (several authors or tags separated by "; ")
+    fn csv_row(&self) -> [String; 12] {
+        let text = |value: Option<&str>| value.unwrap_or_default().to_string();
+        let number = |value: Option<String>| value.unwrap_or_default();
+        [
+            self.path.display().to_string(),
+            self.title.to_string(),
+            self.authors.join("; "),
+            text(self.series),
+            number(self.series_index.map(|index| index.to_string())),
+            text(self.language),
+            text(self.publisher),
+            text(self.published),
+            text(self.isbn),
+            self.tags.join("; "),
+            text(self.status),
+            number(self.size_bytes.map(|size| size.to_string())),
+        ]
+    }
+}
+
+/// Writes the books in a format
+///
+/// # Arguments
+/// * `books` - The books, in the order they're listed
+/// * `format` - Plain text, JSON or CSV
+/// * `out` - Where to (stdout for `list`)
+pub fn write(books: &[&Book], format: ListFormat, out: &mut impl Write) -> io::Result<()> {
+    match format {
+        ListFormat::Plain => {
+            for book in books {
+                writeln!(out, "{}\t{}\t{}", book.display_title(), book.display_authors(), book.path.display())?;
+            }
+        }
+        ListFormat::Json => {
+            let listed: Vec<ListedBook> = books.iter().map(|book| ListedBook::from_book(book)).collect();
+            serde_json::to_writer_pretty(&mut *out, &listed).map_err(io::Error::other)?;
+            writeln!(out)?;
+        }
+        ListFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(out);
+            writer.write_record(COLUMNS).map_err(io::Error::other)?;
+            for book in books {
+                writer.write_record(ListedBook::from_book(book).csv_row()).map_err(io::Error::other)?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,141 @@
+// src/logging.rs
+// Structured logging with `tracing` - written to a log file and kept in memory
+// for the in-app log viewer (stdout can't be used while the TUI owns the terminal)
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tracing::Level;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+/// Name of the log file inside the log directory
+const LOG_FILE: &str = "funkhunt.log";
+
+/// How many recent lines the in-app log viewer keeps
+const BUFFER_LINES: usize = 500;
+
+/// Recent log lines, shared between the logger and the log viewer popup
+/// Cloning is cheap: all clones point to the same buffer
+#[derive(Clone, Default)]
+pub struct LogBuffer(Arc<Mutex<VecDeque<String>>>);
+
+impl LogBuffer {
+    /// Appends a line, dropping the oldest one when the buffer is full
+    fn push(&self, line: String) {
+        if let Ok(mut lines) = self.0.lock() {
+            if lines.len() == BUFFER_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(line);
+        }
+    }
+
+    /// Copies the buffered lines, oldest first
+    pub fn lines(&self) -> Vec<String> {
+        self.0
+            .lock()
+            .map(|lines| lines.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Writer handed to tracing for every event: copies output to the file and the buffer
+struct TeeWriter {
+    /// Log file (None if it couldn't be opened)
+    file: Option<Arc<Mutex<File>>>,
+
+    /// In-memory copy for the log viewer
+    buffer: LogBuffer,
+}
+
+impl Write for TeeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                file.write_all(buf)?;
+            }
+        }
+
+        // tracing writes one formatted event per call - keep each line
+        for line in String::from_utf8_lossy(buf).lines() {
+            if !line.trim().is_empty() {
+                self.buffer.push(line.to_string());
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &self.file {
+            Some(file) => file.lock().map(|mut f| f.flush()).unwrap_or(Ok(())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Installs the global logger
+///
+/// # Arguments
+/// * `level` - Most verbose level to record: "error", "warn", "info", "debug" or "trace"
+/// * `profile` - Where to write the time spent in each span (see profiling.rs),
+///   if asked for
+///
+/// # Returns
+/// The buffer of recent lines for the log viewer, or an error for an unknown level
+/// (or a profile asked of a build without the `profiling` feature).
+/// If the log file can't be opened, logging continues in memory only.
+pub fn init(level: &str, profile: Option<&Path>) -> Result<LogBuffer, String> {
+    let level = Level::from_str(level).map_err(|_| format!("unknown log level: '{}'", level))?;
+    #[cfg(not(feature = "profiling"))]
+    if profile.is_some() {
+        return Err("--profile needs a build with the profiling feature (cargo build --features profiling)".to_string());
+    }
+
+    let buffer = LogBuffer::default();
+    let log_path = crate::paths::log_dir().join(LOG_FILE);
+    let file = std::fs::create_dir_all(crate::paths::log_dir())
+        .and_then(|_| OpenOptions::new().create(true).append(true).open(&log_path));
+
+    let (file, file_error) = match file {
+        Ok(file) => (Some(Arc::new(Mutex::new(file))), None),
+        Err(e) => (None, Some(e)),
+    };
+
+    // Every event gets a fresh TeeWriter sharing the same file and buffer
+    // (the level filters this layer only: the profiler sees every span)
+    let writer_buffer = buffer.clone();
+    let log = tracing_subscriber::fmt::layer()
+        .with_ansi(false)
+        .with_target(false)
+        .with_writer(move || TeeWriter {
+            file: file.clone(),
+            buffer: writer_buffer.clone(),
+        })
+        .with_filter(LevelFilter::from_level(level));
+
+    let registry = tracing_subscriber::registry().with(log);
+    #[cfg(feature = "profiling")]
+    registry.with(profile.map(crate::profiling::layer)).init();
+    #[cfg(not(feature = "profiling"))]
+    registry.init();
+
+    match file_error {
+        Some(e) => tracing::warn!(path = %log_path.display(), error = %e, "cannot open log file, logging in memory only"),
+        None => tracing::info!(path = %log_path.display(), "logging started"),
+    }
+
+    Ok(buffer)
+}
+
+/// Ends logging: writes the profile, if one was asked for (see profiling.rs)
+pub fn finish() {
+    #[cfg(feature = "profiling")]
+    crate::profiling::finish();
+}
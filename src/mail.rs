@@ -0,0 +1,418 @@
+// src/mail.rs
+// Sending books by email - a small SMTP client, enough to mail an EPUB as
+// an attachment (e.g. to a Send-to-Kindle address)
+//
+// The server comes from [email] in config.toml. Connections are secured
+// with STARTTLS (port 587) or TLS from the start (port 465); certificates
+// are checked against the Mozilla root store built into the binary.
+//
+// The attachment is base64-encoded while it's read from disk, so the
+// transfer list shows the upload like any other. Mail servers refuse
+// messages over their size limit, and base64 makes files a third larger:
+// books too big are refused before anything is sent.
+
+use crate::settings::EmailSettings;
+use crate::transfer::Progress;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ring::rand::{SecureRandom, SystemRandom};
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long to wait for the server before giving up
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Bytes of the file per base64 line (57 bytes = 76 characters)
+const LINE_BYTES: usize = 57;
+
+/// Lines encoded at a time
+const LINES_PER_CHUNK: usize = 256;
+
+/// Room for the headers and the text part, on top of the attachment
+const ENVELOPE_SIZE: u64 = 2048;
+
+/// Size of a file once encoded as an attachment (base64 in lines of 76
+/// characters plus CRLF)
+pub fn encoded_size(size: u64) -> u64 {
+    size.div_ceil(LINE_BYTES as u64) * 78
+}
+
+/// Checks the settings and the size of a book before it's queued
+///
+/// # Arguments
+/// * `settings` - The [email] settings
+/// * `size` - Size of the book file in bytes
+///
+/// # Returns
+/// Why the book can't be sent, if it can't
+pub fn check(settings: &EmailSettings, size: u64) -> Result<(), String> {
+    if settings.host.is_none() {
+        return Err("set [email] host in config.toml first".to_string());
+    }
+    if settings.from.is_none() && settings.user.is_none() {
+        return Err("set [email] from in config.toml first".to_string());
+    }
+    let limit = settings.max_attachment_mb * 1024 * 1024;
+    let encoded = encoded_size(size);
+    if encoded > limit {
+        return Err(format!(
+            "{:.1} MB once encoded, over the {} MB limit",
+            encoded as f64 / 1_048_576.0,
+            settings.max_attachment_mb
+        ));
+    }
+    Ok(())
+}
+
+/// Mails a book as an attachment
+///
+/// # Arguments
+/// * `settings` - The [email] settings (server, login, sender)
+/// * `to` - Recipient address
+/// * `title` - Title of the book, used as the subject
+/// * `source` - The book file
+/// * `progress` - The transfer the upload is shown as
+pub fn send(settings: &EmailSettings, to: &str, title: &str, source: &Path, progress: &Progress) -> Result<(), String> {
+    let host = settings.host.as_deref().ok_or("no [email] host configured")?;
+    let from = settings
+        .from
+        .as_deref()
+        .or(settings.user.as_deref())
+        .ok_or("no [email] from address configured")?;
+    let file = File::open(source).map_err(|e| format!("{}: {}", source.display(), e))?;
+    let size = file.metadata().map_err(|e| e.to_string())?.len();
+    progress.set_total(size);
+
+    let mut session = Session::connect(host, settings.port, &settings.security)?;
+    if let (Some(user), Some(password)) = (&settings.user, &settings.password) {
+        session.login(user, password)?;
+    }
+
+    // Servers that tell their limit refuse bigger messages at MAIL FROM
+    let message_size = encoded_size(size) + ENVELOPE_SIZE;
+    if let Some(limit) = session.size_limit() {
+        if message_size > limit {
+            return Err(format!("too large for {} (limit {:.1} MB)", host, limit as f64 / 1_048_576.0));
+        }
+    }
+    let mail_from = match session.has_extension("SIZE") {
+        true => format!("MAIL FROM:<{}> SIZE={}", from, message_size),
+        false => format!("MAIL FROM:<{}>", from),
+    };
+    session.command(&mail_from, 2)?;
+    session.command(&format!("RCPT TO:<{}>", to), 2)?;
+    session.command("DATA", 3)?;
+
+    let boundary = format!("funkhunt-{}", random_hex()?);
+    let name = source.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let filename = crate::server::ascii_filename(&name);
+    let headers = format!(
+        "From: <{from}>\r\n\
+         To: <{to}>\r\n\
+         Subject: {subject}\r\n\
+         Date: {date}\r\n\
+         Message-ID: <{id}@funkhunt>\r\n\
+         MIME-Version: 1.0\r\n\
+         Content-Type: multipart/mixed; boundary=\"{boundary}\"\r\n\
+         \r\n\
+         --{boundary}\r\n\
+         Content-Type: text/plain; charset=utf-8\r\n\
+         Content-Transfer-Encoding: base64\r\n\
+         \r\n\
+         {text}\r\n\
+         --{boundary}\r\n\
+         Content-Type: application/epub+zip; name=\"{filename}\"\r\n\
+         Content-Disposition: attachment; filename=\"{filename}\"\r\n\
+         Content-Transfer-Encoding: base64\r\n\
+         \r\n",
+        subject = encode_header(title),
+        date = rfc2822(crate::book::unix_now()),
+        id = random_hex()?,
+        text = STANDARD.encode(format!("{}\r\n\r\nSent from FunkHunt\r\n", title)),
+    );
+    session.write(headers.as_bytes())?;
+    session.attach(progress.reader(file))?;
+    // Base64 never starts a line with '.', so no dot-stuffing is needed
+    session.write(format!("--{}--\r\n.\r\n", boundary).as_bytes())?;
+    session.reply(2)?;
+
+    let _ = session.command("QUIT", 2);
+    tracing::info!(to, book = %name, "book emailed");
+    Ok(())
+}
+
+/// The connection to the server, secured or not
+enum Stream {
+    /// Plain TCP (before STARTTLS, or with security = "none")
+    Plain(TcpStream),
+
+    /// TLS over TCP
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(tcp) => tcp.read(buf),
+            Stream::Tls(tls) => tls.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(tcp) => tcp.write(buf),
+            Stream::Tls(tls) => tls.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(tcp) => tcp.flush(),
+            Stream::Tls(tls) => tls.flush(),
+        }
+    }
+}
+
+/// An SMTP conversation
+struct Session {
+    /// The connection, buffered for reading replies line by line
+    stream: BufReader<Stream>,
+
+    /// Extensions the server announced in its EHLO reply ("SIZE 35882577",
+    /// "AUTH PLAIN LOGIN"...)
+    extensions: Vec<String>,
+}
+
+impl Session {
+    /// Connects and greets the server, switching to TLS as configured
+    ///
+    /// # Arguments
+    /// * `host` - Server name (checked against its certificate)
+    /// * `port` - Server port
+    /// * `security` - "starttls", "tls" or "none"
+    fn connect(host: &str, port: u16, security: &str) -> Result<Self, String> {
+        let address = (host, port)
+            .to_socket_addrs()
+            .map_err(|e| format!("{}: {}", host, e))?
+            .next()
+            .ok_or_else(|| format!("{}: no address", host))?;
+        let tcp = TcpStream::connect_timeout(&address, TIMEOUT).map_err(|e| format!("{}: {}", host, e))?;
+        tcp.set_read_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+        tcp.set_write_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+        let greeting = ehlo_name(&tcp);
+
+        let stream = match security.to_lowercase().as_str() {
+            "tls" => tls(host, tcp)?,
+            "starttls" | "none" => Stream::Plain(tcp),
+            other => return Err(format!("unknown [email] security \"{}\" (starttls, tls or none)", other)),
+        };
+        let mut session = Self {
+            stream: BufReader::new(stream),
+            extensions: Vec::new(),
+        };
+        session.reply(2)?;
+        session.hello(&greeting)?;
+
+        if security.eq_ignore_ascii_case("starttls") {
+            if !session.has_extension("STARTTLS") {
+                return Err(format!("{} doesn't offer STARTTLS (try security = \"tls\" on port 465)", host));
+            }
+            session.command("STARTTLS", 2)?;
+            // Nothing is pending after the reply, so the buffer can go
+            let Stream::Plain(tcp) = session.stream.into_inner() else {
+                return Err("already secured".to_string());
+            };
+            session.stream = BufReader::new(tls(host, tcp)?);
+            // What the server offers can change once secured
+            session.hello(&greeting)?;
+        }
+        Ok(session)
+    }
+
+    /// Sends EHLO and remembers the extensions the server offers
+    fn hello(&mut self, greeting: &str) -> Result<(), String> {
+        let lines = self.command(&format!("EHLO {}", greeting), 2)?;
+        // The first line is the server's name
+        self.extensions = lines.into_iter().skip(1).collect();
+        Ok(())
+    }
+
+    /// Whether the server announced an extension
+    fn has_extension(&self, name: &str) -> bool {
+        self.extension(name).is_some()
+    }
+
+    /// Parameters of an announced extension ("" if it has none)
+    fn extension(&self, name: &str) -> Option<&str> {
+        self.extensions.iter().find_map(|line| {
+            let (keyword, params) = line.split_once(' ').unwrap_or((line, ""));
+            keyword.eq_ignore_ascii_case(name).then_some(params)
+        })
+    }
+
+    /// Largest message the server takes, if it tells
+    fn size_limit(&self) -> Option<u64> {
+        self.extension("SIZE")
+            .and_then(|params| params.trim().parse().ok())
+            .filter(|&limit| limit > 0)
+    }
+
+    /// Logs in with AUTH PLAIN, or AUTH LOGIN for servers without it
+    fn login(&mut self, user: &str, password: &str) -> Result<(), String> {
+        let methods = self.extension("AUTH").ok_or("the server offers no login")?.to_uppercase();
+        if methods.split_whitespace().any(|m| m == "PLAIN") {
+            let token = STANDARD.encode(format!("\0{}\0{}", user, password));
+            self.command(&format!("AUTH PLAIN {}", token), 2)?;
+        } else if methods.split_whitespace().any(|m| m == "LOGIN") {
+            self.command("AUTH LOGIN", 3)?;
+            self.command(&STANDARD.encode(user), 3)?;
+            self.command(&STANDARD.encode(password), 2)?;
+        } else {
+            return Err(format!("no supported login method (server offers {})", methods));
+        }
+        Ok(())
+    }
+
+    /// Sends a command and reads the reply
+    ///
+    /// # Arguments
+    /// * `line` - The command, without CRLF
+    /// * `class` - First digit of the expected reply code (2 = done, 3 = go on)
+    ///
+    /// # Returns
+    /// The lines of the reply, without their codes
+    fn command(&mut self, line: &str, class: u16) -> Result<Vec<String>, String> {
+        self.write(format!("{}\r\n", line).as_bytes())?;
+        self.reply(class)
+    }
+
+    /// Reads a reply (its lines all start with the code, "250-" on all but
+    /// the last, "250 " on the last)
+    fn reply(&mut self, class: u16) -> Result<Vec<String>, String> {
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            self.stream.read_line(&mut line).map_err(|e| e.to_string())?;
+            let line = line.trim_end();
+            let code: u16 = line
+                .get(..3)
+                .and_then(|code| code.parse().ok())
+                .ok_or_else(|| format!("unexpected reply \"{}\"", line))?;
+            lines.push(line.get(4..).unwrap_or("").to_string());
+            if line.as_bytes().get(3) != Some(&b'-') {
+                if code / 100 != class {
+                    return Err(format!("server said {} {}", code, lines.join(" ")));
+                }
+                return Ok(lines);
+            }
+        }
+    }
+
+    /// Sends raw bytes
+    fn write(&mut self, data: &[u8]) -> Result<(), String> {
+        let stream = self.stream.get_mut();
+        stream.write_all(data).and_then(|()| stream.flush()).map_err(|e| e.to_string())
+    }
+
+    /// Sends a file as base64, in lines of 76 characters
+    fn attach(&mut self, mut file: impl Read) -> Result<(), String> {
+        let mut chunk = vec![0u8; LINE_BYTES * LINES_PER_CHUNK];
+        loop {
+            let filled = fill(&mut file, &mut chunk).map_err(|e| e.to_string())?;
+            if filled == 0 {
+                return Ok(());
+            }
+            let mut encoded = String::with_capacity(filled / 3 * 4 + LINES_PER_CHUNK * 6);
+            for line in chunk[..filled].chunks(LINE_BYTES) {
+                STANDARD.encode_string(line, &mut encoded);
+                encoded.push_str("\r\n");
+            }
+            self.write(encoded.as_bytes())?;
+        }
+    }
+}
+
+/// Reads until the buffer is full or the file ends
+///
+/// # Returns
+/// How many bytes were read
+fn fill(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Wraps a connection in TLS, checking the server's certificate
+fn tls(host: &str, tcp: TcpStream) -> Result<Stream, String> {
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let config = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let name = ServerName::try_from(host.to_string()).map_err(|e| format!("{}: {}", host, e))?;
+    let connection = ClientConnection::new(Arc::new(config), name).map_err(|e| e.to_string())?;
+    Ok(Stream::Tls(Box::new(StreamOwned::new(connection, tcp))))
+}
+
+/// How we introduce ourselves: our address, as an address literal
+fn ehlo_name(tcp: &TcpStream) -> String {
+    match tcp.local_addr().map(|a| a.ip()) {
+        Ok(IpAddr::V4(ip)) => format!("[{}]", ip),
+        Ok(IpAddr::V6(ip)) => format!("[IPv6:{}]", ip),
+        Err(_) => "localhost".to_string(),
+    }
+}
+
+/// Writes a header value as an RFC 2047 encoded word when it isn't plain ASCII
+fn encode_header(text: &str) -> String {
+    let text: String = text.chars().filter(|c| !c.is_control()).collect();
+    if text.is_ascii() {
+        return text;
+    }
+    format!("=?UTF-8?B?{}?=", STANDARD.encode(text))
+}
+
+/// Formats a time as an RFC 2822 date, e.g. "Thu, 15 Oct 2026 09:30:00 +0000"
+fn rfc2822(secs: u64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    // "2026-10-15T09:30:00Z"
+    let stamp = crate::opds::rfc3339(secs);
+    let month: usize = stamp[5..7].parse().unwrap_or(1);
+    format!(
+        "{}, {} {} {} {} +0000",
+        WEEKDAYS[((secs / 86_400) % 7) as usize],
+        &stamp[8..10],
+        MONTHS[month - 1],
+        &stamp[..4],
+        &stamp[11..19]
+    )
+}
+
+/// Random hex string, for message ids and MIME boundaries
+fn random_hex() -> Result<String, String> {
+    let mut bytes = [0u8; 12];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| "no randomness for the message id".to_string())?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
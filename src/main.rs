@@ -2,93 +2,2983 @@
 // Entry point of the FunkHunt application - a TUI for managing EPUB book collections
 
 // Module declarations - these tell Rust about the other files in our project
+mod api;       // JSON REST API (serve mode)
+mod audio;     // Narration played with mpv
+mod authors;   // Author name normalization
+mod bench;     // Scanner benchmark (bench)
 mod book;      // Book data model
+mod calibre;   // Calibre content server client
 mod config;    // CLI argument parsing
+mod convert;   // Format conversion (ebook-convert)
+mod cover;     // New covers from a file, a URL or Open Library
+mod daemon;    // Headless mode (no TUI)
+mod database;  // Library persistence
+mod device;    // E-readers over USB (Kindle, Kobo)
+mod dictionary; // Local dictionaries (dictd, StarDict)
+mod discovery; // LAN peer discovery (mDNS)
+mod duplicates; // Suspected duplicates and their comparison
+mod epub;      // EPUB metadata parsing
+mod exit;      // Exit codes and errors of the commands
+mod export;    // JSON library export
+mod extract;   // Text of books as plain text or Markdown
+mod feeds;     // RSS/Atom feeds of new books
+mod filter;    // Filter expressions
+mod fold;      // Case- and accent-insensitive text for searches
+mod fonts;     // Fonts embedded in EPUBs, stripped or subset
+mod fulltext;  // Full-text index of the books' text
+mod graphics;  // Pictures in the terminal (kitty, iTerm2)
+mod hash;      // Content hashing
+mod health;    // Library health check
+mod import;    // Importers (Calibre, Goodreads...)
+mod inbox;     // Auto-import from an inbox folder
+mod journal;   // Undo/redo of library operations
+mod language;  // Language of a book guessed from its text
+mod listing;   // Library listed for scripts (list)
+mod logging;   // Log file + in-app log buffer
+mod mail;      // Sending books by email (SMTP)
+mod merge;     // Merging another library into this one
+mod metrics;   // Prometheus metrics (serve mode)
+mod notes;     // Highlights exported as Markdown/Org notes
+mod omnibus;   // Omnibus EPUBs split, EPUBs merged into one
+mod opds;      // OPDS catalog feeds
+mod optimize;  // Making oversized EPUBs smaller
+mod organize;  // Moving files into a folder template
+mod parse_queue; // Metadata read in the background (TUI scans)
+mod peer;      // Peer protocol (catalog server + client)
+mod paths;     // Platform directories (XDG...)
+mod profile;   // Named library profiles
+#[cfg(feature = "profiling")]
+mod profiling; // Time spent per span, as folded stacks (--profile)
+mod providers; // Online metadata lookup
+mod qr;        // QR code encoder (terminal rendering)
+mod query;     // Index of the books for filters and orders
+mod quotes;    // Passages captured from books
+mod readability; // How hard books read (Flesch-Kincaid)
+mod reader;    // Text of EPUBs for the built-in reader
+mod remote;    // Remote scan roots (SFTP, WebDAV, S3)
 mod scanner;   // EPUB file scanning
+mod server;    // HTTP server (OPDS + downloads)
+mod session;   // Session state + autosave
+mod settings;  // User configuration file (config.toml)
+mod share;     // Temporary one-book HTTP share (QR code)
+mod sidecar;   // Per-book user data files next to the EPUBs
+mod sort;      // Book list orders
+mod stats;     // Library statistics (stats, statistics screen)
+mod sync;      // Two-way library sync
+mod transfer;  // Download/upload queue with progress
+mod trust;     // Peer pairing, keys and encrypted transfers
 mod tui;       // Terminal User Interface components
+mod userdata;  // Portable user-data export/import
+mod watcher;   // Auto-watch of scan roots
+mod webhooks;  // Library events POSTed to webhook URLs
 
 // Import items from our modules that we'll use in main()
-use crate::config::{show_usage, Config};
-use crate::tui::{handle_key_event, init, render, restore, AppAction, TuiState};
-use crossterm::event::{self, Event};
+use crate::book::Book;
+use crate::config::{show_command_usage, show_usage, Command, Config, ExportKind, ImportKind};
+use crate::discovery::Discovery;
+use crate::exit::Failure;
+use crate::peer::PeerServer;
+use crate::profile::Profile;
+use crate::session::{Autosave, Session};
+use crate::settings::{Settings, SettingsWatcher};
+use crate::tui::{
+    handle_key_event, handle_paste, init, picture_placements, render, restore, AppAction, AppEvent, CalibreBrowser, FeedBrowser, LibraryMerge, Lookup, MetadataReview, OtherLibrary, PeerBrowser, PeerReply,
+    ReadReason, Scan, ScanReason, SyncSent, TextSearch, TuiState, UiMode,
+};
+use crate::watcher::FolderWatcher;
+use ratatui::layout::Rect;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 /// Main function - the entry point of the application
-/// Returns Result<(), std::io::Error> because terminal operations can fail
-fn main() -> std::io::Result<()> {
+///
+/// Runs the command, then exits with the code of what went wrong, if
+/// anything (see exit.rs)
+fn main() {
     // Parse command-line arguments into a Config struct
-    let mut config = Config::from_args();
+    let config = Config::from_args();
+    let errors = config.errors;
+    let result = run(config);
+    // The profile, if one was asked for, is written whatever happened
+    logging::finish();
+    if let Err(failure) = result {
+        failure.report(errors);
+        std::process::exit(failure.code());
+    }
+}
+
+/// Runs the command of the command line
+/// Returns Result<(), Failure> because terminal operations (and everything else) can fail
+fn run(config: Config) -> Result<(), Failure> {
+    // A mistake on the command line: say what it is and how to ask for help
+    if let Some(error) = &config.error {
+        return Err(Failure::Usage(error.clone(), config.command));
+    }
 
-    // If user passed --help or -h, show usage and exit early
+    // If user passed --help or -h (or `help COMMAND`), show usage and exit early
     if config.show_help {
-        show_usage();
+        match config.command {
+            Command::Tui => show_usage(),
+            command => show_command_usage(command),
+        }
         return Ok(()); // Ok(()) means success with no value
     }
 
-    // Scan all provided paths for EPUB files
-    let books = config.scan_all_paths();
+    // Start logging to the log file (and the in-app log buffer)
+    let log_buffer = logging::init(&config.log_level, config.profile.as_deref()).map_err(|e| Failure::Usage(e.to_string(), config.command))?;
 
-    // Convert PathBuf objects to String for display in the UI
-    // .iter() creates an iterator, .map() transforms each element, .collect() gathers results
-    let scan_paths: Vec<String> = config
-        .scan_paths
-        .iter()
-        .map(|p| p.display().to_string())
-        .collect();
+    // Paths that aren't there are told before anything is done
+    if let Some(missing) = config.input_paths().into_iter().find(|path| !path.exists()) {
+        return Err(Failure::InvalidPath(missing.to_path_buf()));
+    }
+
+    // Commands working on book files, no library needed
+    match config.command {
+        Command::Extract => return Ok(extract_text(&config.files[0], config.extract_format.unwrap_or_default())?),
+        Command::Optimize => return Ok(optimize_book(&config.files[0])?),
+        Command::Fonts => return Ok(book_fonts(&config.files[0], config.fonts_action)?),
+        Command::Split => return Ok(split_omnibus(&config.files[0])?),
+        Command::Join => return Ok(join_anthology(&config.files[0], &config.files[1..], config.join_title.as_deref())?),
+        Command::Bench => return run_bench(&config.files[0], &config.bench_threads, config.bench_runs),
+        _ => {}
+    }
+
+    // Move data from pre-XDG locations before anything reads it
+    paths::migrate_legacy_dirs();
+
+    // Open the requested library profile (created on first use)
+    let mut profile = Profile::open(&config.library)?;
+
+    // Paths given on the command line become the profile's scan roots
+    if !config.scan_paths.is_empty() {
+        profile.settings.scan_paths = config.scan_paths.clone();
+        profile.save_settings()?;
+    }
+
+    // Load config.toml (or --config FILE) - a broken file falls back to defaults and reports why
+    let settings_path = config.settings_path.clone().unwrap_or_else(Settings::default_path);
+    let mut settings_watcher = SettingsWatcher::new(settings_path);
+    let (settings, settings_error) = match Settings::load(settings_watcher.path()) {
+        Ok(settings) => (settings, None),
+        Err(e) => (Settings::default(), Some(format!("Config error: {}", e))),
+    };
+    // Logins for remote scan roots - needed before the first scan
+    remote::configure(&settings.remote);
+    fold::configure(&settings.search);
+    sort::configure(&settings.sort);
+
+    // Scan: rescan the folders into the saved library, keeping its user data
+    if config.command == Command::Scan {
+        return scan_library(&profile);
+    }
+
+    // Listing of given folders or files: scanned as they are, the library left alone
+    if config.command == Command::List && !config.files.is_empty() {
+        return list_books(&scan_given(&config.files), &config);
+    }
+    if config.command == Command::Dedupe && !config.files.is_empty() {
+        return dedupe_given(&profile, &config.files, config.dedupe_apply);
+    }
+
+    // Load the library: rescan when new paths were given, otherwise use the database
+    // (the TUI reads the packages of the books scanned in the background)
+    let mut books = load_library(&profile, !config.scan_paths.is_empty(), config.command == Command::Tui)?;
+
+    // Commands working on the library, without the TUI
+    match config.command {
+        Command::Import => return Ok(import_into(&profile, &mut books, &config)?),
+        Command::Export => return Ok(export_library(&profile, &mut books, &config)?),
+        Command::Convert => {
+            let format = config.convert_to.unwrap_or(convert::Format::Epub);
+            return Ok(convert_file(&profile, &settings, &mut books, &config.files[0], format)?);
+        }
+        Command::List => return list_books(&books, &config),
+        Command::Dedupe => {
+            // The books of deleted files go (their user data went to the copies
+            // kept), the hashes computed are kept
+            let deleted = dedupe(&mut books, config.dedupe_apply)?;
+            books.retain(|book| !deleted.iter().any(|(copy, _)| *copy == book.path));
+            return Ok(database::save(&profile.database_path(), &books)?);
+        }
+        Command::Stats => return print_stats(&books, config.stats_json),
+
+        // Server mode: serve the library over HTTP instead of starting the TUI
+        Command::Serve => {
+            let webhooks = webhooks::Webhooks::new(&settings.webhooks, &profile.name, &books);
+            return server::serve(config.port.unwrap_or(server::DEFAULT_PORT), &profile, books, webhooks).map_err(Failure::Server);
+        }
+        _ => {}
+    }
 
     // Initialize application state with found books and scanned paths
-    let mut state = TuiState::new(books, scan_paths);
+    let mut state = TuiState::new(books, display_paths(&profile), profile.name.clone(), settings);
+    state.status_message = settings_error;
+    state.log = log_buffer;
+    state.folder_screen.folders = profile.settings.folder_list();
+    state.collections = profile.settings.collections.clone();
+    state.smart_collections = profile.settings.smart_collections.clone();
+    restore_session(&mut state, &profile);
+
+    // Daemon mode: watcher, servers, peers and sync without the TUI
+    if config.command == Command::Daemon {
+        return daemon::run(profile, state, settings_watcher, config.port.unwrap_or(server::DEFAULT_PORT)).map_err(Failure::Server);
+    }
+
+    // Books scanned without their metadata: read in the background, those shown first
+    state.parsing.request(&state.books);
+
+    // Merge mode: show the other library's changes for review
+    if let Some(source) = &config.merge {
+        open_library_merge(&mut state, source)?;
+    }
+
+    // Sync mode: sync with a library file (conflicts, if any, are shown for review)
+    if let Some(target) = &config.sync {
+        start_sync(&mut state, sync::Partner::File(target.clone()));
+    }
+
+    // Save periodically, so a crash or lost SSH connection doesn't lose the session's edits
+    let mut autosave = Autosave::new(state.settings.autosave.interval);
+
+    // Watch the scan roots that have auto-watch enabled
+    let mut folder_watcher = folder_watcher_for(&profile, &state.settings);
+
+    // Tell the configured webhooks about books added, removed, finished or received
+    let mut webhooks = webhooks::Webhooks::new(&state.settings.webhooks, &profile.name, &state.books);
+
+    // Index the text of the books in the background, for text searches
+    let mut fulltext = fulltext::FullText::open(profile.fulltext_path(), &state.books);
+
+    // Announce this instance on the local network and answer peers, if enabled
+    state.peers = discovery::load_known();
+    state.trusted = trust::load_trusted();
+    state.typography = reader::Typography::load();
+    state.graphics = state.settings.reader.images.protocol();
+    let mut pictures = graphics::Pictures::new(state.graphics);
+    let mut discovery = start_discovery(&profile, &state);
+    let mut peer_server = start_peer_server(&profile, &state);
 
     // Initialize terminal in TUI mode (raw mode + alternate screen)
     // The ? operator propagates errors up if init() fails
-    let mut terminal = init()?;
+    // The guard restores the terminal if we leave main() early (errors, panics)
+    let (mut terminal, _terminal_guard) = init()?;
+
+    // Keys, resizes, pastes and ticks come down the same channel as the
+    // results of background work
+    state.workers.read_input();
+
+    // Watchers, peers, transfers and autosave are checked on the first pass,
+    // then once per tick (see worker::TICK)
+    let mut ticked = true;
+
+    // The screen is drawn again only when something changed: an event was
+    // handled, or a tick changed what's shown (or moves it, see animating)
+    let mut redraw = true;
 
     // Main event loop - runs until user quits (presses 'q')
     while !state.should_quit {
-        // Draw the interface
-        // terminal.draw() takes a closure that receives a Frame to draw on
-        terminal.draw(|frame| {
-            render(frame, &state); // &state = immutable borrow, we only read state here
-        })?;
-
-        // Poll for keyboard events with a 100ms timeout
-        // This prevents the loop from blocking forever and allows periodic redraws
-        if event::poll(std::time::Duration::from_millis(100))? {
-            // Check if the event is a keyboard event
-            if let Event::Key(key) = event::read()? {
+        // What a tick may change on the screen, compared after it
+        let shown = (state.mode, state.status_message.clone());
+
+        if ticked {
+            ticked = false;
+            redraw |= state.animating();
+
+            // Apply config.toml edits live (theme, keys, viewer)
+            if settings_watcher.changed() {
+                state.status_message = Some(match Settings::load(settings_watcher.path()) {
+                    Ok(settings) => {
+                        tracing::info!(path = %settings_watcher.path().display(), "config reloaded");
+                        let peers_changed = settings.peers != state.settings.peers;
+                        remote::configure(&settings.remote);
+                        fold::configure(&settings.search);
+                        sort::configure(&settings.sort);
+                        // Titles, authors and tags are folded anew for the filters,
+                        // and keyed anew for the orders
+                        if settings.search.transliterate != state.settings.search.transliterate
+                            || settings.sort != state.settings.sort
+                        {
+                            state.index.clear();
+                            state.refresh_view();
+                        }
+                        state.settings = settings;
+                        autosave.set_interval(state.settings.autosave.interval);
+                        webhooks.configure(&state.settings.webhooks);
+                        // Books opened from now on show pictures the new way
+                        state.graphics = state.settings.reader.images.protocol();
+                        pictures.set_protocol(state.graphics);
+                        // The theme may have changed too
+                        redraw = true;
+                        // The inbox folder may have changed
+                        folder_watcher = folder_watcher_for(&profile, &state.settings);
+                        if peers_changed {
+                            // Say goodbye under the old settings before announcing again
+                            drop(discovery.take());
+                            drop(peer_server.take());
+                            discovery = start_discovery(&profile, &state);
+                            peer_server = start_peer_server(&profile, &state);
+                        }
+                        "Config reloaded".to_string()
+                    }
+                    // Keep the previous settings when the new file doesn't parse
+                    Err(e) => {
+                        tracing::warn!(error = %e, "config reload failed");
+                        format!("Config error: {}", e)
+                    }
+                });
+            }
+
+            // Rescan watched folders whose files changed (once they've settled)
+            let mut changed = Vec::new();
+            for root in folder_watcher.ready_roots() {
+                if state.settings.inbox.folder.as_ref() == Some(&root) {
+                    import_inbox(&profile, &mut state);
+                } else {
+                    changed.push(root);
+                }
+            }
+            if !changed.is_empty() {
+                scan_in_background(&profile, &mut state, changed, ScanReason::Watched);
+            }
+
+            // Peers that appeared or left, and our own book count
+            if let Some(discovery) = discovery.as_mut() {
+                if discovery.poll() {
+                    state.peers = discovery.peers();
+                    redraw = true;
+                    if let Err(e) = discovery::save_known(&state.peers) {
+                        tracing::warn!(error = %e, "cannot remember peers");
+                    }
+                }
+                if state.books.len() != discovery.book_count() {
+                    discovery.set_book_count(state.books.len());
+                    if let Some(server) = &peer_server {
+                        server.publish(&state.books);
+                    }
+                }
+            }
+
+            // Books offered by peers - asked about once the book list is idle
+            if let Some(server) = &peer_server {
+                state.incoming_offers.extend(server.take_offers());
+            }
+
+            // Peers asking to pair - the PIN is shown once the book list is idle
+            if let Some(server) = &peer_server {
+                for pairing in server.take_pairings() {
+                    peer::add_pairing(&mut state.pairings, pairing);
+                }
+            }
+
+            // Paired peers asking for a book they need an approval for
+            if let Some(server) = &peer_server {
+                state.access_requests.extend(server.take_access_requests());
+            }
+
+            // User data settled by peers that synced with us
+            if let Some(server) = &peer_server {
+                for push in server.take_sync_pushes() {
+                    let from = push.from.clone();
+                    let updated = state.apply_sync_push(push);
+                    redraw = true;
+                    tracing::info!(from = %from, updated, "sync push applied");
+                    state.status_message = Some(format!("{} synced with this library: {} books updated", from, updated));
+                }
+            }
+
+            // Downloads that ended: add their books to the library (or open them)
+            for finished in state.transfers.poll() {
+                finish_transfer(&profile, &mut state, &mut webhooks, finished);
+            }
+
+            // Books added, removed or finished since the last tick
+            webhooks.check(&state.books);
+            fulltext.check(&state.books);
+
+            // A shared book's time is up: stop its server
+            if state.share.as_ref().is_some_and(|share| share.expired()) {
+                if let Some(share) = state.share.take() {
+                    state.status_message = Some(format!("Stopped sharing {}", share.title));
+                }
+                if state.mode == UiMode::Sharing {
+                    state.mode = UiMode::Normal;
+                }
+            }
+
+            // Periodic autosave
+            if autosave.due() {
+                autosave_now(&profile, &mut state);
+                autosave.saved();
+                webhooks.compare(&state.books);
+                // Books whose files were edited are indexed again
+                fulltext.update(&state.books);
+                // Peers see the edits of the last minutes too
+                if let Some(server) = &peer_server {
+                    server.publish(&state.books);
+                }
+            }
+
+            // Where the narration playing is, kept as its book's listening position
+            state.poll_listening();
+        }
+
+        // Titles, authors and series edited: into the EPUBs of the folders that want them
+        if !state.metadata_edits.is_empty() {
+            write_metadata_edits(&profile, &mut state);
+        }
+
+        if state.mode == UiMode::Normal && !state.pairings.is_empty() {
+            state.mode = UiMode::Pairing;
+        } else if state.mode == UiMode::Normal && !state.access_requests.is_empty() {
+            state.mode = UiMode::ApprovingAccess;
+        } else if state.mode == UiMode::Normal && !state.incoming_offers.is_empty() {
+            state.mode = UiMode::ReviewingOffer;
+        }
+
+        redraw |= shown != (state.mode, state.status_message.clone());
+
+        // The books read in the background since the last pass, filtered and sorted once
+        state.update_view();
+
+        // The reader lays its text out for the size of the terminal
+        if let Some(reader) = state.reader.as_mut() {
+            let size = terminal.size()?;
+            reader.fit(size.width, size.height, state.typography);
+        }
+
+        // The first pages of the selected book, when the details pane shows them
+        state.load_preview();
+
+        // The books on the screen are read first, when they're placeholders
+        state.prioritize_parsing(terminal.size()?.height);
+
+        if redraw {
+            redraw = false;
+            // Drawing, the terminal's output included (--profile)
+            #[cfg(feature = "profiling")]
+            let _draw = tracing::trace_span!("draw").entered();
+
+            // Pictures of the reader's page: when they change, the screen is
+            // drawn anew (ratatui doesn't know what they covered)
+            let screen = terminal.size()?;
+            let placements = picture_placements(&state, screen);
+            if pictures.changed(&placements, screen) {
+                pictures.clear(terminal.backend_mut())?;
+                terminal.clear()?;
+            }
+
+            // Draw the interface
+            // terminal.draw() takes a closure that receives a Frame to draw on
+            terminal.draw(|frame| {
+                render(frame, &state); // &state = immutable borrow, we only read state here
+            })?;
+            if let Some(reader) = &state.reader {
+                pictures.show(terminal.backend_mut(), &reader.path, &placements, screen)?;
+            }
+        }
+
+        // Wait for the terminal's events, ticks and the results of background work
+        let mut parsed = Vec::new();
+        for app_event in state.workers.wait() {
+            // Anything but a tick or a book read changes the screen
+            redraw |= !matches!(app_event, AppEvent::Tick | AppEvent::Parsed(_));
+            let action = match app_event {
                 // Process the key press and get back an optional action
                 // &mut state = mutable borrow, handle_key_event can modify state
-                if let Some(action) = handle_key_event(key, &mut state) {
-                    // If an action was returned, execute it
-                    match action {
-                        // User selected a folder to load
-                        AppAction::AddFolder(path) => {
-                            // Scan the selected path for EPUB files
-                            let new_books = scanner::scan_epubs(&path);
-
-                            // Only update if we found at least one book
-                            if !new_books.is_empty() {
-                                // REPLACE books collection (not add to it)
-                                state.books = new_books;
+                AppEvent::Key(key) => handle_key_event(key, &mut state),
+                // A pasted folder is added, pasted text typed into prompts
+                AppEvent::Paste(text) => handle_paste(&text, &mut state),
+                // Everything is laid out again for the new size (the reader's
+                // text too, before the next frame)
+                AppEvent::Resize(columns, rows) => {
+                    terminal.resize(Rect::new(0, 0, columns, rows))?;
+                    continue;
+                }
+                AppEvent::Tick => {
+                    ticked = true;
+                    state.ticks += 1;
+                    continue;
+                }
+                // Placeholders are filled in together, once per pass
+                AppEvent::Parsed(book) => {
+                    parsed.push(*book);
+                    continue;
+                }
+                AppEvent::Scanned(scan) => {
+                    if finish_scan(&mut profile, &mut state, scan) {
+                        folder_watcher = folder_watcher_for(&profile, &state.settings);
+                    }
+                    continue;
+                }
+                // Another library to merge or sync with was read
+                AppEvent::LibraryRead(other) => {
+                    compare_library(&mut profile, &mut state, *other, peer_server.as_ref());
+                    continue;
+                }
+                AppEvent::SyncSent(sync) => {
+                    synced(&mut profile, &mut state, *sync);
+                    continue;
+                }
+                other => {
+                    show_results(&profile, &mut state, other);
+                    continue;
+                }
+            };
+            if let Some(action) = action {
+                // If an action was returned, execute it
+                match action {
+                    // User selected a folder to load: scanned on a worker
+                    // thread, its books replace the library's (finish_scan)
+                    AppAction::AddFolder(path) => {
+                        state.status_message = Some(format!("Scanning {}...", path.display()));
+                        scan_in_background(&profile, &mut state, vec![path], ScanReason::NewFolder);
+                    }
 
-                                // REPLACE scan paths with just the new one
-                                state.scan_paths = vec![path.display().to_string()];
+                    // User picked another library profile
+                    AppAction::SwitchProfile(name) => {
+                        // Persist the library we're leaving
+                        save_profile(&profile, &state.books);
+                        save_session(&profile, &state);
 
-                                // REPLACE config paths
-                                config.scan_paths = vec![path.clone()];
+                        // Open the new profile; stay on the current one if that fails
+                        match Profile::open(&name) {
+                            Ok(new_profile) => {
+                                // A database we can't read (e.g. from a newer version) keeps us here
+                                let new_books = match load_library(&new_profile, false, true) {
+                                    Ok(new_books) => new_books,
+                                    Err(e) => {
+                                        tracing::error!(library = %name, error = %e, "cannot load library");
+                                        state.status_message = Some(format!("Cannot open library '{}': {}", name, e));
+                                        continue;
+                                    }
+                                };
+                                profile = new_profile;
 
-                                // Reset selection to first book
+                                state.books = new_books;
+                                state.index.clear();
+                                state.parsing.clear();
+                                state.parsing.request(&state.books);
+                                state.scan_paths = display_paths(&profile);
+                                state.profile_name = profile.name.clone();
                                 state.selected_index = 0;
+                                state.collections = profile.settings.collections.clone();
+                                state.smart_collections = profile.settings.smart_collections.clone();
+                                state.refresh_view();
+                                state.folder_screen.folders = profile.settings.folder_list();
+                                folder_watcher = folder_watcher_for(&profile, &state.settings);
+                                // The books of the other library weren't added
+                                webhooks = webhooks::Webhooks::new(&state.settings.webhooks, &profile.name, &state.books);
+                                fulltext = fulltext::FullText::open(profile.fulltext_path(), &state.books);
+                                state.text_search = None;
+                                restore_session(&mut state, &profile);
+                                // Peers see the library we have open
+                                if discovery.is_some() {
+                                    drop(discovery.take());
+                                    drop(peer_server.take());
+                                    discovery = start_discovery(&profile, &state);
+                                    peer_server = start_peer_server(&profile, &state);
+                                }
+                                tracing::info!(library = %profile.name, books = state.books.len(), "switched library");
                             }
+                            Err(e) => {
+                                tracing::error!(library = %name, error = %e, "cannot open library");
+                                state.status_message = Some(format!("Cannot open library '{}': {}", name, e));
+                            }
+                        }
+                    }
+
+                    // User changed the settings of the scan roots
+                    AppAction::SaveFolderSettings(folders) => {
+                        for (root, settings) in folders {
+                            // Don't store entries that are all defaults
+                            if settings == Default::default() {
+                                profile.settings.folders.remove(&root);
+                            } else {
+                                profile.settings.folders.insert(root, settings);
+                            }
+                        }
+
+                        // Rescan so new excludes and default tags take effect
+                        save_profile(&profile, &state.books);
+                        scan_in_background(&profile, &mut state, profile.settings.scan_paths.clone(), ScanReason::FolderSettings);
+
+                        folder_watcher = folder_watcher_for(&profile, &state.settings);
+                        state.folder_screen.folders = profile.settings.folder_list();
+                        state.status_message = Some("Folder settings saved".to_string());
+                    }
+
+                    // A book of a remote folder was opened: downloaded first,
+                    // opened once the transfer is done
+                    AppAction::OpenRemote(path) => {
+                        state.status_message = Some(format!("Downloading {}...", path.display()));
+                        state.transfers.enqueue(transfer::Job::RemoteBook { path });
+                    }
+
+                    // Look the selected book up online, on a worker thread (the
+                    // review opens when the answer arrives)
+                    AppAction::FetchMetadata => fetch_metadata(&mut state),
+
+                    // Files were moved or an operation undone - the library
+                    // must not point to the old paths
+                    AppAction::SaveNow => autosave_now(&profile, &mut state),
+
+                    // Search the folders for files with the content of missing
+                    // books - hashing them on a worker thread
+                    AppAction::FindMovedBooks => {
+                        let books = state.books.clone();
+                        let roots = profile.settings.scan_paths.clone();
+                        state.workers.spawn(move || AppEvent::MovesFound(health::find_moved(&books, &roots)));
+                        state.status_message = Some("Looking for the moved books...".to_string());
+                    }
+
+                    // Rescan every folder, then check the files again
+                    AppAction::RescanLibrary => {
+                        state.status_message = Some("Rescanning...".to_string());
+                        scan_in_background(&profile, &mut state, profile.settings.scan_paths.clone(), ScanReason::Health);
+                    }
+
+                    // Author spellings were merged
+                    AppAction::SaveAuthorAliases(aliases) => {
+                        profile.settings.author_aliases.extend(aliases);
+                        save_profile(&profile, &state.books);
+                        state.dirty = false;
+                    }
+
+                    // Peers screen: show what a peer shares
+                    AppAction::BrowsePeer(peer) => browse_peer(&mut state, &peer),
+
+                    // Browsing a peer: download its books into the library
+                    AppAction::DownloadFromPeer(entries) => download_from_peer(&profile, &mut state, entries),
+
+                    // Peers screen: offer books to a peer
+                    AppAction::SendToPeer(peer) => send_to_peer(&mut state, &peer, peer_server.as_ref()),
+
+                    // A peer's offer was accepted: queue the downloads
+                    AppAction::AcceptOffer(incoming) => {
+                        let queued = accept_offer(&profile, &mut state, &incoming, &[]);
+                        if queued > 0 {
+                            state.status_message =
+                                Some(format!("Downloading {} books from {}", queued, incoming.offer.from));
+                        }
+                    }
+
+                    // Peers screen: sync both ways with a peer
+                    AppAction::SyncWithPeer(peer) => match peer.address {
+                        Some(address) => {
+                            let partner = sync::Partner::Peer {
+                                id: peer.id.clone(),
+                                name: peer.name.clone(),
+                                address,
+                                secret: secret_of(&state, &peer),
+                            };
+                            start_sync(&mut state, partner);
+                        }
+                        None => state.status_message = Some(format!("{} has no known address", peer.name)),
+                    },
+
+                    // Share the selected book with a phone
+                    AppAction::ShareBook => share_book(&mut state),
+
+                    // Copy books to an e-reader
+                    AppAction::SendToDevice(device) => send_to_device(&mut state, &device),
+
+                    // Email books (e.g. to a Send-to-Kindle address)
+                    AppAction::EmailBooks(to) => email_books(&mut state, &to),
+
+                    // Convert books to another format (next to them)
+                    AppAction::ConvertBooks(format) => convert_books(&mut state, format),
+
+                    // Make books smaller
+                    AppAction::OptimizeBooks => optimize_books(&profile, &mut state),
+
+                    // Embed a new cover into the selected book
+                    AppAction::SetCover(source) => set_cover(&profile, &mut state, &source),
+
+                    // Strip or subset the fonts of a book
+                    AppAction::SlimFonts(action) => slim_fonts(&profile, &mut state, action),
+
+                    // Play the narration of the selected book
+                    AppAction::Listen => listen(&mut state),
+
+                    // Omnibus split into its parts, books merged into an anthology
+                    AppAction::SplitBook => split_book(&profile, &mut state),
+                    AppAction::JoinBooks(title) => join_books(&profile, &mut state, &title),
+
+                    // Text search: look the words up in the full-text index
+                    AppAction::SearchText(query) => search_text(&fulltext, &mut state, query),
+
+                    // Calibre content server: list, download, add
+                    AppAction::BrowseCalibre => browse_calibre(&mut state),
+                    AppAction::DownloadFromCalibre(books) => download_from_calibre(&profile, &mut state, books),
+                    AppAction::PushToCalibre => push_to_calibre(&mut state),
+
+                    // Feeds: fetch, download
+                    AppAction::BrowseFeeds => browse_feeds(&mut state),
+                    AppAction::DownloadFromFeeds(entries) => download_from_feeds(&profile, &mut state, entries),
+
+                    // Sync conflicts were settled: do the partner's side
+                    AppAction::FinishSync(pending) => {
+                        finish_sync(&mut profile, &mut state, pending, peer_server.as_ref())
+                    }
+
+                    // Peers screen: ask a peer to pair, then compare the PINs
+                    AppAction::PairWithPeer(peer) => pair_with_peer(&mut state, &peer),
+
+                    // The PINs of a pairing matched: trust the peer from now on
+                    AppAction::TrustPeer(pairing) => {
+                        // Pairing again keeps what the peer was given
+                        let mut trusted = pairing.trusted();
+                        if let Some(before) = state.trusted.iter().find(|t| t.id == pairing.peer_id) {
+                            trusted.policy = before.policy.clone();
+                        }
+                        state.trusted.retain(|trusted| trusted.id != pairing.peer_id);
+                        state.trusted.push(trusted);
+                        save_trusted(&state, peer_server.as_ref());
+                        tracing::info!(peer = %pairing.name, "peer paired");
+                        state.status_message = Some(format!("Paired with {}", pairing.name));
+                    }
+
+                    // Peers screen: stop trusting a peer
+                    AppAction::RevokePeer(id) => {
+                        state.trusted.retain(|trusted| trusted.id != id);
+                        save_trusted(&state, peer_server.as_ref());
+                        tracing::info!(peer = %id, "peer unpaired");
+                        state.status_message = Some("Peer unpaired - it has to pair again".to_string());
+                    }
+
+                    // Peers screen: choose what a paired peer gets
+                    AppAction::SetSharePolicy(id, policy) => {
+                        if let Some(trusted) = state.trusted.iter_mut().find(|trusted| trusted.id == id) {
+                            trusted.policy = policy;
+                            let message = format!("{} now gets {}", trusted.name, trusted.policy.label());
+                            save_trusted(&state, peer_server.as_ref());
+                            tracing::info!(peer = %id, "share policy changed");
+                            state.status_message = Some(message);
                         }
                     }
+
+                    // A peer asked for a book: tell the server the answer
+                    AppAction::AnswerAccess(request, approved) => {
+                        if let Some(server) = &peer_server {
+                            server.answer_access(&request, approved);
+                        }
+                        tracing::info!(peer = %request.peer, book = %request.path.display(), approved, "download request answered");
+                        state.status_message = Some(if approved {
+                            format!("{} may download {}", request.peer, request.title)
+                        } else {
+                            format!("Refused {} to {}", request.title, request.peer)
+                        });
+                    }
+
+                    // Peers screen: forget a remembered peer
+                    AppAction::ForgetPeer(id) => {
+                        if let Some(discovery) = discovery.as_mut() {
+                            discovery.forget(&id);
+                        }
+                        state.peers.retain(|peer| peer.id != id);
+                        state.peers_screen.selected_index =
+                            state.peers_screen.selected_index.min(state.peers.len().saturating_sub(1));
+                        if let Err(e) = discovery::save_known(&state.peers) {
+                            tracing::warn!(error = %e, "cannot remember peers");
+                        }
+                    }
+
+                    // Collections were created or deleted
+                    AppAction::SaveCollections(names, smart) => {
+                        profile.settings.collections = names;
+                        profile.settings.smart_collections = smart;
+                        // Deleting a collection also changed books - save both
+                        save_profile(&profile, &state.books);
+                        state.dirty = false;
+                    }
                 }
             }
         }
+
+        // Placeholders whose metadata was read, filled in together - drawn
+        // on the next tick, or now if they were the last ones
+        let read_any = !parsed.is_empty();
+        state.fill_in(parsed, &profile.settings.author_aliases);
+        redraw |= read_any && state.parsing.pending_count() == 0;
     }
 
     // Restore terminal to normal mode (disable raw mode, leave alternate screen)
     restore()?;
 
+    // The narration stops with the app, its position kept
+    state.stop_listening();
+
+    // Save the library so the next session starts where this one ended
+    database::save(&profile.database_path(), &state.books)?;
+    save_session(&profile, &state);
+
     // Return success
     Ok(())
 }
+
+/// Scans folders on a worker thread - lazily (placeholders for the parse
+/// queue), except for the health report - the books found merged into the
+/// library when they arrive (see finish_scan)
+fn scan_in_background(profile: &Profile, state: &mut TuiState, roots: Vec<PathBuf>, reason: ScanReason) {
+    let lazy = reason != ScanReason::Health;
+    let roots: Vec<(PathBuf, profile::FolderSettings)> = roots
+        .into_iter()
+        .map(|root| {
+            let settings = profile.settings.folder(&root);
+            (root, settings)
+        })
+        .collect();
+    state.workers.spawn(move || {
+        let roots = roots
+            .into_iter()
+            .map(|(root, settings)| {
+                let books = profile::scan_root(&root, &settings, lazy);
+                (root, books)
+            })
+            .collect();
+        AppEvent::Scanned(Scan { reason, roots })
+    });
+}
+
+/// Merges the books of a background scan into the library (or, for an
+/// added folder, makes them the library) and saves it
+///
+/// # Returns
+/// Whether the library's folders changed (the watcher must follow them)
+#[cfg_attr(feature = "profiling", tracing::instrument(level = "trace", name = "finish scan", skip_all))]
+fn finish_scan(profile: &mut Profile, state: &mut TuiState, scan: Scan) -> bool {
+    if scan.reason == ScanReason::NewFolder {
+        let Some((path, Some(new_books))) = scan.roots.into_iter().next() else {
+            return false;
+        };
+        // Only update if we found at least one book
+        if new_books.is_empty() {
+            state.status_message = Some(format!("No EPUB files found in {}", path.display()));
+            return false;
+        }
+
+        // REPLACE books collection (not add to it)
+        state.books = new_books;
+        state.index.clear();
+        state.parsing.clear();
+        state.parsing.request(&state.books);
+
+        // REPLACE scan paths with just the new one
+        state.scan_paths = vec![path.display().to_string()];
+        state.status_message = Some(format!("Added {}", path.display()));
+
+        // REPLACE profile scan roots and remember them
+        profile.settings.scan_paths = vec![path];
+        save_profile(profile, &state.books);
+        state.folder_screen.folders = profile.settings.folder_list();
+
+        // Reset selection to first book
+        state.selected_index = 0;
+        state.refresh_view();
+        return true;
+    }
+
+    // Folders that stopped being the library's while they were scanned
+    // (another library opened, a folder removed) are left out
+    let mut rescanned = Vec::new();
+    for (root, books) in scan.roots {
+        if let (Some(books), true) = (books, profile.settings.scan_paths.contains(&root)) {
+            profile.merge_scan(&mut state.books, &root, books);
+            // Their files may have changed: sizes and times are read again
+            state.index.forget(&root);
+            rescanned.push(root.display().to_string());
+        }
+    }
+    state.parsing.request(&state.books);
+    state.refresh_view();
+    save_profile(profile, &state.books);
+    tracing::info!(folders = ?rescanned, books = state.books.len(), reason = ?scan.reason, "folders rescanned");
+
+    match scan.reason {
+        ScanReason::Watched => state.status_message = Some(format!("Rescanned {}", rescanned.join(", "))),
+        ScanReason::Health => {
+            state.dirty = false;
+            state.health_screen.issues = health::check(&state.books);
+            state.health_screen.selected_index = 0;
+            state.status_message = Some(format!("Rescanned - {} problems left", state.health_screen.issues.len()));
+        }
+        ScanReason::FolderSettings | ScanReason::NewFolder => {}
+    }
+    false
+}
+
+/// Shows what the work done in the background found: moved books, an
+/// online lookup, a peer's catalog, a Calibre library, feed entries, a
+/// pairing, an offer's answer, a new cover
+#[cfg_attr(feature = "profiling", tracing::instrument(level = "trace", name = "results", skip_all))]
+fn show_results(profile: &Profile, state: &mut TuiState, event: AppEvent) {
+    match event {
+        AppEvent::MovesFound(relocations) => {
+            let moved = health::apply_relocations(&mut state.books, &relocations);
+            if moved > 0 {
+                state.refresh_view();
+                save_profile(profile, &state.books);
+                state.dirty = false;
+            }
+
+            state.health_screen.issues = health::check(&state.books);
+            state.health_screen.selected_index = 0;
+            state.status_message = Some(format!(
+                "Found {} moved books - {} problems left",
+                moved,
+                state.health_screen.issues.len()
+            ));
+        }
+        AppEvent::MetadataLookedUp(lookup) => review_metadata(state, *lookup),
+        AppEvent::PeerCatalog(reply) => show_peer_catalog(state, *reply),
+        AppEvent::CalibreListed(listed) => show_calibre_library(state, listed),
+        AppEvent::FeedsFetched { entries, failed } => show_feed_entries(state, entries, failed),
+        AppEvent::PairingStarted { peer, result } => show_pairing(state, peer, result),
+        AppEvent::OfferSent { peer, books, result } => offer_sent(state, peer, books, result),
+        AppEvent::CoverSet { path, title, result } => cover_set(state, path, title, result),
+        // Handled by the main loop itself
+        AppEvent::Key(_)
+        | AppEvent::Resize(..)
+        | AppEvent::Paste(_)
+        | AppEvent::Tick
+        | AppEvent::Parsed(_)
+        | AppEvent::Scanned(_)
+        | AppEvent::LibraryRead(_)
+        | AppEvent::SyncSent(_) => {}
+    }
+}
+
+/// Saves a profile's settings and database, logging (instead of failing on) errors
+///
+/// Used from inside the event loop, where an IO error must not crash the TUI.
+fn save_profile(profile: &Profile, books: &[Book]) {
+    // Before the database: a sidecar newer than the database was changed elsewhere
+    sidecar::write_all(books, &profile.settings);
+    if let Err(e) = profile.save_settings() {
+        tracing::error!(library = %profile.name, error = %e, "cannot save library settings");
+    }
+    if let Err(e) = database::save(&profile.database_path(), books) {
+        tracing::error!(library = %profile.name, error = %e, "cannot save library database");
+    }
+}
+
+/// Writes the titles, authors and series edited since the last pass into
+/// the EPUB files, for the books of folders with write_metadata on (and
+/// not read-only)
+///
+/// Errors are logged and shown, never fatal.
+fn write_metadata_edits(profile: &Profile, state: &mut TuiState) {
+    let (mut written, mut failed) = (0, Vec::new());
+    for path in std::mem::take(&mut state.metadata_edits) {
+        let writable = profile
+            .settings
+            .folder_list()
+            .into_iter()
+            .any(|(root, folder)| folder.write_metadata && !folder.read_only && path.starts_with(&root));
+        if !writable || remote::is_remote(&path) {
+            continue;
+        }
+        let Some(book) = state.books.iter_mut().find(|book| book.path == path) else {
+            continue;
+        };
+        match epub::write_metadata(&path, &book.written_metadata()) {
+            Ok(()) => {
+                // The content changed: its hash is computed again when needed
+                book.hash = None;
+                state.dirty = true;
+                written += 1;
+            }
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "cannot write metadata into the EPUB");
+                failed.push(format!("{}: {}", book.display_title(), e));
+            }
+        }
+    }
+
+    if written > 0 {
+        tracing::info!(books = written, "metadata written into EPUB files");
+    }
+    if !failed.is_empty() {
+        state.status_message = Some(format!("Metadata not written into the file of {}", failed.join(", ")));
+    }
+}
+
+/// Saves the library (if books were edited) and the session state
+///
+/// Called by the periodic autosave; errors are logged, never fatal.
+fn autosave_now(profile: &Profile, state: &mut TuiState) {
+    if state.dirty {
+        // Stamps in memory too: peers syncing with us are served from memory
+        database::stamp_changes(&profile.database_path(), &mut state.books);
+        save_profile(profile, &state.books);
+        state.dirty = false;
+        tracing::debug!(library = %profile.name, "library autosaved");
+    }
+    save_session(profile, state);
+}
+
+/// Saves which book is selected, logging (instead of failing on) errors
+fn save_session(profile: &Profile, state: &TuiState) {
+    let session = Session {
+        selected: state.selected_book().map(|book| book.path.clone()),
+        shelf: state.shelf.clone(),
+        filter: state.filter_text.clone(),
+        starred_only: state.starred_only,
+        status_filter: state.status_filter,
+        sort: state.sort,
+        show_archived: state.show_archived,
+    };
+    if let Err(e) = session.save(profile) {
+        tracing::error!(library = %profile.name, error = %e, "cannot save session");
+    }
+}
+
+/// Reshows the collection and book that were on screen when the profile was last closed
+fn restore_session(state: &mut TuiState, profile: &Profile) {
+    let session = Session::load(profile);
+
+    // show_shelf() falls back to all books if the collection was deleted meanwhile
+    state.show_shelf(session.shelf);
+
+    // A filter that no longer parses (e.g. syntax changed) is dropped
+    match filter::Filter::parse(&session.filter) {
+        Ok(parsed) => {
+            state.filter = parsed;
+            state.filter_text = session.filter;
+        }
+        Err(_) => {
+            state.filter = Default::default();
+            state.filter_text.clear();
+        }
+    }
+    state.starred_only = session.starred_only;
+    state.status_filter = session.status_filter;
+    state.sort = session.sort;
+    state.show_archived = session.show_archived;
+    state.refresh_view();
+    if let Some(path) = session.selected {
+        state.select_path(&path);
+    }
+}
+
+/// Reads another library, then hashes the open one's books it's matched by
+/// on a worker thread (the merge screen opens when they're done, see
+/// compare_library)
+///
+/// # Arguments
+/// * `state` - Application state
+/// * `source` - The other library's database or export file
+fn open_library_merge(state: &mut TuiState, source: &std::path::Path) -> std::io::Result<()> {
+    let other = merge::load_books(source)?;
+    let (reason, unhashed) = (ReadReason::Merge(source.to_path_buf()), unhashed(&state.books));
+    state.status_message = Some(format!("Comparing with {}...", source.display()));
+    state.workers.spawn(move || {
+        let hashes = hashes_for_matching(unhashed, &other);
+        AppEvent::LibraryRead(Box::new(OtherLibrary {
+            reason,
+            result: Ok(other),
+            hashes,
+        }))
+    });
+    Ok(())
+}
+
+/// Paths of the books whose content hash isn't known yet (remote ones left
+/// out: they're never hashed)
+fn unhashed(books: &[Book]) -> Vec<PathBuf> {
+    books
+        .iter()
+        .filter(|book| book.hash.is_none() && !remote::is_remote(&book.path))
+        .map(|book| book.path.clone())
+        .collect()
+}
+
+/// Hashes the files of our books, which another library's books are matched
+/// by (see merge::find_match) - run on a worker thread
+///
+/// # Arguments
+/// * `paths` - Our books without a hash (see `unhashed`)
+/// * `theirs` - The other library's books: with no hashes, nothing is hashed
+///
+/// # Returns
+/// Path and hash of the files that could be read
+fn hashes_for_matching(paths: Vec<PathBuf>, theirs: &[Book]) -> Vec<(PathBuf, String)> {
+    if !theirs.iter().any(|book| book.hash.is_some()) {
+        return Vec::new();
+    }
+    paths
+        .into_iter()
+        .filter_map(|path| hash::content_hash(&path).ok().map(|hash| (path, hash)))
+        .collect()
+}
+
+/// Compares another library, read on a worker thread, with the open one:
+/// opens the merge screen, or syncs with it (see start_sync)
+///
+/// # Arguments
+/// * `profile` - The open profile (its last sync times are updated)
+/// * `state` - Application state
+/// * `other` - The other library, and the hashes of our books computed with it
+/// * `server` - Our peer server, which peers download our books from
+fn compare_library(profile: &mut Profile, state: &mut TuiState, other: OtherLibrary, server: Option<&PeerServer>) {
+    let OtherLibrary { reason, result, hashes } = other;
+    // Kept for next time (and so the comparison doesn't hash again)
+    let mut hashes: std::collections::HashMap<PathBuf, String> = hashes.into_iter().collect();
+    for book in state.books.iter_mut().filter(|book| book.hash.is_none()) {
+        if let Some(hash) = hashes.remove(&book.path) {
+            book.hash = Some(hash);
+            state.dirty = true;
+        }
+    }
+
+    match (reason, result) {
+        (ReadReason::Merge(source), Ok(theirs)) => show_library_merge(state, &source, theirs),
+        (ReadReason::Sync(partner), Ok(theirs)) => sync_with(profile, state, partner, theirs, server),
+        (ReadReason::Merge(source), Err(e)) => {
+            tracing::warn!(source = %source.display(), error = %e, "cannot read library to merge");
+            state.status_message = Some(format!("Cannot merge {}: {}", source.display(), e));
+        }
+        (ReadReason::Sync(partner), Err(e)) => {
+            tracing::warn!(partner = %partner.label(), error = %e, "cannot read library to sync with");
+            state.status_message = Some(format!("Cannot sync with {}: {}", partner.label(), e));
+        }
+    }
+}
+
+/// Opens the merge screen with another library's changes
+fn show_library_merge(state: &mut TuiState, source: &std::path::Path, other: Vec<Book>) {
+    let plan = merge::plan(&mut state.books, other);
+    state.status_message = None;
+
+    if plan.updates.is_empty() && plan.added.is_empty() {
+        state.status_message = Some(format!(
+            "Nothing to merge from {} ({} books not found on this machine)",
+            source.display(),
+            plan.unmatched
+        ));
+        return;
+    }
+
+    state.library_merge = Some(LibraryMerge {
+        source: source.display().to_string(),
+        plan,
+        selected_index: 0,
+        sync: None,
+    });
+    state.mode = UiMode::MergingLibrary;
+}
+
+/// Starts announcing the open library on the network, if peers are enabled
+fn start_discovery(profile: &Profile, state: &TuiState) -> Option<Discovery> {
+    let peers = &state.settings.peers;
+    if !peers.enabled {
+        return None;
+    }
+    let name = peers.name.clone().unwrap_or_else(discovery::host_name);
+    Discovery::start(&name, &profile.name, peers.port, state.books.len(), state.peers.clone())
+}
+
+/// Starts answering other peers, if peers are enabled
+fn start_peer_server(profile: &Profile, state: &TuiState) -> Option<PeerServer> {
+    let peers = &state.settings.peers;
+    if !peers.enabled {
+        return None;
+    }
+    match PeerServer::start(
+        peers.port,
+        &peer_name(state),
+        &profile.name,
+        &state.books,
+        state.transfers.clone(),
+        state.trusted.clone(),
+        peers.require_pairing,
+    ) {
+        Ok(server) => Some(server),
+        Err(e) => {
+            tracing::warn!(port = peers.port, error = %e, "cannot start peer server");
+            None
+        }
+    }
+}
+
+/// Offers the marked books (or the selected one) to a peer, on a worker
+/// thread (see offer_sent)
+///
+/// The peer answers right away; its user accepts or declines later, and
+/// on accept the peer downloads the books from our peer server.
+fn send_to_peer(state: &mut TuiState, peer: &discovery::Peer, server: Option<&PeerServer>) {
+    let (Some(server), Some(address)) = (server, peer.address) else {
+        state.status_message = Some("Sending needs [peers] enabled and a reachable peer".to_string());
+        return;
+    };
+
+    let indices = state.target_indices();
+    if indices.is_empty() {
+        return;
+    }
+
+    let (offer, secret, name) = (offer_of(state, server, &indices), secret_of(state, peer), peer.name.clone());
+    state.status_message = Some(format!("Offering {} books to {}...", indices.len(), peer.name));
+    state.workers.spawn(move || {
+        let result = peer::send_offer(address, &offer, secret.as_ref());
+        AppEvent::OfferSent {
+            peer: name,
+            books: offer.books.len(),
+            result,
+        }
+    });
+}
+
+/// Tells how a peer answered an offer of books
+fn offer_sent(state: &mut TuiState, peer: String, books: usize, result: Result<(), String>) {
+    match result {
+        Ok(()) => {
+            tracing::info!(%peer, books, "books offered");
+            state.status_message = Some(format!("Offered {} books to {} - waiting for them to accept", books, peer));
+        }
+        Err(e) => {
+            tracing::warn!(%peer, error = %e, "cannot send offer");
+            state.status_message = Some(format!("Cannot reach {}: {}", peer, e));
+        }
+    }
+}
+
+/// The offer of books of the library to a peer (by their index in the library)
+fn offer_of(state: &TuiState, server: &PeerServer, indices: &[usize]) -> peer::Offer {
+    // The offered ids must match what the server shares
+    server.publish(&state.books);
+    peer::Offer {
+        from: peer_name(state),
+        port: state.settings.peers.port,
+        books: indices.iter().map(|&i| peer::entry_of(i, &state.books[i])).collect(),
+    }
+}
+
+/// Name this instance announces to its peers
+fn peer_name(state: &TuiState) -> String {
+    state.settings.peers.name.clone().unwrap_or_else(discovery::host_name)
+}
+
+/// Our pair secret with a peer (None if we didn't pair with it)
+fn secret_of(state: &TuiState, peer: &discovery::Peer) -> Option<trust::Secret> {
+    trust::secret_for(&state.trusted, peer.instance.as_deref())
+}
+
+/// Asks a peer to pair on a worker thread; the PIN to compare is shown
+/// when it answers (see show_pairing)
+fn pair_with_peer(state: &mut TuiState, peer: &discovery::Peer) {
+    let Some(address) = peer.address else {
+        state.status_message = Some(format!("{} has no known address", peer.name));
+        return;
+    };
+    let (ours, theirs) = (peer_name(state), peer.name.clone());
+    state.status_message = Some(format!("Asking {} to pair...", peer.name));
+    state.workers.spawn(move || AppEvent::PairingStarted {
+        result: peer::request_pairing(address, &ours),
+        peer: theirs,
+    });
+}
+
+/// Shows the PIN of a pairing a peer answered
+fn show_pairing(state: &mut TuiState, peer: String, result: Result<trust::Pairing, String>) {
+    match result {
+        Ok(pairing) => {
+            tracing::info!(%peer, "pairing started");
+            state.status_message = None;
+            state.pairings.insert(0, pairing);
+            state.mode = UiMode::Pairing;
+        }
+        Err(e) => {
+            tracing::warn!(%peer, error = %e, "cannot pair");
+            state.status_message = Some(format!("Cannot pair with {}: {}", peer, e));
+        }
+    }
+}
+
+/// Stores the trusted peers and lets the peer server know
+fn save_trusted(state: &TuiState, server: Option<&PeerServer>) {
+    if let Err(e) = trust::save_trusted(&state.trusted) {
+        tracing::warn!(error = %e, "cannot store trusted peers");
+    }
+    if let Some(server) = server {
+        server.set_trusted(&state.trusted);
+    }
+}
+
+/// Queues the downloads of the books of an accepted offer
+///
+/// Books land in the [peers] incoming folder and are added to the library as
+/// their downloads end (see `finish_transfer`).
+///
+/// # Arguments
+/// * `profile` - The open profile
+/// * `state` - Application state
+/// * `incoming` - The accepted offer
+/// * `synced` - The peer's copies of the books, when they come with a sync
+///   (by id on the peer; their user data is kept)
+///
+/// # Returns
+/// How many downloads were queued
+fn accept_offer(profile: &Profile, state: &mut TuiState, incoming: &peer::IncomingOffer, synced: &[Book]) -> usize {
+    let Some(folder) = incoming_folder(profile, state) else {
+        state.status_message = Some("Receiving books: add a library folder first".to_string());
+        return 0;
+    };
+
+    for entry in &incoming.offer.books {
+        state.transfers.enqueue(transfer::Job::PeerBook {
+            address: incoming.address,
+            entry: Box::new(entry.clone()),
+            folder: folder.clone(),
+            synced: synced.get(entry.id).cloned().map(Box::new),
+            secret: incoming.secret.clone(),
+        });
+    }
+    tracing::info!(from = %incoming.offer.from, books = incoming.offer.books.len(), "downloads queued");
+    incoming.offer.books.len()
+}
+
+/// Queues downloads of books of the peer being browsed into the incoming
+/// folder; they join the library as their downloads end
+fn download_from_peer(profile: &Profile, state: &mut TuiState, entries: Vec<peer::CatalogEntry>) {
+    let Some(folder) = incoming_folder(profile, state) else {
+        state.status_message = Some("Receiving books: add a library folder first".to_string());
+        return;
+    };
+    let Some(browser) = &state.peer_browser else {
+        return;
+    };
+
+    for entry in &entries {
+        state.transfers.enqueue(transfer::Job::PeerBook {
+            address: browser.address,
+            entry: Box::new(entry.clone()),
+            folder: folder.clone(),
+            synced: None,
+            secret: browser.secret.clone(),
+        });
+    }
+    tracing::info!(peer = %browser.peer, books = entries.len(), "downloads queued");
+    state.status_message = Some(format!("Downloading {} books from {}", entries.len(), browser.peer));
+}
+
+/// Folder where books received from peers land
+fn incoming_folder(profile: &Profile, state: &TuiState) -> Option<PathBuf> {
+    match &state.settings.peers.incoming {
+        Some(folder) => Some(folder.clone()),
+        None => profile.settings.scan_paths.first().map(|root| root.join("Incoming")),
+    }
+}
+
+/// Acts on a download that ended: a book received from a peer joins the
+/// library, a remote book is opened
+fn finish_transfer(profile: &Profile, state: &mut TuiState, webhooks: &mut webhooks::Webhooks, finished: transfer::Finished) {
+    let path = match finished.result {
+        Ok(path) => path,
+        Err(e) => {
+            let label = match &finished.job {
+                transfer::Job::PeerBook { entry, .. } => entry.name.clone(),
+                transfer::Job::RemoteBook { path } => path.display().to_string(),
+                transfer::Job::ToDevice { source, device, .. } => format!("{} to {}", source.display(), device),
+                transfer::Job::Email { source, to, .. } => format!("{} to {}", source.display(), to),
+                transfer::Job::FromCalibre { book, .. } => format!("{} from Calibre", book.title),
+                transfer::Job::FromFeed { entry, .. } => format!("{} from {}", entry.title, entry.feed),
+                transfer::Job::ToCalibre { source, .. } => format!("{} to Calibre", source.display()),
+                transfer::Job::Convert { source, format, .. } => format!("{} to {}", source.display(), format.label()),
+                transfer::Job::Optimize { source, .. } => format!("{} (optimize)", source.display()),
+            };
+            tracing::warn!(book = %label, error = %e, "transfer failed");
+            state.status_message = Some(format!("Transfer of {} failed: {}", label, e));
+            return;
+        }
+    };
+
+    match finished.job {
+        transfer::Job::PeerBook {
+            address,
+            entry,
+            folder,
+            synced,
+            ..
+        } => {
+            // The peer being browsed shows it's here now
+            if let Some(browser) = state.peer_browser.as_mut().filter(|browser| browser.address == address) {
+                browser.owned.insert(entry.id);
+            }
+            receive_book(profile, state, &path, &folder, synced);
+            if let Some(book) = state.books.last() {
+                let peer = state
+                    .peers
+                    .iter()
+                    .find(|peer| peer.address == Some(address))
+                    .map(|peer| peer.name.clone())
+                    .unwrap_or_else(|| address.ip().to_string());
+                webhooks.received(book, &peer);
+            }
+        }
+        transfer::Job::FromCalibre { folder, .. } => receive_book(profile, state, &path, &folder, None),
+        transfer::Job::FromFeed { entry, folder } => {
+            // The feed screen shows it's here now
+            if let Some(browser) = state.feed_browser.as_mut() {
+                browser.owned.insert(entry.id);
+            }
+            receive_book(profile, state, &path, &folder, None)
+        }
+        transfer::Job::RemoteBook { path: remote } => open_downloaded(state, &remote, &path),
+        transfer::Job::ToDevice {
+            source,
+            device,
+            documents,
+            ..
+        } => {
+            // Recorded on the device, so it's known to be there next time
+            let Some(book) = state.books.iter().find(|book| book.path == source) else {
+                return;
+            };
+            let file = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let mut manifest = device::Manifest::load(&documents);
+            manifest.record(&file, book);
+            if let Err(e) = manifest.save(&documents) {
+                tracing::warn!(device = %device, error = %e, "cannot update the device manifest");
+            }
+            for shown in state.devices_screen.devices.iter_mut().filter(|d| d.documents == documents) {
+                shown.manifest = manifest.clone();
+            }
+
+            tracing::info!(path = %path.display(), "book sent to device");
+            state.status_message = Some(format!("Sent {} to {}", book.display_title(), device));
+        }
+        transfer::Job::Email { title, to, .. } => state.status_message = Some(format!("Emailed {} to {}", title, to)),
+        transfer::Job::ToCalibre { .. } => {
+            let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            state.status_message = Some(format!("Added {} to Calibre", name));
+        }
+        // An EPUB joins the library (unless it's a book of it, converted over
+        // itself), other formats stay next to the book
+        transfer::Job::Convert {
+            format: convert::Format::Epub,
+            folder,
+            ..
+        } if !state.books.iter().any(|book| book.path == path) => receive_book(profile, state, &path, &folder, None),
+        transfer::Job::Convert { .. } => {
+            tracing::info!(path = %path.display(), "book converted");
+            state.status_message = Some(format!("Converted to {}", path.display()));
+        }
+        transfer::Job::Optimize { source, size } => {
+            let report = optimize::Report {
+                before: size,
+                after: std::fs::metadata(&source).map(|m| m.len()).unwrap_or(size),
+                ..Default::default()
+            };
+            let Some(book) = state.books.iter_mut().find(|book| book.path == source) else {
+                return;
+            };
+            if report.after < report.before {
+                // The content changed: its hash is computed again when needed
+                book.hash = None;
+                state.dirty = true;
+            }
+            state.status_message = Some(format!("Optimized {}: {}", book.display_title(), report.summary()));
+        }
+    }
+}
+
+/// Adds a downloaded book to the library
+///
+/// # Arguments
+/// * `path` - The downloaded file
+/// * `folder` - The folder it was downloaded into (its default tags apply)
+/// * `synced` - The sender's copy of the book, whose user data is kept (sync)
+fn receive_book(profile: &Profile, state: &mut TuiState, path: &Path, folder: &Path, synced: Option<Box<Book>>) {
+    // Default tags of the library folder the incoming folder is in, if any
+    let folder_settings = profile
+        .settings
+        .scan_paths
+        .iter()
+        .find(|root| folder.starts_with(root))
+        .map(|root| profile.settings.folder(root))
+        .unwrap_or_default();
+    let mut book = scanner::book_from_file(path, &folder_settings);
+    if let Some(theirs) = synced {
+        book.user = theirs.user;
+        book.modified = theirs.modified;
+    }
+    let mut received = vec![book];
+    authors::apply_aliases(&mut received, &profile.settings.author_aliases);
+
+    let title = received[0].display_title().to_string();
+    state.books.append(&mut received);
+    state.refresh_view();
+    save_profile(profile, &state.books);
+    state.dirty = false;
+
+    tracing::info!(path = %path.display(), "book received");
+    state.status_message = Some(format!("Received {}", title));
+}
+
+/// Reads a partner's books on a worker thread, hashing ours they're matched
+/// by (the sync goes on when they arrive, see compare_library)
+fn start_sync(state: &mut TuiState, partner: sync::Partner) {
+    let unhashed = unhashed(&state.books);
+    state.status_message = Some(format!("Syncing with {}...", partner.label()));
+    state.workers.spawn(move || {
+        let result = partner.books().map_err(|e| e.to_string());
+        let hashes = match &result {
+            Ok(theirs) => hashes_for_matching(unhashed, theirs),
+            Err(_) => Vec::new(),
+        };
+        AppEvent::LibraryRead(Box::new(OtherLibrary {
+            reason: ReadReason::Sync(partner),
+            result,
+            hashes,
+        }))
+    });
+}
+
+/// Compares a partner's books with ours; what merges cleanly is applied
+/// right away, conflicts open the merge screen first
+///
+/// # Arguments
+/// * `profile` - The open profile (its last sync times are updated)
+/// * `state` - Application state
+/// * `partner` - The library to sync with
+/// * `theirs` - The partner's books
+/// * `server` - Our peer server, which peers download our books from
+fn sync_with(profile: &mut Profile, state: &mut TuiState, partner: sync::Partner, theirs: Vec<Book>, server: Option<&PeerServer>) {
+    let label = partner.label();
+    state.status_message = None;
+
+    // Edits not saved yet count as changes too
+    database::stamp_changes(&profile.database_path(), &mut state.books);
+    let last_sync = profile.settings.last_sync.get(&partner.key()).copied();
+    let (plan, pending) = sync::plan(partner, &mut state.books, theirs, last_sync);
+
+    let mut merge = LibraryMerge {
+        source: label,
+        plan,
+        selected_index: 0,
+        sync: None,
+    };
+    if merge.plan.conflict_count() > 0 {
+        merge.sync = Some(pending);
+        state.library_merge = Some(merge);
+        state.mode = UiMode::MergingLibrary;
+        return;
+    }
+    state.apply_library_merge(merge);
+    finish_sync(profile, state, pending, server);
+}
+
+/// Does the partner's side of a sync once ours was applied: copies the books
+/// each side is missing, sends the settled user data and remembers when
+///
+/// A peer is offered our books (its user accepts or declines them) and sent
+/// the user data on a worker thread (see synced); a library file only takes
+/// (and gives) books of remote folders, the only ones both machines can reach.
+fn finish_sync(profile: &mut Profile, state: &mut TuiState, pending: sync::PendingSync, server: Option<&PeerServer>) {
+    let label = pending.partner.label();
+    let pushes = sync::pushes(&state.books, &pending);
+    let mut problems = Vec::new();
+
+    match &pending.partner {
+        sync::Partner::Peer { address, secret, .. } => {
+            // Their books: downloaded like an accepted offer, user data included
+            let incoming = peer::IncomingOffer {
+                offer: peer::Offer {
+                    from: label.clone(),
+                    port: address.port(),
+                    books: pending.incoming.iter().map(|&i| peer::entry_of(i, &pending.theirs[i])).collect(),
+                },
+                address: *address,
+                secret: secret.clone(),
+            };
+            let received = if incoming.offer.books.is_empty() {
+                0
+            } else {
+                accept_offer(profile, state, &incoming, &pending.theirs)
+            };
+
+            // Our books: offered to the peer
+            let indices: Vec<usize> = state
+                .books
+                .iter()
+                .enumerate()
+                .filter(|(_, book)| pending.outgoing.contains(&book.path))
+                .map(|(i, _)| i)
+                .collect();
+            let offer = match server {
+                _ if indices.is_empty() => None,
+                Some(server) => Some(offer_of(state, server, &indices)),
+                None => {
+                    problems.push("sending books needs [peers] enabled".to_string());
+                    None
+                }
+            };
+            let push = (!pushes.is_empty()).then(|| peer::SyncPush {
+                from: peer_name(state),
+                books: pushes,
+            });
+
+            // Stamped now: what the user edits while the peer is asked is
+            // newer than the sync
+            database::stamp_changes(&profile.database_path(), &mut state.books);
+            let (address, secret, partner) = (*address, secret.clone(), pending.partner.clone());
+            let time = crate::book::unix_now();
+            state.status_message = Some(format!("Syncing with {}...", label));
+            state.workers.spawn(move || {
+                let sent = match offer {
+                    Some(offer) => match peer::send_offer(address, &offer, secret.as_ref()) {
+                        Ok(()) => offer.books.len(),
+                        Err(e) => {
+                            problems.push(format!("cannot offer books: {}", e));
+                            0
+                        }
+                    },
+                    None => 0,
+                };
+                let pushed = match push {
+                    Some(push) => {
+                        if let Err(e) = peer::push_sync(address, &push, secret.as_ref()) {
+                            problems.push(format!("cannot send user data: {}", e));
+                        }
+                        push.books.len()
+                    }
+                    None => 0,
+                };
+                AppEvent::SyncSent(Box::new(SyncSent {
+                    partner,
+                    time,
+                    received,
+                    sent,
+                    pushed,
+                    problems,
+                }))
+            });
+        }
+
+        sync::Partner::File(path) => {
+            let mut theirs = pending.theirs.clone();
+            for pushed in &pushes {
+                if let Some(book) = theirs.iter_mut().find(|b| b.path == pushed.path) {
+                    *book = pushed.clone();
+                }
+            }
+
+            // Only books both machines can reach change sides
+            let reachable = |book: &Book| remote::is_remote(&book.path) || book.path.is_file();
+            let received: Vec<Book> = pending
+                .incoming
+                .iter()
+                .map(|&i| pending.theirs[i].clone())
+                .filter(|book| reachable(book))
+                .collect();
+            let sent: Vec<Book> = state
+                .books
+                .iter()
+                .filter(|book| pending.outgoing.contains(&book.path) && remote::is_remote(&book.path))
+                .cloned()
+                .collect();
+            let (received_count, sent_count) = (received.len(), sent.len());
+            theirs.extend(sent);
+            state.books.extend(received);
+
+            if let Err(e) = sync::write_library_file(path, &theirs) {
+                problems.push(format!("cannot write {}: {}", path.display(), e));
+            }
+
+            // Stamp before remembering the sync, so what it brought in isn't
+            // taken for later edits
+            database::stamp_changes(&profile.database_path(), &mut state.books);
+            let sent = SyncSent {
+                partner: pending.partner.clone(),
+                time: crate::book::unix_now(),
+                received: received_count,
+                sent: sent_count,
+                pushed: pushes.len(),
+                problems,
+            };
+            synced(profile, state, sent);
+        }
+    }
+}
+
+/// Remembers a sync once the partner's side was done, and sums it up
+fn synced(profile: &mut Profile, state: &mut TuiState, sync: SyncSent) {
+    let SyncSent {
+        partner,
+        time,
+        received,
+        sent,
+        pushed,
+        problems,
+    } = sync;
+    let label = partner.label();
+
+    database::stamp_changes(&profile.database_path(), &mut state.books);
+    if problems.is_empty() {
+        profile.settings.last_sync.insert(partner.key(), time);
+    }
+    state.refresh_view();
+    save_profile(profile, &state.books);
+    state.dirty = false;
+
+    tracing::info!(partner = %label, received, sent, pushed, problems = problems.len(), "library synced");
+    let summary = format!(
+        "Synced with {}: {} books to receive, {} sent, {} updated there",
+        label, received, sent, pushed
+    );
+    state.status_message = Some(if problems.is_empty() {
+        summary
+    } else {
+        format!("{} - {}", summary, problems.join(", "))
+    });
+}
+
+/// Queues copies of the marked books (or the selected one) to an e-reader
+///
+/// Books already on the device (by content, see the device manifest) and
+/// books on servers are skipped; Kindles get the [devices] kindle_format.
+fn send_to_device(state: &mut TuiState, device: &device::Device) {
+    let settings = &state.settings.devices;
+    let conversion = match device.kind {
+        device::DeviceKind::Kindle if !settings.kindle_format.eq_ignore_ascii_case("epub") => Some(device::Conversion {
+            command: settings.converter.clone(),
+            format: settings.kindle_format.to_lowercase(),
+        }),
+        _ => None,
+    };
+
+    let (mut queued, mut present) = (0, 0);
+    for i in state.target_indices() {
+        let book = &mut state.books[i];
+        if remote::is_remote(&book.path) {
+            continue;
+        }
+        // The hash tells the book apart from others of the same name
+        if book.hash.is_none() {
+            book.ensure_hash();
+            state.dirty = true;
+        }
+        if device.manifest.contains(book) {
+            present += 1;
+            continue;
+        }
+        state.transfers.enqueue(transfer::Job::ToDevice {
+            source: book.path.clone(),
+            device: device.name.clone(),
+            documents: device.documents.clone(),
+            conversion: conversion.clone(),
+        });
+        queued += 1;
+    }
+
+    tracing::info!(device = %device.name, queued, present, "books sent to device");
+    state.status_message = Some(match present {
+        0 => format!("Sending {} books to {}", queued, device.name),
+        _ => format!("Sending {} books to {} ({} already there)", queued, device.name, present),
+    });
+}
+
+/// Searches the text of the books and shows the chapters that matched
+///
+/// # Arguments
+/// * `fulltext` - The full-text index of the library
+/// * `state` - Application state (the hits go to `text_search`)
+/// * `query` - The words typed
+fn search_text(fulltext: &fulltext::FullText, state: &mut TuiState, query: String) {
+    let started = std::time::Instant::now();
+    let hits = match fulltext.search(&query) {
+        Ok(hits) => hits,
+        Err(e) => {
+            state.status_message = Some(format!("Text search: {}", e));
+            return;
+        }
+    };
+    tracing::info!(query = %query, hits = hits.len(), took = ?started.elapsed(), "text searched");
+
+    let titles = hits
+        .iter()
+        .map(|hit| match state.books.iter().find(|book| book.path == hit.path) {
+            Some(book) => book.display_title().to_string(),
+            None => hit.path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+        })
+        .collect();
+    state.text_search = Some(TextSearch {
+        query,
+        hits,
+        titles,
+        indexing: fulltext.indexing(),
+        selected_index: 0,
+    });
+    state.mode = UiMode::TextSearchResults;
+}
+
+/// Queues emails of the marked books (or the selected one), one book per
+/// message
+///
+/// Books on servers and books over the [email] size limit are skipped.
+fn email_books(state: &mut TuiState, to: &str) {
+    let settings = Box::new(state.settings.email.clone());
+    let (mut queued, mut skipped) = (0, Vec::new());
+    for i in state.target_indices() {
+        let book = &state.books[i];
+        if remote::is_remote(&book.path) {
+            skipped.push(format!("{}: not on this computer", book.display_title()));
+            continue;
+        }
+        let size = std::fs::metadata(&book.path).map(|m| m.len()).unwrap_or(0);
+        if let Err(e) = mail::check(&settings, size) {
+            skipped.push(format!("{}: {}", book.display_title(), e));
+            continue;
+        }
+        state.transfers.enqueue(transfer::Job::Email {
+            source: book.path.clone(),
+            title: book.display_title().to_string(),
+            to: to.to_string(),
+            settings: settings.clone(),
+        });
+        queued += 1;
+    }
+
+    tracing::info!(to, queued, skipped = skipped.len(), "books queued for email");
+    state.status_message = Some(match (queued, skipped.as_slice()) {
+        (0, []) => "Nothing to email".to_string(),
+        (_, []) => format!("Emailing {} books to {}", queued, to),
+        (0, [reason]) => format!("Not emailed - {}", reason),
+        _ => format!("Emailing {} books to {} (skipped {})", queued, to, skipped.join(", ")),
+    });
+}
+
+/// Writes the text of a book to stdout (`extract BOOK`)
+fn extract_text(book: &Path, format: extract::TextFormat) -> std::io::Result<()> {
+    let document = reader::Document::open(book)?;
+    std::io::stdout().lock().write_all(extract::render(&document, format).as_bytes())
+}
+
+/// Optimizes a book (`optimize BOOK`), the progress on stderr
+fn optimize_book(book: &Path) -> std::io::Result<()> {
+    let name = book.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let report = optimize::optimize(book, |done| eprint!("\rOptimizing {}: {:>3}%", name, done), || false);
+    eprintln!();
+    let report = report?;
+    for removed in &report.removed {
+        println!("Removed {}", removed);
+    }
+    if report.images > 0 {
+        println!("Recompressed {} images", report.images);
+    }
+    println!("{}: {}", name, report.summary());
+    Ok(())
+}
+
+/// Lists the fonts embedded in a book (`fonts BOOK`), or slims them
+/// (`--strip`/`--subset`)
+fn book_fonts(book: &Path, action: Option<fonts::Action>) -> std::io::Result<()> {
+    let name = book.file_name().unwrap_or_default().to_string_lossy().to_string();
+    match action {
+        Some(action) => {
+            let report = fonts::slim(book, action)?;
+            println!("{}: {}", name, report.summary(action));
+            println!("Backup: {}", report.backup.display());
+        }
+        None => {
+            let fonts = fonts::list(book)?;
+            for font in &fonts {
+                let family = font.family.as_deref().unwrap_or("-");
+                println!("{:>10}  {:<8}  {:<30}  {}", transfer::human_bytes(font.size as f64), font.kind(), family, font.path);
+            }
+            let total: u64 = fonts.iter().map(|font| font.size).sum();
+            println!("{}: {} fonts, {}", name, fonts.len(), transfer::human_bytes(total as f64));
+        }
+    }
+    Ok(())
+}
+
+/// Splits an omnibus (`split BOOK`), the parts next to it
+fn split_omnibus(book: &Path) -> std::io::Result<()> {
+    let folder = book.parent().map(Path::to_path_buf).unwrap_or_default();
+    for part in omnibus::split(book, &folder)? {
+        println!("{}", part.display());
+    }
+    Ok(())
+}
+
+/// Merges books into an anthology (`join OUTPUT BOOK...`), titled after
+/// OUTPUT unless `--title` says otherwise
+fn join_anthology(output: &Path, books: &[PathBuf], title: Option<&str>) -> std::io::Result<()> {
+    let title = title
+        .map(str::to_string)
+        .unwrap_or_else(|| output.file_stem().unwrap_or_default().to_string_lossy().to_string());
+    omnibus::merge(books, &title, output)?;
+    println!("Merged {} books into {}", books.len(), output.display());
+    Ok(())
+}
+
+/// Times the scanner on a folder (`bench PATH`) and prints the report,
+/// each measure told on stderr while it's taken
+///
+/// Fails with NoBooks when the folder has no EPUBs.
+fn run_bench(root: &Path, threads: &[usize], runs: Option<usize>) -> Result<(), Failure> {
+    let threads = if threads.is_empty() { bench::default_threads() } else { threads.to_vec() };
+    let report = bench::run(root, &threads, runs.unwrap_or(bench::DEFAULT_RUNS), |stage, count| {
+        eprint!("\r\x1b[KTiming {} with {} threads...", stage.label().to_lowercase(), count);
+    });
+    eprint!("\r\x1b[K");
+    if report.files == 0 {
+        return Err(Failure::NoBooks(format!("no EPUBs in {}", root.display())));
+    }
+    for line in report.lines() {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// Rescans the library's folders and saves it (`scan`), keeping the user
+/// data of the books still there
+///
+/// Fails with NoBooks when the folders have none.
+fn scan_library(profile: &Profile) -> Result<(), Failure> {
+    let mut books = database::load(&profile.database_path())?;
+    let before: std::collections::HashSet<PathBuf> = books.iter().map(|book| book.path.clone()).collect();
+
+    // Books of folders that are no longer the library's go
+    let roots = &profile.settings.scan_paths;
+    books.retain(|book| roots.iter().any(|root| book.path.starts_with(root)));
+    for root in roots {
+        profile.rescan_root(&mut books, root, false);
+    }
+    save_profile(profile, &books);
+
+    let added = books.iter().filter(|book| !before.contains(&book.path)).count();
+    let gone = before.len() + added - books.len();
+    println!("Scanned {} folders: {} books ({} new, {} gone)", roots.len(), books.len(), added, gone);
+    if books.is_empty() {
+        return Err(Failure::NoBooks("no books in the library's folders".to_string()));
+    }
+    Ok(())
+}
+
+/// Imports into the library (`import --calibre|--goodreads|--userdata FILE`)
+/// and saves it
+fn import_into(profile: &Profile, books: &mut Vec<Book>, config: &Config) -> std::io::Result<()> {
+    let source = &config.files[0];
+    match config.import {
+        // Merge a Calibre library into this profile
+        Some(ImportKind::Calibre) => {
+            let imported = import::calibre::import(source)?;
+            let summary = import::merge_into(books, imported);
+            database::save(&profile.database_path(), books)?;
+            println!(
+                "Imported from {}: {} added, {} updated",
+                source.display(),
+                summary.added,
+                summary.updated
+            );
+        }
+        // Apply a Goodreads export to this profile
+        Some(ImportKind::Goodreads) => {
+            let summary = import::goodreads::import(source, books)?;
+            database::save(&profile.database_path(), books)?;
+            println!(
+                "Imported from {}: {} books updated, {} rows without a matching book",
+                source.display(),
+                summary.updated,
+                summary.unmatched
+            );
+        }
+        // Reattach exported user data to matching files
+        Some(ImportKind::UserData) | None => {
+            let summary = userdata::import(source, books)?;
+            database::save(&profile.database_path(), books)?;
+            println!(
+                "Imported user data from {}: {} books updated, {} entries without a matching file",
+                source.display(),
+                summary.updated,
+                summary.unmatched
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Writes the library, its user data or its highlights to a file, or
+/// stdout for "-" (`export [--userdata|--highlights] [FILE]`)
+fn export_library(profile: &Profile, books: &mut [Book], config: &Config) -> std::io::Result<()> {
+    let dest = config.files.first().map(PathBuf::as_path).unwrap_or(Path::new("-"));
+    match config.export {
+        ExportKind::Library => export::LibraryExport::new(&profile.name, books).write_to(dest)?,
+        // Tags/ratings keyed by content hash
+        ExportKind::UserData => {
+            let count = userdata::export(books, dest)?;
+            // Hashes were computed along the way - keep them for next time
+            database::save(&profile.database_path(), books)?;
+            println!("Exported user data of {} books to {}", count, dest.display());
+        }
+        // The reader's highlights as notes
+        ExportKind::Highlights => {
+            let count = notes::export_library(&profile.name, books, dest)?;
+            // Stdout holds the notes themselves
+            if dest != Path::new("-") {
+                println!("Exported {} highlights to {}", count, dest.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints the books `list` asks for - those its filter keeps, in its order -
+/// tab-separated, as JSON or as CSV
+///
+/// Fails with NoBooks when none is left to print (an empty list is printed).
+fn list_books(books: &[Book], config: &Config) -> Result<(), Failure> {
+    // Archived books only when the filter asks for them, as in the TUI
+    let filter = filter::Filter::parse(&config.list_filter).map_err(std::io::Error::other)?;
+    // Sort keys worked out once per book, not at every comparison
+    let mut keyed: Vec<(&Book, sort::SortKeys)> = books
+        .iter()
+        .filter(|book| filter.mentions_archived() || !book.user.archived)
+        .filter(|book| filter.matches(book))
+        .map(|book| (book, sort::SortKeys::of(book)))
+        .collect();
+    keyed.sort_by(|(a, keys_a), (b, keys_b)| config.list_sort.compare_keyed(a, keys_a, b, keys_b));
+    let shown: Vec<&Book> = keyed.into_iter().map(|(book, _)| book).collect();
+
+    let mut out = std::io::stdout().lock();
+    listing::write(&shown, config.list_format, &mut out)?;
+    out.flush()?;
+    if shown.is_empty() {
+        return Err(Failure::NoBooks(if books.is_empty() { "no books found" } else { "no book matches" }.to_string()));
+    }
+    Ok(())
+}
+
+/// Scans folders and files given on the command line, without their folder
+/// settings (they needn't be the library's)
+fn scan_given(paths: &[PathBuf]) -> Vec<Book> {
+    let settings = profile::FolderSettings::default();
+    let mut books = Vec::new();
+    for path in paths {
+        if path.is_file() && scanner::is_epub(path) {
+            books.push(scanner::book_from_file(path, &settings));
+        } else {
+            books.extend(scanner::scan_folder(path, &settings));
+        }
+    }
+    books
+}
+
+/// `dedupe PATH...`: the given folders and files, scanned as they are; the
+/// library's books among them stand for their files (their user data is
+/// what's merged), and with `--apply` the library follows: the books of
+/// deleted files go, the copies kept get their user data
+fn dedupe_given(profile: &Profile, paths: &[PathBuf], apply: Option<duplicates::Resolution>) -> Result<(), Failure> {
+    let mut books = scan_given(paths);
+    if apply.is_none() {
+        return dedupe(&mut books, None).map(drop);
+    }
+
+    let mut library = database::load(&profile.database_path())?;
+    let positions: std::collections::HashMap<PathBuf, usize> =
+        library.iter().enumerate().map(|(i, book)| (book.path.clone(), i)).collect();
+    for book in &mut books {
+        if let Some(&i) = positions.get(&book.path) {
+            *book = library[i].clone();
+        }
+    }
+
+    let deleted = dedupe(&mut books, apply)?;
+    if deleted.iter().all(|(copy, _)| !positions.contains_key(copy)) {
+        return Ok(());
+    }
+    let scanned: std::collections::HashMap<&Path, &Book> = books.iter().map(|book| (book.path.as_path(), book)).collect();
+    let mut added = Vec::new();
+    for (copy, kept) in &deleted {
+        let Some(&i) = positions.get(copy) else {
+            continue;
+        };
+        match positions.get(kept) {
+            // Kept in the library: it has the merged user data now
+            Some(&k) => {
+                if let Some(book) = scanned.get(kept.as_path()) {
+                    library[k].user = book.user.clone();
+                    library[k].meta = book.meta.clone();
+                }
+            }
+            // Kept outside of it: the book moved there, as a rescan would see it
+            None => {
+                if let Some(book) = scanned.get(kept.as_path()) {
+                    let mut moved = library[i].clone();
+                    moved.path = book.path.clone();
+                    moved.name = book.name.clone();
+                    moved.user = book.user.clone();
+                    moved.meta = book.meta.clone();
+                    // Another copy of it may have moved there already
+                    added.retain(|other: &Book| other.path != moved.path);
+                    added.push(moved);
+                }
+            }
+        }
+    }
+    library.retain(|book| !deleted.iter().any(|(copy, _)| *copy == book.path));
+    library.extend(added);
+    Ok(database::save(&profile.database_path(), &library)?)
+}
+
+/// Prints the identical files among books, then the books that look like
+/// the same work (`dedupe`); with a resolution (`--apply`), gets rid of the
+/// extra copies of identical files
+///
+/// # Returns
+/// The files deleted, each with the copy kept in its place, or NoBooks if
+/// there are no books to look at
+fn dedupe(books: &mut [Book], apply: Option<duplicates::Resolution>) -> Result<Vec<(PathBuf, PathBuf)>, Failure> {
+    if books.is_empty() {
+        return Err(Failure::NoBooks("no books found".to_string()));
+    }
+    let groups = duplicates::identical(books);
+    let mut out = std::io::stdout().lock();
+
+    let (mut freed, mut deleted) = (0, Vec::new());
+    for group in &groups {
+        let size = transfer::human_bytes(group.size as f64);
+        writeln!(out, "{} identical files of {} ({})", group.paths.len(), size, &group.hash[..12])?;
+        let kept = &group.paths[0];
+        writeln!(out, "  {}", kept.display())?;
+        for copy in &group.paths[1..] {
+            let done = match apply {
+                None => String::new(),
+                Some(resolution) => match resolve_copy(books, copy, kept, &group.hash, resolution) {
+                    Ok(()) => {
+                        tracing::info!(kept = %kept.display(), copy = %copy.display(), ?resolution, "identical file resolved");
+                        freed += group.size;
+                        if resolution == duplicates::Resolution::Delete {
+                            deleted.push((copy.clone(), kept.clone()));
+                            "  (deleted)".to_string()
+                        } else {
+                            "  (hardlinked)".to_string()
+                        }
+                    }
+                    Err(e) => format!("  (not done: {})", e),
+                },
+            };
+            writeln!(out, "  {}{}", copy.display(), done)?;
+        }
+        writeln!(out)?;
+    }
+    // Identical files (and hardlinks) are listed once, as such
+    let identical = |pair: &duplicates::Pair| {
+        duplicates::same_file(&pair.left, &pair.right)
+            || groups.iter().any(|group| group.paths.contains(&pair.left) && group.paths.contains(&pair.right))
+    };
+    let mut pairs = duplicates::suspected(books);
+    let gone = |path: &PathBuf| deleted.iter().any(|(copy, _)| copy == path);
+    pairs.retain(|pair| !identical(pair) && !gone(&pair.left) && !gone(&pair.right));
+    for pair in &pairs {
+        writeln!(out, "Same work? {}\n  {}\n  {}\n", pair.title, pair.left.display(), pair.right.display())?;
+    }
+
+    let extra: u64 = groups.iter().map(|group| group.size * (group.paths.len() as u64 - 1)).sum();
+    writeln!(
+        out,
+        "{} groups of identical files ({} in extra copies), {} pairs of books that look like the same work",
+        groups.len(),
+        transfer::human_bytes(extra as f64),
+        pairs.len()
+    )?;
+    if apply.is_some() {
+        writeln!(out, "Freed {}", transfer::human_bytes(freed as f64))?;
+    }
+    Ok(deleted)
+}
+
+/// Gets rid of an extra copy of a book's file (`dedupe --apply`)
+///
+/// A copy deleted gives its user data (tags, rating, bookmarks...) to the
+/// copy kept; one whose user data differs from the kept copy's (another
+/// rating, say) is left alone, for the user to settle.
+fn resolve_copy(
+    books: &mut [Book],
+    copy: &Path,
+    kept: &Path,
+    hash: &str,
+    resolution: duplicates::Resolution,
+) -> std::io::Result<()> {
+    let position = |path: &Path| books.iter().position(|book| book.path == path);
+    let merged = match (resolution, position(copy), position(kept)) {
+        (duplicates::Resolution::Delete, Some(copy), Some(kept)) => {
+            let update = merge::reconcile(&books[kept], books[copy].clone());
+            if !update.conflicts.is_empty() {
+                let fields: Vec<&str> = update.conflicts.iter().map(|conflict| conflict.field.label()).collect();
+                let text = format!("{} differ from the kept copy's", fields.join(", "));
+                return Err(std::io::Error::other(text));
+            }
+            Some((kept, update))
+        }
+        _ => None,
+    };
+    duplicates::resolve(copy, kept, hash, resolution)?;
+    if let Some((kept, update)) = merged {
+        books[kept].user = update.merged;
+        books[kept].meta = update.merged_meta;
+    }
+    Ok(())
+}
+
+/// Prints the library's totals (`stats`): a table, or JSON
+fn print_stats(books: &[Book], json: bool) -> Result<(), Failure> {
+    if books.is_empty() {
+        return Err(Failure::NoBooks("the library has no books".to_string()));
+    }
+    let stats = stats::LibraryStats::of(books);
+    let mut out = std::io::stdout().lock();
+    if json {
+        serde_json::to_writer_pretty(&mut out, &stats).map_err(std::io::Error::other)?;
+        writeln!(out)?;
+    } else {
+        for line in stats.lines() {
+            writeln!(out, "{}", line)?;
+        }
+    }
+    Ok(())
+}
+
+/// Converts a file given on the command line, showing the progress: an
+/// EPUB is written into the first folder of the library and added to it,
+/// other formats go next to the file
+fn convert_file(profile: &Profile, settings: &Settings, books: &mut Vec<Book>, source: &Path, format: convert::Format) -> std::io::Result<()> {
+    if !source.is_file() {
+        return Err(std::io::Error::other(format!("{}: no such file", source.display())));
+    }
+    let root = match format {
+        convert::Format::Epub => {
+            let root = profile.settings.scan_paths.iter().find(|root| !remote::is_remote(root));
+            Some(root.ok_or_else(|| std::io::Error::other("no library folder on this computer to add the EPUB to (funkhunt ~/Books adds one)"))?)
+        }
+        _ => None,
+    };
+    let folder = match root {
+        Some(root) => root.clone(),
+        None => source.parent().map(Path::to_path_buf).unwrap_or_default(),
+    };
+    let output = convert::output_path(source, &folder, format);
+    if output.exists() {
+        return Err(std::io::Error::other(format!("{} already exists", output.display())));
+    }
+
+    let name = source.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let result = convert::run(
+        &settings.devices.converter,
+        source,
+        &output,
+        |done| eprint!("\rConverting {} to {}: {:>3}%", name, format.label(), done),
+        || false,
+    );
+    // Past the progress line
+    eprintln!();
+    result.map_err(std::io::Error::other)?;
+
+    if let Some(root) = root {
+        let mut converted = vec![scanner::book_from_file(&output, &profile.settings.folder(root))];
+        authors::apply_aliases(&mut converted, &profile.settings.author_aliases);
+        println!("Converted {} to {}, added to the library as {}", name, output.display(), converted[0].display_title());
+        books.append(&mut converted);
+        database::save(&profile.database_path(), books)?;
+    } else {
+        println!("Converted {} to {}", name, output.display());
+    }
+    Ok(())
+}
+
+/// Queues optimizations of the marked books (or the selected one)
+///
+/// Books on servers and in read-only folders are skipped.
+fn optimize_books(profile: &Profile, state: &mut TuiState) {
+    let folders = profile.settings.folder_list();
+    let (mut queued, mut skipped) = (0, Vec::new());
+    for i in state.target_indices() {
+        let book = &state.books[i];
+        if remote::is_remote(&book.path) {
+            skipped.push(format!("{}: not on this computer", book.display_title()));
+            continue;
+        }
+        if folders.iter().any(|(root, folder)| folder.read_only && book.path.starts_with(root)) {
+            skipped.push(format!("{}: read-only folder", book.display_title()));
+            continue;
+        }
+        state.transfers.enqueue(transfer::Job::Optimize {
+            source: book.path.clone(),
+            size: std::fs::metadata(&book.path).map(|m| m.len()).unwrap_or(0),
+        });
+        queued += 1;
+    }
+
+    tracing::info!(queued, skipped = skipped.len(), "books queued for optimization");
+    state.status_message = Some(match (queued, skipped.as_slice()) {
+        (0, []) => "Nothing to optimize".to_string(),
+        (_, []) => format!("Optimizing {} books (see the transfers)", queued),
+        (0, [reason]) => format!("Not optimized - {}", reason),
+        _ => format!("Optimizing {} books (skipped {})", queued, skipped.join(", ")),
+    });
+}
+
+/// Embeds a new cover into the selected book's EPUB, on a worker thread
+/// (see cover_set)
+///
+/// # Arguments
+/// * `source` - Image file or URL, or "" for Open Library's cover of the book's ISBN
+fn set_cover(profile: &Profile, state: &mut TuiState, source: &str) {
+    let Some(book) = state.selected_book() else {
+        return;
+    };
+    let read_only = profile
+        .settings
+        .folder_list()
+        .into_iter()
+        .any(|(root, folder)| folder.read_only && book.path.starts_with(&root));
+    if read_only {
+        state.status_message = Some(format!("Cover not set - {} is in a read-only folder", book.display_title()));
+        return;
+    }
+
+    let (path, title) = (book.path.clone(), book.display_title().to_string());
+    let (source, isbn) = (source.to_string(), book.meta.isbn.clone());
+    state.status_message = Some(format!("Setting the cover of {}...", title));
+    state.workers.spawn(move || {
+        let result = cover::load(&source, isbn.as_deref())
+            .and_then(|image| epub::write_cover(&path, &image).map_err(|e| e.to_string()));
+        match &result {
+            Ok(()) => tracing::info!(path = %path.display(), source, "cover set"),
+            Err(e) => tracing::warn!(path = %path.display(), source, error = %e, "cannot set cover"),
+        }
+        AppEvent::CoverSet { path, title, result }
+    });
+}
+
+/// Tells whether a new cover was embedded into a book
+fn cover_set(state: &mut TuiState, path: PathBuf, title: String, result: Result<(), String>) {
+    match result {
+        Ok(()) => {
+            if let Some(book) = state.books.iter_mut().find(|book| book.path == path) {
+                // The content changed: its hash is computed again when needed
+                book.hash = None;
+            }
+            state.dirty = true;
+            state.status_message = Some(format!("New cover for {}", title));
+        }
+        Err(e) => state.status_message = Some(format!("Cover not set - {}", e)),
+    }
+}
+
+/// Strips or subsets the fonts of the book they were listed for, after a
+/// backup copy (see fonts.rs)
+fn slim_fonts(profile: &Profile, state: &mut TuiState, action: fonts::Action) {
+    let Some(list) = state.font_list.take() else {
+        return;
+    };
+    let name = list.path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let folder = list.path.parent().map(Path::to_path_buf).unwrap_or_default();
+    if let Some(reason) = not_writable(profile, &folder) {
+        state.status_message = Some(format!("Fonts left alone - {}", reason));
+        return;
+    }
+
+    match fonts::slim(&list.path, action) {
+        Ok(report) => {
+            if let Some(book) = state.books.iter_mut().find(|book| book.path == list.path) {
+                // The content changed: its hash is computed again when needed
+                book.hash = None;
+            }
+            state.dirty = true;
+            state.status_message = Some(format!("{}: {} (backup in {})", name, report.summary(action), report.backup.display()));
+        }
+        Err(e) => {
+            tracing::warn!(path = %list.path.display(), ?action, error = %e, "cannot slim fonts");
+            state.status_message = Some(format!("Fonts left alone - {}", e));
+        }
+    }
+}
+
+/// Plays the narration of the selected book from where listening stopped,
+/// with the [audio] player (the one playing already is stopped)
+fn listen(state: &mut TuiState) {
+    let Some(book) = state.selected_book() else {
+        return;
+    };
+    let (path, title, from) = (book.path.clone(), book.display_title().to_string(), book.user.listening.clone());
+    state.stop_listening();
+    let command = state.settings.audio.player.clone().unwrap_or_else(|| "mpv".to_string());
+
+    match audio::narration(&path).and_then(|files| audio::Player::start(&command, &path, &files, from.as_ref())) {
+        Ok(player) => state.start_listening(player),
+        Err(e) => {
+            tracing::warn!(path = %path.display(), player = command, error = %e, "cannot play narration");
+            state.status_message = Some(format!("Cannot listen to {} - {}", title, e));
+        }
+    }
+}
+
+/// Splits the selected book into a book per top-level entry of its table
+/// of contents, written next to it and added to the library
+fn split_book(profile: &Profile, state: &mut TuiState) {
+    let Some(book) = state.selected_book() else {
+        return;
+    };
+    let (path, title) = (book.path.clone(), book.display_title().to_string());
+    let folder = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    if let Some(reason) = not_writable(profile, &folder) {
+        state.status_message = Some(format!("Not split - {}", reason));
+        return;
+    }
+
+    match omnibus::split(&path, &folder) {
+        Ok(parts) => {
+            add_new_books(profile, state, &parts);
+            state.status_message = Some(format!("Split {} into {} books", title, parts.len()));
+        }
+        Err(e) => {
+            tracing::warn!(path = %path.display(), error = %e, "cannot split book");
+            state.status_message = Some(format!("Not split - {}", e));
+        }
+    }
+}
+
+/// Merges the marked books, in the order the list shows them, into an
+/// anthology written into the first one's folder and added to the library
+fn join_books(profile: &Profile, state: &mut TuiState, title: &str) {
+    let mut indices = state.target_indices();
+    indices.sort_by_key(|i| state.view.iter().position(|shown| shown == i).unwrap_or(usize::MAX));
+    let books: Vec<PathBuf> = indices.iter().map(|&i| state.books[i].path.clone()).collect();
+    if let Some(book) = books.iter().find(|book| remote::is_remote(book)) {
+        state.status_message = Some(format!("Not merged - {} is not on this computer", book.display()));
+        return;
+    }
+    let Some(folder) = books.first().and_then(|book| book.parent()).map(Path::to_path_buf) else {
+        return;
+    };
+    if let Some(reason) = not_writable(profile, &folder) {
+        state.status_message = Some(format!("Not merged - {}", reason));
+        return;
+    }
+
+    let output = peer::free_path(&folder, &format!("{}.epub", omnibus::file_name(title)));
+    match omnibus::merge(&books, title, &output) {
+        Ok(()) => {
+            add_new_books(profile, state, std::slice::from_ref(&output));
+            state.marked.clear();
+            state.status_message = Some(format!("Merged {} books into {}", books.len(), output.display()));
+        }
+        Err(e) => {
+            tracing::warn!(path = %output.display(), error = %e, "cannot merge books");
+            state.status_message = Some(format!("Not merged - {}", e));
+        }
+    }
+}
+
+/// Why new books can't be written into a folder (None if they can): it's
+/// in a read-only folder of the library
+fn not_writable(profile: &Profile, folder: &Path) -> Option<String> {
+    profile
+        .settings
+        .folder_list()
+        .into_iter()
+        .any(|(root, settings)| settings.read_only && folder.starts_with(&root))
+        .then(|| format!("{} is a read-only folder", folder.display()))
+}
+
+/// Adds books written by the app (parts of a split, an anthology) to the
+/// library, with the default tags of the library folder they're in
+fn add_new_books(profile: &Profile, state: &mut TuiState, paths: &[PathBuf]) {
+    let mut added: Vec<Book> = paths
+        .iter()
+        .map(|path| {
+            let folder_settings = profile
+                .settings
+                .scan_paths
+                .iter()
+                .find(|root| path.starts_with(root))
+                .map(|root| profile.settings.folder(root))
+                .unwrap_or_default();
+            scanner::book_from_file(path, &folder_settings)
+        })
+        .collect();
+    authors::apply_aliases(&mut added, &profile.settings.author_aliases);
+    tracing::info!(books = added.len(), "new books added");
+    state.books.append(&mut added);
+    state.refresh_view();
+    save_profile(profile, &state.books);
+    state.dirty = false;
+}
+
+/// Queues conversions of the marked books (or the selected one) to a
+/// format, each written next to its EPUB
+///
+/// Books on servers are skipped.
+fn convert_books(state: &mut TuiState, format: convert::Format) {
+    let (mut queued, mut skipped) = (0, Vec::new());
+    for i in state.target_indices() {
+        let book = &state.books[i];
+        if remote::is_remote(&book.path) {
+            skipped.push(format!("{}: not on this computer", book.display_title()));
+            continue;
+        }
+        let Some(folder) = book.path.parent() else {
+            continue;
+        };
+        state.transfers.enqueue(transfer::Job::Convert {
+            source: book.path.clone(),
+            format,
+            folder: folder.to_path_buf(),
+            command: state.settings.devices.converter.clone(),
+        });
+        queued += 1;
+    }
+
+    tracing::info!(format = format.label(), queued, skipped = skipped.len(), "books queued for conversion");
+    state.status_message = Some(match (queued, skipped.as_slice()) {
+        (0, []) => "Nothing to convert".to_string(),
+        (_, []) => format!("Converting {} books to {} (see the transfers)", queued, format.label()),
+        (0, [reason]) => format!("Not converted - {}", reason),
+        _ => format!("Converting {} books to {} (skipped {})", queued, format.label(), skipped.join(", ")),
+    });
+}
+
+/// Lists the books of the Calibre server on a worker thread (the Calibre
+/// screen opens when they arrive, see show_calibre_library)
+fn browse_calibre(state: &mut TuiState) {
+    let settings = state.settings.calibre.clone();
+    state.workers.spawn(move || {
+        AppEvent::CalibreListed(calibre::Calibre::connect(&settings).and_then(|server| server.books()))
+    });
+    state.status_message = Some("Calibre: listing the books...".to_string());
+}
+
+/// What a book listed elsewhere (on a Calibre server, in a feed) is
+/// compared with ours by: the same title and first author, whatever their
+/// case, is probably the same book
+fn ownership_key(title: &str, authors: &[String]) -> (String, String) {
+    (
+        title.trim().to_lowercase(),
+        authors.first().map(|a| a.trim().to_lowercase()).unwrap_or_default(),
+    )
+}
+
+/// Opens the Calibre screen with the books the server listed
+fn show_calibre_library(state: &mut TuiState, listed: Result<calibre::Library, String>) {
+    state.status_message = None;
+    let library = match listed {
+        Ok(library) => library,
+        Err(e) => {
+            tracing::warn!(error = %e, "cannot list the Calibre library");
+            state.status_message = Some(format!("Calibre: {}", e));
+            return;
+        }
+    };
+
+    let ours: std::collections::HashSet<_> =
+        state.books.iter().map(|book| ownership_key(book.display_title(), book.authors())).collect();
+    let owned = library
+        .books
+        .iter()
+        .filter(|book| ours.contains(&ownership_key(&book.title, &book.authors)))
+        .map(|book| book.id)
+        .collect();
+
+    // Reloading keeps the place in the list
+    let selected_index = state
+        .calibre_browser
+        .as_ref()
+        .map(|browser| browser.selected_index.min(library.books.len().saturating_sub(1)))
+        .unwrap_or(0);
+    state.calibre_browser = Some(CalibreBrowser {
+        library,
+        owned,
+        marked: Default::default(),
+        selected_index,
+    });
+    state.mode = UiMode::BrowsingCalibre;
+}
+
+/// Queues downloads of Calibre books into the incoming folder (books
+/// Calibre has in other formats only are skipped)
+fn download_from_calibre(profile: &Profile, state: &mut TuiState, books: Vec<calibre::CalibreBook>) {
+    let Some(folder) = incoming_folder(profile, state) else {
+        state.status_message = Some("Calibre: add a library folder first".to_string());
+        return;
+    };
+
+    let (epub, other): (Vec<_>, Vec<_>) = books.into_iter().partition(|book| book.has_epub);
+    for book in &epub {
+        state.transfers.enqueue(transfer::Job::FromCalibre {
+            book: Box::new(book.clone()),
+            folder: folder.clone(),
+            settings: Box::new(state.settings.calibre.clone()),
+        });
+    }
+
+    tracing::info!(queued = epub.len(), skipped = other.len(), "Calibre downloads queued");
+    state.status_message = Some(match other.len() {
+        0 => format!("Downloading {} books from Calibre", epub.len()),
+        n => format!("Downloading {} books from Calibre ({} without EPUB skipped)", epub.len(), n),
+    });
+}
+
+/// Queues uploads of the marked books (or the selected one) to Calibre
+fn push_to_calibre(state: &mut TuiState) {
+    let (mut queued, mut remote) = (0, 0);
+    for i in state.target_indices() {
+        let source = state.books[i].path.clone();
+        if remote::is_remote(&source) {
+            remote += 1;
+            continue;
+        }
+        state.transfers.enqueue(transfer::Job::ToCalibre {
+            source,
+            settings: Box::new(state.settings.calibre.clone()),
+        });
+        queued += 1;
+    }
+
+    tracing::info!(queued, remote, "Calibre uploads queued");
+    state.status_message = Some(match remote {
+        0 => format!("Adding {} books to Calibre", queued),
+        n => format!("Adding {} books to Calibre ({} on servers skipped)", queued, n),
+    });
+}
+
+/// Fetches the subscribed feeds on a worker thread (the "New from feeds"
+/// screen opens when they arrive, see show_feed_entries)
+fn browse_feeds(state: &mut TuiState) {
+    let urls = state.settings.feeds.urls.clone();
+    state.workers.spawn(move || {
+        let mut entries = Vec::new();
+        let mut failed = Vec::new();
+        for url in &urls {
+            match feeds::fetch(url) {
+                Ok(feed) => entries.extend(feed.entries),
+                Err(e) => {
+                    tracing::warn!(feed = %url, error = %e, "cannot fetch the feed");
+                    failed.push(format!("{}: {}", url, e));
+                }
+            }
+        }
+        AppEvent::FeedsFetched { entries, failed }
+    });
+    state.status_message = Some("Feeds: fetching...".to_string());
+}
+
+/// Opens the "New from feeds" screen with the entries of the feeds fetched
+///
+/// Entries not seen in an earlier fetch are the new ones; from now on
+/// they count as seen.
+fn show_feed_entries(state: &mut TuiState, mut entries: Vec<feeds::FeedEntry>, failed: Vec<String>) {
+    if entries.is_empty() && !failed.is_empty() {
+        state.status_message = Some(format!("Feeds: {}", failed.join(", ")));
+        return;
+    }
+
+    // Reloading keeps what was new when the screen opened
+    let mut seen = feeds::Seen::load();
+    let mut new: std::collections::BTreeSet<String> = entries
+        .iter()
+        .filter(|entry| !seen.ids.contains(&entry.id))
+        .map(|entry| entry.id.clone())
+        .collect();
+    if let Some(browser) = &state.feed_browser {
+        new.extend(browser.new.iter().cloned());
+    }
+    seen.ids.extend(entries.iter().map(|entry| entry.id.clone()));
+    if let Err(e) = seen.save() {
+        tracing::warn!(error = %e, "cannot store the seen feed entries");
+    }
+
+    // New ones first, then newest first (undated ones last)
+    entries.sort_by(|a, b| {
+        new.contains(&b.id)
+            .cmp(&new.contains(&a.id))
+            .then_with(|| b.date.cmp(&a.date))
+    });
+    // A book in several feeds is listed once
+    let mut listed = std::collections::BTreeSet::new();
+    entries.retain(|entry| listed.insert(entry.id.clone()));
+
+    let ours: std::collections::HashSet<_> =
+        state.books.iter().map(|book| ownership_key(book.display_title(), book.authors())).collect();
+    let owned = entries
+        .iter()
+        .filter(|entry| ours.contains(&ownership_key(&entry.title, &entry.authors)))
+        .map(|entry| entry.id.clone())
+        .collect();
+
+    tracing::info!(entries = entries.len(), new = new.len(), failed = failed.len(), "feeds fetched");
+    state.status_message = if failed.is_empty() {
+        None
+    } else {
+        Some(format!("Feeds: {}", failed.join(", ")))
+    };
+    let selected_index = state
+        .feed_browser
+        .as_ref()
+        .map(|browser| browser.selected_index.min(entries.len().saturating_sub(1)))
+        .unwrap_or(0);
+    state.feed_browser = Some(FeedBrowser {
+        entries,
+        new,
+        owned,
+        marked: Default::default(),
+        selected_index,
+    });
+    state.mode = UiMode::BrowsingFeeds;
+}
+
+/// Queues downloads of feed entries into the incoming folder (entries
+/// without an EPUB are skipped)
+fn download_from_feeds(profile: &Profile, state: &mut TuiState, entries: Vec<feeds::FeedEntry>) {
+    let Some(folder) = incoming_folder(profile, state) else {
+        state.status_message = Some("Feeds: add a library folder first".to_string());
+        return;
+    };
+
+    let (epub, other): (Vec<_>, Vec<_>) = entries.into_iter().partition(|entry| entry.epub.is_some());
+    for entry in &epub {
+        state.transfers.enqueue(transfer::Job::FromFeed {
+            entry: Box::new(entry.clone()),
+            folder: folder.clone(),
+        });
+    }
+
+    tracing::info!(queued = epub.len(), skipped = other.len(), "feed downloads queued");
+    state.status_message = Some(match other.len() {
+        0 => format!("Downloading {} books from feeds", epub.len()),
+        n => format!("Downloading {} books from feeds ({} without EPUB skipped)", epub.len(), n),
+    });
+}
+
+/// Serves the selected book on a temporary URL and shows its QR code
+fn share_book(state: &mut TuiState) {
+    // One share at a time: the previous one stops
+    state.share = None;
+    let Some(book) = state.selected_book() else {
+        return;
+    };
+    let settings = &state.settings.share;
+    let lifetime = std::time::Duration::from_secs(settings.minutes * 60);
+    match share::BookShare::start(book, settings.port, lifetime, state.transfers.clone()) {
+        Ok(share) => {
+            state.share = Some(share);
+            state.mode = UiMode::Sharing;
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, "cannot share book");
+            state.status_message = Some(format!("Cannot share: {}", e));
+        }
+    }
+}
+
+/// Fetches the books a peer shares on a worker thread (the peer browser
+/// opens when they arrive, see show_peer_catalog)
+fn browse_peer(state: &mut TuiState, peer: &discovery::Peer) {
+    let Some(address) = peer.address else {
+        state.status_message = Some(format!("{} has no known address", peer.name));
+        return;
+    };
+    let secret = secret_of(state, peer);
+    let peer = peer.clone();
+    state.status_message = Some(format!("Asking {} for its books...", peer.name));
+    state.workers.spawn(move || {
+        let result = peer::fetch_catalog(address, secret.as_ref());
+        AppEvent::PeerCatalog(Box::new(PeerReply { peer, address, secret, result }))
+    });
+}
+
+/// Opens the peer browser with the catalog a peer sent
+fn show_peer_catalog(state: &mut TuiState, reply: PeerReply) {
+    let PeerReply { peer, address, secret, result } = reply;
+    state.status_message = None;
+    match result {
+        Ok(catalog) => {
+            // Same content as one of ours (or else same file name): we have it
+            let owned = catalog
+                .books
+                .iter()
+                .filter(|entry| {
+                    state.books.iter().any(|book| match (&entry.hash, &book.hash) {
+                        (Some(theirs), Some(ours)) => theirs == ours,
+                        _ => entry.name == book.name,
+                    })
+                })
+                .map(|entry| entry.id)
+                .collect();
+            state.peer_browser = Some(PeerBrowser {
+                peer: peer.name.clone(),
+                address,
+                secret,
+                catalog,
+                owned,
+                marked: Default::default(),
+                selected_index: 0,
+            });
+            state.mode = UiMode::BrowsingPeer;
+        }
+        Err(e) => {
+            tracing::warn!(peer = %peer.name, error = %e, "cannot fetch peer catalog");
+            state.status_message = Some(format!("Cannot reach {}: {}", peer.name, e));
+        }
+    }
+}
+
+/// Watches the auto-watch roots of a profile, plus the inbox folder
+fn folder_watcher_for(profile: &Profile, settings: &Settings) -> FolderWatcher {
+    let mut roots = profile.settings.watched_roots();
+    if let Some(inbox) = &settings.inbox.folder {
+        roots.push(inbox.clone());
+    }
+    FolderWatcher::new(roots)
+}
+
+/// Imports the new books of the inbox folder into the open library
+fn import_inbox(profile: &Profile, state: &mut TuiState) {
+    let settings = &state.settings.inbox;
+    let Some(root) = settings.move_to.clone().or_else(|| profile.settings.scan_paths.first().cloned()) else {
+        state.status_message = Some("Inbox: add a library folder first".to_string());
+        return;
+    };
+
+    match inbox::import(
+        settings,
+        &root,
+        &profile.settings.folder(&root),
+        &state.settings.organize.template,
+        &state.books,
+    ) {
+        Ok(mut imported) if !imported.is_empty() => {
+            authors::apply_aliases(&mut imported, &profile.settings.author_aliases);
+            let titles: Vec<String> = imported.iter().map(|b| b.display_title().to_string()).collect();
+            state.status_message = Some(format!("Imported from inbox: {}", titles.join(", ")));
+            state.books.append(&mut imported);
+            state.refresh_view();
+            save_profile(profile, &state.books);
+            state.dirty = false;
+        }
+        Ok(_) => {}
+        Err(e) => {
+            tracing::warn!(error = %e, "cannot read inbox folder");
+            state.status_message = Some(format!("Inbox: {}", e));
+        }
+    }
+}
+
+/// Looks the selected book up with the configured providers on a worker
+/// thread (the review of the changes opens when the answer arrives, see
+/// review_metadata)
+fn fetch_metadata(state: &mut TuiState) {
+    let Some(book) = state.selected_book() else {
+        return;
+    };
+    let (path, meta, name) = (book.path.clone(), book.meta.clone(), book.name.clone());
+    let settings = state.settings.metadata.clone();
+    state.status_message = Some(format!("Looking {} up...", book.display_title()));
+    state.workers.spawn(move || {
+        let result = providers::lookup(&settings, &meta, &name);
+        AppEvent::MetadataLookedUp(Box::new(Lookup { path, meta, result }))
+    });
+}
+
+/// Opens the review of what the providers found about a book
+fn review_metadata(state: &mut TuiState, lookup: Lookup) {
+    let Lookup { path, meta, result } = lookup;
+    state.status_message = None;
+    match result {
+        Ok(Some((source, proposed))) => {
+            let changes = providers::diff(&meta, &proposed);
+            if changes.is_empty() {
+                state.status_message = Some(format!("{} has nothing new for this book", source));
+                return;
+            }
+            state.metadata_review = Some(MetadataReview {
+                path,
+                source,
+                proposed,
+                changes,
+                selected_index: 0,
+            });
+            state.mode = UiMode::ReviewingMetadata;
+        }
+        Ok(None) => state.status_message = Some("No metadata found online".to_string()),
+        Err(e) => {
+            tracing::warn!(path = %path.display(), error = %e, "metadata lookup failed");
+            state.status_message = Some(e);
+        }
+    }
+}
+
+/// Loads the books of a profile
+///
+/// # Arguments
+/// * `profile` - The library profile to load
+/// * `rescan` - Whether to rescan the scan roots instead of reading the database
+/// * `lazy` - Whether EPUBs are left unread, for the parse queue (the TUI)
+///
+/// # Returns
+/// The books of the library. Falls back to scanning when the database is empty.
+/// Fails if the database exists but can't be read or migrated.
+fn load_library(profile: &Profile, rescan: bool, lazy: bool) -> std::io::Result<Vec<Book>> {
+    let mut books = if rescan {
+        Vec::new()
+    } else {
+        database::load(&profile.database_path())?
+    };
+
+    // Nothing stored yet (or a rescan was requested) - scan the roots
+    if books.is_empty() {
+        return Ok(profile.scan_all_paths(lazy));
+    }
+    // Placeholders the TUI saved before reading them
+    if !lazy {
+        profile.fill_placeholders(&mut books);
+    }
+    Ok(books)
+}
+
+/// Opens the downloaded copy of a book of a remote folder
+///
+/// The library keeps the remote path; only the viewer gets the local copy.
+///
+/// # Arguments
+/// * `state` - Application state
+/// * `path` - Path (URL) of the book in the library
+/// * `local` - The downloaded copy
+fn open_downloaded(state: &mut TuiState, path: &std::path::Path, local: &std::path::Path) {
+    let viewer = state.settings.viewer.command.clone();
+    let Some(book) = state.books.iter_mut().find(|b| b.path == path) else {
+        return;
+    };
+    let mut copy = book.clone();
+    copy.path = local.to_path_buf();
+    match copy.open(viewer.as_deref()) {
+        Ok(()) => {
+            // Remember when, for "Recently opened"
+            book.opened = Some(crate::book::unix_now());
+            // The content can be hashed now that it's here
+            if book.hash.is_none() {
+                book.hash = hash::content_hash(local).ok();
+            }
+            state.dirty = true;
+            state.refresh_view();
+        }
+        Err(e) => {
+            tracing::warn!(path = %local.display(), error = %e, "cannot open book");
+            state.status_message = Some(format!("Cannot open book: {}", e));
+        }
+    }
+}
+
+/// Converts a profile's scan roots to Strings for display in the UI
+fn display_paths(profile: &Profile) -> Vec<String> {
+    // .iter() creates an iterator, .map() transforms each element, .collect() gathers results
+    profile
+        .settings
+        .scan_paths
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect()
+}
@@ -0,0 +1,346 @@
+// src/merge.rs
+// Library merge - brings the books and user data of another FunkHunt library
+// (its library.json database, or a `--export` JSON file) into this one
+//
+// Books are matched by content hash, then ISBN, then path. Tags,
+// collections, bookmarks and highlights are combined; other user data that
+// differs on both sides is a conflict the user settles in the merge screen,
+// field by field. Copies of the very same file (same content hash) have
+// their metadata compared too: differences there are edits (a series set
+// here, a title fixed there).
+
+use crate::book::{Book, Metadata, UserData};
+use crate::epub::normalize_isbn;
+use crate::export::{LibraryExport, EXPORT_FORMAT};
+use crate::providers::MetaField;
+use serde_json::Value;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Fields compared between the two libraries
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MergeField {
+    Rating,
+    Status,
+    Starred,
+    Archived,
+    Authors,
+
+    /// A metadata field (compared between copies of the same file only)
+    Meta(MetaField),
+}
+
+impl MergeField {
+    /// All user data fields, in the order they're compared
+    pub const ALL: [MergeField; 5] = [
+        MergeField::Rating,
+        MergeField::Status,
+        MergeField::Starred,
+        MergeField::Archived,
+        MergeField::Authors,
+    ];
+
+    /// Human-readable name, e.g. "Rating"
+    pub fn label(self) -> &'static str {
+        match self {
+            MergeField::Rating => "Rating",
+            MergeField::Status => "Status",
+            MergeField::Starred => "Starred",
+            MergeField::Archived => "Archived",
+            MergeField::Authors => "Authors",
+            // Not to be confused with the author names chosen here
+            MergeField::Meta(MetaField::Authors) => "Authors (metadata)",
+            MergeField::Meta(field) => field.label(),
+        }
+    }
+
+    /// The field's value as one line of text ("" = not set)
+    fn text(self, user: &UserData, meta: &Metadata) -> String {
+        match self {
+            MergeField::Rating => user.rating.map(|r| "★".repeat(r as usize)).unwrap_or_default(),
+            MergeField::Status => user.status.map(|s| s.label().to_string()).unwrap_or_default(),
+            MergeField::Starred => if user.starred { "yes" } else { "" }.to_string(),
+            MergeField::Archived => if user.archived { "yes" } else { "" }.to_string(),
+            MergeField::Authors => user.authors.join(", "),
+            MergeField::Meta(field) => field.text(meta),
+        }
+    }
+
+    /// Copies the field from a book to a record
+    fn copy(self, from: &Book, user: &mut UserData, meta: &mut Metadata) {
+        match self {
+            MergeField::Rating => user.rating = from.user.rating,
+            MergeField::Status => user.status = from.user.status,
+            MergeField::Starred => user.starred = from.user.starred,
+            MergeField::Archived => user.archived = from.user.archived,
+            MergeField::Authors => user.authors = from.user.authors.clone(),
+            MergeField::Meta(field) => field.copy(&from.meta, meta),
+        }
+    }
+}
+
+/// A field set differently in both libraries
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    /// Which field differs
+    pub field: MergeField,
+
+    /// Value in this library
+    pub ours: String,
+
+    /// Value in the other library
+    pub theirs: String,
+
+    /// Whether the other library's value will be used
+    pub take_theirs: bool,
+}
+
+/// Changes to one book of this library
+#[derive(Debug, Clone)]
+pub struct Update {
+    /// Path of the book in this library
+    pub path: PathBuf,
+
+    /// Title, for the merge screen
+    pub title: String,
+
+    /// The book's user data with everything that merged cleanly
+    pub merged: UserData,
+
+    /// The book's metadata with everything that merged cleanly
+    pub merged_meta: Metadata,
+
+    /// The other library's copy of the book (source of the chosen conflict values)
+    pub theirs: Book,
+
+    /// Fields the user must choose for (ours by default)
+    pub conflicts: Vec<Conflict>,
+}
+
+impl Update {
+    /// The user data and metadata the book ends up with, given the choices made
+    pub fn resolved(&self) -> (UserData, Metadata) {
+        let (mut user, mut meta) = (self.merged.clone(), self.merged_meta.clone());
+        for conflict in self.conflicts.iter().filter(|c| c.take_theirs) {
+            conflict.field.copy(&self.theirs, &mut user, &mut meta);
+        }
+        (user, meta)
+    }
+
+    /// Whether the merge changes the book
+    pub fn changes(&self, ours: &Book) -> bool {
+        self.merged != ours.user || self.merged_meta != ours.meta || !self.conflicts.is_empty()
+    }
+}
+
+/// Everything a merge would change
+#[derive(Debug, Default)]
+pub struct MergePlan {
+    /// Matched books whose user data changes
+    pub updates: Vec<Update>,
+
+    /// Books of the other library whose file exists here but isn't in this library
+    pub added: Vec<Book>,
+
+    /// Books of the other library that matched nothing and whose file isn't here
+    pub unmatched: usize,
+}
+
+impl MergePlan {
+    /// Number of conflicts over all books
+    pub fn conflict_count(&self) -> usize {
+        self.updates.iter().map(|u| u.conflicts.len()).sum()
+    }
+}
+
+/// Reads the books of another library
+///
+/// # Arguments
+/// * `source` - A library.json database, or a JSON file written by `--export`
+pub fn load_books(source: &Path) -> io::Result<Vec<Book>> {
+    let text = std::fs::read_to_string(source)?;
+    let value: Value = serde_json::from_str(&text).map_err(io::Error::other)?;
+
+    // Exports say what they are; anything else is treated as a database file
+    if value.get("format").and_then(Value::as_str) == Some(EXPORT_FORMAT) {
+        let export: LibraryExport = serde_json::from_value(value).map_err(io::Error::other)?;
+        Ok(export.books.into_iter().map(|exported| exported.book).collect())
+    } else {
+        crate::database::read(source)
+    }
+}
+
+/// Compares another library with this one
+///
+/// Hashes of this library's books are computed when the other library has
+/// hashes to compare with (and cached on the books).
+///
+/// # Arguments
+/// * `library` - The books of this library
+/// * `other` - The books of the other library
+///
+/// # Returns
+/// The changes the merge would make; books whose user data wouldn't change
+/// are left out
+pub fn plan(library: &mut [Book], other: Vec<Book>) -> MergePlan {
+    let mut plan = MergePlan::default();
+
+    for theirs in other {
+        let Some(index) = find_match(library, &theirs) else {
+            // Only files that exist on this machine can join the library
+            if theirs.path.is_file() {
+                plan.added.push(theirs);
+            } else {
+                plan.unmatched += 1;
+            }
+            continue;
+        };
+
+        let ours = &library[index];
+        let update = reconcile(ours, theirs);
+        if update.changes(ours) {
+            plan.updates.push(update);
+        }
+    }
+
+    plan
+}
+
+/// Applies a reviewed plan
+///
+/// # Returns
+/// How many books were updated and added
+pub fn apply(library: &mut Vec<Book>, plan: MergePlan) -> (usize, usize) {
+    let mut updated = 0;
+    for update in &plan.updates {
+        if let Some(book) = library.iter_mut().find(|b| b.path == update.path) {
+            let (user, meta) = update.resolved();
+            if book.user != user || book.meta != meta {
+                book.user = user;
+                book.meta = meta;
+                updated += 1;
+            }
+        }
+    }
+
+    let added = plan.added.len();
+    library.extend(plan.added);
+    (updated, added)
+}
+
+/// Finds the book of this library matching a book of the other one
+pub fn find_match(library: &mut [Book], theirs: &Book) -> Option<usize> {
+    if let Some(hash) = &theirs.hash {
+        let found = library
+            .iter_mut()
+            .position(|ours| ours.ensure_hash() == Some(hash.as_str()));
+        if found.is_some() {
+            return found;
+        }
+    }
+
+    if let Some(isbn) = theirs.meta.isbn.as_deref().map(normalize_isbn).filter(|i| !i.is_empty()) {
+        let found = library
+            .iter()
+            .position(|ours| ours.meta.isbn.as_deref().map(normalize_isbn) == Some(isbn.clone()));
+        if found.is_some() {
+            return found;
+        }
+    }
+
+    library.iter().position(|ours| ours.path == theirs.path)
+}
+
+/// Whether two books are copies of the same file (same content hash)
+pub fn same_file(a: &Book, b: &Book) -> bool {
+    matches!((&a.hash, &b.hash), (Some(x), Some(y)) if x == y)
+}
+
+/// Whether two books' metadata is compared and synced: copies of the same
+/// file whose packages were both read (a placeholder's empty metadata would
+/// wipe the other's out)
+pub fn shares_metadata(a: &Book, b: &Book) -> bool {
+    same_file(a, b) && !a.is_placeholder() && !b.is_placeholder()
+}
+
+/// The fields compared between two copies of a book: user data, and the
+/// metadata too if they're the same file (see `shares_metadata`)
+pub fn compared_fields(ours: &Book, theirs: &Book) -> Vec<MergeField> {
+    let mut fields = MergeField::ALL.to_vec();
+    if shares_metadata(ours, theirs) {
+        fields.extend(MetaField::ALL.map(MergeField::Meta));
+    }
+    fields
+}
+
+/// Merges the user data (and the metadata, for the same file) of two
+/// copies of a book
+///
+/// Tags, collections, bookmarks and highlights are combined. A field set
+/// on one side only takes that value; a field set differently on both
+/// sides becomes a conflict.
+pub fn reconcile(ours: &Book, theirs: Book) -> Update {
+    let mut merged = ours.user.clone();
+    let mut merged_meta = ours.meta.clone();
+    for tag in &theirs.user.tags {
+        if !merged.tags.contains(tag) {
+            merged.tags.push(tag.clone());
+        }
+    }
+    for collection in &theirs.user.collections {
+        if !merged.collections.contains(collection) {
+            merged.collections.push(collection.clone());
+        }
+    }
+    // A place marked on both sides keeps our label
+    for bookmark in &theirs.user.bookmarks {
+        let position = (bookmark.chapter, bookmark.block);
+        if !merged.bookmarks.iter().any(|b| (b.chapter, b.block) == position) {
+            merged.bookmarks.push(bookmark.clone());
+        }
+    }
+    merged.bookmarks.sort_by_key(|b| (b.chapter, b.block));
+    for highlight in &theirs.user.highlights {
+        if !merged.highlights.iter().any(|h| h.chapter == highlight.chapter && h.text == highlight.text) {
+            merged.highlights.push(highlight.clone());
+        }
+    }
+    merged.highlights.sort_by_key(|h| (h.chapter, h.block));
+    // The position read last wins
+    let read_at = |user: &UserData| user.position.as_ref().map(|p| p.read_at);
+    if read_at(&theirs.user) > read_at(&merged) {
+        merged.position = theirs.user.position.clone();
+    }
+    // And so does the position listened to last
+    let listened_at = |user: &UserData| user.listening.as_ref().map(|p| p.listened_at);
+    if listened_at(&theirs.user) > listened_at(&merged) {
+        merged.listening = theirs.user.listening.clone();
+    }
+
+    let mut conflicts = Vec::new();
+    for field in compared_fields(ours, &theirs) {
+        let (mine, other) = (field.text(&ours.user, &ours.meta), field.text(&theirs.user, &theirs.meta));
+        if other.is_empty() || mine == other {
+            continue;
+        }
+        if mine.is_empty() {
+            field.copy(&theirs, &mut merged, &mut merged_meta);
+        } else {
+            conflicts.push(Conflict {
+                field,
+                ours: mine,
+                theirs: other,
+                take_theirs: false,
+            });
+        }
+    }
+
+    Update {
+        path: ours.path.clone(),
+        title: ours.display_title().to_string(),
+        merged,
+        merged_meta,
+        theirs,
+        conflicts,
+    }
+}
@@ -0,0 +1,165 @@
+// src/metrics.rs
+// Prometheus metrics of serve mode, answered on /metrics
+//
+// Counters live for as long as the process; the library gauges (books,
+// bytes, books per reading status) are computed when they're scraped, the
+// size of the files once per scan. Everything is written in the Prometheus
+// text format, e.g.
+//   # HELP funkhunt_books Books in the library
+//   # TYPE funkhunt_books gauge
+//   funkhunt_books{library="default"} 1234
+
+use crate::book::{Book, ReadingStatus};
+use std::fmt::Write;
+use std::time::Duration;
+
+/// What a server counted since it started
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// When the server started (seconds since 1970)
+    started: u64,
+
+    /// Requests answered, whatever the answer
+    requests: u64,
+
+    /// Requests answered with "not found"
+    not_found: u64,
+
+    /// Answers that couldn't be sent
+    failed_responses: u64,
+
+    /// Book files sent (web page, OPDS and API downloads)
+    downloads: u64,
+
+    /// Bytes of the book files sent
+    download_bytes: u64,
+
+    /// Scans of the scan roots
+    scans: u64,
+
+    /// Time spent scanning, in all
+    scan_time: Duration,
+
+    /// How long the last scan took
+    last_scan: Option<Duration>,
+
+    /// Size of the library files as of the last scan (local files only)
+    library_bytes: u64,
+}
+
+impl Metrics {
+    /// Starts counting for a library
+    pub fn new(books: &[Book]) -> Self {
+        Self {
+            started: crate::book::unix_now(),
+            library_bytes: crate::stats::library_bytes(books),
+            ..Default::default()
+        }
+    }
+
+    /// Counts an answered request
+    pub fn request(&mut self) {
+        self.requests += 1;
+    }
+
+    /// Counts a "not found" answer
+    pub fn not_found(&mut self) {
+        self.not_found += 1;
+    }
+
+    /// Counts an answer that couldn't be sent
+    pub fn failed_response(&mut self) {
+        self.failed_responses += 1;
+    }
+
+    /// Counts a book file sent
+    pub fn download(&mut self, bytes: u64) {
+        self.downloads += 1;
+        self.download_bytes += bytes;
+    }
+
+    /// Counts a scan and how long it took
+    ///
+    /// # Arguments
+    /// * `took` - Duration of the scan
+    /// * `books` - The library after the scan
+    pub fn scan(&mut self, took: Duration, books: &[Book]) {
+        self.scans += 1;
+        self.scan_time += took;
+        self.last_scan = Some(took);
+        self.library_bytes = crate::stats::library_bytes(books);
+    }
+
+    /// Writes the metrics in the Prometheus text format
+    ///
+    /// # Arguments
+    /// * `library` - Name of the library profile (the `library` label)
+    /// * `books` - The books of the library
+    pub fn render(&self, library: &str, books: &[Book]) -> String {
+        let label = format!("library=\"{}\"", library.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, values: &[(String, String)]| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (labels, value) in values {
+                let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+            }
+        };
+        let one = |value: String| vec![(label.clone(), value)];
+
+        metric("funkhunt_books", "gauge", "Books in the library", &one(books.len().to_string()));
+        let statuses: Vec<(String, String)> = ReadingStatus::ALL
+            .iter()
+            .map(|status| {
+                let count = books.iter().filter(|book| book.user.status == Some(*status)).count();
+                (format!("{},status=\"{}\"", label, status.key()), count.to_string())
+            })
+            .collect();
+        metric("funkhunt_books_by_status", "gauge", "Books per reading status", &statuses);
+        metric(
+            "funkhunt_library_bytes",
+            "gauge",
+            "Size of the library files as of the last scan (local files only)",
+            &one(self.library_bytes.to_string()),
+        );
+        metric(
+            "funkhunt_start_time_seconds",
+            "gauge",
+            "When the server started (seconds since 1970)",
+            &one(self.started.to_string()),
+        );
+        metric("funkhunt_requests_total", "counter", "HTTP requests answered", &one(self.requests.to_string()));
+        metric(
+            "funkhunt_errors_total",
+            "counter",
+            "Requests that failed, by kind",
+            &[
+                (format!("{},kind=\"not_found\"", label), self.not_found.to_string()),
+                (format!("{},kind=\"response\"", label), self.failed_responses.to_string()),
+            ],
+        );
+        metric("funkhunt_downloads_total", "counter", "Book files sent", &one(self.downloads.to_string()));
+        metric(
+            "funkhunt_download_bytes_total",
+            "counter",
+            "Bytes of the book files sent",
+            &one(self.download_bytes.to_string()),
+        );
+        metric("funkhunt_scans_total", "counter", "Scans of the scan roots", &one(self.scans.to_string()));
+        metric(
+            "funkhunt_scan_duration_seconds_total",
+            "counter",
+            "Time spent scanning",
+            &one(format!("{:.3}", self.scan_time.as_secs_f64())),
+        );
+        if let Some(last) = self.last_scan {
+            metric(
+                "funkhunt_last_scan_duration_seconds",
+                "gauge",
+                "How long the last scan took",
+                &one(format!("{:.3}", last.as_secs_f64())),
+            );
+        }
+        out
+    }
+}
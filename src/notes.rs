@@ -0,0 +1,158 @@
+// src/notes.rs
+// Highlights exported as notes - the passages highlighted in the built-in
+// reader written as Markdown or Org, book after book, chapter after chapter
+//
+// Markdown:
+//   ## Dune - Frank Herbert
+//
+//   ### Book One: Dune
+//
+//   > Fear is the mind-killer.
+//
+//   *2026-10-15*
+//
+// Org:
+//   * Dune - Frank Herbert
+//   ** Book One: Dune
+//   #+begin_quote
+//   Fear is the mind-killer.
+//   #+end_quote
+//   [2026-10-15]
+//
+// A whole library starts with a title of its own, and books without
+// highlights are left out.
+
+use crate::book::{date_text, Book};
+use serde::Deserialize;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// How notes are written
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotesFormat {
+    #[default]
+    Markdown,
+
+    /// Emacs Org mode
+    Org,
+}
+
+impl NotesFormat {
+    /// The format a file name asks for: Org for .org files, else Markdown
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("org") => NotesFormat::Org,
+            _ => NotesFormat::Markdown,
+        }
+    }
+
+    /// Extension of the files in this format, e.g. "md"
+    pub fn extension(self) -> &'static str {
+        match self {
+            NotesFormat::Markdown => "md",
+            NotesFormat::Org => "org",
+        }
+    }
+}
+
+/// The highlights of some books as notes
+///
+/// # Arguments
+/// * `title` - Title of the whole document (None = the books' own headings only)
+/// * `books` - The books (those without highlights are skipped)
+/// * `format` - Markdown or Org
+pub fn render(title: Option<&str>, books: &[&Book], format: NotesFormat) -> String {
+    let mut out = String::new();
+    // Markdown books are level 2 headings under the title, or level 1 alone
+    let level = match (format, title) {
+        (NotesFormat::Markdown, Some(_)) => 2,
+        _ => 1,
+    };
+    if let Some(title) = title {
+        match format {
+            NotesFormat::Markdown => out.push_str(&format!("# {}\n\n", title)),
+            NotesFormat::Org => out.push_str(&format!("#+title: {}\n\n", title)),
+        }
+    }
+
+    for book in books.iter().filter(|book| !book.user.highlights.is_empty()) {
+        let name = match book.authors() {
+            [] => book.display_title().to_string(),
+            authors => format!("{} - {}", book.display_title(), authors.join(", ")),
+        };
+        out.push_str(&heading(format, level, &name));
+
+        let mut chapter = None;
+        for highlight in &book.user.highlights {
+            if chapter != Some(&highlight.chapter_title) && !highlight.chapter_title.is_empty() {
+                out.push_str(&heading(format, level + 1, &highlight.chapter_title));
+            }
+            chapter = Some(&highlight.chapter_title);
+
+            let date = date_text(highlight.created);
+            match format {
+                NotesFormat::Markdown => {
+                    let quote: Vec<String> = highlight.text.lines().map(|line| format!("> {}", line)).collect();
+                    out.push_str(&format!("{}\n\n*{}*\n\n", quote.join("\n>\n"), date));
+                }
+                NotesFormat::Org => {
+                    out.push_str(&format!("#+begin_quote\n{}\n#+end_quote\n[{}]\n\n", highlight.text, date));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// A heading of some level ("## Title" or "** Title"), with the blank
+/// line Markdown wants after it
+fn heading(format: NotesFormat, level: usize, text: &str) -> String {
+    match format {
+        NotesFormat::Markdown => format!("{} {}\n\n", "#".repeat(level), text),
+        NotesFormat::Org => format!("{} {}\n", "*".repeat(level), text),
+    }
+}
+
+/// Writes the highlights of a whole library to a file
+///
+/// # Arguments
+/// * `library` - Name of the library profile (the document's title)
+/// * `books` - The books of the library
+/// * `dest` - Output file (.org for Org, else Markdown), or `-` for stdout
+///
+/// # Returns
+/// The number of highlights written
+pub fn export_library(library: &str, books: &[Book], dest: &Path) -> io::Result<usize> {
+    let format = NotesFormat::for_path(dest);
+    let books: Vec<&Book> = books.iter().collect();
+    let notes = render(Some(&format!("Highlights - {}", library)), &books, format);
+
+    if dest == Path::new("-") {
+        io::stdout().lock().write_all(notes.as_bytes())?;
+    } else {
+        std::fs::write(dest, notes)?;
+    }
+    Ok(books.iter().map(|book| book.user.highlights.len()).sum())
+}
+
+/// Writes the highlights of one book to a file named after it in a folder
+///
+/// # Arguments
+/// * `book` - The book
+/// * `folder` - Where the notes go (created if needed)
+/// * `format` - Markdown or Org
+///
+/// # Returns
+/// The file written
+pub fn export_book(book: &Book, folder: &Path, format: NotesFormat) -> io::Result<PathBuf> {
+    std::fs::create_dir_all(folder)?;
+    let name: String = book
+        .display_title()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || " -_.,'()".contains(c) { c } else { '_' })
+        .collect();
+    let dest = folder.join(format!("{}.{}", name.trim(), format.extension()));
+    std::fs::write(&dest, render(None, &[book], format))?;
+    Ok(dest)
+}
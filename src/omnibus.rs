@@ -0,0 +1,556 @@
+// src/omnibus.rs
+// Omnibus EPUBs split into their parts, and EPUBs merged into an anthology
+// (`funkhunt split BOOK` and `funkhunt join OUTPUT BOOK...`, or B and J in
+// the app)
+//
+// A split makes a book of each top-level entry of the table of contents:
+// the documents of the spine from the one the entry points to up to the
+// next entry's, plus everything of the omnibus that isn't one of them
+// (style sheets, fonts, images, notes...) - what a part doesn't use is then
+// removed by optimize.rs. Front matter before the first entry is left out.
+// The files keep their paths, so the links between them hold. Each part
+// gets a package of its own: the entry's title, the omnibus's authors and
+// language, and the omnibus as its series.
+//
+// A merge puts the files of each book in a folder of their own ("1/",
+// "2/"... next to the new package), their manifests and spines one after
+// the other. The anthology has the authors of all the books and the cover
+// of the first.
+//
+// Both write EPUB3 packages with a table of contents - the entries under
+// the part's, or an entry per book with the book's own under it - as a
+// navigation document and an NCX (for EPUB2 readers).
+
+use crate::epub::{self, Item};
+use crate::opds::{escape, percent_encode};
+use crate::reader::{self, Link};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zip::write::FileOptions;
+use zip::CompressionMethod;
+
+/// Where the package of an anthology goes, its books' folders next to it
+const ANTHOLOGY_PACKAGE: &str = "OEBPS/content.opf";
+
+/// File names of the navigation document and NCX written next to the package
+const NAV_FILE: &str = "funkhunt-nav.xhtml";
+const NCX_FILE: &str = "funkhunt-toc.ncx";
+
+/// A package written from scratch
+struct Package {
+    title: String,
+    authors: Vec<String>,
+    language: Option<String>,
+
+    /// Series and position in it
+    series: Option<(String, usize)>,
+
+    /// Id of the cover image item
+    cover: Option<String>,
+
+    /// Manifest, hrefs relative to the package (the navigation document
+    /// and NCX are added when it's written)
+    items: Vec<Item>,
+
+    /// Ids of the documents in reading order, with whether each is linear
+    spine: Vec<(String, bool)>,
+
+    /// Table of contents, hrefs relative to the package
+    contents: Vec<Link>,
+}
+
+/// Splits an omnibus into its parts, one book per top-level entry of its
+/// table of contents
+///
+/// # Arguments
+/// * `path` - The omnibus
+/// * `folder` - Where the parts go ("{omnibus} - 01 - {part}.epub"...)
+///
+/// # Returns
+/// The parts written, in order
+pub fn split(path: &Path, folder: &Path) -> io::Result<Vec<PathBuf>> {
+    let meta = epub::read_metadata(path)?;
+    let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(io::Error::other)?;
+    let (opf_path, opf) = epub::read_package(&mut archive)?;
+    let (items, cover_id) = epub::read_manifest(&opf)?;
+    let spine = epub::read_spine(&opf)?;
+    let links = reader::read_toc(&mut archive, &opf_path, &opf)?;
+    let cover = epub::cover_item(&items, cover_id.as_deref()).map(|item| item.id.clone());
+
+    // The omnibus's own tables of contents are replaced
+    let dropped: Vec<&Item> = items.iter().filter(|item| is_toc(item)).collect();
+    let spine: Vec<(String, bool)> = spine
+        .into_iter()
+        .filter(|(id, _)| !dropped.iter().any(|item| item.id == *id))
+        .collect();
+    let item = |id: &str| items.iter().find(|item| item.id == id);
+    let documents: Vec<String> = spine
+        .iter()
+        .map(|(id, _)| item(id).map(|item| epub::entry_path(&opf_path, &item.href)).unwrap_or_default())
+        .collect();
+
+    // Where each part starts: its entry among the links and its first
+    // document in the spine (entries pointing into the previous part's
+    // documents belong to it)
+    let mut starts: Vec<(usize, usize)> = Vec::new();
+    for (l, link) in links.iter().enumerate().filter(|(_, link)| link.depth == 0) {
+        let target = link.href.split('#').next().unwrap_or_default();
+        let Some(document) = documents.iter().position(|document| document == target) else {
+            continue;
+        };
+        if starts.last().is_some_and(|&(_, last)| document <= last) {
+            continue;
+        }
+        starts.push((l, document));
+    }
+    if starts.len() < 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the table of contents has no parts to split into",
+        ));
+    }
+
+    let omnibus = meta.title.clone().unwrap_or_else(|| file_stem(path));
+    let mut parts = Vec::new();
+    for (p, &(l, first)) in starts.iter().enumerate() {
+        let (next_link, next_document) = starts.get(p + 1).copied().unwrap_or((links.len(), documents.len()));
+        let title = match links[l].title.trim() {
+            "" => format!("Part {}", p + 1),
+            title => title.to_string(),
+        };
+
+        // The part's documents, and the notes of the whole book
+        let part_spine: Vec<(String, bool)> = spine
+            .iter()
+            .enumerate()
+            .filter(|&(d, (_, linear))| !linear || (first..next_document).contains(&d))
+            .map(|(_, itemref)| itemref.clone())
+            .collect();
+        let left_out = |id: &str| {
+            spine.iter().any(|(spine_id, _)| spine_id == id) && !part_spine.iter().any(|(part_id, _)| part_id == id)
+        };
+        let kept: Vec<&Item> = items.iter().filter(|item| !is_toc(item) && !left_out(&item.id)).collect();
+
+        let mut contents: Vec<Link> = links[l + 1..next_link]
+            .iter()
+            .map(|link| Link {
+                title: link.title.clone(),
+                href: relative_href(&opf_path, &link.href),
+                depth: link.depth.saturating_sub(1),
+            })
+            .collect();
+        if contents.is_empty() {
+            contents.push(Link {
+                title: title.clone(),
+                href: relative_href(&opf_path, &documents[first]),
+                depth: 0,
+            });
+        }
+
+        let package = Package {
+            title: title.clone(),
+            authors: meta.authors.clone(),
+            language: meta.language.clone(),
+            series: Some((omnibus.clone(), p + 1)),
+            cover: cover.clone().filter(|id| kept.iter().any(|item| item.id == *id)),
+            items: kept.iter().map(|&item| item.clone()).collect(),
+            spine: part_spine,
+            contents,
+        };
+
+        // Everything but what the part leaves out, as it is
+        let skipped: Vec<String> = items
+            .iter()
+            .filter(|item| !kept.iter().any(|kept| kept.id == item.id))
+            .map(|item| epub::entry_path(&opf_path, &item.href))
+            .collect();
+        let name = format!("{} - {:02} - {}.epub", file_stem(path), p + 1, file_name(&title));
+        let dest = crate::peer::free_path(folder, &name);
+        write_book(&dest, &opf_path, &package, |writer| {
+            for i in 0..archive.len() {
+                let entry = archive.by_index_raw(i).map_err(io::Error::other)?;
+                let name = entry.name().to_string();
+                let written = ["mimetype", "META-INF/container.xml", opf_path.as_str()];
+                if entry.is_dir() || written.contains(&name.as_str()) || skipped.contains(&name) {
+                    continue;
+                }
+                writer.raw_copy_file(entry).map_err(io::Error::other)?;
+            }
+            Ok(())
+        })?;
+
+        // Images, fonts... of the other parts go
+        if let Err(e) = crate::optimize::optimize(&dest, |_| {}, || false) {
+            tracing::warn!(path = %dest.display(), error = %e, "cannot remove what the part doesn't use");
+        }
+        parts.push(dest);
+    }
+
+    tracing::info!(path = %path.display(), parts = parts.len(), "omnibus split");
+    Ok(parts)
+}
+
+/// Merges books into one anthology, the books in the order given
+///
+/// # Arguments
+/// * `books` - The EPUBs to merge
+/// * `title` - Title of the anthology
+/// * `output` - The EPUB to write (replaced if it exists)
+pub fn merge(books: &[PathBuf], title: &str, output: &Path) -> io::Result<()> {
+    let mut package = Package {
+        title: title.to_string(),
+        authors: Vec::new(),
+        language: None,
+        series: None,
+        cover: None,
+        items: Vec::new(),
+        spine: Vec::new(),
+        contents: Vec::new(),
+    };
+    let mut archives = Vec::new();
+    for (b, book) in books.iter().enumerate() {
+        let n = b + 1;
+        let meta = epub::read_metadata(book)?;
+        let mut archive = zip::ZipArchive::new(File::open(book)?).map_err(io::Error::other)?;
+        let (opf_path, opf) = epub::read_package(&mut archive)?;
+        let (items, cover_id) = epub::read_manifest(&opf)?;
+        let spine = epub::read_spine(&opf)?;
+        let links = reader::read_toc(&mut archive, &opf_path, &opf)?;
+        let cover = epub::cover_item(&items, cover_id.as_deref()).map(|item| item.id.clone());
+
+        for author in meta.authors {
+            if !package.authors.contains(&author) {
+                package.authors.push(author);
+            }
+        }
+        package.language = package.language.or(meta.language);
+
+        // "Text/ch1.xhtml" of the second book -> "OEBPS/2/Text/ch1.xhtml"
+        let folder = epub::entry_path(ANTHOLOGY_PACKAGE, &n.to_string());
+        let moved = |path: &str| format!("{}/{}", folder, path);
+        let id = |id: &str| format!("b{}-{}", n, id);
+
+        let mut dropped = vec![opf_path.clone()];
+        for item in &items {
+            if is_toc(item) {
+                dropped.push(epub::entry_path(&opf_path, &item.href));
+                continue;
+            }
+            let is_cover = cover.as_deref() == Some(item.id.as_str());
+            if is_cover && b == 0 {
+                package.cover = Some(id(&item.id));
+            }
+            package.items.push(Item {
+                id: id(&item.id),
+                href: relative_href(ANTHOLOGY_PACKAGE, &moved(&epub::entry_path(&opf_path, &item.href))),
+                media_type: item.media_type.clone(),
+                properties: item
+                    .properties
+                    .split_whitespace()
+                    .filter(|&p| p != "cover-image" || (is_cover && b == 0))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            });
+        }
+
+        let spine: Vec<(String, bool)> = spine
+            .into_iter()
+            .filter(|(spine_id, _)| items.iter().any(|item| item.id == *spine_id && !is_toc(item)))
+            .collect();
+        let first = spine
+            .iter()
+            .find(|(_, linear)| *linear)
+            .and_then(|(spine_id, _)| items.iter().find(|item| item.id == *spine_id))
+            .map(|item| epub::entry_path(&opf_path, &item.href));
+        let Some(first) = first else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} has no text", book.display()),
+            ));
+        };
+        package.contents.push(Link {
+            title: meta.title.unwrap_or_else(|| file_stem(book)),
+            href: relative_href(ANTHOLOGY_PACKAGE, &moved(&first)),
+            depth: 0,
+        });
+        package.contents.extend(links.into_iter().map(|link| Link {
+            title: link.title,
+            href: relative_href(ANTHOLOGY_PACKAGE, &moved(&link.href)),
+            depth: link.depth + 1,
+        }));
+        package.spine.extend(spine.into_iter().map(|(spine_id, linear)| (id(&spine_id), linear)));
+
+        archives.push((archive, folder, dropped));
+    }
+
+    write_book(output, ANTHOLOGY_PACKAGE, &package, |writer| {
+        for (archive, folder, dropped) in &mut archives {
+            for i in 0..archive.len() {
+                let entry = archive.by_index_raw(i).map_err(io::Error::other)?;
+                let name = entry.name().to_string();
+                if entry.is_dir() || name == "mimetype" || name.starts_with("META-INF/") || dropped.contains(&name) {
+                    continue;
+                }
+                writer.raw_copy_file_rename(entry, format!("{}/{}", folder, name)).map_err(io::Error::other)?;
+            }
+        }
+        Ok(())
+    })?;
+    tracing::info!(path = %output.display(), books = books.len(), "books merged");
+    Ok(())
+}
+
+/// Whether a manifest item is a table of contents (navigation document or NCX)
+fn is_toc(item: &Item) -> bool {
+    item.properties.split_whitespace().any(|p| p == "nav") || item.media_type == "application/x-dtbncx+xml"
+}
+
+/// Writes an EPUB: the mimetype, the container, the package with its
+/// navigation document and NCX, then the files `copy` adds (to a temporary
+/// file first, see epub::put_in_place)
+///
+/// # Arguments
+/// * `path` - The EPUB to write
+/// * `opf_path` - Where the package goes inside it
+/// * `package` - The package
+/// * `copy` - Adds the books' files
+fn write_book(
+    path: &Path,
+    opf_path: &str,
+    package: &Package,
+    copy: impl FnOnce(&mut zip::ZipWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let identifier = identifier()?;
+    let tmp_path = epub::temp_path(path);
+    let write = || -> io::Result<()> {
+        let mut writer = zip::ZipWriter::new(File::create(&tmp_path)?);
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        // The mimetype first and stored, as EPUB wants
+        writer.start_file("mimetype", stored).map_err(io::Error::other)?;
+        writer.write_all(b"application/epub+zip")?;
+        writer.start_file("META-INF/container.xml", deflated).map_err(io::Error::other)?;
+        writer.write_all(container(opf_path).as_bytes())?;
+        for (file, content) in [
+            (opf_path.to_string(), package.opf(&identifier)),
+            (epub::entry_path(opf_path, NAV_FILE), package.nav()),
+            (epub::entry_path(opf_path, NCX_FILE), package.ncx(&identifier)),
+        ] {
+            writer.start_file(file, deflated).map_err(io::Error::other)?;
+            writer.write_all(content.as_bytes())?;
+        }
+
+        copy(&mut writer)?;
+        writer.finish().map_err(io::Error::other)?;
+        Ok(())
+    };
+    if let Err(e) = write() {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+    epub::put_in_place(&tmp_path, path)
+}
+
+/// META-INF/container.xml pointing to a package
+fn container(opf_path: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n  \
+         <rootfiles>\n    \
+         <rootfile full-path=\"{}\" media-type=\"application/oebps-package+xml\"/>\n  \
+         </rootfiles>\n\
+         </container>\n",
+        escape(opf_path)
+    )
+}
+
+impl Package {
+    /// The OPF package document
+    fn opf(&self, identifier: &str) -> String {
+        let mut opf = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        opf.push_str("<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\">\n");
+        opf.push_str("  <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n");
+        let _ = writeln!(opf, "    <dc:identifier id=\"book-id\">{}</dc:identifier>", escape(identifier));
+        let _ = writeln!(opf, "    <dc:title>{}</dc:title>", escape(&self.title));
+        for author in &self.authors {
+            let _ = writeln!(opf, "    <dc:creator>{}</dc:creator>", escape(author));
+        }
+        let _ = writeln!(opf, "    <dc:language>{}</dc:language>", escape(self.language.as_deref().unwrap_or("en")));
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let _ = writeln!(opf, "    <meta property=\"dcterms:modified\">{}</meta>", crate::opds::rfc3339(now));
+        if let Some((series, index)) = &self.series {
+            let _ = writeln!(opf, "    <meta name=\"calibre:series\" content=\"{}\"/>", escape(series));
+            let _ = writeln!(opf, "    <meta name=\"calibre:series_index\" content=\"{}\"/>", index);
+            let _ = writeln!(opf, "    <meta property=\"belongs-to-collection\" id=\"series\">{}</meta>", escape(series));
+            opf.push_str("    <meta refines=\"#series\" property=\"collection-type\">series</meta>\n");
+            let _ = writeln!(opf, "    <meta refines=\"#series\" property=\"group-position\">{}</meta>", index);
+        }
+        if let Some(cover) = &self.cover {
+            let _ = writeln!(opf, "    <meta name=\"cover\" content=\"{}\"/>", escape(cover));
+        }
+        opf.push_str("  </metadata>\n  <manifest>\n");
+
+        let _ = writeln!(opf, "    <item id=\"funkhunt-nav\" href=\"{}\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>", NAV_FILE);
+        let _ = writeln!(opf, "    <item id=\"funkhunt-ncx\" href=\"{}\" media-type=\"application/x-dtbncx+xml\"/>", NCX_FILE);
+        for item in &self.items {
+            let properties = if self.cover.as_deref() == Some(item.id.as_str()) {
+                cover_properties(&item.properties)
+            } else {
+                item.properties.clone()
+            };
+            let _ = write!(
+                opf,
+                "    <item id=\"{}\" href=\"{}\" media-type=\"{}\"",
+                escape(&item.id),
+                escape(&item.href),
+                escape(&item.media_type)
+            );
+            if !properties.is_empty() {
+                let _ = write!(opf, " properties=\"{}\"", escape(&properties));
+            }
+            opf.push_str("/>\n");
+        }
+
+        opf.push_str("  </manifest>\n  <spine toc=\"funkhunt-ncx\">\n");
+        for (id, linear) in &self.spine {
+            let linear = if *linear { "" } else { " linear=\"no\"" };
+            let _ = writeln!(opf, "    <itemref idref=\"{}\"{}/>", escape(id), linear);
+        }
+        opf.push_str("  </spine>\n</package>\n");
+        opf
+    }
+
+    /// The EPUB3 navigation document, its `<ol>`s nested as the entries are
+    fn nav(&self) -> String {
+        let mut nav = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n");
+        nav.push_str("<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\">\n");
+        let _ = writeln!(nav, "<head><title>{}</title></head>", escape(&self.title));
+        nav.push_str("<body>\n<nav epub:type=\"toc\" id=\"toc\">\n");
+        let _ = writeln!(nav, "<h1>{}</h1>", escape(&self.title));
+        nav.push_str("<ol>");
+        let depths = depths(&self.contents);
+        for (i, (link, &depth)) in self.contents.iter().zip(&depths).enumerate() {
+            let previous = if i == 0 { 0 } else { depths[i - 1] };
+            if i > 0 && depth > previous {
+                nav.push_str("<ol>");
+            } else if i > 0 {
+                nav.push_str("</li>");
+                nav.push_str(&"</ol></li>".repeat(previous - depth));
+            }
+            let _ = write!(
+                nav,
+                "\n{}<li><a href=\"{}\">{}</a>",
+                "  ".repeat(depth + 1),
+                escape(&link.href),
+                escape(&link.title)
+            );
+        }
+        if let Some(&last) = depths.last() {
+            nav.push_str("</li>");
+            nav.push_str(&"</ol></li>".repeat(last));
+        }
+        nav.push_str("\n</ol>\n</nav>\n</body>\n</html>\n");
+        nav
+    }
+
+    /// The EPUB2 NCX, its navPoints nested as the entries are
+    fn ncx(&self, identifier: &str) -> String {
+        let mut ncx = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        ncx.push_str("<ncx xmlns=\"http://www.daisy.org/z3986/2005/ncx/\" version=\"2005-1\">\n<head>\n");
+        let _ = writeln!(ncx, "  <meta name=\"dtb:uid\" content=\"{}\"/>", escape(identifier));
+        ncx.push_str("</head>\n");
+        let _ = writeln!(ncx, "<docTitle><text>{}</text></docTitle>", escape(&self.title));
+        ncx.push_str("<navMap>");
+        let depths = depths(&self.contents);
+        for (i, (link, &depth)) in self.contents.iter().zip(&depths).enumerate() {
+            if i > 0 && depth <= depths[i - 1] {
+                ncx.push_str(&"</navPoint>".repeat(depths[i - 1] - depth + 1));
+            }
+            let _ = write!(
+                ncx,
+                "\n{}<navPoint id=\"point-{}\" playOrder=\"{}\"><navLabel><text>{}</text></navLabel><content src=\"{}\"/>",
+                "  ".repeat(depth + 1),
+                i + 1,
+                i + 1,
+                escape(&link.title),
+                escape(&link.href)
+            );
+        }
+        if let Some(&last) = depths.last() {
+            ncx.push_str(&"</navPoint>".repeat(last + 1));
+        }
+        ncx.push_str("\n</navMap>\n</ncx>\n");
+        ncx
+    }
+}
+
+/// Nesting levels of entries that nest properly: the first at the top,
+/// each at most one level under the one before it
+fn depths(contents: &[Link]) -> Vec<usize> {
+    let mut depths: Vec<usize> = Vec::with_capacity(contents.len());
+    for link in contents {
+        let deepest = depths.last().map_or(0, |&previous| previous + 1);
+        depths.push(link.depth.min(deepest));
+    }
+    depths
+}
+
+/// The properties of the cover image's item, with "cover-image"
+fn cover_properties(properties: &str) -> String {
+    if properties.split_whitespace().any(|p| p == "cover-image") {
+        return properties.to_string();
+    }
+    format!("{} cover-image", properties).trim().to_string()
+}
+
+/// Link from a file of an archive to another, both paths inside it
+/// ("OEBPS/content.opf", "OEBPS/Text/a b.xhtml#c" -> "Text/a%20b.xhtml#c")
+fn relative_href(base: &str, path: &str) -> String {
+    let (path, fragment) = path.split_once('#').unwrap_or((path, ""));
+    let mut from: Vec<&str> = base.split('/').collect();
+    from.pop();
+    let to: Vec<&str> = path.split('/').collect();
+    let common = from.iter().zip(&to[..to.len() - 1]).take_while(|(a, b)| a == b).count();
+
+    let mut parts: Vec<String> = vec!["..".to_string(); from.len() - common];
+    parts.extend(to[common..].iter().map(|part| percent_encode(part)));
+    let mut href = parts.join("/");
+    if !fragment.is_empty() {
+        href.push('#');
+        href.push_str(fragment);
+    }
+    href
+}
+
+/// A new unique identifier for a book, e.g. "urn:uuid:0f8a...-..."
+fn identifier() -> io::Result<String> {
+    let mut bytes = [0u8; 16];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| io::Error::other("no randomness for the book's identifier"))?;
+    // A version 4 (random) UUID
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(format!("urn:uuid:{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..]))
+}
+
+/// Name of a file, without its extension
+fn file_stem(path: &Path) -> String {
+    path.file_stem().unwrap_or_default().to_string_lossy().to_string()
+}
+
+/// A title as part of a file name (characters file systems dislike replaced)
+pub fn file_name(title: &str) -> String {
+    title
+        .chars()
+        .map(|c| if c.is_alphanumeric() || " -_.,'()".contains(c) { c } else { '_' })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
@@ -0,0 +1,388 @@
+// src/opds.rs
+// OPDS 1.2 catalog generation - Atom feeds that ereader apps (KOReader, Moon+ Reader...)
+// use to browse a library and download books
+
+use crate::book::Book;
+use crate::sort;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of entries per feed page
+pub const PAGE_SIZE: usize = 50;
+
+/// Media type of navigation feeds (lists of other feeds)
+const NAVIGATION_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=navigation";
+
+/// Media type of acquisition feeds (lists of downloadable books)
+const ACQUISITION_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=acquisition";
+
+/// Builds the feeds of one library
+///
+/// All hrefs are absolute paths on the serving host:
+/// - `{base}` - root navigation feed
+/// - `{base}/titles` - every book sorted by title (paged)
+/// - `{base}/authors` - one navigation entry per author (paged)
+/// - `{base}/authors/{name}` - books of one author (paged)
+/// - `{download}/{index}` - the EPUB file of `books[index]`
+pub struct Catalog<'a> {
+    /// Name of the library profile (used in titles and ids)
+    library: &'a str,
+
+    /// Books of the library - their index is their download id
+    books: &'a [Book],
+
+    /// Path prefix of the catalog feeds, e.g. "/opds"
+    base: &'a str,
+
+    /// Path prefix of book downloads, e.g. "/download"
+    download: &'a str,
+
+    /// Timestamp used for every <updated> element of this catalog
+    updated: String,
+}
+
+impl<'a> Catalog<'a> {
+    /// Creates a catalog for the given books
+    ///
+    /// # Arguments
+    /// * `library` - Name of the library profile
+    /// * `books` - Books to publish
+    /// * `base` - Path prefix of the feeds (e.g. "/opds")
+    /// * `download` - Path prefix of downloads (e.g. "/download")
+    pub fn new(library: &'a str, books: &'a [Book], base: &'a str, download: &'a str) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        Self {
+            library,
+            books,
+            base,
+            download,
+            updated: rfc3339(now),
+        }
+    }
+
+    /// Root navigation feed with the "By title" and "By author" entries
+    pub fn root(&self) -> String {
+        let mut xml = self.feed_start("root", &format!("FunkHunt: {}", self.library), self.base, NAVIGATION_TYPE);
+
+        self.navigation_entry(
+            &mut xml,
+            "titles",
+            "By title",
+            &format!("All {} books sorted by title", self.books.len()),
+            &format!("{}/titles", self.base),
+            ACQUISITION_TYPE,
+        );
+        self.navigation_entry(
+            &mut xml,
+            "authors",
+            "By author",
+            "Books grouped by author",
+            &format!("{}/authors", self.base),
+            NAVIGATION_TYPE,
+        );
+
+        xml.push_str("</feed>\n");
+        xml
+    }
+
+    /// Acquisition feed with every book, sorted by title
+    ///
+    /// # Arguments
+    /// * `page` - 1-based page number
+    pub fn titles(&self, page: usize) -> String {
+        // Indices of all books, sorted by title (collated, see sort.rs)
+        let mut indices: Vec<usize> = (0..self.books.len()).collect();
+        indices.sort_by_cached_key(|&i| sort::title_key(self.books[i].display_title()));
+
+        let href = format!("{}/titles", self.base);
+        self.acquisition_feed("titles", "All books by title", &href, &indices, page)
+    }
+
+    /// Navigation feed with one entry per author
+    ///
+    /// # Arguments
+    /// * `page` - 1-based page number
+    pub fn authors(&self, page: usize) -> String {
+        let href = format!("{}/authors", self.base);
+        let mut xml = self.feed_start("authors", "Authors", &href, NAVIGATION_TYPE);
+
+        // Authors collated, as in the book list (see sort.rs)
+        let mut authors: Vec<(String, Vec<usize>)> = self.books_by_author().into_iter().collect();
+        authors.sort_by_cached_key(|(author, _)| sort::collation_key(author));
+        let total = authors.len();
+        let page = clamp_page(page, total);
+        self.paging_links(&mut xml, &href, page, total, NAVIGATION_TYPE);
+
+        for (author, indices) in authors.iter().skip((page - 1) * PAGE_SIZE).take(PAGE_SIZE) {
+            let count = indices.len();
+            self.navigation_entry(
+                &mut xml,
+                &format!("author:{}", author),
+                author,
+                &format!("{} book{}", count, if count == 1 { "" } else { "s" }),
+                &format!("{}/authors/{}", self.base, percent_encode(author)),
+                ACQUISITION_TYPE,
+            );
+        }
+
+        xml.push_str("</feed>\n");
+        xml
+    }
+
+    /// Acquisition feed with the books of one author, sorted by series then title
+    ///
+    /// # Arguments
+    /// * `author` - Author name as shown in the authors feed
+    /// * `page` - 1-based page number
+    ///
+    /// # Returns
+    /// None if the library has no books by that author
+    pub fn author(&self, author: &str, page: usize) -> Option<String> {
+        let mut indices = self.books_by_author().remove(author)?;
+        indices.sort_by_cached_key(|&i| {
+            let book = &self.books[i];
+            (
+                sort::title_key(book.meta.series.as_deref().unwrap_or_default()),
+                (book.meta.series_index.unwrap_or(0.0) * 100.0) as i64,
+                sort::title_key(book.display_title()),
+            )
+        });
+
+        let href = format!("{}/authors/{}", self.base, percent_encode(author));
+        Some(self.acquisition_feed(&format!("author:{}", author), author, &href, &indices, page))
+    }
+
+    /// Groups book indices by author ("Unknown" for books without authors)
+    fn books_by_author(&self) -> BTreeMap<String, Vec<usize>> {
+        let mut map: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+
+        for (i, book) in self.books.iter().enumerate() {
+            if book.authors().is_empty() {
+                map.entry("Unknown".to_string()).or_default().push(i);
+            }
+            for author in book.authors() {
+                map.entry(author.clone()).or_default().push(i);
+            }
+        }
+
+        map
+    }
+
+    /// Builds a paged acquisition feed from a list of book indices
+    fn acquisition_feed(&self, id: &str, title: &str, href: &str, indices: &[usize], page: usize) -> String {
+        let mut xml = self.feed_start(id, title, href, ACQUISITION_TYPE);
+
+        let page = clamp_page(page, indices.len());
+        self.paging_links(&mut xml, href, page, indices.len(), ACQUISITION_TYPE);
+
+        for &i in indices.iter().skip((page - 1) * PAGE_SIZE).take(PAGE_SIZE) {
+            self.book_entry(&mut xml, i);
+        }
+
+        xml.push_str("</feed>\n");
+        xml
+    }
+
+    /// Writes the XML prolog and the feed-level elements
+    fn feed_start(&self, id: &str, title: &str, href: &str, kind: &str) -> String {
+        let mut xml = String::new();
+        xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(
+            "<feed xmlns=\"http://www.w3.org/2005/Atom\" \
+             xmlns:dc=\"http://purl.org/dc/terms/\" \
+             xmlns:opds=\"http://opds-spec.org/2010/catalog\">\n",
+        );
+        // write! into a String can't fail, so the results are ignored
+        let _ = writeln!(xml, "  <id>urn:funkhunt:{}:{}</id>", escape(self.library), escape(id));
+        let _ = writeln!(xml, "  <title>{}</title>", escape(title));
+        let _ = writeln!(xml, "  <updated>{}</updated>", self.updated);
+        xml.push_str("  <author><name>FunkHunt</name></author>\n");
+        let _ = writeln!(xml, "  <link rel=\"self\" href=\"{}\" type=\"{}\"/>", escape(href), kind);
+        let _ = writeln!(xml, "  <link rel=\"start\" href=\"{}\" type=\"{}\"/>", escape(self.base), NAVIGATION_TYPE);
+        xml
+    }
+
+    /// Adds first/previous/next/last links for paged feeds
+    fn paging_links(&self, xml: &mut String, href: &str, page: usize, total: usize, kind: &str) {
+        let last = page_count(total);
+        if last <= 1 {
+            return;
+        }
+
+        let mut link = |rel: &str, target: usize| {
+            let _ = writeln!(
+                xml,
+                "  <link rel=\"{}\" href=\"{}?page={}\" type=\"{}\"/>",
+                rel,
+                escape(href),
+                target,
+                kind
+            );
+        };
+
+        link("first", 1);
+        if page > 1 {
+            link("previous", page - 1);
+        }
+        if page < last {
+            link("next", page + 1);
+        }
+        link("last", last);
+    }
+
+    /// Adds an entry pointing to another feed
+    fn navigation_entry(&self, xml: &mut String, id: &str, title: &str, content: &str, href: &str, kind: &str) {
+        xml.push_str("  <entry>\n");
+        let _ = writeln!(xml, "    <title>{}</title>", escape(title));
+        let _ = writeln!(xml, "    <id>urn:funkhunt:{}:{}</id>", escape(self.library), escape(id));
+        let _ = writeln!(xml, "    <updated>{}</updated>", self.updated);
+        let _ = writeln!(xml, "    <content type=\"text\">{}</content>", escape(content));
+        let _ = writeln!(xml, "    <link rel=\"subsection\" href=\"{}\" type=\"{}\"/>", escape(href), kind);
+        xml.push_str("  </entry>\n");
+    }
+
+    /// Adds an entry for a downloadable book
+    fn book_entry(&self, xml: &mut String, index: usize) {
+        let book = &self.books[index];
+
+        // Stable id: the package identifier when present, otherwise the file path
+        let id = book
+            .meta
+            .isbn
+            .as_ref()
+            .map(|isbn| format!("urn:isbn:{}", isbn))
+            .or_else(|| book.meta.identifier.clone())
+            .unwrap_or_else(|| format!("urn:funkhunt:file:{}", book.path.display()));
+
+        xml.push_str("  <entry>\n");
+        let _ = writeln!(xml, "    <title>{}</title>", escape(book.display_title()));
+        let _ = writeln!(xml, "    <id>{}</id>", escape(&id));
+        let _ = writeln!(xml, "    <updated>{}</updated>", self.updated);
+        for author in book.authors() {
+            let _ = writeln!(xml, "    <author><name>{}</name></author>", escape(author));
+        }
+        if let Some(language) = &book.meta.language {
+            let _ = writeln!(xml, "    <dc:language>{}</dc:language>", escape(language));
+        }
+        if let Some(publisher) = &book.meta.publisher {
+            let _ = writeln!(xml, "    <dc:publisher>{}</dc:publisher>", escape(publisher));
+        }
+        if let Some(published) = &book.meta.published {
+            let _ = writeln!(xml, "    <dc:issued>{}</dc:issued>", escape(published));
+        }
+        for subject in &book.meta.subjects {
+            let _ = writeln!(xml, "    <category term=\"{0}\" label=\"{0}\"/>", escape(subject));
+        }
+        if let Some(description) = &book.meta.description {
+            let _ = writeln!(xml, "    <summary>{}</summary>", escape(description));
+        }
+        let _ = writeln!(
+            xml,
+            "    <link rel=\"http://opds-spec.org/acquisition\" href=\"{}/{}\" type=\"application/epub+zip\"/>",
+            self.download, index
+        );
+        xml.push_str("  </entry>\n");
+    }
+}
+
+/// Number of pages needed for `total` entries (at least 1)
+fn page_count(total: usize) -> usize {
+    total.div_ceil(PAGE_SIZE).max(1)
+}
+
+/// Keeps a requested page number inside 1..=page_count
+fn clamp_page(page: usize, total: usize) -> usize {
+    page.clamp(1, page_count(total))
+}
+
+/// Escapes the five XML special characters
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Percent-encodes a string for use as a URL path segment
+pub fn percent_encode(text: &str) -> String {
+    let mut out = String::new();
+    for byte in text.bytes() {
+        match byte {
+            // Unreserved characters stay as they are
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(byte as char),
+            _ => {
+                let _ = write!(out, "%{:02X}", byte);
+            }
+        }
+    }
+    out
+}
+
+/// Decodes a percent-encoded URL segment (`+` is left as is)
+///
+/// # Returns
+/// None if the result is not valid UTF-8
+pub fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        // "%41" -> 0x41, anything malformed is copied verbatim
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Some(byte) = std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8(out).ok()
+}
+
+/// Formats seconds since the Unix epoch as an RFC 3339 UTC timestamp
+/// (e.g. "2024-05-01T12:30:00Z"), as required by Atom's <updated>
+pub fn rfc3339(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // Civil-from-days conversion (proleptic Gregorian calendar)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        (rem % 3_600) / 60,
+        rem % 60
+    )
+}
@@ -0,0 +1,347 @@
+// src/optimize.rs
+// EPUB optimization - oversized books made smaller without touching their
+// text (`funkhunt optimize BOOK`, or Z in the app):
+// - PNG images are compressed again at the best level, without their text,
+//   time and Exif chunks; JPEG images lose their comments and their Exif,
+//   XMP and Photoshop segments. Pixels are never re-encoded, so nothing is
+//   lost but metadata (Exif's orientation too - book images rarely have one).
+// - Resources nothing refers to are removed: manifest items that aren't in
+//   the spine, the guide or the cover and whose file name appears in no
+//   other file of the book, and files the manifest doesn't list (e.g.
+//   __MACOSX/, .DS_Store) unless some file names them.
+// - Every file is compressed again at the best level (mimetype stays first
+//   and stored, as EPUB wants).
+//
+// The book is repacked next to itself (see epub::temp_path) and only takes
+// its place when smaller.
+
+use crate::epub;
+use crate::graphics::ImageFormat;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use quick_xml::events::Event;
+use quick_xml::{Reader, Writer};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// Extensions of the files references to resources are looked for in
+const TEXT_EXTENSIONS: [&str; 8] = ["xhtml", "html", "htm", "css", "ncx", "svg", "xml", "smil"];
+
+/// PNG chunks dropped from images (text, modification time, Exif)
+const DROPPED_PNG_CHUNKS: [&[u8]; 5] = [b"tEXt", b"zTXt", b"iTXt", b"tIME", b"eXIf"];
+
+/// What an optimization did
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// Size of the book before, in bytes
+    pub before: u64,
+
+    /// Size of the book after, in bytes (the same if it couldn't be made smaller)
+    pub after: u64,
+
+    /// Images made smaller
+    pub images: usize,
+
+    /// Files removed, as paths inside the archive
+    pub removed: Vec<String>,
+}
+
+impl Report {
+    /// e.g. "80.1 MB -> 3.2 MB (saved 96%)"
+    pub fn summary(&self) -> String {
+        if self.after >= self.before {
+            return format!("{} - as small as it gets", crate::transfer::human_bytes(self.before as f64));
+        }
+        format!(
+            "{} -> {} (saved {}%)",
+            crate::transfer::human_bytes(self.before as f64),
+            crate::transfer::human_bytes(self.after as f64),
+            (self.before - self.after) * 100 / self.before.max(1)
+        )
+    }
+}
+
+/// Makes an EPUB smaller
+///
+/// # Arguments
+/// * `path` - The EPUB file (replaced by the smaller one)
+/// * `progress` - Called with the percentage done as files are repacked
+/// * `cancelled` - Asked between files; the book is left as it was once this says yes
+pub fn optimize(path: &Path, mut progress: impl FnMut(u8), cancelled: impl Fn() -> bool) -> io::Result<Report> {
+    let before = std::fs::metadata(path)?.len();
+    let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(io::Error::other)?;
+    let (opf_path, opf) = epub::read_package(&mut archive)?;
+    let removed = unused_entries(&mut archive, &opf_path, &opf)?;
+    let opf = without_items(&opf, &opf_path, &removed)?;
+
+    let total: u64 = (0..archive.len())
+        .filter_map(|i| archive.by_index_raw(i).ok().map(|entry| entry.size()))
+        .sum();
+    let tmp_path = epub::temp_path(path);
+    let mut images = 0;
+    let mut write = || -> io::Result<()> {
+        let mut writer = zip::ZipWriter::new(File::create(&tmp_path)?);
+        let (mut done, mut shown) = (0, None);
+        for i in 0..archive.len() {
+            if cancelled() {
+                return Err(io::Error::other("cancelled"));
+            }
+            let entry = archive.by_index_raw(i).map_err(io::Error::other)?;
+            let name = entry.name().to_string();
+            done += entry.size();
+            let percent = (done * 100 / total.max(1)) as u8;
+            if shown != Some(percent) {
+                progress(percent);
+                shown = Some(percent);
+            }
+            if entry.is_dir() || removed.contains(&name) {
+                continue;
+            }
+            if name == "mimetype" {
+                writer.raw_copy_file(entry).map_err(io::Error::other)?;
+                continue;
+            }
+            let options = zip::write::FileOptions::default()
+                .compression_method(zip::CompressionMethod::Deflated)
+                .compression_level(Some(9))
+                .last_modified_time(entry.last_modified());
+            drop(entry);
+
+            let content = if name == opf_path {
+                opf.as_bytes().to_vec()
+            } else {
+                let mut content = Vec::new();
+                archive.by_index(i).map_err(io::Error::other)?.read_to_end(&mut content)?;
+                let smaller = match crate::graphics::probe(&content) {
+                    Some((ImageFormat::Png, _)) => shrink_png(&content),
+                    Some((ImageFormat::Jpeg, _)) => shrink_jpeg(&content),
+                    _ => None,
+                };
+                match smaller {
+                    Some(smaller) => {
+                        images += 1;
+                        smaller
+                    }
+                    None => content,
+                }
+            };
+            writer.start_file(name.as_str(), options).map_err(io::Error::other)?;
+            writer.write_all(&content)?;
+        }
+        writer.finish().map_err(io::Error::other)?;
+        Ok(())
+    };
+    if let Err(e) = write() {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+
+    let after = std::fs::metadata(&tmp_path)?.len();
+    if after >= before {
+        std::fs::remove_file(&tmp_path)?;
+        tracing::info!(path = %path.display(), size = before, "book already as small as it gets");
+        return Ok(Report {
+            before,
+            after: before,
+            ..Default::default()
+        });
+    }
+    epub::put_in_place(&tmp_path, path)?;
+    let mut report = Report {
+        before,
+        after,
+        images,
+        removed: removed.into_iter().collect(),
+    };
+    report.removed.sort();
+    tracing::info!(
+        path = %path.display(),
+        before,
+        after,
+        images = report.images,
+        removed = report.removed.len(),
+        "book optimized"
+    );
+    Ok(report)
+}
+
+/// The files of an EPUB nothing refers to (paths inside the archive; see
+/// the top of this file)
+fn unused_entries(archive: &mut zip::ZipArchive<File>, opf_path: &str, opf: &str) -> io::Result<HashSet<String>> {
+    let (items, cover_id) = epub::read_manifest(opf)?;
+
+    // What the package itself refers to: items by id (spine, NCX, fallbacks,
+    // media overlays) and files by link (guide)
+    let mut ids: HashSet<String> = cover_id.into_iter().collect();
+    let mut linked = HashSet::new();
+    let mut reader = Reader::from_str(opf);
+    loop {
+        match reader.read_event().map_err(io::Error::other)? {
+            Event::Start(e) | Event::Empty(e) => {
+                for name in [&b"idref"[..], b"toc", b"fallback", b"fallback-style", b"media-overlay"] {
+                    ids.extend(epub::attribute(&e, name));
+                }
+                if e.local_name().as_ref() == b"reference" {
+                    linked.extend(epub::attribute(&e, b"href").map(|href| epub::entry_path(opf_path, &href)));
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    // The other files, where resources are named
+    let mut texts = String::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(io::Error::other)?;
+        let extension = entry.name().rsplit('.').next().unwrap_or_default().to_lowercase();
+        if entry.name() != opf_path && TEXT_EXTENSIONS.contains(&extension.as_str()) {
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content)?;
+            texts.push_str(&String::from_utf8_lossy(&content));
+        }
+    }
+    // "images/c v.jpg" is named "c v.jpg" or "c%20v.jpg"
+    let named = |path: &str| {
+        let file_name = path.rsplit('/').next().unwrap_or(path);
+        texts.contains(file_name) || texts.contains(&crate::opds::percent_encode(file_name))
+    };
+
+    let mut listed = HashSet::new();
+    let mut unused = HashSet::new();
+    for item in &items {
+        let path = epub::entry_path(opf_path, &item.href);
+        let used = ids.contains(&item.id) || !item.properties.is_empty() || linked.contains(&path) || named(&path);
+        if !used {
+            unused.insert(path.clone());
+        }
+        listed.insert(path);
+    }
+    for name in archive.file_names() {
+        let kept = name == "mimetype" || name == opf_path || name.starts_with("META-INF/") || name.ends_with('/');
+        if !kept && !listed.contains(name) && !named(name) {
+            unused.insert(name.to_string());
+        }
+    }
+    Ok(unused)
+}
+
+/// An OPF package document without the manifest items of some files
+pub fn without_items(opf: &str, opf_path: &str, removed: &HashSet<String>) -> io::Result<String> {
+    let mut reader = Reader::from_str(opf);
+    let mut writer = Writer::new(Vec::new());
+    let mut skipping = 0;
+    // Blank text before an element, dropped with it
+    let mut blank = None;
+    loop {
+        let event = reader.read_event().map_err(io::Error::other)?;
+        if skipping > 0 {
+            match event {
+                Event::Start(_) => skipping += 1,
+                Event::End(_) => skipping -= 1,
+                Event::Eof => break,
+                _ => {}
+            }
+            continue;
+        }
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e)
+                if e.local_name().as_ref() == b"item"
+                    && epub::attribute(e, b"href").is_some_and(|href| removed.contains(&epub::entry_path(opf_path, &href))) =>
+            {
+                if matches!(event, Event::Start(_)) {
+                    skipping = 1;
+                }
+                blank = None;
+            }
+            Event::Text(ref text) if text.iter().all(u8::is_ascii_whitespace) => {
+                if let Some(blank) = blank.replace(event.into_owned()) {
+                    writer.write_event(blank).map_err(io::Error::other)?;
+                }
+            }
+            Event::Eof => break,
+            event => {
+                if let Some(blank) = blank.take() {
+                    writer.write_event(blank).map_err(io::Error::other)?;
+                }
+                writer.write_event(event).map_err(io::Error::other)?;
+            }
+        }
+    }
+    String::from_utf8(writer.into_inner()).map_err(io::Error::other)
+}
+
+/// A PNG compressed again at the best level, without text, time and Exif
+/// chunks (None if that isn't smaller, or it isn't a PNG it understands)
+fn shrink_png(png: &[u8]) -> Option<Vec<u8>> {
+    // Chunks are length, type, data and CRC; the image data may be split
+    // into consecutive IDAT chunks, joined here
+    let mut chunks: Vec<(&[u8], Vec<u8>)> = Vec::new();
+    let mut data = Vec::new();
+    let mut at = 8;
+    while at + 8 <= png.len() {
+        let length = u32::from_be_bytes(png[at..at + 4].try_into().ok()?) as usize;
+        let kind = &png[at + 4..at + 8];
+        let content = png.get(at + 8..at + 8 + length)?;
+        at += 12 + length;
+        if kind == b"IDAT" {
+            if data.is_empty() {
+                chunks.push((kind, Vec::new()));
+            }
+            data.extend_from_slice(content);
+        } else if !DROPPED_PNG_CHUNKS.contains(&kind) {
+            chunks.push((kind, content.to_vec()));
+        }
+        if kind == b"IEND" {
+            break;
+        }
+    }
+
+    let mut pixels = Vec::new();
+    ZlibDecoder::new(data.as_slice()).read_to_end(&mut pixels).ok()?;
+    let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(&pixels).ok()?;
+    let compressed = encoder.finish().ok()?;
+
+    let mut out = png[..8].to_vec();
+    for (kind, content) in chunks {
+        let content = if kind == b"IDAT" { &compressed } else { &content };
+        let mut crc = flate2::Crc::new();
+        crc.update(kind);
+        crc.update(content);
+        out.extend_from_slice(&(content.len() as u32).to_be_bytes());
+        out.extend_from_slice(kind);
+        out.extend_from_slice(content);
+        out.extend_from_slice(&crc.sum().to_be_bytes());
+    }
+    (out.len() < png.len()).then_some(out)
+}
+
+/// A JPEG without comments and Exif, XMP and Photoshop segments (APP1,
+/// APP3 to APP13, APP15); JFIF, ICC profiles and Adobe's segment stay, as
+/// they change the colors (None if nothing was removed)
+fn shrink_jpeg(jpeg: &[u8]) -> Option<Vec<u8>> {
+    let mut out = jpeg[..2].to_vec();
+    let mut at = 2;
+    loop {
+        if *jpeg.get(at)? != 0xFF {
+            return None;
+        }
+        let marker = *jpeg.get(at + 1)?;
+        // The image data starts: the rest goes as it is
+        if marker == 0xDA {
+            out.extend_from_slice(&jpeg[at..]);
+            break;
+        }
+        let length = u16::from_be_bytes(jpeg.get(at + 2..at + 4)?.try_into().ok()?) as usize;
+        let segment = jpeg.get(at..at + 2 + length)?;
+        let dropped = marker == 0xFE || marker == 0xE1 || (0xE3..=0xED).contains(&marker) || marker == 0xEF;
+        if !dropped {
+            out.extend_from_slice(segment);
+        }
+        at += 2 + length;
+    }
+    (out.len() < jpeg.len()).then_some(out)
+}
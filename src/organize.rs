@@ -0,0 +1,322 @@
+// src/organize.rs
+// Moves book files into a folder structure built from their metadata,
+// e.g. "{author}/{series}/{title}.epub" -> "Tolkien/The Lord of the Rings/The Two Towers.epub"
+//
+// Placeholders:
+// - `{author}`   first author ("Unknown Author" if none)
+// - `{authors}`  all authors, comma-separated
+// - `{title}`    title (the file name if the book has none)
+// - `{series}`   series name
+// - `{index}`    position in the series
+// - `{year}`     publication year
+// - `{language}` language code
+// - `{name}`     current file name without extension
+// Folders whose placeholders are all empty (a book without series) are left out.
+
+use crate::book::Book;
+use crate::profile::FolderSettings;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Why a planned move can't be done
+#[derive(Debug, Clone, PartialEq)]
+pub enum Conflict {
+    /// Another file already exists at the destination
+    Exists,
+    /// Several books would end up at the same destination
+    Duplicate,
+    /// The book is in a read-only folder
+    ReadOnly,
+    /// The book isn't inside any folder of the library
+    OutsideLibrary,
+}
+
+impl Conflict {
+    /// Short description for the preview, e.g. "exists"
+    pub fn label(&self) -> &'static str {
+        match self {
+            Conflict::Exists => "exists",
+            Conflict::Duplicate => "duplicate",
+            Conflict::ReadOnly => "read-only",
+            Conflict::OutsideLibrary => "outside library",
+        }
+    }
+}
+
+/// One file move of a plan
+#[derive(Debug, Clone)]
+pub struct Move {
+    /// Current path of the book
+    pub from: PathBuf,
+
+    /// Path the template gives
+    pub to: PathBuf,
+
+    /// Scan root of the book (folders are never removed above it)
+    pub root: PathBuf,
+
+    /// Why the move will be skipped (None = it will be done)
+    pub conflict: Option<Conflict>,
+}
+
+/// Fills in a template for a book
+///
+/// Every folder / file name is cleaned of characters that aren't allowed in
+/// file names, and ".epub" is added when the template has no extension.
+///
+/// # Returns
+/// The path relative to the book's scan root
+pub fn render(template: &str, book: &Book) -> PathBuf {
+    let values = placeholders(book);
+
+    let mut path = PathBuf::new();
+    for part in template.split('/') {
+        let clean = sanitize(&fill(part, &values));
+        if !clean.is_empty() {
+            path.push(clean);
+        }
+    }
+
+    if path.extension().is_none() {
+        path.set_extension("epub");
+    }
+    path
+}
+
+/// Fills in a file name template for a book (for renaming in place)
+///
+/// Like `render`, but the whole result is one file name: a '/' coming from
+/// the template or the metadata becomes '_'.
+///
+/// # Returns
+/// The new file name, e.g. "Frank Herbert - Dune.epub"
+pub fn render_name(template: &str, book: &Book) -> String {
+    let name = sanitize(&fill(template, &placeholders(book)));
+    if name.to_lowercase().ends_with(".epub") {
+        name
+    } else {
+        format!("{}.epub", name)
+    }
+}
+
+/// Path that renames a book in its current folder
+pub fn renamed(book: &Book, template: &str) -> PathBuf {
+    book.path.with_file_name(render_name(template, book))
+}
+
+/// Values of the placeholders for a book
+fn placeholders(book: &Book) -> [(&'static str, String); 8] {
+    let stem = Path::new(&book.name)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let year = book
+        .meta
+        .published
+        .as_deref()
+        .map(|date| date.chars().take(4).collect::<String>())
+        .filter(|year| year.len() == 4 && year.chars().all(|c| c.is_ascii_digit()))
+        .unwrap_or_default();
+
+    [
+        ("{author}", book.authors().first().cloned().unwrap_or_else(|| "Unknown Author".to_string())),
+        ("{authors}", book.display_authors()),
+        ("{title}", book.display_title().to_string()),
+        ("{series}", book.meta.series.clone().unwrap_or_default()),
+        ("{index}", book.meta.series_index.map(|i| i.to_string()).unwrap_or_default()),
+        ("{year}", year),
+        ("{language}", book.meta.language.clone().unwrap_or_default()),
+        ("{name}", stem),
+    ]
+}
+
+/// Replaces the placeholders in a piece of template
+fn fill(text: &str, values: &[(&'static str, String)]) -> String {
+    let mut text = text.to_string();
+    for (placeholder, value) in values {
+        text = text.replace(placeholder, value);
+    }
+    text
+}
+
+/// Makes a text usable as a file or folder name
+///
+/// Characters that Windows or macOS refuse become '_', and leftovers of empty
+/// placeholders ("Dune - " or " - 1") are trimmed.
+pub fn sanitize(text: &str) -> String {
+    let replaced: String = text
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let trim = |s: &str| {
+        s.trim_matches(|c: char| c.is_whitespace() || c == '-' || c == '.')
+            .to_string()
+    };
+
+    // "Dune - .epub" becomes "Dune.epub" - the extension dot must survive the trim
+    match replaced.trim().strip_suffix(".epub") {
+        Some(stem) if !trim(stem).is_empty() => format!("{}.epub", trim(stem)),
+        Some(_) => String::new(),
+        None => trim(&replaced),
+    }
+}
+
+/// Plans moving books to the paths a template gives
+///
+/// Destinations are inside each book's own scan root. Books already at their
+/// destination are left out; moves that can't be done are kept in the plan
+/// with the reason, so the preview can show them.
+///
+/// # Arguments
+/// * `books` - The books to move
+/// * `folders` - Scan roots of the library with their settings
+/// * `destination` - Gives the new path of a book, relative to its root
+///   (an absolute path is used as it is)
+///
+/// # Returns
+/// The planned moves, in the order of `books`
+pub fn plan<'a>(
+    books: impl Iterator<Item = &'a Book>,
+    folders: &[(PathBuf, FolderSettings)],
+    destination: impl Fn(&Book) -> PathBuf,
+) -> Vec<Move> {
+    let mut moves: Vec<Move> = books
+        .filter_map(|book| {
+            // The deepest root containing the book is the one it was found in
+            let root = folders
+                .iter()
+                .filter(|(root, _)| book.path.starts_with(root))
+                .max_by_key(|(root, _)| root.components().count());
+
+            let (to, conflict) = match root {
+                None => (book.path.clone(), Some(Conflict::OutsideLibrary)),
+                // Files on a server are never moved
+                Some((root, settings)) if settings.read_only || crate::remote::is_remote(root) => {
+                    (book.path.clone(), Some(Conflict::ReadOnly))
+                }
+                Some((root, _)) => {
+                    let to = root.join(destination(book));
+                    if to == book.path {
+                        return None;
+                    }
+                    // Only a case change ("dune.epub" -> "Dune.epub") may find itself
+                    let exists = to.exists() && !same_file(&to, &book.path);
+                    (to, exists.then_some(Conflict::Exists))
+                }
+            };
+            Some(Move {
+                from: book.path.clone(),
+                to,
+                root: root.map(|(root, _)| root.clone()).unwrap_or_default(),
+                conflict,
+            })
+        })
+        .collect();
+
+    // Two books with the same destination: neither is moved
+    let mut counts: HashMap<PathBuf, usize> = HashMap::new();
+    for planned in moves.iter().filter(|m| m.conflict.is_none()) {
+        *counts.entry(planned.to.clone()).or_default() += 1;
+    }
+    for planned in moves.iter_mut().filter(|m| m.conflict.is_none()) {
+        if counts[&planned.to] > 1 {
+            planned.conflict = Some(Conflict::Duplicate);
+        }
+    }
+
+    moves
+}
+
+/// Carries out the moves without a conflict, all or nothing
+///
+/// If a move fails, the files moved so far are moved back before the
+/// error is returned, so the library is never left half-organized.
+/// Folders left empty by the moves are removed.
+///
+/// # Returns
+/// The moves that were done, or the error that stopped them
+pub fn execute(moves: &[Move]) -> io::Result<Vec<Move>> {
+    let mut done: Vec<Move> = Vec::new();
+
+    for planned in moves.iter().filter(|m| m.conflict.is_none()) {
+        match move_file(&planned.from, &planned.to) {
+            Ok(()) => done.push(planned.clone()),
+            Err(e) => {
+                tracing::error!(from = %planned.from.display(), to = %planned.to.display(), error = %e, "move failed, rolling back");
+                rollback(&done);
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("{}: {} (nothing was moved)", planned.from.display(), e),
+                ));
+            }
+        }
+    }
+
+    for moved in &done {
+        remove_empty_parents(&moved.from, &moved.root);
+    }
+    Ok(done)
+}
+
+/// Moves files back to where they were, newest move first
+pub fn rollback(done: &[Move]) {
+    for moved in done.iter().rev() {
+        if let Err(e) = move_file(&moved.to, &moved.from) {
+            tracing::error!(path = %moved.to.display(), error = %e, "cannot move file back");
+        }
+        remove_empty_parents(&moved.to, &moved.root);
+    }
+}
+
+/// Points the book stored at `from` to the file at `to`
+pub fn repoint(books: &mut [Book], from: &Path, to: &Path) {
+    if let Some(book) = books.iter_mut().find(|b| b.path == from) {
+        book.path = to.to_path_buf();
+        if let Some(name) = to.file_name() {
+            book.name = name.to_string_lossy().to_string();
+        }
+    }
+}
+
+/// Moves one file (and its sidecar), creating the destination folders
+///
+/// Falls back to copy + delete when the destination is on another file system.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    // The user data file goes along
+    crate::sidecar::follow(from, to);
+    Ok(())
+}
+
+/// Removes the folders above a moved file as long as they're empty, up to
+/// (not including) the scan root
+/// (remove_dir refuses non-empty folders, which stops the walk up)
+fn remove_empty_parents(path: &Path, root: &Path) {
+    let mut dir = path.parent();
+    while let Some(current) = dir {
+        if current == root || !current.starts_with(root) || fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
+
+/// Whether two paths name the same file (e.g. differing only in case on macOS)
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
@@ -0,0 +1,188 @@
+// src/parse_queue.rs
+// Metadata of the books read in the background, so the interface opens at
+// once on a library that was never scanned (or has new files)
+//
+// A scan in the TUI only walks the folders: every new file becomes a
+// placeholder book (its file name, sidecar and default tags), and the
+// queue reads the packages - title, authors, language, readability - with
+// a few threads, which send each book read to the main loop (an AppEvent,
+// see tui/worker.rs) to fill the placeholder in. The books on the screen
+// go first: the main loop tells the queue which ones they are before every
+// frame, and they jump ahead of the others still waiting.
+//
+// The commands without the TUI (scan, list, serve...) read everything up
+// front, as they always did.
+
+use crate::book::Book;
+use crate::profile::FolderSettings;
+use crate::tui::worker::AppEvent;
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Most threads reading packages at once
+const MAX_THREADS: usize = 4;
+
+/// Files waiting to be read, shared with the threads
+#[derive(Default)]
+struct Waiting {
+    /// Next to read first
+    files: Mutex<VecDeque<PathBuf>>,
+
+    /// Rung when a file is queued, or the queue is dropped
+    queued: Condvar,
+
+    /// The queue was dropped: the threads end
+    closed: AtomicBool,
+}
+
+/// The books whose metadata is being read
+pub struct ParseQueue {
+    /// None until the first book is queued (no threads before that)
+    waiting: Option<Arc<Waiting>>,
+
+    /// Where the threads send the books they read (the main loop's channel)
+    results: Sender<AppEvent>,
+
+    /// Files queued and not read yet - their books are placeholders
+    pending: HashSet<PathBuf>,
+
+    /// The files last put first, to only reorder the queue when they change
+    prioritized: Vec<PathBuf>,
+}
+
+impl ParseQueue {
+    /// An empty queue (its threads start with the first book queued)
+    ///
+    /// # Arguments
+    /// * `results` - Where the books read are sent (Workers::sender)
+    pub fn new(results: Sender<AppEvent>) -> Self {
+        Self {
+            waiting: None,
+            results,
+            pending: HashSet::new(),
+            prioritized: Vec::new(),
+        }
+    }
+
+    /// Queues the books whose metadata wasn't read: the placeholders of a
+    /// scan, and the books whose package had no title (a file copied
+    /// while it was scanned may be whole now). Books of remote folders are
+    /// read by the scan itself and left out.
+    ///
+    /// # Returns
+    /// How many books were queued
+    pub fn request(&mut self, books: &[Book]) -> usize {
+        let files: Vec<PathBuf> = books
+            .iter()
+            .filter(|book| book.is_placeholder() && !crate::remote::is_remote(&book.path))
+            .filter(|book| !self.pending.contains(&book.path))
+            .map(|book| book.path.clone())
+            .collect();
+        if files.is_empty() {
+            return 0;
+        }
+
+        let results = &self.results;
+        let waiting = self.waiting.get_or_insert_with(|| {
+            let waiting = Arc::new(Waiting::default());
+            let threads = std::thread::available_parallelism().map_or(1, |n| n.get()).min(MAX_THREADS);
+            for _ in 0..threads {
+                let waiting = waiting.clone();
+                let results = results.clone();
+                std::thread::spawn(move || read_packages(&waiting, &results));
+            }
+            waiting
+        });
+        let queued = files.len();
+        self.pending.extend(files.iter().cloned());
+        waiting.files.lock().unwrap_or_else(|e| e.into_inner()).extend(files);
+        waiting.queued.notify_all();
+        tracing::debug!(books = queued, "metadata queued");
+        queued
+    }
+
+    /// Whether a book is a placeholder whose metadata is still to be read
+    pub fn is_pending(&self, path: &Path) -> bool {
+        self.pending.contains(path)
+    }
+
+    /// How many books are still to be read
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Puts files ahead of the others still waiting, in the order given
+    /// (those already read or being read are skipped)
+    pub fn prioritize(&mut self, files: Vec<PathBuf>) {
+        if files == self.prioritized || self.pending.is_empty() {
+            return;
+        }
+        if let Some(waiting) = &self.waiting {
+            let mut queue = waiting.files.lock().unwrap_or_else(|e| e.into_inner());
+            for file in files.iter().rev() {
+                if let Some(at) = queue.iter().position(|queued| queued == file) {
+                    if let Some(file) = queue.remove(at) {
+                        queue.push_front(file);
+                    }
+                }
+            }
+        }
+        self.prioritized = files;
+    }
+
+    /// Takes a book read off the pending ones (its AppEvent arrived)
+    ///
+    /// # Returns
+    /// Whether it was pending - false for a book of a queue since cleared
+    pub fn finish(&mut self, path: &Path) -> bool {
+        self.pending.remove(path)
+    }
+
+    /// Forgets the books still waiting (e.g. when another library is
+    /// opened); those being read are dropped when they arrive
+    pub fn clear(&mut self) {
+        if let Some(waiting) = &self.waiting {
+            waiting.files.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        }
+        self.pending.clear();
+        self.prioritized.clear();
+    }
+}
+
+impl Drop for ParseQueue {
+    fn drop(&mut self) {
+        if let Some(waiting) = &self.waiting {
+            // Under the lock, so no thread is between its check and its wait
+            let _files = waiting.files.lock().unwrap_or_else(|e| e.into_inner());
+            waiting.closed.store(true, Ordering::Relaxed);
+            waiting.queued.notify_all();
+        }
+    }
+}
+
+/// What a thread does: reads the next file waiting (or waits for one),
+/// until the queue is dropped
+fn read_packages(waiting: &Waiting, results: &Sender<AppEvent>) {
+    loop {
+        let file = {
+            let mut files = waiting.files.lock().unwrap_or_else(|e| e.into_inner());
+            loop {
+                if waiting.closed.load(Ordering::Relaxed) {
+                    return;
+                }
+                match files.pop_front() {
+                    Some(file) => break file,
+                    None => files = waiting.queued.wait(files).unwrap_or_else(|e| e.into_inner()),
+                }
+            }
+        };
+        // Sidecars and default tags are the placeholder's already
+        let book = crate::scanner::book_from_file(&file, &FolderSettings::default());
+        if results.send(AppEvent::Parsed(Box::new(book))).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,102 @@
+// src/paths.rs
+// Where FunkHunt keeps its files - resolved per platform:
+// - Linux: XDG base directories ($XDG_CONFIG_HOME/funkhunt, $XDG_DATA_HOME/funkhunt, ...)
+// - macOS: ~/Library/Application Support/funkhunt, ~/Library/Caches/funkhunt
+// - Windows: %APPDATA%\funkhunt, %LOCALAPPDATA%\funkhunt
+//
+// FUNKHUNT_CONFIG_DIR, FUNKHUNT_DATA_DIR and FUNKHUNT_CACHE_DIR override the
+// platform locations (handy for isolated test setups and containers).
+
+use directories::ProjectDirs;
+use std::path::PathBuf;
+
+/// Application name used for all directories
+const APP_NAME: &str = "funkhunt";
+
+/// Gets the platform directories for FunkHunt
+///
+/// # Returns
+/// None only when no home directory can be determined
+fn project_dirs() -> Option<ProjectDirs> {
+    ProjectDirs::from("", "", APP_NAME)
+}
+
+/// Reads a directory override from the environment (ignored when empty)
+fn env_dir(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Fallback when the platform directories are unknown: `$HOME/.funkhunt` (or `./.funkhunt`)
+fn fallback_dir() -> PathBuf {
+    std::env::var("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("."))
+        .join(".funkhunt")
+}
+
+/// Directory for configuration files (e.g. ~/.config/funkhunt)
+pub fn config_dir() -> PathBuf {
+    if let Some(dir) = env_dir("FUNKHUNT_CONFIG_DIR") {
+        return dir;
+    }
+    project_dirs()
+        .map(|dirs| dirs.config_dir().to_path_buf())
+        .unwrap_or_else(fallback_dir)
+}
+
+/// Directory for persistent data - library databases (e.g. ~/.local/share/funkhunt)
+pub fn data_dir() -> PathBuf {
+    if let Some(dir) = env_dir("FUNKHUNT_DATA_DIR") {
+        return dir;
+    }
+    project_dirs()
+        .map(|dirs| dirs.data_dir().to_path_buf())
+        .unwrap_or_else(fallback_dir)
+}
+
+/// Directory for data that can be regenerated at any time (e.g. ~/.cache/funkhunt)
+pub fn cache_dir() -> PathBuf {
+    if let Some(dir) = env_dir("FUNKHUNT_CACHE_DIR") {
+        return dir;
+    }
+    project_dirs()
+        .map(|dirs| dirs.cache_dir().to_path_buf())
+        .unwrap_or_else(|| fallback_dir().join("cache"))
+}
+
+/// Directory for log files
+///
+/// Uses $XDG_STATE_HOME on Linux (e.g. ~/.local/state/funkhunt/logs),
+/// and a `logs` folder in the data directory elsewhere or when FUNKHUNT_DATA_DIR is set.
+pub fn log_dir() -> PathBuf {
+    if env_dir("FUNKHUNT_DATA_DIR").is_some() {
+        return data_dir().join("logs");
+    }
+    project_dirs()
+        .and_then(|dirs| dirs.state_dir().map(|d| d.to_path_buf()))
+        .unwrap_or_else(data_dir)
+        .join("logs")
+}
+
+/// Directory holding one sub-directory per library profile
+pub fn libraries_dir() -> PathBuf {
+    data_dir().join("libraries")
+}
+
+/// Moves libraries from the old `~/.funkhunt/libraries` location to the data directory
+///
+/// Runs once: only when the old folder exists and the new one doesn't.
+/// Failures are ignored - the old libraries simply stay where they were.
+pub fn migrate_legacy_dirs() {
+    let legacy = fallback_dir().join("libraries");
+    let current = libraries_dir();
+
+    if legacy != current && legacy.is_dir() && !current.exists() {
+        if let Some(parent) = current.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        let _ = std::fs::rename(&legacy, &current);
+    }
+}
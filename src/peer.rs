@@ -0,0 +1,1099 @@
+// src/peer.rs
+// Peer protocol - what FunkHunt instances on the LAN say to each other
+//
+// Every instance with [peers] enabled runs a small HTTP server (on the
+// announced port) next to the TUI. Other peers talk to it in JSON:
+// - `GET /peer/catalog` - the library name and its books
+// - `GET /peer/books/{id}` - download a book of the catalog (a byte range
+//   of it with a Range header, for resumable downloads)
+// - `POST /peer/offer` - "I'd like to send you these books"; the user is
+//   asked, and on accept the books are downloaded from the sender
+// - `GET /peer/sync` - the whole library with user data, for two-way sync
+// - `POST /peer/sync` - user data the syncing peer settled (see `sync`)
+// - `POST /peer/pair`, then `POST /peer/pair/reveal` - a pairing (see
+//   `trust`); the only requests answered for unpaired peers when [peers]
+//   require_pairing is on
+//
+// Requests of paired peers are signed, and both ways their bodies are
+// sealed (encrypted) with the pair key.
+//
+// What a paired peer gets follows its share policy (see `trust`): the
+// catalog only lists what the policy shares, other books are answered 404,
+// sync is refused (403) unless the whole library is shared, and with
+// "ask first" a download is refused (403) until the user approved it - the
+// request waits in the main loop meanwhile. Unpaired peers never sync,
+// whatever [peers] require_pairing says.
+
+use crate::book::Book;
+use crate::server::{ascii_filename, header};
+use crate::transfer::{self, Chunk, Progress, Transfers};
+use crate::trust::{
+    self, OpenedReader, PairAnswer, PairRequest, PairReveal, Pairing, PairingKey, SealedReader, SeenNonces, Secret,
+    SharePolicy, Signature, Transcript, TrustedPeer,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tiny_http::{Method, Request, Response, Server, StatusCode};
+
+/// Path of the catalog
+pub const CATALOG_PATH: &str = "/peer/catalog";
+
+/// Path prefix of book downloads (`/peer/books/{id}`)
+pub const BOOKS_PATH: &str = "/peer/books";
+
+/// Path where offers are posted
+pub const OFFER_PATH: &str = "/peer/offer";
+
+/// Path of the sync library (GET) and of sync pushes (POST)
+pub const SYNC_PATH: &str = "/peer/sync";
+
+/// Path where pairings are requested
+pub const PAIR_PATH: &str = "/peer/pair";
+
+/// Path where the asking peer reveals its key, once it got ours
+pub const PAIR_REVEAL_PATH: &str = "/peer/pair/reveal";
+
+/// Content type of sealed bodies
+const SEALED_TYPE: &str = "application/x-funkhunt-sealed";
+
+/// Largest offer body accepted (a few thousand books' worth of JSON)
+const MAX_OFFER_BYTES: u64 = 1024 * 1024;
+
+/// Largest sync push accepted (whole books, user data included)
+const MAX_SYNC_BYTES: u64 = 32 * 1024 * 1024;
+
+/// How long a peer may take to answer
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most requests answered at once (each on a thread of its own); more
+/// are told to retry
+const MAX_HANDLERS: usize = 16;
+
+/// Most pairings kept at once, waiting for a reveal or for the user (the
+/// requests are unauthenticated: anyone may send them)
+const MAX_PAIRINGS: usize = 8;
+
+/// How long a pairing waits for the asking peer's reveal
+const PAIRING_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The shared books of a peer
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Catalog {
+    /// Name of the peer's open library profile
+    pub library: String,
+
+    /// The shared books
+    pub books: Vec<CatalogEntry>,
+}
+
+/// One shared book
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    /// Identifier of the book on the peer (valid until its library changes)
+    pub id: usize,
+
+    /// Title (the file name if the book has none)
+    pub title: String,
+
+    /// Authors, canonical names first
+    pub authors: Vec<String>,
+
+    /// Series name, if any
+    pub series: Option<String>,
+
+    /// File name on the peer, e.g. "Dune.epub"
+    pub name: String,
+
+    /// File size in bytes (None if the file couldn't be read)
+    pub size: Option<u64>,
+
+    /// Content hash, if the peer knows it (lets us spot books we already have)
+    pub hash: Option<String>,
+}
+
+impl CatalogEntry {
+    /// Details shown for the book while the peer is browsed (like
+    /// `Book::get_metadata` for our own books)
+    pub fn details(&self) -> String {
+        let authors = if self.authors.is_empty() {
+            "Unknown".to_string()
+        } else {
+            self.authors.join(", ")
+        };
+        let series = match &self.series {
+            Some(name) => format!("\n\nSeries: {}", name),
+            None => String::new(),
+        };
+        let size = match self.size {
+            Some(size) => format!("{} KB", size / 1024),
+            None => "unknown".to_string(),
+        };
+        format!(
+            "Title: {}\n\nAuthors: {}{}\n\nFile: {}\n\nSize: {}",
+            self.title, authors, series, self.name, size
+        )
+    }
+}
+
+/// Books a peer would like to send us
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Offer {
+    /// Name of the sending peer, e.g. "Living room"
+    pub from: String,
+
+    /// Port the sender serves its books on
+    pub port: u16,
+
+    /// The offered books (ids are valid on the sender)
+    pub books: Vec<CatalogEntry>,
+}
+
+/// An offer received from the network, waiting for the user's answer
+#[derive(Debug, Clone)]
+pub struct IncomingOffer {
+    /// What was offered
+    pub offer: Offer,
+
+    /// Where the books can be downloaded from
+    pub address: SocketAddr,
+
+    /// Pair secret of the sender (None if it isn't paired with us)
+    pub secret: Option<Secret>,
+}
+
+/// A library as sent for two-way sync: every book, archived ones too
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncLibrary {
+    /// Name of the peer's open library profile
+    pub library: String,
+
+    /// The books (their position is their id for downloads)
+    pub books: Vec<Book>,
+}
+
+/// User data a syncing peer sends back once the sync was settled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncPush {
+    /// Name of the syncing peer, e.g. "Living room"
+    pub from: String,
+
+    /// Our books (our paths) with the user data they should now have
+    pub books: Vec<Book>,
+}
+
+/// A paired peer asking for a book its policy makes us approve first
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessRequest {
+    /// The peer's instance id
+    pub peer_id: String,
+
+    /// The peer's name when paired
+    pub peer: String,
+
+    /// The book asked for
+    pub path: PathBuf,
+
+    /// Its title, for the question
+    pub title: String,
+}
+
+/// Who sent a request, once authenticated
+struct Caller {
+    /// Pair secret, for a paired peer
+    secret: Option<Secret>,
+
+    /// The paired peer (None for unpaired peers)
+    peer: Option<TrustedPeer>,
+
+    /// The request's signature, for a paired peer (its body is checked
+    /// against it when read)
+    signature: Option<Signature>,
+}
+
+impl Caller {
+    /// What the caller may get (unpaired peers: the whole library, but
+    /// they never sync)
+    fn policy(&self) -> SharePolicy {
+        self.peer.as_ref().map(|peer| peer.policy.clone()).unwrap_or_default()
+    }
+}
+
+/// What the server thread shares with the main loop
+struct Shared {
+    /// Name shown to other peers (sent back when pairing)
+    name: String,
+
+    /// Name of the open library
+    library: String,
+
+    /// Snapshot of the library, replaced by `publish`
+    books: Vec<Book>,
+
+    /// Offers received since the main loop last looked
+    offers: Vec<IncomingOffer>,
+
+    /// Sync pushes received since the main loop last looked
+    sync_pushes: Vec<SyncPush>,
+
+    /// Where uploads to peers are shown
+    transfers: Transfers,
+
+    /// Peers we paired with, replaced by `set_trusted`
+    trusted: Vec<TrustedPeer>,
+
+    /// Whether unpaired peers are turned away
+    require_pairing: bool,
+
+    /// Whether pairing requests are turned away (nobody to compare the PINs)
+    refuse_pairing: bool,
+
+    /// The nonces of the paired peers' requests taken lately
+    seen: SeenNonces,
+
+    /// Pairings answered, waiting for the asking peer's reveal
+    unrevealed: Vec<Unrevealed>,
+
+    /// Pairings requested since the main loop last looked
+    pairings: Vec<Pairing>,
+
+    /// Downloads asked for since the main loop last looked that need the
+    /// user's approval
+    access_requests: Vec<AccessRequest>,
+
+    /// Downloads waiting for the user's answer
+    waiting: Vec<AccessRequest>,
+
+    /// Downloads the user answered (approved or not), for this session
+    answered: Vec<(AccessRequest, bool)>,
+}
+
+/// A pairing we answered, waiting for the asking peer's key
+struct Unrevealed {
+    /// The id our answer gave it
+    session: String,
+
+    /// What the asking peer sent first
+    request: PairRequest,
+
+    /// Our half
+    key: PairingKey,
+
+    /// When we answered
+    since: Instant,
+}
+
+/// The HTTP server answering other peers (stopped when dropped)
+pub struct PeerServer {
+    /// The listening server (shared with its thread, to unblock it)
+    server: Arc<Server>,
+
+    /// The published library
+    shared: Arc<Mutex<Shared>>,
+
+    /// Thread taking the requests (each is answered on a thread of its
+    /// own, which it waits for before it ends)
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PeerServer {
+    /// Starts serving on a port (all interfaces)
+    ///
+    /// # Arguments
+    /// * `port` - The port announced to the other peers
+    /// * `name` - Name shown to other peers
+    /// * `library` - Name of the open library profile
+    /// * `books` - The books to share
+    /// * `transfers` - Where the books peers download are shown
+    /// * `trusted` - The peers we paired with
+    /// * `require_pairing` - Whether to turn unpaired peers away
+    pub fn start(
+        port: u16,
+        name: &str,
+        library: &str,
+        books: &[Book],
+        transfers: Transfers,
+        trusted: Vec<TrustedPeer>,
+        require_pairing: bool,
+    ) -> io::Result<Self> {
+        let server = Arc::new(Server::http(("0.0.0.0", port)).map_err(io::Error::other)?);
+        let shared = Arc::new(Mutex::new(Shared {
+            name: name.to_string(),
+            library: library.to_string(),
+            books: books.to_vec(),
+            offers: Vec::new(),
+            sync_pushes: Vec::new(),
+            transfers,
+            trusted,
+            require_pairing,
+            refuse_pairing: false,
+            seen: SeenNonces::default(),
+            unrevealed: Vec::new(),
+            pairings: Vec::new(),
+            access_requests: Vec::new(),
+            waiting: Vec::new(),
+            answered: Vec::new(),
+        }));
+
+        let thread = {
+            let (server, shared) = (Arc::clone(&server), Arc::clone(&shared));
+            std::thread::spawn(move || {
+                // A long download doesn't hold up the other peers' requests.
+                // The threads still running are counted by their handles (one
+                // that panicked is finished too), and waited for at the end
+                let mut handlers: Vec<JoinHandle<()>> = Vec::new();
+                // Ends when the server is unblocked
+                for request in server.incoming_requests() {
+                    tracing::debug!(method = %request.method(), url = request.url(), "peer request");
+                    let (finished, running) = std::mem::take(&mut handlers).into_iter().partition(|h| h.is_finished());
+                    handlers = running;
+                    join_handlers(finished);
+                    if handlers.len() >= MAX_HANDLERS {
+                        let busy = Response::from_string("busy - retry later").with_status_code(503);
+                        if let Err(e) = request.respond(busy) {
+                            tracing::warn!(error = %e, "failed to answer peer");
+                        }
+                        continue;
+                    }
+                    let shared = Arc::clone(&shared);
+                    handlers.push(std::thread::spawn(move || {
+                        if let Err(e) = handle_request(request, &shared) {
+                            tracing::warn!(error = %e, "failed to answer peer");
+                        }
+                    }));
+                }
+                join_handlers(handlers);
+            })
+        };
+
+        tracing::info!(port, library, "peer server started");
+        Ok(Self {
+            server,
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    /// Shares a new snapshot of the library
+    pub fn publish(&self, books: &[Book]) {
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.books = books.to_vec();
+        }
+    }
+
+    /// Takes the offers received since the last call
+    pub fn take_offers(&self) -> Vec<IncomingOffer> {
+        self.shared
+            .lock()
+            .map(|mut shared| std::mem::take(&mut shared.offers))
+            .unwrap_or_default()
+    }
+
+    /// Takes the sync pushes received since the last call
+    pub fn take_sync_pushes(&self) -> Vec<SyncPush> {
+        self.shared
+            .lock()
+            .map(|mut shared| std::mem::take(&mut shared.sync_pushes))
+            .unwrap_or_default()
+    }
+
+    /// Takes the pairings requested since the last call
+    pub fn take_pairings(&self) -> Vec<Pairing> {
+        self.shared
+            .lock()
+            .map(|mut shared| std::mem::take(&mut shared.pairings))
+            .unwrap_or_default()
+    }
+
+    /// Takes the downloads that started waiting for an approval since the
+    /// last call
+    pub fn take_access_requests(&self) -> Vec<AccessRequest> {
+        self.shared
+            .lock()
+            .map(|mut shared| std::mem::take(&mut shared.access_requests))
+            .unwrap_or_default()
+    }
+
+    /// Answers a download waiting for an approval; an approved download is
+    /// served when the peer asks again (its transfer retries by itself)
+    pub fn answer_access(&self, request: &AccessRequest, approved: bool) {
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.waiting.retain(|r| r != request);
+            shared.answered.push((request.clone(), approved));
+        }
+    }
+
+    /// Turns pairing requests away from now on (no one is at the screen to
+    /// compare the PINs, e.g. in daemon mode)
+    pub fn refuse_pairing(&self) {
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.refuse_pairing = true;
+        }
+    }
+
+    /// Replaces the peers let in (after a pairing or a revocation)
+    pub fn set_trusted(&self, trusted: &[TrustedPeer]) {
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.trusted = trusted.to_vec();
+        }
+    }
+}
+
+impl Drop for PeerServer {
+    /// Stops the server thread, once the requests being answered are done
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Waits for the threads of answered requests
+fn join_handlers(handlers: Vec<JoinHandle<()>>) {
+    for handler in handlers {
+        if handler.join().is_err() {
+            tracing::error!("peer request handler panicked");
+        }
+    }
+}
+
+/// Routes a request of another peer
+fn handle_request(mut request: Request, shared: &Mutex<Shared>) -> io::Result<()> {
+    let url = request.url().to_string();
+    let path = url.split_once('?').map(|(path, _)| path).unwrap_or(&url);
+
+    // Pairing: the one request unpaired peers may always make
+    if path == PAIR_PATH && *request.method() == Method::Post {
+        return answer_pairing(request, shared);
+    }
+    if path == PAIR_REVEAL_PATH && *request.method() == Method::Post {
+        return finish_pairing(request, shared);
+    }
+
+    // Paired peers are answered sealed; unpaired ones only if allowed
+    let caller = match authenticate(&request, path, shared) {
+        Ok(caller) => caller,
+        Err(reason) => {
+            tracing::warn!(remote = ?request.remote_addr(), reason, "peer request refused");
+            return request.respond(Response::from_string(reason).with_status_code(401));
+        }
+    };
+    let secret = caller.secret.as_ref();
+    let policy = caller.policy();
+
+    // Catalog
+    if path == CATALOG_PATH {
+        let catalog = match shared.lock() {
+            Ok(shared) => catalog_of(&shared.library, &shared.books, &policy),
+            Err(_) => Catalog::default(),
+        };
+        return respond_json(request, &catalog, secret);
+    }
+
+    // Offers: queued for the main loop, which asks the user
+    if path == OFFER_PATH && *request.method() == Method::Post {
+        let body = read_body(&mut request, MAX_OFFER_BYTES, &caller);
+        let remote = request.remote_addr().copied();
+        let (offer, remote) = match (body.ok().and_then(|body| serde_json::from_str::<Offer>(&body).ok()), remote) {
+            (Some(offer), Some(remote)) => (offer, remote),
+            _ => return request.respond(Response::from_string("Bad offer").with_status_code(400)),
+        };
+        tracing::info!(from = %offer.from, books = offer.books.len(), paired = secret.is_some(), "offer received");
+        if let Ok(mut shared) = shared.lock() {
+            shared.offers.push(IncomingOffer {
+                address: SocketAddr::new(remote.ip(), offer.port),
+                offer,
+                secret: secret.cloned(),
+            });
+        }
+        return request.respond(Response::from_string("Offer received").with_status_code(202));
+    }
+
+    // Sync: the whole library out, settled user data in (applied by the main
+    // loop) - for paired peers only: an unpaired one could change any book
+    if path == SYNC_PATH && caller.peer.is_none() {
+        let text = "pair with this library to sync with it";
+        return request.respond(Response::from_string(text).with_status_code(403));
+    }
+    if path == SYNC_PATH && !policy.allows_sync() {
+        let text = "this library is only partly shared with you - no sync";
+        return request.respond(Response::from_string(text).with_status_code(403));
+    }
+    if path == SYNC_PATH && *request.method() == Method::Get {
+        let library = match shared.lock() {
+            Ok(shared) => SyncLibrary {
+                library: shared.library.clone(),
+                books: shared.books.clone(),
+            },
+            Err(_) => SyncLibrary::default(),
+        };
+        return respond_json(request, &library, secret);
+    }
+    if path == SYNC_PATH && *request.method() == Method::Post {
+        let body = read_body(&mut request, MAX_SYNC_BYTES, &caller);
+        let Some(push) = body.ok().and_then(|body| serde_json::from_str::<SyncPush>(&body).ok()) else {
+            return request.respond(Response::from_string("Bad sync push").with_status_code(400));
+        };
+        tracing::info!(from = %push.from, books = push.books.len(), "sync push received");
+        if let Ok(mut shared) = shared.lock() {
+            shared.sync_pushes.push(push);
+        }
+        return request.respond(Response::from_string("Sync received").with_status_code(202));
+    }
+
+    // Book downloads (what the policy shares), shown in the transfer list
+    let book = path
+        .strip_prefix(&format!("{}/", BOOKS_PATH))
+        .and_then(|id| id.parse::<usize>().ok())
+        .and_then(|id| {
+            let shared = shared.lock().ok()?;
+            Some((shared.books.get(id)?.clone(), shared.transfers.clone()))
+        })
+        .filter(|(book, _)| policy.shares(book));
+    if let Some((book, transfers)) = book {
+        if let (true, Some(peer)) = (policy.needs_approval(), &caller.peer) {
+            let text = match approval(shared, peer, &book) {
+                Some(true) => None,
+                Some(false) => Some("the owner of this library declined"),
+                None => Some("waiting for approval - retry once the owner of this library accepts"),
+            };
+            if let Some(text) = text {
+                return request.respond(Response::from_string(text).with_status_code(403));
+            }
+        }
+        match File::open(&book.path).and_then(|file| file.metadata().map(|m| (file, m.len()))) {
+            Ok((mut file, size)) => {
+                // A range of the file (bytes of the file, also when sealed)
+                let range = request
+                    .headers()
+                    .iter()
+                    .find(|h| h.field.equiv("Range"))
+                    .map(|h| parse_range(h.value.as_str(), size));
+                let (status, start, length) = match range {
+                    Some(Some((start, end))) => (206, start, end - start + 1),
+                    Some(None) => {
+                        let response = Response::from_string("Range not satisfiable")
+                            .with_status_code(416)
+                            .with_header(header("Content-Range", &format!("bytes */{}", size))?);
+                        return request.respond(response);
+                    }
+                    None => (200, 0, size),
+                };
+                file.seek(SeekFrom::Start(start))?;
+                let file = file.take(length);
+
+                let remote = request.remote_addr().map(|a| a.ip().to_string()).unwrap_or_default();
+                let label = match start {
+                    0 => format!("{} to {}", book.name, remote),
+                    _ => format!("{} to {} (from {})", book.name, remote, transfer::human_bytes(start as f64)),
+                };
+                let progress = transfers.track(label, Some(size));
+                progress.resume_at(start);
+                let disposition = format!("attachment; filename=\"{}\"", ascii_filename(&book.name));
+                let mut headers = vec![header("Content-Disposition", &disposition)?, header("Accept-Ranges", "bytes")?];
+                // The content hash tells versions apart, for resumed downloads
+                if let Some(hash) = &book.hash {
+                    headers.push(header("ETag", &format!("\"{}\"", hash))?);
+                }
+                if status == 206 {
+                    headers.push(header("Content-Range", &format!("bytes {}-{}/{}", start, start + length - 1, size))?);
+                }
+                // Returns once the whole file was sent (or the peer went away)
+                let result = match secret {
+                    // Sealed: the length on the wire isn't the file's
+                    Some(secret) => SealedReader::new(progress.reader(file), secret).and_then(|reader| {
+                        headers.push(header("Content-Type", SEALED_TYPE)?);
+                        request.respond(Response::new(StatusCode(status), headers, reader, None, None))
+                    }),
+                    None => {
+                        headers.push(header("Content-Type", "application/epub+zip")?);
+                        let reader = progress.reader(file);
+                        request.respond(Response::new(StatusCode(status), headers, reader, Some(length as usize), None))
+                    }
+                };
+                progress.finish(&result);
+                return result;
+            }
+            Err(e) => tracing::warn!(path = %book.path.display(), error = %e, "cannot open book for a peer"),
+        }
+    }
+
+    request.respond(Response::from_string("Not found").with_status_code(404))
+}
+
+/// The user's answer to a peer downloading a book its policy makes us
+/// approve first
+///
+/// # Returns
+/// Whether the download was approved, None while the user hasn't answered
+/// (the request is then queued for the main loop, once)
+fn approval(shared: &Mutex<Shared>, peer: &TrustedPeer, book: &Book) -> Option<bool> {
+    let mut shared = shared.lock().ok()?;
+    let request = AccessRequest {
+        peer_id: peer.id.clone(),
+        peer: peer.name.clone(),
+        path: book.path.clone(),
+        title: book.display_title().to_string(),
+    };
+    if let Some((_, approved)) = shared.answered.iter().find(|(answered, _)| *answered == request) {
+        return Some(*approved);
+    }
+
+    // Asked again (a retry) while the user still has to answer: asked once
+    if !shared.waiting.contains(&request) {
+        tracing::info!(peer = %peer.name, book = %book.name, "download waits for approval");
+        shared.waiting.push(request.clone());
+        shared.access_requests.push(request);
+    }
+    None
+}
+
+/// Checks who sent a request
+///
+/// # Returns
+/// The paired peer and its pair secret, nobody for an unpaired peer (if
+/// they're let in), or why the request is refused
+fn authenticate(request: &Request, path: &str, shared: &Mutex<Shared>) -> Result<Caller, &'static str> {
+    let value = |name: &'static str| {
+        request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv(name))
+            .map(|h| h.value.as_str().to_string())
+    };
+    let mut shared = shared.lock().map_err(|_| "server error")?;
+    let shared = &mut *shared;
+
+    let Some(peer) = value(trust::PEER_HEADER) else {
+        if shared.require_pairing {
+            return Err("pairing required");
+        }
+        return Ok(Caller {
+            secret: None,
+            peer: None,
+            signature: None,
+        });
+    };
+    let signature = Signature {
+        peer,
+        time: value(trust::TIME_HEADER).unwrap_or_default(),
+        nonce: value(trust::NONCE_HEADER).unwrap_or_default(),
+        body: value(trust::BODY_HEADER).unwrap_or_default(),
+        auth: value(trust::AUTH_HEADER).unwrap_or_default(),
+    };
+    let secret = trust::verify(&shared.trusted, &mut shared.seen, request.method().as_str(), path, &signature)?;
+    Ok(Caller {
+        secret: Some(secret),
+        peer: shared.trusted.iter().find(|t| t.id == signature.peer).cloned(),
+        signature: Some(signature),
+    })
+}
+
+/// Answers a pairing request: our half of the key goes back, and the
+/// pairing waits for the asking peer to reveal its own (see finish_pairing)
+fn answer_pairing(mut request: Request, shared: &Mutex<Shared>) -> io::Result<()> {
+    let mut body = String::new();
+    request.as_reader().take(MAX_OFFER_BYTES).read_to_string(&mut body)?;
+    let Ok(theirs) = serde_json::from_str::<PairRequest>(&body) else {
+        return request.respond(Response::from_string("Bad pairing request").with_status_code(400));
+    };
+
+    let (key, session) = (PairingKey::generate()?, trust::random_id()?);
+    let public = key.public().to_string();
+    let name = {
+        let Ok(mut shared) = shared.lock() else {
+            return request.respond(Response::from_string("Server error").with_status_code(500));
+        };
+        if shared.refuse_pairing {
+            drop(shared);
+            let text = "this library doesn't pair here - pair from its TUI";
+            return request.respond(Response::from_string(text).with_status_code(403));
+        }
+        shared.unrevealed.retain(|pending| pending.since.elapsed() < PAIRING_TIMEOUT);
+        if shared.unrevealed.len() >= MAX_PAIRINGS {
+            drop(shared);
+            return request.respond(Response::from_string("busy - retry later").with_status_code(503));
+        }
+        shared.unrevealed.push(Unrevealed {
+            session: session.clone(),
+            request: theirs,
+            key,
+            since: Instant::now(),
+        });
+        shared.name.clone()
+    };
+
+    let ours = PairAnswer {
+        id: trust::local_id(),
+        name,
+        public,
+        session,
+    };
+    respond_json(request, &ours, None)
+}
+
+/// Takes the asking peer's key: if it's the one it committed to, the
+/// pairing waits in the main loop for the user to compare the PINs
+fn finish_pairing(mut request: Request, shared: &Mutex<Shared>) -> io::Result<()> {
+    let mut body = String::new();
+    request.as_reader().take(MAX_OFFER_BYTES).read_to_string(&mut body)?;
+    let Ok(reveal) = serde_json::from_str::<PairReveal>(&body) else {
+        return request.respond(Response::from_string("Bad pairing request").with_status_code(400));
+    };
+
+    let pending = shared.lock().ok().and_then(|mut shared| {
+        let index = shared.unrevealed.iter().position(|pending| pending.session == reveal.session)?;
+        Some(shared.unrevealed.swap_remove(index))
+    });
+    let Some(pending) = pending.filter(|pending| pending.since.elapsed() < PAIRING_TIMEOUT) else {
+        return request.respond(Response::from_string("No such pairing").with_status_code(404));
+    };
+    if trust::commitment(&reveal.public, &pending.request.id) != pending.request.commitment {
+        tracing::warn!(peer = %pending.request.name, "pairing key doesn't match its commitment");
+        return request.respond(Response::from_string("Key doesn't match its commitment").with_status_code(403));
+    }
+
+    let transcript = Transcript {
+        asker: (pending.request.id.clone(), reveal.public),
+        answerer: (trust::local_id(), pending.key.public().to_string()),
+    };
+    let pairing = match trust::agree(pending.key, &transcript, &pending.request.name, false) {
+        Ok(pairing) => pairing,
+        Err(_) => return request.respond(Response::from_string("Bad pairing request").with_status_code(400)),
+    };
+    tracing::info!(peer = %pending.request.name, "pairing requested");
+    if let Ok(mut shared) = shared.lock() {
+        add_pairing(&mut shared.pairings, pairing);
+    }
+    request.respond(Response::from_string("OK"))
+}
+
+/// Adds a pairing to those waiting for the user: one per peer (the latest),
+/// and no more than MAX_PAIRINGS (the oldest go)
+pub fn add_pairing(pairings: &mut Vec<Pairing>, pairing: Pairing) {
+    pairings.retain(|waiting| waiting.peer_id != pairing.peer_id);
+    pairings.push(pairing);
+    if pairings.len() > MAX_PAIRINGS {
+        pairings.drain(..pairings.len() - MAX_PAIRINGS);
+    }
+}
+
+/// Reads a request body (at most `limit` bytes), checked against the
+/// request's signature and opened if it is sealed
+fn read_body(request: &mut Request, limit: u64, caller: &Caller) -> io::Result<String> {
+    let mut raw = Vec::new();
+    request.as_reader().take(limit).read_to_end(&mut raw)?;
+    if let Some(signature) = &caller.signature {
+        if !trust::body_matches(signature, &raw) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "body doesn't match its signature"));
+        }
+    }
+    let mut body = String::new();
+    match &caller.secret {
+        Some(secret) => OpenedReader::new(raw.as_slice(), secret).read_to_string(&mut body)?,
+        None => raw.as_slice().read_to_string(&mut body)?,
+    };
+    Ok(body)
+}
+
+/// Answers with JSON, sealed for a paired peer
+fn respond_json(request: Request, value: &impl Serialize, secret: Option<&Secret>) -> io::Result<()> {
+    let json = serde_json::to_vec(value).map_err(io::Error::other)?;
+    match secret {
+        Some(secret) => {
+            let sealed = trust::seal(secret, &json)?;
+            request.respond(Response::from_data(sealed).with_header(header("Content-Type", SEALED_TYPE)?))
+        }
+        None => request.respond(Response::from_data(json).with_header(header("Content-Type", "application/json")?)),
+    }
+}
+
+/// Builds the catalog of a library: the books a share policy shares
+fn catalog_of(library: &str, books: &[Book], policy: &SharePolicy) -> Catalog {
+    Catalog {
+        library: library.to_string(),
+        books: books
+            .iter()
+            .enumerate()
+            .filter(|(_, book)| policy.shares(book))
+            .map(|(id, book)| entry_of(id, book))
+            .collect(),
+    }
+}
+
+/// Describes a book for other peers
+///
+/// # Arguments
+/// * `id` - Position of the book in the published library
+/// * `book` - The book
+pub fn entry_of(id: usize, book: &Book) -> CatalogEntry {
+    CatalogEntry {
+        id,
+        title: book.display_title().to_string(),
+        authors: book.authors().to_vec(),
+        series: book.meta.series.clone(),
+        name: book.name.clone(),
+        size: std::fs::metadata(&book.path).map(|m| m.len()).ok(),
+        hash: book.hash.clone(),
+    }
+}
+
+/// Asks a peer for its catalog
+///
+/// # Arguments
+/// * `address` - The peer
+/// * `secret` - Our pair secret with the peer (None if we didn't pair)
+///
+/// # Returns
+/// The catalog, or a message saying why the peer couldn't be asked
+pub fn fetch_catalog(address: SocketAddr, secret: Option<&Secret>) -> Result<Catalog, String> {
+    get_json(address, CATALOG_PATH, secret)
+}
+
+/// Asks a peer for its whole library, for two-way sync
+///
+/// # Returns
+/// The library, or a message saying why the peer couldn't be asked
+pub fn fetch_sync_library(address: SocketAddr, secret: Option<&Secret>) -> Result<SyncLibrary, String> {
+    get_json(address, SYNC_PATH, secret)
+}
+
+/// Sends a peer the user data settled by a sync
+pub fn push_sync(address: SocketAddr, push: &SyncPush, secret: Option<&Secret>) -> Result<(), String> {
+    let body = serde_json::to_vec(push).map_err(|e| e.to_string())?;
+    call(&agent(), "POST", address, SYNC_PATH, Some(body), secret).map(|_| ())
+}
+
+/// Offers books to a peer (it answers right away; the user decides later)
+pub fn send_offer(address: SocketAddr, offer: &Offer, secret: Option<&Secret>) -> Result<(), String> {
+    let body = serde_json::to_vec(offer).map_err(|e| e.to_string())?;
+    call(&agent(), "POST", address, OFFER_PATH, Some(body), secret).map(|_| ())
+}
+
+/// Asks a peer to pair
+///
+/// Our key is committed to first and sent once the peer's arrived. Both
+/// sides get the same pairing (and PIN); each keeps the other as trusted
+/// once its user confirmed the PINs match.
+///
+/// # Arguments
+/// * `address` - The peer
+/// * `name` - Our name, shown to the peer's user
+pub fn request_pairing(address: SocketAddr, name: &str) -> Result<Pairing, String> {
+    let key = PairingKey::generate().map_err(|e| e.to_string())?;
+    let id = trust::local_id();
+    let ours = PairRequest {
+        id: id.clone(),
+        name: name.to_string(),
+        commitment: trust::commitment(key.public(), &id),
+    };
+    let body = serde_json::to_vec(&ours).map_err(|e| e.to_string())?;
+    let theirs: PairAnswer = call(&agent(), "POST", address, PAIR_PATH, Some(body), None)?
+        .into_json()
+        .map_err(|e| e.to_string())?;
+
+    let reveal = PairReveal {
+        session: theirs.session.clone(),
+        public: key.public().to_string(),
+    };
+    let body = serde_json::to_vec(&reveal).map_err(|e| e.to_string())?;
+    call(&agent(), "POST", address, PAIR_REVEAL_PATH, Some(body), None)?;
+
+    let transcript = Transcript {
+        asker: (id, key.public().to_string()),
+        answerer: (theirs.id, theirs.public),
+    };
+    trust::agree(key, &transcript, &theirs.name, true).map_err(|e| e.to_string())
+}
+
+/// Downloads a book from a peer into a folder
+///
+/// The file keeps its name; " (2)", " (3)"... is added if the name is taken.
+/// It's fetched in ranges and resumes after a failure (see
+/// `transfer::fetch_chunked`); the content hash the peer gave is checked.
+///
+/// # Arguments
+/// * `address` - The peer
+/// * `entry` - The book, as described by the peer
+/// * `folder` - Where the file goes (created if needed)
+/// * `progress` - The transfer the download is shown as
+/// * `secret` - Our pair secret with the peer (None if we didn't pair)
+///
+/// # Returns
+/// The path of the downloaded file
+pub fn download(
+    address: SocketAddr,
+    entry: &CatalogEntry,
+    folder: &Path,
+    progress: &Progress,
+    secret: Option<&Secret>,
+) -> Result<PathBuf, String> {
+    let path = format!("{}/{}", BOOKS_PATH, entry.id);
+    // No overall timeout: a big book (or a paused transfer) takes a while
+    let agent = ureq::AgentBuilder::new().timeout_connect(TIMEOUT).timeout_read(TIMEOUT).build();
+
+    std::fs::create_dir_all(folder).map_err(|e| e.to_string())?;
+    // The same book from another peer resumes its download too
+    let source = entry.hash.clone().unwrap_or_else(|| format!("{}{}", address, path));
+    let partial = partial_path(folder, &entry.name, &source);
+    transfer::fetch_chunked(&partial, entry.size, progress, |start, end| {
+        let response = request(&agent, "GET", address, &path, &[], secret)?
+            .set("Range", &format!("bytes={}-{}", start, end))
+            .call()
+            .map_err(refusal)?;
+        // Sealed answers have no length: the range says where they are
+        let (start, total) = Chunk::position(&response);
+        Ok(Chunk {
+            tag: Chunk::tag(&response),
+            reader: body_of(response, secret),
+            start,
+            total,
+        })
+    })?;
+
+    let dest = free_path(folder, &entry.name);
+    transfer::complete(&partial, &dest, entry.size, entry.hash.as_deref())?;
+    Ok(dest)
+}
+
+/// Agent for short requests
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new().timeout(TIMEOUT).build()
+}
+
+/// Asks a peer for JSON
+fn get_json<T: DeserializeOwned>(address: SocketAddr, path: &str, secret: Option<&Secret>) -> Result<T, String> {
+    let response = call(&agent(), "GET", address, path, None, secret)?;
+    serde_json::from_reader(body_of(response, secret)).map_err(|e| e.to_string())
+}
+
+/// Sends a request to a peer: signed, with a sealed body, if we paired with it
+///
+/// # Arguments
+/// * `agent` - The agent (timeouts)
+/// * `method` - HTTP method, e.g. "GET"
+/// * `address` - The peer
+/// * `path` - Request path, e.g. "/peer/catalog"
+/// * `body` - JSON body, if any
+/// * `secret` - Our pair secret with the peer (None if we didn't pair)
+///
+/// # Returns
+/// The peer's answer, or a message saying why the peer couldn't be asked
+fn call(
+    agent: &ureq::Agent,
+    method: &str,
+    address: SocketAddr,
+    path: &str,
+    body: Option<Vec<u8>>,
+    secret: Option<&Secret>,
+) -> Result<ureq::Response, String> {
+    // Sealed first: the signature covers the body as sent
+    let body = match (body, secret) {
+        (Some(body), Some(secret)) => Some((trust::seal(secret, &body).map_err(|e| e.to_string())?, SEALED_TYPE)),
+        (Some(body), None) => Some((body, "application/json")),
+        (None, _) => None,
+    };
+    let sent = body.as_ref().map_or(&[][..], |(body, _)| body.as_slice());
+    let request = request(agent, method, address, path, sent, secret)?;
+    let result = match &body {
+        Some((body, content_type)) => request.set("Content-Type", content_type).send_bytes(body),
+        None => request.call(),
+    };
+    result.map_err(refusal)
+}
+
+/// Starts a request to a peer, signed (with its body) if we paired with it
+fn request(
+    agent: &ureq::Agent,
+    method: &str,
+    address: SocketAddr,
+    path: &str,
+    body: &[u8],
+    secret: Option<&Secret>,
+) -> Result<ureq::Request, String> {
+    let mut request = agent.request(method, &format!("http://{}{}", address, path));
+    if let Some(secret) = secret {
+        for (name, value) in trust::auth_headers(secret, method, path, body).map_err(|e| e.to_string())? {
+            request = request.set(name, &value);
+        }
+    }
+    Ok(request)
+}
+
+/// Says why a peer didn't answer a request
+fn refusal(error: ureq::Error) -> String {
+    match error {
+        ureq::Error::Status(401, response) => format!(
+            "refused ({}) - pair with the peer first",
+            response.into_string().unwrap_or_default()
+        ),
+        // Not shared with us (or not yet): the peer says why
+        ureq::Error::Status(403, response) => response.into_string().unwrap_or_else(|_| "refused".to_string()),
+        e => e.to_string(),
+    }
+}
+
+/// The body of a peer's answer, opened if we paired with the peer
+fn body_of(response: ureq::Response, secret: Option<&Secret>) -> Box<dyn Read + Send + Sync> {
+    match secret {
+        Some(secret) => Box::new(OpenedReader::new(response.into_reader(), secret)),
+        None => response.into_reader(),
+    }
+}
+
+/// Where a download is written until it's complete, e.g.
+/// ".Dune.epub.3f2a9c01.part" (hidden, and not an EPUB for the scanner)
+///
+/// # Arguments
+/// * `folder` - Where the file goes
+/// * `name` - Its name
+/// * `source` - What it's downloaded from (a URL, a content hash...): two
+///   books of the same name never resume into each other's file
+pub fn partial_path(folder: &Path, name: &str, source: &str) -> PathBuf {
+    let name = Path::new(name).file_name().unwrap_or_default().to_string_lossy();
+    let id = ring::digest::digest(&ring::digest::SHA256, source.as_bytes());
+    let id: String = id.as_ref()[..4].iter().map(|b| format!("{:02x}", b)).collect();
+    folder.join(format!(".{}.{}.part", name, id))
+}
+
+/// Reads a Range header ("bytes=100-199", "bytes=100-" or "bytes=-100")
+///
+/// # Returns
+/// First and last byte asked for, None if the range isn't satisfiable
+fn parse_range(value: &str, size: u64) -> Option<(u64, u64)> {
+    let (first, last) = value.trim().strip_prefix("bytes=")?.split_once('-')?;
+    let (start, end) = match (first.trim().parse::<u64>().ok(), last.trim().parse::<u64>().ok()) {
+        (Some(start), Some(end)) => (start, end.min(size.checked_sub(1)?)),
+        (Some(start), None) => (start, size.checked_sub(1)?),
+        // The last bytes
+        (None, Some(suffix)) => (size.saturating_sub(suffix), size.checked_sub(1)?),
+        (None, None) => return None,
+    };
+    (start <= end).then_some((start, end))
+}
+
+/// A path in `folder` for `name` that doesn't exist yet
+pub fn free_path(folder: &Path, name: &str) -> PathBuf {
+    // Only the file name counts: a peer can't send files elsewhere
+    let name = Path::new(name)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "book.epub".to_string());
+    let path = folder.join(&name);
+    if !path.exists() {
+        return path;
+    }
+
+    let stem = Path::new(&name).file_stem().unwrap_or_default().to_string_lossy().to_string();
+    (2..)
+        .map(|n| folder.join(format!("{} ({}).epub", stem, n)))
+        .find(|path| !path.exists())
+        .unwrap_or(path)
+}
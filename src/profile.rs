@@ -0,0 +1,370 @@
+// src/profile.rs
+// Named library profiles - each profile is an independent library with its own
+// scan roots, database and settings, stored in its own directory
+
+use crate::book::Book;
+use crate::sort::SortOrder;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the profile used when no `--library` flag is given
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// File (inside the profile directory) holding the profile settings
+const SETTINGS_FILE: &str = "settings.json";
+
+/// File (inside the profile directory) holding the library database
+const DATABASE_FILE: &str = "library.json";
+
+/// File (inside the profile directory) holding the full-text index
+const FULLTEXT_FILE: &str = "fulltext.sqlite";
+
+/// Settings that belong to a single library profile
+/// Default gives an empty settings object for brand new profiles
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfileSettings {
+    /// Folders scanned for EPUB files when this library is (re)scanned
+    #[serde(default)]
+    pub scan_paths: Vec<PathBuf>,
+
+    /// Settings of individual scan roots, keyed by the root path
+    /// (roots without an entry use the defaults)
+    #[serde(default)]
+    pub folders: BTreeMap<PathBuf, FolderSettings>,
+
+    /// Names of the collections (shelves) of this library, in creation order
+    /// Kept here so a collection survives while it's still empty
+    #[serde(default)]
+    pub collections: Vec<String>,
+
+    /// Smart collections (saved filters) of this library
+    #[serde(default)]
+    pub smart_collections: Vec<SmartCollection>,
+
+    /// Merged author spellings: spelling -> canonical name
+    /// Applied to newly scanned books so merges stick
+    #[serde(default)]
+    pub author_aliases: BTreeMap<String, String>,
+
+    /// When this library was last synced with each partner
+    /// (seconds since the Unix epoch, keyed by `sync::Partner::key`)
+    #[serde(default)]
+    pub last_sync: BTreeMap<String, u64>,
+}
+
+/// A saved filter and sort order, shown like a collection
+/// Its books are found again every time it's shown, so it stays up to date
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SmartCollection {
+    /// Name shown in the collection picker, e.g. "Unread fantasy"
+    pub name: String,
+
+    /// Filter expression (see `filter`), e.g. "status:unread tag:fantasy size:<1mb"
+    pub query: String,
+
+    /// Order of the books when the collection is shown
+    #[serde(default)]
+    pub sort: SortOrder,
+}
+
+/// Which part of the library the book list shows
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Shelf {
+    /// Every book
+    #[default]
+    All,
+    /// The books of a collection
+    Collection(String),
+    /// The books matching a smart collection
+    Smart(String),
+    /// Books added in the last `RECENT_DAYS` days, newest first
+    RecentlyAdded,
+    /// Books opened at least once, most recent first
+    RecentlyOpened,
+    /// The books of one series, in reading order
+    Series(String),
+}
+
+/// How many days a book counts as "recently added"
+pub const RECENT_DAYS: u64 = 30;
+
+/// Settings attached to a single scan root
+/// Default: writable, not watched, nothing excluded, no tags
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FolderSettings {
+    /// FunkHunt never modifies files in this folder (no renames, moves or deletes)
+    pub read_only: bool,
+
+    /// Rescan the folder automatically when files appear or disappear
+    pub auto_watch: bool,
+
+    /// Wildcard patterns of files or folders to skip, e.g. "*.sample.epub" or "Drafts"
+    /// Patterns containing '/' are matched against the path relative to the root
+    pub exclude: Vec<String>,
+
+    /// Tags added to every book found in this folder
+    pub default_tags: Vec<String>,
+
+    /// Keep each book's user data in a "<book>.funkhunt.json" file next to it
+    /// (see `sidecar`), and read those files back when scanning
+    pub sidecars: bool,
+
+    /// Write titles, authors and series edited in FunkHunt back into the
+    /// EPUB files, so other readers see them (ignored when read-only)
+    pub write_metadata: bool,
+}
+
+impl ProfileSettings {
+    /// Gets the settings of a scan root (defaults if none were saved)
+    pub fn folder(&self, root: &Path) -> FolderSettings {
+        self.folders.get(root).cloned().unwrap_or_default()
+    }
+
+    /// Lists every scan root with its settings, in scan order
+    pub fn folder_list(&self) -> Vec<(PathBuf, FolderSettings)> {
+        self.scan_paths
+            .iter()
+            .map(|root| (root.clone(), self.folder(root)))
+            .collect()
+    }
+
+    /// Scan roots that should be watched for changes
+    /// (remote roots can't be watched - they're rescanned by hand)
+    pub fn watched_roots(&self) -> Vec<PathBuf> {
+        self.scan_paths
+            .iter()
+            .filter(|root| self.folder(root).auto_watch && !crate::remote::is_remote(root))
+            .cloned()
+            .collect()
+    }
+}
+
+/// A named library profile (e.g. "default", "work", "kids")
+#[derive(Debug)]
+pub struct Profile {
+    /// Profile name as given on the command line (`--library work`)
+    pub name: String,
+
+    /// Directory where this profile keeps its database and settings
+    pub dir: PathBuf,
+
+    /// Settings loaded from the profile directory
+    pub settings: ProfileSettings,
+}
+
+impl Profile {
+    /// Opens (or creates) the profile with the given name
+    ///
+    /// The profile directory is created if it doesn't exist yet, so opening
+    /// an unknown name simply starts a new, empty library.
+    ///
+    /// # Arguments
+    /// * `name` - The profile name (must be a plain name, not a path)
+    ///
+    /// # Returns
+    /// The opened Profile, or an error if the name is invalid or the directory can't be created
+    pub fn open(name: &str) -> io::Result<Self> {
+        // Reject names that would escape the profiles directory
+        if !is_valid_name(name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid library name: '{}'", name),
+            ));
+        }
+
+        // Make sure the profile directory exists
+        let dir = profiles_root().join(name);
+        std::fs::create_dir_all(&dir)?;
+
+        // Load settings - a missing or unreadable file means default settings
+        let settings = std::fs::read_to_string(dir.join(SETTINGS_FILE))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+
+        Ok(Self {
+            name: name.to_string(),
+            dir,
+            settings,
+        })
+    }
+
+    /// Writes the profile settings back to disk
+    pub fn save_settings(&self) -> io::Result<()> {
+        // Pretty JSON so the file stays human-editable
+        let json = serde_json::to_string_pretty(&self.settings).map_err(io::Error::other)?;
+        std::fs::write(self.dir.join(SETTINGS_FILE), json)
+    }
+
+    /// Path of this profile's library database file
+    pub fn database_path(&self) -> PathBuf {
+        self.dir.join(DATABASE_FILE)
+    }
+
+    /// Path of this profile's full-text index of the books' text
+    pub fn fulltext_path(&self) -> PathBuf {
+        self.dir.join(FULLTEXT_FILE)
+    }
+
+    /// Scans all of this profile's scan roots and returns all found books
+    ///
+    /// # Arguments
+    /// * `lazy` - Leave the EPUBs unread: placeholders for the parse queue (the TUI)
+    ///
+    /// # Returns
+    /// A Vec<Book> containing all EPUB files found in all scan paths
+    pub fn scan_all_paths(&self, lazy: bool) -> Vec<Book> {
+        let scan_folder = if lazy { crate::scanner::scan_placeholders } else { crate::scanner::scan_folder };
+
+        // Accumulator for all books across all paths
+        let mut all_books = Vec::new();
+
+        // Scan each path (with its folder settings) and add results to accumulator
+        for path in &self.settings.scan_paths {
+            let mut books = scan_folder(path, &self.settings.folder(path));
+            // append() moves all elements from books into all_books
+            all_books.append(&mut books);
+        }
+
+        // Use the merged author names for the new books too
+        crate::authors::apply_aliases(&mut all_books, &self.settings.author_aliases);
+
+        all_books
+    }
+
+    /// Reads the packages of the placeholders saved by the TUI before its
+    /// parse queue got to them (the commands without the TUI show what
+    /// they read up front)
+    ///
+    /// # Arguments
+    /// * `books` - The books of the library (modified in place)
+    ///
+    /// # Returns
+    /// How many placeholders were read
+    pub fn fill_placeholders(&self, books: &mut [Book]) -> usize {
+        let mut filled = 0;
+        for book in books.iter_mut().filter(|b| b.is_placeholder() && !crate::remote::is_remote(&b.path) && b.path.is_file()) {
+            // Only the package is taken: the user data stays the library's
+            let parsed = crate::scanner::book_from_file(&book.path, &FolderSettings::default());
+            if parsed.is_placeholder() {
+                continue;
+            }
+            book.meta = parsed.meta;
+            book.detected_language = parsed.detected_language;
+            book.readability = parsed.readability;
+            crate::authors::apply_aliases(std::slice::from_mut(book), &self.settings.author_aliases);
+            filled += 1;
+        }
+        if filled > 0 {
+            tracing::info!(books = filled, "metadata of placeholders read");
+        }
+        filled
+    }
+
+    /// Rescans one scan root and merges the result into the library
+    ///
+    /// Books that are still there keep their user data, new files are
+    /// added and books whose files are gone (or now excluded) are removed.
+    ///
+    /// # Arguments
+    /// * `books` - The books of the library (modified in place)
+    /// * `root` - The scan root to rescan
+    /// * `lazy` - Leave the new EPUBs unread: placeholders for the parse queue (the TUI)
+    pub fn rescan_root(&self, books: &mut Vec<Book>, root: &Path, lazy: bool) {
+        if let Some(scanned) = scan_root(root, &self.settings.folder(root), lazy) {
+            self.merge_scan(books, root, scanned);
+        }
+    }
+
+    /// Merges the books found by scanning one scan root (`scan_root`) into
+    /// the library - the second half of `rescan_root`, for scans done on
+    /// another thread
+    ///
+    /// # Arguments
+    /// * `books` - The books of the library (modified in place)
+    /// * `root` - The scan root that was scanned
+    /// * `scanned` - The books found there
+    pub fn merge_scan(&self, books: &mut Vec<Book>, root: &Path, mut scanned: Vec<Book>) {
+        let settings = self.settings.folder(root);
+        crate::authors::apply_aliases(&mut scanned, &self.settings.author_aliases);
+        crate::scanner::merge_rescan(books, root, scanned, &settings.default_tags);
+
+        // Sidecars changed by another tool since the last save win
+        if settings.sidecars {
+            let saved = std::fs::metadata(self.database_path())
+                .and_then(|m| m.modified())
+                .ok();
+            for book in books.iter_mut().filter(|b| b.path.starts_with(root)) {
+                if let Some(user) = crate::sidecar::read_if_newer(&book.path, saved) {
+                    book.user = user;
+                }
+            }
+        }
+    }
+}
+
+/// Scans one scan root without touching the library - the first half of
+/// `Profile::rescan_root`
+///
+/// # Arguments
+/// * `root` - The scan root
+/// * `settings` - Its folder settings
+/// * `lazy` - Leave the EPUBs unread: placeholders for the parse queue (the TUI)
+///
+/// # Returns
+/// The books found, or None for a server that can't be reached (its books
+/// are kept - it's most likely just offline)
+pub fn scan_root(root: &Path, settings: &FolderSettings, lazy: bool) -> Option<Vec<Book>> {
+    if crate::remote::is_remote(root) {
+        return match crate::remote::scan(root, settings) {
+            Ok(scanned) => Some(scanned),
+            Err(e) => {
+                tracing::warn!(path = %root.display(), error = %e, "cannot rescan remote folder");
+                None
+            }
+        };
+    }
+    Some(if lazy {
+        crate::scanner::scan_placeholders(root, settings)
+    } else {
+        crate::scanner::scan_folder(root, settings)
+    })
+}
+
+/// Directory that contains one sub-directory per profile
+///
+/// # Returns
+/// The `libraries` folder of the platform data directory (see `paths`)
+pub fn profiles_root() -> PathBuf {
+    crate::paths::libraries_dir()
+}
+
+/// Lists the names of all existing profiles, sorted alphabetically
+///
+/// The default profile is always included, even before it was first created.
+pub fn list_profiles() -> Vec<String> {
+    let mut names = vec![DEFAULT_PROFILE.to_string()];
+
+    // Every sub-directory of the profiles root is a profile
+    if let Ok(read_dir) = std::fs::read_dir(profiles_root()) {
+        for entry in read_dir.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.path().is_dir() && is_valid_name(&name) && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+
+    names.sort();
+    names
+}
+
+/// Checks that a profile name is a plain directory name
+/// (non-empty, not hidden, no path separators)
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\'])
+}
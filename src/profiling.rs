@@ -0,0 +1,110 @@
+// src/profiling.rs
+// Where the time goes (`--profile FILE`), in builds with the `profiling`
+// feature: `cargo build --release --features profiling`
+//
+// Scanning, reading packages, hashing, drawing and handling events run in
+// tracing spans (trace level, compiled in only with the feature). This layer
+// times them on every thread and, when the app ends, writes what it measured
+// as folded stacks - a line per stack of spans, with the microseconds spent
+// in it and not in the spans below:
+//
+//   scan;parse;metadata 182034
+//   render 5120
+//
+// which flamegraph.pl, inferno-flamegraph and speedscope turn into a flame
+// graph. Time outside any span (waiting for keys) isn't counted.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::span::Id;
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Microseconds per stack ("scan;parse;metadata"), of all threads
+static STACKS: Mutex<Option<HashMap<String, u64>>> = Mutex::new(None);
+
+/// Where the stacks are written (set by `layer`)
+static OUTPUT: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+thread_local! {
+    /// The spans this thread is in, outermost first, and when the
+    /// innermost started counting (entered, or left by the span below it)
+    static ENTERED: RefCell<(Vec<&'static str>, Option<Instant>)> = const { RefCell::new((Vec::new(), None)) };
+}
+
+/// Times the spans, to be added to the logger
+pub struct Profiler;
+
+/// The profiler, its stacks written to `path` by `finish`
+pub fn layer(path: &Path) -> Profiler {
+    *OUTPUT.lock().unwrap_or_else(|e| e.into_inner()) = Some(path.to_path_buf());
+    *STACKS.lock().unwrap_or_else(|e| e.into_inner()) = Some(HashMap::new());
+    Profiler
+}
+
+impl<S> Layer<S> for Profiler
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        let Some(metadata) = ctx.metadata(id) else {
+            return;
+        };
+        ENTERED.with(|entered| {
+            let (stack, since) = &mut *entered.borrow_mut();
+            count(stack, since);
+            stack.push(metadata.name());
+        });
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        if ctx.metadata(id).is_none() {
+            return;
+        }
+        ENTERED.with(|entered| {
+            let (stack, since) = &mut *entered.borrow_mut();
+            count(stack, since);
+            stack.pop();
+        });
+    }
+}
+
+/// Adds the time since `since` to the stack, and starts counting again
+fn count(stack: &[&'static str], since: &mut Option<Instant>) {
+    let now = Instant::now();
+    if let (Some(started), false) = (since.replace(now), stack.is_empty()) {
+        let micros = now.duration_since(started).as_micros() as u64;
+        if let Some(stacks) = STACKS.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            *stacks.entry(stack.join(";")).or_default() += micros;
+        }
+    }
+}
+
+/// Writes the stacks measured so far (when the app ends); nothing when
+/// the app wasn't profiled
+pub fn finish() {
+    let Some(path) = OUTPUT.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+        return;
+    };
+    let stacks = STACKS.lock().unwrap_or_else(|e| e.into_inner()).take().unwrap_or_default();
+    match write(&path, &stacks) {
+        Ok(()) => tracing::info!(path = %path.display(), stacks = stacks.len(), "profile written"),
+        Err(e) => tracing::warn!(path = %path.display(), error = %e, "cannot write profile"),
+    }
+}
+
+/// Writes folded stacks, a line per stack (sorted, so profiles compare)
+fn write(path: &Path, stacks: &HashMap<String, u64>) -> io::Result<()> {
+    let mut lines: Vec<(&String, &u64)> = stacks.iter().collect();
+    lines.sort();
+    let mut out = io::BufWriter::new(std::fs::File::create(path)?);
+    for (stack, micros) in lines {
+        writeln!(out, "{} {}", stack, micros)?;
+    }
+    out.flush()
+}
@@ -0,0 +1,128 @@
+// src/providers/googlebooks.rs
+// Looks books up on Google Books (https://developers.google.com/books)
+// Useful when Open Library has no record, especially for recent books
+
+use super::openlibrary::{MAX_SUBJECTS, TIMEOUT, USER_AGENT};
+use super::Provider;
+use crate::book::Metadata;
+use serde::Deserialize;
+
+/// Volume search endpoint of the Google Books API
+const SEARCH_URL: &str = "https://www.googleapis.com/books/v1/volumes";
+
+/// The part of the search answer we use
+#[derive(Deserialize)]
+struct SearchResponse {
+    /// Missing when nothing matched
+    #[serde(default)]
+    items: Vec<Volume>,
+}
+
+/// One search result
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Volume {
+    volume_info: VolumeInfo,
+}
+
+/// Descriptive data of a volume
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct VolumeInfo {
+    title: Option<String>,
+    subtitle: Option<String>,
+    authors: Vec<String>,
+    publisher: Option<String>,
+    /// "2004", "2004-10" or "2004-10-21"
+    published_date: Option<String>,
+    description: Option<String>,
+    industry_identifiers: Vec<Identifier>,
+    categories: Vec<String>,
+}
+
+/// An ISBN (or other id) of a volume
+#[derive(Deserialize)]
+struct Identifier {
+    /// "ISBN_13", "ISBN_10" or "OTHER"
+    #[serde(rename = "type")]
+    kind: String,
+    identifier: String,
+}
+
+/// The Google Books provider
+pub struct GoogleBooks {
+    /// API key from config.toml (None = anonymous requests)
+    pub api_key: Option<String>,
+}
+
+impl Provider for GoogleBooks {
+    fn name(&self) -> &'static str {
+        "Google Books"
+    }
+
+    /// Searches by ISBN when the book has one, by title and first author otherwise
+    fn lookup(&self, meta: &Metadata, file_name: &str) -> Result<Option<Metadata>, String> {
+        // Google's query syntax: "isbn:978..." or "intitle:Dune inauthor:Herbert"
+        let query = match &meta.isbn {
+            Some(isbn) => format!("isbn:{}", isbn),
+            None => {
+                let title = meta.title.as_deref().unwrap_or(file_name);
+                match meta.authors.first() {
+                    Some(author) => format!("intitle:{} inauthor:{}", title, author),
+                    None => format!("intitle:{}", title),
+                }
+            }
+        };
+
+        let mut request = ureq::get(SEARCH_URL)
+            .timeout(TIMEOUT)
+            .set("User-Agent", USER_AGENT)
+            .query("q", &query)
+            .query("maxResults", "1");
+        if let Some(key) = &self.api_key {
+            request = request.query("key", key);
+        }
+
+        let body = request
+            .call()
+            .map_err(|e| format!("Google Books: {}", e))?
+            .into_string()
+            .map_err(|e| format!("Google Books: {}", e))?;
+        let response: SearchResponse = serde_json::from_str(&body)
+            .map_err(|e| format!("Google Books sent an unexpected answer: {}", e))?;
+
+        Ok(response
+            .items
+            .into_iter()
+            .next()
+            .map(|volume| to_metadata(volume.volume_info)))
+    }
+}
+
+/// Converts a volume to our metadata record
+fn to_metadata(info: VolumeInfo) -> Metadata {
+    // "Dune" + "Deluxe Edition" -> "Dune: Deluxe Edition"
+    let title = match (info.title, info.subtitle) {
+        (Some(title), Some(subtitle)) => Some(format!("{}: {}", title, subtitle)),
+        (title, _) => title,
+    };
+
+    // Prefer the 13-digit ISBN
+    let isbn = ["ISBN_13", "ISBN_10"].iter().find_map(|kind| {
+        info.industry_identifiers
+            .iter()
+            .find(|id| id.kind == *kind)
+            .map(|id| id.identifier.clone())
+    });
+
+    Metadata {
+        title,
+        authors: info.authors,
+        publisher: info.publisher,
+        published: info.published_date,
+        description: info.description,
+        isbn,
+        subjects: info.categories.into_iter().take(MAX_SUBJECTS).collect(),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,206 @@
+// src/providers/mod.rs
+// Online metadata providers - look a book up on a web service and propose
+// changes to its local record, which the user reviews before applying
+
+pub mod googlebooks;
+pub mod openlibrary;
+
+use crate::book::Metadata;
+use crate::settings::{MetadataSettings, ProviderChoice};
+
+/// A web service that knows about books
+pub trait Provider {
+    /// Human-readable name, e.g. "Open Library"
+    fn name(&self) -> &'static str;
+
+    /// Looks a book up
+    ///
+    /// # Arguments
+    /// * `meta` - The book's current metadata (ISBN, title, authors)
+    /// * `file_name` - Used as the title when the metadata has none
+    ///
+    /// # Returns
+    /// * `Ok(Some(meta))` - Metadata of the best match
+    /// * `Ok(None)` - The service doesn't know the book
+    /// * `Err(message)` - The request failed
+    fn lookup(&self, meta: &Metadata, file_name: &str) -> Result<Option<Metadata>, String>;
+}
+
+/// The providers to ask, in order, for the configured choice
+pub fn configured(settings: &MetadataSettings) -> Vec<Box<dyn Provider>> {
+    let open_library = || Box::new(openlibrary::OpenLibrary) as Box<dyn Provider>;
+    let google_books = || {
+        Box::new(googlebooks::GoogleBooks {
+            api_key: settings.google_books_api_key.clone(),
+        }) as Box<dyn Provider>
+    };
+
+    match settings.provider {
+        ProviderChoice::Auto => vec![open_library(), google_books()],
+        ProviderChoice::OpenLibrary => vec![open_library()],
+        ProviderChoice::GoogleBooks => vec![google_books()],
+    }
+}
+
+/// Asks the configured providers in turn until one knows the book
+///
+/// A failing provider doesn't stop the search; its error is only reported
+/// when no other provider found the book.
+///
+/// # Returns
+/// * `Ok(Some((provider name, meta)))` - The first match
+/// * `Ok(None)` - No provider knows the book
+/// * `Err(message)` - No match, and a request failed
+pub fn lookup(
+    settings: &MetadataSettings,
+    meta: &Metadata,
+    file_name: &str,
+) -> Result<Option<(&'static str, Metadata)>, String> {
+    let mut last_error = None;
+
+    for provider in configured(settings) {
+        match provider.lookup(meta, file_name) {
+            Ok(Some(found)) => return Ok(Some((provider.name(), found))),
+            Ok(None) => {}
+            Err(e) => {
+                tracing::info!(provider = provider.name(), error = %e, "provider failed, trying the next one");
+                last_error = Some(e);
+            }
+        }
+    }
+
+    match last_error {
+        Some(e) => Err(e),
+        None => Ok(None),
+    }
+}
+
+/// Fields a provider can propose a new value for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetaField {
+    Title,
+    Authors,
+    Publisher,
+    Published,
+    Description,
+    Subjects,
+    Isbn,
+    Series,
+}
+
+impl MetaField {
+    /// All fields, in the order they're shown
+    pub const ALL: [MetaField; 8] = [
+        MetaField::Title,
+        MetaField::Authors,
+        MetaField::Publisher,
+        MetaField::Published,
+        MetaField::Description,
+        MetaField::Subjects,
+        MetaField::Isbn,
+        MetaField::Series,
+    ];
+
+    /// Human-readable name, e.g. "Publisher"
+    pub fn label(self) -> &'static str {
+        match self {
+            MetaField::Title => "Title",
+            MetaField::Authors => "Authors",
+            MetaField::Publisher => "Publisher",
+            MetaField::Published => "Published",
+            MetaField::Description => "Description",
+            MetaField::Subjects => "Subjects",
+            MetaField::Isbn => "ISBN",
+            MetaField::Series => "Series",
+        }
+    }
+
+    /// The field's value as one line of text ("" = not set)
+    pub fn text(self, meta: &Metadata) -> String {
+        match self {
+            MetaField::Title => meta.title.clone().unwrap_or_default(),
+            MetaField::Authors => meta.authors.join(", "),
+            MetaField::Publisher => meta.publisher.clone().unwrap_or_default(),
+            MetaField::Published => meta.published.clone().unwrap_or_default(),
+            MetaField::Description => meta.description.clone().unwrap_or_default(),
+            MetaField::Subjects => meta.subjects.join(", "),
+            MetaField::Isbn => meta.isbn.clone().unwrap_or_default(),
+            MetaField::Series => match (&meta.series, meta.series_index) {
+                (Some(name), Some(index)) => format!("{} #{}", name, index),
+                (Some(name), None) => name.clone(),
+                _ => String::new(),
+            },
+        }
+    }
+
+    /// Copies the field from one record to another
+    pub fn copy(self, from: &Metadata, to: &mut Metadata) {
+        match self {
+            MetaField::Title => to.title = from.title.clone(),
+            MetaField::Authors => to.authors = from.authors.clone(),
+            MetaField::Publisher => to.publisher = from.publisher.clone(),
+            MetaField::Published => to.published = from.published.clone(),
+            MetaField::Description => to.description = from.description.clone(),
+            MetaField::Subjects => to.subjects = from.subjects.clone(),
+            MetaField::Isbn => to.isbn = from.isbn.clone(),
+            MetaField::Series => {
+                to.series = from.series.clone();
+                to.series_index = from.series_index;
+            }
+        }
+    }
+}
+
+/// One proposed change to a book's metadata
+#[derive(Debug, Clone)]
+pub struct Change {
+    /// Which field changes
+    pub field: MetaField,
+
+    /// Current value ("" = not set)
+    pub old: String,
+
+    /// Proposed value
+    pub new: String,
+
+    /// Whether the change will be applied (the user can untick it)
+    pub accepted: bool,
+}
+
+/// Compares a book's metadata with what a provider found
+///
+/// Fields the provider knows nothing about are left out, as are fields
+/// that already have the proposed value.
+///
+/// # Returns
+/// The proposed changes, all accepted
+pub fn diff(current: &Metadata, proposed: &Metadata) -> Vec<Change> {
+    MetaField::ALL
+        .iter()
+        .map(|&field| (field, field.text(current), field.text(proposed)))
+        .filter(|(_, old, new)| !new.is_empty() && old != new)
+        .map(|(field, old, new)| Change {
+            field,
+            old,
+            new,
+            accepted: true,
+        })
+        .collect()
+}
+
+/// Applies the accepted changes to a book's metadata
+///
+/// # Arguments
+/// * `meta` - The book's metadata (modified in place)
+/// * `proposed` - What the provider found
+/// * `changes` - The reviewed changes; only accepted ones are applied
+///
+/// # Returns
+/// How many fields changed
+pub fn apply(meta: &mut Metadata, proposed: &Metadata, changes: &[Change]) -> usize {
+    let accepted: Vec<&Change> = changes.iter().filter(|c| c.accepted).collect();
+    for change in &accepted {
+        change.field.copy(proposed, meta);
+    }
+    accepted.len()
+}
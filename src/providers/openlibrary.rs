@@ -0,0 +1,105 @@
+// src/providers/openlibrary.rs
+// Looks books up on Open Library (https://openlibrary.org) by ISBN, or by
+// title and author when the book has no ISBN
+
+use super::Provider;
+use crate::book::Metadata;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Search endpoint of Open Library
+const SEARCH_URL: &str = "https://openlibrary.org/search.json";
+
+/// Fields requested from the search, so the answer stays small
+const SEARCH_FIELDS: &str = "title,author_name,publisher,first_publish_year,isbn,subject";
+
+/// Open Library asks clients to identify themselves
+pub(super) const USER_AGENT: &str = concat!("FunkHunt/", env!("CARGO_PKG_VERSION"), " (metadata lookup)");
+
+/// How long to wait for an answer before giving up
+pub(super) const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// At most this many subjects are taken (Open Library lists dozens)
+pub(super) const MAX_SUBJECTS: usize = 10;
+
+/// The part of the search answer we use
+#[derive(Deserialize)]
+struct SearchResponse {
+    docs: Vec<SearchDoc>,
+}
+
+/// One search result (a "work" on Open Library)
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct SearchDoc {
+    title: Option<String>,
+    author_name: Vec<String>,
+    publisher: Vec<String>,
+    first_publish_year: Option<u32>,
+    isbn: Vec<String>,
+    subject: Vec<String>,
+}
+
+/// The Open Library provider (no account or key needed)
+pub struct OpenLibrary;
+
+impl Provider for OpenLibrary {
+    fn name(&self) -> &'static str {
+        "Open Library"
+    }
+
+    /// Searches by ISBN when the book has one, by title and first author otherwise
+    fn lookup(&self, meta: &Metadata, file_name: &str) -> Result<Option<Metadata>, String> {
+        search(meta, file_name)
+    }
+}
+
+/// Runs the search request and takes the first result
+fn search(meta: &Metadata, file_name: &str) -> Result<Option<Metadata>, String> {
+    let mut request = ureq::get(SEARCH_URL)
+        .timeout(TIMEOUT)
+        .set("User-Agent", USER_AGENT)
+        .query("fields", SEARCH_FIELDS)
+        .query("limit", "1");
+
+    request = match &meta.isbn {
+        Some(isbn) => request.query("isbn", isbn),
+        None => {
+            let title = meta.title.as_deref().unwrap_or(file_name);
+            request = request.query("title", title);
+            match meta.authors.first() {
+                Some(author) => request.query("author", author),
+                None => request,
+            }
+        }
+    };
+
+    let body = request
+        .call()
+        .map_err(|e| format!("Open Library: {}", e))?
+        .into_string()
+        .map_err(|e| format!("Open Library: {}", e))?;
+    let response: SearchResponse =
+        serde_json::from_str(&body).map_err(|e| format!("Open Library sent an unexpected answer: {}", e))?;
+
+    Ok(response.docs.into_iter().next().map(to_metadata))
+}
+
+/// Converts a search result to our metadata record
+fn to_metadata(doc: SearchDoc) -> Metadata {
+    Metadata {
+        title: doc.title,
+        authors: doc.author_name,
+        publisher: doc.publisher.into_iter().next(),
+        published: doc.first_publish_year.map(|year| year.to_string()),
+        // Prefer the 13-digit ISBN
+        isbn: doc
+            .isbn
+            .iter()
+            .find(|isbn| isbn.len() == 13)
+            .or(doc.isbn.first())
+            .cloned(),
+        subjects: doc.subject.into_iter().take(MAX_SUBJECTS).collect(),
+        ..Default::default()
+    }
+}
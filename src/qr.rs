@@ -0,0 +1,615 @@
+// src/qr.rs
+// QR codes - just enough of ISO/IEC 18004 to show a URL in the terminal
+//
+// Byte mode, error correction level M, versions 1 to 10 (up to 213 bytes:
+// plenty for a LAN URL). The mask is chosen by the standard's penalty rules.
+// Rendered with half-block characters: one text line holds two module rows.
+
+/// Largest version supported (57 x 57 modules)
+const MAX_VERSION: usize = 10;
+
+/// Light modules around the code (the standard asks for 4)
+const QUIET_ZONE: usize = 4;
+
+/// Error correction of level M per version (index 0 = version 1):
+/// (EC codewords per block, blocks in group 1, data codewords per block
+/// of group 1, blocks in group 2; group 2 blocks hold one codeword more)
+const EC_BLOCKS: [(usize, usize, usize, usize); MAX_VERSION] = [
+    (10, 1, 16, 0),
+    (16, 1, 28, 0),
+    (26, 1, 44, 0),
+    (18, 2, 32, 0),
+    (24, 2, 43, 0),
+    (16, 4, 27, 0),
+    (18, 4, 31, 0),
+    (22, 2, 38, 2),
+    (22, 3, 36, 2),
+    (26, 4, 43, 1),
+];
+
+/// Centers of the alignment patterns per version (index 0 = version 1)
+const ALIGNMENT: [&[usize]; MAX_VERSION] = [
+    &[],
+    &[6, 18],
+    &[6, 22],
+    &[6, 26],
+    &[6, 30],
+    &[6, 34],
+    &[6, 22, 38],
+    &[6, 24, 42],
+    &[6, 26, 46],
+    &[6, 28, 50],
+];
+
+/// Format bits of level M
+const LEVEL_M: u32 = 0b00;
+
+/// A QR code: a square of dark and light modules
+#[derive(Debug, Clone)]
+pub struct QrCode {
+    /// Modules per side
+    size: usize,
+
+    /// Module colors by row then column (true = dark)
+    modules: Vec<Vec<bool>>,
+
+    /// Modules that aren't data (finders, timing...), never masked
+    function: Vec<Vec<bool>>,
+}
+
+impl QrCode {
+    /// Encodes text (e.g. a URL) in the smallest version that holds it
+    ///
+    /// # Returns
+    /// The code, or None if the text is too long for the supported versions
+    pub fn encode(text: &str) -> Option<Self> {
+        let data = text.as_bytes();
+        let version = (1..=MAX_VERSION).find(|&v| data_capacity(v) * 8 >= data_bits(v, data.len()))?;
+
+        let mut code = Self::blank(version);
+        code.draw_function_patterns(version);
+        code.draw_data(&codewords(version, data));
+
+        // The mask with the lowest penalty wins
+        let mask = (0..8)
+            .min_by_key(|&mask| {
+                let mut candidate = code.clone();
+                candidate.apply_mask(mask);
+                candidate.draw_format(mask);
+                candidate.penalty()
+            })
+            .unwrap_or(0);
+        code.apply_mask(mask);
+        code.draw_format(mask);
+        Some(code)
+    }
+
+    /// The code as text lines of half blocks ('▀', '▄', '█', ' '), quiet zone
+    /// included; meant to be drawn dark on light
+    pub fn lines(&self) -> Vec<String> {
+        let side = self.size + 2 * QUIET_ZONE;
+        let dark = |row: usize, column: usize| {
+            row >= QUIET_ZONE
+                && column >= QUIET_ZONE
+                && row < QUIET_ZONE + self.size
+                && column < QUIET_ZONE + self.size
+                && self.modules[row - QUIET_ZONE][column - QUIET_ZONE]
+        };
+
+        (0..side)
+            .step_by(2)
+            .map(|row| {
+                (0..side)
+                    .map(|column| match (dark(row, column), dark(row + 1, column)) {
+                        (true, true) => '█',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (false, false) => ' ',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// An all-light code of a version
+    fn blank(version: usize) -> Self {
+        let size = version * 4 + 17;
+        Self {
+            size,
+            modules: vec![vec![false; size]; size],
+            function: vec![vec![false; size]; size],
+        }
+    }
+
+    /// Sets a function module
+    fn set_function(&mut self, column: usize, row: usize, dark: bool) {
+        self.modules[row][column] = dark;
+        self.function[row][column] = true;
+    }
+
+    /// Draws the finders, timing and alignment patterns, and reserves the
+    /// format and version areas
+    fn draw_function_patterns(&mut self, version: usize) {
+        let size = self.size;
+
+        // Timing patterns
+        for i in 0..size {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+
+        // Finders (with their light separators) in three corners
+        for (cx, cy) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+            for dy in -4i32..=4 {
+                for dx in -4i32..=4 {
+                    let (x, y) = (cx as i32 + dx, cy as i32 + dy);
+                    if x < 0 || y < 0 || x >= size as i32 || y >= size as i32 {
+                        continue;
+                    }
+                    let distance = dx.abs().max(dy.abs());
+                    self.set_function(x as usize, y as usize, distance != 2 && distance != 4);
+                }
+            }
+        }
+
+        // Alignment patterns, except where the finders are
+        let centers = ALIGNMENT[version - 1];
+        let last = centers.len().saturating_sub(1);
+        for (i, &cy) in centers.iter().enumerate() {
+            for (j, &cx) in centers.iter().enumerate() {
+                if [(0, 0), (0, last), (last, 0)].contains(&(i, j)) {
+                    continue;
+                }
+                for dy in -2i32..=2 {
+                    for dx in -2i32..=2 {
+                        let dark = dx.abs().max(dy.abs()) != 1;
+                        self.set_function((cx as i32 + dx) as usize, (cy as i32 + dy) as usize, dark);
+                    }
+                }
+            }
+        }
+
+        // Reserve the format areas (drawn for real once the mask is known)
+        self.draw_format(0);
+
+        // Version information (versions 7 and up)
+        if version >= 7 {
+            let bits = (version as u32) << 12 | bch_remainder(version as u32, 0x1F25, 12);
+            for i in 0..18 {
+                let dark = (bits >> i) & 1 != 0;
+                let (a, b) = (size - 11 + i % 3, i / 3);
+                self.set_function(a, b, dark);
+                self.set_function(b, a, dark);
+            }
+        }
+    }
+
+    /// Draws both copies of the format information (level and mask)
+    fn draw_format(&mut self, mask: u32) {
+        let size = self.size;
+        let data = LEVEL_M << 3 | mask;
+        let bits = (data << 10 | bch_remainder(data, 0x537, 10)) ^ 0x5412;
+        let bit = |i: usize| (bits >> i) & 1 != 0;
+
+        // Around the top left finder
+        for i in 0..=5 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+
+        // Split between the two other finders
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        // Always dark
+        self.set_function(8, size - 8, true);
+    }
+
+    /// Places the codewords in the zigzag order of the standard
+    fn draw_data(&mut self, data: &[u8]) {
+        let size = self.size;
+        let mut i = 0;
+        let mut right = size as i32 - 1;
+        while right >= 1 {
+            // The vertical timing pattern is skipped over
+            if right == 6 {
+                right = 5;
+            }
+            let upward = (right + 1) & 2 == 0;
+            for vertical in 0..size {
+                let row = if upward { size - 1 - vertical } else { vertical };
+                for j in 0..2 {
+                    let column = (right - j) as usize;
+                    if self.function[row][column] || i >= data.len() * 8 {
+                        continue;
+                    }
+                    self.modules[row][column] = (data[i / 8] >> (7 - i % 8)) & 1 != 0;
+                    i += 1;
+                }
+            }
+            right -= 2;
+        }
+    }
+
+    /// Flips the data modules where a mask pattern says so
+    fn apply_mask(&mut self, mask: u32) {
+        for row in 0..self.size {
+            for column in 0..self.size {
+                if self.function[row][column] {
+                    continue;
+                }
+                let (x, y) = (column, row);
+                let flip = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                self.modules[row][column] ^= flip;
+            }
+        }
+    }
+
+    /// How hard the code is to scan (the standard's four penalty rules)
+    fn penalty(&self) -> usize {
+        let size = self.size;
+        let at = |row: usize, column: usize| self.modules[row][column];
+        let mut penalty = 0;
+
+        // Rows and columns, read the same way
+        let lines: Vec<Vec<bool>> = (0..size)
+            .map(|r| (0..size).map(|c| at(r, c)).collect())
+            .chain((0..size).map(|c| (0..size).map(|r| at(r, c)).collect()))
+            .collect();
+        let finder_like = [true, false, true, true, true, false, true];
+        for line in &lines {
+            // Runs of 5 or more modules of one color
+            let mut run = 1;
+            for i in 1..=size {
+                if i < size && line[i] == line[i - 1] {
+                    run += 1;
+                    continue;
+                }
+                if run >= 5 {
+                    penalty += run - 2;
+                }
+                run = 1;
+            }
+
+            // Patterns looking like a finder, with 4 light modules on a side
+            for start in 0..=size - finder_like.len() {
+                if line[start..start + 7] != finder_like {
+                    continue;
+                }
+                let light_before = start >= 4 && line[start - 4..start].iter().all(|&m| !m);
+                let light_after = start + 11 <= size && line[start + 7..start + 11].iter().all(|&m| !m);
+                if light_before || light_after {
+                    penalty += 40;
+                }
+            }
+        }
+
+        // 2 x 2 blocks of one color
+        for row in 0..size - 1 {
+            for column in 0..size - 1 {
+                let color = at(row, column);
+                if at(row, column + 1) == color && at(row + 1, column) == color && at(row + 1, column + 1) == color {
+                    penalty += 3;
+                }
+            }
+        }
+
+        // Far from half dark
+        let dark = self.modules.iter().flatten().filter(|&&m| m).count();
+        let percent = dark * 100 / (size * size);
+        penalty + percent.abs_diff(50) / 5 * 10
+    }
+}
+
+/// Data codewords a version holds (level M)
+fn data_capacity(version: usize) -> usize {
+    let (_, group1, per_block, group2) = EC_BLOCKS[version - 1];
+    group1 * per_block + group2 * (per_block + 1)
+}
+
+/// Bits needed for `length` bytes in byte mode
+fn data_bits(version: usize, length: usize) -> usize {
+    4 + count_bits(version) + length * 8
+}
+
+/// Size of the byte count field
+fn count_bits(version: usize) -> usize {
+    if version < 10 {
+        8
+    } else {
+        16
+    }
+}
+
+/// The data of a version, padded, split into blocks with their error
+/// correction, and interleaved
+fn codewords(version: usize, data: &[u8]) -> Vec<u8> {
+    let capacity = data_capacity(version);
+
+    // Mode (byte), count, the bytes, then a terminator and padding
+    let mut bits: Vec<bool> = Vec::new();
+    let mut push = |value: usize, count: usize| {
+        for i in (0..count).rev() {
+            bits.push((value >> i) & 1 != 0);
+        }
+    };
+    push(0b0100, 4);
+    push(data.len(), count_bits(version));
+    for &byte in data {
+        push(byte as usize, 8);
+    }
+    let terminator = (capacity * 8 - bits.len()).min(4);
+    bits.extend(std::iter::repeat_n(false, terminator));
+    while !bits.len().is_multiple_of(8) {
+        bits.push(false);
+    }
+    let mut bytes: Vec<u8> = bits
+        .chunks(8)
+        .map(|byte| byte.iter().fold(0u8, |acc, &bit| acc << 1 | bit as u8))
+        .collect();
+    for pad in [0xEC, 0x11].into_iter().cycle() {
+        if bytes.len() >= capacity {
+            break;
+        }
+        bytes.push(pad);
+    }
+
+    // Blocks, each with its error correction
+    let (ec_length, group1, per_block, group2) = EC_BLOCKS[version - 1];
+    let divisor = rs_divisor(ec_length);
+    let mut blocks = Vec::new();
+    let mut rest = bytes.as_slice();
+    for block in 0..group1 + group2 {
+        let length = if block < group1 { per_block } else { per_block + 1 };
+        let (data, remaining) = rest.split_at(length);
+        rest = remaining;
+        blocks.push((data.to_vec(), rs_remainder(data, &divisor)));
+    }
+
+    // Interleaved: the first codeword of every block, then the second...
+    let mut result = Vec::new();
+    for i in 0..=per_block {
+        result.extend(blocks.iter().filter_map(|(data, _)| data.get(i)));
+    }
+    for i in 0..ec_length {
+        result.extend(blocks.iter().map(|(_, ec)| ec[i]));
+    }
+    result
+}
+
+/// Remainder of a BCH code (format and version information)
+fn bch_remainder(data: u32, generator: u32, degree: u32) -> u32 {
+    let mut remainder = data << degree;
+    for bit in (degree..degree + 6).rev() {
+        if remainder & (1 << bit) != 0 {
+            remainder ^= generator << (bit - degree);
+        }
+    }
+    remainder
+}
+
+/// Reed-Solomon generator polynomial of a degree (leading 1 left out)
+fn rs_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0u8; degree];
+    result[degree - 1] = 1;
+    let mut root = 1u8;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_multiply(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_multiply(root, 0x02);
+    }
+    result
+}
+
+/// Error correction codewords of a block
+fn rs_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0u8; divisor.len()];
+    for &byte in data {
+        let factor = byte ^ result.remove(0);
+        result.push(0);
+        for (r, &d) in result.iter_mut().zip(divisor) {
+            *r ^= gf_multiply(d, factor);
+        }
+    }
+    result
+}
+
+/// Multiplication in GF(256) modulo x^8 + x^4 + x^3 + x^2 + 1
+fn gf_multiply(x: u8, y: u8) -> u8 {
+    let mut z: u32 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x11D);
+        z ^= ((y as u32 >> i) & 1) * x as u32;
+    }
+    z as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The text of the version 8 code: 130 bytes, two groups of blocks
+    const LONG_TEXT: &str = "http://192.168.1.20:8574/peer/books/funkhunt-0123456789-funkhunt-0123456789-funkhunt-0123456789-funkhunt-0123456789-funkhunt-01234";
+
+    /// "HELLO", version 1, mask 2 (made by another encoder)
+    const HELLO_V1: &[&str] = &[
+    "#######....#..#######",
+    "#.....#...#.#.#.....#",
+    "#.###.#.##....#.###.#",
+    "#.###.#.#.#.#.#.###.#",
+    "#.###.#.##..#.#.###.#",
+    "#.....#.####..#.....#",
+    "#######.#.#.#.#######",
+    "........##...........",
+    "#.#####...##..#####..",
+    ".##.##.#.######..##..",
+    "..#####.#...#.##.###.",
+    ".##.#....######..##..",
+    ".#.######...#..#..#.#",
+    "........#.#.#..#.#...",
+    "#######..###.#..#.##.",
+    "#.....#.#.#....#####.",
+    "#.###.#.##.#.#..#.##.",
+    "#.###.#.##.#####.#...",
+    "#.###.#.##..#.##..#..",
+    "#.....#..######.###..",
+    "#######.##..#...#.##.",
+    ];
+
+    /// "http://192.168.1.20:8574/share/3f9a", version 3, mask 3 (made by another encoder)
+    const URL_V3: &[&str] = &[
+    "#######.#.###..##..#..#######",
+    "#.....#.###...##..#...#.....#",
+    "#.###.#..#######..###.#.###.#",
+    "#.###.#.##.#.....##.#.#.###.#",
+    "#.###.#...#..#...####.#.###.#",
+    "#.....#..##...#...##..#.....#",
+    "#######.#.#.#.#.#.#.#.#######",
+    "........#..#.##..#..#........",
+    "#.##.###.#..###.##..#.#..#.##",
+    "#####...####....####..#.#...#",
+    ".##..##.####..#.#.#.##....##.",
+    "....##...##..##.#.#......#..#",
+    "#..#..#...#.#...##.###.#.##..",
+    "#.#..#..#...##.#.#######...##",
+    "...#####.#.##.#.##.#....#####",
+    "####....####.#..#....#.#...#.",
+    "#.....##.##.......#....##..#.",
+    ".#####...#..##.#.#........##.",
+    "#.#.####.####..##.#...#..##..",
+    "..#.##..#...#..#.#.##.##..#..",
+    ".#....#.#.#.##...##########..",
+    "........##...##...###...#####",
+    "#######.####.#..##.##.#.##.#.",
+    "#.....#.#..##..#..###...##.#.",
+    "#.###.#..####.#..#..########.",
+    "#.###.#.#.#...##.##.....#.#.#",
+    "#.###.#.##..###..#..##......#",
+    "#.....#..#.##.###.#.##.##..#.",
+    "#######.###.#.....#.##.###.#.",
+    ];
+
+    /// LONG_TEXT, version 8, mask 2 (made by another encoder)
+    const LONG_V8: &[&str] = &[
+    "#######......#.###.#...#......##.#..##..#.#######",
+    "#.....#....##.#..#...###.#.#.##..#.##.###.#.....#",
+    "#.###.#.#######.#####..###..######.#...##.#.###.#",
+    "#.###.#.#..#..##...#.#..##...###....#..#..#.###.#",
+    "#.###.#.#.#.#....##..########.#.##.###....#.###.#",
+    "#.....#.#...####.###.##...#.##.#.####.#...#.....#",
+    "#######.#.#.#.#.#.#.#.#.#.#.#.#.#.#.#.#.#.#######",
+    "........##...###.#....#...#.#...####...#.........",
+    "#.#####.....#..##..##.######.#.#.#..#.###.#####..",
+    "###.##.##...##...##..#.###...##.##.#...#.####....",
+    "#.#.#.#..#.##..#.#.####.###.#..##.######....#####",
+    "..####..###.##...###.#.#...######..#.....#.##....",
+    ".##..###.#..####.#....#.#....##..##.#...##.#..##.",
+    "##..#...#.##.##..#.##.###....##..#.....#..#...##.",
+    "##..###.##.##.####..###.###.....#.##.##.....#..##",
+    ".##.##..#.###..##..##.##.#.#.#....##.##.#####...#",
+    "####..#####..##.#..###..#...#..####.##..#.##.##..",
+    "..#.##..#..###..#.....##...###..#.##...#..#.#....",
+    ".##...###.....#.#...#.#.#.#.#.####..###....###..#",
+    "#.#..#..####.#.....#.....######.#.##.###.##.#..##",
+    "###.###.###.#..#.#.######.#..#.#.##.#.#.###...#..",
+    ".##.#..##.#.##..#........##...##.#..#..#.##.###..",
+    "##.#######.#.#.####..######..#.##.#####.#####.###",
+    "##.##...##..###.#...#.#...###...####...##...#..##",
+    "#.###.#.##.#.....#..###.#.##..##....#####.#.####.",
+    "###.#...#..#.##....#.##...##.###.#......#...#....",
+    "..########..##.#..###.#####..#.#.######.#####..##",
+    "##.#...#.####..#.#.####.#..###.##.......#...#..##",
+    "#.#.#.###.#.###.#.##....#.........#.##.#....#####",
+    "#.####....######..#..###..##.##.##..#...#.##.....",
+    "......#.#.###.#..#.#.......#....#.#..##..##.#####",
+    "...#.#.#..###....#...###..#...##.#.#.....#.##....",
+    ".#.######....#.##.####.#...#.##.....#..#.#..#.#.#",
+    ".#.....#.######.###..##.##....##.#.....###.#...#.",
+    "#..#..##.##...###..#.#..#..#.##.....#####.####..#",
+    "##..##.#.#.#..#.#..#..#.###.###.##.#.....#..#...#",
+    "#.##..#.#...#.##.####..#.##...#..##.##.#.#..#.#..",
+    ".##.##..#..##.##.....##.##.#..##.#.#...#.###.##..",
+    ".#...##.#.#.#.###.###.#....##...#.#.#####.##.#..#",
+    ".###....###....#...#.##.###.#####.##.#...#.#...##",
+    "###...###..####.....#.#####..###....#..######.#..",
+    "........#...#####.....#...#####.#..##...#...#.##.",
+    "#######..#######...####.#.#.#..#.########.#.#####",
+    "#.....#.###..##......##...#.#..#####...##...#....",
+    "#.###.#.##.#.##.###..######..#...#..#.###########",
+    "#.###.#.#.###..#.#.##.#.....###..#.....###.##...#",
+    "#.###.#.#.#.##.###..#..###..#.....#####..........",
+    "#.....#..#.####.######.#...#.#....##.....##.....#",
+    "#######.#..##..##..###.#.#..#..####.##...#...####",
+    ];
+
+    /// A code drawn with a given mask, as rows of '#' (dark) and '.'
+    fn drawn(text: &str, version: usize, mask: u32) -> Vec<String> {
+        let mut code = QrCode::blank(version);
+        code.draw_function_patterns(version);
+        code.draw_data(&codewords(version, text.as_bytes()));
+        code.apply_mask(mask);
+        code.draw_format(mask);
+        rows(&code)
+    }
+
+    /// The modules of a code as rows of '#' (dark) and '.'
+    fn rows(code: &QrCode) -> Vec<String> {
+        let row = |modules: &Vec<bool>| modules.iter().map(|&dark| if dark { '#' } else { '.' }).collect();
+        code.modules.iter().map(row).collect()
+    }
+
+    #[test]
+    fn matches_reference_codes() {
+        assert_eq!(drawn("HELLO", 1, 2), HELLO_V1);
+        assert_eq!(drawn("http://192.168.1.20:8574/share/3f9a", 3, 3), URL_V3);
+        assert_eq!(drawn(LONG_TEXT, 8, 2), LONG_V8);
+    }
+
+    #[test]
+    fn encodes_in_smallest_version() {
+        assert_eq!(QrCode::encode("HELLO").map(|code| code.size), Some(21));
+        assert_eq!(QrCode::encode(LONG_TEXT).map(|code| code.size), Some(49));
+        assert_eq!(QrCode::encode(&"x".repeat(213)).map(|code| code.size), Some(57));
+        assert!(QrCode::encode(&"x".repeat(214)).is_none());
+    }
+
+    #[test]
+    fn reed_solomon_of_known_data() {
+        // Version 1-M "HELLO WORLD" (alphanumeric), from the standard's worked example
+        let data = [32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17];
+        let ec = rs_remainder(&data, &rs_divisor(10));
+        assert_eq!(ec, [196, 35, 39, 119, 235, 215, 231, 226, 93, 23]);
+    }
+
+    #[test]
+    fn half_blocks_hold_two_rows() {
+        let code = QrCode::encode("HELLO").unwrap();
+        let lines = code.lines();
+        // 21 modules and 4 light ones on each side, two rows a line
+        assert_eq!(lines.len(), 15);
+        assert!(lines.iter().all(|line| line.chars().count() == 29));
+        // The top of the finder pattern: a dark row over its hollow one
+        assert!(lines[2].starts_with("    █▀▀▀▀▀█"));
+    }
+}
@@ -0,0 +1,402 @@
+// src/query.rs
+// Index of the library for the book list's filters and orders, so that
+// filtering a big library (as a filter is typed) doesn't fold every title
+// and ask the disk about every file again at each key
+//
+// What filters and orders compare is worked out once per book and kept:
+// its folded title, file name, authors and tags (see fold.rs), its file's
+// size and modification time, and its sort keys (see sort.rs). Authors,
+// tags, words, sizes and times are indexed too - the books of each author,
+// tag and word of a title, file name or author, the books ordered by size
+// and by time - so the `author:`, `tag:`, `size:` and `modified:` terms of a
+// filter, and its plain words, find their books without going through the
+// others, and only those are checked against the rest of it.
+//
+// The index follows the library: books edited in place are `touch`ed by
+// whoever edits them, and `update` works out again only those (if their
+// indexed fields changed) and the books that are new or moved; it rebuilds
+// the lookups only when something did. Sizes and times are those of when a
+// book was indexed: a folder scanned again is forgotten (`forget`), so its
+// books are looked at anew.
+
+use crate::book::Book;
+use crate::filter::{Filter, Lookup};
+use crate::fold::fold;
+use crate::sort::{SortKeys, SortOrder};
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// What the filters and orders compare of a book, worked out once
+#[derive(Debug, Clone)]
+pub struct Facts {
+    /// Hash of the fields these come from, to tell when they changed
+    source: u64,
+
+    /// The book's file
+    path: PathBuf,
+
+    /// Folded display title
+    pub title: String,
+
+    /// Folded file name
+    pub name: String,
+
+    /// Folded authors
+    pub authors: Vec<String>,
+
+    /// Folded tags
+    pub tags: Vec<String>,
+
+    /// File size in bytes (None on a server, or if it can't be read)
+    pub size: Option<u64>,
+
+    /// File modification time, as a unix time (None as for the size)
+    pub modified: Option<u64>,
+
+    /// What the orders compare
+    pub keys: SortKeys,
+}
+
+/// The index of the books of a library
+#[derive(Debug, Default)]
+pub struct BookIndex {
+    /// An entry per book, in the library's order
+    entries: Vec<Facts>,
+
+    /// Folded author -> the books (indices) by them
+    authors: BTreeMap<String, Vec<usize>>,
+
+    /// Folded tag -> the books with it
+    tags: BTreeMap<String, Vec<usize>>,
+
+    /// Folded word of a title, file name or author -> the books with it
+    words: BTreeMap<String, Vec<usize>>,
+
+    /// (size, book), smallest first
+    sizes: Vec<(u64, usize)>,
+
+    /// (modification time, book), oldest first
+    modified: Vec<(u64, usize)>,
+
+    /// Books edited since the last `update`, to look at again
+    touched: HashSet<PathBuf>,
+
+    /// Every book is to be looked at again (e.g. authors were merged)
+    all_touched: bool,
+}
+
+impl Facts {
+    /// Works out the facts of a book (asking the disk about its file)
+    fn of(book: &Book, source: u64) -> Self {
+        let metadata = if crate::remote::is_remote(&book.path) {
+            None
+        } else {
+            std::fs::metadata(&book.path).ok()
+        };
+        Self {
+            source,
+            path: book.path.clone(),
+            title: fold(book.display_title()),
+            name: fold(&book.name),
+            authors: book.authors().iter().map(|author| fold(author)).collect(),
+            tags: book.user.tags.iter().map(|tag| fold(tag)).collect(),
+            size: metadata.as_ref().map(|metadata| metadata.len()),
+            modified: metadata
+                .and_then(|metadata| metadata.modified().ok())
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|since| since.as_secs()),
+            keys: SortKeys::of(book),
+        }
+    }
+}
+
+/// Hash of the fields of a book the index keeps something of
+fn source_of(book: &Book) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    book.path.hash(&mut hasher);
+    book.name.hash(&mut hasher);
+    book.display_title().hash(&mut hasher);
+    book.authors().hash(&mut hasher);
+    book.user.tags.hash(&mut hasher);
+    book.meta.series.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl BookIndex {
+    /// An empty index (the first `update` fills it)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Notes that a book was edited in place: the next `update` looks at it
+    /// again (books added, removed or moved are found by their paths)
+    pub fn touch(&mut self, path: &Path) {
+        if !self.all_touched {
+            self.touched.insert(path.to_path_buf());
+        }
+    }
+
+    /// Notes that any book may have been edited in place
+    pub fn touch_all(&mut self) {
+        self.all_touched = true;
+        self.touched.clear();
+    }
+
+    /// Brings the index up to date with the books: the books touched whose
+    /// indexed fields changed, and those that are new, are worked out
+    /// again; the others keep their entries (wherever they moved to in the
+    /// list)
+    pub fn update(&mut self, books: &[Book]) {
+        let in_place = books.len() == self.entries.len() && books.iter().zip(&self.entries).all(|(book, entry)| book.path == entry.path);
+        if in_place && self.touched.is_empty() && !self.all_touched {
+            return;
+        }
+
+        let (touched, all_touched) = (std::mem::take(&mut self.touched), std::mem::replace(&mut self.all_touched, false));
+        let mut worked_out = 0;
+        // The source of a book to work out again: touched, and changed
+        let stale = |entry: &Facts, book: &Book| {
+            if !all_touched && !touched.contains(&book.path) {
+                return None;
+            }
+            Some(source_of(book)).filter(|&source| source != entry.source)
+        };
+        if in_place {
+            for (entry, book) in self.entries.iter_mut().zip(books) {
+                if let Some(source) = stale(entry, book) {
+                    *entry = Facts::of(book, source);
+                    worked_out += 1;
+                }
+            }
+        } else {
+            // Entries found by their books' paths, wherever these moved to
+            let mut kept: HashMap<PathBuf, Facts> = self.entries.drain(..).map(|entry| (entry.path.clone(), entry)).collect();
+            self.entries = books
+                .iter()
+                .map(|book| {
+                    let source = match kept.remove(&book.path) {
+                        Some(entry) => match stale(&entry, book) {
+                            Some(source) => source,
+                            None => return entry,
+                        },
+                        None => source_of(book),
+                    };
+                    worked_out += 1;
+                    Facts::of(book, source)
+                })
+                .collect();
+        }
+        if in_place && worked_out == 0 {
+            return;
+        }
+        self.build_lookups();
+        tracing::debug!(books = books.len(), worked_out, "book index updated");
+    }
+
+    /// Forgets every entry, so the next `update` works them all out again
+    /// (the folding changed)
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Forgets the entries of the books in a folder, so the next `update`
+    /// works them out again (the folder was scanned: files may have changed)
+    pub fn forget(&mut self, root: &Path) {
+        for entry in self.entries.iter_mut().filter(|entry| entry.path.starts_with(root)) {
+            entry.source = 0;
+            if !self.all_touched {
+                self.touched.insert(entry.path.clone());
+            }
+        }
+    }
+
+    /// Rebuilds the author, tag, word, size and time lookups from the entries
+    fn build_lookups(&mut self) {
+        self.authors.clear();
+        self.tags.clear();
+        self.words.clear();
+        self.sizes.clear();
+        self.modified.clear();
+        for (index, entry) in self.entries.iter().enumerate() {
+            for author in &entry.authors {
+                self.authors.entry(author.clone()).or_default().push(index);
+            }
+            for tag in &entry.tags {
+                self.tags.entry(tag.clone()).or_default().push(index);
+            }
+            let texts = std::iter::once(&entry.title).chain(std::iter::once(&entry.name)).chain(&entry.authors);
+            for word in texts.flat_map(|text| words(text)) {
+                let books = self.words.entry(word.to_string()).or_default();
+                // A word repeated in a book's texts lists it once
+                if books.last() != Some(&index) {
+                    books.push(index);
+                }
+            }
+            if let Some(size) = entry.size {
+                self.sizes.push((size, index));
+            }
+            if let Some(modified) = entry.modified {
+                self.modified.push((modified, index));
+            }
+        }
+        self.sizes.sort_unstable();
+        self.modified.sort_unstable();
+    }
+
+    /// The books (indices, in library order) that match a filter
+    ///
+    /// The books of its lookups are found in the index and only those are
+    /// checked; a filter without any (e.g. only negated terms) is checked
+    /// against every book (with its entry). Falls back to checking the books as they are when the
+    /// index isn't up to date with them.
+    pub fn matching(&self, filter: &Filter, books: &[Book]) -> Vec<usize> {
+        if self.entries.len() != books.len() {
+            return (0..books.len()).filter(|&index| filter.matches(&books[index])).collect();
+        }
+
+        let mut candidates: Option<Vec<usize>> = None;
+        for lookup in filter.lookups() {
+            let mut found = self.lookup(lookup);
+            if let Some(previous) = candidates {
+                found.retain(|index| previous.binary_search(index).is_ok());
+            }
+            candidates = Some(found);
+        }
+
+        let check = |&index: &usize| filter.matches_indexed(&books[index], &self.entries[index]);
+        match candidates {
+            Some(candidates) => candidates.into_iter().filter(check).collect(),
+            None => (0..books.len()).filter(check).collect(),
+        }
+    }
+
+    /// Whether one book (`books[index]`) matches a filter, with its entry
+    /// when it has one
+    pub fn matches(&self, filter: &Filter, index: usize, book: &Book) -> bool {
+        match self.entries.get(index) {
+            Some(entry) => filter.matches_indexed(book, entry),
+            None => filter.matches(book),
+        }
+    }
+
+    /// Compares two books (indices) in an order, with their sort keys
+    pub fn compare(&self, sort: SortOrder, a: usize, b: usize, books: &[Book]) -> Ordering {
+        match (self.entries.get(a), self.entries.get(b)) {
+            (Some(keys_a), Some(keys_b)) if self.entries.len() == books.len() => {
+                sort.compare_keyed(&books[a], &keys_a.keys, &books[b], &keys_b.keys)
+            }
+            _ => sort.compare(&books[a], &books[b]),
+        }
+    }
+
+    /// The books of a lookup, sorted, without repeats
+    fn lookup(&self, lookup: Lookup) -> Vec<usize> {
+        let mut found: Vec<usize> = match lookup {
+            // Authors and tags are far fewer than books: each is checked
+            Lookup::Author(text) => self
+                .authors
+                .iter()
+                .filter(|(author, _)| author.contains(text))
+                .flat_map(|(_, books)| books.iter().copied())
+                .collect(),
+            Lookup::Tag(text) => self
+                .tags
+                .iter()
+                .filter(|(tag, _)| tag.contains(text))
+                .flat_map(|(_, books)| books.iter().copied())
+                .collect(),
+            // So are the different words of the library
+            Lookup::Word(text) => self
+                .words
+                .iter()
+                .filter(|(word, _)| word.contains(text))
+                .flat_map(|(_, books)| books.iter().copied())
+                .collect(),
+            Lookup::Size(from, to) => in_range(&self.sizes, from, to),
+            Lookup::Modified(from, to) => in_range(&self.modified, from, to),
+        };
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+}
+
+/// The words of a folded text: its runs of letters and digits
+///
+/// A text containing another one has a word containing each of the other's
+/// words, which is how the index finds the books of a plain filter word.
+pub fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty())
+}
+
+/// The books whose value is in a range, in a list sorted by value
+fn in_range(sorted: &[(u64, usize)], from: Bound<u64>, to: Bound<u64>) -> Vec<usize> {
+    let start = match from {
+        Bound::Included(value) => sorted.partition_point(|&(x, _)| x < value),
+        Bound::Excluded(value) => sorted.partition_point(|&(x, _)| x <= value),
+        Bound::Unbounded => 0,
+    };
+    let end = match to {
+        Bound::Included(value) => sorted.partition_point(|&(x, _)| x <= value),
+        Bound::Excluded(value) => sorted.partition_point(|&(x, _)| x < value),
+        Bound::Unbounded => sorted.len(),
+    };
+    sorted.get(start..end.max(start)).unwrap_or_default().iter().map(|&(_, index)| index).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A book with a title (its file doesn't exist)
+    fn book(path: &str, title: &str) -> Book {
+        let mut book = Book::new(format!("{}.epub", path), PathBuf::from(format!("/nowhere/{}.epub", path)));
+        book.meta.title = Some(title.to_string());
+        book
+    }
+
+    /// The titles of the books a filter finds with the index
+    fn found(index: &BookIndex, filter: &str, books: &[Book]) -> Vec<String> {
+        let filter = Filter::parse(filter).unwrap_or_default();
+        index.matching(&filter, books).into_iter().map(|i| books[i].display_title().to_string()).collect()
+    }
+
+    #[test]
+    fn plain_words_are_looked_up() {
+        let books = vec![book("a", "The Lord of the Rings"), book("b", "Dune"), book("c", "Landlord")];
+        let mut index = BookIndex::new();
+        index.update(&books);
+        assert_eq!(found(&index, "lord", &books), ["The Lord of the Rings", "Landlord"]);
+        assert_eq!(found(&index, "\"lord of\"", &books), ["The Lord of the Rings"]);
+        assert_eq!(found(&index, "dun", &books), ["Dune"]);
+        assert!(found(&index, "dunes", &books).is_empty());
+    }
+
+    #[test]
+    fn only_touched_books_are_worked_out_again() {
+        let mut books = vec![book("a", "Dune"), book("b", "Emma")];
+        let mut index = BookIndex::new();
+        index.update(&books);
+
+        // An edit the index isn't told about isn't looked for
+        books[0].meta.title = Some("Persuasion".to_string());
+        index.update(&books);
+        assert_eq!(found(&index, "dune", &books), ["Persuasion"]);
+
+        index.touch(&books[0].path);
+        index.update(&books);
+        assert_eq!(found(&index, "persuasion", &books), ["Persuasion"]);
+        assert!(found(&index, "dune", &books).is_empty());
+
+        // Books added or moved are found by their paths
+        books.insert(0, book("c", "Dune"));
+        index.update(&books);
+        assert_eq!(found(&index, "dune", &books), ["Dune"]);
+        assert_eq!(found(&index, "emma", &books), ["Emma"]);
+    }
+}
@@ -0,0 +1,110 @@
+// src/quotes.rs
+// Quotes - passages captured from any book into one scratchpad for the
+// whole app (every library), each with where it comes from: y in the
+// reader selects one as h does, Y picks sentences of the selected book's
+// description, and y in the book list shows them all
+//
+// They're kept in quotes.json in the data directory, oldest first.
+
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::PathBuf;
+
+/// File of the quotes, in the data directory
+const QUOTES_FILE: &str = "quotes.json";
+
+/// A passage captured from a book
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Quote {
+    /// The passage (paragraphs separated by newlines)
+    pub text: String,
+
+    /// Title of the book it comes from
+    pub title: String,
+
+    #[serde(default)]
+    pub authors: Vec<String>,
+
+    /// Where in the book: the chapter's title, or "Description" (empty = unknown)
+    #[serde(default)]
+    pub place: String,
+
+    /// When it was captured (seconds since 1970)
+    #[serde(default)]
+    pub created: u64,
+}
+
+impl Quote {
+    /// Where the quote comes from, e.g. "Dune, Frank Herbert - Book One: Dune"
+    pub fn attribution(&self) -> String {
+        let mut attribution = self.title.clone();
+        if !self.authors.is_empty() {
+            attribution.push_str(", ");
+            attribution.push_str(&self.authors.join(" & "));
+        }
+        if !self.place.is_empty() {
+            attribution.push_str(" - ");
+            attribution.push_str(&self.place);
+        }
+        attribution
+    }
+}
+
+/// The quotes captured so far (none if the file is missing or unreadable)
+pub fn load() -> Vec<Quote> {
+    std::fs::read_to_string(quotes_path())
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+/// Writes the quotes, replacing the file's
+pub fn save(quotes: &[Quote]) -> io::Result<()> {
+    let path = quotes_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let json = serde_json::to_string_pretty(quotes).map_err(io::Error::other)?;
+    std::fs::write(path, json)
+}
+
+/// Adds a quote to the file
+///
+/// # Returns
+/// How many quotes there are now
+pub fn add(quote: Quote) -> io::Result<usize> {
+    let mut quotes = load();
+    quotes.push(quote);
+    save(&quotes)?;
+    Ok(quotes.len())
+}
+
+/// The sentences of a text, for picking a passage of it: split after '.',
+/// '!' or '?' (closing quotes and brackets included) followed by a space,
+/// and at line breaks
+pub fn sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    for paragraph in text.lines() {
+        let mut sentence = String::new();
+        let mut chars = paragraph.chars().peekable();
+        while let Some(c) = chars.next() {
+            sentence.push(c);
+            if matches!(c, '.' | '!' | '?' | '…') {
+                while let Some(&close) = chars.peek().filter(|&&next| "\"'”’»)]".contains(next)) {
+                    sentence.push(close);
+                    chars.next();
+                }
+                if chars.peek().is_some_and(|next| next.is_whitespace()) {
+                    sentences.push(std::mem::take(&mut sentence).trim().to_string());
+                }
+            }
+        }
+        sentences.push(sentence.trim().to_string());
+    }
+    sentences.retain(|sentence| !sentence.is_empty());
+    sentences
+}
+
+fn quotes_path() -> PathBuf {
+    crate::paths::data_dir().join(QUOTES_FILE)
+}
@@ -0,0 +1,120 @@
+// src/readability.rs
+// How hard a book reads, measured on the sample of its text read for its
+// language (see language.rs) - for choosing books for language learners
+//
+// - Average sentence length, in words
+// - Flesch-Kincaid grade level: the school grade (US) able to read the text
+//   (the English formula for every language - higher for languages of
+//   longer words, so better compared between books of one language)
+// - Flesch reading ease, from 100 (very easy) down to 0 (very difficult),
+//   with the formula adapted to the language when there is one: Fernández
+//   Huerta (Spanish), Kandel-Moles (French), Amstad (German), Flesch-Vacca
+//   (Italian), Douma (Dutch); the English one for the others
+// Syllables are counted as groups of vowels, which is near enough for the
+// languages written in the Latin script; texts in other scripts aren't
+// measured.
+
+use serde::{Deserialize, Serialize};
+
+/// Fewest words of text measured
+const MIN_WORDS: usize = 100;
+
+/// Vowels syllables are counted by
+const VOWELS: &str = "aeiouyàáâãäåæèéêëìíîïòóôõöøœùúûüý";
+
+/// How hard a book reads
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Readability {
+    /// Average sentence length, in words
+    pub words_per_sentence: f32,
+
+    /// Flesch-Kincaid grade level (US school grade)
+    pub grade: f32,
+
+    /// Flesch reading ease (100 = very easy, 0 = very difficult)
+    pub ease: f32,
+}
+
+impl Readability {
+    /// Measures a text
+    ///
+    /// # Arguments
+    /// * `text` - The text (paragraphs may be joined by spaces)
+    /// * `language` - Its language, e.g. "es" or "en-GB", for the reading ease formula
+    ///
+    /// # Returns
+    /// The measures, or None if the text is too short or not mostly in the Latin script
+    #[cfg_attr(feature = "profiling", tracing::instrument(level = "trace", name = "readability", skip_all))]
+    pub fn of(text: &str, language: Option<&str>) -> Option<Self> {
+        let words: Vec<&str> = text
+            .split(|c: char| !c.is_alphabetic() && c != '\'' && c != '’')
+            .map(|word| word.trim_matches(|c| c == '\'' || c == '’'))
+            .filter(|word| !word.is_empty())
+            .collect();
+        if words.len() < MIN_WORDS {
+            return None;
+        }
+        let language = language.map(crate::language::primary);
+        let english = matches!(language.as_deref(), None | Some("en"));
+        let syllables: usize = words.iter().map(|word| syllables(word, english)).sum();
+        let latin = words.iter().filter(|word| word.chars().any(is_vowel)).count();
+        if latin * 2 < words.len() {
+            return None;
+        }
+
+        let sentences = crate::quotes::sentences(text).len().max(1);
+        let words_per_sentence = words.len() as f32 / sentences as f32;
+        let syllables_per_word = syllables as f32 / words.len() as f32;
+        let grade = 0.39 * words_per_sentence + 11.8 * syllables_per_word - 15.59;
+        let (base, per_sentence, per_syllable) = match language.as_deref() {
+            Some("es") => (206.84, 1.02, 60.0),
+            Some("fr") => (207.0, 1.015, 73.6),
+            Some("de") => (180.0, 1.0, 58.5),
+            Some("it") => (217.0, 1.3, 60.0),
+            Some("nl") => (206.84, 0.93, 77.0),
+            _ => (206.835, 1.015, 84.6),
+        };
+        let ease = base - per_sentence * words_per_sentence - per_syllable * syllables_per_word;
+        Some(Self {
+            words_per_sentence,
+            grade: grade.max(0.0),
+            ease: ease.clamp(0.0, 100.0),
+        })
+    }
+
+    /// What the reading ease means, e.g. "Fairly easy"
+    pub fn level(&self) -> &'static str {
+        match self.ease {
+            e if e >= 90.0 => "Very easy",
+            e if e >= 80.0 => "Easy",
+            e if e >= 70.0 => "Fairly easy",
+            e if e >= 60.0 => "Standard",
+            e if e >= 50.0 => "Fairly difficult",
+            e if e >= 30.0 => "Difficult",
+            _ => "Very difficult",
+        }
+    }
+}
+
+/// Syllables of a word: its groups of vowels (at least one), a silent
+/// final "e" left out in English
+fn syllables(word: &str, english: bool) -> usize {
+    let word = word.to_lowercase();
+    let mut count = 0;
+    let mut after_vowel = false;
+    for c in word.chars() {
+        let vowel = is_vowel(c);
+        if vowel && !after_vowel {
+            count += 1;
+        }
+        after_vowel = vowel;
+    }
+    if english && count > 1 && word.ends_with('e') && !word.ends_with("le") && !word.ends_with("ee") {
+        count -= 1;
+    }
+    count.max(1)
+}
+
+fn is_vowel(c: char) -> bool {
+    c.to_lowercase().any(|c| VOWELS.contains(c))
+}
@@ -0,0 +1,1364 @@
+// src/reader.rs
+// Text of an EPUB for the built-in reader - the XHTML documents of the
+// spine turned into styled blocks (headings, paragraphs, quotes...), and
+// the blocks wrapped into lines of a given width
+//
+// The table of contents comes from the EPUB3 navigation document, else the
+// EPUB2 NCX, else the first heading of each document of the spine. Its
+// entries point to a block: the one holding the element their link's
+// #fragment names (or the document's first).
+//
+// Only what reads well in a terminal is kept: bold, italic and underlined
+// text, headings, block quotes, list items, preformatted text and
+// separators. Style sheets and scripts are skipped; an image shows as
+// "[Image: <alt text>]", with the picture itself below its block when the
+// terminal can draw it (see graphics.rs). Malformed XHTML is read up to
+// the first error.
+//
+// How the lines are laid out (their width, the margins, the space between
+// paragraphs, justification and hyphenation) is the reader's typography,
+// changed from the reader's keys and kept in reader.json in the data
+// directory, with the colors of the page (the app's theme, or a reading
+// theme of its own). Hyphenation breaks words at their soft hyphens, else between
+// syllables by a rule of thumb that suits most Latin-script languages, and
+// Cyrillic and Greek ones with their own vowels - going by the language of
+// the book (see language.rs); languages written without syllables of
+// vowels and consonants (Chinese, Arabic...) only break at soft hyphens.
+
+use crate::epub;
+use crate::graphics::{self, ImageFormat, Protocol};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Widest a line of text can be set to
+pub const MAX_WIDTH: usize = 200;
+
+/// Narrowest a line of text gets, however small the terminal
+pub const MIN_WIDTH: usize = 20;
+
+/// Widest the margins can be set to
+pub const MAX_MARGIN: usize = 20;
+
+/// Most blank lines between paragraphs
+pub const MAX_SPACING: usize = 3;
+
+/// File of the typography, in the data directory
+const TYPOGRAPHY_FILE: &str = "reader.json";
+
+/// Fewest letters of a word kept before and after a hyphenation break
+const MIN_HYPHENATED: (usize, usize) = (2, 3);
+
+/// Fewest letters of a word hyphenated between syllables
+const MIN_SYLLABLES: usize = 6;
+
+/// Vowels of the syllable rule of hyphenation, by script
+const LATIN_VOWELS: &str = "aeiouyàáâãäåæèéêëìíîïòóôõöøœùúûüý";
+const CYRILLIC_VOWELS: &str = "аеёиоуыэюяіїєў";
+const GREEK_VOWELS: &str = "αεηιουωάέήίόύώϊϋΐΰ";
+
+/// Bytes of an image read to find its size
+const PROBE_BYTES: u64 = 64 * 1024;
+
+/// How the reader lays text out
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Typography {
+    /// Widest a line of text gets, whatever the width of the terminal
+    pub width: usize,
+
+    /// Blank columns left on both sides of the text
+    pub margin: usize,
+
+    /// Blank lines between paragraphs (one more between chapters)
+    pub spacing: usize,
+
+    /// The lines of paragraphs are filled to the full width
+    pub justify: bool,
+
+    /// Words that don't fit at the end of a line are hyphenated
+    pub hyphenate: bool,
+
+    /// Colors of the page
+    pub theme: ReaderTheme,
+}
+
+/// Colors of the reader's page, whatever the app's theme
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReaderTheme {
+    /// The app's own theme ([theme] in config.toml)
+    #[default]
+    App,
+
+    /// Brown on cream, like old paper
+    Sepia,
+
+    /// Light gray on near-black
+    Dark,
+
+    /// White and yellow on black
+    HighContrast,
+}
+
+impl ReaderTheme {
+    pub const ALL: [ReaderTheme; 4] = [ReaderTheme::App, ReaderTheme::Sepia, ReaderTheme::Dark, ReaderTheme::HighContrast];
+
+    /// The theme after this one (back to the app's after the last)
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&theme| theme == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// Human-readable name, for the status message
+    pub fn label(self) -> &'static str {
+        match self {
+            ReaderTheme::App => "app theme",
+            ReaderTheme::Sepia => "sepia",
+            ReaderTheme::Dark => "dark",
+            ReaderTheme::HighContrast => "high contrast",
+        }
+    }
+}
+
+impl Default for Typography {
+    fn default() -> Self {
+        Self {
+            width: 80,
+            margin: 2,
+            spacing: 1,
+            justify: false,
+            hyphenate: false,
+            theme: ReaderTheme::App,
+        }
+    }
+}
+
+impl Typography {
+    /// The saved typography (the default one if none was saved)
+    pub fn load() -> Self {
+        std::fs::read_to_string(typography_path())
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    /// Keeps the typography for the next sessions
+    pub fn save(&self) -> io::Result<()> {
+        let path = typography_path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        std::fs::write(path, json)
+    }
+
+    /// Columns of the lines of text in a terminal this wide
+    pub fn column(&self, columns: usize) -> usize {
+        columns.saturating_sub(2 * self.margin).min(self.width).max(MIN_WIDTH)
+    }
+}
+
+/// Where the typography is saved
+fn typography_path() -> PathBuf {
+    crate::paths::data_dir().join(TYPOGRAPHY_FILE)
+}
+
+/// The text of a book, one chapter per document of the spine
+pub struct Document {
+    pub chapters: Vec<Chapter>,
+
+    /// The table of contents, in reading order
+    pub contents: Vec<TocEntry>,
+
+    /// Language of the text, e.g. "ru", which decides how words are
+    /// hyphenated (None = unknown, hyphenated as a Latin-script language)
+    pub language: Option<String>,
+}
+
+/// One XHTML document of the spine
+pub struct Chapter {
+    /// Path of the document inside the EPUB
+    pub href: String,
+
+    /// Text of its first heading, if it has one
+    pub title: Option<String>,
+
+    pub blocks: Vec<Block>,
+
+    /// Ids of its elements, with the index of the block each one is in
+    pub anchors: Vec<(String, usize)>,
+}
+
+/// An entry of the table of contents
+#[derive(Debug, Clone)]
+pub struct TocEntry {
+    pub title: String,
+
+    /// Nesting level (0 = top level, 1 = a section of a top-level entry...)
+    pub depth: usize,
+
+    /// Index of the chapter it points to
+    pub chapter: usize,
+
+    /// Index of the block it points to in the chapter
+    pub block: usize,
+}
+
+/// A paragraph, heading, list item... of a chapter
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub kind: BlockKind,
+    pub spans: Vec<Span>,
+
+    /// Its images, in order (their alt text is in `spans`)
+    pub images: Vec<Image>,
+}
+
+/// An `<img>` (or SVG `<image>`) of a chapter
+#[derive(Debug, Clone)]
+pub struct Image {
+    /// Path of the image inside the EPUB
+    pub href: String,
+
+    /// Its format and size in pixels (None until Document::measure_images,
+    /// or if it isn't a PNG, JPEG or GIF)
+    pub size: Option<(ImageFormat, (u32, u32))>,
+}
+
+/// What a block is, which decides how it's laid out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockKind {
+    /// `<h1>` to `<h6>`
+    Heading,
+
+    /// `<p>`, `<div>` and text outside of any other block
+    Paragraph,
+
+    /// `<blockquote>` (indented)
+    Quote,
+
+    /// `<li>` (bulleted)
+    ListItem,
+
+    /// `<pre>` (whitespace kept, never wrapped at spaces)
+    Preformatted,
+
+    /// `<hr>` (a centered "* * *")
+    Rule,
+}
+
+/// A run of text with one style
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub style: TextStyle,
+}
+
+/// Inline styles of a span
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TextStyle {
+    /// `<b>`, `<strong>`
+    pub bold: bool,
+
+    /// `<i>`, `<em>`, `<cite>`
+    pub italic: bool,
+
+    /// `<u>`, `<ins>`
+    pub underline: bool,
+}
+
+/// A line of text as laid out for the screen
+#[derive(Debug, Clone)]
+pub struct Line {
+    /// Index of the chapter it belongs to
+    pub chapter: usize,
+
+    /// Index of its block in the chapter
+    pub block: usize,
+
+    pub kind: BlockKind,
+
+    /// Its text (empty for the blank lines between blocks)
+    pub spans: Vec<Span>,
+
+    /// The image of its block whose picture starts on it (index in the
+    /// block's images; the picture covers the blank lines after it)
+    pub picture: Option<usize>,
+}
+
+impl Document {
+    /// Reads the text of an EPUB file
+    ///
+    /// Documents of the spine marked `linear="no"` (usually notes) and
+    /// documents without text (e.g. a cover page) are left out.
+    ///
+    /// # Returns
+    /// The document, or an error if the file isn't a readable EPUB or has no text
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(io::Error::other)?;
+        let (opf_path, opf) = epub::read_package(&mut archive)?;
+        let package = Package::parse(&opf)?;
+
+        let mut chapters = Vec::new();
+        for href in &package.documents {
+            let href = epub::entry_path(&opf_path, href);
+            match epub::read_entry(&mut archive, &href) {
+                Ok(xhtml) => chapters.extend(parse_xhtml(href, &xhtml)),
+                Err(e) => tracing::warn!(path = %path.display(), document = %href, error = %e, "cannot read chapter"),
+            }
+        }
+
+        if chapters.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the book has no readable text"));
+        }
+
+        let links = read_links(&mut archive, &opf_path, &package);
+
+        let mut document = Self {
+            chapters,
+            contents: Vec::new(),
+            language: None,
+        };
+        document.contents = links.iter().filter_map(|link| document.locate(link)).collect();
+        if document.contents.is_empty() {
+            document.contents = document
+                .chapters
+                .iter()
+                .enumerate()
+                .map(|(c, chapter)| TocEntry {
+                    title: chapter.title.clone().unwrap_or_else(|| format!("Section {}", c + 1)),
+                    depth: 0,
+                    chapter: c,
+                    block: 0,
+                })
+                .collect();
+        }
+        Ok(document)
+    }
+
+    /// Entry of the table of contents for a link (None if it points
+    /// outside of the chapters, e.g. to a note left out of the spine)
+    fn locate(&self, link: &Link) -> Option<TocEntry> {
+        let (path, fragment) = link.href.split_once('#').unwrap_or((&link.href, ""));
+        let chapter = self.chapters.iter().position(|chapter| chapter.href == path)?;
+        let block = self.chapters[chapter]
+            .anchors
+            .iter()
+            .find(|(id, _)| id == fragment)
+            .map_or(0, |(_, block)| *block);
+        Some(TocEntry {
+            title: link.title.clone(),
+            depth: link.depth,
+            chapter,
+            block,
+        })
+    }
+
+    /// Reads the size of the images of the chapters (only their first
+    /// bytes), for pictures to be laid out
+    pub fn measure_images(&mut self, path: &Path) -> io::Result<()> {
+        let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(io::Error::other)?;
+        let images = self.chapters.iter_mut().flat_map(|chapter| &mut chapter.blocks).flat_map(|block| &mut block.images);
+        for image in images {
+            let Ok(entry) = archive.by_name(&image.href) else {
+                continue;
+            };
+            let mut bytes = Vec::new();
+            entry.take(PROBE_BYTES).read_to_end(&mut bytes)?;
+            image.size = graphics::probe(&bytes);
+        }
+        Ok(())
+    }
+
+    /// Wraps the text into lines of at most `width` columns (MIN_WIDTH at
+    /// least), with `typography.spacing` blank lines between blocks and one
+    /// more between chapters
+    ///
+    /// With a graphics protocol, the measured images it can draw get blank
+    /// lines for their pictures after the text of their block.
+    pub fn layout(&self, width: usize, typography: &Typography, graphics: Option<Protocol>) -> Vec<Line> {
+        let width = width.max(MIN_WIDTH);
+        // Headings are neither justified nor hyphenated
+        let heading = Typography {
+            justify: false,
+            hyphenate: false,
+            ..*typography
+        };
+        let vowels = hyphenation_vowels(self.language.as_deref());
+        let mut lines = Vec::new();
+
+        for (c, chapter) in self.chapters.iter().enumerate() {
+            for (b, block) in chapter.blocks.iter().enumerate() {
+                let blank = Line {
+                    chapter: c,
+                    block: b,
+                    kind: BlockKind::Paragraph,
+                    spans: Vec::new(),
+                    picture: None,
+                };
+                if !lines.is_empty() {
+                    let count = typography.spacing + usize::from(b == 0);
+                    lines.extend(std::iter::repeat_n(blank, count));
+                }
+
+                let wrapped = match block.kind {
+                    BlockKind::Rule => vec![vec![plain(&format!("{:^width$}", "* * *", width = width))]],
+                    BlockKind::Preformatted => block
+                        .spans
+                        .iter()
+                        .map(|span| span.text.as_str())
+                        .collect::<String>()
+                        .split('\n')
+                        .flat_map(|line| chunks(line, width))
+                        .map(|line| vec![plain(&line)])
+                        .collect(),
+                    BlockKind::Quote => wrap(&block.spans, width, "    ", "    ", typography, vowels),
+                    BlockKind::ListItem => wrap(&block.spans, width, "• ", "  ", typography, vowels),
+                    BlockKind::Paragraph => wrap(&block.spans, width, "", "", typography, vowels),
+                    BlockKind::Heading => wrap(&block.spans, width, "", "", &heading, vowels),
+                };
+                lines.extend(wrapped.into_iter().map(|spans| Line {
+                    chapter: c,
+                    block: b,
+                    kind: block.kind,
+                    spans,
+                    picture: None,
+                }));
+
+                // Room for the pictures
+                let Some(protocol) = graphics else {
+                    continue;
+                };
+                for (i, image) in block.images.iter().enumerate() {
+                    let Some((_, size)) = image.size.filter(|(format, _)| protocol.shows(*format)) else {
+                        continue;
+                    };
+                    let (_, rows) = graphics::cells(size, width);
+                    for row in 0..rows {
+                        lines.push(Line {
+                            chapter: c,
+                            block: b,
+                            kind: block.kind,
+                            spans: Vec::new(),
+                            picture: (row == 0).then_some(i),
+                        });
+                    }
+                }
+            }
+        }
+        lines
+    }
+}
+
+impl Chapter {
+    /// Text of the chapter without its styles, a line per block
+    pub fn text(&self) -> String {
+        self.blocks.iter().map(Block::text).collect::<Vec<_>>().join("\n")
+    }
+}
+
+impl Block {
+    /// Text of the block without its styles
+    pub fn text(&self) -> String {
+        plain_text(&self.spans)
+    }
+}
+
+impl Line {
+    /// Where the words of the line are: character ranges of letters and digits
+    pub fn words(&self) -> Vec<Range<usize>> {
+        let mut words = Vec::new();
+        let mut start = None;
+        let text = plain_text(&self.spans);
+        for (i, c) in text.chars().chain([' ']).enumerate() {
+            match (c.is_alphanumeric(), start) {
+                (true, None) => start = Some(i),
+                (false, Some(first)) => {
+                    words.push(first..i);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        words
+    }
+}
+
+/// What the reader needs from the OPF package document (paths relative to it)
+struct Package {
+    /// The documents of the spine, in reading order
+    documents: Vec<String>,
+
+    /// The EPUB3 navigation document
+    nav: Option<String>,
+
+    /// The EPUB2 table of contents (NCX)
+    ncx: Option<String>,
+}
+
+impl Package {
+    fn parse(opf: &str) -> io::Result<Self> {
+        // Manifest items as (id, href, media type, properties), the ids of
+        // the spine and the id of the NCX it names
+        let mut items: Vec<(String, String, String, String)> = Vec::new();
+        let mut order: Vec<String> = Vec::new();
+        let mut ncx_id = None;
+        let mut reader = Reader::from_str(opf);
+        loop {
+            match reader.read_event().map_err(io::Error::other)? {
+                Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                    b"item" => items.push((
+                        epub::attribute(&e, b"id").unwrap_or_default(),
+                        epub::attribute(&e, b"href").unwrap_or_default(),
+                        epub::attribute(&e, b"media-type").unwrap_or_default(),
+                        epub::attribute(&e, b"properties").unwrap_or_default(),
+                    )),
+                    b"spine" => ncx_id = epub::attribute(&e, b"toc"),
+                    b"itemref" if epub::attribute(&e, b"linear").as_deref() != Some("no") => {
+                        order.extend(epub::attribute(&e, b"idref"));
+                    }
+                    _ => {}
+                },
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        let href = |item: &(String, String, String, String)| item.1.clone();
+        Ok(Self {
+            documents: order
+                .iter()
+                .filter_map(|id| items.iter().find(|(item, ..)| item == id))
+                .filter(|(_, _, media, _)| media.contains("html"))
+                .map(href)
+                .collect(),
+            nav: items
+                .iter()
+                .find(|(.., properties)| properties.split_whitespace().any(|p| p == "nav"))
+                .map(href),
+            ncx: items
+                .iter()
+                .find(|(id, _, media, _)| Some(id) == ncx_id.as_ref() || media == "application/x-dtbncx+xml")
+                .map(href),
+        })
+    }
+}
+
+/// Reads the table of contents of an EPUB: the links of its navigation
+/// document, else of its NCX (empty if it has neither)
+///
+/// # Arguments
+/// * `archive` - The EPUB, open
+/// * `opf_path` - Path of its package document inside the archive
+/// * `opf` - The package document
+///
+/// # Returns
+/// The links, their hrefs relative to the archive ("Text/ch1.xhtml#part2")
+pub fn read_toc(archive: &mut zip::ZipArchive<File>, opf_path: &str, opf: &str) -> io::Result<Vec<Link>> {
+    let package = Package::parse(opf)?;
+    Ok(read_links(archive, opf_path, &package))
+}
+
+/// The links of the navigation document (or NCX) of a package, relative to the archive
+fn read_links(archive: &mut zip::ZipArchive<File>, opf_path: &str, package: &Package) -> Vec<Link> {
+    for (toc, read) in [(&package.nav, read_nav as fn(&str) -> Vec<Link>), (&package.ncx, read_ncx)] {
+        let Some(toc) = toc else {
+            continue;
+        };
+        let toc = epub::entry_path(opf_path, toc);
+        match epub::read_entry(archive, &toc) {
+            Ok(text) => {
+                let mut links = read(&text);
+                // From now on relative to the archive, as the chapters
+                for link in &mut links {
+                    link.href = format!("{}#{}", epub::entry_path(&toc, &link.href), link.fragment());
+                }
+                if !links.is_empty() {
+                    return links;
+                }
+            }
+            Err(e) => tracing::debug!(toc = %toc, error = %e, "cannot read table of contents"),
+        }
+    }
+    Vec::new()
+}
+
+/// A link of the table of contents, as found in the navigation document or NCX
+#[derive(Debug, Clone, Default)]
+pub struct Link {
+    pub title: String,
+
+    /// Path the link points to, with its #fragment (relative to the
+    /// navigation document as read, to the archive once `read_toc` returns it)
+    pub href: String,
+
+    /// Nesting level (0 = top level)
+    pub depth: usize,
+}
+
+impl Link {
+    /// The #fragment of the link, without the '#' ("" if it has none)
+    pub fn fragment(&self) -> &str {
+        self.href.split_once('#').map_or("", |(_, fragment)| fragment)
+    }
+}
+
+/// Reads the links of the `<nav epub:type="toc">` of an EPUB3 navigation document
+fn read_nav(xhtml: &str) -> Vec<Link> {
+    let mut reader = Reader::from_str(xhtml);
+    reader.check_end_names(false);
+
+    let mut links = Vec::new();
+    let mut in_toc = false;
+    let mut lists = 0;
+    let mut link: Option<Link> = None;
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => match e.local_name().as_ref() {
+                b"nav" => {
+                    in_toc = epub::attribute(&e, b"type").is_some_and(|t| t.split_whitespace().any(|t| t == "toc"));
+                }
+                b"ol" if in_toc => lists += 1,
+                b"a" if in_toc => {
+                    link = epub::attribute(&e, b"href").map(|href| Link {
+                        href,
+                        depth: lists.max(1) - 1,
+                        ..Default::default()
+                    })
+                }
+                _ => {}
+            },
+            Ok(Event::End(e)) => match e.local_name().as_ref() {
+                b"nav" => in_toc = false,
+                b"ol" if in_toc => lists -= 1,
+                b"a" => {
+                    if let Some(mut link) = link.take() {
+                        link.title = collapse_whitespace(&link.title).trim().to_string();
+                        links.push(link);
+                    }
+                }
+                _ => {}
+            },
+            Ok(Event::Text(e)) => {
+                if let Some(link) = link.as_mut() {
+                    link.title.push_str(&unescape(&String::from_utf8_lossy(&e)));
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+    links
+}
+
+/// Reads the links of the `<navMap>` of an EPUB2 NCX
+fn read_ncx(ncx: &str) -> Vec<Link> {
+    let mut reader = Reader::from_str(ncx);
+    reader.check_end_names(false);
+
+    let mut links = Vec::new();
+    let mut points = 0;
+    let mut label: Option<String> = None;
+    let mut in_text = false;
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => match e.local_name().as_ref() {
+                b"navPoint" => {
+                    points += 1;
+                    label = None;
+                }
+                b"text" if points > 0 => {
+                    in_text = true;
+                    label.get_or_insert_with(String::new);
+                }
+                _ => {}
+            },
+            Ok(Event::Empty(e)) if e.local_name().as_ref() == b"content" && points > 0 => {
+                if let Some(href) = epub::attribute(&e, b"src") {
+                    links.push(Link {
+                        title: collapse_whitespace(label.as_deref().unwrap_or_default()).trim().to_string(),
+                        href,
+                        depth: points - 1,
+                    });
+                }
+            }
+            Ok(Event::End(e)) => match e.local_name().as_ref() {
+                b"navPoint" => points -= 1,
+                b"text" => in_text = false,
+                _ => {}
+            },
+            Ok(Event::Text(e)) if in_text => {
+                if let Some(label) = label.as_mut() {
+                    label.push_str(&unescape(&String::from_utf8_lossy(&e)));
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+    links
+}
+
+/// Walks an XHTML document, collecting its blocks
+#[derive(Default)]
+struct Parser {
+    /// Path of the document inside the EPUB, which image paths are relative to
+    href: String,
+
+    blocks: Vec<Block>,
+
+    /// Kinds of the block elements open around the current text
+    open: Vec<BlockKind>,
+
+    /// Text of the block being read
+    spans: Vec<Span>,
+
+    /// Images of the block being read
+    images: Vec<Image>,
+
+    /// Open `<b>`, `<i>` and `<u>` elements (and their synonyms)
+    bold: usize,
+    italic: usize,
+    underline: usize,
+
+    /// Open elements whose content isn't text (`<head>`, `<script>`...)
+    skipped: usize,
+
+    /// Whether any text was found (images and separators aren't text)
+    has_text: bool,
+
+    /// Ids of the elements met, with the index of the block each one is in
+    anchors: Vec<(String, usize)>,
+}
+
+impl Parser {
+    /// Kind of the block being read
+    fn kind(&self) -> BlockKind {
+        self.open.last().copied().unwrap_or(BlockKind::Paragraph)
+    }
+
+    /// Adds text to the block being read, with the current styles
+    fn text(&mut self, text: &str) {
+        if self.skipped > 0 || text.is_empty() {
+            return;
+        }
+        self.has_text |= !text.trim().is_empty();
+        let text = if self.kind() == BlockKind::Preformatted {
+            text.to_string()
+        } else {
+            collapse_whitespace(text)
+        };
+        let style = TextStyle {
+            bold: self.bold > 0,
+            italic: self.italic > 0,
+            underline: self.underline > 0,
+        };
+        push_span(&mut self.spans, &text, style);
+    }
+
+    /// Ends the block being read (if it has text)
+    fn flush(&mut self) {
+        let mut spans = std::mem::take(&mut self.spans);
+        let images = std::mem::take(&mut self.images);
+        let kind = self.kind();
+        if kind == BlockKind::Preformatted {
+            // The line break right after <pre> isn't part of the text
+            if let Some(first) = spans.first_mut() {
+                first.text = first.text.trim_start_matches(['\r', '\n']).to_string();
+            }
+            if let Some(last) = spans.last_mut() {
+                last.text = last.text.trim_end().to_string();
+            }
+        } else {
+            trim_spans(&mut spans);
+        }
+        spans.retain(|span| !span.text.is_empty());
+        if !spans.is_empty() {
+            self.blocks.push(Block { kind, spans, images });
+        }
+    }
+
+    /// An element opens (`empty` for `<br/>`-like elements, which close too)
+    fn start(&mut self, element: &BytesStart, empty: bool) {
+        let name = element.local_name().as_ref().to_ascii_lowercase();
+        self.element(element, &name, empty);
+
+        // The block being read is the next one pushed (a block element
+        // flushed the one before it)
+        if let Some(id) = epub::attribute(element, b"id") {
+            self.anchors.push((id, self.blocks.len()));
+        }
+    }
+
+    /// Acts on an element that opens
+    fn element(&mut self, element: &BytesStart, name: &[u8], empty: bool) {
+        match name {
+            b"br" => self.text_break(),
+            b"hr" => {
+                self.flush();
+                self.blocks.push(Block {
+                    kind: BlockKind::Rule,
+                    spans: Vec::new(),
+                    images: Vec::new(),
+                });
+            }
+            b"img" | b"image" if self.skipped == 0 => {
+                let alt = epub::attribute(element, b"alt").map(|alt| collapse_whitespace(&alt));
+                let alt = alt.as_deref().map(str::trim).filter(|alt| !alt.is_empty());
+                let placeholder = match alt {
+                    Some(alt) => format!(" [Image: {}] ", alt),
+                    None => " [Image] ".to_string(),
+                };
+                push_span(&mut self.spans, &placeholder, TextStyle::default());
+                let src = epub::attribute(element, b"src").or_else(|| epub::attribute(element, b"href"));
+                if let Some(src) = src.filter(|src| !src.starts_with("data:")) {
+                    self.images.push(Image {
+                        href: epub::entry_path(&self.href, &src),
+                        size: None,
+                    });
+                }
+            }
+            b"td" | b"th" => self.text(" "),
+            _ if empty => {
+                // <p/> and the like still end the block before them
+                if block_kind(name, self.kind()).is_some() {
+                    self.flush();
+                }
+            }
+            b"head" | b"script" | b"style" => self.skipped += 1,
+            b"b" | b"strong" => self.bold += 1,
+            b"i" | b"em" | b"cite" | b"dfn" | b"var" => self.italic += 1,
+            b"u" | b"ins" => self.underline += 1,
+            _ => {
+                if let Some(kind) = block_kind(name, self.kind()) {
+                    self.flush();
+                    self.open.push(kind);
+                }
+            }
+        }
+    }
+
+    /// An element closes
+    fn end(&mut self, name: &[u8]) {
+        let name = name.to_ascii_lowercase();
+        match name.as_slice() {
+            b"head" | b"script" | b"style" => self.skipped = self.skipped.saturating_sub(1),
+            b"b" | b"strong" => self.bold = self.bold.saturating_sub(1),
+            b"i" | b"em" | b"cite" | b"dfn" | b"var" => self.italic = self.italic.saturating_sub(1),
+            b"u" | b"ins" => self.underline = self.underline.saturating_sub(1),
+            _ => {
+                if block_kind(&name, BlockKind::Paragraph).is_some() {
+                    self.flush();
+                    self.open.pop();
+                }
+            }
+        }
+    }
+
+    /// A `<br/>`: the text goes on on a new line, in the same block
+    fn text_break(&mut self) {
+        if self.skipped == 0 {
+            push_span(&mut self.spans, "\n", TextStyle::default());
+        }
+    }
+}
+
+/// Kind of the block an element starts, if it's a block element
+///
+/// # Arguments
+/// * `name` - Local name of the element, lowercase
+/// * `outer` - Kind of the block it's in (a `<p>` inside a `<blockquote>` is quoted)
+fn block_kind(name: &[u8], outer: BlockKind) -> Option<BlockKind> {
+    match name {
+        b"h1" | b"h2" | b"h3" | b"h4" | b"h5" | b"h6" => Some(BlockKind::Heading),
+        b"blockquote" => Some(BlockKind::Quote),
+        b"li" | b"dt" | b"dd" => Some(BlockKind::ListItem),
+        b"pre" => Some(BlockKind::Preformatted),
+        b"p" | b"div" | b"section" | b"article" | b"aside" | b"header" | b"footer" | b"nav" | b"main"
+        | b"figure" | b"figcaption" | b"body" | b"table" | b"tr" | b"caption" | b"ul" | b"ol" | b"dl"
+        | b"center" | b"address" => Some(match outer {
+            BlockKind::Heading | BlockKind::Rule => BlockKind::Paragraph,
+            outer => outer,
+        }),
+        _ => None,
+    }
+}
+
+/// Reads an XHTML document of the spine
+///
+/// # Arguments
+/// * `href` - Path of the document inside the EPUB
+/// * `xhtml` - Its content
+///
+/// # Returns
+/// The chapter, or None if the document has no text (e.g. a cover page)
+fn parse_xhtml(href: String, xhtml: &str) -> Option<Chapter> {
+    let mut reader = Reader::from_str(xhtml);
+    // Books aren't always valid XML: keep going past mismatched end tags
+    reader.check_end_names(false);
+
+    let mut parser = Parser {
+        href: href.clone(),
+        ..Parser::default()
+    };
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => parser.start(&e, false),
+            Ok(Event::Empty(e)) => parser.start(&e, true),
+            Ok(Event::End(e)) => parser.end(e.local_name().as_ref()),
+            Ok(Event::Text(e)) => parser.text(&unescape(&String::from_utf8_lossy(&e))),
+            Ok(Event::CData(e)) => parser.text(&String::from_utf8_lossy(&e)),
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => {
+                tracing::debug!(error = %e, "malformed chapter, read up to the error");
+                break;
+            }
+        }
+    }
+    parser.flush();
+    if !parser.has_text {
+        return None;
+    }
+
+    let title = parser
+        .blocks
+        .iter()
+        .find(|block| block.kind == BlockKind::Heading)
+        .map(|heading| plain_text(&heading.spans).replace('\n', " "));
+    Some(Chapter {
+        href,
+        title,
+        blocks: parser.blocks,
+        anchors: parser.anchors,
+    })
+}
+
+/// Replaces the character and entity references of XHTML text
+///
+/// Named HTML entities aren't defined in XML, but books use them anyway;
+/// unknown ones are left as they are.
+pub fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';').filter(|end| *end <= 12) else {
+            out.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let name = &rest[1..end];
+        let decoded = match name.strip_prefix('#') {
+            Some(number) => match number.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => number.parse().ok(),
+            }
+            .and_then(char::from_u32)
+            .map(String::from),
+            None => entity(name).map(str::to_string),
+        };
+        match decoded {
+            Some(decoded) => {
+                out.push_str(&decoded);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Text of a named entity (the XML ones and the HTML ones books use most)
+fn entity(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "amp" => "&",
+        "lt" => "<",
+        "gt" => ">",
+        "quot" => "\"",
+        "apos" => "'",
+        "nbsp" => "\u{a0}",
+        "ensp" | "emsp" | "thinsp" => " ",
+        "shy" | "zwnj" | "zwj" => "",
+        "mdash" => "—",
+        "ndash" => "–",
+        "hellip" => "…",
+        "lsquo" => "‘",
+        "rsquo" => "’",
+        "sbquo" => "‚",
+        "ldquo" => "“",
+        "rdquo" => "”",
+        "bdquo" => "„",
+        "laquo" => "«",
+        "raquo" => "»",
+        "copy" => "©",
+        "reg" => "®",
+        "trade" => "™",
+        "deg" => "°",
+        "middot" => "·",
+        "bull" => "•",
+        "dagger" => "†",
+        "Dagger" => "‡",
+        "sect" => "§",
+        "para" => "¶",
+        "times" => "×",
+        "divide" => "÷",
+        "frac12" => "½",
+        "frac14" => "¼",
+        "frac34" => "¾",
+        "prime" => "′",
+        "Prime" => "″",
+        "iexcl" => "¡",
+        "iquest" => "¿",
+        "aacute" => "á",
+        "eacute" => "é",
+        "iacute" => "í",
+        "oacute" => "ó",
+        "uacute" => "ú",
+        "Aacute" => "Á",
+        "Eacute" => "É",
+        "Iacute" => "Í",
+        "Oacute" => "Ó",
+        "Uacute" => "Ú",
+        "agrave" => "à",
+        "egrave" => "è",
+        "ograve" => "ò",
+        "acirc" => "â",
+        "ecirc" => "ê",
+        "icirc" => "î",
+        "ocirc" => "ô",
+        "ucirc" => "û",
+        "auml" => "ä",
+        "euml" => "ë",
+        "iuml" => "ï",
+        "ouml" => "ö",
+        "uuml" => "ü",
+        "Auml" => "Ä",
+        "Ouml" => "Ö",
+        "Uuml" => "Ü",
+        "ntilde" => "ñ",
+        "Ntilde" => "Ñ",
+        "ccedil" => "ç",
+        "Ccedil" => "Ç",
+        "szlig" => "ß",
+        "aelig" => "æ",
+        "oelig" => "œ",
+        "oslash" => "ø",
+        "aring" => "å",
+        _ => return None,
+    })
+}
+
+/// Turns every run of whitespace into one space (as browsers do)
+fn collapse_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut space = false;
+    for c in text.chars() {
+        // A no-break space is text, not whitespace to collapse
+        if c.is_whitespace() && c != '\u{a0}' {
+            space = true;
+        } else {
+            if space {
+                out.push(' ');
+                space = false;
+            }
+            out.push(c);
+        }
+    }
+    if space {
+        out.push(' ');
+    }
+    out
+}
+
+/// Drops the spaces at the start and end of a block, and the doubled ones
+/// where two spans meet or around line breaks
+fn trim_spans(spans: &mut [Span]) {
+    let mut after_space = true;
+    for span in spans.iter_mut() {
+        let mut text = String::with_capacity(span.text.len());
+        for c in span.text.chars() {
+            if c == ' ' && after_space {
+                continue;
+            }
+            if c == '\n' && text.ends_with(' ') {
+                text.pop();
+            }
+            after_space = c == ' ' || c == '\n';
+            text.push(c);
+        }
+        span.text = text;
+    }
+    if let Some(last) = spans.iter_mut().rev().find(|span| !span.text.is_empty()) {
+        last.text = last.text.trim_end().to_string();
+    }
+}
+
+/// Appends text to a list of spans, joining it to the last one if it has the same style
+fn push_span(spans: &mut Vec<Span>, text: &str, style: TextStyle) {
+    match spans.last_mut() {
+        Some(last) if last.style == style => last.text.push_str(text),
+        _ => spans.push(Span {
+            text: text.to_string(),
+            style,
+        }),
+    }
+}
+
+/// An unstyled span
+fn plain(text: &str) -> Span {
+    Span {
+        text: text.to_string(),
+        style: TextStyle::default(),
+    }
+}
+
+/// The text of spans without their styles
+fn plain_text(spans: &[Span]) -> String {
+    spans.iter().map(|span| span.text.as_str()).collect()
+}
+
+/// Splits a line into pieces of at most `width` characters
+fn chunks(line: &str, width: usize) -> Vec<String> {
+    let chars: Vec<char> = line.chars().collect();
+    if chars.is_empty() {
+        return vec![String::new()];
+    }
+    chars.chunks(width).map(|chunk| chunk.iter().collect()).collect()
+}
+
+/// Wraps styled text at spaces into lines of at most `width` characters
+///
+/// # Arguments
+/// * `spans` - The text (a "\n" forces a new line, a soft hyphen allows a
+///   hyphenation break)
+/// * `width` - Columns of a line, prefix included
+/// * `first` - Prefix of the first line, e.g. "• "
+/// * `rest` - Prefix of the other lines, e.g. "  "
+/// * `typography` - Whether lines are justified and words hyphenated
+/// * `vowels` - Vowels of the text's language (see `hyphenation_vowels`)
+fn wrap(spans: &[Span], width: usize, first: &str, rest: &str, typography: &Typography, vowels: &str) -> Vec<Vec<Span>> {
+    let mut lines = Vec::new();
+    let mut line = vec![plain(first)];
+    let mut prefix = first.chars().count();
+    let mut used = prefix;
+    let mut empty = true;
+
+    // Words as styled pieces, where their soft hyphens were, and whether a
+    // forced line break follows
+    let mut words: Vec<(Vec<Span>, Vec<usize>, bool)> = Vec::new();
+    let mut word: Vec<Span> = Vec::new();
+    let mut soft = Vec::new();
+    let mut length = 0;
+    for span in spans {
+        for c in span.text.chars() {
+            match c {
+                ' ' | '\n' => {
+                    if !word.is_empty() || c == '\n' {
+                        words.push((std::mem::take(&mut word), std::mem::take(&mut soft), c == '\n'));
+                        length = 0;
+                    }
+                }
+                '\u{ad}' => soft.push(length),
+                c => {
+                    push_span(&mut word, c.encode_utf8(&mut [0; 4]), span.style);
+                    length += 1;
+                }
+            }
+        }
+    }
+    if !word.is_empty() {
+        words.push((word, soft, false));
+    }
+
+    for (mut word, mut soft, breaks) in words {
+        loop {
+            let length: usize = word.iter().map(|span| span.text.chars().count()).sum();
+            if length == 0 {
+                break;
+            }
+            let room = width.saturating_sub(if empty { used } else { used + 1 });
+            if length <= room {
+                if !empty {
+                    line.push(plain(" "));
+                    used += 1;
+                }
+                for span in word {
+                    push_span(&mut line, &span.text, span.style);
+                }
+                used += length;
+                empty = false;
+                break;
+            }
+
+            // As much of the word as fits with a hyphen, the rest on the next line
+            let chars: Vec<char> = word.iter().flat_map(|span| span.text.chars()).collect();
+            let at = match typography.hyphenate {
+                true => hyphenation_point(&chars, &soft, room.saturating_sub(1), vowels),
+                false => None,
+            };
+            if let Some(at) = at {
+                let (head, tail) = split_word(word, at);
+                if !empty {
+                    line.push(plain(" "));
+                }
+                let style = head.last().map_or(TextStyle::default(), |span| span.style);
+                for span in head {
+                    push_span(&mut line, &span.text, span.style);
+                }
+                if chars[at - 1] != '-' {
+                    push_span(&mut line, "-", style);
+                }
+                if typography.justify {
+                    justify(&mut line, width, prefix);
+                }
+                lines.push(std::mem::replace(&mut line, vec![plain(rest)]));
+                prefix = rest.chars().count();
+                used = prefix;
+                empty = true;
+                word = tail;
+                soft = soft.into_iter().filter(|&s| s > at).map(|s| s - at).collect();
+                continue;
+            }
+
+            // The word starts the next line
+            if !empty {
+                if typography.justify {
+                    justify(&mut line, width, prefix);
+                }
+                lines.push(std::mem::replace(&mut line, vec![plain(rest)]));
+                prefix = rest.chars().count();
+                used = prefix;
+                empty = true;
+                continue;
+            }
+
+            // A word longer than a whole line is cut
+            for span in word {
+                for piece in span.text.chars() {
+                    if used >= width {
+                        lines.push(std::mem::replace(&mut line, vec![plain(rest)]));
+                        prefix = rest.chars().count();
+                        used = prefix;
+                    }
+                    push_span(&mut line, piece.encode_utf8(&mut [0; 4]), span.style);
+                    used += 1;
+                }
+            }
+            empty = false;
+            break;
+        }
+
+        if breaks {
+            lines.push(std::mem::replace(&mut line, vec![plain(rest)]));
+            prefix = rest.chars().count();
+            used = prefix;
+            empty = true;
+        }
+    }
+    if !empty || lines.is_empty() {
+        lines.push(line);
+    }
+
+    // Prefixes of blank lines aren't needed
+    for line in &mut lines {
+        line.retain(|span| !span.text.is_empty());
+    }
+    lines
+}
+
+/// Where to hyphenate a word so that its first part has at most `room`
+/// characters (None if it can't be): its last soft hyphen that allows it,
+/// else after a hyphen it has, else between two syllables - before a
+/// consonant between two vowels, or between two consonants after a vowel
+/// (no syllables without `vowels`)
+fn hyphenation_point(word: &[char], soft: &[usize], room: usize, vowels: &str) -> Option<usize> {
+    let (before, after) = MIN_HYPHENATED;
+    // Letters from a point on (punctuation doesn't count)
+    let letters_after = |at: usize| word[at..].iter().filter(|c| c.is_alphabetic()).count();
+    let fits = |at: usize| at >= before && at <= room && letters_after(at) >= after;
+    if !soft.is_empty() {
+        return soft.iter().rev().copied().find(|&at| fits(at));
+    }
+    if word.iter().filter(|c| c.is_alphabetic()).count() < MIN_SYLLABLES {
+        return None;
+    }
+
+    let vowel = |c: char| c.to_lowercase().any(|c| vowels.contains(c));
+    let letter = |at: usize| word.get(at).copied().filter(|c| c.is_alphabetic());
+    (1..word.len()).rev().filter(|&at| fits(at)).find(|&at| {
+        if word[at - 1] == '-' {
+            return true;
+        }
+        let (Some(a), Some(b), Some(c)) = (letter(at - 1), letter(at), letter(at + 1)) else {
+            return false;
+        };
+        let vowel_consonant_vowel = vowel(a) && !vowel(b) && vowel(c);
+        let consonants = !vowel(a) && !vowel(b) && vowel(c) && letter(at - 2).is_some_and(vowel);
+        vowel_consonant_vowel || consonants
+    })
+}
+
+/// Vowels the syllables of a language are told by, for hyphenation (none
+/// for the languages whose script doesn't spell syllables that way)
+fn hyphenation_vowels(language: Option<&str>) -> &'static str {
+    let Some(language) = language else {
+        return LATIN_VOWELS;
+    };
+    match crate::language::primary(language).as_str() {
+        "ru" | "uk" | "bg" | "sr" | "be" | "mk" => CYRILLIC_VOWELS,
+        "el" => GREEK_VOWELS,
+        "zh" | "ja" | "ko" | "th" | "ar" | "fa" | "he" | "hi" => "",
+        _ => LATIN_VOWELS,
+    }
+}
+
+/// Splits styled text in two at a character
+fn split_word(word: Vec<Span>, at: usize) -> (Vec<Span>, Vec<Span>) {
+    let (mut head, mut tail) = (Vec::new(), Vec::new());
+    for (i, (c, style)) in word
+        .iter()
+        .flat_map(|span| span.text.chars().map(move |c| (c, span.style)))
+        .enumerate()
+    {
+        let part = if i < at { &mut head } else { &mut tail };
+        push_span(part, c.encode_utf8(&mut [0; 4]), style);
+    }
+    (head, tail)
+}
+
+/// Fills a line to `width` columns by widening the spaces between its
+/// words (not those of its first `prefix` characters), the first ones most
+fn justify(line: &mut Vec<Span>, width: usize, prefix: usize) {
+    let chars: Vec<(char, TextStyle)> = line
+        .iter()
+        .flat_map(|span| span.text.chars().map(move |c| (c, span.style)))
+        .collect();
+    let gaps: Vec<usize> = (prefix..chars.len()).filter(|&i| chars[i].0 == ' ').collect();
+    let missing = width.saturating_sub(chars.len());
+    if gaps.is_empty() || missing == 0 {
+        return;
+    }
+
+    let mut justified = Vec::new();
+    let mut gap = 0;
+    for (i, (c, style)) in chars.into_iter().enumerate() {
+        push_span(&mut justified, c.encode_utf8(&mut [0; 4]), style);
+        if gaps.get(gap) == Some(&i) {
+            let extra = missing / gaps.len() + usize::from(gap < missing % gaps.len());
+            push_span(&mut justified, &" ".repeat(extra), style);
+            gap += 1;
+        }
+    }
+    *line = justified;
+}
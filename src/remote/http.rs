@@ -0,0 +1,116 @@
+// src/remote/http.rs
+// Pieces shared by the backends that speak HTTP (WebDAV, S3): reading a file
+// with range requests, and turning HTTP errors into I/O errors
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// First range request of a read; doubled while the file is read in order
+const MIN_CHUNK: u64 = 64 * 1024;
+
+/// Largest range request (downloads of whole books use this)
+const MAX_CHUNK: u64 = 4 * 1024 * 1024;
+
+/// A file on a web server, read piece by piece with range requests
+pub struct RangeReader {
+    /// Builds the GET request for the file (once per range, so signed
+    /// requests get a fresh signature)
+    request: Box<dyn Fn() -> ureq::Request>,
+
+    /// File size in bytes
+    size: u64,
+
+    /// Current read position
+    position: u64,
+
+    /// Bytes fetched last
+    buffer: Vec<u8>,
+
+    /// File offset of the first byte in `buffer`
+    buffer_start: u64,
+
+    /// Size of the next range request
+    chunk: u64,
+}
+
+impl RangeReader {
+    /// Prepares reading a file (nothing is fetched before the first read)
+    ///
+    /// # Arguments
+    /// * `request` - Builds a GET request for the file
+    /// * `size` - File size in bytes
+    pub fn new(request: Box<dyn Fn() -> ureq::Request>, size: u64) -> Self {
+        Self {
+            request,
+            size,
+            position: 0,
+            buffer: Vec::new(),
+            buffer_start: 0,
+            chunk: MIN_CHUNK,
+        }
+    }
+
+    /// Fetches the bytes at the current position
+    fn fill(&mut self) -> io::Result<()> {
+        // Reading straight on: ask for more at once next time
+        let in_order = !self.buffer.is_empty() && self.position == self.buffer_start + self.buffer.len() as u64;
+        self.chunk = if in_order { (self.chunk * 2).min(MAX_CHUNK) } else { MIN_CHUNK };
+
+        let end = (self.position + self.chunk).min(self.size) - 1;
+        let response = (self.request)()
+            .set("Range", &format!("bytes={}-{}", self.position, end))
+            .call()
+            .map_err(http_error)?;
+
+        // A server that ignores ranges sends the whole file - keep all of it
+        let whole_file = response.status() == 200;
+        let mut buffer = Vec::new();
+        response.into_reader().read_to_end(&mut buffer)?;
+        self.buffer_start = if whole_file { 0 } else { self.position };
+        self.buffer = buffer;
+        Ok(())
+    }
+}
+
+impl Read for RangeReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.size || out.is_empty() {
+            return Ok(0);
+        }
+
+        let buffered = self.buffer_start..self.buffer_start + self.buffer.len() as u64;
+        if !buffered.contains(&self.position) {
+            self.fill()?;
+        }
+
+        let offset = (self.position - self.buffer_start) as usize;
+        let available = &self.buffer[offset.min(self.buffer.len())..];
+        let count = available.len().min(out.len());
+        out[..count].copy_from_slice(&available[..count]);
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl Seek for RangeReader {
+    fn seek(&mut self, from: SeekFrom) -> io::Result<u64> {
+        let position = match from {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start of the file"))?;
+        Ok(self.position)
+    }
+}
+
+/// Turns an HTTP error into an I/O error (404 becomes NotFound)
+pub fn http_error(error: ureq::Error) -> io::Error {
+    match error {
+        ureq::Error::Status(404, _) => io::Error::new(io::ErrorKind::NotFound, "not found on the server"),
+        ureq::Error::Status(401, _) | ureq::Error::Status(403, _) => {
+            io::Error::new(io::ErrorKind::PermissionDenied, "login refused by the server")
+        }
+        other => io::Error::other(other.to_string()),
+    }
+}
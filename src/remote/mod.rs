@@ -0,0 +1,302 @@
+// src/remote/mod.rs
+// Remote scan roots - libraries on another machine, written as URLs like
+// `sftp://nas.local/srv/books`, `davs://cloud.example.com/remote.php/dav/files/me/Books`
+// or `s3://my-books/library`
+//
+// Every protocol is a `Backend`: it lists files and opens them for reading;
+// the rest (excludes, metadata, the download cache) is shared.
+//
+// Books of a remote root keep their URL as path. Scanning lists the files and
+// reads each EPUB's metadata over the connection (only the zip directory and
+// the OPF package are transferred); the whole file is downloaded into the
+// cache when the book is opened. Connection details (user, key file, S3
+// endpoint...) come from the [remote."<host>"] sections of config.toml.
+
+pub mod http;
+pub mod s3;
+pub mod sftp;
+pub mod webdav;
+
+use crate::book::Book;
+use crate::profile::FolderSettings;
+use crate::settings::RemoteHost;
+use crate::transfer::{self, Chunk, Progress};
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// Connection settings per host name, from config.toml (see `configure`)
+static HOSTS: RwLock<BTreeMap<String, RemoteHost>> = RwLock::new(BTreeMap::new());
+
+/// Folder (inside the cache directory) holding downloaded remote books
+const CACHE_FOLDER: &str = "remote";
+
+/// A file or folder on a server, parsed from `scheme://[user@]host[:port]/path`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    /// Protocol, lowercase (e.g. "sftp")
+    pub scheme: String,
+
+    /// User name given in the URL, if any
+    pub user: Option<String>,
+
+    /// Server name or address
+    pub host: String,
+
+    /// Port given in the URL, if any
+    pub port: Option<u16>,
+
+    /// Absolute path on the server, e.g. "/srv/books"
+    pub path: String,
+}
+
+impl Location {
+    /// Parses a remote path (None for local paths)
+    pub fn parse(path: &Path) -> Option<Self> {
+        let text = path.to_str()?;
+        let (scheme, rest) = text.split_once("://")?;
+        if scheme.is_empty() || !scheme.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+
+        // "user@host:port" up to the first '/', the path after it
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => (&rest[..slash], &rest[slash..]),
+            None => (rest, "/"),
+        };
+        let (user, host_port) = match authority.rsplit_once('@') {
+            Some((user, host)) => (Some(user.to_string()), host),
+            None => (None, authority),
+        };
+        let (host, port) = match host_port.rsplit_once(':') {
+            Some((host, port)) if port.parse::<u16>().is_ok() => (host, port.parse().ok()),
+            _ => (host_port, None),
+        };
+        if host.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            scheme: scheme.to_lowercase(),
+            user,
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// Another path on the same server, written as a URL path again
+    pub fn with_path(&self, path: &str) -> PathBuf {
+        let user = self.user.as_ref().map(|u| format!("{}@", u)).unwrap_or_default();
+        let port = self.port.map(|p| format!(":{}", p)).unwrap_or_default();
+        PathBuf::from(format!("{}://{}{}{}{}", self.scheme, user, self.host, port, path))
+    }
+}
+
+/// A file found on a server
+#[derive(Debug, Clone)]
+pub struct RemoteFile {
+    /// Absolute path on the server
+    pub path: String,
+
+    /// Size in bytes
+    pub size: u64,
+}
+
+/// Something that reads like a file: sequentially and with seeks
+/// (reading the zip directory of an EPUB needs both)
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// A connection to a server holding books (one per protocol)
+pub trait Backend {
+    /// Lists the files under a folder, recursively
+    fn list(&mut self, folder: &str) -> io::Result<Vec<RemoteFile>>;
+
+    /// Size of one file in bytes
+    fn size(&mut self, path: &str) -> io::Result<u64>;
+
+    /// Opens a file for reading
+    fn open(&mut self, path: &str) -> io::Result<Box<dyn ReadSeek>>;
+
+    /// Creates or replaces a file
+    fn write(&mut self, path: &str, data: &[u8]) -> io::Result<()>;
+}
+
+/// Uses new connection settings (called when config.toml is loaded)
+pub fn configure(hosts: &BTreeMap<String, RemoteHost>) {
+    if let Ok(mut current) = HOSTS.write() {
+        *current = hosts.clone();
+    }
+}
+
+/// Whether a path is on a server (a URL like sftp://host/path) rather than local
+pub fn is_remote(path: &Path) -> bool {
+    Location::parse(path).is_some()
+}
+
+/// Scans a remote scan root
+///
+/// Excludes and default tags work as for local folders. Sidecar files are
+/// never read from (or written to) a server.
+///
+/// # Arguments
+/// * `root` - The scan root, e.g. `sftp://nas.local/srv/books`
+/// * `settings` - Settings of that folder
+///
+/// # Returns
+/// The books found, or the error that stopped the scan (no connection, no
+/// such folder...) - a failed scan must not look like an empty folder
+pub fn scan(root: &Path, settings: &FolderSettings) -> io::Result<Vec<Book>> {
+    let location = location_of(root)?;
+    let mut backend = connect(&location)?;
+
+    let mut files = backend.list(&location.path)?;
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut books = Vec::new();
+    let mut bytes = 0;
+    for file in files {
+        let path = location.with_path(&file.path);
+        // Excluded files, and files inside excluded folders
+        let excluded = path
+            .ancestors()
+            .take_while(|ancestor| *ancestor != root)
+            .any(|ancestor| crate::scanner::is_excluded(root, ancestor, &settings.exclude));
+        if excluded || !crate::scanner::is_epub(&path) {
+            continue;
+        }
+
+        // Same as for local files: a broken EPUB is listed without metadata
+        let meta = backend
+            .open(&file.path)
+            .and_then(crate::epub::read_metadata_from)
+            .unwrap_or_else(|e| {
+                tracing::warn!(path = %path.display(), error = %e, "cannot read EPUB metadata");
+                Default::default()
+            });
+        books.push(crate::scanner::book_with_metadata(&path, meta, settings));
+        bytes += file.size;
+    }
+
+    tracing::info!(path = %root.display(), books = books.len(), bytes, "remote folder scanned");
+    Ok(books)
+}
+
+/// Downloads a remote book into the cache, so it can be opened
+///
+/// A cached copy with the same size as the file on the server is reused.
+/// The file is fetched in ranges, and resumes after a failure (the partial
+/// file stays in the cache).
+///
+/// # Arguments
+/// * `path` - Path (URL) of the book
+/// * `progress` - The transfer the download is shown as
+///
+/// # Returns
+/// Path of the local copy
+pub fn fetch(path: &Path, progress: &Progress) -> io::Result<PathBuf> {
+    let location = location_of(path)?;
+    let mut backend = connect(&location)?;
+
+    let cached = cache_path(&location);
+    let size = backend.size(&location.path)?;
+    progress.set_total(size);
+    if std::fs::metadata(&cached).map(|m| m.len() == size).unwrap_or(false) {
+        return Ok(cached);
+    }
+
+    if let Some(dir) = cached.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // Download under another name: an interrupted download is never taken for the book
+    let partial = cached.with_extension("part");
+    // A range that failed is asked for on a new connection
+    let mut connected = Some(backend);
+    transfer::fetch_chunked(&partial, Some(size), progress, |start, end| {
+        let mut backend = match connected.take() {
+            Some(backend) => backend,
+            None => connect(&location).map_err(|e| e.to_string())?,
+        };
+        let mut file = backend.open(&location.path).map_err(|e| e.to_string())?;
+        file.seek(SeekFrom::Start(start)).map_err(|e| e.to_string())?;
+        Ok(Chunk {
+            reader: Box::new(file.take(end - start + 1)),
+            start,
+            total: Some(size),
+            // The cached copy's path is the book's: the size tells versions apart
+            tag: None,
+        })
+    })
+    .map_err(io::Error::other)?;
+    transfer::complete(&partial, &cached, Some(size), None).map_err(io::Error::other)?;
+
+    tracing::info!(path = %path.display(), bytes = size, "remote book downloaded");
+    Ok(cached)
+}
+
+/// Reads a whole (small) file from a server, bypassing the cache
+pub fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    let location = location_of(path)?;
+    let mut data = Vec::new();
+    connect(&location)?.open(&location.path)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Creates or replaces a file on a server
+pub fn write_file(path: &Path, data: &[u8]) -> io::Result<()> {
+    let location = location_of(path)?;
+    connect(&location)?.write(&location.path, data)
+}
+
+/// Where the downloaded copy of a remote book is kept:
+/// `<cache>/remote/<scheme>/<host>/<path on the server>`
+fn cache_path(location: &Location) -> PathBuf {
+    let mut path = crate::paths::cache_dir()
+        .join(CACHE_FOLDER)
+        .join(&location.scheme)
+        .join(&location.host);
+    // Only plain names: ".." from a server must not lead out of the cache
+    for part in location.path.split('/').filter(|p| !p.is_empty() && *p != "." && *p != "..") {
+        path.push(part);
+    }
+    path
+}
+
+/// Parses a remote path, failing for anything else
+fn location_of(path: &Path) -> io::Result<Location> {
+    Location::parse(path).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("not a remote path: {}", path.display()),
+        )
+    })
+}
+
+/// Opens a connection for a location, with the settings of its host
+fn connect(location: &Location) -> io::Result<Box<dyn Backend>> {
+    let host = HOSTS
+        .read()
+        .ok()
+        .and_then(|hosts| hosts.get(&location.host).cloned())
+        .unwrap_or_default();
+
+    match location.scheme.as_str() {
+        "sftp" | "ssh" => Ok(Box::new(sftp::Sftp::connect(location, &host)?)),
+        "dav" | "davs" | "webdav" | "webdavs" => Ok(Box::new(webdav::WebDav::connect(location, &host)?)),
+        "s3" => Ok(Box::new(s3::S3::connect(location, &host)?)),
+        other => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("unsupported remote protocol '{}'", other),
+        )),
+    }
+}
+
+/// Name of the local user, used when neither the URL nor the config names one
+pub fn local_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "root".to_string())
+}
@@ -0,0 +1,310 @@
+// src/remote/s3.rs
+// S3 backend - remote roots in an S3-compatible bucket (AWS, MinIO, Garage...):
+// s3://bucket/prefix
+//
+// Requests use path-style addressing (<endpoint>/<bucket>/<key>), which every
+// S3-compatible server understands, and are signed with AWS Signature
+// Version 4. The endpoint, region and keys come from the bucket's
+// [remote."<bucket>"] section of config.toml.
+
+use super::http::{http_error, RangeReader};
+use super::{Backend, Location, ReadSeek, RemoteFile};
+use crate::opds::percent_encode;
+use crate::settings::RemoteHost;
+use hmac::{Hmac, Mac};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use sha2::{Digest, Sha256};
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Seconds to wait for the server before giving up
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Region used when the config names none
+const DEFAULT_REGION: &str = "us-east-1";
+
+/// Payload hash for requests without a body (allowed by S3 for any request)
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// A bucket and the keys to sign requests with
+pub struct S3 {
+    /// HTTP client (keeps connections open between requests)
+    agent: ureq::Agent,
+
+    /// Everything a request is built and signed from
+    signer: Signer,
+}
+
+/// Builds signed requests for one bucket (cloned into range readers)
+#[derive(Clone)]
+struct Signer {
+    /// e.g. "https://s3.eu-west-1.amazonaws.com" or "http://nas.local:9000"
+    endpoint: String,
+
+    /// "host[:port]" of the endpoint, as sent in the Host header
+    host: String,
+
+    /// Bucket name
+    bucket: String,
+
+    /// Region the signature is made for
+    region: String,
+
+    /// Access key id (None = anonymous requests, for public buckets)
+    access_key: Option<String>,
+
+    /// Secret access key
+    secret_key: String,
+}
+
+impl S3 {
+    /// Prepares requests to a bucket (nothing is sent yet)
+    ///
+    /// Keys missing from the config are taken from the usual
+    /// AWS_ACCESS_KEY_ID / AWS_SECRET_ACCESS_KEY variables.
+    ///
+    /// # Arguments
+    /// * `location` - The bucket (host part of the URL) and prefix
+    /// * `host` - Settings of that bucket from config.toml
+    pub fn connect(location: &Location, host: &RemoteHost) -> io::Result<Self> {
+        let region = host.region.clone().unwrap_or_else(|| DEFAULT_REGION.to_string());
+        let endpoint = host
+            .endpoint
+            .clone()
+            .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region))
+            .trim_end_matches('/')
+            .to_string();
+        let endpoint_host = endpoint
+            .split_once("://")
+            .map(|(_, rest)| rest)
+            .unwrap_or(&endpoint)
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+
+        let access_key = host
+            .access_key
+            .clone()
+            .or_else(|| std::env::var("AWS_ACCESS_KEY_ID").ok());
+        let secret_key = host
+            .secret_key
+            .clone()
+            .or_else(|| std::env::var("AWS_SECRET_ACCESS_KEY").ok());
+        if access_key.is_some() && secret_key.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: access_key set without secret_key", location.host),
+            ));
+        }
+
+        Ok(Self {
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+            signer: Signer {
+                endpoint,
+                host: endpoint_host,
+                bucket: location.host.clone(),
+                region,
+                access_key,
+                secret_key: secret_key.unwrap_or_default(),
+            },
+        })
+    }
+}
+
+impl Backend for S3 {
+    fn list(&mut self, folder: &str) -> io::Result<Vec<RemoteFile>> {
+        // "/books" -> keys starting with "books/"
+        let prefix = match folder.trim_matches('/') {
+            "" => String::new(),
+            prefix => format!("{}/", prefix),
+        };
+
+        let mut files = Vec::new();
+        let mut token: Option<String> = None;
+        // Up to 1000 keys per answer - follow the continuation tokens
+        loop {
+            let mut query = vec![("list-type", "2".to_string()), ("prefix", prefix.clone())];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token.clone()));
+            }
+            let body = self
+                .signer
+                .request(&self.agent, "GET", "", &query)
+                .call()
+                .map_err(http_error)?
+                .into_string()?;
+
+            let (keys, next) = parse_list(&body);
+            files.extend(
+                keys.into_iter()
+                    // Folder markers some tools create
+                    .filter(|(key, _)| !key.ends_with('/'))
+                    .map(|(key, size)| RemoteFile {
+                        path: format!("/{}", key),
+                        size,
+                    }),
+            );
+            match next {
+                Some(next) => token = Some(next),
+                None => break,
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn size(&mut self, path: &str) -> io::Result<u64> {
+        let response = self
+            .signer
+            .request(&self.agent, "HEAD", path, &[])
+            .call()
+            .map_err(http_error)?;
+        response
+            .header("Content-Length")
+            .and_then(|length| length.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "server sent no file size"))
+    }
+
+    fn open(&mut self, path: &str) -> io::Result<Box<dyn ReadSeek>> {
+        let size = self.size(path)?;
+        let (agent, signer, path) = (self.agent.clone(), self.signer.clone(), path.to_string());
+        Ok(Box::new(RangeReader::new(
+            Box::new(move || signer.request(&agent, "GET", &path, &[])),
+            size,
+        )))
+    }
+
+    fn write(&mut self, path: &str, data: &[u8]) -> io::Result<()> {
+        self.signer
+            .request(&self.agent, "PUT", path, &[])
+            .send_bytes(data)
+            .map_err(http_error)?;
+        Ok(())
+    }
+}
+
+impl Signer {
+    /// Builds a signed request
+    ///
+    /// # Arguments
+    /// * `agent` - HTTP client
+    /// * `method` - "GET", "HEAD" or "PUT"
+    /// * `key` - Object path inside the bucket ("" for the bucket itself)
+    /// * `query` - Query parameters (unencoded)
+    fn request(&self, agent: &ureq::Agent, method: &str, key: &str, query: &[(&str, String)]) -> ureq::Request {
+        // Path and query exactly as signed: every segment encoded once
+        let mut path = format!("/{}", percent_encode(&self.bucket));
+        for segment in key.split('/').filter(|s| !s.is_empty()) {
+            path.push('/');
+            path.push_str(&percent_encode(segment));
+        }
+        let mut pairs: Vec<String> = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", percent_encode(name), percent_encode(value)))
+            .collect();
+        pairs.sort();
+        let query = pairs.join("&");
+
+        let url = if query.is_empty() {
+            format!("{}{}", self.endpoint, path)
+        } else {
+            format!("{}{}?{}", self.endpoint, path, query)
+        };
+        let request = agent.request(method, &url);
+
+        let Some(access_key) = &self.access_key else {
+            return request;
+        };
+
+        // 20240501T123000Z and 20240501
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let timestamp = crate::opds::rfc3339(secs).replace(['-', ':'], "");
+        let date = &timestamp[..8];
+
+        let canonical = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\nhost;x-amz-content-sha256;x-amz-date\n{}",
+            method, path, query, self.host, UNSIGNED_PAYLOAD, timestamp, UNSIGNED_PAYLOAD
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{:x}",
+            timestamp,
+            scope,
+            Sha256::digest(canonical.as_bytes())
+        );
+
+        // The signing key is derived from the secret, step by step
+        let mut key = format!("AWS4{}", self.secret_key).into_bytes();
+        for part in [date, self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac(&key, part);
+        }
+        let signature: String = hmac(&key, &to_sign).iter().map(|b| format!("{:02x}", b)).collect();
+
+        request
+            .set("x-amz-content-sha256", UNSIGNED_PAYLOAD)
+            .set("x-amz-date", &timestamp)
+            .set(
+                "Authorization",
+                &format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={}",
+                    access_key, scope, signature
+                ),
+            )
+    }
+}
+
+/// HMAC-SHA256 of a text
+fn hmac(key: &[u8], text: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(text.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Reads a ListObjectsV2 answer
+///
+/// # Returns
+/// (key, size) of every object, and the continuation token if the list goes on
+fn parse_list(xml: &str) -> (Vec<(String, u64)>, Option<String>) {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+
+    let mut objects = Vec::new();
+    let mut next = None;
+    let mut truncated = false;
+    // The object being read: key and size
+    let (mut key, mut size) = (String::new(), 0);
+    // Name of the element whose text we're reading
+    let mut current = Vec::new();
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => current = e.local_name().as_ref().to_vec(),
+            Ok(Event::Text(text)) => {
+                let text = text.unescape().map(|t| t.to_string()).unwrap_or_default();
+                match current.as_slice() {
+                    b"Key" => key = text,
+                    b"Size" => size = text.parse().unwrap_or(0),
+                    b"IsTruncated" => truncated = text == "true",
+                    b"NextContinuationToken" => next = Some(text),
+                    _ => {}
+                }
+            }
+            Ok(Event::End(e)) => {
+                if e.local_name().as_ref() == b"Contents" && !key.is_empty() {
+                    objects.push((std::mem::take(&mut key), size));
+                    size = 0;
+                }
+                current.clear();
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+
+    (objects, next.filter(|_| truncated))
+}
@@ -0,0 +1,114 @@
+// src/remote/sftp.rs
+// SFTP backend - remote roots like sftp://[user@]host[:port]/path, over libssh2
+
+use super::{Backend, Location, ReadSeek, RemoteFile};
+use crate::settings::RemoteHost;
+use ssh2::Session;
+use std::io::{self, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+
+/// Port used when neither the URL nor the config names one
+const DEFAULT_PORT: u16 = 22;
+
+/// Milliseconds to wait for the server before giving up
+const TIMEOUT_MS: u32 = 15_000;
+
+/// A logged-in SFTP session
+pub struct Sftp {
+    /// The SSH session (must outlive the SFTP channel)
+    _session: Session,
+
+    /// The SFTP channel files are listed and read through
+    sftp: ssh2::Sftp,
+}
+
+impl Sftp {
+    /// Connects and logs in
+    ///
+    /// Login uses the configured key file, else the configured password,
+    /// else the keys of the running SSH agent.
+    ///
+    /// # Arguments
+    /// * `location` - Server (and user/port, if the URL gives them)
+    /// * `host` - Settings of that server from config.toml
+    pub fn connect(location: &Location, host: &RemoteHost) -> io::Result<Self> {
+        let port = location.port.or(host.port).unwrap_or(DEFAULT_PORT);
+        let user = location
+            .user
+            .clone()
+            .or_else(|| host.user.clone())
+            .unwrap_or_else(super::local_user);
+
+        let tcp = TcpStream::connect((location.host.as_str(), port))?;
+        let mut session = Session::new()?;
+        session.set_timeout(TIMEOUT_MS);
+        session.set_tcp_stream(tcp);
+        session.handshake()?;
+
+        if let Some(identity) = &host.identity {
+            session.userauth_pubkey_file(&user, None, identity, None)?;
+        } else if let Some(password) = &host.password {
+            session.userauth_password(&user, password)?;
+        } else {
+            session.userauth_agent(&user)?;
+        }
+        if !session.authenticated() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{}@{}: login failed", user, location.host),
+            ));
+        }
+
+        let sftp = session.sftp()?;
+        tracing::info!(host = %location.host, port, user = %user, "connected over SFTP");
+        Ok(Self {
+            _session: session,
+            sftp,
+        })
+    }
+}
+
+impl Backend for Sftp {
+    fn list(&mut self, folder: &str) -> io::Result<Vec<RemoteFile>> {
+        let mut files = Vec::new();
+        let mut pending = vec![PathBuf::from(folder)];
+
+        while let Some(dir) = pending.pop() {
+            let entries = match self.sftp.readdir(&dir) {
+                Ok(entries) => entries,
+                // The root itself must be readable; an unreadable subfolder is skipped
+                Err(e) if dir != Path::new(folder) => {
+                    tracing::warn!(path = %dir.display(), error = %e, "skipping unreadable remote folder");
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            for (path, stat) in entries {
+                if stat.is_dir() {
+                    pending.push(path);
+                } else if stat.is_file() {
+                    files.push(RemoteFile {
+                        path: path.to_string_lossy().into_owned(),
+                        size: stat.size.unwrap_or(0),
+                    });
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn size(&mut self, path: &str) -> io::Result<u64> {
+        Ok(self.sftp.stat(Path::new(path))?.size.unwrap_or(0))
+    }
+
+    fn open(&mut self, path: &str) -> io::Result<Box<dyn ReadSeek>> {
+        Ok(Box::new(self.sftp.open(Path::new(path))?))
+    }
+
+    fn write(&mut self, path: &str, data: &[u8]) -> io::Result<()> {
+        self.sftp.create(Path::new(path))?.write_all(data)
+    }
+}
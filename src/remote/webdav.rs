@@ -0,0 +1,234 @@
+// src/remote/webdav.rs
+// WebDAV backend - remote roots on Nextcloud, ownCloud or any WebDAV share:
+// davs://[user@]host[:port]/path (HTTPS) or dav://... (plain HTTP)
+//
+// Folders are listed with PROPFIND (one level per request - Nextcloud refuses
+// "Depth: infinity"), files are read with HTTP range requests so reading
+// the metadata of a book only transfers a few kilobytes (see `http`).
+
+use super::http::{http_error, RangeReader};
+use super::{Backend, Location, ReadSeek, RemoteFile};
+use crate::opds::{percent_decode, percent_encode};
+use crate::settings::RemoteHost;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::io;
+use std::time::Duration;
+
+/// Seconds to wait for the server before giving up
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Properties asked for when listing a folder
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:">
+  <d:prop><d:resourcetype/><d:getcontentlength/></d:prop>
+</d:propfind>"#;
+
+/// A WebDAV share
+pub struct WebDav {
+    /// HTTP client (keeps connections open between requests)
+    agent: ureq::Agent,
+
+    /// "https://host:port", without a path
+    base: String,
+
+    /// Value of the Authorization header (None = no login)
+    auth: Option<String>,
+}
+
+impl WebDav {
+    /// Prepares requests to a share (nothing is sent yet)
+    ///
+    /// # Arguments
+    /// * `location` - Server (and user/port, if the URL gives them)
+    /// * `host` - Settings of that server from config.toml (user and password;
+    ///   for Nextcloud use an app password)
+    pub fn connect(location: &Location, host: &RemoteHost) -> io::Result<Self> {
+        let secure = matches!(location.scheme.as_str(), "davs" | "webdavs");
+        let port = location
+            .port
+            .or(host.port)
+            .map(|p| format!(":{}", p))
+            .unwrap_or_default();
+        let base = format!(
+            "{}://{}{}",
+            if secure { "https" } else { "http" },
+            location.host,
+            port
+        );
+
+        // Basic authentication, only when a password is configured
+        let user = location.user.clone().or_else(|| host.user.clone());
+        let auth = match (user, &host.password) {
+            (Some(user), Some(password)) => Some(format!(
+                "Basic {}",
+                base64(format!("{}:{}", user, password).as_bytes())
+            )),
+            _ => None,
+        };
+
+        Ok(Self {
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+            base,
+            auth,
+        })
+    }
+
+    /// Full URL of a path on the server
+    fn url(&self, path: &str) -> String {
+        let encoded: Vec<String> = path.split('/').map(percent_encode).collect();
+        format!("{}{}", self.base, encoded.join("/"))
+    }
+
+    /// Starts a request with the login header
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        let request = self.agent.request(method, &self.url(path));
+        match &self.auth {
+            Some(auth) => request.set("Authorization", auth),
+            None => request,
+        }
+    }
+
+    /// Lists one folder (not its subfolders)
+    ///
+    /// # Returns
+    /// (path, is folder, size) of every entry, the folder itself left out
+    fn propfind(&self, folder: &str) -> io::Result<Vec<(String, bool, u64)>> {
+        let body = self
+            .request("PROPFIND", folder)
+            .set("Depth", "1")
+            .set("Content-Type", "application/xml; charset=utf-8")
+            .send_string(PROPFIND_BODY)
+            .map_err(http_error)?
+            .into_string()?;
+
+        let folder = folder.trim_end_matches('/');
+        Ok(parse_multistatus(&body)
+            .into_iter()
+            .filter(|(path, _, _)| path.trim_end_matches('/') != folder)
+            .collect())
+    }
+}
+
+impl Backend for WebDav {
+    fn list(&mut self, folder: &str) -> io::Result<Vec<RemoteFile>> {
+        let mut files = Vec::new();
+        let mut pending = vec![folder.to_string()];
+
+        while let Some(dir) = pending.pop() {
+            let entries = match self.propfind(&dir) {
+                Ok(entries) => entries,
+                // The root itself must be readable; an unreadable subfolder is skipped
+                Err(e) if dir != folder => {
+                    tracing::warn!(path = %dir, error = %e, "skipping unreadable remote folder");
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            for (path, is_folder, size) in entries {
+                if is_folder {
+                    pending.push(path);
+                } else {
+                    files.push(RemoteFile { path, size });
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn size(&mut self, path: &str) -> io::Result<u64> {
+        let response = self.request("HEAD", path).call().map_err(http_error)?;
+        response
+            .header("Content-Length")
+            .and_then(|length| length.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "server sent no file size"))
+    }
+
+    fn open(&mut self, path: &str) -> io::Result<Box<dyn ReadSeek>> {
+        let size = self.size(path)?;
+        let request = self.request("GET", path);
+        Ok(Box::new(RangeReader::new(Box::new(move || request.clone()), size)))
+    }
+
+    fn write(&mut self, path: &str, data: &[u8]) -> io::Result<()> {
+        self.request("PUT", path).send_bytes(data).map_err(http_error)?;
+        Ok(())
+    }
+}
+
+/// Reads the entries of a PROPFIND answer
+///
+/// # Returns
+/// (decoded path, is folder, size) of every `<response>`
+fn parse_multistatus(xml: &str) -> Vec<(String, bool, u64)> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+
+    let mut entries = Vec::new();
+    // The response being read: href, collection flag, content length
+    let (mut href, mut is_folder, mut size) = (String::new(), false, 0);
+    // Name of the element whose text we're reading
+    let mut current = Vec::new();
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                current = e.local_name().as_ref().to_vec();
+                if current == b"collection" {
+                    is_folder = true;
+                }
+            }
+            Ok(Event::Empty(e)) if e.local_name().as_ref() == b"collection" => is_folder = true,
+            Ok(Event::Text(text)) => {
+                let text = text.unescape().map(|t| t.to_string()).unwrap_or_default();
+                match current.as_slice() {
+                    b"href" => href = text,
+                    b"getcontentlength" => size = text.parse().unwrap_or(0),
+                    _ => {}
+                }
+            }
+            Ok(Event::End(e)) => {
+                if e.local_name().as_ref() == b"response" && !href.is_empty() {
+                    entries.push((href_path(&href), is_folder, size));
+                    (href, is_folder, size) = (String::new(), false, 0);
+                }
+                current.clear();
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+
+    entries
+}
+
+/// Turns an href (a path or a full URL, percent-encoded) into a plain path
+fn href_path(href: &str) -> String {
+    let path = match href.split_once("://") {
+        // "https://host/dav/Books" -> "/dav/Books"
+        Some((_, rest)) => rest.find('/').map(|slash| &rest[slash..]).unwrap_or("/"),
+        None => href,
+    };
+    percent_decode(path).unwrap_or_else(|| path.to_string())
+}
+
+/// Encodes bytes as standard base64 (for the Authorization header)
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for group in bytes.chunks(3) {
+        // Three bytes -> four 6-bit digits, '=' for the missing ones
+        let n = group.iter().enumerate().fold(0u32, |n, (i, &b)| n | ((b as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= group.len() {
+                out.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
@@ -1,59 +1,314 @@
 // src/scanner.rs
 // Recursively scans directories for EPUB files
 
-use crate::book::Book;
-use std::path::Path;
+use crate::book::{Book, Metadata};
+use crate::epub::read_metadata;
+use crate::profile::FolderSettings;
+use crate::readability::Readability;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use walkdir::WalkDir; // External crate for recursive directory traversal
 
-/// Scans a directory (recursively) for EPUB files and returns them as Book objects
+/// Scans a scan root (recursively) using its folder settings
 ///
-/// # Generic Parameters
-/// * `P: AsRef<Path>` - Accepts any type that can be converted to a Path reference
-///   This allows passing String, &str, PathBuf, &Path, etc.
+/// Files and folders matching an exclude pattern are skipped, and the
+/// folder's default tags are added to every book found.
 ///
 /// # Arguments
-/// * `path` - The directory path to scan
+/// * `root` - The directory to scan
+/// * `settings` - Settings of that folder
 ///
 /// # Returns
 /// A Vec<Book> containing all found EPUB files, or empty Vec if none found
-pub fn scan_epubs<P: AsRef<Path>>(path: P) -> Vec<Book> {
-    // Convert the generic path type to a Path reference
-    let path_ref = path.as_ref();
+#[cfg_attr(feature = "profiling", tracing::instrument(level = "trace", name = "scan", skip_all))]
+pub fn scan_folder(root: &Path, settings: &FolderSettings) -> Vec<Book> {
+    // Roots on a server (sftp://..., davs://...) are listed over the network
+    if crate::remote::is_remote(root) {
+        return crate::remote::scan(root, settings).unwrap_or_else(|e| {
+            tracing::warn!(path = %root.display(), error = %e, "cannot scan remote folder");
+            Vec::new()
+        });
+    }
+
+    // Transform each file into a Book struct
+    epub_files(root, settings)
+        .iter()
+        .map(|path| book_from_file(path, settings))
+        .collect()
+}
 
+/// Scans a scan root (recursively) without reading its EPUBs: each book
+/// is a placeholder (its file name, sidecar and default tags) whose
+/// metadata the parse queue reads later (see parse_queue.rs)
+///
+/// Roots on a server are scanned as `scan_folder` does, their metadata
+/// read over the connection.
+///
+/// # Returns
+/// A Vec<Book> containing all found EPUB files, or empty Vec if none found
+#[cfg_attr(feature = "profiling", tracing::instrument(level = "trace", name = "scan", skip_all))]
+pub fn scan_placeholders(root: &Path, settings: &FolderSettings) -> Vec<Book> {
+    if crate::remote::is_remote(root) {
+        return scan_folder(root, settings);
+    }
+    epub_files(root, settings)
+        .iter()
+        .map(|path| book_with_metadata(path, Metadata::default(), settings))
+        .collect()
+}
+
+/// Finds the EPUB files of a local scan root (recursively), skipping the
+/// excluded ones - the walk of `scan_folder`, before anything is read
+///
+/// # Returns
+/// The files, or empty Vec if `root` isn't a directory
+#[cfg_attr(feature = "profiling", tracing::instrument(level = "trace", name = "walk", skip_all))]
+pub fn epub_files(root: &Path, settings: &FolderSettings) -> Vec<PathBuf> {
     // Validate the path exists and is a directory
     // Return empty vector if invalid
-    if !path_ref.exists() || !path_ref.is_dir() {
+    if !root.exists() || !root.is_dir() {
+        tracing::warn!(path = %root.display(), "scan path is not a directory");
         return Vec::new();
     }
 
     // Use the iterator pattern to process directory entries
     // This is a functional programming approach: transform data through a pipeline
-    WalkDir::new(path_ref) // Start recursive directory walker
+    WalkDir::new(root) // Start recursive directory walker
         .into_iter() // Convert to iterator
-        // Filter out errors, keep only Ok entries
+        // Don't even descend into excluded folders
+        .filter_entry(|entry| !is_excluded(root, entry.path(), &settings.exclude))
+        // Filter out errors (logging them), keep only Ok entries
         // filter_map combines filter + map in one step
-        .filter_map(|entry| entry.ok())
-        // Keep only entries with .epub extension
-        .filter(|entry| {
-            entry
-                .path() // Get the file path
-                .extension() // Get file extension (returns Option<&OsStr>)
-                .and_then(|ext| ext.to_str()) // Convert OsStr to &str (may fail)
-                .map(|ext| ext.eq_ignore_ascii_case("epub")) // Check if extension is "epub"
-                .unwrap_or(false) // If any step failed (no extension, can't convert), return false
-        })
-        // Transform each entry into a Book struct
-        .map(|entry| {
-            // Extract the filename to use as book name
-            let name = entry
-                .path() // Get the full path
-                .file_name() // Get just the filename (returns Option<&OsStr>)
-                .and_then(|n| n.to_str()) // Convert to &str
-                .unwrap_or("Unknown") // Default to "Unknown" if conversion fails
-                .to_string(); // Convert &str to owned String
-
-            // Create a new Book with the extracted name and full path
-            Book::new(name, entry.path().to_path_buf())
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                tracing::warn!(error = %e, "skipping unreadable directory entry");
+                None
+            }
         })
-        .collect() // Collect all Book objects into a Vec<Book>
+        // Keep only entries with .epub extension
+        .filter(|entry| is_epub(entry.path()))
+        .map(walkdir::DirEntry::into_path)
+        .collect() // Collect all paths into a Vec<PathBuf>
+}
+
+/// Creates a Book for one EPUB file, reading its metadata (and its sidecar
+/// when the folder has sidecars enabled), guessing its language from its text
+/// and measuring its readability
+///
+/// # Arguments
+/// * `path` - The EPUB file
+/// * `settings` - Settings of the folder the book belongs to (for default tags)
+#[cfg_attr(feature = "profiling", tracing::instrument(level = "trace", name = "parse", skip_all))]
+pub fn book_from_file(path: &Path, settings: &FolderSettings) -> Book {
+    // Read title/authors/etc. from the EPUB package
+    // A broken EPUB still shows up in the library, just without metadata
+    let meta = read_metadata(path).unwrap_or_else(|e| {
+        tracing::warn!(path = %path.display(), error = %e, "cannot read EPUB metadata");
+        Default::default()
+    });
+    let mut book = book_with_metadata(path, meta, settings);
+    // Its text tells which language it's really in (see language.rs) and how hard it reads
+    match crate::language::sample(path) {
+        Ok(text) => {
+            book.detected_language = crate::language::detect(&text).map(str::to_string);
+            book.readability = Readability::of(&text, book.language());
+        }
+        Err(e) => tracing::debug!(path = %path.display(), error = %e, "cannot sample the text of the book"),
+    }
+    book
+}
+
+/// Creates a Book for one EPUB file whose metadata was already read
+/// (local files go through `book_from_file`)
+///
+/// # Arguments
+/// * `path` - The EPUB file
+/// * `meta` - Its package metadata
+/// * `settings` - Settings of the folder the book belongs to (for default tags)
+pub fn book_with_metadata(path: &Path, meta: Metadata, settings: &FolderSettings) -> Book {
+    // Extract the filename to use as book name
+    let name = path
+        .file_name() // Get just the filename (returns Option<&OsStr>)
+        .and_then(|n| n.to_str()) // Convert to &str
+        .unwrap_or("Unknown") // Default to "Unknown" if conversion fails
+        .to_string(); // Convert &str to owned String
+
+    // Create a new Book with the extracted name and full path
+    let mut book = Book::new(name, path.to_path_buf());
+    book.meta = meta;
+
+    // User data travelling with the file, if the folder uses sidecars
+    // (remote folders have none - nothing is written to the server)
+    if settings.sidecars && !crate::remote::is_remote(path) {
+        if let Some(user) = crate::sidecar::read(path) {
+            book.user = user;
+        }
+    }
+
+    // Tags every book of this folder gets
+    for tag in &settings.default_tags {
+        if !book.user.tags.contains(tag) {
+            book.user.tags.push(tag.clone());
+        }
+    }
+
+    book
+}
+
+/// Whether a path has the .epub extension (any case)
+pub fn is_epub(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("epub"))
+        .unwrap_or(false)
+}
+
+/// Merges the result of rescanning one root into the library
+///
+/// - Books outside `root` are untouched
+/// - Books under `root` that were found again keep their data (and gain the default tags)
+/// - Books under `root` that were not found again are removed, unless a new
+///   file has the same content hash - then the book moves to that file and
+///   keeps its tags, rating and status
+/// - Newly found books are appended
+///
+/// # Arguments
+/// * `books` - The books of the library (modified in place)
+/// * `root` - The scan root that was rescanned
+/// * `scanned` - Result of `scan_folder(root, ...)`
+/// * `default_tags` - The folder's default tags
+#[cfg_attr(feature = "profiling", tracing::instrument(level = "trace", name = "merge", skip_all))]
+pub fn merge_rescan(books: &mut Vec<Book>, root: &Path, scanned: Vec<Book>, default_tags: &[String]) {
+    // Take out the books of this root whose file wasn't found again
+    let found: HashSet<&Path> = scanned.iter().map(|book| book.path.as_path()).collect();
+    let (gone, kept): (Vec<Book>, Vec<Book>) = std::mem::take(books)
+        .into_iter()
+        .partition(|book| book.path.starts_with(root) && !found.contains(book.path.as_path()));
+    *books = kept;
+
+    // Only books with a known hash can be recognized at a new path
+    let mut by_hash: HashMap<String, Vec<Book>> = HashMap::new();
+    for book in gone {
+        if let Some(hash) = book.hash.clone() {
+            by_hash.entry(hash).or_default().push(book);
+        }
+    }
+
+    // Where each path is in the library, looked up rather than searched for
+    let mut positions: HashMap<PathBuf, usize> = books.iter().enumerate().map(|(i, book)| (book.path.clone(), i)).collect();
+    for new_book in scanned {
+        match positions.get(&new_book.path).map(|&i| &mut books[i]) {
+            Some(existing) => {
+                // A file first seen mid-copy may have had no readable metadata yet
+                if existing.is_placeholder() {
+                    existing.meta = new_book.meta;
+                    existing.user.authors = new_book.user.authors;
+                }
+                // The file may have changed since its text was sampled
+                if new_book.detected_language.is_some() {
+                    existing.detected_language = new_book.detected_language;
+                }
+                if new_book.readability.is_some() {
+                    existing.readability = new_book.readability;
+                }
+                for tag in default_tags {
+                    if !existing.user.tags.contains(tag) {
+                        existing.user.tags.push(tag.clone());
+                    }
+                }
+            }
+            None => {
+                positions.insert(new_book.path.clone(), books.len());
+                match take_moved(&mut by_hash, &new_book) {
+                    // Same content as a vanished book: the file was moved or renamed
+                    Some(mut moved) => {
+                        tracing::info!(from = %moved.path.display(), to = %new_book.path.display(), "moved book found by content hash");
+                        moved.path = new_book.path;
+                        moved.name = new_book.name;
+                        books.push(moved);
+                    }
+                    None => books.push(new_book),
+                }
+            }
+        }
+    }
+}
+
+/// Takes the vanished book with the same content as a newly found file, if any
+///
+/// The new file is only hashed when there are vanished books to compare with.
+///
+/// # Arguments
+/// * `gone` - The vanished books, by hash
+/// * `new_book` - The newly found file
+fn take_moved(gone: &mut HashMap<String, Vec<Book>>, new_book: &Book) -> Option<Book> {
+    if gone.is_empty() {
+        return None;
+    }
+    let hash = crate::hash::content_hash(&new_book.path).ok()?;
+    let same = gone.get_mut(&hash)?;
+    let moved = (!same.is_empty()).then(|| same.remove(0));
+    if same.is_empty() {
+        gone.remove(&hash);
+    }
+    moved
+}
+
+/// Checks whether a path matches one of the exclude patterns
+///
+/// Patterns without '/' are matched against the file or folder name,
+/// patterns with '/' against the path relative to the scan root.
+pub fn is_excluded(root: &Path, path: &Path, patterns: &[String]) -> bool {
+    if patterns.is_empty() || path == root {
+        return false;
+    }
+
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let relative = path
+        .strip_prefix(root)
+        .map(|p| p.to_string_lossy().replace('\\', "/"))
+        .unwrap_or_default();
+
+    patterns.iter().any(|pattern| {
+        if pattern.contains('/') {
+            wildcard_match(pattern.trim_matches('/'), &relative)
+        } else {
+            wildcard_match(pattern, &name)
+        }
+    })
+}
+
+/// Matches text against a wildcard pattern (`*` = any run of characters, `?` = one character)
+/// Case-insensitive, since file systems often are too
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    // Classic greedy matching with backtracking to the last '*'
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            // Let the last '*' swallow one more character
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    // Only trailing '*' may remain
+    pattern[p..].iter().all(|&c| c == '*')
 }
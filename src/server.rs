@@ -0,0 +1,396 @@
+// src/server.rs
+// Small HTTP server publishing the library - a web page for browsers,
+// OPDS feeds for ereader apps, a JSON API for scripts (see api.rs),
+// Prometheus metrics (see metrics.rs), plus book downloads
+//
+// The web page is a companion for the rest of the household: it searches
+// the library (same filter expressions as the TUI), shows the covers, offers
+// the downloads and lets readers set the reading status of a book. Status
+// changes are saved to the library right away; everything else is still
+// managed from the TUI.
+
+use crate::book::{Book, ReadingStatus};
+use crate::filter::Filter;
+use crate::metrics::Metrics;
+use crate::opds::{escape, percent_decode, percent_encode, Catalog};
+use crate::profile::Profile;
+use crate::sort;
+use crate::webhooks::Webhooks;
+use std::fs::File;
+use std::io::{self, Read};
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Port of `funkhunt serve` when no `--port` is given
+pub const DEFAULT_PORT: u16 = 8080;
+
+/// Path prefix of the OPDS feeds
+const OPDS_BASE: &str = "/opds";
+
+/// Path prefix of book downloads (`/download/{index}`)
+const DOWNLOAD_BASE: &str = "/download";
+
+/// Path prefix of cover images (`/cover/{index}`)
+const COVER_BASE: &str = "/cover";
+
+/// Path of the Prometheus metrics
+const METRICS_PATH: &str = "/metrics";
+
+/// Path prefix of reading status changes (`POST /status/{index}`)
+const STATUS_BASE: &str = "/status";
+
+/// Serves the library over HTTP until the process is stopped (Ctrl+C)
+///
+/// # Arguments
+/// * `port` - TCP port to listen on (all interfaces)
+/// * `profile` - The library profile being served (status changes are saved to it)
+/// * `books` - Books to publish
+/// * `webhooks` - Told about the books scanned or finished from the web page and the API
+pub fn serve(port: u16, profile: &Profile, mut books: Vec<Book>, mut webhooks: Webhooks) -> io::Result<()> {
+    let mut server = LibraryServer::start(port, &books)?;
+    let library = profile.name.as_str();
+
+    println!("Serving {} books from library '{}'", books.len(), library);
+    print_urls(port);
+    println!("Press Ctrl+C to stop.");
+
+    tracing::info!(port, library, books = books.len(), "server started");
+
+    loop {
+        if server.answer(profile, &mut books, Duration::from_secs(1))? {
+            webhooks.check(&books);
+        }
+    }
+}
+
+/// Prints where the web page, OPDS catalog, API and metrics are served
+pub fn print_urls(port: u16) {
+    println!("Web page:     http://<this-host>:{}/", port);
+    println!("OPDS catalog: http://<this-host>:{}{}", port, OPDS_BASE);
+    println!("JSON API:     http://<this-host>:{}{}", port, crate::api::API_BASE);
+    println!("Metrics:      http://<this-host>:{}{}", port, METRICS_PATH);
+}
+
+/// The HTTP server of a library, answering requests when asked to - so it
+/// can share a loop with other work (see daemon.rs)
+pub struct LibraryServer {
+    server: Server,
+
+    /// What was counted since the server started
+    metrics: Metrics,
+}
+
+impl LibraryServer {
+    /// Starts listening
+    ///
+    /// # Arguments
+    /// * `port` - TCP port to listen on (all interfaces)
+    /// * `books` - The books of the library (for the metrics)
+    pub fn start(port: u16, books: &[Book]) -> io::Result<Self> {
+        Ok(Self {
+            server: Server::http(("0.0.0.0", port)).map_err(io::Error::other)?,
+            metrics: Metrics::new(books),
+        })
+    }
+
+    /// Answers the next request, waiting for one at most `wait`
+    ///
+    /// A failed response doesn't stop the server: it's logged and counted.
+    ///
+    /// # Arguments
+    /// * `profile` - The library profile being served (status changes are saved to it)
+    /// * `books` - The books of the library
+    /// * `wait` - How long to wait for a request
+    ///
+    /// # Returns
+    /// Whether a request was answered
+    pub fn answer(&mut self, profile: &Profile, books: &mut Vec<Book>, wait: Duration) -> io::Result<bool> {
+        let Some(request) = self.server.recv_timeout(wait)? else {
+            return Ok(false);
+        };
+        tracing::debug!(method = %request.method(), url = request.url(), "request");
+        self.metrics.request();
+        if let Err(e) = handle_request(request, profile, books, &mut self.metrics) {
+            tracing::warn!(error = %e, "failed to send response");
+            self.metrics.failed_response();
+        }
+        Ok(true)
+    }
+
+    /// Counts a scan made outside of the server (on /metrics)
+    ///
+    /// # Arguments
+    /// * `took` - Duration of the scan
+    /// * `books` - The library after the scan
+    pub fn scanned(&mut self, took: Duration, books: &[Book]) {
+        self.metrics.scan(took, books);
+    }
+}
+
+/// Routes a single request to the API, the metrics, the web page, the
+/// matching feed, a cover, a status change or a download
+fn handle_request(mut request: Request, profile: &Profile, books: &mut Vec<Book>, metrics: &mut Metrics) -> io::Result<()> {
+    // Split "/opds/titles?page=2" into path and query
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((url.as_str(), ""));
+    let page = query_param(query, "page")
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(1);
+
+    // JSON API
+    if path == crate::api::API_BASE || path.starts_with(&format!("{}/", crate::api::API_BASE)) {
+        return crate::api::respond(request, path, query, profile, books, metrics);
+    }
+
+    // Prometheus metrics
+    if path == METRICS_PATH {
+        let response = Response::from_string(metrics.render(&profile.name, books))
+            .with_header(header("Content-Type", "text/plain; version=0.0.4")?);
+        return request.respond(response);
+    }
+
+    // Reading status set from the web page, then back to where it was sent from
+    if let Some(index) = path.strip_prefix(&format!("{}/", STATUS_BASE)).and_then(|i| i.parse::<usize>().ok()) {
+        if *request.method() != Method::Post || index >= books.len() {
+            metrics.not_found();
+            return request.respond(Response::from_string("Not found").with_status_code(404));
+        }
+        let mut form = String::new();
+        request.as_reader().take(4096).read_to_string(&mut form)?;
+        let status = form_param(&form, "status").unwrap_or_default();
+        set_status(profile, books, index, ReadingStatus::ALL.into_iter().find(|s| s.key() == status));
+        let back = form_param(&form, "back").filter(|back| is_local(back)).unwrap_or_else(|| "/".to_string());
+        let response = Response::empty(303).with_header(header("Location", &ascii_filename(&back))?);
+        return request.respond(response);
+    }
+
+    // Web page for browsers
+    if path == "/" {
+        let response = Response::from_string(index_page(&profile.name, books, query))
+            .with_header(header("Content-Type", "text/html;charset=utf-8")?);
+        return request.respond(response);
+    }
+
+    // OPDS feeds
+    let catalog = Catalog::new(&profile.name, books, OPDS_BASE, DOWNLOAD_BASE);
+    let feed = match path.trim_end_matches('/') {
+        OPDS_BASE => Some(catalog.root()),
+        p if p == format!("{}/titles", OPDS_BASE) => Some(catalog.titles(page)),
+        p if p == format!("{}/authors", OPDS_BASE) => Some(catalog.authors(page)),
+        p => p
+            .strip_prefix(&format!("{}/authors/", OPDS_BASE))
+            .and_then(percent_decode)
+            .and_then(|author| catalog.author(&author, page)),
+    };
+    if let Some(xml) = feed {
+        let response = Response::from_string(xml)
+            .with_header(header("Content-Type", "application/atom+xml;charset=utf-8")?);
+        return request.respond(response);
+    }
+
+    // Covers, read from the EPUB each time (browsers cache them)
+    let cover = path
+        .strip_prefix(&format!("{}/", COVER_BASE))
+        .and_then(|id| id.parse::<usize>().ok())
+        .and_then(|index| books.get(index));
+    if let Some(book) = cover {
+        match crate::epub::read_cover(&book.path) {
+            Ok(Some((image, media_type))) => {
+                // The media type comes from the package: only a plain image type is sent as is
+                let image_type = media_type.starts_with("image/") && media_type.bytes().all(|b| b.is_ascii_graphic());
+                let content_type = if image_type { media_type.as_str() } else { "application/octet-stream" };
+                let response = Response::from_data(image)
+                    .with_header(header("Content-Type", content_type)?)
+                    .with_header(header("Cache-Control", "max-age=86400")?);
+                return request.respond(response);
+            }
+            Ok(None) => {}
+            Err(e) => tracing::debug!(path = %book.path.display(), error = %e, "cannot read cover"),
+        }
+    }
+
+    // Book downloads
+    let book = path
+        .strip_prefix(&format!("{}/", DOWNLOAD_BASE))
+        .and_then(|id| id.parse::<usize>().ok())
+        .and_then(|index| books.get(index));
+    if let Some(book) = book {
+        match File::open(&book.path) {
+            Ok(file) => {
+                metrics.download(file.metadata().map(|m| m.len()).unwrap_or(0));
+                let disposition = format!("attachment; filename=\"{}\"", ascii_filename(&book.name));
+                let response = Response::from_file(file)
+                    .with_header(header("Content-Type", "application/epub+zip")?)
+                    .with_header(header("Content-Disposition", &disposition)?);
+                return request.respond(response);
+            }
+            Err(e) => {
+                tracing::warn!(path = %book.path.display(), error = %e, "cannot open book for download");
+            }
+        }
+    }
+
+    // Anything else
+    metrics.not_found();
+    request.respond(Response::from_string("Not found").with_status_code(404))
+}
+
+/// Sets the reading status of a book and saves the library (and the
+/// book's sidecar, when its folder has them)
+fn set_status(profile: &Profile, books: &mut [Book], index: usize, status: Option<ReadingStatus>) {
+    if books[index].user.status == status {
+        return;
+    }
+    books[index].user.status = status;
+    tracing::info!(book = %books[index].name, status = status.map(|s| s.key()).unwrap_or("none"), "status set from the web page");
+
+    crate::sidecar::write_all(&books[index..=index], &profile.settings);
+    if let Err(e) = crate::database::save(&profile.database_path(), books) {
+        tracing::error!(library = %profile.name, error = %e, "cannot save library database");
+    }
+}
+
+/// Builds the web page: a search box, the status filters, then a card per
+/// matching book (cover, title, authors, status, download link), by title
+///
+/// # Arguments
+/// * `library` - Name of the library
+/// * `books` - The books of the library (their index is their id in links)
+/// * `query` - Query string of the page: `q` (a filter expression) and `status`
+fn index_page(library: &str, books: &[Book], query: &str) -> String {
+    let search = query_param(query, "q").and_then(form_decode).unwrap_or_default();
+    let status = query_param(query, "status").unwrap_or("");
+    let (filter, error) = match Filter::parse(&search) {
+        Ok(filter) => (filter, None),
+        Err(e) => (Filter::default(), Some(e)),
+    };
+
+    // Archived books stay hidden unless the search asks for them, as in the TUI
+    let mut order: Vec<usize> = (0..books.len())
+        .filter(|&i| filter.mentions_archived() || !books[i].user.archived)
+        .filter(|&i| filter.matches(&books[i]))
+        .filter(|&i| status.is_empty() || books[i].user.status.map(|s| s.key()) == Some(status))
+        .collect();
+    order.sort_by_cached_key(|&i| sort::title_key(books[i].display_title()));
+
+    // Status changes come back to this very page
+    let back = format!("/?{}", query);
+    let cards: String = order
+        .iter()
+        .map(|&i| {
+            let book = &books[i];
+            let size = std::fs::metadata(&book.path)
+                .map(|m| format!(" ({} KB)", m.len() / 1024))
+                .unwrap_or_default();
+            let options: String = std::iter::once(("", "No status"))
+                .chain(ReadingStatus::ALL.iter().map(|s| (s.key(), s.label())))
+                .map(|(key, label)| {
+                    let selected = if book.user.status.map(|s| s.key()).unwrap_or("") == key { " selected" } else { "" };
+                    format!("<option value=\"{}\"{}>{}</option>", key, selected, label)
+                })
+                .collect();
+            format!(
+                "<div class=\"book\"><a href=\"{download}/{i}\"><img src=\"{cover}/{i}\" alt=\"\" loading=\"lazy\" \
+                 onerror=\"this.style.visibility='hidden'\"></a>\n\
+                 <div class=\"title\">{title}</div><div class=\"authors\">{authors}</div>\n\
+                 <form method=\"post\" action=\"{status}/{i}\"><input type=\"hidden\" name=\"back\" value=\"{back}\">\
+                 <select name=\"status\" onchange=\"this.form.submit()\">{options}</select>\
+                 <noscript><button>Set</button></noscript></form>\n\
+                 <a href=\"{download}/{i}\">Download{size}</a></div>\n",
+                download = DOWNLOAD_BASE,
+                cover = COVER_BASE,
+                status = STATUS_BASE,
+                i = i,
+                title = escape(book.display_title()),
+                authors = escape(&book.display_authors()),
+                back = escape(&back),
+                options = options,
+                size = size
+            )
+        })
+        .collect();
+
+    // "All" plus one link per status, keeping the search
+    let q = percent_encode(&search);
+    let statuses: String = std::iter::once(("", "All"))
+        .chain(ReadingStatus::ALL.iter().map(|s| (s.key(), s.label())))
+        .map(|(key, label)| {
+            if key == status {
+                format!("<b>{}</b> ", label)
+            } else {
+                format!("<a href=\"/?q={}&amp;status={}\">{}</a> ", q, key, label)
+            }
+        })
+        .collect();
+    let error = error
+        .map(|e| format!("<p class=\"error\">{}</p>\n", escape(&e)))
+        .unwrap_or_default();
+
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{library} - FunkHunt</title>\n\
+         <style>body{{font-family:sans-serif;margin:1em}}input[type=search]{{width:20em;max-width:100%}}\
+         .books{{display:grid;grid-template-columns:repeat(auto-fill,minmax(10em,1fr));gap:1em}}\
+         .book img{{width:100%;aspect-ratio:2/3;object-fit:cover;background:#eee}}\
+         .title{{font-weight:bold}}.authors{{color:#555}}.error{{color:#b00}}</style>\n\
+         </head><body>\n<h1>{library}</h1>\n\
+         <form><input type=\"search\" name=\"q\" value=\"{search}\" placeholder=\"Search, e.g. tolkien or tag:fantasy\">\
+         <input type=\"hidden\" name=\"status\" value=\"{status}\"> <button>Search</button></form>\n\
+         <p>{statuses}</p>\n{error}<p>{shown} of {count} books - <a href=\"{opds}\">OPDS catalog</a></p>\n\
+         <div class=\"books\">\n{cards}</div>\n</body></html>\n",
+        library = escape(library),
+        search = escape(&search),
+        status = escape(status),
+        statuses = statuses,
+        error = error,
+        shown = order.len(),
+        count = books.len(),
+        opds = OPDS_BASE,
+        cards = cards
+    )
+}
+
+/// Finds the value of a form field (`application/x-www-form-urlencoded`),
+/// decoded
+fn form_param(form: &str, name: &str) -> Option<String> {
+    query_param(form.trim(), name).and_then(form_decode)
+}
+
+/// Decodes a form or query value: `+` is a space, then percent-decoding
+pub fn form_decode(value: &str) -> Option<String> {
+    percent_decode(&value.replace('+', " "))
+}
+
+/// Finds the value of a query-string parameter ("page=2&x=y")
+pub fn query_param<'q>(query: &'q str, name: &str) -> Option<&'q str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// Builds an HTTP header
+///
+/// # Errors
+/// When the name or the value isn't ASCII (e.g. a media type read from a
+/// book's package)
+pub fn header(name: &str, value: &str) -> io::Result<Header> {
+    Header::from_bytes(name.as_bytes(), value.as_bytes())
+        .map_err(|()| io::Error::new(io::ErrorKind::InvalidData, format!("invalid {} header", name)))
+}
+
+/// Whether a page to go back to is on this server: a path, not "//host/..."
+/// (which browsers take for another site) nor one with backslashes (which
+/// some read as slashes)
+fn is_local(back: &str) -> bool {
+    back.starts_with('/') && !back.starts_with("//") && !back.contains('\\')
+}
+
+/// Makes a filename safe for a Content-Disposition header (ASCII, no quotes)
+pub fn ascii_filename(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii() && !c.is_ascii_control() && c != '"' { c } else { '_' })
+        .collect()
+}
@@ -0,0 +1,103 @@
+// src/session.rs
+// Session state (where the user was when the app closed) and periodic autosave
+// so a crash or SSH disconnect loses at most a few seconds of work
+
+use crate::book::ReadingStatus;
+use crate::profile::{Profile, Shelf};
+use crate::sort::SortOrder;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// File (inside the profile directory) holding the session state
+const SESSION_FILE: &str = "session.json";
+
+/// What the user was looking at, restored on the next start
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// Path of the selected book (a path survives rescans, an index doesn't)
+    pub selected: Option<PathBuf>,
+
+    /// Collection or smart collection shown in the book list
+    pub shelf: Shelf,
+
+    /// Filter typed at the '/' prompt
+    pub filter: String,
+
+    /// Whether only starred books were shown
+    pub starred_only: bool,
+
+    /// Reading status the list was filtered by (None = any)
+    pub status_filter: Option<ReadingStatus>,
+
+    /// Order of the book list
+    pub sort: SortOrder,
+
+    /// Whether archived books were shown
+    pub show_archived: bool,
+}
+
+impl Session {
+    /// Loads the session of a profile (empty session if none was saved)
+    pub fn load(profile: &Profile) -> Self {
+        std::fs::read_to_string(profile.dir.join(SESSION_FILE))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    /// Saves the session of a profile
+    ///
+    /// Written to a temporary file and renamed, like the database,
+    /// because autosave may be interrupted at any moment.
+    pub fn save(&self, profile: &Profile) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        let path = profile.dir.join(SESSION_FILE);
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, json)?;
+        std::fs::rename(&tmp_path, path)
+    }
+}
+
+/// Decides when the periodic autosave is due
+pub struct Autosave {
+    /// Time between saves (None = periodic autosave disabled)
+    interval: Option<Duration>,
+
+    /// When the last save happened
+    last_save: Instant,
+}
+
+impl Autosave {
+    /// Creates an autosave timer
+    ///
+    /// # Arguments
+    /// * `interval_secs` - Seconds between saves, 0 disables periodic saving
+    pub fn new(interval_secs: u64) -> Self {
+        let mut autosave = Self {
+            interval: None,
+            last_save: Instant::now(),
+        };
+        autosave.set_interval(interval_secs);
+        autosave
+    }
+
+    /// Changes the interval (e.g. after config.toml was reloaded)
+    pub fn set_interval(&mut self, interval_secs: u64) {
+        self.interval = (interval_secs > 0).then(|| Duration::from_secs(interval_secs));
+    }
+
+    /// Whether the interval has passed since the last save
+    pub fn due(&self) -> bool {
+        self.interval
+            .map(|interval| self.last_save.elapsed() >= interval)
+            .unwrap_or(false)
+    }
+
+    /// Restarts the interval (call after every save, periodic or not)
+    pub fn saved(&mut self) {
+        self.last_save = Instant::now();
+    }
+}
@@ -0,0 +1,913 @@
+// src/settings.rs
+// User configuration file (config.toml) - theme colors, key bindings and book viewer
+//
+// Example:
+// ```toml
+// [theme]
+// header = "cyan"
+// selected = "#ffaa00"
+//
+// [keys]
+// quit = "q"
+// add_folder = "a"
+//
+// [viewer]
+// command = "foliate"
+//
+// [autosave]
+// interval = 30
+//
+// [metadata]
+// provider = "auto"
+// google_books_api_key = "..."
+//
+// [organize]
+// template = "{author}/{series}/{title}.epub"
+// rename_template = "{author} - {title}"
+//
+// [inbox]
+// folder = "/home/me/Downloads"
+// organize = true
+//
+// [peers]
+// enabled = true
+// name = "Living room"
+// require_pairing = true
+//
+// [share]
+// port = 8574
+// minutes = 15
+//
+// [devices]
+// mounts = ["/media/me/POCKETBOOK"]
+// kindle_format = "azw3"
+//
+// [email]
+// host = "smtp.example.com"
+// user = "me@example.com"
+// from = "me@example.com"
+// to = "me_kindle@kindle.com"
+//
+// [calibre]
+// url = "http://nas.local:8080"
+// user = "me"
+//
+// [feeds]
+// urls = ["https://standardebooks.org/feeds/rss/new-releases"]
+//
+// [webhooks]
+// urls = ["http://homeassistant.local:8123/api/webhook/funkhunt"]
+// events = ["added", "finished", "received"]
+//
+// [dictionary]
+// paths = ["/usr/share/dictd", "/home/me/dictionaries"]
+//
+// [notes]
+// folder = "/home/me/Notes/books"
+// format = "org"
+//
+// [reader]
+// images = "kitty"
+//
+// [extract]
+// folder = "/home/me/Texts"
+// format = "markdown"
+//
+// [search]
+// transliterate = true
+//
+// [sort]
+// ignore_articles = true
+// articles = ["the", "a", "an", "le", "la", "les", "l'"]
+//
+// [audio]
+// player = "mpv --volume=80"
+//
+// [remote."nas.local"]
+// user = "me"
+// identity = "/home/me/.ssh/id_ed25519"
+//
+// [remote."my-books"]
+// endpoint = "http://nas.local:9000"
+// access_key = "..."
+// secret_key = "..."
+// ```
+//
+// Any value can be overridden with an environment variable named
+// FUNKHUNT_<SECTION>_<KEY>, e.g. FUNKHUNT_THEME_HEADER=red or FUNKHUNT_VIEWER_COMMAND=foliate
+// (except the [remote."<host>"] tables, whose keys are host names)
+
+use crate::extract::TextFormat;
+use crate::graphics::ImageSetting;
+use crate::notes::NotesFormat;
+use crate::reader::ReaderTheme;
+use ratatui::style::Color;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Name of the configuration file inside the config directory
+const SETTINGS_FILE: &str = "config.toml";
+
+/// Prefix of environment variables that override settings
+const ENV_PREFIX: &str = "FUNKHUNT_";
+
+/// Sections of config.toml that can be overridden from the environment
+const ENV_SECTIONS: [&str; 21] = [
+    "theme", "keys", "viewer", "autosave", "metadata", "organize", "inbox", "peers", "share", "devices", "email",
+    "calibre", "feeds", "webhooks", "dictionary", "notes", "reader", "extract", "search", "sort", "audio",
+];
+
+/// Everything that can be configured in config.toml
+/// Every section is optional - missing values use the defaults
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Colors of the interface
+    pub theme: Theme,
+
+    /// Letter keys for the main actions
+    pub keys: KeyBindings,
+
+    /// How books are opened
+    pub viewer: ViewerSettings,
+
+    /// How often the library is saved while the app runs
+    pub autosave: AutosaveSettings,
+
+    /// Where book metadata is looked up online
+    pub metadata: MetadataSettings,
+
+    /// Where "organize library" moves the files
+    pub organize: OrganizeSettings,
+
+    /// Folder whose new books are imported automatically
+    pub inbox: InboxSettings,
+
+    /// Sharing with other FunkHunt instances on the local network
+    pub peers: PeerSettings,
+
+    /// Sharing a single book with a phone (QR code)
+    pub share: ShareSettings,
+
+    /// E-readers plugged in over USB
+    pub devices: DeviceSettings,
+
+    /// Sending books by email
+    pub email: EmailSettings,
+
+    /// A Calibre content server to browse
+    pub calibre: CalibreSettings,
+
+    /// RSS/Atom feeds of new books
+    pub feeds: FeedSettings,
+
+    /// URLs told about library events
+    pub webhooks: WebhookSettings,
+
+    /// Dictionaries words are looked up in from the reader
+    pub dictionary: DictionarySettings,
+
+    /// Where the reader's highlights are exported
+    pub notes: NotesSettings,
+
+    /// How the built-in reader shows images
+    pub reader: ReaderSettings,
+
+    /// Where the text of books is written
+    pub extract: ExtractSettings,
+
+    /// How searches and filters compare text
+    pub search: SearchSettings,
+
+    /// How titles and authors are ordered
+    pub sort: SortSettings,
+
+    /// What plays the narration of books
+    pub audio: AudioSettings,
+
+    /// Connection details of the servers holding remote scan roots, keyed
+    /// by host name (as written in the root's URL)
+    pub remote: BTreeMap<String, RemoteHost>,
+}
+
+/// Interface colors
+/// Colors are written as names ("cyan", "light-blue") or hex ("#40e0d0")
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    /// Header text
+    pub header: Color,
+    /// Borders of the header
+    pub border: Color,
+    /// Regular text
+    pub text: Color,
+    /// Selected list entry
+    pub selected: Color,
+    /// Secondary text (footer, empty lists)
+    pub muted: Color,
+    /// Highlighted secondary text (paths, titles in popups)
+    pub accent: Color,
+    /// Background behind full-screen popups
+    pub background: Color,
+    /// Background of popup dialogs
+    pub popup_bg: Color,
+    /// Background of the selected entry in popups
+    pub popup_selected_bg: Color,
+}
+
+/// Letter keys for the main actions (arrows, Enter and Esc are fixed)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    /// Quit the application
+    pub quit: char,
+    /// Open the "add folder" file browser
+    pub add_folder: char,
+    /// Open the library profile picker
+    pub switch_library: char,
+    /// Open the log viewer
+    pub show_log: char,
+    /// Open the folder settings screen
+    pub folder_settings: char,
+    /// Open the collection picker (choose which collection is shown)
+    pub collections: char,
+    /// Add the selected book to a collection
+    pub add_to_collection: char,
+    /// Remove the selected book from the shown collection
+    pub remove_from_collection: char,
+    /// Star / unstar the selected book
+    pub star: char,
+    /// Show only starred books (toggle)
+    pub starred_only: char,
+    /// Cycle the reading status of the selected book
+    pub cycle_status: char,
+    /// Cycle the reading status filter (all, To Read, Reading...)
+    pub status_filter: char,
+    /// Cycle the order of the book list
+    pub sort: char,
+    /// Open the filter prompt
+    pub filter: char,
+    /// Archive / unarchive the selected book
+    pub archive: char,
+    /// Show archived books too (toggle)
+    pub show_archived: char,
+    /// Open the author merge screen
+    pub merge_authors: char,
+    /// Show the series of the selected book in reading order (toggle)
+    pub series: char,
+    /// Mark / unmark the selected book for bulk operations
+    pub mark: char,
+    /// Open the bulk edit form for the marked books
+    pub bulk_edit: char,
+    /// Open the metadata form of the selected book (title, authors, series, tags, language)
+    pub edit_metadata: char,
+    /// Undo the last library operation
+    pub undo: char,
+    /// Redo the last undone operation
+    pub redo: char,
+    /// Look the selected book's metadata up online
+    pub fetch_metadata: char,
+    /// Open the library health report
+    pub health: char,
+    /// Show the library's statistics (formats, authors, size, largest files)
+    pub statistics: char,
+    /// List the books that look like duplicates, to compare them
+    pub duplicates: char,
+    /// Preview moving the files into the organize template
+    pub organize: char,
+    /// Preview renaming the marked (or shown) books by the rename template
+    pub rename: char,
+    /// Open the peers screen (other FunkHunt instances on the network)
+    pub peers: char,
+    /// Open the transfer list (downloads and uploads)
+    pub transfers: char,
+    /// Share the selected book with a phone (URL shown as a QR code)
+    pub share: char,
+    /// Open the e-reader list (send books to a Kindle or Kobo)
+    pub devices: char,
+    /// Email the marked books (or the selected one), e.g. to a Kindle
+    pub email: char,
+    /// Convert the marked books (or the selected one) to AZW3, MOBI or PDF
+    pub convert: char,
+    /// Write the text of the marked books (or the selected one) to files
+    pub extract: char,
+    /// Set the cover of the selected book (image file, URL or Open Library)
+    pub cover: char,
+    /// Make the marked books (or the selected one) smaller
+    pub optimize: char,
+    /// List the fonts embedded in the selected book, to strip or subset them
+    pub fonts: char,
+    /// Split the selected omnibus into a book per part
+    pub split: char,
+    /// Merge the marked books into an anthology
+    pub join: char,
+    /// Show the first pages of the selected book instead of its details (toggle)
+    pub preview: char,
+    /// Show the quotes captured from books
+    pub quotes: char,
+    /// Quote sentences of the selected book's description
+    pub quote_description: char,
+    /// Browse the Calibre content server
+    pub calibre: char,
+    /// Open the new books of the subscribed feeds
+    pub feeds: char,
+    /// Read the selected book in the built-in reader
+    pub read: char,
+    /// Search the text of the books (full-text index)
+    pub search_text: char,
+    /// Reopen the book read last in the built-in reader, where reading stopped
+    pub continue_reading: char,
+    /// Listen to the narration of the selected book (play, pause, seek)
+    pub listen: char,
+}
+
+/// Book viewer settings
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ViewerSettings {
+    /// Command used to open books, e.g. "foliate" or "ebook-viewer --detach"
+    /// The book path is appended as the last argument.
+    /// None = the system default application
+    pub command: Option<String>,
+}
+
+/// Autosave settings
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AutosaveSettings {
+    /// Seconds between automatic saves of the library and session (0 = only on exit
+    /// and after big changes like adding a folder)
+    pub interval: u64,
+}
+
+/// Organize settings
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OrganizeSettings {
+    /// Path of a book inside its scan root, with placeholders like {author}
+    /// and {title} (see organize.rs for the full list)
+    pub template: String,
+
+    /// File name for batch renaming (the file stays in its folder)
+    pub rename_template: String,
+}
+
+impl Default for OrganizeSettings {
+    fn default() -> Self {
+        Self {
+            template: "{author}/{series}/{title}.epub".to_string(),
+            rename_template: "{author} - {title}".to_string(),
+        }
+    }
+}
+
+/// Inbox settings: EPUBs appearing in the inbox folder are imported into
+/// the open library
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct InboxSettings {
+    /// The watched folder, e.g. ~/Downloads (None = no inbox)
+    pub folder: Option<PathBuf>,
+
+    /// Library folder the books are moved to (None = the first scan root)
+    pub move_to: Option<PathBuf>,
+
+    /// Move the files out of the inbox (false = import them where they are)
+    pub move_files: bool,
+
+    /// Use the [organize] template for the new path (false = keep the file name)
+    pub organize: bool,
+}
+
+impl Default for InboxSettings {
+    fn default() -> Self {
+        Self {
+            folder: None,
+            move_to: None,
+            move_files: true,
+            organize: false,
+        }
+    }
+}
+
+/// Local network sharing settings
+/// Off by default: nothing is announced on the network until enabled
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct PeerSettings {
+    /// Announce this instance and look for others (mDNS / DNS-SD)
+    pub enabled: bool,
+
+    /// Name shown to other peers (None = the computer's host name)
+    pub name: Option<String>,
+
+    /// Port other peers connect to
+    pub port: u16,
+
+    /// Folder where books received from peers land
+    /// (None = an "Incoming" folder inside the first library folder)
+    pub incoming: Option<PathBuf>,
+
+    /// Only answer peers we paired with (false also serves unpaired peers,
+    /// unencrypted, like older versions did)
+    pub require_pairing: bool,
+}
+
+impl Default for PeerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            name: None,
+            port: 8573,
+            incoming: None,
+            require_pairing: true,
+        }
+    }
+}
+
+/// Single-book sharing settings
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ShareSettings {
+    /// Port the shared book is served on (0 = any free port)
+    pub port: u16,
+
+    /// Minutes before a share stops by itself
+    pub minutes: u64,
+}
+
+impl Default for ShareSettings {
+    fn default() -> Self {
+        Self { port: 0, minutes: 15 }
+    }
+}
+
+/// E-reader settings
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DeviceSettings {
+    /// Folders to treat as e-readers besides the Kindles and Kobos found
+    /// by themselves, e.g. a PocketBook's books folder
+    pub mounts: Vec<PathBuf>,
+
+    /// Format books are converted to for Kindles ("epub" = copy as is)
+    pub kindle_format: String,
+
+    /// Converter command (input and output paths are appended), also used
+    /// by conversions to other formats
+    pub converter: String,
+}
+
+impl Default for DeviceSettings {
+    fn default() -> Self {
+        Self {
+            mounts: Vec::new(),
+            kindle_format: "azw3".to_string(),
+            converter: "ebook-convert".to_string(),
+        }
+    }
+}
+
+/// Email settings (an SMTP server books are sent through, e.g. to a
+/// Send-to-Kindle address)
+/// The password is better kept out of the file: FUNKHUNT_EMAIL_PASSWORD
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct EmailSettings {
+    /// SMTP server (None = sending by email is off)
+    pub host: Option<String>,
+
+    /// SMTP port (587 for STARTTLS, 465 for TLS)
+    pub port: u16,
+
+    /// How the connection is secured: "starttls", "tls" or "none"
+    pub security: String,
+
+    /// User name to log in with (None = no login)
+    pub user: Option<String>,
+
+    /// Password to log in with
+    pub password: Option<String>,
+
+    /// Sender address (None = the user name)
+    pub from: Option<String>,
+
+    /// Address the prompt starts with, e.g. a Send-to-Kindle address
+    pub to: Option<String>,
+
+    /// Largest attachment the server takes, in megabytes (as encoded)
+    pub max_attachment_mb: u64,
+}
+
+impl Default for EmailSettings {
+    fn default() -> Self {
+        Self {
+            host: None,
+            port: 587,
+            security: "starttls".to_string(),
+            user: None,
+            password: None,
+            from: None,
+            to: None,
+            max_attachment_mb: 25,
+        }
+    }
+}
+
+/// Calibre content server settings
+/// The password can come from FUNKHUNT_CALIBRE_PASSWORD
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct CalibreSettings {
+    /// Address of the server, e.g. "http://nas.local:8080" (None = off)
+    pub url: Option<String>,
+
+    /// Library to use, by id or name (None = the server's default library)
+    pub library: Option<String>,
+
+    /// User name, for servers with users
+    pub user: Option<String>,
+
+    /// Password of that user
+    pub password: Option<String>,
+
+    /// Add books Calibre already has (by title and author) again
+    pub duplicates: bool,
+}
+
+/// RSS/Atom feeds to watch for new books
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FeedSettings {
+    /// Addresses of the feeds, e.g. Standard Ebooks' new releases
+    pub urls: Vec<String>,
+}
+
+/// Webhooks POSTed when books are added, removed, finished or received
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct WebhookSettings {
+    /// Addresses to POST the events to, e.g. a Home Assistant or Discord webhook
+    pub urls: Vec<String>,
+
+    /// Events to send: "added", "removed", "finished", "received" (empty = all)
+    pub events: Vec<String>,
+}
+
+/// Local dictionaries for the reader's word lookup
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DictionarySettings {
+    /// Folders holding dictd (.index) or StarDict (.ifo) dictionaries
+    /// (empty = /usr/share/dictd, /usr/share/stardict/dic and ~/.stardict/dic)
+    pub paths: Vec<PathBuf>,
+}
+
+/// Export of the highlights of a book from the reader
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct NotesSettings {
+    /// Folder the notes files go to (None = "notes" in the data directory)
+    pub folder: Option<PathBuf>,
+
+    /// "markdown" or "org"
+    pub format: NotesFormat,
+}
+
+/// The built-in reader (its typography is changed from its keys instead)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ReaderSettings {
+    /// Graphics protocol for pictures: "auto", "kitty", "iterm" or "off"
+    pub images: ImageSetting,
+}
+
+/// How searches and filters compare text (they always ignore case and accents)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SearchSettings {
+    /// Cyrillic and Greek match words typed in Latin letters ("tolstoy" finds "Толстой")
+    pub transliterate: bool,
+}
+
+/// How the orders of the book list compare titles and authors (they always
+/// ignore case and accents, see sort.rs)
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SortSettings {
+    /// Titles and series are ordered without their leading article ("The
+    /// Road" with the R's)
+    pub ignore_articles: bool,
+
+    /// The articles left out, any case ("l'" and other elided ones without
+    /// a space after them)
+    pub articles: Vec<String>,
+}
+
+impl Default for SortSettings {
+    fn default() -> Self {
+        Self {
+            ignore_articles: false,
+            articles: vec!["the".to_string(), "a".to_string(), "an".to_string()],
+        }
+    }
+}
+
+/// Listening to the narration of audiobooks and read-aloud EPUBs (see audio.rs)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    /// mpv, with its options if any (None = "mpv" from the PATH); it is
+    /// controlled through its IPC socket, so other players won't do
+    pub player: Option<String>,
+}
+
+/// Text of books written for other tools
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ExtractSettings {
+    /// Folder the text files go to (None = "text" in the data directory)
+    pub folder: Option<PathBuf>,
+
+    /// "text" or "markdown"
+    pub format: TextFormat,
+}
+
+/// How to log in to a server holding a remote scan root (sftp://host/path,
+/// davs://host/path), or to an S3 bucket (s3://bucket/prefix)
+/// Values given in the URL itself (sftp://user@host:2222/path) win
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RemoteHost {
+    /// User name (None = the local user name)
+    pub user: Option<String>,
+
+    /// Port (None = the protocol's default: 22 for SFTP, 443/80 for WebDAV)
+    pub port: Option<u16>,
+
+    /// Private key file for SFTP (None = the keys of the running SSH agent)
+    pub identity: Option<PathBuf>,
+
+    /// Password: for WebDAV (on Nextcloud, an app password), or for SFTP
+    /// servers without key login (a key or the agent is safer)
+    pub password: Option<String>,
+
+    /// S3 server, e.g. "http://nas.local:9000" for MinIO
+    /// (None = AWS in the configured region)
+    pub endpoint: Option<String>,
+
+    /// S3 region (None = "us-east-1", which MinIO accepts too)
+    pub region: Option<String>,
+
+    /// S3 access key id (None = $AWS_ACCESS_KEY_ID, else anonymous access)
+    pub access_key: Option<String>,
+
+    /// S3 secret access key (None = $AWS_SECRET_ACCESS_KEY)
+    pub secret_key: Option<String>,
+}
+
+/// Online metadata lookup settings
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MetadataSettings {
+    /// Which service to ask
+    pub provider: ProviderChoice,
+
+    /// Google Books API key (optional - without one, requests share a small
+    /// anonymous quota)
+    pub google_books_api_key: Option<String>,
+}
+
+/// Metadata services to ask, written as "auto", "open-library" or "google-books"
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProviderChoice {
+    /// Open Library first, Google Books when Open Library has no record
+    #[default]
+    Auto,
+    /// Only Open Library
+    OpenLibrary,
+    /// Only Google Books
+    GoogleBooks,
+}
+
+impl Default for AutosaveSettings {
+    fn default() -> Self {
+        Self { interval: 30 }
+    }
+}
+
+impl Theme {
+    /// The colors of the reader's page in a reading theme (the theme itself
+    /// for ReaderTheme::App): `background`, `text`, `header` (headings),
+    /// `accent` (quotes) and `muted` (separators, footer)
+    pub fn for_reader(&self, reader: ReaderTheme) -> Theme {
+        let (background, text, header, accent, muted) = match reader {
+            ReaderTheme::App => return self.clone(),
+            ReaderTheme::Sepia => (
+                Color::Rgb(244, 236, 216),
+                Color::Rgb(91, 70, 54),
+                Color::Rgb(112, 66, 20),
+                Color::Rgb(128, 96, 64),
+                Color::Rgb(150, 130, 110),
+            ),
+            ReaderTheme::Dark => (
+                Color::Rgb(24, 24, 24),
+                Color::Rgb(200, 200, 200),
+                Color::Rgb(235, 235, 235),
+                Color::Rgb(160, 175, 200),
+                Color::Rgb(110, 110, 110),
+            ),
+            ReaderTheme::HighContrast => (Color::Black, Color::White, Color::Yellow, Color::LightCyan, Color::White),
+        };
+        Theme {
+            background,
+            text,
+            header,
+            accent,
+            muted,
+            ..self.clone()
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            header: Color::Cyan,
+            border: Color::Blue,
+            text: Color::White,
+            selected: Color::Yellow,
+            muted: Color::Gray,
+            accent: Color::Cyan,
+            background: Color::Black,
+            popup_bg: Color::Rgb(40, 40, 40),
+            popup_selected_bg: Color::Rgb(60, 60, 60),
+        }
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            quit: 'q',
+            add_folder: 'a',
+            switch_library: 'p',
+            show_log: 'L',
+            folder_settings: 'f',
+            collections: 'c',
+            add_to_collection: '+',
+            remove_from_collection: '-',
+            star: 's',
+            starred_only: 'S',
+            cycle_status: 'r',
+            status_filter: 'R',
+            sort: 'o',
+            filter: '/',
+            archive: 'x',
+            show_archived: 'X',
+            merge_authors: 'A',
+            series: 'e',
+            mark: 'v',
+            bulk_edit: 'E',
+            edit_metadata: 'b',
+            undo: 'u',
+            redo: 'U',
+            fetch_metadata: 'm',
+            health: 'H',
+            statistics: 'w',
+            duplicates: 'd',
+            organize: 'O',
+            rename: 'N',
+            peers: 'P',
+            transfers: 'T',
+            share: 'Q',
+            devices: 'D',
+            email: 'M',
+            convert: 'K',
+            extract: 'W',
+            cover: 'I',
+            optimize: 'Z',
+            fonts: 't',
+            split: 'B',
+            join: 'J',
+            preview: 'i',
+            quotes: 'y',
+            quote_description: 'Y',
+            calibre: 'C',
+            feeds: 'F',
+            read: 'V',
+            search_text: 'g',
+            continue_reading: 'k',
+            listen: 'n',
+        }
+    }
+}
+
+impl Settings {
+    /// Default location of the configuration file (in the platform config directory)
+    pub fn default_path() -> PathBuf {
+        crate::paths::config_dir().join(SETTINGS_FILE)
+    }
+
+    /// Loads settings from a TOML file, then applies FUNKHUNT_* environment overrides
+    ///
+    /// # Arguments
+    /// * `path` - The configuration file
+    ///
+    /// # Returns
+    /// * `Ok(Settings)` - Parsed settings (defaults if the file doesn't exist)
+    /// * `Err(String)` - Human-readable reason the file or an override couldn't be parsed
+    pub fn load(path: &Path) -> Result<Self, String> {
+        // Read the file into a generic TOML table first, so overrides can be merged in
+        let mut table: toml::Table = match std::fs::read_to_string(path) {
+            Ok(text) => text.parse().map_err(|e| format!("{}: {}", path.display(), e))?,
+            // No config file is perfectly fine - use the defaults
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => toml::Table::new(),
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+
+        apply_env_overrides(&mut table, std::env::vars());
+
+        toml::Value::Table(table)
+            .try_into()
+            .map_err(|e| format!("{} (or FUNKHUNT_* variables): {}", path.display(), e))
+    }
+}
+
+/// Merges FUNKHUNT_<SECTION>_<KEY>=value variables into a settings table
+///
+/// `FUNKHUNT_THEME_POPUP_BG=#202020` becomes `[theme] popup_bg = "#202020"`.
+/// Variables for unknown sections (like FUNKHUNT_DATA_DIR) are left alone.
+///
+/// # Arguments
+/// * `table` - The parsed config.toml
+/// * `vars` - Environment variables as (name, value) pairs
+fn apply_env_overrides(table: &mut toml::Table, vars: impl Iterator<Item = (String, String)>) {
+    for (name, value) in vars {
+        let Some(rest) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+
+        // "THEME_POPUP_BG" -> section "theme", key "popup_bg"
+        let rest = rest.to_lowercase();
+        let Some((section, key)) = rest.split_once('_') else {
+            continue;
+        };
+        if !ENV_SECTIONS.contains(&section) {
+            continue;
+        }
+
+        let section_value = table
+            .entry(section.to_string())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        if let toml::Value::Table(section_table) = section_value {
+            section_table.insert(key.to_string(), toml::Value::String(value));
+        }
+    }
+}
+
+/// Watches the configuration file for changes by polling its modification time
+pub struct SettingsWatcher {
+    /// The file being watched
+    path: PathBuf,
+
+    /// Modification time seen last (None = file didn't exist)
+    last_modified: Option<SystemTime>,
+}
+
+impl SettingsWatcher {
+    /// Starts watching a file (its current state counts as "seen")
+    pub fn new(path: PathBuf) -> Self {
+        let last_modified = modified_time(&path);
+        Self {
+            path,
+            last_modified,
+        }
+    }
+
+    /// Path of the watched file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Checks whether the file was created, modified or deleted since the last call
+    ///
+    /// A single stat() call - cheap enough to run on every loop iteration.
+    pub fn changed(&mut self) -> bool {
+        let modified = modified_time(&self.path);
+        if modified != self.last_modified {
+            self.last_modified = modified;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Gets the modification time of a file (None if it doesn't exist)
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
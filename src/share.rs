@@ -0,0 +1,165 @@
+// src/share.rs
+// Single-book sharing - a temporary HTTP endpoint serving one file, for a
+// phone or anything else with a browser (the URL is shown as a QR code)
+//
+// The URL holds a random token, so only whoever sees the code can fetch
+// the book. The server stops when the share screen is closed, or by
+// itself after [share] minutes.
+
+use crate::book::Book;
+use crate::qr::QrCode;
+use crate::server::{ascii_filename, header};
+use crate::transfer::Transfers;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fs::File;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tiny_http::{Request, Response, Server, StatusCode};
+
+/// A book being shared (the server stops when dropped)
+pub struct BookShare {
+    /// Title of the shared book
+    pub title: String,
+
+    /// Where the book can be downloaded
+    pub url: String,
+
+    /// The URL as a QR code (None if the URL is too long for one)
+    pub qr: Option<QrCode>,
+
+    /// The listening server (shared with its thread, to unblock it)
+    server: Arc<Server>,
+
+    /// Thread handling the requests
+    thread: Option<JoinHandle<()>>,
+
+    /// How many times the book was downloaded
+    downloads: Arc<AtomicUsize>,
+
+    /// When the share stops by itself
+    deadline: Instant,
+}
+
+impl BookShare {
+    /// Starts serving a book
+    ///
+    /// # Arguments
+    /// * `book` - The book (a local file)
+    /// * `port` - Port to listen on (0 = any free port)
+    /// * `lifetime` - How long the share lasts at most
+    /// * `transfers` - Where the downloads are shown
+    pub fn start(book: &Book, port: u16, lifetime: Duration, transfers: Transfers) -> io::Result<Self> {
+        let server = Arc::new(Server::http(("0.0.0.0", port)).map_err(io::Error::other)?);
+        let port = server.server_addr().to_ip().map(|a| a.port()).unwrap_or(port);
+
+        let mut token = [0u8; 8];
+        SystemRandom::new()
+            .fill(&mut token)
+            .map_err(|_| io::Error::other("no randomness for the share token"))?;
+        let token: String = token.iter().map(|b| format!("{:02x}", b)).collect();
+        // Short, for a small code: the file name comes with the download
+        let path = format!("/{}", token);
+        let url = format!("http://{}:{}{}", lan_address(), port, path);
+
+        let downloads = Arc::new(AtomicUsize::new(0));
+        let thread = {
+            let server = Arc::clone(&server);
+            let downloads = Arc::clone(&downloads);
+            let (file, name) = (book.path.clone(), book.name.clone());
+            std::thread::spawn(move || {
+                // Ends when the server is unblocked
+                for request in server.incoming_requests() {
+                    tracing::debug!(method = %request.method(), url = request.url(), "share request");
+                    let result = if request.url() == path {
+                        serve(request, &file, &name, &transfers).inspect(|()| {
+                            downloads.fetch_add(1, Ordering::Relaxed);
+                        })
+                    } else {
+                        request.respond(Response::from_string("Not found").with_status_code(404))
+                    };
+                    if let Err(e) = result {
+                        tracing::warn!(error = %e, "failed to answer share request");
+                    }
+                }
+            })
+        };
+
+        tracing::info!(book = %book.name, port, "book shared");
+        Ok(Self {
+            title: book.display_title().to_string(),
+            qr: QrCode::encode(&url),
+            url,
+            server,
+            thread: Some(thread),
+            downloads,
+            deadline: Instant::now() + lifetime,
+        })
+    }
+
+    /// How many times the book was downloaded
+    pub fn downloads(&self) -> usize {
+        self.downloads.load(Ordering::Relaxed)
+    }
+
+    /// Time left before the share stops by itself
+    pub fn remaining(&self) -> Duration {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+
+    /// Whether the share should stop
+    pub fn expired(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+}
+
+impl Drop for BookShare {
+    /// Stops the server thread
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        tracing::info!(title = %self.title, downloads = self.downloads(), "book share stopped");
+    }
+}
+
+/// Sends the shared file, shown in the transfer list
+fn serve(request: Request, path: &Path, name: &str, transfers: &Transfers) -> io::Result<()> {
+    let file = File::open(path)?;
+    let size = file.metadata().ok().map(|m| m.len());
+    let remote = request.remote_addr().map(|a| a.ip().to_string()).unwrap_or_default();
+    let progress = transfers.track(format!("{} to {}", name, remote), size);
+    let disposition = format!("attachment; filename=\"{}\"", ascii_filename(name));
+    let response = Response::new(
+        StatusCode(200),
+        vec![
+            header("Content-Type", "application/epub+zip")?,
+            header("Content-Disposition", &disposition)?,
+        ],
+        progress.reader(file),
+        size.map(|s| s as usize),
+        None,
+    );
+    let result = request.respond(response);
+    progress.finish(&result);
+    result
+}
+
+/// Address of this machine on the local network
+///
+/// Connecting a UDP socket sends nothing; it only makes the OS pick the
+/// interface (and address) it would route through.
+fn lan_address() -> IpAddr {
+    UdpSocket::bind(("0.0.0.0", 0))
+        .and_then(|socket| {
+            socket.connect(("192.0.2.1", 9))?;
+            socket.local_addr()
+        })
+        .map(|addr| addr.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST))
+}
@@ -0,0 +1,139 @@
+// src/sidecar.rs
+// Per-book sidecar files - the user data of a book (tags, rating, status...)
+// written as "<book>.funkhunt.json" next to the EPUB, so it travels with the
+// file when the folder is synced or copied by other tools
+
+use crate::book::{Book, UserData};
+use crate::profile::ProfileSettings;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Value of the `format` field of a sidecar file
+pub const SIDECAR_FORMAT: &str = "funkhunt-sidecar";
+
+/// Version of the sidecar layout
+pub const SIDECAR_VERSION: u32 = 1;
+
+/// Extension replacing ".epub": "Dune.epub" -> "Dune.funkhunt.json"
+const SIDECAR_EXTENSION: &str = "funkhunt.json";
+
+/// Contents of a sidecar file
+#[derive(Debug, Serialize, Deserialize)]
+struct Sidecar {
+    /// Always SIDECAR_FORMAT
+    format: String,
+
+    /// Layout version (SIDECAR_VERSION at the time of writing)
+    version: u32,
+
+    /// Everything the user added to the book
+    user: UserData,
+}
+
+/// Path of the sidecar file of a book
+pub fn path_for(book_path: &Path) -> PathBuf {
+    book_path.with_extension(SIDECAR_EXTENSION)
+}
+
+/// Reads the sidecar of a book
+///
+/// # Returns
+/// The user data, or None if there's no sidecar or it isn't valid
+pub fn read(book_path: &Path) -> Option<UserData> {
+    let path = path_for(book_path);
+    let text = fs::read_to_string(&path).ok()?;
+    match serde_json::from_str::<Sidecar>(&text) {
+        Ok(sidecar) if sidecar.format == SIDECAR_FORMAT => Some(sidecar.user),
+        Ok(_) => None,
+        Err(e) => {
+            tracing::warn!(path = %path.display(), error = %e, "invalid sidecar file");
+            None
+        }
+    }
+}
+
+/// Reads the sidecar of a book if it was changed after `since`
+///
+/// Sidecars are written just before the library database, so one that is
+/// newer than the database was changed outside FunkHunt (e.g. synced from
+/// another machine) and its data wins over the stored one.
+///
+/// # Arguments
+/// * `book_path` - The EPUB file
+/// * `since` - When the library database was last saved (None = always read)
+pub fn read_if_newer(book_path: &Path, since: Option<SystemTime>) -> Option<UserData> {
+    let modified = fs::metadata(path_for(book_path)).and_then(|m| m.modified()).ok()?;
+    match since {
+        Some(since) if modified <= since => None,
+        _ => read(book_path),
+    }
+}
+
+/// Writes the sidecar of a book, if its contents changed
+///
+/// Books the user hasn't touched get no sidecar, but an existing sidecar is
+/// still updated (so clearing the last tag reaches the other copies).
+///
+/// # Returns
+/// Whether the file was written
+pub fn write(book: &Book) -> io::Result<bool> {
+    let path = path_for(&book.path);
+    let existing = fs::read_to_string(&path).ok();
+    if existing.is_none() && book.user.is_empty() {
+        return Ok(false);
+    }
+
+    let sidecar = Sidecar {
+        format: SIDECAR_FORMAT.to_string(),
+        version: SIDECAR_VERSION,
+        user: book.user.clone(),
+    };
+    // Pretty JSON so the file stays human-editable
+    let json = serde_json::to_string_pretty(&sidecar).map_err(io::Error::other)?;
+    if existing.as_deref() == Some(json.as_str()) {
+        return Ok(false);
+    }
+    fs::write(&path, json)?;
+    Ok(true)
+}
+
+/// Writes the sidecars of every book in a folder that has sidecars enabled
+///
+/// Read-only folders are skipped. Errors are logged, never fatal.
+///
+/// # Returns
+/// The number of sidecars written
+pub fn write_all(books: &[Book], settings: &ProfileSettings) -> usize {
+    let roots: Vec<PathBuf> = settings
+        .folder_list()
+        .into_iter()
+        .filter(|(root, folder)| folder.sidecars && !folder.read_only && !crate::remote::is_remote(root))
+        .map(|(root, _)| root)
+        .collect();
+    if roots.is_empty() {
+        return 0;
+    }
+
+    let mut written = 0;
+    for book in books.iter().filter(|b| roots.iter().any(|root| b.path.starts_with(root))) {
+        match write(book) {
+            Ok(true) => written += 1,
+            Ok(false) => {}
+            Err(e) => tracing::warn!(path = %book.path.display(), error = %e, "cannot write sidecar"),
+        }
+    }
+    written
+}
+
+/// Moves the sidecar of a book along with its file (nothing if it has none)
+pub fn follow(from: &Path, to: &Path) {
+    let (from, to) = (path_for(from), path_for(to));
+    if from.exists() {
+        if let Err(e) = fs::rename(&from, &to) {
+            tracing::warn!(path = %from.display(), error = %e, "cannot move sidecar");
+        }
+    }
+}
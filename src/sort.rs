@@ -0,0 +1,260 @@
+// src/sort.rs
+// Orders in which the book list can be sorted
+//
+// Titles, authors and series are collated rather than compared byte by
+// byte: first as folded text (see fold.rs) - so "Ärger" sorts with the A's,
+// "émile" with the E's and "Zoë" before "Zola" - without punctuation, then
+// by their accents, then by their case. With [sort] ignore_articles, titles
+// and series are ordered without their leading article ("The Road" with
+// the R's). The OPDS catalog and the web page order titles the same way.
+
+use crate::book::Book;
+use crate::fold::unmarked;
+use crate::settings::SortSettings;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::sync::RwLock;
+
+/// The articles titles are ordered without, folded ([sort] articles; none
+/// unless [sort] ignore_articles)
+static ARTICLES: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// What the text orders compare of a book, worked out once (the book
+/// index keeps them, see query.rs) rather than at every comparison
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SortKeys {
+    /// Display title
+    pub title: String,
+    /// First author ("" for none)
+    pub author: String,
+    /// Series name
+    pub series: Option<String>,
+}
+
+impl SortKeys {
+    /// The keys of a book
+    pub fn of(book: &Book) -> Self {
+        Self {
+            title: title_key(book.display_title()),
+            author: collation_key(book.authors().first().map(String::as_str).unwrap_or("")),
+            series: book.meta.series.as_deref().map(title_key),
+        }
+    }
+}
+
+/// How the book list is ordered
+/// Stored as "library", "title", "author", "rating", "added", "opened" or "series"
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortOrder {
+    /// The order books were added to the library in
+    #[default]
+    Library,
+    /// Title A-Z
+    Title,
+    /// First author A-Z, then title
+    Author,
+    /// Highest rating first, unrated books last
+    Rating,
+    /// Most recently added first
+    Added,
+    /// Most recently opened first, never opened books last
+    Opened,
+    /// Series name A-Z, then position in the series; books without series last
+    Series,
+}
+
+impl SortOrder {
+    /// Human-readable name, e.g. "rating"
+    pub fn label(self) -> &'static str {
+        match self {
+            SortOrder::Library => "library order",
+            SortOrder::Title => "title",
+            SortOrder::Author => "author",
+            SortOrder::Rating => "rating",
+            SortOrder::Added => "date added",
+            SortOrder::Opened => "last opened",
+            SortOrder::Series => "series",
+        }
+    }
+
+    /// The order a name is for, as stored (e.g. "rating")
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "library" => Some(SortOrder::Library),
+            "title" => Some(SortOrder::Title),
+            "author" => Some(SortOrder::Author),
+            "rating" => Some(SortOrder::Rating),
+            "added" => Some(SortOrder::Added),
+            "opened" => Some(SortOrder::Opened),
+            "series" => Some(SortOrder::Series),
+            _ => None,
+        }
+    }
+
+    /// Next order when cycling through them with a key
+    pub fn next(self) -> Self {
+        match self {
+            SortOrder::Library => SortOrder::Title,
+            SortOrder::Title => SortOrder::Author,
+            SortOrder::Author => SortOrder::Rating,
+            SortOrder::Rating => SortOrder::Added,
+            SortOrder::Added => SortOrder::Opened,
+            SortOrder::Opened => SortOrder::Series,
+            SortOrder::Series => SortOrder::Library,
+        }
+    }
+
+    /// Compares two books in this order
+    ///
+    /// Library order compares as equal, so a stable sort keeps the
+    /// original order of the library.
+    pub fn compare(self, a: &Book, b: &Book) -> Ordering {
+        self.compare_keyed(a, &SortKeys::of(a), b, &SortKeys::of(b))
+    }
+
+    /// Compares two books in this order, their keys already worked out
+    pub fn compare_keyed(self, a: &Book, keys_a: &SortKeys, b: &Book, keys_b: &SortKeys) -> Ordering {
+        match self {
+            SortOrder::Library => Ordering::Equal,
+            SortOrder::Title => keys_a.title.cmp(&keys_b.title),
+            SortOrder::Author => keys_a
+                .author
+                .cmp(&keys_b.author)
+                .then_with(|| keys_a.title.cmp(&keys_b.title)),
+            // Reversed: 5 stars before 1 star; None sorts below every rating
+            SortOrder::Rating => b
+                .user
+                .rating
+                .cmp(&a.user.rating)
+                .then_with(|| keys_a.title.cmp(&keys_b.title)),
+            // Newest first, same trick as the rating (None sorts last)
+            SortOrder::Added => b.added.cmp(&a.added),
+            SortOrder::Opened => b.opened.cmp(&a.opened),
+            SortOrder::Series => match (&keys_a.series, &keys_b.series) {
+                (Some(series_a), Some(series_b)) => series_a
+                    .cmp(series_b)
+                    .then_with(|| compare_position(a.meta.series_index, b.meta.series_index))
+                    .then_with(|| keys_a.title.cmp(&keys_b.title)),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => keys_a.title.cmp(&keys_b.title),
+            },
+        }
+    }
+}
+
+/// Compares positions in a series (1 before 1.5 before 2), unnumbered books last
+fn compare_position(a: Option<f32>, b: Option<f32>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Uses new sort settings (called when config.toml is loaded)
+pub fn configure(settings: &SortSettings) {
+    let articles = if settings.ignore_articles {
+        settings
+            .articles
+            .iter()
+            .map(|article| unmarked(article.trim()).replace('\u{2019}', "'"))
+            .filter(|article| !article.is_empty())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    *ARTICLES.write().unwrap_or_else(|e| e.into_inner()) = articles;
+}
+
+/// A text as it's ordered: keys compare as bytes the way the texts collate
+/// (folded letters and digits first, then accents, then case)
+pub fn collation_key(text: &str) -> String {
+    key(text, false)
+}
+
+/// A title (or series) as it's ordered: as `collation_key`, without its
+/// leading article with [sort] ignore_articles
+pub fn title_key(text: &str) -> String {
+    key(text, true)
+}
+
+/// The key of a text: its folded words (letters and digits, a space
+/// between them), then its lowercase text, then the text itself, a NUL
+/// between each, so a level only decides when the ones before are equal
+fn key(text: &str, ignore_article: bool) -> String {
+    let text = text.trim();
+    let folded = unmarked(text).replace('\u{2019}', "'");
+    let articles = ARTICLES.read().unwrap_or_else(|e| e.into_inner());
+    let words = if ignore_article { without_article(&folded, &articles) } else { &folded };
+
+    let mut key = String::with_capacity(text.len() * 3 + 2);
+    for word in words.split_whitespace() {
+        let letters = word.chars().filter(|c| c.is_alphanumeric());
+        if !key.is_empty() && word.chars().any(char::is_alphanumeric) {
+            key.push(' ');
+        }
+        key.extend(letters);
+    }
+    key.push('\0');
+    key.push_str(&text.to_lowercase());
+    key.push('\0');
+    key.push_str(text);
+    key
+}
+
+/// A folded text without its leading article (if it's followed by more
+/// words, or is elided: "l'amour")
+fn without_article<'a>(folded: &'a str, articles: &[String]) -> &'a str {
+    for article in articles {
+        let Some(rest) = folded.strip_prefix(article.as_str()) else {
+            continue;
+        };
+        let separated = article.ends_with('\'') || rest.starts_with(char::is_whitespace);
+        if separated && rest.chars().any(char::is_alphanumeric) {
+            return rest.trim_start();
+        }
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Texts in the order their keys sort them
+    fn collated(texts: &[&str]) -> Vec<String> {
+        let mut keyed: Vec<(String, &str)> = texts.iter().map(|text| (collation_key(text), *text)).collect();
+        keyed.sort();
+        keyed.into_iter().map(|(_, text)| text.to_string()).collect()
+    }
+
+    #[test]
+    fn accents_sort_with_their_letters() {
+        assert_eq!(collated(&["Zola", "Ärger", "Bach", "Apfel"]), ["Apfel", "Ärger", "Bach", "Zola"]);
+        assert_eq!(collated(&["Zola", "Zoë"]), ["Zoë", "Zola"]);
+        assert_eq!(collated(&["émile", "Emma", "Eve"]), ["émile", "Emma", "Eve"]);
+    }
+
+    #[test]
+    fn case_and_punctuation_come_last() {
+        assert_eq!(collated(&["banana", "Apple"]), ["Apple", "banana"]);
+        assert_eq!(collated(&["\"Salem's Lot\"", "Rebecca", "Shining"]), ["Rebecca", "\"Salem's Lot\"", "Shining"]);
+        // Same letters: unaccented first, then by case (uppercase first)
+        assert_eq!(collated(&["resume", "résumé", "Resume"]), ["Resume", "resume", "résumé"]);
+        assert!(collation_key("Dune") < collation_key("Dune Messiah"));
+    }
+
+    #[test]
+    fn articles_are_left_out_when_asked() {
+        let articles = ["the".to_string(), "l'".to_string()];
+        assert_eq!(without_article("the road", &articles), "road");
+        assert_eq!(without_article("l'amour", &articles), "amour");
+        // Not an article: a word starting like one, or the whole title
+        assert_eq!(without_article("theory", &articles), "theory");
+        assert_eq!(without_article("the", &articles), "the");
+        assert_eq!(without_article("the road", &[]), "the road");
+    }
+}
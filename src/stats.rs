@@ -0,0 +1,133 @@
+// src/stats.rs
+// Library statistics - books by format, authors, size and the largest
+// files - shown by `funkhunt stats` (a table or JSON) and the statistics
+// screen (w)
+//
+// Sizes are those of the files on this computer: books of remote folders
+// are counted, not measured on their server.
+
+use crate::book::Book;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// How many of the largest files are listed
+const LARGEST: usize = 10;
+
+/// Totals of a library
+#[derive(Debug, Clone, Serialize)]
+pub struct LibraryStats {
+    pub books: usize,
+
+    /// Books per format (file extension), most first
+    pub formats: Vec<FormatCount>,
+
+    /// Distinct authors, spellings merged (see authors::author_key)
+    pub authors: usize,
+
+    /// Size of the files on this computer, in bytes
+    pub size_bytes: u64,
+
+    /// Books of remote folders, not in size_bytes
+    pub remote: usize,
+
+    /// The largest files, largest first
+    pub largest: Vec<LargeFile>,
+}
+
+/// Books of one format
+#[derive(Debug, Clone, Serialize)]
+pub struct FormatCount {
+    /// e.g. "epub"
+    pub format: String,
+    pub books: usize,
+}
+
+/// A file among the largest
+#[derive(Debug, Clone, Serialize)]
+pub struct LargeFile {
+    pub path: PathBuf,
+    pub size_bytes: u64,
+}
+
+impl LibraryStats {
+    /// Counts and measures the books (reads each local file's size)
+    pub fn of(books: &[Book]) -> Self {
+        let mut formats: HashMap<String, usize> = HashMap::new();
+        let mut authors: HashSet<String> = HashSet::new();
+        let mut sizes: Vec<LargeFile> = Vec::new();
+        let mut remote = 0;
+        for book in books {
+            *formats.entry(format_of(&book.path)).or_default() += 1;
+            authors.extend(book.authors().iter().map(|author| crate::authors::author_key(author)));
+            if crate::remote::is_remote(&book.path) {
+                remote += 1;
+            } else if let Ok(metadata) = std::fs::metadata(&book.path) {
+                sizes.push(LargeFile {
+                    path: book.path.clone(),
+                    size_bytes: metadata.len(),
+                });
+            }
+        }
+
+        let mut formats: Vec<FormatCount> = formats.into_iter().map(|(format, books)| FormatCount { format, books }).collect();
+        formats.sort_by(|a, b| b.books.cmp(&a.books).then_with(|| a.format.cmp(&b.format)));
+        let size_bytes = sizes.iter().map(|file| file.size_bytes).sum();
+        sizes.sort_by_key(|file| std::cmp::Reverse(file.size_bytes));
+        sizes.truncate(LARGEST);
+
+        Self {
+            books: books.len(),
+            formats,
+            authors: authors.len(),
+            size_bytes,
+            remote,
+            largest: sizes,
+        }
+    }
+
+    /// The statistics as a table, a line per row (for the terminal and the screen)
+    pub fn lines(&self) -> Vec<String> {
+        let human = |bytes: u64| crate::transfer::human_bytes(bytes as f64);
+        let mut lines = vec![
+            format!("Books     {:>10}", self.books),
+            format!("Authors   {:>10}", self.authors),
+            format!("Size      {:>10}", human(self.size_bytes)),
+        ];
+        if self.remote > 0 {
+            lines.push(format!("Remote    {:>10}  (on servers, not in the size)", self.remote));
+        }
+
+        lines.push(String::new());
+        lines.push("Format         Books".to_string());
+        for count in &self.formats {
+            lines.push(format!("{:<10} {:>9}", count.format, count.books));
+        }
+
+        if !self.largest.is_empty() {
+            lines.push(String::new());
+            lines.push("Largest files".to_string());
+            for file in &self.largest {
+                lines.push(format!("{:>10}  {}", human(file.size_bytes), file.path.display()));
+            }
+        }
+        lines
+    }
+}
+
+/// Size of the files of a library on this computer, in bytes
+pub fn library_bytes(books: &[Book]) -> u64 {
+    books
+        .iter()
+        .filter(|book| !crate::remote::is_remote(&book.path))
+        .filter_map(|book| std::fs::metadata(&book.path).ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// A book's format: its extension, lowercase ("none" without one)
+fn format_of(path: &Path) -> String {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_else(|| "none".to_string())
+}
@@ -0,0 +1,219 @@
+// src/sync.rs
+// Two-way sync - reconciles the open library with another FunkHunt library:
+// a peer on the LAN, or a library database kept on a server or shared drive
+// (e.g. sftp://nas.local/funkhunt/library.json)
+//
+// Books are matched like in a merge (content hash, ISBN, path). User data
+// changed on one side only since the last sync goes to the other side; when
+// both sides changed a book, tags and collections are combined and the
+// fields set differently become conflicts (the newer side pre-selected) that
+// the user settles in the merge screen. Books only one side has are copied
+// to the other. Copies of the same file sync their metadata edits the same
+// way (see `merge`), once both were read - a placeholder's empty metadata
+// is never sent or taken.
+
+use crate::book::Book;
+use crate::merge::{MergePlan, Update};
+use crate::trust::Secret;
+use std::collections::HashSet;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+/// The library on the other side of a sync
+#[derive(Debug, Clone)]
+pub enum Partner {
+    /// A FunkHunt instance on the network (it must have [peers] enabled)
+    Peer {
+        /// DNS-SD id of the peer (unique on the network)
+        id: String,
+
+        /// Name the peer announces, e.g. "Living room"
+        name: String,
+
+        /// Where the peer answers
+        address: SocketAddr,
+
+        /// Our pair secret with the peer (None if we didn't pair)
+        secret: Option<Secret>,
+    },
+
+    /// A library database file, local or on a server
+    File(PathBuf),
+}
+
+impl Partner {
+    /// Key the last sync time is stored under, e.g. "file:sftp://nas/library.json"
+    pub fn key(&self) -> String {
+        match self {
+            Partner::Peer { id, .. } => format!("peer:{}", id),
+            Partner::File(path) => format!("file:{}", path.display()),
+        }
+    }
+
+    /// Name for messages
+    pub fn label(&self) -> String {
+        match self {
+            Partner::Peer { name, .. } => name.clone(),
+            Partner::File(path) => path.display().to_string(),
+        }
+    }
+
+    /// Reads the partner's books
+    ///
+    /// A library file that doesn't exist yet is an empty library: the first
+    /// sync creates it.
+    pub fn books(&self) -> io::Result<Vec<Book>> {
+        match self {
+            Partner::Peer { address, secret, .. } => crate::peer::fetch_sync_library(*address, secret.as_ref())
+                .map(|library| library.books)
+                .map_err(io::Error::other),
+            Partner::File(path) => match read_library_file(path) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+                result => result,
+            },
+        }
+    }
+}
+
+/// A sync waiting for its conflicts to be settled
+#[derive(Debug, Clone)]
+pub struct PendingSync {
+    /// The other library
+    pub partner: Partner,
+
+    /// The partner's books, as read when the sync started
+    pub theirs: Vec<Book>,
+
+    /// Matched books: our path and the position of the book in `theirs`
+    pub pairs: Vec<(PathBuf, usize)>,
+
+    /// Positions in `theirs` of the books we don't have
+    pub incoming: Vec<usize>,
+
+    /// Paths of our books the partner doesn't have
+    pub outgoing: Vec<PathBuf>,
+}
+
+/// Compares the partner's books with ours
+///
+/// Hashes of our books are computed when the partner has hashes to compare
+/// with (and cached on the books).
+///
+/// # Arguments
+/// * `partner` - The other library
+/// * `ours` - The books of the open library
+/// * `theirs` - The partner's books
+/// * `last_sync` - When we last synced with this partner (None = never)
+///
+/// # Returns
+/// The user data changes for our side (a merge plan, reviewed like one) and
+/// what's left to do once it's applied
+pub fn plan(partner: Partner, ours: &mut [Book], theirs: Vec<Book>, last_sync: Option<u64>) -> (MergePlan, PendingSync) {
+    let since = last_sync.unwrap_or(0);
+    let mut merge = MergePlan::default();
+    let mut pending = PendingSync {
+        partner,
+        theirs: Vec::new(),
+        pairs: Vec::new(),
+        incoming: Vec::new(),
+        outgoing: Vec::new(),
+    };
+    let mut matched = HashSet::new();
+
+    for (index, other) in theirs.iter().enumerate() {
+        let Some(mine) = crate::merge::find_match(ours, other) else {
+            // Archived books stay where they are
+            if !other.user.archived {
+                pending.incoming.push(index);
+            }
+            continue;
+        };
+        matched.insert(mine);
+        pending.pairs.push((ours[mine].path.clone(), index));
+
+        let book = &ours[mine];
+        let same_file = crate::merge::shares_metadata(book, other);
+        if book.user == other.user && (!same_file || book.meta == other.meta) {
+            continue;
+        }
+        let (our_time, their_time) = (book.modified.unwrap_or(0), other.modified.unwrap_or(0));
+        if our_time > since && their_time > since {
+            // Changed on both sides: combine, and let the user settle the rest
+            let mut update = crate::merge::reconcile(book, other.clone());
+            for conflict in &mut update.conflicts {
+                conflict.take_theirs = their_time > our_time;
+            }
+            if update.changes(book) {
+                merge.updates.push(update);
+            }
+        } else if their_time > our_time {
+            // Changed over there only: theirs wins as a whole
+            merge.updates.push(Update {
+                path: book.path.clone(),
+                title: book.display_title().to_string(),
+                merged: other.user.clone(),
+                merged_meta: if same_file { other.meta.clone() } else { book.meta.clone() },
+                theirs: other.clone(),
+                conflicts: Vec::new(),
+            });
+        }
+        // Changed here only: goes over with the pushes
+    }
+
+    pending.outgoing = ours
+        .iter()
+        .enumerate()
+        .filter(|(i, book)| !matched.contains(i) && !book.user.archived)
+        .map(|(_, book)| book.path.clone())
+        .collect();
+    pending.theirs = theirs;
+    (merge, pending)
+}
+
+/// The partner's matched books whose user data differs from ours, carrying
+/// our user data - and our metadata, for copies of the same file (called
+/// once our side of the sync was applied)
+///
+/// # Arguments
+/// * `ours` - The books of the open library
+/// * `pending` - The sync
+pub fn pushes(ours: &[Book], pending: &PendingSync) -> Vec<Book> {
+    pending
+        .pairs
+        .iter()
+        .filter_map(|(path, index)| {
+            let mine = ours.iter().find(|b| &b.path == path)?;
+            let theirs = pending.theirs.get(*index)?;
+            let same_file = crate::merge::shares_metadata(mine, theirs);
+            (mine.user != theirs.user || (same_file && mine.meta != theirs.meta)).then(|| {
+                let mut book = theirs.clone();
+                book.user = mine.user.clone();
+                if same_file {
+                    book.meta = mine.meta.clone();
+                }
+                book.modified = mine.modified.or(Some(crate::book::unix_now()));
+                book
+            })
+        })
+        .collect()
+}
+
+/// Reads a library database file, local or on a server
+pub fn read_library_file(path: &Path) -> io::Result<Vec<Book>> {
+    if crate::remote::is_remote(path) {
+        let data = crate::remote::read_file(path)?;
+        crate::database::from_text(path, &String::from_utf8_lossy(&data))
+    } else {
+        crate::merge::load_books(path)
+    }
+}
+
+/// Writes a library database file, local or on a server
+pub fn write_library_file(path: &Path, books: &[Book]) -> io::Result<()> {
+    if crate::remote::is_remote(path) {
+        crate::remote::write_file(path, crate::database::to_text(books)?.as_bytes())
+    } else {
+        crate::database::save(path, books)
+    }
+}
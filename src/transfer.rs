@@ -0,0 +1,1050 @@
+// src/transfer.rs
+// Transfer queue - every download and upload of books, with progress
+//
+// Downloads (books accepted from a peer, books fetched by a sync, remote
+// books being opened, books of a Calibre server or a feed), copies to e-readers,
+// books sent by email, books added to Calibre, conversions to other
+// formats and optimizations are queued as jobs. The main loop calls `poll`
+// on every pass: it starts queued jobs on their own threads (a few at a
+// time) and hands back the ones that ended, so their books can be added or
+// opened.
+//
+// Uploads (peers downloading from our peer server) run on the server's
+// thread; they're only tracked, so they show up in the same list.
+//
+// Every transfer reads through a `Tracked` reader, which counts the bytes,
+// measures the speed, waits while the transfer is paused and fails once it
+// was cancelled.
+//
+// Downloads go through `fetch_chunked`: the file is asked for in ranges and
+// written to a partial file. A range that fails is asked for again (from
+// where it stopped) a few times; when the download still fails, the partial
+// file stays, and retrying the transfer picks up where it was. Partial files
+// are named after the source of the file (see `peer::partial_path`), and
+// the size and ETag (or Last-Modified) the server gave are kept next to
+// them: a file that changed on the server since is downloaded again from
+// the start. Completed files are checked against the size (and content
+// hash) the source gave.
+
+use crate::book::Book;
+use crate::calibre::CalibreBook;
+use crate::convert::Format;
+use crate::device::Conversion;
+use crate::feeds::FeedEntry;
+use crate::peer::CatalogEntry;
+use crate::settings::{CalibreSettings, EmailSettings};
+use crate::trust::Secret;
+use std::fs::OpenOptions;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How many downloads run at the same time (paused ones don't count)
+const MAX_RUNNING: usize = 2;
+
+/// How often the speed is measured again
+const SPEED_WINDOW: Duration = Duration::from_secs(1);
+
+/// How long a paused transfer sleeps before looking at its state again
+const PAUSE_POLL: Duration = Duration::from_millis(100);
+
+/// Bytes asked for per request by chunked downloads
+const CHUNK_SIZE: u64 = 32 * 1024 * 1024;
+
+/// Requests for a chunk before a download fails (retrying it resumes)
+const CHUNK_ATTEMPTS: u32 = 5;
+
+/// Wait before asking for a chunk again (doubled after each failure)
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Which way the data goes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    /// To this machine
+    Download,
+
+    /// From this machine to a peer or a device
+    Upload,
+
+    /// Nowhere: a file made on this machine (conversions)
+    Local,
+}
+
+/// Where a transfer stands
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransferState {
+    /// Waiting for a free slot
+    Queued,
+
+    /// Moving data
+    Running,
+
+    /// Stopped by the user, can be resumed
+    Paused,
+
+    /// Finished successfully
+    Done,
+
+    /// Stopped by an error
+    Failed(String),
+
+    /// Stopped by the user for good
+    Cancelled,
+}
+
+impl TransferState {
+    /// Whether the transfer ended (one way or another)
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Done | Self::Failed(_) | Self::Cancelled)
+    }
+
+    /// Short label for the transfer list
+    pub fn label(&self) -> &str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Paused => "paused",
+            Self::Done => "done",
+            Self::Failed(_) => "failed",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// A download the queue runs
+#[derive(Debug, Clone)]
+pub enum Job {
+    /// A book of a peer's catalog, downloaded into a folder of the library
+    PeerBook {
+        /// The peer
+        address: SocketAddr,
+
+        /// The book, as described by the peer
+        entry: Box<CatalogEntry>,
+
+        /// Where the file goes
+        folder: PathBuf,
+
+        /// The peer's copy of the book when it comes with a sync (its user
+        /// data is kept), None for an accepted offer
+        synced: Option<Box<Book>>,
+
+        /// Our pair secret with the peer (None if we didn't pair)
+        secret: Option<Secret>,
+    },
+
+    /// A book of a remote scan root, downloaded into the cache to be opened
+    RemoteBook {
+        /// Path (URL) of the book
+        path: PathBuf,
+    },
+
+    /// A book of the library, copied to an e-reader
+    ToDevice {
+        /// The library file
+        source: PathBuf,
+
+        /// Name of the device, e.g. "Kindle"
+        device: String,
+
+        /// The device's documents folder
+        documents: PathBuf,
+
+        /// Conversion to do first (None = copy the EPUB as is)
+        conversion: Option<Conversion>,
+    },
+
+    /// A book of the library, sent by email as an attachment
+    Email {
+        /// The library file
+        source: PathBuf,
+
+        /// Title of the book (the subject)
+        title: String,
+
+        /// Recipient address
+        to: String,
+
+        /// Server and login, as configured when the book was queued
+        settings: Box<EmailSettings>,
+    },
+
+    /// A book of a Calibre library, downloaded into a folder of the library
+    FromCalibre {
+        /// The book, as described by Calibre
+        book: Box<CalibreBook>,
+
+        /// Where the file goes
+        folder: PathBuf,
+
+        /// The server, as configured when the book was queued
+        settings: Box<CalibreSettings>,
+    },
+
+    /// The EPUB of a feed entry, downloaded into a folder of the library
+    FromFeed {
+        /// The entry, as the feed describes it
+        entry: Box<FeedEntry>,
+
+        /// Where the file goes
+        folder: PathBuf,
+    },
+
+    /// A book of the library, added to a Calibre library
+    ToCalibre {
+        /// The library file
+        source: PathBuf,
+
+        /// The server, as configured when the book was queued
+        settings: Box<CalibreSettings>,
+    },
+
+    /// A book converted to another format with ebook-convert
+    Convert {
+        /// The book to convert
+        source: PathBuf,
+
+        /// Format to convert to
+        format: Format,
+
+        /// Where the converted file goes
+        folder: PathBuf,
+
+        /// Converter command, as configured when the conversion was queued
+        command: String,
+    },
+
+    /// A book made smaller (see optimize.rs)
+    Optimize {
+        /// The book, replaced by the smaller one
+        source: PathBuf,
+
+        /// Its size when the optimization was queued, in bytes
+        size: u64,
+    },
+}
+
+impl Job {
+    /// What the transfer list shows for the job
+    fn label(&self) -> String {
+        match self {
+            Job::PeerBook { address, entry, .. } => format!("{} from {}", entry.name, address.ip()),
+            Job::RemoteBook { path } => path.display().to_string(),
+            Job::ToDevice { source, device, .. } => format!(
+                "{} to {}",
+                source.file_name().unwrap_or_default().to_string_lossy(),
+                device
+            ),
+            Job::Email { source, to, .. } => format!(
+                "{} to {}",
+                source.file_name().unwrap_or_default().to_string_lossy(),
+                to
+            ),
+            Job::FromCalibre { book, .. } => format!("{} from Calibre", book.file_name()),
+            Job::FromFeed { entry, .. } => format!("{} from {}", entry.file_name(), entry.feed),
+            Job::ToCalibre { source, .. } => format!(
+                "{} to Calibre",
+                source.file_name().unwrap_or_default().to_string_lossy()
+            ),
+            Job::Convert { source, format, .. } => format!(
+                "{} to {}",
+                source.file_name().unwrap_or_default().to_string_lossy(),
+                format.label()
+            ),
+            Job::Optimize { source, .. } => format!(
+                "{} (optimize)",
+                source.file_name().unwrap_or_default().to_string_lossy()
+            ),
+        }
+    }
+
+    /// Which way the data goes
+    fn direction(&self) -> Direction {
+        match self {
+            Job::PeerBook { .. } | Job::RemoteBook { .. } | Job::FromCalibre { .. } | Job::FromFeed { .. } => {
+                Direction::Download
+            }
+            Job::ToDevice { .. } | Job::Email { .. } | Job::ToCalibre { .. } => Direction::Upload,
+            Job::Convert { .. } | Job::Optimize { .. } => Direction::Local,
+        }
+    }
+
+    /// Size in bytes, when known before the transfer starts
+    fn size(&self) -> Option<u64> {
+        match self {
+            Job::PeerBook { entry, .. } => entry.size,
+            // Conversions and optimizations count in percent, once they run
+            Job::RemoteBook { .. } | Job::Convert { .. } | Job::Optimize { .. } => None,
+            // Conversion changes it
+            Job::ToDevice { conversion: Some(_), .. } => None,
+            Job::FromCalibre { book, .. } => book.size,
+            Job::FromFeed { entry, .. } => entry.size,
+            Job::ToDevice { source, .. } | Job::Email { source, .. } | Job::ToCalibre { source, .. } => {
+                std::fs::metadata(source).ok().map(|m| m.len())
+            }
+        }
+    }
+
+    /// Downloads (or sends) the file
+    ///
+    /// # Returns
+    /// Path of the local file (the sent one for uploads)
+    fn run(&self, progress: &Progress) -> Result<PathBuf, String> {
+        match self {
+            Job::PeerBook {
+                address,
+                entry,
+                folder,
+                secret,
+                ..
+            } => crate::peer::download(*address, entry, folder, progress, secret.as_ref()),
+            Job::RemoteBook { path } => crate::remote::fetch(path, progress).map_err(|e| e.to_string()),
+            Job::ToDevice {
+                source,
+                documents,
+                conversion,
+                ..
+            } => crate::device::send(source, documents, conversion.as_ref(), progress),
+            Job::Email {
+                source,
+                title,
+                to,
+                settings,
+            } => crate::mail::send(settings, to, title, source, progress).map(|()| source.clone()),
+            Job::FromCalibre { book, folder, settings } => {
+                crate::calibre::Calibre::connect(settings)?.download(book, folder, progress)
+            }
+            Job::FromFeed { entry, folder } => crate::feeds::download(entry, folder, progress),
+            Job::ToCalibre { source, settings } => crate::calibre::Calibre::connect(settings)?
+                .upload(source, settings.duplicates, progress)
+                .map(|_| source.clone()),
+            Job::Convert {
+                source,
+                format,
+                folder,
+                command,
+            } => {
+                let output = crate::convert::output_path(source, folder, *format);
+                progress.set_total(100);
+                crate::convert::run(command, source, &output, |done| progress.resume_at(done as u64), || {
+                    progress.is_cancelled()
+                })?;
+                Ok(output)
+            }
+            Job::Optimize { source, .. } => {
+                progress.set_total(100);
+                crate::optimize::optimize(source, |done| progress.resume_at(done as u64), || progress.is_cancelled())
+                    .map_err(|e| e.to_string())?;
+                Ok(source.clone())
+            }
+        }
+    }
+}
+
+/// A download that ended, handed to the main loop by `poll`
+pub struct Finished {
+    /// The job that ran
+    pub job: Job,
+
+    /// Path of the local file, or why there is none
+    pub result: Result<PathBuf, String>,
+}
+
+/// One transfer, as shown in the transfer list
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    /// Identifier, unique in this session
+    pub id: u64,
+
+    /// What is transferred, e.g. "Dune.epub from 192.168.1.20"
+    pub label: String,
+
+    /// Which way the data goes
+    pub direction: Direction,
+
+    /// Where the transfer stands
+    pub state: TransferState,
+
+    /// Bytes transferred so far
+    pub done: u64,
+
+    /// Size in bytes, if known
+    pub total: Option<u64>,
+
+    /// Bytes per second over the last second or so
+    pub speed: f64,
+
+    /// Start of the current speed measurement, and the byte count then
+    window: Option<(Instant, u64)>,
+
+    /// Whether the transfer was started (a paused one resumes running,
+    /// not queued)
+    started: bool,
+}
+
+impl Transfer {
+    /// How far the transfer got, from 0.0 to 1.0 (None if the size is unknown)
+    pub fn ratio(&self) -> Option<f64> {
+        match self.total {
+            Some(total) if total > 0 => Some((self.done as f64 / total as f64).min(1.0)),
+            _ if self.state == TransferState::Done => Some(1.0),
+            _ => None,
+        }
+    }
+
+    /// Counts newly transferred bytes and measures the speed
+    fn advance(&mut self, bytes: u64) {
+        self.done += bytes;
+        let now = Instant::now();
+        match self.window {
+            Some((start, at)) if now.duration_since(start) >= SPEED_WINDOW => {
+                self.speed = (self.done - at) as f64 / now.duration_since(start).as_secs_f64();
+                self.window = Some((now, self.done));
+            }
+            Some(_) => {}
+            None => self.window = Some((now, self.done)),
+        }
+    }
+}
+
+/// One transfer with what the queue needs to run it
+struct Entry {
+    /// Shown state, shared with the thread moving the data
+    transfer: Arc<Mutex<Transfer>>,
+
+    /// The download to run (None for tracked uploads)
+    job: Option<Job>,
+
+    /// Path of the local file once the job ended, until `poll` hands it over
+    result: Option<Result<PathBuf, String>>,
+
+    /// Whether the job's thread was started
+    started: bool,
+}
+
+/// The transfers of this session (cheap to clone: clones share the queue)
+#[derive(Clone, Default)]
+pub struct Transfers {
+    /// Every transfer, oldest first
+    entries: Arc<Mutex<Vec<Entry>>>,
+
+    /// Identifier of the next transfer
+    next_id: Arc<Mutex<u64>>,
+}
+
+impl Transfers {
+    /// Creates an empty queue
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a download (or a copy to a device)
+    ///
+    /// # Returns
+    /// Identifier of the transfer
+    pub fn enqueue(&self, job: Job) -> u64 {
+        let transfer = self.create(job.label(), job.direction(), job.size(), TransferState::Queued);
+        let id = transfer.lock().map(|t| t.id).unwrap_or_default();
+        tracing::debug!(id, label = %job.label(), "transfer queued");
+        self.push(Entry {
+            transfer,
+            job: Some(job),
+            result: None,
+            started: false,
+        });
+        id
+    }
+
+    /// Tracks a transfer done by someone else (an upload of the peer server)
+    ///
+    /// # Arguments
+    /// * `label` - What is transferred
+    /// * `total` - Size in bytes, if known
+    ///
+    /// # Returns
+    /// Progress handle to read through, and to report the end with
+    pub fn track(&self, label: String, total: Option<u64>) -> Progress {
+        let transfer = self.create(label, Direction::Upload, total, TransferState::Running);
+        self.push(Entry {
+            transfer: Arc::clone(&transfer),
+            job: None,
+            result: None,
+            started: true,
+        });
+        Progress { transfer }
+    }
+
+    /// Starts queued downloads while there are free slots, and takes the
+    /// downloads that ended since the last call
+    pub fn poll(&self) -> Vec<Finished> {
+        let Ok(mut entries) = self.entries.lock() else {
+            return Vec::new();
+        };
+
+        let mut running = entries
+            .iter()
+            .filter(|e| e.job.is_some() && state_of(&e.transfer) == TransferState::Running)
+            .count();
+        for entry in entries.iter_mut() {
+            if running >= MAX_RUNNING {
+                break;
+            }
+            if entry.started || state_of(&entry.transfer) != TransferState::Queued {
+                continue;
+            }
+            let Some(job) = entry.job.clone() else {
+                continue;
+            };
+            entry.started = true;
+            running += 1;
+            if let Ok(mut transfer) = entry.transfer.lock() {
+                transfer.state = TransferState::Running;
+                transfer.started = true;
+            }
+
+            let progress = Progress {
+                transfer: Arc::clone(&entry.transfer),
+            };
+            let entries = Arc::clone(&self.entries);
+            std::thread::spawn(move || {
+                let result = job.run(&progress);
+                progress.finish(&result);
+                if let Ok(mut entries) = entries.lock() {
+                    if let Some(entry) = entries.iter_mut().find(|e| Arc::ptr_eq(&e.transfer, &progress.transfer)) {
+                        entry.result = Some(result);
+                    }
+                }
+            });
+        }
+
+        // Cancelled before they started: nothing will ever report them
+        let mut finished = Vec::new();
+        for entry in entries.iter_mut() {
+            if !entry.started && state_of(&entry.transfer) == TransferState::Cancelled {
+                entry.started = true;
+                entry.result = Some(Err("cancelled".to_string()));
+            }
+            if let (Some(result), Some(job)) = (entry.result.take(), entry.job.clone()) {
+                finished.push(Finished { job, result });
+            }
+        }
+        finished
+    }
+
+    /// Snapshot of every transfer, oldest first
+    pub fn list(&self) -> Vec<Transfer> {
+        self.entries
+            .lock()
+            .map(|entries| {
+                entries
+                    .iter()
+                    .filter_map(|e| e.transfer.lock().ok().map(|t| t.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// How many transfers are queued, running or paused
+    pub fn active_count(&self) -> usize {
+        self.list().iter().filter(|t| !t.state.is_finished()).count()
+    }
+
+    /// Pauses a queued or running transfer, or resumes a paused one
+    /// (conversions can't be paused)
+    pub fn toggle_pause(&self, id: u64) {
+        self.update(id, |transfer| {
+            if transfer.direction == Direction::Local {
+                return;
+            }
+            transfer.state = match transfer.state {
+                TransferState::Queued | TransferState::Running => TransferState::Paused,
+                // A download that never started goes back to the queue
+                TransferState::Paused if !transfer.started => TransferState::Queued,
+                TransferState::Paused => TransferState::Running,
+                ref other => other.clone(),
+            };
+            // The pause doesn't count in the speed
+            transfer.window = None;
+            transfer.speed = 0.0;
+        });
+    }
+
+    /// Cancels a transfer that hasn't ended
+    pub fn cancel(&self, id: u64) {
+        self.update(id, |transfer| {
+            if !transfer.state.is_finished() {
+                transfer.state = TransferState::Cancelled;
+            }
+        });
+    }
+
+    /// Queues a failed (or cancelled) download again; it resumes from its
+    /// partial file
+    pub fn retry(&self, id: u64) {
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        for entry in entries.iter_mut().filter(|e| e.job.is_some() && e.result.is_none()) {
+            let Ok(mut transfer) = entry.transfer.lock() else {
+                continue;
+            };
+            if transfer.id != id || !matches!(transfer.state, TransferState::Failed(_) | TransferState::Cancelled) {
+                continue;
+            }
+            transfer.state = TransferState::Queued;
+            transfer.started = false;
+            transfer.done = 0;
+            transfer.speed = 0.0;
+            transfer.window = None;
+            entry.started = false;
+            tracing::debug!(id, label = %transfer.label, "transfer queued again");
+            return;
+        }
+    }
+
+    /// Removes the transfers that ended from the list
+    pub fn clear_finished(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.retain(|e| !state_of(&e.transfer).is_finished() || e.result.is_some());
+        }
+    }
+
+    /// Creates the shown state of a new transfer
+    fn create(&self, label: String, direction: Direction, total: Option<u64>, state: TransferState) -> Arc<Mutex<Transfer>> {
+        let id = match self.next_id.lock() {
+            Ok(mut next) => {
+                *next += 1;
+                *next
+            }
+            Err(_) => 0,
+        };
+        Arc::new(Mutex::new(Transfer {
+            id,
+            label,
+            direction,
+            state: state.clone(),
+            done: 0,
+            total,
+            speed: 0.0,
+            window: None,
+            started: state == TransferState::Running,
+        }))
+    }
+
+    /// Adds a transfer to the list
+    fn push(&self, entry: Entry) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.push(entry);
+        }
+    }
+
+    /// Changes one transfer (by id)
+    fn update(&self, id: u64, change: impl FnOnce(&mut Transfer)) {
+        let Ok(entries) = self.entries.lock() else {
+            return;
+        };
+        for entry in entries.iter() {
+            if let Ok(mut transfer) = entry.transfer.lock() {
+                if transfer.id == id {
+                    change(&mut transfer);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Handle of one transfer, for the code moving its data
+pub struct Progress {
+    /// The shown state
+    transfer: Arc<Mutex<Transfer>>,
+}
+
+impl Progress {
+    /// Sets the size once it's known
+    pub fn set_total(&self, total: u64) {
+        if let Ok(mut transfer) = self.transfer.lock() {
+            transfer.total = Some(total);
+        }
+    }
+
+    /// Sets how much was transferred, when a download resumes (or a range
+    /// is asked for again)
+    pub fn resume_at(&self, done: u64) {
+        if let Ok(mut transfer) = self.transfer.lock() {
+            transfer.done = done;
+            transfer.window = None;
+        }
+    }
+
+    /// Whether the transfer was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        state_of(&self.transfer) == TransferState::Cancelled
+    }
+
+    /// Wraps a reader so reading through it counts as progress
+    pub fn reader<R: Read>(&self, inner: R) -> Tracked<R> {
+        Tracked {
+            inner,
+            transfer: Arc::clone(&self.transfer),
+        }
+    }
+
+    /// Records how the transfer ended
+    pub fn finish<T, E: ToString>(&self, result: &Result<T, E>) {
+        if let Ok(mut transfer) = self.transfer.lock() {
+            // A cancelled transfer stays cancelled, whatever error that caused
+            if transfer.state == TransferState::Cancelled {
+                return;
+            }
+            transfer.state = match result {
+                Ok(_) => TransferState::Done,
+                Err(e) => TransferState::Failed(e.to_string()),
+            };
+            transfer.speed = 0.0;
+        }
+    }
+}
+
+/// A reader that reports to a transfer (see `Progress::reader`)
+pub struct Tracked<R> {
+    /// The data
+    inner: R,
+
+    /// The shown state
+    transfer: Arc<Mutex<Transfer>>,
+}
+
+impl<R: Read> Read for Tracked<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Hold the data back while paused
+        loop {
+            match state_of(&self.transfer) {
+                TransferState::Paused => std::thread::sleep(PAUSE_POLL),
+                // Not ErrorKind::Interrupted: io::copy would retry forever
+                TransferState::Cancelled => return Err(io::Error::other("cancelled")),
+                _ => break,
+            }
+        }
+
+        let read = self.inner.read(buf)?;
+        if let Ok(mut transfer) = self.transfer.lock() {
+            transfer.advance(read as u64);
+        }
+        Ok(read)
+    }
+}
+
+/// A piece of a file, as a server sent it
+pub struct Chunk {
+    /// The data
+    pub reader: Box<dyn Read>,
+
+    /// Where in the file the data starts (0 when the server ignored the
+    /// range and sends the whole file)
+    pub start: u64,
+
+    /// Size of the whole file, if the server said
+    pub total: Option<u64>,
+
+    /// What tells versions of the file apart (ETag, or Last-Modified), if
+    /// the server said
+    pub tag: Option<String>,
+}
+
+impl Chunk {
+    /// Where the data of an HTTP answer starts in the file, and the size of
+    /// the file: from Content-Range for 206 answers, the whole file (with
+    /// its Content-Length) for the others
+    pub fn position(response: &ureq::Response) -> (u64, Option<u64>) {
+        if response.status() != 206 {
+            return (0, response.header("Content-Length").and_then(|l| l.parse().ok()));
+        }
+        // "bytes 100-199/1000" (or ".../*" when the size isn't known)
+        let range = response.header("Content-Range").and_then(|value| value.trim().strip_prefix("bytes "));
+        let start = range
+            .and_then(|r| r.split('-').next())
+            .and_then(|start| start.trim().parse().ok())
+            .unwrap_or(0);
+        let total = range
+            .and_then(|r| r.rsplit('/').next())
+            .and_then(|total| total.trim().parse().ok());
+        (start, total)
+    }
+
+    /// What tells versions of the file of an HTTP answer apart: its ETag,
+    /// or its Last-Modified date
+    pub fn tag(response: &ureq::Response) -> Option<String> {
+        response
+            .header("ETag")
+            .or_else(|| response.header("Last-Modified"))
+            .map(|tag| tag.trim().to_string())
+    }
+}
+
+/// What a partial file was downloaded from: the size and tag the server
+/// gave, kept next to it ("{partial}.tag", a line each)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct PartialSource {
+    total: Option<u64>,
+    tag: Option<String>,
+}
+
+impl PartialSource {
+    /// Where the source of a partial file is kept
+    fn path(partial: &Path) -> PathBuf {
+        let mut name = partial.as_os_str().to_owned();
+        name.push(".tag");
+        PathBuf::from(name)
+    }
+
+    /// The source kept for a partial file (None if there's none)
+    fn load(partial: &Path) -> Option<Self> {
+        let text = std::fs::read_to_string(Self::path(partial)).ok()?;
+        let mut lines = text.lines();
+        let total = lines.next()?.parse().ok();
+        let tag = lines.next().map(str::to_string).filter(|tag| !tag.is_empty());
+        Some(Self { total, tag })
+    }
+
+    /// Keeps the source of a partial file
+    fn save(&self, partial: &Path) -> io::Result<()> {
+        let total = self.total.map(|total| total.to_string()).unwrap_or_default();
+        std::fs::write(Self::path(partial), format!("{}\n{}\n", total, self.tag.as_deref().unwrap_or("")))
+    }
+
+    /// Whether a server's answer is about another version of the file
+    /// (what neither side said can't tell them apart)
+    fn differs(&self, chunk: &Chunk) -> bool {
+        fn differ<T: PartialEq>(ours: &Option<T>, theirs: &Option<T>) -> bool {
+            matches!((ours, theirs), (Some(a), Some(b)) if a != b)
+        }
+        differ(&self.total, &chunk.total) || differ(&self.tag, &chunk.tag)
+    }
+
+    /// Forgets the source of a partial file (done, or started over)
+    fn remove(partial: &Path) {
+        let _ = std::fs::remove_file(Self::path(partial));
+    }
+}
+
+/// Downloads a file range by range into a partial file, starting where an
+/// earlier attempt stopped - unless the server's file changed since (its
+/// size or tag differ from those kept with the partial file)
+///
+/// # Arguments
+/// * `partial` - The partial file (kept when the download fails, so that
+///   retrying resumes; removed when it's cancelled)
+/// * `total` - Size of the file, if known beforehand
+/// * `progress` - The transfer the download is shown as
+/// * `fetch` - Asks the server for the bytes `start..=end` of the file
+///
+/// # Returns
+/// Size of the downloaded file
+pub fn fetch_chunked(
+    partial: &Path,
+    mut total: Option<u64>,
+    progress: &Progress,
+    mut fetch: impl FnMut(u64, u64) -> Result<Chunk, String>,
+) -> Result<u64, String> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(partial)
+        .map_err(|e| format!("{}: {}", partial.display(), e))?;
+    let mut offset = file.metadata().map(|m| m.len()).unwrap_or(0);
+    if total.is_some_and(|total| offset > total) {
+        offset = 0;
+    }
+    // Checked against the server's first answer, when resuming
+    let mut kept = if offset > 0 { PartialSource::load(partial) } else { None };
+    if offset > 0 {
+        tracing::info!(path = %partial.display(), offset, "download resumed");
+    }
+
+    let mut failures = 0;
+    loop {
+        if let Some(total) = total {
+            progress.set_total(total);
+            if offset >= total {
+                break;
+            }
+        }
+        progress.resume_at(offset);
+        let end = match total {
+            Some(total) => (offset + CHUNK_SIZE).min(total) - 1,
+            None => offset + CHUNK_SIZE - 1,
+        };
+
+        let mut copy = |chunk: Chunk| -> io::Result<u64> {
+            file.set_len(chunk.start)?;
+            file.seek(SeekFrom::Start(chunk.start))?;
+            io::copy(&mut progress.reader(chunk.reader), &mut file)
+        };
+        let chunk = match fetch(offset, end) {
+            // Another version of the file: what we have of it is no use
+            Ok(chunk) if offset > 0 && kept.as_ref().is_some_and(|kept| kept.differs(&chunk)) => {
+                tracing::info!(path = %partial.display(), "file changed on the server, downloading it again");
+                kept = None;
+                offset = 0;
+                file.set_len(0).map_err(|e| e.to_string())?;
+                continue;
+            }
+            Ok(chunk) => {
+                let source = PartialSource {
+                    total: chunk.total,
+                    tag: chunk.tag.clone(),
+                };
+                if kept.as_ref() != Some(&source) {
+                    if let Err(e) = source.save(partial) {
+                        tracing::debug!(path = %partial.display(), error = %e, "cannot keep the download's source");
+                    }
+                    kept = Some(source);
+                }
+                Ok(chunk)
+            }
+            Err(e) => Err(e),
+        };
+        let result = chunk.and_then(|chunk| {
+            let start = chunk.start;
+            total = chunk.total.or(total);
+            copy(chunk).map(|copied| (start, copied)).map_err(|e| e.to_string())
+        });
+
+        match result {
+            Ok((start, copied)) => {
+                failures = 0;
+                let asked = offset;
+                offset = start + copied;
+                // A short answer, or the whole file instead of the range,
+                // ends a download of unknown size
+                if total.is_none() && (start != asked || copied != end - asked + 1) {
+                    total = Some(offset);
+                }
+                if total.is_some_and(|total| offset < total) && copied == 0 {
+                    return Err("the server sent nothing".to_string());
+                }
+            }
+            Err(e) if progress.is_cancelled() => {
+                drop(file);
+                let _ = std::fs::remove_file(partial);
+                PartialSource::remove(partial);
+                return Err(e);
+            }
+            Err(e) => {
+                failures += 1;
+                // What arrived before the failure is kept
+                offset = file.metadata().map(|m| m.len()).unwrap_or(0);
+                if failures >= CHUNK_ATTEMPTS {
+                    return Err(format!("{} (retry to resume at {})", e, human_bytes(offset as f64)));
+                }
+                tracing::debug!(path = %partial.display(), offset, error = %e, failures, "chunk failed, asking again");
+                std::thread::sleep(RETRY_DELAY * 2u32.pow(failures - 1));
+            }
+        }
+    }
+
+    file.sync_all().map_err(|e| e.to_string())?;
+    PartialSource::remove(partial);
+    Ok(offset)
+}
+
+/// Checks a downloaded file, then gives it its final name
+///
+/// A file that doesn't match is removed: downloading it again starts over.
+///
+/// # Arguments
+/// * `partial` - The downloaded file
+/// * `dest` - Its final path
+/// * `size` - Expected size, if the source gave one
+/// * `hash` - Expected content hash (see `hash::content_hash`), if known
+pub fn complete(partial: &Path, dest: &Path, size: Option<u64>, hash: Option<&str>) -> Result<(), String> {
+    let actual = std::fs::metadata(partial).map(|m| m.len()).map_err(|e| e.to_string())?;
+    let problem = match (size, hash) {
+        (Some(size), _) if size != actual => Some(format!("{} bytes instead of {}", actual, size)),
+        (_, Some(hash)) => match crate::hash::content_hash(partial) {
+            Ok(actual) if actual == hash => None,
+            Ok(_) => Some("content hash mismatch".to_string()),
+            Err(e) => return Err(e.to_string()),
+        },
+        _ => None,
+    };
+    if let Some(problem) = problem {
+        let _ = std::fs::remove_file(partial);
+        tracing::warn!(path = %dest.display(), problem = %problem, "downloaded file is damaged");
+        return Err(format!("damaged download ({}), try again", problem));
+    }
+    std::fs::rename(partial, dest).map_err(|e| e.to_string())
+}
+
+/// Current state of a transfer
+fn state_of(transfer: &Mutex<Transfer>) -> TransferState {
+    transfer
+        .lock()
+        .map(|t| t.state.clone())
+        .unwrap_or(TransferState::Cancelled)
+}
+
+/// Formats a byte count for the transfer list, e.g. "1.4 MB"
+pub fn human_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", value as u64, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A server with one version of a file, answering ranges of it
+    fn serve(data: &'static [u8], tag: &'static str) -> impl FnMut(u64, u64) -> Result<Chunk, String> {
+        move |start, end| {
+            let end = (end as usize).min(data.len() - 1);
+            Ok(Chunk {
+                reader: Box::new(&data[start as usize..=end]),
+                start,
+                total: Some(data.len() as u64),
+                tag: Some(tag.to_string()),
+            })
+        }
+    }
+
+    #[test]
+    fn resumes_only_the_same_version() {
+        let dir = std::env::temp_dir().join(format!("funkhunt-transfer-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let partial = dir.join(".book.epub.part");
+        let progress = Transfers::new().track("book".to_string(), None);
+
+        // Same version: what's there is kept
+        std::fs::write(&partial, b"hello").unwrap();
+        let v1 = PartialSource {
+            total: Some(11),
+            tag: Some("v1".to_string()),
+        };
+        v1.save(&partial).unwrap();
+        fetch_chunked(&partial, None, &progress, serve(b"XXXXX world", "v1")).unwrap();
+        assert_eq!(std::fs::read(&partial).unwrap(), b"hello world");
+        assert!(!PartialSource::path(&partial).exists());
+
+        // Changed on the server: downloaded again from the start
+        std::fs::write(&partial, b"hello").unwrap();
+        v1.save(&partial).unwrap();
+        fetch_chunked(&partial, None, &progress, serve(b"HELLO WORLD", "v2")).unwrap();
+        assert_eq!(std::fs::read(&partial).unwrap(), b"HELLO WORLD");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,878 @@
+// src/trust.rs
+// Trust between peers - pairing, per-peer keys and encrypted transfers
+//
+// Every instance has a random id (identity.json in the data directory).
+// Two instances pair once, with fresh X25519 keys. The asking peer first
+// commits to its key (sends the SHA-256 of it and its id), gets the other
+// key, then reveals its own: neither side can pick its key once it saw the
+// other's. Both derive the same secret, and a 9-digit PIN from the hash of
+// the whole exchange (both keys, both ids). When the users see the same
+// PIN on both screens, nobody sat in the middle - someone there would have
+// one guess in a billion - and each side stores the other's id with the key
+// derived from the secret (trusted_peers.json - the allowlist managed from
+// the peers screen).
+//
+// Requests between paired peers carry the sender's id, the time, a random
+// nonce and the SHA-256 of their body, and an HMAC of all of these under
+// the pair key. A request is taken once: the nonces seen within the clock
+// skew allowed are remembered, and older requests are refused. Bodies (catalogs, books, offers, syncs)
+// travel as a sealed stream: a random salt, then ChaCha20-Poly1305 frames
+// encrypted with a key derived from the pair key and the salt.
+//
+// Each paired peer also has a share policy, enforced by our peer server:
+// the whole library, some collections only, or every download approved by
+// the user first. Unpaired peers (if let in at all) see the whole library,
+// but can't sync with it.
+
+use crate::book::Book;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305};
+use ring::agreement::{self, EphemeralPrivateKey, UnparsedPublicKey, X25519};
+use ring::digest::{digest, SHA256};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// File (inside the data directory) holding this instance's id
+const IDENTITY_FILE: &str = "identity.json";
+
+/// File (inside the data directory) holding the paired peers and their keys
+const TRUSTED_FILE: &str = "trusted_peers.json";
+
+/// Header naming the sending instance
+pub const PEER_HEADER: &str = "X-FunkHunt-Peer";
+
+/// Header with the time the request was made (seconds since 1970)
+pub const TIME_HEADER: &str = "X-FunkHunt-Time";
+
+/// Header with a random value used once, so a request isn't taken twice
+pub const NONCE_HEADER: &str = "X-FunkHunt-Nonce";
+
+/// Header with the SHA-256 of the request's body as sent (sealed)
+pub const BODY_HEADER: &str = "X-FunkHunt-Body";
+
+/// Header with the HMAC of the request under the pair key
+pub const AUTH_HEADER: &str = "X-FunkHunt-Auth";
+
+/// How far the clocks of two peers may differ (and how long the nonces
+/// of requests are remembered)
+const MAX_CLOCK_SKEW: u64 = 5 * 60;
+
+/// Random bytes of a request's nonce
+const NONCE_LEN: usize = 16;
+
+/// Digits of the PIN compared when pairing
+const PIN_DIGITS: u32 = 9;
+
+/// Plaintext bytes per frame of a sealed stream
+const FRAME_SIZE: usize = 64 * 1024;
+
+/// Bytes of random salt in front of a sealed stream
+const SALT_LEN: usize = 16;
+
+/// Bit of the frame header marking the last frame (a cut stream is noticed)
+const FINAL_FRAME: u32 = 1 << 31;
+
+/// This instance as other peers know it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Identity {
+    /// Random id, 32 hex characters
+    id: String,
+}
+
+/// A peer the user paired with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustedPeer {
+    /// The peer's instance id
+    pub id: String,
+
+    /// Name the peer had when paired, e.g. "Living room"
+    pub name: String,
+
+    /// Pair key, hex
+    key: String,
+
+    /// When the pairing was confirmed (seconds since 1970)
+    pub paired: u64,
+
+    /// What the peer may see and download
+    #[serde(default)]
+    pub policy: SharePolicy,
+}
+
+/// What a paired peer may get from our library
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SharePolicy {
+    /// Every book (archived ones aside), and two-way sync
+    #[default]
+    Library,
+
+    /// Only the books in these collections; no sync
+    Collections(Vec<String>),
+
+    /// Every book is listed, but each download waits for the user's
+    /// approval; no sync
+    Ask,
+}
+
+impl SharePolicy {
+    /// Whether a book is listed for the peer (downloads may still need an
+    /// approval, see `needs_approval`)
+    pub fn shares(&self, book: &Book) -> bool {
+        match self {
+            SharePolicy::Library | SharePolicy::Ask => !book.user.archived,
+            SharePolicy::Collections(names) => {
+                !book.user.archived && book.user.collections.iter().any(|c| names.contains(c))
+            }
+        }
+    }
+
+    /// Whether downloads need the user's approval
+    pub fn needs_approval(&self) -> bool {
+        *self == SharePolicy::Ask
+    }
+
+    /// Whether the peer may sync (it sees and changes every book)
+    pub fn allows_sync(&self) -> bool {
+        *self == SharePolicy::Library
+    }
+
+    /// Short description for the peers screen, e.g. "2 collections"
+    pub fn label(&self) -> String {
+        match self {
+            SharePolicy::Library => "whole library".to_string(),
+            SharePolicy::Collections(names) if names.len() == 1 => format!("collection {}", names[0]),
+            SharePolicy::Collections(names) => format!("{} collections", names.len()),
+            SharePolicy::Ask => "ask first".to_string(),
+        }
+    }
+}
+
+/// What a paired connection needs: who we are and the pair key
+#[derive(Clone)]
+pub struct Secret {
+    /// Our instance id
+    pub local_id: String,
+
+    /// The pair key
+    key: [u8; 32],
+}
+
+impl std::fmt::Debug for Secret {
+    /// Never prints the key
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret({})", self.local_id)
+    }
+}
+
+/// A pairing waiting for the user to compare the PINs
+#[derive(Debug, Clone)]
+pub struct Pairing {
+    /// The other peer's instance id
+    pub peer_id: String,
+
+    /// The other peer's name
+    pub name: String,
+
+    /// PIN both screens should show
+    pub pin: String,
+
+    /// Whether we asked (false: the other peer asked us)
+    pub outgoing: bool,
+
+    /// The key both sides derived
+    key: [u8; 32],
+}
+
+impl Pairing {
+    /// The peer to remember once the user confirmed the PIN
+    pub fn trusted(&self) -> TrustedPeer {
+        TrustedPeer {
+            id: self.peer_id.clone(),
+            name: self.name.clone(),
+            key: hex(&self.key),
+            paired: crate::book::unix_now(),
+            policy: SharePolicy::default(),
+        }
+    }
+}
+
+/// The first message of a pairing, from the asking peer: its key is
+/// committed to, not sent yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairRequest {
+    /// Instance id of the sender
+    pub id: String,
+
+    /// Name of the sender
+    pub name: String,
+
+    /// SHA-256 of the sender's public key and id, hex (see `commitment`)
+    pub commitment: String,
+}
+
+/// The answer to a pairing request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairAnswer {
+    /// Instance id of the sender
+    pub id: String,
+
+    /// Name of the sender
+    pub name: String,
+
+    /// Fresh X25519 public key of the sender, hex
+    pub public: String,
+
+    /// Random id of the pairing, which the reveal names
+    pub session: String,
+}
+
+/// The last message of a pairing: the asking peer's key, sent once it got
+/// the other one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairReveal {
+    /// The pairing, as the answer named it
+    pub session: String,
+
+    /// Public key of the sender, hex (matching its commitment)
+    pub public: String,
+}
+
+/// Our half of a pairing: a fresh key pair
+pub struct PairingKey {
+    private: EphemeralPrivateKey,
+
+    /// The public half, hex
+    public: String,
+}
+
+impl PairingKey {
+    /// A fresh key pair
+    pub fn generate() -> io::Result<Self> {
+        let private = EphemeralPrivateKey::generate(&X25519, &SystemRandom::new()).map_err(crypto_error)?;
+        let public = hex(private.compute_public_key().map_err(crypto_error)?.as_ref());
+        Ok(Self { private, public })
+    }
+
+    /// The public half, hex
+    pub fn public(&self) -> &str {
+        &self.public
+    }
+}
+
+/// Both keys and ids of a pairing: what the PIN and the pair key are made of
+#[derive(Debug, Clone)]
+pub struct Transcript {
+    /// Instance id and public key (hex) of the asking peer
+    pub asker: (String, String),
+
+    /// Instance id and public key (hex) of the answering peer
+    pub answerer: (String, String),
+}
+
+impl Transcript {
+    /// SHA-256 of all of it
+    fn hash(&self) -> Vec<u8> {
+        let text = format!(
+            "funkhunt pairing\n{}\n{}\n{}\n{}",
+            self.asker.0, self.asker.1, self.answerer.0, self.answerer.1
+        );
+        digest(&SHA256, text.as_bytes()).as_ref().to_vec()
+    }
+}
+
+/// Our instance id (created on first use, read once per run)
+pub fn local_id() -> String {
+    static ID: OnceLock<String> = OnceLock::new();
+    ID.get_or_init(load_or_create_id).clone()
+}
+
+/// Reads the instance id, creating it if there is none yet
+fn load_or_create_id() -> String {
+    let path = crate::paths::data_dir().join(IDENTITY_FILE);
+    if let Some(identity) = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|text| serde_json::from_str::<Identity>(&text).ok())
+    {
+        return identity.id;
+    }
+
+    let mut bytes = [0u8; 16];
+    // Only fails if the OS has no randomness - then there are bigger problems
+    let _ = SystemRandom::new().fill(&mut bytes);
+    let identity = Identity { id: hex(&bytes) };
+    if let Err(e) = write_private(&path, &identity) {
+        tracing::warn!(path = %path.display(), error = %e, "cannot store instance id");
+    }
+    identity.id
+}
+
+/// Loads the paired peers
+pub fn load_trusted() -> Vec<TrustedPeer> {
+    std::fs::read_to_string(trusted_path())
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+/// Stores the paired peers (readable by the user only: the keys are in there)
+pub fn save_trusted(peers: &[TrustedPeer]) -> io::Result<()> {
+    write_private(&trusted_path(), &peers)
+}
+
+/// The secret for talking to a peer, if we paired with it
+///
+/// # Arguments
+/// * `trusted` - The paired peers
+/// * `peer_id` - The peer's instance id (None for peers that don't announce one)
+pub fn secret_for(trusted: &[TrustedPeer], peer_id: Option<&str>) -> Option<Secret> {
+    let peer = trusted.iter().find(|t| Some(t.id.as_str()) == peer_id)?;
+    Some(Secret {
+        local_id: local_id(),
+        key: unhex(&peer.key)?.try_into().ok()?,
+    })
+}
+
+/// What the asking peer sends before its key: the SHA-256 of the key and
+/// its id, hex
+pub fn commitment(public: &str, id: &str) -> String {
+    hex(digest(&SHA256, format!("funkhunt pairing commitment\n{}\n{}", public, id).as_bytes()).as_ref())
+}
+
+/// A random id, hex (e.g. of a pairing)
+pub fn random_id() -> io::Result<String> {
+    let mut bytes = [0u8; 16];
+    SystemRandom::new().fill(&mut bytes).map_err(crypto_error)?;
+    Ok(hex(&bytes))
+}
+
+/// Ends a pairing: derives the pair key from our private key and the other
+/// peer's public one, and the PIN from the transcript (both are tied to it)
+///
+/// # Arguments
+/// * `key` - Our half of the pairing
+/// * `transcript` - Both keys and ids
+/// * `name` - The other peer's name
+/// * `outgoing` - Whether we asked for the pairing (the other peer answered)
+pub fn agree(key: PairingKey, transcript: &Transcript, name: &str, outgoing: bool) -> io::Result<Pairing> {
+    let (peer_id, public) = if outgoing { &transcript.answerer } else { &transcript.asker };
+    let public = unhex(public).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "bad public key"))?;
+    let hash = transcript.hash();
+    let key = agreement::agree_ephemeral(key.private, &UnparsedPublicKey::new(&X25519, public), |shared| {
+        let mut input = b"funkhunt pair key".to_vec();
+        input.extend_from_slice(&hash);
+        let key: [u8; 32] = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, shared), &input)
+            .as_ref()
+            .try_into()
+            .unwrap_or([0; 32]);
+        key
+    })
+    .map_err(crypto_error)?;
+
+    Ok(Pairing {
+        peer_id: peer_id.clone(),
+        name: name.to_string(),
+        pin: pin_of(&hash),
+        outgoing,
+        key,
+    })
+}
+
+/// The PIN of a pairing, from its transcript's hash
+fn pin_of(hash: &[u8]) -> String {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&hash[..8]);
+    let number = u64::from_be_bytes(bytes) % 10u64.pow(PIN_DIGITS);
+    format!("{:0width$}", number, width = PIN_DIGITS as usize)
+}
+
+/// Headers that authenticate a request to a paired peer
+///
+/// # Arguments
+/// * `secret` - The pair secret
+/// * `method` - HTTP method, e.g. "GET"
+/// * `path` - Request path, e.g. "/peer/catalog"
+/// * `body` - The body as sent (sealed; empty for none)
+pub fn auth_headers(secret: &Secret, method: &str, path: &str, body: &[u8]) -> io::Result<[(&'static str, String); 5]> {
+    let time = crate::book::unix_now();
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new().fill(&mut nonce).map_err(crypto_error)?;
+    let (nonce, body) = (hex(&nonce), body_hash(body));
+    let text = request_text(method, path, time, &nonce, &body);
+    let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &secret.key), &text);
+    Ok([
+        (PEER_HEADER, secret.local_id.clone()),
+        (TIME_HEADER, time.to_string()),
+        (NONCE_HEADER, nonce),
+        (BODY_HEADER, body),
+        (AUTH_HEADER, hex(tag.as_ref())),
+    ])
+}
+
+/// The authentication headers of a request, as received
+#[derive(Debug, Clone)]
+pub struct Signature {
+    /// Peer header: who says they sent it
+    pub peer: String,
+
+    /// Time header
+    pub time: String,
+
+    /// Nonce header
+    pub nonce: String,
+
+    /// Body header: the SHA-256 of the body, checked when it's read
+    pub body: String,
+
+    /// Auth header: the HMAC
+    pub auth: String,
+}
+
+/// The nonces of the requests taken lately, so a request caught on the
+/// network isn't taken again
+#[derive(Debug, Default)]
+pub struct SeenNonces {
+    /// (peer id, nonce) -> the time of the request
+    seen: HashMap<(String, String), u64>,
+}
+
+impl SeenNonces {
+    /// Remembers a request's nonce (and forgets those too old to matter:
+    /// their requests are refused anyway)
+    ///
+    /// # Returns
+    /// false if the nonce was seen already - the request is a replay
+    fn first_time(&mut self, peer: &str, nonce: &str, time: u64) -> bool {
+        let now = crate::book::unix_now();
+        self.seen.retain(|_, seen| now.abs_diff(*seen) <= MAX_CLOCK_SKEW);
+        self.seen.insert((peer.to_string(), nonce.to_string()), time).is_none()
+    }
+}
+
+/// Checks the authentication of a request
+///
+/// # Arguments
+/// * `trusted` - The paired peers
+/// * `seen` - The nonces of the requests taken lately (this one's is added)
+/// * `method` - HTTP method
+/// * `path` - Request path
+/// * `signature` - The authentication headers
+///
+/// # Returns
+/// The key to answer with, or why the request isn't trusted. The body
+/// isn't checked: see `body_matches`.
+pub fn verify(
+    trusted: &[TrustedPeer],
+    seen: &mut SeenNonces,
+    method: &str,
+    path: &str,
+    signature: &Signature,
+) -> Result<Secret, &'static str> {
+    let trusted_peer = trusted.iter().find(|t| t.id == signature.peer).ok_or("not paired")?;
+    let key: [u8; 32] = unhex(&trusted_peer.key)
+        .and_then(|k| k.try_into().ok())
+        .ok_or("broken pair key")?;
+    check(&key, seen, method, path, signature)?;
+
+    Ok(Secret {
+        local_id: local_id(),
+        key,
+    })
+}
+
+/// Checks a request's signature under a pair key, and that it wasn't taken already
+fn check(key: &[u8; 32], seen: &mut SeenNonces, method: &str, path: &str, signature: &Signature) -> Result<(), &'static str> {
+    let time: u64 = signature.time.parse().map_err(|_| "bad time")?;
+    if crate::book::unix_now().abs_diff(time) > MAX_CLOCK_SKEW {
+        return Err("request too old (are both clocks right?)");
+    }
+    if unhex(&signature.nonce).is_none_or(|nonce| nonce.len() != NONCE_LEN) {
+        return Err("bad nonce");
+    }
+    let tag = unhex(&signature.auth).ok_or("bad signature")?;
+    let text = request_text(method, path, time, &signature.nonce, &signature.body);
+    hmac::verify(&hmac::Key::new(hmac::HMAC_SHA256, key), &text, &tag).map_err(|_| "bad signature")?;
+    if !seen.first_time(&signature.peer, &signature.nonce, time) {
+        return Err("request replayed");
+    }
+    Ok(())
+}
+
+/// Whether a body received is the one its request was signed with
+pub fn body_matches(signature: &Signature, body: &[u8]) -> bool {
+    body_hash(body) == signature.body
+}
+
+/// Encrypts a whole (small) message, e.g. a JSON body
+pub fn seal(secret: &Secret, data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut sealed = Vec::new();
+    SealedReader::new(data, secret)?.read_to_end(&mut sealed)?;
+    Ok(sealed)
+}
+
+/// Encrypts what is read through it (see the top of this file)
+pub struct SealedReader<R> {
+    /// The plaintext
+    inner: R,
+
+    /// Key of this stream
+    key: LessSafeKey,
+
+    /// Frames written so far (the nonce of the next one)
+    counter: u64,
+
+    /// Encrypted bytes not read yet
+    out: Vec<u8>,
+
+    /// Position in `out`
+    position: usize,
+
+    /// Whether the last frame was produced
+    finished: bool,
+}
+
+impl<R: Read> SealedReader<R> {
+    /// Starts a sealed stream under a pair secret
+    pub fn new(inner: R, secret: &Secret) -> io::Result<Self> {
+        let mut salt = [0u8; SALT_LEN];
+        SystemRandom::new().fill(&mut salt).map_err(crypto_error)?;
+        Ok(Self {
+            inner,
+            key: stream_key(&secret.key, &salt)?,
+            counter: 0,
+            out: salt.to_vec(),
+            position: 0,
+            finished: false,
+        })
+    }
+
+    /// Encrypts the next frame into `out`
+    fn next_frame(&mut self) -> io::Result<()> {
+        let mut frame = vec![0u8; FRAME_SIZE];
+        let mut filled = 0;
+        while filled < FRAME_SIZE {
+            match self.inner.read(&mut frame[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        frame.truncate(filled);
+        // A short frame means the plaintext ended
+        let last = filled < FRAME_SIZE;
+
+        let header = (filled as u32 | if last { FINAL_FRAME } else { 0 }).to_be_bytes();
+        self.key
+            .seal_in_place_append_tag(nonce(self.counter), Aad::from(header), &mut frame)
+            .map_err(crypto_error)?;
+        self.counter += 1;
+
+        self.out.clear();
+        self.out.extend_from_slice(&header);
+        self.out.extend_from_slice(&frame);
+        self.position = 0;
+        self.finished = last;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for SealedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.out.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            self.next_frame()?;
+        }
+        let count = buf.len().min(self.out.len() - self.position);
+        buf[..count].copy_from_slice(&self.out[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+/// Decrypts a sealed stream read through it; fails on anything tampered
+/// with or cut short
+pub struct OpenedReader<R> {
+    /// The sealed stream
+    inner: R,
+
+    /// The pair key (the stream key needs the salt, read first)
+    secret: [u8; 32],
+
+    /// Key of this stream, once the salt was read
+    key: Option<LessSafeKey>,
+
+    /// Frames read so far (the nonce of the next one)
+    counter: u64,
+
+    /// Decrypted bytes not read yet
+    out: Vec<u8>,
+
+    /// Position in `out`
+    position: usize,
+
+    /// Whether the last frame was read
+    finished: bool,
+}
+
+impl<R: Read> OpenedReader<R> {
+    /// Starts reading a sealed stream under a pair secret
+    pub fn new(inner: R, secret: &Secret) -> Self {
+        Self {
+            inner,
+            secret: secret.key,
+            key: None,
+            counter: 0,
+            out: Vec::new(),
+            position: 0,
+            finished: false,
+        }
+    }
+
+    /// Decrypts the next frame into `out`
+    fn next_frame(&mut self) -> io::Result<()> {
+        if self.key.is_none() {
+            let mut salt = [0u8; SALT_LEN];
+            self.inner.read_exact(&mut salt)?;
+            self.key = Some(stream_key(&self.secret, &salt)?);
+        }
+
+        let mut header = [0u8; 4];
+        self.inner
+            .read_exact(&mut header)
+            .map_err(|_| io::Error::new(io::ErrorKind::UnexpectedEof, "sealed stream cut short"))?;
+        let value = u32::from_be_bytes(header);
+        let length = (value & !FINAL_FRAME) as usize;
+        if length > FRAME_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bad sealed frame"));
+        }
+
+        let mut frame = vec![0u8; length + CHACHA20_POLY1305.tag_len()];
+        self.inner.read_exact(&mut frame)?;
+        let key = self.key.as_ref().ok_or_else(|| io::Error::other("no stream key"))?;
+        let plain = key
+            .open_in_place(nonce(self.counter), Aad::from(header), &mut frame)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "sealed frame failed to decrypt"))?;
+        self.counter += 1;
+
+        self.out = plain.to_vec();
+        self.position = 0;
+        self.finished = value & FINAL_FRAME != 0;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for OpenedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Empty frames are allowed (a stream ending on a frame boundary)
+        while self.position >= self.out.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            self.next_frame()?;
+        }
+        let count = buf.len().min(self.out.len() - self.position);
+        buf[..count].copy_from_slice(&self.out[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+/// What a request's HMAC covers
+fn request_text(method: &str, path: &str, time: u64, nonce: &str, body: &str) -> Vec<u8> {
+    format!("{}\n{}\n{}\n{}\n{}", method, path, time, nonce, body).into_bytes()
+}
+
+/// SHA-256 of a body, in hex
+fn body_hash(body: &[u8]) -> String {
+    hex(digest(&SHA256, body).as_ref())
+}
+
+/// Key of one sealed stream: the pair key mixed with the stream's salt, so
+/// nonces (frame counters) are never reused under the same key
+fn stream_key(secret: &[u8; 32], salt: &[u8]) -> io::Result<LessSafeKey> {
+    let mut input = b"funkhunt stream".to_vec();
+    input.extend_from_slice(salt);
+    let derived = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, secret), &input);
+    let key = UnboundKey::new(&CHACHA20_POLY1305, derived.as_ref()).map_err(crypto_error)?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Nonce of a frame: its number
+fn nonce(counter: u64) -> Nonce {
+    let mut bytes = [0u8; 12];
+    bytes[4..].copy_from_slice(&counter.to_be_bytes());
+    Nonce::assume_unique_for_key(bytes)
+}
+
+/// Where the paired peers are stored
+fn trusted_path() -> PathBuf {
+    crate::paths::data_dir().join(TRUSTED_FILE)
+}
+
+/// Writes a JSON file only the user can read
+fn write_private(path: &std::path::Path, value: &impl Serialize) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let json = serde_json::to_string_pretty(value).map_err(io::Error::other)?;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    // Created unreadable to others, and made so before anything is written
+    // if it was already there
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(json.as_bytes())
+}
+
+/// ring's errors say nothing - this at least says where it happened
+fn crypto_error(_: ring::error::Unspecified) -> io::Error {
+    io::Error::other("cryptographic operation failed")
+}
+
+/// Bytes as lowercase hex
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Hex back to bytes (None if it isn't hex)
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pair secret for the tests
+    fn secret() -> Secret {
+        Secret {
+            local_id: "peer-a".to_string(),
+            key: [7; 32],
+        }
+    }
+
+    /// The signature a request would arrive with
+    fn signed(method: &str, path: &str, body: &[u8]) -> Signature {
+        let headers = auth_headers(&secret(), method, path, body).unwrap();
+        let value = |name: &str| headers.iter().find(|(header, _)| *header == name).unwrap().1.clone();
+        Signature {
+            peer: value(PEER_HEADER),
+            time: value(TIME_HEADER),
+            nonce: value(NONCE_HEADER),
+            body: value(BODY_HEADER),
+            auth: value(AUTH_HEADER),
+        }
+    }
+
+    #[test]
+    fn signed_request_is_taken_once() {
+        let mut seen = SeenNonces::default();
+        let signature = signed("POST", "/peer/sync", b"body");
+        assert_eq!(check(&[7; 32], &mut seen, "POST", "/peer/sync", &signature), Ok(()));
+        assert_eq!(check(&[7; 32], &mut seen, "POST", "/peer/sync", &signature), Err("request replayed"));
+    }
+
+    #[test]
+    fn signature_covers_method_path_and_key() {
+        let mut seen = SeenNonces::default();
+        let signature = signed("POST", "/peer/sync", b"");
+        assert_eq!(check(&[7; 32], &mut seen, "GET", "/peer/sync", &signature), Err("bad signature"));
+        assert_eq!(check(&[7; 32], &mut seen, "POST", "/peer/offer", &signature), Err("bad signature"));
+        assert_eq!(check(&[8; 32], &mut seen, "POST", "/peer/sync", &signature), Err("bad signature"));
+    }
+
+    #[test]
+    fn signature_covers_body() {
+        let mut signature = signed("POST", "/peer/offer", b"offer");
+        assert!(body_matches(&signature, b"offer"));
+        assert!(!body_matches(&signature, b"other offer"));
+
+        // Another body's hash in the header breaks the HMAC
+        signature.body = body_hash(b"other offer");
+        let mut seen = SeenNonces::default();
+        assert_eq!(check(&[7; 32], &mut seen, "POST", "/peer/offer", &signature), Err("bad signature"));
+    }
+
+    #[test]
+    fn old_requests_are_refused() {
+        let mut signature = signed("GET", "/peer/catalog", b"");
+        signature.time = (crate::book::unix_now() - MAX_CLOCK_SKEW - 1).to_string();
+        let mut seen = SeenNonces::default();
+        assert_eq!(
+            check(&[7; 32], &mut seen, "GET", "/peer/catalog", &signature),
+            Err("request too old (are both clocks right?)")
+        );
+    }
+
+    #[test]
+    fn both_sides_of_a_pairing_agree() {
+        let (asker, answerer) = (PairingKey::generate().unwrap(), PairingKey::generate().unwrap());
+        let transcript = Transcript {
+            asker: ("peer-a".to_string(), asker.public().to_string()),
+            answerer: ("peer-b".to_string(), answerer.public().to_string()),
+        };
+        let committed = commitment(asker.public(), "peer-a");
+        assert_eq!(committed, commitment(&transcript.asker.1, "peer-a"));
+        assert_ne!(committed, commitment(&transcript.asker.1, "peer-c"));
+
+        let ours = agree(asker, &transcript, "B", true).unwrap();
+        let theirs = agree(answerer, &transcript, "A", false).unwrap();
+        assert_eq!((ours.peer_id.as_str(), theirs.peer_id.as_str()), ("peer-b", "peer-a"));
+        assert_eq!(ours.pin, theirs.pin);
+        assert_eq!(ours.pin.len(), PIN_DIGITS as usize);
+        assert_eq!(ours.key, theirs.key);
+    }
+
+    #[test]
+    fn pin_covers_keys_and_ids() {
+        let key = |seed: u8| ("peer".to_string(), hex(&[seed; 32]));
+        let pin = |transcript: &Transcript| pin_of(&transcript.hash());
+        let transcript = Transcript {
+            asker: key(1),
+            answerer: key(2),
+        };
+        let other_key = Transcript {
+            answerer: key(3),
+            ..transcript.clone()
+        };
+        let other_id = Transcript {
+            asker: ("other".to_string(), hex(&[1; 32])),
+            ..transcript.clone()
+        };
+        assert_ne!(pin(&transcript), pin(&other_key));
+        assert_ne!(pin(&transcript), pin(&other_id));
+    }
+
+    #[test]
+    fn sealed_stream_opens_back() {
+        let data: Vec<u8> = (0..FRAME_SIZE * 2 + 10).map(|i| i as u8).collect();
+        let sealed = seal(&secret(), &data).unwrap();
+        let mut opened = Vec::new();
+        OpenedReader::new(sealed.as_slice(), &secret()).read_to_end(&mut opened).unwrap();
+        assert_eq!(opened, data);
+
+        // Cut short: noticed
+        let cut = &sealed[..sealed.len() - 100];
+        assert!(OpenedReader::new(cut, &secret()).read_to_end(&mut Vec::new()).is_err());
+    }
+}
@@ -3,17 +3,28 @@
 
 use ratatui::{
     layout::Rect,
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
+    text::{Line, Span},
     widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
     Frame,
 };
 
-use super::state::TuiState;
+use crate::book::{stars_text, Book, ReadingStatus};
+use crate::graphics::{self, Placement};
+use crate::profile::Shelf;
+use crate::reader::{self, BlockKind, ReaderTheme};
+use crate::settings::Theme;
+use crate::sort::SortOrder;
+
+use super::state::{BookReader, PeerBrowser, Preview, TuiState};
+
+/// Frames of the header's spinner while work runs in the background, one per tick
+const SPINNER: [char; 8] = ['⣾', '⣽', '⣻', '⢿', '⡿', '⣟', '⣯', '⣷'];
 
 /// Renders the application header showing book count and scanned paths
 ///
 /// The header displays:
-/// - App name ("FunkHunt")
+/// - App name ("FunkHunt") and the open library profile
 /// - Number of books in the library
 /// - Scan paths info (single path, multiple paths count, or "No folders added")
 ///
@@ -34,17 +45,89 @@ pub fn render_header(frame: &mut Frame, state: &TuiState, area: Rect) {
         format!("{} folders", state.scan_paths.len())
     };
 
-    // Build header text: "FunkHunt | Books: 42 | ~/Books"
-    let header_text = format!("FunkHunt | Books: {} | {}", state.books.len(), path_info);
+    // Build header text: "FunkHunt [default] | Books: 42 | ~/Books"
+    let mut header_text = format!(
+        "FunkHunt [{}] | Books: {} | {}",
+        state.profile_name,
+        state.books.len(),
+        path_info
+    );
+
+    // Name the collection or series being shown, if any
+    if let Shelf::Series(name) = &state.shelf {
+        header_text.push_str(&format!(" | Series: {}", name));
+    } else if let Some(name) = state.shelf_name() {
+        header_text.push_str(&format!(" | Collection: {}", name));
+    }
+    if !state.filter.is_empty() {
+        header_text.push_str(&format!(" | Filter: {}", state.filter_text));
+    }
+    if state.starred_only {
+        header_text.push_str(" | ★ only");
+    }
+    if state.show_archived {
+        header_text.push_str(" | +archived");
+    }
+    if let Some(status) = state.status_filter {
+        header_text.push_str(&format!(" | {} only", status.label()));
+    }
+
+    // Books per reading status: "○ 3 ◐ 1 ● 12 ✗ 0" (counted in one pass)
+    let mut per_status = [0; ReadingStatus::ALL.len()];
+    for status in state.books.iter().filter_map(|b| b.user.status) {
+        if let Some(at) = ReadingStatus::ALL.iter().position(|&s| s == status) {
+            per_status[at] += 1;
+        }
+    }
+    let counts: Vec<String> = ReadingStatus::ALL
+        .iter()
+        .zip(per_status)
+        .map(|(status, count)| format!("{} {}", status.badge(), count))
+        .collect();
+    header_text.push_str(&format!(" | {}", counts.join(" ")));
+
+    // Transfers still going, so they aren't forgotten behind a popup
+    let active = state.transfers.active_count();
+    if active > 0 {
+        header_text.push_str(&format!(" | ⇅ {} transfers", active));
+    }
+
+    // Scans, lookups and fetches still running in the background
+    let spinner = SPINNER[state.ticks % SPINNER.len()];
+    let working = state.workers.running();
+    if working > 0 {
+        header_text.push_str(&format!(" | {} {} working", spinner, working));
+    }
+
+    // Books scanned whose metadata is still being read
+    let parsing = state.parsing.pending_count();
+    if parsing > 0 {
+        header_text.push_str(&format!(" | {} reading {} books", spinner, parsing));
+    }
+
+    // Create header widget with styling (colors come from the theme)
+    let theme = &state.settings.theme;
+    let mut block = Block::default()
+        .borders(Borders::ALL) // Border on all sides
+        .style(Style::default().fg(theme.border)); // Border (blue by default)
+
+    // The book read last, on the border: " ▶ Continue reading Dune (42%): k "
+    if let Some(book) = state.continue_reading_book().map(|index| &state.books[index]) {
+        let percent = book.user.position.as_ref().map_or(0, |position| position.percent);
+        block = block.title(Span::styled(
+            format!(
+                " ▶ Continue reading {} ({}%): {} ",
+                book.display_title(),
+                percent,
+                state.settings.keys.continue_reading
+            ),
+            Style::default().fg(theme.header).add_modifier(Modifier::BOLD),
+        ));
+    }
 
-    // Create header widget with styling
     let header = Paragraph::new(header_text)
-        .style(Style::default().fg(Color::Cyan)) // Cyan text
-        .block(
-            Block::default()
-                .borders(Borders::ALL) // Border on all sides
-                .style(Style::default().fg(Color::Blue)), // Blue border
-        );
+        .style(Style::default().fg(theme.header)) // Header text (cyan by default)
+        .block(block);
 
     // Draw the widget
     frame.render_widget(header, area);
@@ -53,8 +136,9 @@ pub fn render_header(frame: &mut Frame, state: &TuiState, area: Rect) {
 /// Renders the scrollable list of books
 ///
 /// Features:
-/// - Shows book filenames
+/// - Shows book filenames (only the books in `state.view`)
 /// - Highlights the currently selected book in yellow/bold
+/// - In a series view, shows each book's position and marks the next one to read
 /// - Shows helpful message if list is empty
 ///
 /// # Arguments
@@ -62,33 +146,106 @@ pub fn render_header(frame: &mut Frame, state: &TuiState, area: Rect) {
 /// * `state` - Current application state (books and selection)
 /// * `area` - The rectangular area to draw in
 pub fn render_book_list(frame: &mut Frame, state: &TuiState, area: Rect) {
-    // Title shows total book count
-    let title = format!("Book List ({})", state.books.len());
+    // Title shows how many books are listed (and which collection)
+    let mut title = match state.shelf_name() {
+        Some(name) => format!("{} ({})", name, state.view.len()),
+        None => format!("Book List ({})", state.view.len()),
+    };
+    if state.sort != SortOrder::Library {
+        title.push_str(&format!(" - by {}", state.sort.label()));
+    }
+    if !state.marked.is_empty() {
+        title.push_str(&format!(" - {} marked", state.marked.len()));
+    }
+
+    // In a series view, the next unread book is marked
+    let next_in_series = state.next_in_series();
 
     // Build list items
     let items: Vec<ListItem> = if state.books.is_empty() {
-        // Empty list - show helpful message
+        // Empty library - show helpful message
         vec![ListItem::new("No books found. Press 'a' to add a folder.")]
+    } else if state.view.is_empty() {
+        // Books exist, but none pass the collection / starred filters
+        vec![ListItem::new("No books match.")]
     } else {
-        // Map books to styled list items
+        // Map shown books to styled list items - only those that fit
+        // between the borders, the list can be long
         state
-            .books
+            .view
             .iter()
-            .enumerate() // Get (index, book) pairs
-            .map(|(i, book)| {
+            .take(area.height.saturating_sub(2) as usize)
+            .enumerate() // Get (position, index) pairs
+            .map(|(i, &index)| {
+                let book = &state.books[index];
+                let is_next = next_in_series == Some(index);
+                let placeholder = state.parsing.is_pending(&book.path);
+
                 // Style the selected book differently
                 let style = if i == state.selected_index {
-                    // Selected: yellow and bold
+                    // Selected: highlight color (yellow by default) and bold
                     Style::default()
-                        .fg(Color::Yellow)
+                        .fg(state.settings.theme.selected)
                         .add_modifier(Modifier::BOLD)
+                } else if is_next {
+                    // Next book to read in the series: accent color
+                    Style::default().fg(state.settings.theme.accent)
+                } else if book.user.archived || placeholder {
+                    // Archived (only listed when asked for), or metadata
+                    // not read yet: muted color
+                    Style::default().fg(state.settings.theme.muted)
                 } else {
-                    // Normal: white
-                    Style::default().fg(Color::White)
+                    // Normal: text color (white by default)
+                    Style::default().fg(state.settings.theme.text)
                 };
 
+                // Marked books get a '»', starred books a '★' in front of
+                // the name, followed by the reading status badge
+                let marker = if state.marked.contains(&book.path) {
+                    '»'
+                } else if book.user.starred {
+                    '★'
+                } else {
+                    ' '
+                };
+                let badge = book.user.status.map_or(' ', ReadingStatus::badge);
+
+                // Fixed-layout and read-aloud books are badged after the
+                // name (e-ink readers often can't show them)
+                let format = match (book.meta.fixed_layout, book.meta.media_overlays) {
+                    (true, true) => " [fixed] [read-aloud]",
+                    (true, false) => " [fixed]",
+                    (false, true) => " [read-aloud]",
+                    (false, false) => "",
+                };
+
+                // Rated books show their stars after the name
+                let rating = book
+                    .user
+                    .rating
+                    .map(|stars| format!("  {}", stars_text(stars)))
+                    .unwrap_or_default();
+
+                // Series view: "#2 name  ← next"
+                let (position, next) = if matches!(state.shelf, Shelf::Series(_)) {
+                    let position = match book.meta.series_index {
+                        Some(n) => format!("#{} ", n),
+                        None => "#? ".to_string(),
+                    };
+                    (position, if is_next { "  ← next" } else { "" })
+                } else {
+                    (String::new(), "")
+                };
+
+                // Books whose metadata is being read: "name …"
+                let reading = if placeholder { " …" } else { "" };
+
                 // Create list item with book name and style
-                ListItem::new(book.name.as_str()).style(style)
+                ListItem::new(format!(
+                    "{}{} {}{}{}{}{}{}",
+                    marker, badge, position, book.name, reading, format, rating, next
+                ))
+                .style(style)
             })
             .collect() // Collect into Vec<ListItem>
     };
@@ -114,8 +271,19 @@ pub fn render_book_list(frame: &mut Frame, state: &TuiState, area: Rect) {
 /// * `state` - Current application state
 /// * `area` - The rectangular area to draw in
 pub fn render_book_details(frame: &mut Frame, state: &TuiState, area: Rect) {
+    // Its first pages instead, when asked for
+    if let (true, Some(book), Some(preview)) = (state.previewing, state.selected_book(), &state.preview) {
+        if preview.path == book.path {
+            return render_preview(frame, state, book, preview, area);
+        }
+    }
+
     // Get details text based on whether a book is selected
     let details = match state.selected_book() {
+        Some(book) if state.parsing.is_pending(&book.path) => {
+            // Scanned, its package not read yet (see parse_queue.rs)
+            format!("{}\n\nReading its metadata…", book.get_metadata())
+        }
         Some(book) => {
             // Book selected - get its metadata
             book.get_metadata()
@@ -128,7 +296,7 @@ pub fn render_book_details(frame: &mut Frame, state: &TuiState, area: Rect) {
 
     // Create paragraph widget
     let details_widget = Paragraph::new(details)
-        .style(Style::default().fg(Color::White)) // White text
+        .style(Style::default().fg(state.settings.theme.text)) // Text color (white by default)
         .block(Block::default().borders(Borders::ALL).title("Book Details")) // Border with title
         .wrap(Wrap { trim: true }); // Wrap long lines, trim whitespace
 
@@ -136,24 +304,454 @@ pub fn render_book_details(frame: &mut Frame, state: &TuiState, area: Rect) {
     frame.render_widget(details_widget, area);
 }
 
+/// Renders the first pages of the selected book in place of its details:
+/// its title and authors, then the text of its first blocks
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Application state (theme)
+/// * `book` - The selected book
+/// * `preview` - Its first pages (see TuiState::load_preview)
+/// * `area` - The rectangular area to draw in
+fn render_preview(frame: &mut Frame, state: &TuiState, book: &Book, preview: &Preview, area: Rect) {
+    let theme = &state.settings.theme;
+    let mut lines = vec![
+        Line::from(Span::styled(
+            book.display_title().to_string(),
+            Style::default().fg(theme.header).add_modifier(Modifier::BOLD),
+        )),
+        Line::from(Span::styled(book.display_authors(), Style::default().fg(theme.accent))),
+    ];
+    match &preview.blocks {
+        Ok(blocks) => {
+            for block in blocks {
+                lines.push(Line::default());
+                let mut spans = styled_spans(&block.spans, block_style(block.kind, theme));
+                // The line breaks of a block are spaces here: it's one wrapped line
+                for span in &mut spans {
+                    span.content = span.content.replace('\n', " ").into();
+                }
+                match block.kind {
+                    BlockKind::Rule => spans = vec![Span::styled("* * *", block_style(block.kind, theme))],
+                    BlockKind::ListItem => spans.insert(0, Span::styled("• ", block_style(block.kind, theme))),
+                    _ => {}
+                }
+                lines.push(Line::from(spans));
+            }
+        }
+        Err(message) => {
+            lines.push(Line::default());
+            lines.push(Line::from(Span::styled(message.clone(), Style::default().fg(theme.muted))));
+        }
+    }
+
+    let preview_widget = Paragraph::new(lines)
+        .style(Style::default().fg(theme.text))
+        .block(Block::default().borders(Borders::ALL).title("Preview"))
+        .wrap(Wrap { trim: true });
+    frame.render_widget(preview_widget, area);
+}
+
+/// Renders the header while a peer's library is browsed
+///
+/// Shows the peer, its library and how many books it shares, e.g.
+/// `FunkHunt [default] | Peer: Living room | Library: home | Books: 42 | 3 here already`
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (theme, profile name)
+/// * `browser` - The peer being browsed
+/// * `area` - The rectangular area to draw in
+pub fn render_peer_header(frame: &mut Frame, state: &TuiState, browser: &PeerBrowser, area: Rect) {
+    let header_text = format!(
+        "FunkHunt [{}] | Peer: {} | Library: {} | Books: {} | {} here already",
+        state.profile_name,
+        browser.peer,
+        browser.catalog.library,
+        browser.catalog.books.len(),
+        browser.owned.len()
+    );
+
+    let theme = &state.settings.theme;
+    let header = Paragraph::new(header_text)
+        .style(Style::default().fg(theme.header))
+        .block(Block::default().borders(Borders::ALL).style(Style::default().fg(theme.border)));
+    frame.render_widget(header, area);
+}
+
+/// Renders the books a peer shares, like the book list
+///
+/// Marked books get a '»' in front, books we have already a '✓' (and the
+/// muted color).
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (theme)
+/// * `browser` - The peer being browsed
+/// * `area` - The rectangular area to draw in
+pub fn render_peer_book_list(frame: &mut Frame, state: &TuiState, browser: &PeerBrowser, area: Rect) {
+    let theme = &state.settings.theme;
+    let mut title = format!("{} ({})", browser.peer, browser.catalog.books.len());
+    if !browser.marked.is_empty() {
+        title.push_str(&format!(" - {} marked", browser.marked.len()));
+    }
+
+    // Keep the selected book visible
+    let visible = area.height.saturating_sub(2).max(1) as usize;
+    let first = browser.selected_index.saturating_sub(visible - 1);
+
+    let items: Vec<ListItem> = if browser.catalog.books.is_empty() {
+        vec![ListItem::new("This peer shares no books with you.")]
+    } else {
+        browser
+            .catalog
+            .books
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(visible)
+            .map(|(i, entry)| {
+                let owned = browser.owned.contains(&entry.id);
+                let style = if i == browser.selected_index {
+                    Style::default().fg(theme.selected).add_modifier(Modifier::BOLD)
+                } else if owned {
+                    Style::default().fg(theme.muted)
+                } else {
+                    Style::default().fg(theme.text)
+                };
+
+                let marker = if browser.marked.contains(&entry.id) {
+                    '»'
+                } else if owned {
+                    '✓'
+                } else {
+                    ' '
+                };
+                let mut text = format!("{} {}", marker, entry.title);
+                if !entry.authors.is_empty() {
+                    text.push_str(&format!(" - {}", entry.authors.join(", ")));
+                }
+                ListItem::new(text).style(style)
+            })
+            .collect()
+    };
+
+    let list = List::new(items).block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(list, area);
+}
+
+/// Renders the details of the selected book of a peer
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (theme)
+/// * `browser` - The peer being browsed
+/// * `area` - The rectangular area to draw in
+pub fn render_peer_book_details(frame: &mut Frame, state: &TuiState, browser: &PeerBrowser, area: Rect) {
+    let details = match browser.selected() {
+        Some(entry) if browser.owned.contains(&entry.id) => {
+            format!("{}\n\nAlready in your library", entry.details())
+        }
+        Some(entry) => entry.details(),
+        None => "Nothing to show".to_string(),
+    };
+
+    let details_widget = Paragraph::new(details)
+        .style(Style::default().fg(state.settings.theme.text))
+        .block(Block::default().borders(Borders::ALL).title("Book Details"))
+        .wrap(Wrap { trim: true });
+    frame.render_widget(details_widget, area);
+}
+
+/// Renders the footer while a peer's library is browsed: its keys, then
+/// the status message if there is one
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Application state (status message)
+/// * `area` - The rectangular area to draw in
+pub fn render_peer_footer(frame: &mut Frame, state: &TuiState, area: Rect) {
+    let mut footer_text =
+        "↑↓: navigate | v/Space: mark | Enter/d: download marked (or selected) into the library | Esc: back to peers"
+            .to_string();
+    if let Some(message) = &state.status_message {
+        footer_text.push_str(" | ");
+        footer_text.push_str(message);
+    }
+
+    let footer = Paragraph::new(footer_text)
+        .style(Style::default().fg(state.settings.theme.muted))
+        .block(Block::default().borders(Borders::ALL));
+    frame.render_widget(footer, area);
+}
+
 /// Renders the footer with keyboard controls help (normal mode)
 ///
-/// Shows available key bindings for the normal mode interface.
+/// Shows available key bindings for the normal mode interface
+/// (using the keys configured in config.toml), followed by the
+/// current status message if there is one.
 ///
 /// # Arguments
 /// * `frame` - The frame buffer to draw on
-/// * `_state` - Application state (unused, but kept for consistency)
+/// * `state` - Application state (key bindings and status message)
 /// * `area` - The rectangular area to draw in
-pub fn render_footer(frame: &mut Frame, _state: &TuiState, area: Rect) {
+pub fn render_footer(frame: &mut Frame, state: &TuiState, area: Rect) {
     // Help text showing keyboard controls
-    let footer_text = "q: quit | ↑↓: navigate | Enter: open book | a: add folder";
+    let keys = &state.settings.keys;
+    let mut footer_text = format!(
+        "{}: quit | ↑↓: navigate | Enter: open book | {}: read here | {}: add folder | {}: folders | {}: collections | {}/{}: add/remove | {}/{}: star/starred | {}/{}: status/filter | 0-5: rate | {}: sort | {}: filter | {}/{}: archive/show | {}: merge authors | {}: series | {}/{}: mark/bulk edit | {}: edit metadata | {}/{}: undo/redo | {}: fetch metadata | {}: health | {}: statistics | {}: duplicates | {}/{}: organize/rename | {}: peers | {}: transfers | {}: share (QR) | {}: e-readers | {}: email | {}: convert | {}: write text | {}: set cover | {}: optimize | {}: fonts | {}/{}: split/merge | {}: preview | {}/{}: quotes/quote description | {}: Calibre | {}: feeds | {}: search text | {}: continue reading | {}: listen | {}: library",
+        keys.quit,
+        keys.read,
+        keys.add_folder,
+        keys.folder_settings,
+        keys.collections,
+        keys.add_to_collection,
+        keys.remove_from_collection,
+        keys.star,
+        keys.starred_only,
+        keys.cycle_status,
+        keys.status_filter,
+        keys.sort,
+        keys.filter,
+        keys.archive,
+        keys.show_archived,
+        keys.merge_authors,
+        keys.series,
+        keys.mark,
+        keys.bulk_edit,
+        keys.edit_metadata,
+        keys.undo,
+        keys.redo,
+        keys.fetch_metadata,
+        keys.health,
+        keys.statistics,
+        keys.duplicates,
+        keys.organize,
+        keys.rename,
+        keys.peers,
+        keys.transfers,
+        keys.share,
+        keys.devices,
+        keys.email,
+        keys.convert,
+        keys.extract,
+        keys.cover,
+        keys.optimize,
+        keys.fonts,
+        keys.split,
+        keys.join,
+        keys.preview,
+        keys.quotes,
+        keys.quote_description,
+        keys.calibre,
+        keys.feeds,
+        keys.search_text,
+        keys.continue_reading,
+        keys.listen,
+        keys.switch_library
+    );
+
+    // Append the status message (e.g. "Config reloaded")
+    if let Some(message) = &state.status_message {
+        footer_text.push_str(" | ");
+        footer_text.push_str(message);
+    }
 
     // Create paragraph widget
     let footer = Paragraph::new(footer_text)
-        .style(Style::default().fg(Color::Gray)) // Gray text
+        .style(Style::default().fg(state.settings.theme.muted)) // Muted text (gray by default)
         .block(Block::default().borders(Borders::ALL)); // Border
 
     // Draw the widget
     frame.render_widget(footer, area);
 }
 
+/// Where the reader's page of text goes on the screen: a column of its
+/// width, centered, between the header and the footer
+fn reader_text_area(reader: &BookReader, area: Rect) -> Rect {
+    let width = (reader.width as u16).min(area.width);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + 1,
+        width,
+        height: area.height.saturating_sub(2),
+    }
+}
+
+/// Where the pictures of the reader's page go: over the blank lines the
+/// layout left for them, centered (only pictures shown whole are drawn)
+///
+/// # Arguments
+/// * `reader` - The book being read
+/// * `area` - The rectangular area the reader is drawn in
+pub fn reader_pictures(reader: &BookReader, area: Rect) -> Vec<Placement> {
+    if area.height < 3 {
+        return Vec::new();
+    }
+    let text_area = reader_text_area(reader, area);
+    let mut placements = Vec::new();
+    for (row, line) in reader.lines.iter().skip(reader.top).take(reader.page_height).enumerate() {
+        let Some(image) = line
+            .picture
+            .map(|i| &reader.document.chapters[line.chapter].blocks[line.block].images[i])
+        else {
+            continue;
+        };
+        let Some((_, size)) = image.size else {
+            continue;
+        };
+        let (columns, rows) = graphics::cells(size, reader.width);
+        if row + rows > reader.page_height {
+            continue;
+        }
+        placements.push(Placement {
+            href: image.href.clone(),
+            area: Rect {
+                x: text_area.x + text_area.width.saturating_sub(columns as u16) / 2,
+                y: text_area.y + row as u16,
+                width: (columns as u16).min(text_area.width),
+                height: rows as u16,
+            },
+        });
+    }
+    placements
+}
+
+/// Style of the text of a block of a book, by its kind
+fn block_style(kind: BlockKind, theme: &Theme) -> Style {
+    match kind {
+        BlockKind::Heading => Style::default().fg(theme.header).add_modifier(Modifier::BOLD),
+        BlockKind::Quote | BlockKind::Preformatted => Style::default().fg(theme.accent),
+        BlockKind::Rule => Style::default().fg(theme.muted),
+        BlockKind::Paragraph | BlockKind::ListItem => Style::default().fg(theme.text),
+    }
+}
+
+/// Spans of a book's text with their bold, italic and underline on top of
+/// their block's style
+fn styled_spans(spans: &[reader::Span], block_style: Style) -> Vec<Span<'static>> {
+    spans
+        .iter()
+        .map(|span| {
+            let mut style = block_style;
+            if span.style.bold {
+                style = style.add_modifier(Modifier::BOLD);
+            }
+            if span.style.italic {
+                style = style.add_modifier(Modifier::ITALIC);
+            }
+            if span.style.underline {
+                style = style.add_modifier(Modifier::UNDERLINED);
+            }
+            Span::styled(span.text.clone(), style)
+        })
+        .collect()
+}
+
+/// Renders the built-in reader: the title and chapter on the first line,
+/// a page of text in a centered column, the position (chapter, percent)
+/// and keys on the last line
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Application state (theme and status message)
+/// * `reader` - The book being read (laid out by BookReader::fit for this size)
+/// * `area` - The rectangular area to draw in (the whole terminal)
+pub fn render_reader(frame: &mut Frame, state: &TuiState, reader: &BookReader, area: Rect) {
+    let theme = &state.settings.theme.for_reader(reader.typography.theme);
+    if area.height < 3 {
+        return;
+    }
+    // A reading theme colors the whole page
+    if reader.typography.theme != ReaderTheme::App {
+        frame.render_widget(Block::default().style(Style::default().bg(theme.background)), area);
+    }
+
+    // Header: book title, then the chapter
+    let mut title = reader.title.clone();
+    if let Some(chapter) = reader.chapter_title().filter(|chapter| *chapter != reader.title) {
+        title.push_str(" - ");
+        title.push_str(chapter);
+    }
+    let header = Paragraph::new(title).style(Style::default().fg(theme.header).add_modifier(Modifier::BOLD));
+    frame.render_widget(header, Rect { height: 1, ..area });
+
+    // A page of text, centered
+    let text_area = reader_text_area(reader, area);
+    let mut lines: Vec<Line> = reader
+        .lines
+        .iter()
+        .skip(reader.top)
+        .take(reader.page_height)
+        .map(|line| Line::from(styled_spans(&line.spans, block_style(line.kind, theme))))
+        .collect();
+
+    // The word selected for a lookup (or the passage for a highlight), in reverse video
+    for (i, shown) in lines.iter_mut().enumerate() {
+        if let Some(range) = reader.selected_range(reader.top + i) {
+            let mut spans = Vec::new();
+            let mut position = 0;
+            for span in shown.spans.drain(..) {
+                let length = span.content.chars().count();
+                let chars: Vec<char> = span.content.chars().collect();
+                let start = range.start.clamp(position, position + length) - position;
+                let end = range.end.clamp(position, position + length) - position;
+                for (text, style) in [
+                    (&chars[..start], span.style),
+                    (&chars[start..end], span.style.add_modifier(Modifier::REVERSED)),
+                    (&chars[end..], span.style),
+                ] {
+                    if !text.is_empty() {
+                        spans.push(Span::styled(text.iter().collect::<String>(), style));
+                    }
+                }
+                position += length;
+            }
+            shown.spans = spans;
+        }
+    }
+    frame.render_widget(Paragraph::new(lines), text_area);
+
+    // Footer: where in the book the page is (first, so narrow terminals
+    // show it), the keys and the status message
+    let chapters = reader.document.contents.len();
+    let mut footer_text = match reader.current_entry() {
+        Some(entry) => format!("Chapter {} of {}", entry + 1, chapters),
+        None => format!("{} chapters", chapters),
+    };
+    footer_text.push_str(&format!(" | {}% | ", reader.progress()));
+    match (&reader.bookmark_label, reader.word) {
+        (Some(label), _) => {
+            footer_text = format!("Bookmark this page as: {}_ (Enter: save, a label is optional | Esc: cancel)", label)
+        }
+        (None, Some(_)) if reader.quoting && reader.highlight_start.is_none() => {
+            footer_text.push_str("Quote: ←→↑↓: select the first word | Enter: start here | Esc: cancel")
+        }
+        (None, Some(_)) if reader.quoting => {
+            footer_text.push_str("Quote: ←→↑↓: select the last word | Enter: quote | Esc: cancel")
+        }
+        (None, Some(_)) if reader.highlighting && reader.highlight_start.is_none() => {
+            footer_text.push_str("Highlight: ←→↑↓: select the first word | Enter: start here | Esc: cancel")
+        }
+        (None, Some(_)) if reader.highlighting => {
+            footer_text.push_str("Highlight: ←→↑↓: select the last word | Enter: highlight | Esc: cancel")
+        }
+        (None, Some(_)) => footer_text.push_str("←→↑↓: select a word | Enter: look it up | Esc: stop selecting"),
+        (None, None) => footer_text.push_str(
+            "PgDn/Space: next page | PgUp: previous page | ↑↓: scroll | n/p: next/previous chapter | t: contents | b/B: bookmark/bookmarks | h/H: highlight/export | y: quote | d: dictionary | </>: width | [/]: margins | s: spacing | j/-: justify/hyphenate | c: colors | Esc: close",
+        ),
+    }
+    if let Some(message) = &state.status_message {
+        footer_text.push_str(" | ");
+        footer_text.push_str(message);
+    }
+    let footer = Paragraph::new(footer_text).style(Style::default().fg(theme.muted));
+    frame.render_widget(
+        footer,
+        Rect {
+            y: area.y + area.height - 1,
+            height: 1,
+            ..area
+        },
+    );
+}
@@ -2,8 +2,25 @@
 // Keyboard event handling - translates keypresses into state changes and actions
 
 use crossterm::event::{KeyCode, KeyEvent};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 
-use super::state::{AppAction, TuiState, UiMode};
+use crate::convert::Format;
+use crate::book::{Bookmark, Highlight, ReadingStatus};
+use crate::filter::Filter;
+use crate::fonts::Action as FontAction;
+use crate::profile::{Shelf, SmartCollection};
+use crate::quotes::Quote;
+use crate::reader::{self, Document};
+use crate::trust::{Pairing, SharePolicy};
+
+use super::state::{
+    AppAction, BookReader, BulkEdit, BulkField, CollectionPurpose, DescriptionQuote, DuplicatesScreen, FolderField, FontList, Lookup, MetadataForm,
+    MoveReview, PolicyEditor, TuiState, UiMode,
+};
+
+/// Columns `<` and `>` take from or add to the reader's lines
+const WIDTH_STEP: usize = 4;
 
 /// Main event handler - dispatches to mode-specific handlers
 ///
@@ -17,34 +34,206 @@ use super::state::{AppAction, TuiState, UiMode};
 /// # Returns
 /// * `None` - Event was handled entirely within state
 /// * `Some(AppAction)` - Event requires main loop to perform an action
+#[cfg_attr(feature = "profiling", tracing::instrument(level = "trace", name = "key", skip_all))]
 pub fn handle_key_event(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
     // Dispatch based on current mode
     match state.mode {
         UiMode::Normal => handle_normal_mode(key_event, state),
         UiMode::AddingFolder => handle_adding_folder_mode(key_event, state),
+        UiMode::PickingProfile => handle_picking_profile_mode(key_event, state),
+        UiMode::ViewingLog => handle_viewing_log_mode(key_event, state),
+        UiMode::FolderSettings => handle_folder_settings_mode(key_event, state),
+        UiMode::PickingCollection => handle_picking_collection_mode(key_event, state),
+        UiMode::Filtering => handle_filtering_mode(key_event, state),
+        UiMode::MergingAuthors => handle_merging_authors_mode(key_event, state),
+        UiMode::BulkEditing => handle_bulk_editing_mode(key_event, state),
+        UiMode::EditingMetadata => handle_editing_metadata_mode(key_event, state),
+        UiMode::ReviewingMetadata => handle_reviewing_metadata_mode(key_event, state),
+        UiMode::HealthReport => handle_health_report_mode(key_event, state),
+        UiMode::Statistics => handle_statistics_mode(key_event, state),
+        UiMode::Duplicates => handle_duplicates_mode(key_event, state),
+        UiMode::ReviewingMoves => handle_reviewing_moves_mode(key_event, state),
+        UiMode::MergingLibrary => handle_merging_library_mode(key_event, state),
+        UiMode::Peers => handle_peers_mode(key_event, state),
+        UiMode::BrowsingPeer => handle_browsing_peer_mode(key_event, state),
+        UiMode::ReviewingOffer => handle_reviewing_offer_mode(key_event, state),
+        UiMode::Pairing => handle_pairing_mode(key_event, state),
+        UiMode::EditingPolicy => handle_editing_policy_mode(key_event, state),
+        UiMode::ApprovingAccess => handle_approving_access_mode(key_event, state),
+        UiMode::Sharing => handle_sharing_mode(key_event, state),
+        UiMode::Devices => handle_devices_mode(key_event, state),
+        UiMode::Emailing => handle_emailing_mode(key_event, state),
+        UiMode::Converting => handle_converting_mode(key_event, state),
+        UiMode::SettingCover => handle_setting_cover_mode(key_event, state),
+        UiMode::Fonts => handle_fonts_mode(key_event, state),
+        UiMode::Joining => handle_joining_mode(key_event, state),
+        UiMode::Quotes => handle_quotes_mode(key_event, state),
+        UiMode::QuotingDescription => handle_quoting_description_mode(key_event, state),
+        UiMode::BrowsingCalibre => handle_browsing_calibre_mode(key_event, state),
+        UiMode::BrowsingFeeds => handle_browsing_feeds_mode(key_event, state),
+        UiMode::Reading => {
+            let action = handle_reading_mode(key_event, state);
+            state.remember_reading_position();
+            action
+        }
+        UiMode::Listening => handle_listening_mode(key_event, state),
+        UiMode::SearchingText => handle_searching_text_mode(key_event, state),
+        UiMode::TextSearchResults => handle_text_search_results_mode(key_event, state),
+        UiMode::Transfers => handle_transfers_mode(key_event, state),
+    }
+}
+
+/// Handles text pasted into the terminal (bracketed paste)
+///
+/// * In a prompt or a form - The text is typed into it, its line breaks as
+///   spaces (an Enter would submit the prompt)
+/// * In Normal and AddingFolder mode - A folder's path (as a file manager
+///   copies it: quoted, `file://...`, `~/...`) adds that folder
+/// * Elsewhere - Ignored
+///
+/// # Arguments
+/// * `text` - What was pasted
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - The text was typed, ignored, or isn't a folder
+/// * `Some(AppAction::AddFolder)` - The pasted folder should be scanned
+#[cfg_attr(feature = "profiling", tracing::instrument(level = "trace", name = "paste", skip_all))]
+pub fn handle_paste(text: &str, state: &mut TuiState) -> Option<AppAction> {
+    if accepts_text(state) {
+        for c in text.trim_end_matches(['\r', '\n']).chars() {
+            let c = if c.is_control() { ' ' } else { c };
+            handle_key_event(KeyEvent::from(KeyCode::Char(c)), state);
+        }
+        return None;
+    }
+    if !matches!(state.mode, UiMode::Normal | UiMode::AddingFolder) {
+        return None;
+    }
+
+    let path = pasted_path(text)?;
+    if !path.is_dir() {
+        state.status_message = Some(format!("Paste a folder to add it: {} is not one", path.display()));
+        return None;
     }
+    state.mode = UiMode::Normal;
+    Some(AppAction::AddFolder(path))
+}
+
+/// Whether the current mode has a text input the keys are typed into
+fn accepts_text(state: &TuiState) -> bool {
+    match state.mode {
+        UiMode::Filtering
+        | UiMode::Emailing
+        | UiMode::SettingCover
+        | UiMode::Joining
+        | UiMode::SearchingText
+        | UiMode::EditingMetadata => true,
+        // The status field is cycled, not typed into
+        UiMode::BulkEditing => state.bulk_edit.field != BulkField::Status,
+        UiMode::FolderSettings => state.folder_screen.editing.is_some(),
+        _ => false,
+    }
+}
+
+/// The path in pasted text: its first line, without the quotes a file
+/// manager may add, `file://` URLs decoded and `~` as the home folder
+fn pasted_path(text: &str) -> Option<PathBuf> {
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let line = line.trim_matches(|c| c == '\'' || c == '"');
+    let line = match line.strip_prefix("file://") {
+        Some(url) => crate::opds::percent_decode(url)?,
+        None => line.to_string(),
+    };
+    Some(match (line.strip_prefix('~'), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            PathBuf::from(home).join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(line),
+    })
 }
 
 /// Handles keyboard events in Normal mode (book list view)
 ///
-/// # Key bindings:
+/// # Key bindings (letters are the defaults, configurable in config.toml):
 /// * `q` - Quit the application
 /// * `↑` - Move selection up in book list
 /// * `↓` - Move selection down in book list
-/// * `Enter` - Open the selected book with system viewer
+/// * `Enter` - Open the selected book with the configured (or system) viewer
 /// * `a` - Switch to AddingFolder mode (file browser popup)
+/// * `p` - Switch to PickingProfile mode (library profile picker popup)
+/// * `L` - Switch to ViewingLog mode (recent log messages)
+/// * `f` - Switch to FolderSettings mode (settings of the scan roots)
+/// * `c` - Switch to PickingCollection mode (choose the shown collection)
+/// * `+` - Switch to PickingCollection mode (add the selected book to a collection)
+/// * `-` - Remove the selected book from the shown collection
+/// * `s` - Star / unstar the selected book
+/// * `S` - Show only starred books (toggle)
+/// * `r` - Cycle the reading status of the selected book
+/// * `R` - Cycle the reading status filter
+/// * `1`-`5` - Rate the selected book, `0` clears the rating
+/// * `o` - Cycle the order of the book list
+/// * `/` - Switch to Filtering mode (type a filter expression)
+/// * `x` - Archive / unarchive the selected book
+/// * `X` - Show archived books too (toggle)
+/// * `A` - Switch to MergingAuthors mode (review author spellings)
+/// * `e` - Show the series of the selected book in reading order (toggle)
+/// * `v` - Mark / unmark the selected book and move down
+/// * `E` - Switch to BulkEditing mode (edit the marked books, or the selected one)
+/// * `b` - Switch to EditingMetadata mode (the selected book's metadata form)
+/// * `u` - Undo the last library operation (bulk edit, removal, move, rename...)
+/// * `U` - Redo the last undone operation
+/// * `m` - Look the selected book up online (main loop fetches, then ReviewingMetadata mode)
+/// * `H` - Switch to HealthReport mode (books with missing or broken files)
+/// * `w` - Switch to Statistics mode (the library's totals)
+/// * `d` - Switch to Duplicates mode (books that look like the same work)
+/// * `O` - Switch to ReviewingMoves mode (preview organizing the marked books, or all)
+/// * `N` - Switch to ReviewingMoves mode (preview renaming the marked books, or the shown ones)
+/// * `P` - Switch to Peers mode (other FunkHunt instances on the network)
+/// * `T` - Switch to Transfers mode (downloads and uploads)
+/// * `Q` - Share the selected book with a phone (main loop starts the share, then Sharing mode)
+/// * `D` - Switch to Devices mode (e-readers plugged in over USB)
+/// * `M` - Switch to Emailing mode (type the address to email the marked books, or the selected one, to)
+/// * `K` - Switch to Converting mode (choose the format to convert the marked books, or the selected one, to)
+/// * `W` - Write the text of the marked books (or the selected one) to files ([extract] folder and format)
+/// * `I` - Switch to SettingCover mode (type the image file or URL of the selected book's new cover)
+/// * `Z` - Make the marked books (or the selected one) smaller (main loop queues the optimizations)
+/// * `t` - Switch to Fonts mode (the fonts embedded in the selected book)
+/// * `B` - Split the selected omnibus into a book per part, added to the library
+/// * `J` - Switch to Joining mode (type the title of the anthology the marked books are merged into)
+/// * `i` - Show (or hide) the first pages of the selected book in the details pane
+/// * `y` - Switch to Quotes mode (the passages captured from books)
+/// * `Y` - Switch to QuotingDescription mode (pick sentences of the selected book's description as a quote)
+/// * `C` - Browse the Calibre content server (main loop fetches, then BrowsingCalibre mode)
+/// * `F` - Show the new books of the subscribed feeds (main loop fetches, then BrowsingFeeds mode)
+/// * `V` - Switch to Reading mode (the selected book in the built-in reader)
+/// * `g` - Switch to SearchingText mode (type words to find in the books' text)
+/// * `k` - Continue reading the book read last, where reading stopped
+/// * `n` - Listen to the selected book's narration (main loop starts mpv, then Listening mode)
+/// * `Esc` - Clear the marks
 ///
 /// # Arguments
 /// * `key_event` - The keyboard event
 /// * `state` - Mutable reference to application state
 ///
 /// # Returns
-/// Always returns None (no actions needed, everything is handled in state)
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::FetchMetadata)` - The selected book should be looked up online
+/// * `Some(AppAction::SaveNow)` - An operation was undone or redone
+/// * `Some(AppAction::ShareBook)` - The selected book should be shared
+/// * `Some(AppAction::BrowseCalibre)` - The Calibre library should be listed
+/// * `Some(AppAction::BrowseFeeds)` - The subscribed feeds should be fetched
+/// * `Some(AppAction::OptimizeBooks)` - The marked books (or the selected one) should be optimized
+/// * `Some(AppAction::Listen)` - The selected book's narration should be played
+/// * `Some(AppAction::SplitBook)` - The selected book should be split into its parts
 fn handle_normal_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    // Configured letter keys - cloned so we can still modify state in the match
+    let keys = state.settings.keys.clone();
+
     // Pattern match on the key that was pressed
     match key_event.code {
         // 'q' key quits the application
-        KeyCode::Char('q') => state.should_quit = true,
+        KeyCode::Char(c) if c == keys.quit => state.should_quit = true,
 
         // Arrow keys navigate the book list
         KeyCode::Up => state.move_selection_up(),
@@ -52,15 +241,37 @@ fn handle_normal_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAc
 
         // Enter opens the selected book
         KeyCode::Enter => {
-            // Get the selected book (if any)
+            // A book on a server is downloaded first - main loop does that
             if let Some(book) = state.selected_book() {
-                // Try to open it, ignore errors (using _ = discards the Result)
-                let _ = book.open();
+                if crate::remote::is_remote(&book.path) {
+                    return Some(AppAction::OpenRemote(book.path.clone()));
+                }
+            }
+
+            let viewer = state.settings.viewer.command.clone();
+
+            // Get the selected book (if any)
+            if let Some(book) = state.selected_book_mut() {
+                // Try to open it - failures are logged and shown in the footer
+                match book.open(viewer.as_deref()) {
+                    Ok(()) => {
+                        // Remember when, for "Recently opened"
+                        book.opened = Some(crate::book::unix_now());
+                        // and what it contains, so it can be found again if it moves
+                        book.ensure_hash();
+                        state.dirty = true;
+                        state.refresh_view();
+                    }
+                    Err(e) => {
+                        tracing::warn!(path = %book.path.display(), error = %e, "cannot open book");
+                        state.status_message = Some(format!("Cannot open book: {}", e));
+                    }
+                }
             }
         }
 
         // 'a' key opens the file browser to add a folder
-        KeyCode::Char('a') => {
+        KeyCode::Char(c) if c == keys.add_folder => {
             // Switch to AddingFolder mode
             state.mode = UiMode::AddingFolder;
 
@@ -68,6 +279,468 @@ fn handle_normal_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAc
             state.browser.load_entries();
         }
 
+        // 'p' key opens the library profile picker
+        KeyCode::Char(c) if c == keys.switch_library => {
+            // Refresh the profile list before showing it
+            let current = state.profile_name.clone();
+            state.profile_picker.load(&current);
+
+            state.mode = UiMode::PickingProfile;
+        }
+
+        // 'L' key opens the log viewer, scrolled to the newest line
+        KeyCode::Char(c) if c == keys.show_log => {
+            state.log_scroll = 0;
+            state.mode = UiMode::ViewingLog;
+        }
+
+        // 'f' key opens the folder settings screen
+        KeyCode::Char(c) if c == keys.folder_settings => {
+            state.folder_screen.selected_index = 0;
+            state.folder_screen.editing = None;
+            state.folder_screen.dirty = false;
+            state.mode = UiMode::FolderSettings;
+        }
+
+        // 'c' key picks the collection shown in the list
+        KeyCode::Char(c) if c == keys.collections => {
+            open_collection_picker(state, CollectionPurpose::Show);
+        }
+
+        // '+' key adds the selected book to a collection
+        KeyCode::Char(c) if c == keys.add_to_collection && state.selected_book().is_some() => {
+            open_collection_picker(state, CollectionPurpose::AddBook);
+        }
+
+        // '-' key removes the selected book from the shown collection
+        KeyCode::Char(c) if c == keys.remove_from_collection => {
+            let Shelf::Collection(name) = state.shelf.clone() else {
+                state.status_message = Some(format!(
+                    "Show a collection first ({}) to remove books from it",
+                    keys.collections
+                ));
+                return None;
+            };
+            if let Some(book) = state.selected_book_mut() {
+                book.user.collections.retain(|c| *c != name);
+                state.dirty = true;
+                state.status_message = Some(format!("Removed from '{}'", name));
+                // The book no longer belongs to the shown list
+                state.refresh_view();
+            }
+        }
+
+        // 's' key stars or unstars the selected book
+        KeyCode::Char(c) if c == keys.star => {
+            if let Some(book) = state.selected_book_mut() {
+                book.user.starred = !book.user.starred;
+                state.dirty = true;
+                // An unstarred book leaves the "starred only" list
+                state.refresh_view();
+            }
+        }
+
+        // 'S' key toggles the "starred only" filter
+        KeyCode::Char(c) if c == keys.starred_only => {
+            state.starred_only = !state.starred_only;
+            state.refresh_view();
+        }
+
+        // 'r' key moves the selected book to its next reading status
+        KeyCode::Char(c) if c == keys.cycle_status => {
+            if let Some(book) = state.selected_book_mut() {
+                book.user.status = ReadingStatus::cycle(book.user.status);
+                let label = book.user.status.map_or("No status", ReadingStatus::label);
+                state.status_message = Some(format!("Status: {}", label));
+                state.dirty = true;
+                // With a status filter on, the book may leave the list
+                state.refresh_view();
+            }
+        }
+
+        // 'R' key cycles the filter: any status -> To Read -> ... -> Abandoned -> any
+        KeyCode::Char(c) if c == keys.status_filter => {
+            state.status_filter = ReadingStatus::cycle(state.status_filter);
+            state.selected_index = 0;
+            state.refresh_view();
+        }
+
+        // '/' key opens the filter prompt with the current filter to edit
+        KeyCode::Char(c) if c == keys.filter => {
+            state.filter_input = state.filter_text.clone();
+            state.mode = UiMode::Filtering;
+        }
+
+        // 'x' key archives or unarchives the selected book
+        KeyCode::Char(c) if c == keys.archive => {
+            if let Some(book) = state.selected_book_mut() {
+                book.user.archived = !book.user.archived;
+                let message = if book.user.archived { "Archived" } else { "Unarchived" };
+                state.status_message = Some(message.to_string());
+                state.dirty = true;
+                // An archived book leaves the list unless archived books are shown
+                state.refresh_view();
+            }
+        }
+
+        // 'X' key shows or hides archived books
+        KeyCode::Char(c) if c == keys.show_archived => {
+            state.show_archived = !state.show_archived;
+            state.refresh_view();
+        }
+
+        // 'A' key looks for author spellings to merge
+        KeyCode::Char(c) if c == keys.merge_authors => {
+            let groups = crate::authors::find_variants(&state.books);
+            if groups.is_empty() {
+                state.status_message = Some("No author spellings to merge".to_string());
+            } else {
+                state.author_screen.groups = groups;
+                state.author_screen.selected_index = 0;
+                state.author_screen.choice = 0;
+                state.mode = UiMode::MergingAuthors;
+            }
+        }
+
+        // 'e' key shows the series of the selected book, or goes back to all books
+        KeyCode::Char(c) if c == keys.series => {
+            if matches!(state.shelf, Shelf::Series(_)) {
+                state.show_shelf(Shelf::All);
+            } else {
+                match state.selected_book().and_then(|book| book.meta.series.clone()) {
+                    Some(series) => {
+                        state.show_shelf(Shelf::Series(series));
+                        // Start on the book to read next
+                        if let Some(next) = state.next_in_series() {
+                            let path = state.books[next].path.clone();
+                            state.select_path(&path);
+                        }
+                    }
+                    None => {
+                        state.status_message = Some("This book is not part of a series".to_string())
+                    }
+                }
+            }
+        }
+
+        // 'v' key marks the selected book and moves on, so runs are quick to mark
+        KeyCode::Char(c) if c == keys.mark => {
+            state.toggle_mark();
+            state.move_selection_down();
+        }
+
+        // 'E' key opens the bulk edit form with empty fields
+        KeyCode::Char(c) if c == keys.bulk_edit => {
+            if state.target_indices().is_empty() {
+                state.status_message = Some("No books to edit".to_string());
+            } else {
+                state.bulk_edit = BulkEdit::new();
+                state.mode = UiMode::BulkEditing;
+            }
+        }
+
+        // 'b' key opens the selected book's metadata form, filled in
+        KeyCode::Char(c) if c == keys.edit_metadata => {
+            state.metadata_form = Some(MetadataForm::new(state.selected_book()?));
+            state.mode = UiMode::EditingMetadata;
+        }
+
+        // 'u' / 'U' keys undo / redo the last library operation - saved right
+        // away, as files may have moved back
+        KeyCode::Char(c) if c == keys.undo || c == keys.redo => {
+            state.status_message = Some(state.undo_redo(c == keys.redo));
+            return Some(AppAction::SaveNow);
+        }
+
+        // 'm' key asks the main loop to look the selected book up online
+        KeyCode::Char(c) if c == keys.fetch_metadata && state.selected_book().is_some() => {
+            return Some(AppAction::FetchMetadata);
+        }
+
+        // 'H' key checks every file of the library and shows the problems
+        KeyCode::Char(c) if c == keys.health => {
+            state.health_screen.issues = crate::health::check(&state.books);
+            state.health_screen.selected_index = 0;
+            state.health_screen.relocate_input = None;
+            state.mode = UiMode::HealthReport;
+        }
+
+        // 'w' key counts and measures the library
+        KeyCode::Char(c) if c == keys.statistics => {
+            state.statistics = Some(crate::stats::LibraryStats::of(&state.books));
+            state.mode = UiMode::Statistics;
+        }
+
+        // 'd' key lists the books that look like duplicates
+        KeyCode::Char(c) if c == keys.duplicates => {
+            state.duplicates_screen = DuplicatesScreen {
+                pairs: crate::duplicates::suspected(&state.books),
+                ..DuplicatesScreen::default()
+            };
+            state.mode = UiMode::Duplicates;
+        }
+
+        // 'P' key lists the peers found on the network
+        KeyCode::Char(c) if c == keys.peers => {
+            state.peers_screen.selected_index = 0;
+            state.mode = UiMode::Peers;
+        }
+
+        // 'T' key lists the downloads and uploads
+        KeyCode::Char(c) if c == keys.transfers => {
+            state.transfers_screen.selected_index = 0;
+            state.mode = UiMode::Transfers;
+        }
+
+        // 'D' key lists the e-readers plugged in
+        KeyCode::Char(c) if c == keys.devices => {
+            state.devices_screen.devices = crate::device::detect(&state.settings.devices.mounts);
+            state.devices_screen.selected_index = 0;
+            state.mode = UiMode::Devices;
+        }
+
+        // 'M' key asks where to email the marked books (or the selected one)
+        KeyCode::Char(c) if c == keys.email => {
+            state.selected_book()?;
+            if state.settings.email.host.is_none() {
+                state.status_message = Some("Sending by email: set [email] host in config.toml first".to_string());
+                return None;
+            }
+            state.email_input = state.settings.email.to.clone().unwrap_or_default();
+            state.mode = UiMode::Emailing;
+        }
+
+        // 'K' key asks what to convert the marked books (or the selected one) to
+        KeyCode::Char(c) if c == keys.convert => {
+            state.selected_book()?;
+            state.mode = UiMode::Converting;
+        }
+
+        // 'W' key writes the text of the marked books (or the selected one) to files
+        KeyCode::Char(c) if c == keys.extract => {
+            state.selected_book()?;
+            let settings = &state.settings.extract;
+            let folder = settings.folder.clone().unwrap_or_else(|| crate::paths::data_dir().join("text"));
+            let (mut written, mut failed) = (Vec::new(), Vec::new());
+            for i in state.target_indices() {
+                let book = &state.books[i];
+                if crate::remote::is_remote(&book.path) {
+                    failed.push(format!("{}: not on this computer", book.display_title()));
+                    continue;
+                }
+                match crate::extract::export_book(&book.path, book.display_title(), &folder, settings.format) {
+                    Ok(dest) => written.push(dest),
+                    Err(e) => {
+                        tracing::warn!(path = %book.path.display(), error = %e, "cannot write the text of a book");
+                        failed.push(format!("{}: {}", book.display_title(), e));
+                    }
+                }
+            }
+            state.status_message = Some(match (written.as_slice(), failed.as_slice()) {
+                ([dest], []) => format!("Text written to {}", dest.display()),
+                (_, []) => format!("Text of {} books written to {}", written.len(), folder.display()),
+                ([], [reason]) => format!("Text not written - {}", reason),
+                _ => format!("Text of {} books written to {} (failed: {})", written.len(), folder.display(), failed.join(", ")),
+            });
+        }
+
+        // 'I' key asks where the selected book's new cover comes from
+        KeyCode::Char(c) if c == keys.cover => {
+            let book = state.selected_book()?;
+            if crate::remote::is_remote(&book.path) {
+                state.status_message = Some("Only books on this computer can get a new cover".to_string());
+                return None;
+            }
+            state.cover_input.clear();
+            state.mode = UiMode::SettingCover;
+        }
+
+        // 'Z' key makes the marked books (or the selected one) smaller
+        KeyCode::Char(c) if c == keys.optimize => {
+            state.selected_book()?;
+            return Some(AppAction::OptimizeBooks);
+        }
+
+        // 't' key lists the fonts embedded in the selected book
+        KeyCode::Char(c) if c == keys.fonts => {
+            let path = state.selected_book()?.path.clone();
+            if crate::remote::is_remote(&path) {
+                state.status_message = Some("Only the fonts of books on this computer can be listed".to_string());
+                return None;
+            }
+            let fonts = crate::fonts::list(&path).map_err(|e| e.to_string());
+            state.font_list = Some(FontList { path, fonts });
+            state.mode = UiMode::Fonts;
+        }
+
+        // 'B' key splits the selected omnibus into its parts
+        KeyCode::Char(c) if c == keys.split => {
+            let book = state.selected_book()?;
+            if crate::remote::is_remote(&book.path) {
+                state.status_message = Some("Only books on this computer can be split".to_string());
+                return None;
+            }
+            return Some(AppAction::SplitBook);
+        }
+
+        // 'J' key asks the title of the anthology the marked books are merged into
+        KeyCode::Char(c) if c == keys.join => {
+            if state.marked.len() < 2 {
+                state.status_message = Some(format!("Mark the books to merge first ({}), at least two", keys.mark));
+                return None;
+            }
+            state.join_input.clear();
+            state.mode = UiMode::Joining;
+        }
+
+        // 'i' key shows the first pages of the selected book instead of its
+        // details (main loop reads them), or its details again
+        KeyCode::Char(c) if c == keys.preview => {
+            state.previewing = !state.previewing;
+            state.preview = None;
+        }
+
+        // 'y' key shows the quotes captured from books
+        KeyCode::Char(c) if c == keys.quotes => {
+            state.quotes_screen.quotes = crate::quotes::load();
+            state.quotes_screen.selected_index = state.quotes_screen.quotes.len().saturating_sub(1);
+            state.mode = UiMode::Quotes;
+        }
+
+        // 'Y' key picks sentences of the selected book's description as a quote
+        KeyCode::Char(c) if c == keys.quote_description => {
+            let book = state.selected_book()?;
+            let sentences = crate::quotes::sentences(book.meta.description.as_deref().unwrap_or_default());
+            if sentences.is_empty() {
+                state.status_message = Some("The book has no description to quote".to_string());
+                return None;
+            }
+            state.description_quote = Some(DescriptionQuote {
+                title: book.display_title().to_string(),
+                authors: book.meta.authors.clone(),
+                sentences,
+                selected_index: 0,
+                start: None,
+            });
+            state.mode = UiMode::QuotingDescription;
+        }
+
+        // 'C' key lists the books of the Calibre content server
+        KeyCode::Char(c) if c == keys.calibre => {
+            if state.settings.calibre.url.is_none() {
+                state.status_message = Some("Calibre: set [calibre] url in config.toml first".to_string());
+                return None;
+            }
+            return Some(AppAction::BrowseCalibre);
+        }
+
+        // 'F' key shows the new books of the subscribed feeds
+        KeyCode::Char(c) if c == keys.feeds => {
+            if state.settings.feeds.urls.is_empty() {
+                state.status_message = Some("Feeds: set [feeds] urls in config.toml first".to_string());
+                return None;
+            }
+            return Some(AppAction::BrowseFeeds);
+        }
+
+        // 'V' key opens the selected book in the built-in reader
+        KeyCode::Char(c) if c == keys.read => open_reader(state),
+
+        // 'g' key asks for words to find in the text of the books
+        KeyCode::Char(c) if c == keys.search_text => state.mode = UiMode::SearchingText,
+
+        // 'k' key reopens the book read last, where reading stopped
+        KeyCode::Char(c) if c == keys.continue_reading => match state.continue_reading_book() {
+            Some(index) => {
+                let path = state.books[index].path.clone();
+                state.select_path(&path);
+                read_book(state, index);
+            }
+            None => state.status_message = Some(format!("No book read here yet: {} reads one", keys.read)),
+        },
+
+        // 'n' key plays the selected book's narration, or shows the controls
+        // of the one playing
+        KeyCode::Char(c) if c == keys.listen => {
+            let path = state.selected_book()?.path.clone();
+            if state.listening.as_ref().is_some_and(|listening| listening.player.book == path) {
+                state.mode = UiMode::Listening;
+                return None;
+            }
+            if crate::remote::is_remote(&path) {
+                state.status_message = Some("Only books on this computer can be listened to".to_string());
+                return None;
+            }
+            return Some(AppAction::Listen);
+        }
+
+        // 'Q' key shares the selected book with a phone (QR code of its URL)
+        KeyCode::Char(c) if c == keys.share => {
+            let book = state.selected_book()?;
+            if crate::remote::is_remote(&book.path) {
+                state.status_message = Some("Only books on this computer can be shared".to_string());
+                return None;
+            }
+            return Some(AppAction::ShareBook);
+        }
+
+        // 'O' key previews organizing the marked books (or the whole library)
+        KeyCode::Char(c) if c == keys.organize => {
+            let template = state.settings.organize.template.clone();
+            let indices: Vec<usize> = if state.marked.is_empty() {
+                (0..state.books.len()).collect()
+            } else {
+                state.target_indices()
+            };
+            let moves = crate::organize::plan(
+                indices.iter().map(|&i| &state.books[i]),
+                &state.folder_screen.folders,
+                |book| crate::organize::render(&template, book),
+            );
+            open_move_review(state, format!("Organize into {}", template), moves);
+        }
+
+        // 'N' key previews renaming the marked books (or every book the filters show)
+        KeyCode::Char(c) if c == keys.rename => {
+            let template = state.settings.organize.rename_template.clone();
+            let indices: Vec<usize> = if state.marked.is_empty() {
+                state.view.clone()
+            } else {
+                state.target_indices()
+            };
+            let moves = crate::organize::plan(
+                indices.iter().map(|&i| &state.books[i]),
+                &state.folder_screen.folders,
+                |book| crate::organize::renamed(book, &template),
+            );
+            open_move_review(state, format!("Rename to {}", template), moves);
+        }
+
+        // Esc clears the marks
+        KeyCode::Esc => state.marked.clear(),
+
+        // 'o' key cycles the list order (the selected book stays selected)
+        KeyCode::Char(c) if c == keys.sort => {
+            state.sort = state.sort.next();
+            state.status_message = Some(format!("Sorted by {}", state.sort.label()));
+            state.refresh_view();
+        }
+
+        // Digit keys rate the selected book: 1-5 stars, 0 clears
+        KeyCode::Char(c @ '0'..='5') => {
+            if let Some(book) = state.selected_book_mut() {
+                let stars = c as u8 - b'0';
+                book.user.rating = (stars > 0).then_some(stars);
+                state.status_message = Some(match book.user.rating {
+                    Some(stars) => format!("Rated {}", crate::book::stars_text(stars)),
+                    None => "Rating cleared".to_string(),
+                });
+                state.dirty = true;
+                // The book may move when the list is sorted by rating
+                state.refresh_view();
+            }
+        }
+
         // Any other key is ignored
         _ => {}
     }
@@ -137,3 +810,2337 @@ fn handle_adding_folder_mode(key_event: KeyEvent, state: &mut TuiState) -> Optio
     // No action needed (unless we returned early with Enter)
     None
 }
+
+/// Handles keyboard events in PickingProfile mode (library profile picker popup)
+///
+/// # Key bindings:
+/// * `↑` - Move selection up in profile list
+/// * `↓` - Move selection down in profile list
+/// * `Enter` - Switch to the selected profile
+/// * `Esc` - Cancel and return to Normal mode
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::SwitchProfile)` - User picked a different profile
+fn handle_picking_profile_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    match key_event.code {
+        // Arrow keys navigate the profile list
+        KeyCode::Up => state.profile_picker.move_up(),
+        KeyCode::Down => state.profile_picker.move_down(),
+
+        // Enter switches to the selected profile
+        KeyCode::Enter => {
+            state.mode = UiMode::Normal;
+
+            // Picking the profile that is already open is a no-op
+            if let Some(name) = state.profile_picker.selected_name() {
+                if *name != state.profile_name {
+                    return Some(AppAction::SwitchProfile(name.clone()));
+                }
+            }
+        }
+
+        // Esc cancels - keep the current profile
+        KeyCode::Esc => state.mode = UiMode::Normal,
+
+        // Any other key is ignored
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in ViewingLog mode (log viewer popup)
+///
+/// # Key bindings:
+/// * `↑` / `PageUp` - Scroll towards older messages
+/// * `↓` / `PageDown` - Scroll towards newer messages
+/// * `Esc` or `q` - Close the log viewer
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// Always None (scrolling only changes state)
+fn handle_viewing_log_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    // Can't scroll further up than the number of lines
+    let max_scroll = state.log.lines().len().saturating_sub(1);
+
+    match key_event.code {
+        KeyCode::Up => state.log_scroll = (state.log_scroll + 1).min(max_scroll),
+        KeyCode::PageUp => state.log_scroll = (state.log_scroll + 10).min(max_scroll),
+        KeyCode::Down => state.log_scroll = state.log_scroll.saturating_sub(1),
+        KeyCode::PageDown => state.log_scroll = state.log_scroll.saturating_sub(10),
+        KeyCode::Esc | KeyCode::Char('q') => state.mode = UiMode::Normal,
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in FolderSettings mode (scan root settings screen)
+///
+/// # Key bindings:
+/// * `↑` / `↓` - Select a folder
+/// * `r` - Toggle read-only
+/// * `w` - Toggle auto-watch
+/// * `m` - Toggle writing edited metadata into the EPUB files
+/// * `e` - Edit exclude patterns (comma-separated)
+/// * `t` - Edit default tags (comma-separated)
+/// * `Esc` - Close the screen (saving any changes)
+///
+/// While a text field is being edited, typed characters go to the input
+/// line: `Enter` stores it, `Esc` discards it, `Backspace` deletes.
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::SaveFolderSettings)` - Screen closed with changes
+fn handle_folder_settings_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let screen = &mut state.folder_screen;
+
+    // Text input line is active - it gets every key
+    if screen.editing.is_some() {
+        match key_event.code {
+            KeyCode::Enter => screen.commit_editing(),
+            KeyCode::Esc => {
+                screen.editing = None;
+                screen.input.clear();
+            }
+            KeyCode::Backspace => {
+                screen.input.pop();
+            }
+            KeyCode::Char(c) => screen.input.push(c),
+            _ => {}
+        }
+        return None;
+    }
+
+    match key_event.code {
+        KeyCode::Up => screen.move_up(),
+        KeyCode::Down => screen.move_down(),
+
+        // Toggles
+        KeyCode::Char('r') => {
+            if let Some(settings) = screen.selected_mut() {
+                settings.read_only = !settings.read_only;
+                screen.dirty = true;
+            }
+        }
+        KeyCode::Char('w') => {
+            if let Some(settings) = screen.selected_mut() {
+                settings.auto_watch = !settings.auto_watch;
+                screen.dirty = true;
+            }
+        }
+        KeyCode::Char('j') => {
+            if let Some(settings) = screen.selected_mut() {
+                settings.sidecars = !settings.sidecars;
+                screen.dirty = true;
+            }
+        }
+        KeyCode::Char('m') => {
+            if let Some(settings) = screen.selected_mut() {
+                settings.write_metadata = !settings.write_metadata;
+                screen.dirty = true;
+            }
+        }
+
+        // Text fields
+        KeyCode::Char('e') => screen.start_editing(FolderField::Exclude),
+        KeyCode::Char('t') => screen.start_editing(FolderField::DefaultTags),
+
+        // Close - hand the changes to the main loop
+        KeyCode::Esc => {
+            state.mode = UiMode::Normal;
+            if state.folder_screen.dirty {
+                state.folder_screen.dirty = false;
+                return Some(AppAction::SaveFolderSettings(state.folder_screen.folders.clone()));
+            }
+        }
+
+        _ => {}
+    }
+
+    None
+}
+
+/// Opens the selected book in the built-in reader
+///
+/// Books on a server aren't read in place: Enter downloads them first.
+fn open_reader(state: &mut TuiState) {
+    let Some(&index) = state.view.get(state.selected_index) else {
+        return;
+    };
+    if crate::remote::is_remote(&state.books[index].path) {
+        state.status_message = Some("Books on a server can't be read here: open them with Enter".to_string());
+        return;
+    }
+    read_book(state, index);
+}
+
+/// Opens a book of the library in the built-in reader, where reading it
+/// stopped (at its beginning the first time)
+///
+/// # Arguments
+/// * `state` - Mutable reference to application state
+/// * `index` - Index of the book in `state.books` (a file on this computer)
+fn read_book(state: &mut TuiState, index: usize) {
+    let book = &state.books[index];
+    match Document::open(&book.path) {
+        Ok(document) => {
+            let mut document = document;
+            // Hyphenated the way the language of its text wants
+            document.language = book.language().map(str::to_string);
+            if state.graphics.is_some() {
+                if let Err(e) = document.measure_images(&book.path) {
+                    tracing::warn!(path = %book.path.display(), error = %e, "cannot read images");
+                }
+            }
+            let title = book.display_title().to_string();
+            let mut reader = BookReader::new(title, book.path.clone(), document, state.typography, state.graphics);
+            if let Some(position) = &book.user.position {
+                reader.go_to(position.chapter, position.block);
+            }
+            state.reader = Some(reader);
+            state.mode = UiMode::Reading;
+            // Remembered as for Enter, for "Recently opened" and moved files
+            let book = &mut state.books[index];
+            book.opened = Some(crate::book::unix_now());
+            // Now the book read last, even if no page is turned
+            if let Some(position) = book.user.position.as_mut() {
+                position.read_at = crate::book::unix_now();
+            }
+            book.ensure_hash();
+            state.dirty = true;
+        }
+        Err(e) => {
+            tracing::warn!(path = %book.path.display(), error = %e, "cannot read book");
+            state.status_message = Some(format!("Cannot read book: {}", e));
+        }
+    }
+}
+
+/// Opens the collection picker for the given purpose
+///
+/// When choosing what to show, the cursor starts on the shown shelf.
+fn open_collection_picker(state: &mut TuiState, purpose: CollectionPurpose) {
+    state.collection_picker.purpose = purpose;
+    state.collection_picker.input = None;
+
+    let current = state.shelf.clone();
+    state.collection_picker.selected_index = state
+        .collection_entries()
+        .iter()
+        .position(|entry| purpose == CollectionPurpose::Show && *entry == current)
+        .unwrap_or(0);
+
+    state.mode = UiMode::PickingCollection;
+}
+
+/// Handles keyboard events in PickingCollection mode (collection picker popup)
+///
+/// # Key bindings:
+/// * `↑` / `↓` - Select a collection
+/// * `Enter` - Show the collection, or add the selected book to it
+/// * `n` - Create a new collection (type its name, Enter to confirm)
+/// * `m` - Save the current filters and sort order as a smart collection
+/// * `d` - Delete the selected collection (books stay in the library)
+/// * `Esc` - Cancel and return to Normal mode
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::SaveCollections)` - A collection was created or deleted
+fn handle_picking_collection_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    // Typing the name of a new collection - the input line gets every key
+    if let Some(input) = &mut state.collection_picker.input {
+        match key_event.code {
+            KeyCode::Enter => {
+                let name = input.trim().to_string();
+                state.collection_picker.input = None;
+                return if state.collection_picker.input_smart {
+                    create_smart_collection(state, name)
+                } else {
+                    create_collection(state, name)
+                };
+            }
+            KeyCode::Esc => state.collection_picker.input = None,
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Char(c) => input.push(c),
+            _ => {}
+        }
+        return None;
+    }
+
+    let entries = state.collection_entries();
+    let picker = &mut state.collection_picker;
+
+    match key_event.code {
+        KeyCode::Up => {
+            picker.selected_index = picker.selected_index.saturating_sub(1);
+        }
+        KeyCode::Down if picker.selected_index < entries.len().saturating_sub(1) => {
+            picker.selected_index += 1;
+        }
+
+        // Start typing a new collection name
+        KeyCode::Char('n') => {
+            picker.input = Some(String::new());
+            picker.input_smart = false;
+        }
+
+        // Start typing the name of a smart collection for the current filters
+        KeyCode::Char('m') if picker.purpose == CollectionPurpose::Show => {
+            if state.current_query().is_empty() {
+                state.status_message = Some("Set a filter first ('/', starred or status) to save it".to_string());
+            } else {
+                let picker = &mut state.collection_picker;
+                picker.input = Some(String::new());
+                picker.input_smart = true;
+            }
+        }
+
+        // Delete the selected collection ("All books" can't be deleted)
+        KeyCode::Char('d') => match entries.get(picker.selected_index) {
+            Some(Shelf::Collection(name)) => return delete_collection(state, name.clone()),
+            Some(Shelf::Smart(name)) => return delete_smart_collection(state, name.clone()),
+            _ => {}
+        },
+
+        KeyCode::Enter => {
+            let entry = entries.get(picker.selected_index).cloned()?;
+            state.mode = UiMode::Normal;
+
+            match state.collection_picker.purpose {
+                // Show the picked shelf from its first book
+                CollectionPurpose::Show => state.show_shelf(entry),
+                CollectionPurpose::AddBook => {
+                    if let Shelf::Collection(name) = entry {
+                        add_selected_to_collection(state, &name);
+                    }
+                }
+            }
+        }
+
+        KeyCode::Esc => state.mode = UiMode::Normal,
+
+        _ => {}
+    }
+
+    None
+}
+
+/// Creates a collection from the picker's input line
+///
+/// When the picker was opened to add a book, the book is added to the new
+/// collection right away.
+fn create_collection(state: &mut TuiState, name: String) -> Option<AppAction> {
+    if name.is_empty() {
+        return None;
+    }
+    if state.collections.contains(&name) {
+        state.status_message = Some(format!("Collection '{}' already exists", name));
+        return None;
+    }
+
+    state.collections.push(name.clone());
+    tracing::info!(collection = %name, "collection created");
+
+    if state.collection_picker.purpose == CollectionPurpose::AddBook {
+        state.mode = UiMode::Normal;
+        add_selected_to_collection(state, &name);
+    } else {
+        // Put the cursor on the new entry
+        let new_entry = Shelf::Collection(name);
+        state.collection_picker.selected_index = state
+            .collection_entries()
+            .iter()
+            .position(|entry| *entry == new_entry)
+            .unwrap_or(0);
+    }
+
+    Some(save_collections(state))
+}
+
+/// Saves the current filters and sort order as a smart collection and shows it
+fn create_smart_collection(state: &mut TuiState, name: String) -> Option<AppAction> {
+    if name.is_empty() {
+        return None;
+    }
+    if state.smart_collections.iter().any(|s| s.name == name) {
+        state.status_message = Some(format!("Smart collection '{}' already exists", name));
+        return None;
+    }
+
+    let smart = SmartCollection {
+        name: name.clone(),
+        query: state.current_query(),
+        sort: state.sort,
+    };
+    tracing::info!(collection = %name, query = %smart.query, "smart collection created");
+    state.smart_collections.push(smart);
+
+    // The smart collection now carries the filters - clear the loose ones
+    state.filter_text.clear();
+    state.filter = Filter::default();
+    state.starred_only = false;
+    state.status_filter = None;
+    state.mode = UiMode::Normal;
+    state.show_shelf(Shelf::Smart(name));
+
+    Some(save_collections(state))
+}
+
+/// Deletes a collection and takes every book out of it (the books themselves stay)
+fn delete_collection(state: &mut TuiState, name: String) -> Option<AppAction> {
+    state.collections.retain(|c| *c != name);
+    for book in &mut state.books {
+        book.user.collections.retain(|c| *c != name);
+    }
+    state.dirty = true;
+    tracing::info!(collection = %name, "collection deleted");
+
+    after_shelf_deleted(state, Shelf::Collection(name.clone()));
+    state.status_message = Some(format!("Deleted collection '{}'", name));
+
+    Some(save_collections(state))
+}
+
+/// Deletes a smart collection (only the saved filter - no book is touched)
+fn delete_smart_collection(state: &mut TuiState, name: String) -> Option<AppAction> {
+    state.smart_collections.retain(|s| s.name != name);
+    tracing::info!(collection = %name, "smart collection deleted");
+
+    after_shelf_deleted(state, Shelf::Smart(name.clone()));
+    state.status_message = Some(format!("Deleted smart collection '{}'", name));
+
+    Some(save_collections(state))
+}
+
+/// Leaves a deleted shelf if it was shown, and keeps the picker cursor in range
+fn after_shelf_deleted(state: &mut TuiState, deleted: Shelf) {
+    if state.shelf == deleted {
+        state.show_shelf(Shelf::All);
+    } else {
+        state.refresh_view();
+    }
+
+    let entries = state.collection_entries().len();
+    if state.collection_picker.selected_index >= entries {
+        state.collection_picker.selected_index = entries.saturating_sub(1);
+    }
+}
+
+/// Builds the action that stores both collection lists in the profile
+fn save_collections(state: &TuiState) -> AppAction {
+    AppAction::SaveCollections(state.collections.clone(), state.smart_collections.clone())
+}
+
+/// Adds the selected book to a collection (if it isn't in it already)
+fn add_selected_to_collection(state: &mut TuiState, name: &str) {
+    let Some(book) = state.selected_book_mut() else {
+        return;
+    };
+
+    if book.user.collections.iter().any(|c| c == name) {
+        state.status_message = Some(format!("Already in '{}'", name));
+    } else {
+        book.user.collections.push(name.to_string());
+        state.dirty = true;
+        state.status_message = Some(format!("Added to '{}'", name));
+    }
+}
+
+/// Handles keyboard events in Filtering mode (the '/' filter prompt)
+///
+/// # Key bindings:
+/// * Typing - Edit the filter expression (see `filter` for the syntax); the
+///   list shows what it matches as it's typed, whenever it parses
+/// * `Enter` - Apply the filter (an empty one shows everything again)
+/// * `Esc` - Close the prompt, back to the previous filter
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// Always returns None (everything is handled in state)
+fn handle_filtering_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    match key_event.code {
+        KeyCode::Enter => match Filter::parse(&state.filter_input) {
+            Ok(filter) => {
+                state.filter = filter;
+                state.filter_text = state.filter_input.trim().to_string();
+                state.selected_index = 0;
+                state.refresh_view();
+                state.mode = UiMode::Normal;
+            }
+            // Keep the prompt open so the expression can be fixed
+            Err(e) => state.status_message = Some(format!("Filter: {}", e)),
+        },
+        KeyCode::Esc => {
+            state.filter = Filter::parse(&state.filter_text).unwrap_or_default();
+            state.refilter();
+            state.mode = UiMode::Normal;
+        }
+        KeyCode::Backspace => {
+            state.filter_input.pop();
+            preview_filter(state);
+        }
+        KeyCode::Char(c) => {
+            state.filter_input.push(c);
+            preview_filter(state);
+        }
+        _ => {}
+    }
+
+    None
+}
+
+/// Shows what the expression typed so far matches (the book index answers,
+/// see query.rs); one that doesn't parse yet, e.g. `size:<`, changes nothing
+fn preview_filter(state: &mut TuiState) {
+    if let Ok(filter) = Filter::parse(&state.filter_input) {
+        state.filter = filter;
+        state.refilter();
+    }
+}
+
+/// Handles keyboard events in Emailing mode (the recipient prompt)
+///
+/// # Key bindings:
+/// * Typing - Edit the address
+/// * `Enter` - Send the books to the address
+/// * `Esc` - Close the prompt, sending nothing
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::EmailBooks)` - The books should be emailed to the typed address
+fn handle_emailing_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    match key_event.code {
+        KeyCode::Enter => {
+            let to = state.email_input.trim().to_string();
+            // Keep the prompt open so the address can be fixed
+            if !to.contains('@') || to.contains(char::is_whitespace) {
+                state.status_message = Some(format!("Email: \"{}\" is not an address", to));
+                return None;
+            }
+            state.mode = UiMode::Normal;
+            return Some(AppAction::EmailBooks(to));
+        }
+        KeyCode::Esc => state.mode = UiMode::Normal,
+        KeyCode::Backspace => {
+            state.email_input.pop();
+        }
+        KeyCode::Char(c) => state.email_input.push(c),
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in SettingCover mode (the cover prompt)
+///
+/// # Key bindings:
+/// * Typing - Edit the image file or URL (nothing = Open Library's cover of the book's ISBN)
+/// * `Enter` - Embed the image into the book
+/// * `Esc` - Close the prompt, changing nothing
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::SetCover)` - The image should be embedded into the selected book
+fn handle_setting_cover_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    match key_event.code {
+        KeyCode::Enter => {
+            state.mode = UiMode::Normal;
+            return Some(AppAction::SetCover(state.cover_input.trim().to_string()));
+        }
+        KeyCode::Esc => state.mode = UiMode::Normal,
+        KeyCode::Backspace => {
+            state.cover_input.pop();
+        }
+        KeyCode::Char(c) => state.cover_input.push(c),
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in Fonts mode (the fonts of the selected book)
+///
+/// # Key bindings:
+/// * `s` - Strip the fonts out of the book
+/// * `u` - Subset them to the characters the book uses
+/// * `Esc` - Close the list, changing nothing
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::SlimFonts)` - The book's fonts should be stripped or subset
+fn handle_fonts_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let action = match key_event.code {
+        KeyCode::Char('s') => FontAction::Strip,
+        KeyCode::Char('u') => FontAction::Subset,
+        KeyCode::Esc => {
+            state.font_list = None;
+            state.mode = UiMode::Normal;
+            return None;
+        }
+        _ => return None,
+    };
+    let list = state.font_list.as_ref()?;
+    if !list.fonts.as_ref().is_ok_and(|fonts| !fonts.is_empty()) {
+        return None;
+    }
+    state.mode = UiMode::Normal;
+    Some(AppAction::SlimFonts(action))
+}
+
+/// Handles keyboard events in Listening mode (the controls of the narration playing)
+///
+/// # Key bindings:
+/// * `Space` - Pause, or go on
+/// * `←` / `→` - Go back / forward 30 seconds
+/// * `<` / `>` - Go to the previous / next audio file
+/// * `s` - Stop playing (the position is kept)
+/// * `Esc` - Close the controls; the narration goes on playing
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+fn handle_listening_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let Some(listening) = state.listening.as_ref() else {
+        state.mode = UiMode::Normal;
+        return None;
+    };
+    let player = &listening.player;
+    let result = match key_event.code {
+        KeyCode::Char(' ') => player.toggle_pause(),
+        KeyCode::Left => player.seek(-30),
+        KeyCode::Right => player.seek(30),
+        KeyCode::Char('<') => player.skip(false),
+        KeyCode::Char('>') => player.skip(true),
+        KeyCode::Char('s') => {
+            state.stop_listening();
+            state.mode = UiMode::Normal;
+            return None;
+        }
+        KeyCode::Esc => {
+            state.mode = UiMode::Normal;
+            return None;
+        }
+        _ => return None,
+    };
+    if let Err(e) = result {
+        state.status_message = Some(format!("The player didn't answer - {}", e));
+    }
+    // What the key changed shows at once
+    state.poll_listening_now();
+    None
+}
+
+/// Handles keyboard events in Joining mode (the anthology's title prompt)
+///
+/// # Key bindings:
+/// * Typing - Edit the title (nothing = "Anthology")
+/// * `Enter` - Merge the marked books
+/// * `Esc` - Close the prompt, merging nothing
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::JoinBooks)` - The marked books should be merged into an anthology
+fn handle_joining_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    match key_event.code {
+        KeyCode::Enter => {
+            state.mode = UiMode::Normal;
+            let title = match state.join_input.trim() {
+                "" => "Anthology",
+                title => title,
+            };
+            return Some(AppAction::JoinBooks(title.to_string()));
+        }
+        KeyCode::Esc => state.mode = UiMode::Normal,
+        KeyCode::Backspace => {
+            state.join_input.pop();
+        }
+        KeyCode::Char(c) => state.join_input.push(c),
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in Quotes mode (the passages captured from books)
+///
+/// # Key bindings:
+/// * `↑/↓` / `PageUp/PageDown` - Select a quote
+/// * `x` / `Delete` - Remove the selected quote from the file
+/// * `Esc` / `q` - Back to the book list
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// Always `None` - the quotes file is written here
+fn handle_quotes_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let screen = &mut state.quotes_screen;
+    let last = screen.quotes.len().saturating_sub(1);
+    match key_event.code {
+        KeyCode::Up => screen.selected_index = screen.selected_index.saturating_sub(1),
+        KeyCode::Down => screen.selected_index = (screen.selected_index + 1).min(last),
+        KeyCode::PageUp => screen.selected_index = screen.selected_index.saturating_sub(10),
+        KeyCode::PageDown => screen.selected_index = (screen.selected_index + 10).min(last),
+        KeyCode::Char('x') | KeyCode::Delete if screen.selected_index < screen.quotes.len() => {
+            screen.quotes.remove(screen.selected_index);
+            screen.selected_index = screen.selected_index.min(screen.quotes.len().saturating_sub(1));
+            state.status_message = Some(match crate::quotes::save(&screen.quotes) {
+                Ok(()) => format!("Quote removed ({} left)", screen.quotes.len()),
+                Err(e) => {
+                    tracing::warn!(error = %e, "cannot write quotes");
+                    format!("Cannot write the quotes: {}", e)
+                }
+            });
+        }
+        KeyCode::Esc | KeyCode::Char('q') => state.mode = UiMode::Normal,
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in QuotingDescription mode (sentences of a
+/// book's description picked as a quote)
+///
+/// # Key bindings:
+/// * `↑/↓` - Select a sentence
+/// * `Enter` - The first sentence of the passage, then its last one (the passage is quoted)
+/// * `Esc` - Close the picker, quoting nothing
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// Always `None` - the quotes file is written here
+fn handle_quoting_description_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let Some(picker) = state.description_quote.as_mut() else {
+        state.mode = UiMode::Normal;
+        return None;
+    };
+    let last = picker.sentences.len().saturating_sub(1);
+    match key_event.code {
+        KeyCode::Up => picker.selected_index = picker.selected_index.saturating_sub(1),
+        KeyCode::Down => picker.selected_index = (picker.selected_index + 1).min(last),
+        KeyCode::Enter if picker.start.is_none() => picker.start = Some(picker.selected_index),
+        KeyCode::Enter => {
+            let quote = Quote {
+                text: picker.sentences[picker.passage()].join(" "),
+                title: picker.title.clone(),
+                authors: picker.authors.clone(),
+                place: "Description".to_string(),
+                created: crate::book::unix_now(),
+            };
+            state.status_message = Some(save_quote(quote));
+            state.description_quote = None;
+            state.mode = UiMode::Normal;
+        }
+        KeyCode::Esc => {
+            state.description_quote = None;
+            state.mode = UiMode::Normal;
+        }
+        _ => {}
+    }
+
+    None
+}
+
+/// Adds a quote to the quotes file
+///
+/// # Returns
+/// The status message saying how it went
+fn save_quote(quote: Quote) -> String {
+    match crate::quotes::add(quote) {
+        Ok(count) => format!("Quoted ({} quotes)", count),
+        Err(e) => {
+            tracing::warn!(error = %e, "cannot write quotes");
+            format!("Cannot write the quotes: {}", e)
+        }
+    }
+}
+
+/// Handles keyboard events in Converting mode (the format picker)
+///
+/// # Key bindings:
+/// * `↑/↓` - Select a format
+/// * `Enter` - Convert the books to it
+/// * `Esc` - Close the picker, converting nothing
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::ConvertBooks)` - The books should be converted to the selected format
+fn handle_converting_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    match key_event.code {
+        KeyCode::Up => state.convert_index = state.convert_index.saturating_sub(1),
+        KeyCode::Down => state.convert_index = (state.convert_index + 1).min(Format::TARGETS.len() - 1),
+        KeyCode::Enter => {
+            state.mode = UiMode::Normal;
+            return Some(AppAction::ConvertBooks(Format::TARGETS[state.convert_index]));
+        }
+        KeyCode::Esc => state.mode = UiMode::Normal,
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in SearchingText mode (the text search prompt)
+///
+/// # Key bindings:
+/// * Typing - Edit the words (a "quoted phrase" must match whole)
+/// * `Enter` - Search the text of the books for them
+/// * `Esc` - Close the prompt, searching nothing
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::SearchText)` - The index should be searched for the typed words
+fn handle_searching_text_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    match key_event.code {
+        KeyCode::Enter => {
+            let query = state.text_search_input.trim().to_string();
+            if query.is_empty() {
+                return None;
+            }
+            state.mode = UiMode::Normal;
+            return Some(AppAction::SearchText(query));
+        }
+        KeyCode::Esc => state.mode = UiMode::Normal,
+        KeyCode::Backspace => {
+            state.text_search_input.pop();
+        }
+        KeyCode::Char(c) => state.text_search_input.push(c),
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in TextSearchResults mode (chapters that matched)
+///
+/// # Key bindings:
+/// * `↑` / `↓` / `PageUp` / `PageDown` - Select a hit
+/// * `Enter` - Read the chapter of the selected hit, from its first paragraph with a word searched
+/// * `/` - Search again (back to the prompt)
+/// * `Esc` / `q` - Back to the book list
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// Always `None` - the hits were found by the main loop already
+fn handle_text_search_results_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let Some(search) = state.text_search.as_mut() else {
+        state.mode = UiMode::Normal;
+        return None;
+    };
+    let last = search.hits.len().saturating_sub(1);
+
+    match key_event.code {
+        KeyCode::Up => search.selected_index = search.selected_index.saturating_sub(1),
+        KeyCode::Down => search.selected_index = (search.selected_index + 1).min(last),
+        KeyCode::PageUp => search.selected_index = search.selected_index.saturating_sub(10),
+        KeyCode::PageDown => search.selected_index = (search.selected_index + 10).min(last),
+        KeyCode::Enter => {
+            let hit = search.hits.get(search.selected_index).cloned()?;
+            let words = crate::fulltext::query_words(&search.query);
+            let Some(index) = state.books.iter().position(|book| book.path == hit.path) else {
+                state.status_message = Some("That book is no longer in the library".to_string());
+                return None;
+            };
+            read_book(state, index);
+            if let Some(reader) = state.reader.as_mut() {
+                reader.show_words(hit.chapter, &words);
+            }
+        }
+        KeyCode::Char('/') => state.mode = UiMode::SearchingText,
+        KeyCode::Esc | KeyCode::Char('q') => {
+            state.text_search = None;
+            state.mode = UiMode::Normal;
+        }
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in MergingAuthors mode (author merge screen)
+///
+/// # Key bindings:
+/// * `↑` / `↓` - Select an author
+/// * `←` / `→` - Choose which spelling becomes the canonical name
+/// * `Enter` - Merge: every book uses the chosen spelling from now on
+/// * `Esc` - Close the screen (unmerged authors are left alone)
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::SaveAuthorAliases)` - An author was merged
+fn handle_merging_authors_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let screen = &mut state.author_screen;
+    let spellings = screen
+        .groups
+        .get(screen.selected_index)
+        .map(|group| group.spellings.len())
+        .unwrap_or(0);
+
+    match key_event.code {
+        KeyCode::Up => {
+            screen.selected_index = screen.selected_index.saturating_sub(1);
+            screen.choice = 0;
+        }
+        KeyCode::Down if screen.selected_index < screen.groups.len().saturating_sub(1) => {
+            screen.selected_index += 1;
+            screen.choice = 0;
+        }
+        KeyCode::Left => screen.choice = screen.choice.saturating_sub(1),
+        KeyCode::Right if screen.choice < spellings.saturating_sub(1) => {
+            screen.choice += 1;
+        }
+
+        KeyCode::Enter => {
+            if screen.selected_index >= screen.groups.len() {
+                return None;
+            }
+            let group = screen.groups.remove(screen.selected_index);
+            let canonical = group.spellings[screen.choice].0.clone();
+
+            // Every other spelling points to the chosen one
+            let aliases: BTreeMap<String, String> = group
+                .spellings
+                .iter()
+                .filter(|(spelling, _)| *spelling != canonical)
+                .map(|(spelling, _)| (spelling.clone(), canonical.clone()))
+                .collect();
+
+            // Keep the cursor in range for the next group
+            if screen.selected_index >= screen.groups.len() {
+                screen.selected_index = screen.groups.len().saturating_sub(1);
+            }
+            screen.choice = 0;
+            if screen.groups.is_empty() {
+                state.mode = UiMode::Normal;
+            }
+
+            let changed = crate::authors::apply_aliases(&mut state.books, &aliases);
+            tracing::info!(author = %canonical, books = changed, "authors merged");
+            state.index.touch_all();
+            state.status_message = Some(format!("Merged into '{}' ({} books)", canonical, changed));
+            state.dirty = true;
+            state.refresh_view();
+
+            return Some(AppAction::SaveAuthorAliases(aliases));
+        }
+
+        KeyCode::Esc => state.mode = UiMode::Normal,
+
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in BulkEditing mode (bulk edit form)
+///
+/// # Key bindings:
+/// * `↑` / `↓` / `Tab` - Move between fields
+/// * `←` / `→` / `Space` - Cycle the reading status (on the status field)
+/// * typing / `Backspace` - Edit the text field the cursor is on
+/// * `Enter` - Apply the form to the marked books (undo with `u`)
+/// * `Esc` - Close the form without changing anything
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// Always returns None (books are changed in state and saved by autosave)
+fn handle_bulk_editing_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let form = &mut state.bulk_edit;
+
+    match key_event.code {
+        KeyCode::Up | KeyCode::BackTab => form.move_field(false),
+        KeyCode::Down | KeyCode::Tab => form.move_field(true),
+
+        KeyCode::Left | KeyCode::Right | KeyCode::Char(' ') if form.field == BulkField::Status => {
+            form.cycle_status()
+        }
+
+        KeyCode::Backspace => {
+            if let Some(input) = form.input_mut() {
+                input.pop();
+            }
+        }
+        KeyCode::Char(c) => {
+            if let Some(input) = form.input_mut() {
+                input.push(c);
+            }
+        }
+
+        KeyCode::Enter => {
+            let changed = state.apply_bulk_edit();
+            tracing::info!(books = changed, "bulk edit applied");
+            state.status_message = Some(format!(
+                "Edited {} books (press '{}' to undo)",
+                changed, state.settings.keys.undo
+            ));
+            state.mode = UiMode::Normal;
+        }
+
+        KeyCode::Esc => state.mode = UiMode::Normal,
+
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in EditingMetadata mode (the metadata form of a book)
+///
+/// # Key bindings:
+/// * `Tab` / `↓` - Next field
+/// * `Shift+Tab` / `↑` - Previous field
+/// * typing / `Backspace` - Edit the field the cursor is on
+/// * `Enter` - Save the form if every field is valid (undo with `u`); else
+///   go to the first invalid field
+/// * `Esc` - Close the form without changing anything
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// Always returns None (the book is changed in state and saved by autosave)
+fn handle_editing_metadata_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let Some(form) = state.metadata_form.as_mut() else {
+        state.mode = UiMode::Normal;
+        return None;
+    };
+
+    match key_event.code {
+        KeyCode::Up | KeyCode::BackTab => form.move_field(false),
+        KeyCode::Down | KeyCode::Tab => form.move_field(true),
+
+        KeyCode::Backspace => {
+            form.input_mut().pop();
+            form.error = None;
+        }
+        KeyCode::Char(c) => {
+            form.input_mut().push(c);
+            form.error = None;
+        }
+
+        KeyCode::Enter => {
+            // An invalid form stays open, on the field to fix
+            if !state.save_metadata_form() {
+                return None;
+            }
+            state.status_message = Some(format!("Metadata saved (press '{}' to undo)", state.settings.keys.undo));
+            state.mode = UiMode::Normal;
+        }
+
+        KeyCode::Esc => {
+            state.metadata_form = None;
+            state.mode = UiMode::Normal;
+        }
+
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in ReviewingMetadata mode (changes found online)
+///
+/// # Key bindings:
+/// * `↑` / `↓` - Select a change
+/// * `Space` - Tick / untick the selected change
+/// * `Enter` - Apply the ticked changes to the book
+/// * `Esc` - Discard everything the provider found
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// Always returns None (the book is changed in state and saved by autosave)
+fn handle_reviewing_metadata_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let Some(review) = state.metadata_review.as_mut() else {
+        state.mode = UiMode::Normal;
+        return None;
+    };
+
+    match key_event.code {
+        KeyCode::Up => review.selected_index = review.selected_index.saturating_sub(1),
+        KeyCode::Down if review.selected_index < review.changes.len().saturating_sub(1) => {
+            review.selected_index += 1;
+        }
+        KeyCode::Char(' ') => {
+            if let Some(change) = review.changes.get_mut(review.selected_index) {
+                change.accepted = !change.accepted;
+            }
+        }
+
+        KeyCode::Enter => {
+            let review = state.metadata_review.take()?;
+            // Found by path: the book may have moved in the list meanwhile
+            let mut applied = 0;
+            let description = format!("metadata from {}", review.source);
+            state.edit_book(&review.path, &description, |book| {
+                applied = crate::providers::apply(&mut book.meta, &review.proposed, &review.changes);
+            });
+            tracing::info!(path = %review.path.display(), source = review.source, fields = applied, "metadata updated");
+            state.status_message = Some(format!("Updated {} fields from {}", applied, review.source));
+            state.mode = UiMode::Normal;
+        }
+
+        KeyCode::Esc => {
+            state.metadata_review = None;
+            state.mode = UiMode::Normal;
+        }
+
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in Statistics mode (the library's totals)
+///
+/// # Key bindings:
+/// * `Esc` / `w` - Close the statistics
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+fn handle_statistics_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    match key_event.code {
+        KeyCode::Esc => {}
+        KeyCode::Char(c) if c == state.settings.keys.statistics => {}
+        _ => return None,
+    }
+    state.statistics = None;
+    state.mode = UiMode::Normal;
+    None
+}
+
+/// Handles keyboard events in HealthReport mode (books with broken files)
+///
+/// # Key bindings:
+/// * `↑` / `↓` - Select a book
+/// * `l` - Relocate: type the new path of the file (Enter to confirm)
+/// * `h` - Find moved files: search the folders for files with the same content
+/// * `d` - Remove the book from the library (the file is left alone)
+/// * `s` - Rescan every folder, then check again
+/// * `Esc` - Close the report
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::RescanLibrary)` - The folders should be rescanned
+/// * `Some(AppAction::FindMovedBooks)` - The folders should be searched by hash
+fn handle_health_report_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let screen = &mut state.health_screen;
+
+    // Path input is active - it gets every key
+    if let Some(input) = screen.relocate_input.as_mut() {
+        match key_event.code {
+            KeyCode::Enter => {
+                let new_path = PathBuf::from(input.trim());
+                screen.relocate_input = None;
+                relocate_selected(state, new_path);
+            }
+            KeyCode::Esc => screen.relocate_input = None,
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Char(c) => input.push(c),
+            _ => {}
+        }
+        return None;
+    }
+
+    match key_event.code {
+        KeyCode::Up => screen.selected_index = screen.selected_index.saturating_sub(1),
+        KeyCode::Down if screen.selected_index < screen.issues.len().saturating_sub(1) => {
+            screen.selected_index += 1;
+        }
+
+        // Start typing the new path, from the old one
+        KeyCode::Char('l') => {
+            if let Some(issue) = screen.issues.get(screen.selected_index) {
+                screen.relocate_input = Some(issue.path.display().to_string());
+            }
+        }
+
+        KeyCode::Char('d') => {
+            let issue = screen.issues.get(screen.selected_index)?;
+            let path = issue.path.clone();
+            screen.resolve_selected();
+
+            let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            state.remove_books(std::slice::from_ref(&path), format!("removal of {}", name));
+            tracing::info!(path = %path.display(), "entry removed from library");
+            state.status_message = Some(format!("Removed {} from the library", path.display()));
+        }
+
+        KeyCode::Char('s') => return Some(AppAction::RescanLibrary),
+        KeyCode::Char('h') => return Some(AppAction::FindMovedBooks),
+
+        KeyCode::Esc => state.mode = UiMode::Normal,
+
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in Duplicates mode (books that look like the
+/// same work, then two of them side by side)
+///
+/// # Key bindings:
+/// * `↑` / `↓` - Select a pair of books (or scroll the comparison)
+/// * `Enter` - Compare the selected pair side by side (reads both files)
+/// * `1` / `2` - In the comparison: keep the left / right book, removing
+///   the other from the library (its file is left alone)
+/// * `Esc` - Back to the list from the comparison, else close the screen
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// Always `None` - everything is done in state
+fn handle_duplicates_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let screen = &mut state.duplicates_screen;
+
+    // Side by side
+    if let Some(comparison) = &screen.comparison {
+        match key_event.code {
+            KeyCode::Up => screen.scroll = screen.scroll.saturating_sub(1),
+            KeyCode::Down if screen.scroll + 1 < comparison.rows.len() => screen.scroll += 1,
+            KeyCode::Char(c @ ('1' | '2')) => {
+                let (kept, removed) = match c {
+                    '1' => (comparison.left.clone(), comparison.right.clone()),
+                    _ => (comparison.right.clone(), comparison.left.clone()),
+                };
+                screen.comparison = None;
+                screen.pairs.retain(|pair| pair.left != removed && pair.right != removed);
+                screen.selected_index = screen.selected_index.min(screen.pairs.len().saturating_sub(1));
+
+                let name = removed.file_name().unwrap_or_default().to_string_lossy().to_string();
+                state.remove_books(std::slice::from_ref(&removed), format!("removal of duplicate {}", name));
+                tracing::info!(kept = %kept.display(), removed = %removed.display(), "duplicate removed from library");
+                state.status_message = Some(format!("Kept {}, removed {} from the library", kept.display(), name));
+            }
+            KeyCode::Esc => screen.comparison = None,
+            _ => {}
+        }
+        return None;
+    }
+
+    match key_event.code {
+        KeyCode::Up => screen.selected_index = screen.selected_index.saturating_sub(1),
+        KeyCode::Down if screen.selected_index + 1 < screen.pairs.len() => screen.selected_index += 1,
+        KeyCode::Enter => {
+            let pair = screen.pairs.get(screen.selected_index)?;
+            let book = |path: &PathBuf| state.books.iter().find(|book| &book.path == path);
+            let (Some(left), Some(right)) = (book(&pair.left), book(&pair.right)) else {
+                return None;
+            };
+            let comparison = crate::duplicates::compare(left, right);
+            state.duplicates_screen.comparison = Some(comparison);
+            state.duplicates_screen.scroll = 0;
+        }
+        KeyCode::Esc => state.mode = UiMode::Normal,
+        _ => {}
+    }
+    None
+}
+
+/// Points the book of the selected health issue to a new file
+///
+/// The new file must exist; the book keeps its user data (tags, rating...).
+fn relocate_selected(state: &mut TuiState, new_path: PathBuf) {
+    let Some(issue) = state.health_screen.issues.get(state.health_screen.selected_index) else {
+        return;
+    };
+    let old_path = issue.path.clone();
+
+    if !new_path.is_file() {
+        state.status_message = Some(format!("No file at {}", new_path.display()));
+        return;
+    }
+    if state.books.iter().any(|book| book.path == new_path) {
+        state.status_message = Some(format!("{} is already in the library", new_path.display()));
+        return;
+    }
+
+    let relocated = state.edit_book(&old_path, "relocation", |book| {
+        crate::organize::repoint(std::slice::from_mut(book), &old_path, &new_path);
+    });
+    if relocated {
+        tracing::info!(from = %old_path.display(), to = %new_path.display(), "book relocated");
+        state.status_message = Some(format!("Relocated to {}", new_path.display()));
+        state.health_screen.resolve_selected();
+    }
+}
+
+/// Shows planned moves for review (or says there's nothing to do)
+fn open_move_review(state: &mut TuiState, title: String, moves: Vec<crate::organize::Move>) {
+    if moves.is_empty() {
+        state.status_message = Some("Every file is already in place".to_string());
+        return;
+    }
+    state.move_review = Some(MoveReview {
+        title,
+        moves,
+        selected_index: 0,
+    });
+    state.mode = UiMode::ReviewingMoves;
+}
+
+/// Handles keyboard events in ReviewingMoves mode (old -> new paths)
+///
+/// # Key bindings:
+/// * `↑` / `↓` - Scroll through the moves
+/// * `Enter` - Move the files (moves with a conflict are skipped); if one
+///   fails, everything is moved back
+/// * `Esc` - Cancel without touching any file
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::SaveNow)` - Files were moved, the library must be saved
+fn handle_reviewing_moves_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let Some(review) = state.move_review.as_mut() else {
+        state.mode = UiMode::Normal;
+        return None;
+    };
+
+    match key_event.code {
+        KeyCode::Up => review.selected_index = review.selected_index.saturating_sub(1),
+        KeyCode::Down if review.selected_index < review.moves.len().saturating_sub(1) => {
+            review.selected_index += 1;
+        }
+
+        KeyCode::Enter => {
+            let review = state.move_review.take()?;
+            state.mode = UiMode::Normal;
+
+            match crate::organize::execute(&review.moves) {
+                Ok(done) => {
+                    let skipped = review.moves.len() - done.len();
+                    tracing::info!(moved = done.len(), skipped, "files moved");
+                    let moved = done.len();
+                    state.apply_moves(done, review.title.to_lowercase());
+                    state.status_message = Some(format!("Moved {} files ({} skipped)", moved, skipped));
+                    return Some(AppAction::SaveNow);
+                }
+                Err(e) => state.status_message = Some(format!("Cannot move files: {}", e)),
+            }
+        }
+
+        KeyCode::Esc => {
+            state.move_review = None;
+            state.mode = UiMode::Normal;
+        }
+
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in MergingLibrary mode (conflicts of a library merge)
+///
+/// # Key bindings:
+/// * `↑` / `↓` - Select a conflict
+/// * `←` / `→` (or `h` / `l`) - Keep ours / take theirs for the selected conflict
+/// * `Space` - Switch the selected conflict between this library and the other one
+/// * `o` / `t` - Keep ours / take theirs for every conflict
+/// * `Enter` - Merge
+/// * `Esc` - Cancel the merge
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::SaveNow)` - The merge was applied, the library must be saved
+/// * `Some(AppAction::FinishSync)` - Our side of a sync was applied, the partner's is next
+fn handle_merging_library_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let Some(merge) = state.library_merge.as_mut() else {
+        state.mode = UiMode::Normal;
+        return None;
+    };
+
+    match key_event.code {
+        KeyCode::Up => merge.selected_index = merge.selected_index.saturating_sub(1),
+        KeyCode::Down if merge.selected_index < merge.plan.conflict_count().saturating_sub(1) => {
+            merge.selected_index += 1;
+        }
+        KeyCode::Char(' ') => {
+            if let Some(conflict) = merge.selected_mut() {
+                conflict.take_theirs = !conflict.take_theirs;
+            }
+        }
+        KeyCode::Left | KeyCode::Char('h') => {
+            if let Some(conflict) = merge.selected_mut() {
+                conflict.take_theirs = false;
+            }
+        }
+        KeyCode::Right | KeyCode::Char('l') => {
+            if let Some(conflict) = merge.selected_mut() {
+                conflict.take_theirs = true;
+            }
+        }
+        KeyCode::Char('o') => merge.choose_all(false),
+        KeyCode::Char('t') => merge.choose_all(true),
+
+        KeyCode::Enter => {
+            let mut merge = state.library_merge.take()?;
+            state.mode = UiMode::Normal;
+            // A sync goes on in the main loop (copies, pushes to the partner)
+            if let Some(sync) = merge.sync.take() {
+                state.apply_library_merge(merge);
+                return Some(AppAction::FinishSync(sync));
+            }
+            let source = merge.source.clone();
+            let (updated, added) = state.apply_library_merge(merge);
+            tracing::info!(source = %source, updated, added, "library merged");
+            state.status_message = Some(format!("Merged {}: {} books updated, {} added", source, updated, added));
+            return Some(AppAction::SaveNow);
+        }
+
+        KeyCode::Esc => {
+            let merge = state.library_merge.take()?;
+            state.mode = UiMode::Normal;
+            state.status_message = Some(if merge.sync.is_some() { "Sync cancelled" } else { "Merge cancelled" }.to_string());
+        }
+
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in Peers mode (other FunkHunt instances)
+///
+/// # Key bindings:
+/// * `↑` / `↓` - Select a peer
+/// * `Enter` - Browse the selected peer's books (main loop asks the peer)
+/// * `s` - Send the marked books (or the selected one) to the selected peer
+/// * `y` - Sync the library with the selected peer (two-way)
+/// * `a` - Pair with the selected peer (both screens show a PIN to compare)
+/// * `r` - Stop trusting the selected peer (it has to pair again)
+/// * `o` - Edit what the selected (paired) peer may get from the library
+/// * `d` - Forget the selected peer
+/// * `Esc` - Back to the book list
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::BrowsePeer)` - The peer's catalog should be fetched
+/// * `Some(AppAction::ForgetPeer)` - The peer should be forgotten
+/// * `Some(AppAction::SendToPeer)` - The books should be offered to the peer
+/// * `Some(AppAction::SyncWithPeer)` - The library should be synced with the peer
+/// * `Some(AppAction::PairWithPeer)` - The peer should be asked to pair
+/// * `Some(AppAction::RevokePeer)` - The peer should no longer be trusted
+fn handle_peers_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let screen = &mut state.peers_screen;
+
+    match key_event.code {
+        KeyCode::Up => screen.selected_index = screen.selected_index.saturating_sub(1),
+        KeyCode::Down if screen.selected_index < state.peers.len().saturating_sub(1) => {
+            screen.selected_index += 1;
+        }
+        KeyCode::Enter => {
+            let peer = state.peers.get(screen.selected_index)?;
+            if !peer.online {
+                state.status_message = Some(format!("{} is offline", peer.name));
+                return None;
+            }
+            return Some(AppAction::BrowsePeer(peer.clone()));
+        }
+        KeyCode::Char('s') => {
+            let peer = state.peers.get(screen.selected_index)?;
+            if !peer.online {
+                state.status_message = Some(format!("{} is offline", peer.name));
+                return None;
+            }
+            return Some(AppAction::SendToPeer(peer.clone()));
+        }
+        KeyCode::Char('y') => {
+            let peer = state.peers.get(screen.selected_index)?;
+            if !peer.online {
+                state.status_message = Some(format!("{} is offline", peer.name));
+                return None;
+            }
+            return Some(AppAction::SyncWithPeer(peer.clone()));
+        }
+        KeyCode::Char('a') => {
+            let peer = state.peers.get(screen.selected_index)?;
+            if !peer.online {
+                state.status_message = Some(format!("{} is offline", peer.name));
+                return None;
+            }
+            if peer.instance.is_none() {
+                state.status_message = Some(format!("{} runs a version without pairing", peer.name));
+                return None;
+            }
+            return Some(AppAction::PairWithPeer(peer.clone()));
+        }
+        KeyCode::Char('r') => {
+            let peer = state.peers.get(screen.selected_index)?;
+            let Some(trusted) = state.trusted_peer(peer) else {
+                state.status_message = Some(format!("{} isn't paired", peer.name));
+                return None;
+            };
+            return Some(AppAction::RevokePeer(trusted.id.clone()));
+        }
+        KeyCode::Char('o') => {
+            let peer = state.peers.get(screen.selected_index)?;
+            let Some(trusted) = state.trusted_peer(peer) else {
+                state.status_message = Some(format!("{} isn't paired - pair first to choose what it gets", peer.name));
+                return None;
+            };
+            state.policy_editor = Some(PolicyEditor {
+                peer_id: trusted.id.clone(),
+                peer: trusted.name.clone(),
+                policy: trusted.policy.clone(),
+                selected_index: 0,
+            });
+            state.mode = UiMode::EditingPolicy;
+        }
+        KeyCode::Char('d') => {
+            let peer = state.peers.get(screen.selected_index)?;
+            return Some(AppAction::ForgetPeer(peer.id.clone()));
+        }
+        KeyCode::Esc => state.mode = UiMode::Normal,
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in BrowsingPeer mode (a peer's shared books)
+///
+/// # Key bindings:
+/// * `↑` / `↓` - Select a book
+/// * `v` / `Space` - Mark / unmark the selected book and move down
+/// * `Enter` / `d` - Download the marked books (or the selected one) into
+///   the library
+/// * `Esc` - Back to the peers screen
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::DownloadFromPeer)` - The books should be downloaded
+fn handle_browsing_peer_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let Some(browser) = state.peer_browser.as_mut() else {
+        state.mode = UiMode::Peers;
+        return None;
+    };
+    let count = browser.catalog.books.len();
+
+    match key_event.code {
+        KeyCode::Up => browser.selected_index = browser.selected_index.saturating_sub(1),
+        KeyCode::Down if browser.selected_index < count.saturating_sub(1) => browser.selected_index += 1,
+        KeyCode::Char('v') | KeyCode::Char(' ') => {
+            let id = browser.selected()?.id;
+            if !browser.marked.remove(&id) {
+                browser.marked.insert(id);
+            }
+            browser.selected_index = (browser.selected_index + 1).min(count.saturating_sub(1));
+        }
+        KeyCode::Enter | KeyCode::Char('d') => {
+            let entries: Vec<_> = if browser.marked.is_empty() {
+                browser.selected().cloned().into_iter().collect()
+            } else {
+                browser
+                    .catalog
+                    .books
+                    .iter()
+                    .filter(|entry| browser.marked.contains(&entry.id))
+                    .cloned()
+                    .collect()
+            };
+            browser.marked.clear();
+            return Some(AppAction::DownloadFromPeer(entries));
+        }
+        KeyCode::Esc => {
+            state.peer_browser = None;
+            state.mode = UiMode::Peers;
+        }
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in BrowsingCalibre mode (a Calibre library)
+///
+/// # Key bindings:
+/// * `↑` / `↓` - Select a book
+/// * `v` / `Space` - Mark / unmark the selected book and move down
+/// * `Enter` / `d` - Download the marked books (or the selected one)
+/// * `p` - Add the marked books of our library (or the selected one) to Calibre
+/// * `r` - Reload the book list
+/// * `Esc` - Back to the book list
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::DownloadFromCalibre)` - The books should be downloaded
+/// * `Some(AppAction::PushToCalibre)` - Our books should be added to Calibre
+/// * `Some(AppAction::BrowseCalibre)` - The book list should be fetched again
+fn handle_browsing_calibre_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let Some(browser) = state.calibre_browser.as_mut() else {
+        state.mode = UiMode::Normal;
+        return None;
+    };
+    let count = browser.library.books.len();
+
+    match key_event.code {
+        KeyCode::Up => browser.selected_index = browser.selected_index.saturating_sub(1),
+        KeyCode::Down if browser.selected_index < count.saturating_sub(1) => browser.selected_index += 1,
+        KeyCode::Char('v') | KeyCode::Char(' ') => {
+            let book = browser.library.books.get(browser.selected_index)?;
+            if !browser.marked.remove(&book.id) {
+                browser.marked.insert(book.id);
+            }
+            browser.selected_index = (browser.selected_index + 1).min(count.saturating_sub(1));
+        }
+        KeyCode::Enter | KeyCode::Char('d') => {
+            let books: Vec<_> = if browser.marked.is_empty() {
+                browser.library.books.get(browser.selected_index).cloned().into_iter().collect()
+            } else {
+                browser
+                    .library
+                    .books
+                    .iter()
+                    .filter(|book| browser.marked.contains(&book.id))
+                    .cloned()
+                    .collect()
+            };
+            browser.marked.clear();
+            return Some(AppAction::DownloadFromCalibre(books));
+        }
+        KeyCode::Char('p') => return Some(AppAction::PushToCalibre),
+        KeyCode::Char('r') => return Some(AppAction::BrowseCalibre),
+        KeyCode::Esc => {
+            state.calibre_browser = None;
+            state.mode = UiMode::Normal;
+        }
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in BrowsingFeeds mode (new books of the feeds)
+///
+/// # Key bindings:
+/// * `↑` / `↓` - Select an entry
+/// * `v` / `Space` - Mark / unmark the selected entry and move down
+/// * `Enter` / `d` - Download the marked entries (or the selected one)
+/// * `r` - Fetch the feeds again
+/// * `Esc` - Back to the book list
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::DownloadFromFeeds)` - The EPUBs should be downloaded
+/// * `Some(AppAction::BrowseFeeds)` - The feeds should be fetched again
+fn handle_browsing_feeds_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let Some(browser) = state.feed_browser.as_mut() else {
+        state.mode = UiMode::Normal;
+        return None;
+    };
+    let count = browser.entries.len();
+
+    match key_event.code {
+        KeyCode::Up => browser.selected_index = browser.selected_index.saturating_sub(1),
+        KeyCode::Down if browser.selected_index < count.saturating_sub(1) => browser.selected_index += 1,
+        KeyCode::Char('v') | KeyCode::Char(' ') => {
+            let entry = browser.entries.get(browser.selected_index)?;
+            if !browser.marked.remove(&entry.id) {
+                browser.marked.insert(entry.id.clone());
+            }
+            browser.selected_index = (browser.selected_index + 1).min(count.saturating_sub(1));
+        }
+        KeyCode::Enter | KeyCode::Char('d') => {
+            let entries: Vec<_> = if browser.marked.is_empty() {
+                browser.entries.get(browser.selected_index).cloned().into_iter().collect()
+            } else {
+                browser
+                    .entries
+                    .iter()
+                    .filter(|entry| browser.marked.contains(&entry.id))
+                    .cloned()
+                    .collect()
+            };
+            browser.marked.clear();
+            return Some(AppAction::DownloadFromFeeds(entries));
+        }
+        KeyCode::Char('r') => return Some(AppAction::BrowseFeeds),
+        KeyCode::Esc => {
+            state.feed_browser = None;
+            state.mode = UiMode::Normal;
+        }
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in Reading mode (a book in the built-in reader)
+///
+/// # Key bindings:
+/// * `PageDown` / `Space` / `→` - Next page
+/// * `PageUp` / `←` - Previous page
+/// * `↓` / `↑` - Next / previous line
+/// * `n` / `p` - Next chapter / beginning of this one (or the previous one)
+/// * `t` - Show the table of contents (`↑↓` select, `Enter` jumps, `Esc` / `t` closes)
+/// * `d` - Select a word to look up in the dictionaries (`←→↑↓` select, `Enter` looks it up, `Esc` stops)
+/// * `h` - Highlight a passage (`←→↑↓` select, `Enter` on its first word, then on its last one; `Esc` cancels)
+/// * `H` - Write the highlights of the book as notes ([notes] folder and format)
+/// * `y` - Quote a passage (selected as for `h`), added to the quotes of all books
+/// * `b` - Bookmark the page (type a label or nothing, then `Enter`; `Esc` cancels)
+/// * `B` - Show the bookmarks of the book (`↑↓` select, `Enter` jumps, `x` / `Delete` removes, `Esc` / `B` closes)
+/// * `<` / `>` - Narrower / wider lines
+/// * `[` / `]` - Narrower / wider margins
+/// * `s` - More blank lines between paragraphs (back to none after the most)
+/// * `j` - Justify the lines or not
+/// * `-` - Hyphenate the words or not
+/// * `c` - Next reading theme (the app's, sepia, dark, high contrast)
+/// * `Home` / `End` - Beginning / end of the book
+/// * `Esc` / `q` - Back to the book list (or to the text search hits it was opened from)
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// Always `None` - reading only changes the state
+fn handle_reading_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let Some(reader) = state.reader.as_mut() else {
+        state.mode = UiMode::Normal;
+        return None;
+    };
+    let page = reader.page_height as isize;
+
+    // The label of a new bookmark is being typed
+    if let Some(label) = reader.bookmark_label.as_mut() {
+        match key_event.code {
+            KeyCode::Enter => {
+                let label = label.trim().to_string();
+                reader.bookmark_label = None;
+                let (chapter, block) = reader.position();
+                let book = state.books.iter_mut().find(|book| book.path == reader.path)?;
+                let bookmarks = &mut book.user.bookmarks;
+                match bookmarks.iter_mut().find(|b| (b.chapter, b.block) == (chapter, block)) {
+                    Some(bookmark) => bookmark.label = label,
+                    None => {
+                        bookmarks.push(Bookmark {
+                            chapter,
+                            block,
+                            label,
+                            created: crate::book::unix_now(),
+                        });
+                        bookmarks.sort_by_key(|b| (b.chapter, b.block));
+                    }
+                }
+                state.dirty = true;
+                state.status_message = Some(format!("Bookmarked ({} in this book)", bookmarks.len()));
+            }
+            KeyCode::Esc => reader.bookmark_label = None,
+            KeyCode::Backspace => {
+                label.pop();
+            }
+            KeyCode::Char(c) => label.push(c),
+            _ => {}
+        }
+        return None;
+    }
+
+    // So does the bookmark list while it's shown
+    if let Some(selected) = reader.bookmarks {
+        let Some(book) = state.books.iter_mut().find(|book| book.path == reader.path) else {
+            reader.bookmarks = None;
+            return None;
+        };
+        let bookmarks = &mut book.user.bookmarks;
+        let last = bookmarks.len().saturating_sub(1);
+        match key_event.code {
+            KeyCode::Up => reader.bookmarks = Some(selected.saturating_sub(1)),
+            KeyCode::Down => reader.bookmarks = Some((selected + 1).min(last)),
+            KeyCode::Enter => {
+                if let Some(bookmark) = bookmarks.get(selected) {
+                    reader.go_to(bookmark.chapter, bookmark.block);
+                }
+                reader.bookmarks = None;
+            }
+            KeyCode::Char('x') | KeyCode::Delete if selected < bookmarks.len() => {
+                bookmarks.remove(selected);
+                state.dirty = true;
+                reader.bookmarks = match bookmarks.len() {
+                    0 => None,
+                    count => Some(selected.min(count - 1)),
+                };
+            }
+            KeyCode::Esc | KeyCode::Char('B') => reader.bookmarks = None,
+            _ => {}
+        }
+        return None;
+    }
+
+    // The dictionary entries take the keys while they're shown
+    if let Some(lookup) = reader.lookup.as_mut() {
+        match key_event.code {
+            KeyCode::Up => lookup.scroll = lookup.scroll.saturating_sub(1),
+            KeyCode::Down => lookup.scroll += 1,
+            KeyCode::PageUp => lookup.scroll = lookup.scroll.saturating_sub(10),
+            KeyCode::PageDown => lookup.scroll += 10,
+            KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => reader.lookup = None,
+            _ => {}
+        }
+        return None;
+    }
+
+    // The table of contents takes the keys while it's shown
+    if let Some(selected) = reader.contents {
+        let count = reader.document.contents.len();
+        match key_event.code {
+            KeyCode::Up => reader.contents = Some(selected.saturating_sub(1)),
+            KeyCode::Down => reader.contents = Some((selected + 1).min(count.saturating_sub(1))),
+            KeyCode::PageUp => reader.contents = Some(selected.saturating_sub(10)),
+            KeyCode::PageDown => reader.contents = Some((selected + 10).min(count.saturating_sub(1))),
+            KeyCode::Enter => {
+                reader.jump_to(selected);
+                reader.contents = None;
+            }
+            KeyCode::Esc | KeyCode::Char('t') => reader.contents = None,
+            _ => {}
+        }
+        return None;
+    }
+
+    // So do the arrows while a word is selected
+    if reader.word.is_some() {
+        match key_event.code {
+            KeyCode::Right => reader.move_word(true),
+            KeyCode::Left => reader.move_word(false),
+            KeyCode::Down => reader.move_word_line(true),
+            KeyCode::Up => reader.move_word_line(false),
+            // The first word of a highlight, then its last one
+            KeyCode::Enter if reader.highlighting && reader.highlight_start.is_none() => {
+                reader.highlight_start = reader.word;
+            }
+            KeyCode::Enter if reader.highlighting => {
+                let text = reader.selected_text();
+                let start = reader.highlight_start.min(reader.word)?;
+                let (chapter, block) = (reader.lines[start.0].chapter, reader.lines[start.0].block);
+                let chapter_title = match reader.entry_at(chapter, block) {
+                    Some(entry) => entry.title.clone(),
+                    None => reader.document.chapters[chapter].title.clone().unwrap_or_default(),
+                };
+                let quoting = reader.quoting;
+                reader.stop_selecting();
+                let book = state.books.iter_mut().find(|book| book.path == reader.path)?;
+                if quoting {
+                    let quote = Quote {
+                        text,
+                        title: book.display_title().to_string(),
+                        authors: book.meta.authors.clone(),
+                        place: chapter_title,
+                        created: crate::book::unix_now(),
+                    };
+                    state.status_message = Some(save_quote(quote));
+                    return None;
+                }
+                let highlights = &mut book.user.highlights;
+                highlights.push(Highlight {
+                    chapter,
+                    block,
+                    chapter_title,
+                    text,
+                    created: crate::book::unix_now(),
+                });
+                highlights.sort_by_key(|h| (h.chapter, h.block));
+                state.dirty = true;
+                state.status_message = Some(format!("Highlighted ({} in this book)", highlights.len()));
+            }
+            KeyCode::Enter => {
+                let word = reader.selected_word()?;
+                match crate::dictionary::look_up(&word, &state.settings.dictionary.paths, reader.document.language.as_deref()) {
+                    Ok(definitions) if definitions.is_empty() => {
+                        state.status_message = Some(format!("\"{}\" isn't in the dictionaries", word));
+                    }
+                    Ok(definitions) => {
+                        reader.lookup = Some(Lookup {
+                            word,
+                            definitions,
+                            scroll: 0,
+                        });
+                    }
+                    Err(e) => state.status_message = Some(format!("Dictionary: {}", e)),
+                }
+            }
+            KeyCode::Esc | KeyCode::Char('d') | KeyCode::Char('h') | KeyCode::Char('y') => reader.stop_selecting(),
+            _ => {}
+        }
+        return None;
+    }
+
+    match key_event.code {
+        KeyCode::PageDown | KeyCode::Char(' ') | KeyCode::Right => reader.scroll(page),
+        KeyCode::PageUp | KeyCode::Left => reader.scroll(-page),
+        KeyCode::Down => reader.scroll(1),
+        KeyCode::Up => reader.scroll(-1),
+        KeyCode::Char('n') => reader.next_chapter(),
+        KeyCode::Char('p') => reader.previous_chapter(),
+        KeyCode::Char('t') => reader.contents = Some(reader.current_entry().unwrap_or(0)),
+        KeyCode::Char('H') => {
+            let book = state.books.iter().find(|book| book.path == reader.path)?;
+            if book.user.highlights.is_empty() {
+                state.status_message = Some("No highlights in this book: h highlights a passage".to_string());
+                return None;
+            }
+            let notes = &state.settings.notes;
+            let folder = notes.folder.clone().unwrap_or_else(|| crate::paths::data_dir().join("notes"));
+            state.status_message = Some(match crate::notes::export_book(book, &folder, notes.format) {
+                Ok(dest) => format!("{} highlights written to {}", book.user.highlights.len(), dest.display()),
+                Err(e) => {
+                    tracing::warn!(folder = %folder.display(), error = %e, "cannot write highlights");
+                    format!("Cannot write the highlights: {}", e)
+                }
+            });
+        }
+        KeyCode::Char('b') => reader.bookmark_label = Some(String::new()),
+        KeyCode::Char('B') => {
+            let book = state.books.iter().find(|book| book.path == reader.path)?;
+            if book.user.bookmarks.is_empty() {
+                state.status_message = Some("No bookmarks in this book: b marks the page".to_string());
+                return None;
+            }
+            // The last bookmark before the page, or the first one
+            let position = reader.position();
+            let before = book.user.bookmarks.iter().filter(|b| (b.chapter, b.block) <= position).count();
+            reader.bookmarks = Some(before.saturating_sub(1));
+        }
+        KeyCode::Char('d') | KeyCode::Char('h') | KeyCode::Char('y') => {
+            reader.select_word();
+            reader.highlighting = key_event.code != KeyCode::Char('d');
+            reader.quoting = key_event.code == KeyCode::Char('y');
+            if reader.word.is_none() {
+                state.status_message = Some("No word on this page".to_string());
+            }
+        }
+        KeyCode::Char(c @ ('<' | '>' | '[' | ']' | 's' | 'j' | '-' | 'c')) => {
+            // The reader lays the text out again at the next frame
+            let typography = &mut state.typography;
+            let message = match c {
+                '<' | '>' => {
+                    typography.width = match c {
+                        '<' => typography.width.saturating_sub(WIDTH_STEP).max(reader::MIN_WIDTH),
+                        _ => (typography.width + WIDTH_STEP).min(reader::MAX_WIDTH),
+                    };
+                    format!("Lines of {} columns at most", typography.width)
+                }
+                '[' | ']' => {
+                    typography.margin = match c {
+                        '[' => typography.margin.saturating_sub(1),
+                        _ => (typography.margin + 1).min(reader::MAX_MARGIN),
+                    };
+                    format!("Margins of {} columns", typography.margin)
+                }
+                's' => {
+                    typography.spacing = (typography.spacing + 1) % (reader::MAX_SPACING + 1);
+                    format!("{} blank lines between paragraphs", typography.spacing)
+                }
+                'j' => {
+                    typography.justify = !typography.justify;
+                    format!("Justified lines: {}", if typography.justify { "on" } else { "off" })
+                }
+                'c' => {
+                    typography.theme = typography.theme.next();
+                    format!("Reading theme: {}", typography.theme.label())
+                }
+                _ => {
+                    typography.hyphenate = !typography.hyphenate;
+                    format!("Hyphenation: {}", if typography.hyphenate { "on" } else { "off" })
+                }
+            };
+            if let Err(e) = typography.save() {
+                tracing::warn!(error = %e, "cannot save the reader typography");
+            }
+            state.status_message = Some(message);
+        }
+        KeyCode::Home => reader.top = 0,
+        KeyCode::End => reader.scroll(isize::MAX),
+        KeyCode::Esc | KeyCode::Char('q') => {
+            state.reader = None;
+            state.mode = match state.text_search {
+                Some(_) => UiMode::TextSearchResults,
+                None => UiMode::Normal,
+            };
+        }
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in ReviewingOffer mode (books a peer wants to send)
+///
+/// # Key bindings:
+/// * `y` / `Enter` - Accept: download the books into the incoming folder
+/// * `n` / `Esc` - Decline
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::AcceptOffer)` - The books should be downloaded
+fn handle_reviewing_offer_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    if state.incoming_offers.is_empty() {
+        state.mode = UiMode::Normal;
+        return None;
+    }
+
+    match key_event.code {
+        KeyCode::Char('y') | KeyCode::Enter => {
+            let incoming = state.incoming_offers.remove(0);
+            state.mode = UiMode::Normal;
+            return Some(AppAction::AcceptOffer(incoming));
+        }
+        KeyCode::Char('n') | KeyCode::Esc => {
+            let incoming = state.incoming_offers.remove(0);
+            tracing::info!(from = %incoming.offer.from, "offer declined");
+            state.status_message = Some(format!("Declined books from {}", incoming.offer.from));
+            state.mode = UiMode::Normal;
+        }
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in Transfers mode (downloads and uploads)
+///
+/// # Key bindings:
+/// * `↑` / `↓` - Select a transfer
+/// * `Space` / `p` - Pause or resume the selected transfer
+/// * `c` - Cancel the selected transfer
+/// * `r` - Retry the selected transfer if it failed (downloads resume)
+/// * `C` - Clear the transfers that ended from the list
+/// * `Esc` - Back to the book list
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Always (the queue is changed directly)
+fn handle_transfers_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let transfers = state.transfers.list();
+    let screen = &mut state.transfers_screen;
+
+    match key_event.code {
+        KeyCode::Up => screen.selected_index = screen.selected_index.saturating_sub(1),
+        KeyCode::Down if screen.selected_index < transfers.len().saturating_sub(1) => {
+            screen.selected_index += 1;
+        }
+        KeyCode::Char(' ') | KeyCode::Char('p') => {
+            let transfer = transfers.get(screen.selected_index)?;
+            state.transfers.toggle_pause(transfer.id);
+        }
+        KeyCode::Char('c') => {
+            let transfer = transfers.get(screen.selected_index)?;
+            state.transfers.cancel(transfer.id);
+        }
+        KeyCode::Char('r') => {
+            let transfer = transfers.get(screen.selected_index)?;
+            state.transfers.retry(transfer.id);
+        }
+        KeyCode::Char('C') => {
+            state.transfers.clear_finished();
+            screen.selected_index = 0;
+        }
+        KeyCode::Esc => state.mode = UiMode::Normal,
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in Pairing mode (comparing the PIN of a pairing)
+///
+/// # Key bindings:
+/// * `y` / `Enter` - The other screen shows the same PIN: trust the peer
+/// * `n` / `Esc` - The PINs differ (or the pairing is unwanted): drop it
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::TrustPeer)` - The peer should be added to the trusted peers
+fn handle_pairing_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    if state.pairings.is_empty() {
+        state.mode = UiMode::Normal;
+        return None;
+    }
+
+    // Pairings asked for from the peers screen go back there
+    let back = |pairing: &Pairing| if pairing.outgoing { UiMode::Peers } else { UiMode::Normal };
+    match key_event.code {
+        KeyCode::Char('y') | KeyCode::Enter => {
+            let pairing = state.pairings.remove(0);
+            state.mode = back(&pairing);
+            return Some(AppAction::TrustPeer(pairing));
+        }
+        KeyCode::Char('n') | KeyCode::Esc => {
+            let pairing = state.pairings.remove(0);
+            tracing::info!(peer = %pairing.name, "pairing declined");
+            state.status_message = Some(format!("Not paired with {}", pairing.name));
+            state.mode = back(&pairing);
+        }
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in EditingPolicy mode (what a paired peer gets)
+///
+/// # Key bindings:
+/// * `↑` / `↓` - Select a row
+/// * `Space` - Pick the selected policy, or tick / untick the selected
+///   collection (sharing collections only)
+/// * `Enter` - Store the policy and go back to the peers screen
+/// * `Esc` - Back to the peers screen, policy unchanged
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::SetSharePolicy)` - The policy should be stored
+fn handle_editing_policy_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let Some(editor) = state.policy_editor.as_mut() else {
+        state.mode = UiMode::Peers;
+        return None;
+    };
+
+    // Whole library, ask first, then the collections
+    let rows = 2 + state.collections.len();
+    match key_event.code {
+        KeyCode::Up => editor.selected_index = editor.selected_index.saturating_sub(1),
+        KeyCode::Down if editor.selected_index < rows - 1 => editor.selected_index += 1,
+        KeyCode::Char(' ') => match editor.selected_index {
+            0 => editor.policy = SharePolicy::Library,
+            1 => editor.policy = SharePolicy::Ask,
+            i => {
+                let name = state.collections[i - 2].clone();
+                let mut names = match &editor.policy {
+                    SharePolicy::Collections(names) => names.clone(),
+                    _ => Vec::new(),
+                };
+                match names.iter().position(|n| *n == name) {
+                    Some(position) => {
+                        names.remove(position);
+                    }
+                    None => names.push(name),
+                }
+                editor.policy = SharePolicy::Collections(names);
+            }
+        },
+        KeyCode::Enter => {
+            let editor = state.policy_editor.take()?;
+            state.mode = UiMode::Peers;
+            return Some(AppAction::SetSharePolicy(editor.peer_id, editor.policy));
+        }
+        KeyCode::Esc => {
+            state.policy_editor = None;
+            state.mode = UiMode::Peers;
+        }
+        _ => {}
+    }
+
+    None
+}
+
+/// Handles keyboard events in ApprovingAccess mode (a peer asks for a book)
+///
+/// # Key bindings:
+/// * `y` / `Enter` - Let the peer download the book
+/// * `n` / `Esc` - Don't
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Nothing to answer (no request waiting)
+/// * `Some(AppAction::AnswerAccess)` - The peer server should be told the answer
+fn handle_approving_access_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    if state.access_requests.is_empty() {
+        state.mode = UiMode::Normal;
+        return None;
+    }
+
+    let approved = match key_event.code {
+        KeyCode::Char('y') | KeyCode::Enter => true,
+        KeyCode::Char('n') | KeyCode::Esc => false,
+        _ => return None,
+    };
+    let request = state.access_requests.remove(0);
+    if state.access_requests.is_empty() {
+        state.mode = UiMode::Normal;
+    }
+    Some(AppAction::AnswerAccess(request, approved))
+}
+
+/// Handles keyboard events in Sharing mode (the QR code of a shared book)
+///
+/// # Key bindings:
+/// * `Esc` / `Enter` - Stop sharing and close the screen
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Always (stopping the share only changes the state)
+fn handle_sharing_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    if let KeyCode::Esc | KeyCode::Enter = key_event.code {
+        // Dropping the share stops its server
+        if let Some(share) = state.share.take() {
+            state.status_message = Some(format!("Stopped sharing {}", share.title));
+        }
+        state.mode = UiMode::Normal;
+    }
+
+    None
+}
+
+/// Handles keyboard events in Devices mode (e-readers plugged in over USB)
+///
+/// # Key bindings:
+/// * `↑` / `↓` - Select a device
+/// * `Enter` - Send the marked books (or the selected one) to the selected device
+/// * `r` - Look for devices again (after plugging one in)
+/// * `Esc` - Back to the book list
+///
+/// # Arguments
+/// * `key_event` - The keyboard event
+/// * `state` - Mutable reference to application state
+///
+/// # Returns
+/// * `None` - Event was handled in state
+/// * `Some(AppAction::SendToDevice)` - The books should be copied to the device
+fn handle_devices_mode(key_event: KeyEvent, state: &mut TuiState) -> Option<AppAction> {
+    let screen = &mut state.devices_screen;
+
+    match key_event.code {
+        KeyCode::Up => screen.selected_index = screen.selected_index.saturating_sub(1),
+        KeyCode::Down if screen.selected_index < screen.devices.len().saturating_sub(1) => {
+            screen.selected_index += 1;
+        }
+        KeyCode::Enter => {
+            let device = screen.devices.get(screen.selected_index)?.clone();
+            state.mode = UiMode::Normal;
+            return Some(AppAction::SendToDevice(device));
+        }
+        KeyCode::Char('r') => {
+            screen.devices = crate::device::detect(&state.settings.devices.mounts);
+            screen.selected_index = screen.selected_index.min(screen.devices.len().saturating_sub(1));
+        }
+        KeyCode::Esc => state.mode = UiMode::Normal,
+        _ => {}
+    }
+
+    None
+}
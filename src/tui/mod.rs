@@ -6,8 +6,10 @@ pub mod events;
 pub mod popup;
 pub mod render;
 pub mod state;
+pub mod worker;
 
 // Re-exportar tipos principales
-pub use events::handle_key_event;
-pub use render::{init, render, restore};
-pub use state::{AppAction, TuiState};
+pub use events::{handle_key_event, handle_paste};
+pub use render::{init, picture_placements, render, restore};
+pub use state::{AppAction, CalibreBrowser, FeedBrowser, LibraryMerge, MetadataReview, PeerBrowser, TextSearch, TuiState, UiMode};
+pub use worker::{AppEvent, Lookup, OtherLibrary, PeerReply, ReadReason, Scan, ScanReason, SyncSent};
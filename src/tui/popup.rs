@@ -4,11 +4,20 @@
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    widgets::{Block, Borders, Clear, List, ListItem, Paragraph},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Wrap},
     Frame,
 };
 
-use super::state::TuiState;
+use crate::convert::Format;
+use crate::profile::Shelf;
+use crate::transfer::{human_bytes, Direction as TransferDirection, TransferState};
+use crate::trust::SharePolicy;
+
+use super::state::{BookReader, BulkField, CollectionPurpose, FolderField, MetadataField, TuiState};
+
+/// Width of the progress bars in the transfer list, in characters
+const PROGRESS_WIDTH: usize = 20;
 
 /// Renders the "add folder" popup over the normal interface
 ///
@@ -51,12 +60,15 @@ pub fn render_add_folder_popup(frame: &mut Frame, state: &TuiState) {
 /// * `state` - Current application state (contains browser state)
 /// * `area` - The area where the modal should be drawn (typically full screen)
 fn render_folder_modal(frame: &mut Frame, state: &TuiState, area: Rect) {
+    // Colors come from the user's theme
+    let theme = &state.settings.theme;
+
     // STEP 1: Clear the entire area first
     // This is CRITICAL - it erases whatever was drawn before
     frame.render_widget(Clear, area);
 
     // STEP 2: Draw dark background over the entire area
-    let background = Block::default().style(Style::default().bg(Color::Black));
+    let background = Block::default().style(Style::default().bg(theme.background));
     frame.render_widget(background, area);
 
     // STEP 3: Calculate centered modal rectangle (90% width, 80% height)
@@ -66,15 +78,15 @@ fn render_folder_modal(frame: &mut Frame, state: &TuiState, area: Rect) {
     frame.render_widget(Clear, inner_modal);
 
     // STEP 5: Draw solid background for the modal
-    // Using RGB(40,40,40) = dark gray for better contrast
-    let modal_bg = Block::default().style(Style::default().bg(Color::Rgb(40, 40, 40)));
+    // Default theme uses RGB(40,40,40) = dark gray for better contrast
+    let modal_bg = Block::default().style(Style::default().bg(theme.popup_bg));
     frame.render_widget(modal_bg, inner_modal);
 
     // STEP 6: Draw border and title for the modal
     let block = Block::default()
         .borders(Borders::ALL)
         .title(" FILE BROWSER ")
-        .style(Style::default().bg(Color::Rgb(40, 40, 40)).fg(Color::White));
+        .style(Style::default().bg(theme.popup_bg).fg(theme.text));
     frame.render_widget(block, inner_modal);
 
     // STEP 7: Layout the modal content
@@ -90,14 +102,14 @@ fn render_folder_modal(frame: &mut Frame, state: &TuiState, area: Rect) {
     // STEP 8: Display current path with folder emoji
     let current_path = format!("📁 {}", state.browser.current_path.display());
     let path_display = Paragraph::new(current_path)
-        .style(Style::default().fg(Color::Cyan).bg(Color::Rgb(40, 40, 40)));
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg));
     frame.render_widget(path_display, modal_chunks[0]);
 
     // STEP 9: Build the directory list
     let items: Vec<ListItem> = if state.browser.entries.is_empty() {
         // Empty directory - show message
         vec![ListItem::new("(empty)").style(
-            Style::default().fg(Color::Gray).bg(Color::Rgb(40, 40, 40))
+            Style::default().fg(theme.muted).bg(theme.popup_bg)
         )]
     } else {
         // Map directory entries to styled list items
@@ -113,16 +125,16 @@ fn render_folder_modal(frame: &mut Frame, state: &TuiState, area: Rect) {
 
                 // Style based on selection state
                 let style = if i == state.browser.selected_index {
-                    // Selected directory: yellow, bold, slightly lighter background
+                    // Selected directory: highlight color, bold, slightly lighter background
                     Style::default()
-                        .fg(Color::Yellow)
+                        .fg(theme.selected)
                         .add_modifier(Modifier::BOLD)
-                        .bg(Color::Rgb(60, 60, 60))
+                        .bg(theme.popup_selected_bg)
                 } else {
-                    // Normal directory: white text, standard background
+                    // Normal directory: text color, standard background
                     Style::default()
-                        .fg(Color::White)
-                        .bg(Color::Rgb(40, 40, 40))
+                        .fg(theme.text)
+                        .bg(theme.popup_bg)
                 };
 
                 ListItem::new(text).style(style)
@@ -136,11 +148,2390 @@ fn render_folder_modal(frame: &mut Frame, state: &TuiState, area: Rect) {
             Block::default()
                 .borders(Borders::ALL)
                 .title("Directories")
-                .style(Style::default().bg(Color::Rgb(40, 40, 40)))
+                .style(Style::default().bg(theme.popup_bg))
         );
     frame.render_widget(list, modal_chunks[1]);
 }
 
+/// Renders the "switch library" popup on top of the normal interface
+///
+/// Shows every known library profile in a small centered dialog.
+/// The profile that is currently open is marked with a `*`,
+/// the selected one is highlighted in yellow/bold.
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains profile picker state)
+pub fn render_profile_popup(frame: &mut Frame, state: &TuiState) {
+    let theme = &state.settings.theme;
+
+    // Small dialog: 50% width, 50% height of the screen
+    let area = centered_in_rect(50, 50, frame.size());
+
+    // Erase what the normal interface drew underneath
+    frame.render_widget(Clear, area);
+
+    // Build one list item per profile
+    let items: Vec<ListItem> = state
+        .profile_picker
+        .names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            // Mark the profile that is currently open
+            let marker = if *name == state.profile_name { "* " } else { "  " };
+            let text = format!("{}{}", marker, name);
+
+            let style = if i == state.profile_picker.selected_index {
+                Style::default()
+                    .fg(theme.selected)
+                    .add_modifier(Modifier::BOLD)
+                    .bg(theme.popup_selected_bg)
+            } else {
+                Style::default().fg(theme.text).bg(theme.popup_bg)
+            };
+
+            ListItem::new(text).style(style)
+        })
+        .collect();
+
+    // Bordered list with usage hint in the title
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" LIBRARIES (Enter: open, Esc: cancel) ")
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, area);
+}
+
+/// Renders the collection picker popup on top of the normal interface
+///
+/// Each entry shows how many books it holds. When choosing what to show,
+/// the first entry is "All books" and the shown collection is marked with `*`.
+/// While a new collection is being named, an input line is shown at the bottom.
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (collections and picker state)
+pub fn render_collection_popup(frame: &mut Frame, state: &TuiState) {
+    let theme = &state.settings.theme;
+    let picker = &state.collection_picker;
+
+    let area = centered_in_rect(50, 50, frame.size());
+    frame.render_widget(Clear, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    let entries = state.collection_entries();
+    let items: Vec<ListItem> = if entries.is_empty() {
+        vec![ListItem::new("No collections yet. Press 'n' to create one.")
+            .style(Style::default().fg(theme.muted))]
+    } else {
+        entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                // "* Summer reading (12)", smart collections marked with "⚙"
+                let marker = if picker.purpose == CollectionPurpose::Show && *entry == state.shelf {
+                    "* "
+                } else {
+                    "  "
+                };
+                let text = match entry {
+                    Shelf::All => format!("{}All books ({})", marker, state.books.len()),
+                    Shelf::RecentlyAdded => format!("{}Recently added", marker),
+                    Shelf::RecentlyOpened => format!("{}Recently opened", marker),
+                    Shelf::Series(name) => format!("{}Series: {}", marker, name),
+                    Shelf::Collection(name) => {
+                        let count = state.books.iter().filter(|b| b.user.collections.contains(name)).count();
+                        format!("{}{} ({})", marker, name, count)
+                    }
+                    Shelf::Smart(name) => {
+                        let query = state
+                            .smart_collections
+                            .iter()
+                            .find(|s| s.name == *name)
+                            .map(|s| s.query.as_str())
+                            .unwrap_or("");
+                        format!("{}⚙ {}  [{}]", marker, name, query)
+                    }
+                };
+
+                let style = if i == picker.selected_index {
+                    Style::default()
+                        .fg(theme.selected)
+                        .add_modifier(Modifier::BOLD)
+                        .bg(theme.popup_selected_bg)
+                } else {
+                    Style::default().fg(theme.text).bg(theme.popup_bg)
+                };
+                ListItem::new(text).style(style)
+            })
+            .collect()
+    };
+
+    let title = match picker.purpose {
+        CollectionPurpose::Show => " COLLECTIONS ",
+        CollectionPurpose::AddBook => " ADD TO COLLECTION ",
+    };
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, chunks[0]);
+
+    // Input line while naming a new collection, key help otherwise
+    let (title, text) = match &picker.input {
+        Some(input) if picker.input_smart => (
+            " New smart collection from the current filters (Enter: create, Esc: cancel) ",
+            format!("{}_", input),
+        ),
+        Some(input) => (" New collection (Enter: create, Esc: cancel) ", format!("{}_", input)),
+        None if picker.purpose == CollectionPurpose::Show => (
+            " Keys ",
+            "Enter: show | n: new | m: save filters as smart collection | d: delete | Esc: cancel".to_string(),
+        ),
+        None => (" Keys ", "Enter: add | n: new | d: delete | Esc: cancel".to_string()),
+    };
+    let input = Paragraph::new(text)
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(input, chunks[1]);
+}
+
+/// Renders the filter prompt over the footer
+///
+/// Shows the expression being typed with a short syntax reminder in the title.
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the prompt input)
+pub fn render_filter_prompt(frame: &mut Frame, state: &TuiState) {
+    let theme = &state.settings.theme;
+
+    // Same place and height as the footer
+    let screen = frame.size();
+    let height = screen.height.min(3);
+    let area = Rect::new(screen.x, screen.y + screen.height - height, screen.width, height);
+    frame.render_widget(Clear, area);
+
+    let prompt = Paragraph::new(format!("/{}_", state.filter_input))
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(
+            " Filter: words, tag:x author:x status:unread starred rating:>=4 size:<1mb -term (Enter: apply, Esc: cancel) ",
+        ));
+    frame.render_widget(prompt, area);
+}
+
+/// Renders the email prompt over the footer
+///
+/// Shows the recipient address being typed and how many books go.
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the prompt input)
+pub fn render_email_prompt(frame: &mut Frame, state: &TuiState) {
+    let theme = &state.settings.theme;
+
+    // Same place and height as the footer
+    let screen = frame.size();
+    let height = screen.height.min(3);
+    let area = Rect::new(screen.x, screen.y + screen.height - height, screen.width, height);
+    frame.render_widget(Clear, area);
+
+    let count = state.target_indices().len();
+    let title = format!(
+        " Email {} {} to (Enter: send, Esc: cancel) ",
+        count,
+        if count == 1 { "book" } else { "books" }
+    );
+    let prompt = Paragraph::new(format!("{}_", state.email_input))
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(prompt, area);
+}
+
+/// Renders the cover prompt over the footer
+///
+/// Shows the image file or URL being typed; empty, the cover comes from
+/// Open Library (by the book's ISBN).
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the prompt input)
+pub fn render_cover_prompt(frame: &mut Frame, state: &TuiState) {
+    let theme = &state.settings.theme;
+
+    // Same place and height as the footer
+    let screen = frame.size();
+    let height = screen.height.min(3);
+    let area = Rect::new(screen.x, screen.y + screen.height - height, screen.width, height);
+    frame.render_widget(Clear, area);
+
+    let title = match state.selected_book() {
+        Some(book) if book.meta.isbn.is_some() => {
+            " New cover: image file or URL, nothing for Open Library's (Enter: set, Esc: cancel) "
+        }
+        _ => " New cover: image file or URL (Enter: set, Esc: cancel) ",
+    };
+    let prompt = Paragraph::new(format!("{}_", state.cover_input))
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(prompt, area);
+}
+
+/// Renders the anthology's title prompt over the footer
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the prompt input and the marks)
+pub fn render_join_prompt(frame: &mut Frame, state: &TuiState) {
+    let theme = &state.settings.theme;
+
+    // Same place and height as the footer
+    let screen = frame.size();
+    let height = screen.height.min(3);
+    let area = Rect::new(screen.x, screen.y + screen.height - height, screen.width, height);
+    frame.render_widget(Clear, area);
+
+    let title = format!(
+        " Merge {} books into an anthology titled (Enter: merge, Esc: cancel) ",
+        state.marked.len()
+    );
+    let prompt = Paragraph::new(format!("{}_", state.join_input))
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(prompt, area);
+}
+
+/// Renders the quotes captured from books on top of the normal interface
+///
+/// One line per quote (its first words, then where it comes from), the
+/// selected one in full below the list.
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the quotes screen)
+pub fn render_quotes_popup(frame: &mut Frame, state: &TuiState) {
+    let theme = &state.settings.theme;
+    let screen = &state.quotes_screen;
+
+    let area = centered_in_rect(90, 80, frame.size());
+    frame.render_widget(Clear, area);
+
+    // Quote list on top, the selected quote below
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(10)])
+        .split(area);
+
+    // The list scrolls to keep the selected quote in view
+    let height = chunks[0].height.saturating_sub(2) as usize;
+    let first = (screen.selected_index + 1).saturating_sub(height.max(1));
+    let items: Vec<ListItem> = if screen.quotes.is_empty() {
+        vec![ListItem::new("No quotes yet: y in the reader quotes a passage, Y the description of a book")
+            .style(Style::default().fg(theme.muted))]
+    } else {
+        screen
+            .quotes
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(height)
+            .map(|(i, quote)| {
+                let (text_style, source_style) = if i == screen.selected_index {
+                    let selected = Style::default().fg(theme.selected).bg(theme.popup_selected_bg);
+                    (selected.add_modifier(Modifier::BOLD), selected)
+                } else {
+                    (Style::default().fg(theme.text), Style::default().fg(theme.muted))
+                };
+                let text = quote.text.replace('\n', " ");
+                ListItem::new(Line::from(vec![
+                    Span::styled(format!("\"{}\"", text), text_style),
+                    Span::styled(format!("  - {}", quote.attribution()), source_style),
+                ]))
+            })
+            .collect()
+    };
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" QUOTES ({}) ", screen.quotes.len()))
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, chunks[0]);
+
+    let text = match screen.quotes.get(screen.selected_index) {
+        Some(quote) => format!(
+            "{}\n\n- {} ({})",
+            quote.text,
+            quote.attribution(),
+            crate::book::date_text(quote.created)
+        ),
+        None => String::new(),
+    };
+    let body = Paragraph::new(text).wrap(Wrap { trim: true }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" ↑↓: select | x: remove | Esc: close ")
+            .style(Style::default().bg(theme.popup_bg).fg(theme.accent)),
+    );
+    frame.render_widget(body, chunks[1]);
+}
+
+/// Renders the sentences of a book's description being picked as a quote
+///
+/// The passage chosen so far (from its first sentence to the selected
+/// one) stands out.
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the picker)
+pub fn render_description_quote_popup(frame: &mut Frame, state: &TuiState) {
+    let Some(picker) = &state.description_quote else {
+        return;
+    };
+    let theme = &state.settings.theme;
+
+    let area = centered_in_rect(90, 60, frame.size());
+    frame.render_widget(Clear, area);
+
+    let passage = picker.start.map(|_| picker.passage());
+    let lines: Vec<Line> = picker
+        .sentences
+        .iter()
+        .enumerate()
+        .map(|(i, sentence)| {
+            let style = if i == picker.selected_index {
+                Style::default()
+                    .fg(theme.selected)
+                    .add_modifier(Modifier::BOLD)
+                    .bg(theme.popup_selected_bg)
+            } else if passage.as_ref().is_some_and(|passage| passage.contains(&i)) {
+                Style::default().fg(theme.accent)
+            } else {
+                Style::default().fg(theme.text)
+            };
+            Line::from(Span::styled(sentence.as_str(), style))
+        })
+        .collect();
+
+    let title = match picker.start {
+        None => format!(" Quote from {}: ↑↓: select the first sentence | Enter: start here | Esc: cancel ", picker.title),
+        Some(_) => format!(" Quote from {}: ↑↓: select the last sentence | Enter: quote | Esc: cancel ", picker.title),
+    };
+    // Scrolled so the selected sentence stays in view, as the sentences wrap
+    let (width, height) = (area.width.saturating_sub(2).max(1) as usize, area.height.saturating_sub(2) as usize);
+    let rows = |sentence: &String| sentence.chars().count().div_ceil(width).max(1);
+    let above: usize = picker.sentences[..picker.selected_index].iter().map(rows).sum();
+    let scroll = (above + rows(&picker.sentences[picker.selected_index])).saturating_sub(height.max(1));
+    let body = Paragraph::new(lines).wrap(Wrap { trim: true }).scroll((scroll as u16, 0)).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(body, area);
+}
+
+/// Renders the text search prompt over the footer
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the prompt input)
+pub fn render_text_search_prompt(frame: &mut Frame, state: &TuiState) {
+    let theme = &state.settings.theme;
+
+    // Same place and height as the footer
+    let screen = frame.size();
+    let height = screen.height.min(3);
+    let area = Rect::new(screen.x, screen.y + screen.height - height, screen.width, height);
+    frame.render_widget(Clear, area);
+
+    let prompt = Paragraph::new(format!("{}_", state.text_search_input))
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Search the text of the books for (\"a phrase\" matches whole; Enter: search, Esc: cancel) "),
+        );
+    frame.render_widget(prompt, area);
+}
+
+/// Renders the hits of a text search on top of the normal interface
+///
+/// Two lines per hit - the book and chapter, then the text around the
+/// words found (highlighted):
+/// ```
+/// The Hobbit - Chapter 5: Riddles in the Dark
+///   …deep down here by the dark water lived old Gollum…
+/// ```
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the search)
+pub fn render_text_search_popup(frame: &mut Frame, state: &TuiState) {
+    let Some(search) = &state.text_search else {
+        return;
+    };
+    let theme = &state.settings.theme;
+
+    let area = centered_in_rect(90, 80, frame.size());
+    frame.render_widget(Clear, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    // Keep the selected hit visible: two lines per hit, minus the borders
+    let visible = (chunks[0].height.saturating_sub(2) / 2).max(1) as usize;
+    let first = search.selected_index.saturating_sub(visible - 1);
+
+    let items: Vec<ListItem> = if search.hits.is_empty() {
+        vec![ListItem::new("  No chapter has these words").style(Style::default().fg(theme.muted))]
+    } else {
+        search
+            .hits
+            .iter()
+            .zip(&search.titles)
+            .enumerate()
+            .skip(first)
+            .take(visible)
+            .map(|(i, (hit, title))| {
+                let (heading, text, found) = if i == search.selected_index {
+                    let selected = Style::default().fg(theme.selected).bg(theme.popup_selected_bg);
+                    (selected.add_modifier(Modifier::BOLD), selected, selected.add_modifier(Modifier::UNDERLINED))
+                } else {
+                    let text = Style::default().fg(theme.text).bg(theme.popup_bg);
+                    (text.add_modifier(Modifier::BOLD), text, Style::default().fg(theme.accent).add_modifier(Modifier::BOLD))
+                };
+                let name = match &hit.chapter_title {
+                    Some(chapter) => format!("{} - {}", title, chapter),
+                    None => format!("{} - section {}", title, hit.chapter + 1),
+                };
+
+                // The extract on one line, the words found highlighted
+                let mut spans = vec![Span::styled("  ", text)];
+                for (n, piece) in hit.extract.split(crate::fulltext::MATCH_START).enumerate() {
+                    let (word, rest) = match piece.split_once(crate::fulltext::MATCH_END) {
+                        Some((word, rest)) if n > 0 => (word, rest),
+                        _ => ("", piece),
+                    };
+                    spans.push(Span::styled(word.to_string(), found));
+                    spans.push(Span::styled(rest.replace('\n', " "), text));
+                }
+                ListItem::new(vec![Line::styled(name, heading), Line::from(spans)]).style(text)
+            })
+            .collect()
+    };
+
+    let mut title = format!(" TEXT SEARCH - \"{}\": {} ", search.query, search.hits.len());
+    title.push_str(if search.hits.len() == 1 { "chapter " } else { "chapters " });
+    if let Some((done, total)) = search.indexing {
+        title.push_str(&format!("(index being updated: {}/{} books) ", done, total));
+    }
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, chunks[0]);
+
+    let help = Paragraph::new("↑↓/PgUp/PgDn: select | Enter: read the chapter | /: search again | Esc: close")
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(help, chunks[1]);
+}
+
+/// Renders planned file moves on top of the normal interface
+///
+/// Two lines per move - the old path, then the new one (or why it's skipped):
+/// ```
+/// ~/Books/dune.epub
+///   -> ~/Books/Frank Herbert/Dune/Dune.epub
+/// ```
+/// Renames inside the same folder only show the new file name.
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the review)
+pub fn render_move_review_popup(frame: &mut Frame, state: &TuiState) {
+    let Some(review) = &state.move_review else {
+        return;
+    };
+    let theme = &state.settings.theme;
+
+    let area = centered_in_rect(90, 80, frame.size());
+    frame.render_widget(Clear, area);
+
+    // Keep the selected move visible: two lines per move, minus the borders
+    let visible = (area.height.saturating_sub(2) / 2).max(1) as usize;
+    let first = review.selected_index.saturating_sub(visible - 1);
+
+    let items: Vec<ListItem> = review
+        .moves
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(visible)
+        .map(|(i, planned)| {
+            // A rename in place only shows the new file name
+            let to = if planned.to.parent() == planned.from.parent() {
+                planned.to.file_name().unwrap_or_default().to_string_lossy().to_string()
+            } else {
+                planned.to.display().to_string()
+            };
+            let target = match &planned.conflict {
+                None => format!("  -> {}", to),
+                Some(conflict) => format!("  !! skipped ({}): {}", conflict.label(), to),
+            };
+
+            let style = if i == review.selected_index {
+                Style::default()
+                    .fg(theme.selected)
+                    .add_modifier(Modifier::BOLD)
+                    .bg(theme.popup_selected_bg)
+            } else if planned.conflict.is_some() {
+                Style::default().fg(theme.muted).bg(theme.popup_bg)
+            } else {
+                Style::default().fg(theme.text).bg(theme.popup_bg)
+            };
+            ListItem::new(format!("{}\n{}", planned.from.display(), target)).style(style)
+        })
+        .collect();
+
+    let ready = review.moves.iter().filter(|m| m.conflict.is_none()).count();
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(
+                " {} - {} to move, {} skipped (Enter: move, Esc: cancel) ",
+                review.title.to_uppercase(),
+                ready,
+                review.moves.len() - ready
+            ))
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, area);
+}
+
+/// Renders a library merge on top of the normal interface
+///
+/// A summary line on top; below, the conflicts on the left (one line per
+/// field, with the side that will be kept: `Dune - Rating  ◀ ours`) and the
+/// book of the selected one side by side on the right - this library's
+/// values next to the other's, the kept one highlighted.
+/// The conflicts of a two-way sync are settled here too.
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the merge)
+pub fn render_library_merge_popup(frame: &mut Frame, state: &TuiState) {
+    let Some(merge) = &state.library_merge else {
+        return;
+    };
+    let theme = &state.settings.theme;
+
+    let area = centered_in_rect(90, 80, frame.size());
+    frame.render_widget(Clear, area);
+
+    // Summary on top, conflicts in the middle, help line at the bottom
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    let (summary, title) = match &merge.sync {
+        Some(sync) => (
+            format!(
+                "{} books updated, {} to receive, {} to send - the side changed last is chosen first",
+                merge.plan.updates.len(),
+                sync.incoming.len(),
+                sync.outgoing.len()
+            ),
+            format!(" SYNC WITH {} ", merge.source),
+        ),
+        None => (
+            format!(
+                "{} books updated, {} added, {} not found on this machine",
+                merge.plan.updates.len(),
+                merge.plan.added.len(),
+                merge.plan.unmatched
+            ),
+            format!(" MERGE {} ", merge.source),
+        ),
+    };
+    let summary = Paragraph::new(summary)
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(summary, chunks[0]);
+
+    let help = Paragraph::new(
+        "↑↓: select | ←/→: keep ours / take theirs | Space: switch | o/t: all ours / all theirs | Enter: apply | Esc: cancel",
+    )
+    .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+    .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(help, chunks[2]);
+
+    if merge.plan.conflict_count() == 0 {
+        let text = Paragraph::new("No conflicts - tags and collections are combined.").block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().bg(theme.popup_bg).fg(theme.muted)),
+        );
+        frame.render_widget(text, chunks[1]);
+        return;
+    }
+
+    // Conflict list on the left, the selected book side by side on the right
+    let body = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(30), Constraint::Percentage(30)])
+        .split(chunks[1]);
+
+    // Keep the selected conflict visible
+    let visible = body[0].height.saturating_sub(2).max(1) as usize;
+    let first = merge.selected_index.saturating_sub(visible - 1);
+    let items: Vec<ListItem> = merge
+        .conflicts()
+        .enumerate()
+        .skip(first)
+        .take(visible)
+        .map(|(i, (title, conflict))| {
+            let side = if conflict.take_theirs { "theirs ▶" } else { "◀ ours" };
+            let text = format!("{} - {}  {}", title, conflict.field.label(), side);
+            let style = if i == merge.selected_index {
+                Style::default()
+                    .fg(theme.selected)
+                    .add_modifier(Modifier::BOLD)
+                    .bg(theme.popup_selected_bg)
+            } else {
+                Style::default().fg(theme.text).bg(theme.popup_bg)
+            };
+            ListItem::new(text).style(style)
+        })
+        .collect();
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" {} CONFLICTS ", merge.plan.conflict_count()))
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, body[0]);
+
+    let Some((update, selected)) = merge.selected_update() else {
+        return;
+    };
+    // Values are cut into lines by hand so both columns stay level
+    let width = body[1].width.min(body[2].width).saturating_sub(4).max(1) as usize;
+    let cut = |value: &str| -> Vec<String> {
+        let chars: Vec<char> = if value.is_empty() { "(not set)".chars().collect() } else { value.chars().collect() };
+        chars.chunks(width).map(|chunk| chunk.iter().collect()).collect()
+    };
+
+    // One column per side: every conflicting field of the book, the kept
+    // value highlighted
+    let column = |theirs: bool| {
+        let mut lines = Vec::new();
+        for (i, conflict) in update.conflicts.iter().enumerate() {
+            let label_style = if i == selected {
+                Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(theme.text).add_modifier(Modifier::BOLD)
+            };
+            let marker = if i == selected { "▶ " } else { "  " };
+            lines.push(Line::styled(format!("{}{}", marker, conflict.field.label()), label_style));
+
+            let kept = conflict.take_theirs == theirs;
+            let value_style = if kept {
+                Style::default().fg(theme.selected).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(theme.muted)
+            };
+            let (ours, other) = (cut(&conflict.ours), cut(&conflict.theirs));
+            let value = if theirs { &other } else { &ours };
+            for line in value {
+                lines.push(Line::styled(format!("  {}", line), value_style));
+            }
+            // The shorter side is padded to the longer one
+            for _ in value.len()..ours.len().max(other.len()) {
+                lines.push(Line::default());
+            }
+            lines.push(Line::styled(if kept { "  ✓ kept" } else { "" }, value_style));
+        }
+        lines
+    };
+
+    let ours = Paragraph::new(column(false)).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" {} - THIS LIBRARY ", update.title))
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(ours, body[1]);
+    let theirs = Paragraph::new(column(true)).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" {} ", merge.source.to_uppercase()))
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(theirs, body[2]);
+}
+
+/// Renders the peers screen on top of the normal interface
+///
+/// One line per peer: `● Living room   192.168.1.20:8573   412 books   library: default`
+/// (offline peers are dimmed and marked with ○)
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the peers)
+pub fn render_peers_popup(frame: &mut Frame, state: &TuiState) {
+    let theme = &state.settings.theme;
+
+    let area = centered_in_rect(90, 60, frame.size());
+    frame.render_widget(Clear, area);
+
+    // Peer list on top, help line at the bottom
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    let items: Vec<ListItem> = if state.peers.is_empty() {
+        let text = if state.settings.peers.enabled {
+            "No peers found yet. Other FunkHunt instances need [peers] enabled too."
+        } else {
+            "Peer discovery is off. Set [peers] enabled = true in config.toml."
+        };
+        vec![ListItem::new(text).style(Style::default().fg(theme.muted))]
+    } else {
+        state
+            .peers
+            .iter()
+            .enumerate()
+            .map(|(i, peer)| {
+                let address = peer
+                    .address
+                    .map(|a| a.to_string())
+                    .unwrap_or_else(|| "(no address)".to_string());
+                // Paired peers: what they get from us
+                let paired = match state.trusted_peer(peer) {
+                    Some(trusted) => format!("paired, gets {}", trusted.policy.label()),
+                    None => String::new(),
+                };
+                let text = format!(
+                    "{} {:<20} {:<22} {:>6} books  {:<28} library: {}",
+                    if peer.online { '●' } else { '○' },
+                    peer.name,
+                    address,
+                    peer.books,
+                    paired,
+                    peer.library
+                );
+
+                let style = if i == state.peers_screen.selected_index {
+                    Style::default()
+                        .fg(theme.selected)
+                        .add_modifier(Modifier::BOLD)
+                        .bg(theme.popup_selected_bg)
+                } else if !peer.online {
+                    Style::default().fg(theme.muted).bg(theme.popup_bg)
+                } else {
+                    Style::default().fg(theme.text).bg(theme.popup_bg)
+                };
+                ListItem::new(text).style(style)
+            })
+            .collect()
+    };
+
+    let online = state.peers.iter().filter(|p| p.online).count();
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" PEERS - {} online ", online))
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, chunks[0]);
+
+    let help = Paragraph::new(
+        "↑↓: select | Enter: browse books | s: send marked books | y: sync | a: pair | r: unpair | o: sharing | d: forget | Esc: close",
+    )
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(help, chunks[1]);
+}
+
+/// Renders the oldest pending offer of a peer on top of the normal interface
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the offers)
+pub fn render_offer_popup(frame: &mut Frame, state: &TuiState) {
+    let Some(incoming) = state.incoming_offers.first() else {
+        return;
+    };
+    let theme = &state.settings.theme;
+
+    let area = centered_in_rect(70, 50, frame.size());
+    frame.render_widget(Clear, area);
+
+    // Offered books on top, answer keys at the bottom
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    let items: Vec<ListItem> = incoming
+        .offer
+        .books
+        .iter()
+        .map(|entry| {
+            let mut text = entry.title.clone();
+            if !entry.authors.is_empty() {
+                text.push_str(&format!(" - {}", entry.authors.join(", ")));
+            }
+            ListItem::new(text).style(Style::default().fg(theme.text).bg(theme.popup_bg))
+        })
+        .collect();
+
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(
+                " {} WANTS TO SEND YOU {} BOOKS ",
+                incoming.offer.from.to_uppercase(),
+                incoming.offer.books.len()
+            ))
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, chunks[0]);
+
+    let more = state.incoming_offers.len() - 1;
+    let mut help = "y/Enter: accept | n/Esc: decline".to_string();
+    if more > 0 {
+        help.push_str(&format!(" | {} more offers waiting", more));
+    }
+    let help = Paragraph::new(help)
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(help, chunks[1]);
+}
+
+/// Renders the oldest pending pairing on top of the normal interface
+///
+/// Shows the PIN the other screen should show too
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the pairings)
+pub fn render_pairing_popup(frame: &mut Frame, state: &TuiState) {
+    let Some(pairing) = state.pairings.first() else {
+        return;
+    };
+    let theme = &state.settings.theme;
+
+    let area = centered_in_rect(60, 40, frame.size());
+    frame.render_widget(Clear, area);
+
+    // PIN on top, answer keys at the bottom
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    let asked = if pairing.outgoing {
+        format!("You asked {} to pair.", pairing.name)
+    } else {
+        format!("{} asks to pair with this library.", pairing.name)
+    };
+    let text = format!(
+        "{}\n\nPIN  {} {} {}\n\nDoes {} show the same PIN? Only then is nobody in between:\nbooks and user data will travel encrypted.",
+        asked,
+        &pairing.pin[..3],
+        &pairing.pin[3..6],
+        &pairing.pin[6..],
+        pairing.name
+    );
+    let body = Paragraph::new(text).wrap(Wrap { trim: true }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" PAIRING ")
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(body, chunks[0]);
+
+    let more = state.pairings.len() - 1;
+    let mut help = "y/Enter: same PIN, trust | n/Esc: different, cancel".to_string();
+    if more > 0 {
+        help.push_str(&format!(" | {} more pairings waiting", more));
+    }
+    let help = Paragraph::new(help)
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(help, chunks[1]);
+}
+
+/// Renders the share policy of a paired peer on top of the normal interface
+///
+/// Two choices (`(•) Whole library`, `( ) Ask before each download`), then
+/// one checkbox per collection (`[x] Summer reading`) - ticking one shares
+/// the ticked collections only.
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the policy editor)
+pub fn render_policy_popup(frame: &mut Frame, state: &TuiState) {
+    let Some(editor) = &state.policy_editor else {
+        return;
+    };
+    let theme = &state.settings.theme;
+
+    let area = centered_in_rect(60, 60, frame.size());
+    frame.render_widget(Clear, area);
+
+    // Choices on top, help line at the bottom
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    let radio = |on: bool| if on { "(•)" } else { "( )" };
+    let mut rows = vec![
+        format!("{} Whole library (and sync)", radio(editor.policy == SharePolicy::Library)),
+        format!("{} Ask before each download", radio(editor.policy == SharePolicy::Ask)),
+    ];
+    for name in &state.collections {
+        let ticked = matches!(&editor.policy, SharePolicy::Collections(names) if names.contains(name));
+        rows.push(format!("    [{}] {}", if ticked { 'x' } else { ' ' }, name));
+    }
+    if state.collections.is_empty() {
+        rows.push("    (no collections to share on their own)".to_string());
+    }
+
+    // Keep the selected row visible
+    let visible = chunks[0].height.saturating_sub(2).max(1) as usize;
+    let first = editor.selected_index.saturating_sub(visible - 1);
+    let items: Vec<ListItem> = rows
+        .into_iter()
+        .enumerate()
+        .skip(first)
+        .take(visible)
+        .map(|(i, text)| {
+            let style = if i == editor.selected_index {
+                Style::default()
+                    .fg(theme.selected)
+                    .add_modifier(Modifier::BOLD)
+                    .bg(theme.popup_selected_bg)
+            } else {
+                Style::default().fg(theme.text).bg(theme.popup_bg)
+            };
+            ListItem::new(text).style(style)
+        })
+        .collect();
+
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" WHAT {} GETS ", editor.peer.to_uppercase()))
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, chunks[0]);
+
+    let help = Paragraph::new("↑↓: select | Space: choose / tick collection | Enter: save | Esc: cancel")
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(help, chunks[1]);
+}
+
+/// Renders the oldest download waiting for an approval on top of the
+/// normal interface
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the requests)
+pub fn render_access_popup(frame: &mut Frame, state: &TuiState) {
+    let Some(request) = state.access_requests.first() else {
+        return;
+    };
+    let theme = &state.settings.theme;
+
+    let area = centered_in_rect(60, 30, frame.size());
+    frame.render_widget(Clear, area);
+
+    // Question on top, answer keys at the bottom
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    let text = format!("{} would like to download
+
+{}
+
+from this library.", request.peer, request.title);
+    let body = Paragraph::new(text).wrap(Wrap { trim: true }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" DOWNLOAD REQUEST ")
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(body, chunks[0]);
+
+    let more = state.access_requests.len() - 1;
+    let mut help = "y/Enter: allow | n/Esc: refuse".to_string();
+    if more > 0 {
+        help.push_str(&format!(" | {} more requests waiting", more));
+    }
+    let help = Paragraph::new(help)
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(help, chunks[1]);
+}
+
+/// Renders the books of a Calibre library on top of the normal interface
+///
+/// One line per book: `[x] in library  Dune - Frank Herbert [Dune] (612 KB)`;
+/// books Calibre has in other formats only are greyed out.
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the Calibre library)
+pub fn render_calibre_popup(frame: &mut Frame, state: &TuiState) {
+    let Some(browser) = &state.calibre_browser else {
+        return;
+    };
+    let theme = &state.settings.theme;
+
+    let area = centered_in_rect(90, 80, frame.size());
+    frame.render_widget(Clear, area);
+
+    // Book list on top, help line at the bottom
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    // Keep the selected book visible
+    let visible = chunks[0].height.saturating_sub(2).max(1) as usize;
+    let first = browser.selected_index.saturating_sub(visible - 1);
+
+    let books = &browser.library.books;
+    let items: Vec<ListItem> = if books.is_empty() {
+        vec![ListItem::new("This Calibre library has no books.").style(Style::default().fg(theme.muted))]
+    } else {
+        books
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(visible)
+            .map(|(i, book)| {
+                let mark = if browser.marked.contains(&book.id) { "[x]" } else { "[ ]" };
+                let owned = if browser.owned.contains(&book.id) { "in library" } else { "" };
+                let mut text = format!("{} {:<10}  {}", mark, owned, book.title);
+                if !book.authors.is_empty() {
+                    text.push_str(&format!(" - {}", book.authors.join(", ")));
+                }
+                if let Some(series) = &book.series {
+                    text.push_str(&format!(" [{}]", series));
+                }
+                match book.size {
+                    _ if !book.has_epub => text.push_str(" (no EPUB)"),
+                    Some(size) => text.push_str(&format!(" ({} KB)", size / 1024)),
+                    None => {}
+                }
+
+                let style = if i == browser.selected_index {
+                    Style::default()
+                        .fg(theme.selected)
+                        .add_modifier(Modifier::BOLD)
+                        .bg(theme.popup_selected_bg)
+                } else if !book.has_epub {
+                    Style::default().fg(theme.muted).bg(theme.popup_bg)
+                } else {
+                    Style::default().fg(theme.text).bg(theme.popup_bg)
+                };
+                ListItem::new(text).style(style)
+            })
+            .collect()
+    };
+
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(
+                " CALIBRE - LIBRARY '{}' - {} BOOKS ",
+                browser.library.name,
+                books.len()
+            ))
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, chunks[0]);
+
+    let help = Paragraph::new(
+        "↑↓: select | v: mark | Enter: download marked books (or the selected one) | p: add our marked books (or the selected one) to Calibre | r: reload | Esc: close",
+    )
+    .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+    .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(help, chunks[1]);
+}
+
+/// Renders the entries of the subscribed feeds on top of the normal interface
+///
+/// One line per entry: `[x] new  in library  2026-10-14  Dune - Frank Herbert (612 KB)`;
+/// new entries are highlighted, entries without an EPUB are greyed out.
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the feed entries)
+pub fn render_feeds_popup(frame: &mut Frame, state: &TuiState) {
+    let Some(browser) = &state.feed_browser else {
+        return;
+    };
+    let theme = &state.settings.theme;
+
+    let area = centered_in_rect(90, 80, frame.size());
+    frame.render_widget(Clear, area);
+
+    // Entry list on top, help line at the bottom
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    // Keep the selected entry visible
+    let visible = chunks[0].height.saturating_sub(2).max(1) as usize;
+    let first = browser.selected_index.saturating_sub(visible - 1);
+
+    let entries = &browser.entries;
+    let items: Vec<ListItem> = if entries.is_empty() {
+        vec![ListItem::new("The feeds have no entries.").style(Style::default().fg(theme.muted))]
+    } else {
+        entries
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(visible)
+            .map(|(i, entry)| {
+                let mark = if browser.marked.contains(&entry.id) { "[x]" } else { "[ ]" };
+                let new = if browser.new.contains(&entry.id) { "new" } else { "" };
+                let owned = if browser.owned.contains(&entry.id) { "in library" } else { "" };
+                let date = entry.date.as_deref().unwrap_or("");
+                let mut text = format!("{} {:<3}  {:<10}  {:<10}  {}", mark, new, owned, date, entry.title);
+                if !entry.authors.is_empty() {
+                    text.push_str(&format!(" - {}", entry.authors.join(", ")));
+                }
+                match entry.size {
+                    _ if entry.epub.is_none() => text.push_str(" (no EPUB)"),
+                    Some(size) => text.push_str(&format!(" ({} KB)", size / 1024)),
+                    None => {}
+                }
+                text.push_str(&format!("  [{}]", entry.feed));
+
+                let style = if i == browser.selected_index {
+                    Style::default()
+                        .fg(theme.selected)
+                        .add_modifier(Modifier::BOLD)
+                        .bg(theme.popup_selected_bg)
+                } else if entry.epub.is_none() {
+                    Style::default().fg(theme.muted).bg(theme.popup_bg)
+                } else if browser.new.contains(&entry.id) {
+                    Style::default().fg(theme.accent).bg(theme.popup_bg)
+                } else {
+                    Style::default().fg(theme.text).bg(theme.popup_bg)
+                };
+                ListItem::new(text).style(style)
+            })
+            .collect()
+    };
+
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" NEW FROM FEEDS - {} NEW OF {} ", browser.new.len(), entries.len()))
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, chunks[0]);
+
+    let help = Paragraph::new("↑↓: select | v: mark | Enter: download marked books (or the selected one) | r: reload | Esc: close")
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(help, chunks[1]);
+}
+
+/// Renders the table of contents of the book being read on top of the reader
+///
+/// Entries are indented by their level; the one the page is in starts
+/// selected.
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (theme)
+/// * `reader` - The book being read (its contents are shown)
+pub fn render_contents_popup(frame: &mut Frame, state: &TuiState, reader: &BookReader) {
+    let theme = &state.settings.theme;
+    let selected = reader.contents.unwrap_or(0);
+
+    let area = centered_in_rect(60, 80, frame.size());
+    frame.render_widget(Clear, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    // Keep the selected entry visible
+    let visible = chunks[0].height.saturating_sub(2).max(1) as usize;
+    let first = selected.saturating_sub(visible - 1);
+
+    let items: Vec<ListItem> = reader
+        .document
+        .contents
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(visible)
+        .map(|(i, entry)| {
+            let style = if i == selected {
+                Style::default()
+                    .fg(theme.selected)
+                    .add_modifier(Modifier::BOLD)
+                    .bg(theme.popup_selected_bg)
+            } else {
+                Style::default().fg(theme.text).bg(theme.popup_bg)
+            };
+            ListItem::new(format!("{}{}", "  ".repeat(entry.depth), entry.title)).style(style)
+        })
+        .collect();
+
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" CONTENTS - {} ", reader.title))
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, chunks[0]);
+
+    let help = Paragraph::new("↑↓/PgUp/PgDn: select | Enter: go to the chapter | Esc: close")
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(help, chunks[1]);
+}
+
+/// Renders the bookmarks of the book being read
+///
+/// One line per bookmark: the chapter it's in, then its label (or the
+/// beginning of the paragraph when it has none):
+/// ```
+/// Chapter 3  The riddle game
+/// Chapter 7  “It was a dark and stormy night — the rain…”
+/// ```
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (the book's bookmarks)
+/// * `reader` - The book being read
+pub fn render_bookmarks_popup(frame: &mut Frame, state: &TuiState, reader: &BookReader) {
+    let Some(book) = state.books.iter().find(|book| book.path == reader.path) else {
+        return;
+    };
+    let theme = &state.settings.theme;
+    let selected = reader.bookmarks.unwrap_or(0);
+
+    let area = centered_in_rect(70, 70, frame.size());
+    frame.render_widget(Clear, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    // Keep the selected bookmark visible
+    let visible = chunks[0].height.saturating_sub(2).max(1) as usize;
+    let first = selected.saturating_sub(visible - 1);
+
+    let items: Vec<ListItem> = book
+        .user
+        .bookmarks
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(visible)
+        .map(|(i, bookmark)| {
+            let style = if i == selected {
+                Style::default()
+                    .fg(theme.selected)
+                    .add_modifier(Modifier::BOLD)
+                    .bg(theme.popup_selected_bg)
+            } else {
+                Style::default().fg(theme.text).bg(theme.popup_bg)
+            };
+            let chapter = match reader.entry_at(bookmark.chapter, bookmark.block) {
+                Some(entry) => entry.title.clone(),
+                None => format!("Section {}", bookmark.chapter + 1),
+            };
+            let label = if bookmark.label.is_empty() {
+                let text = reader
+                    .document
+                    .chapters
+                    .get(bookmark.chapter)
+                    .and_then(|chapter| chapter.blocks.get(bookmark.block))
+                    .map(|block| block.text())
+                    .unwrap_or_default();
+                let beginning: String = text.chars().take(50).collect();
+                let more = if text.chars().count() > 50 { "…" } else { "" };
+                format!("“{}{}”", beginning.replace('\n', " "), more)
+            } else {
+                bookmark.label.clone()
+            };
+            ListItem::new(format!("{}  {}", chapter, label)).style(style)
+        })
+        .collect();
+
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" BOOKMARKS - {} ", reader.title))
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, chunks[0]);
+
+    let help = Paragraph::new("↑↓: select | Enter: go to the bookmark | x/Del: remove | Esc: close")
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(help, chunks[1]);
+}
+
+/// Renders what the dictionaries say of the word selected in the reader
+///
+/// Each entry starts with its dictionary and headword:
+/// ```
+/// German-English FreeDict Dictionary - Haus
+/// Haus /haʊs/
+/// house
+/// ```
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (theme)
+/// * `reader` - The book being read (its lookup is shown)
+pub fn render_lookup_popup(frame: &mut Frame, state: &TuiState, reader: &BookReader) {
+    let Some(lookup) = &reader.lookup else {
+        return;
+    };
+    let theme = &state.settings.theme;
+
+    let area = centered_in_rect(70, 70, frame.size());
+    frame.render_widget(Clear, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    let mut lines = Vec::new();
+    for (i, definition) in lookup.definitions.iter().enumerate() {
+        if i > 0 {
+            lines.push(Line::from(""));
+        }
+        lines.push(Line::styled(
+            format!("{} - {}", definition.dictionary, definition.headword),
+            Style::default().fg(theme.accent).add_modifier(Modifier::BOLD),
+        ));
+        lines.extend(definition.text.lines().map(|line| Line::from(line.to_string())));
+    }
+
+    let text = Paragraph::new(lines)
+        .style(Style::default().fg(theme.text).bg(theme.popup_bg))
+        .wrap(Wrap { trim: false })
+        .scroll((lookup.scroll.min(u16::MAX as usize) as u16, 0))
+        .block(Block::default().borders(Borders::ALL).title(format!(" DICTIONARY - {} ", lookup.word)));
+    frame.render_widget(text, chunks[0]);
+
+    let help = Paragraph::new("↑↓/PgUp/PgDn: scroll | Esc/Enter: close")
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(help, chunks[1]);
+}
+
+/// Renders the e-readers plugged in on top of the normal interface
+///
+/// One line per device: `Kindle   Kindle   /media/me/Kindle   12 books of this library`
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the devices)
+pub fn render_devices_popup(frame: &mut Frame, state: &TuiState) {
+    let theme = &state.settings.theme;
+    let screen = &state.devices_screen;
+
+    let area = centered_in_rect(90, 60, frame.size());
+    frame.render_widget(Clear, area);
+
+    // Device list on top, help line at the bottom
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    let items: Vec<ListItem> = if screen.devices.is_empty() {
+        vec![ListItem::new("No e-reader found. Plug one in (USB mass storage), then press r.")
+            .style(Style::default().fg(theme.muted))]
+    } else {
+        screen
+            .devices
+            .iter()
+            .enumerate()
+            .map(|(i, device)| {
+                let text = format!(
+                    "{:<10} {:<20} {:<40} {} books of this library",
+                    device.kind.label(),
+                    device.name,
+                    device.root.display(),
+                    device.count_of(&state.books)
+                );
+                let style = if i == screen.selected_index {
+                    Style::default()
+                        .fg(theme.selected)
+                        .add_modifier(Modifier::BOLD)
+                        .bg(theme.popup_selected_bg)
+                } else {
+                    Style::default().fg(theme.text).bg(theme.popup_bg)
+                };
+                ListItem::new(text).style(style)
+            })
+            .collect()
+    };
+
+    let targets = if state.marked.is_empty() { 1 } else { state.marked.len() };
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" E-READERS - SEND {} BOOKS ", targets))
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, chunks[0]);
+
+    let help = Paragraph::new("↑↓: select | Enter: send marked books (or the selected one) | r: look again | Esc: close")
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(help, chunks[1]);
+}
+
+/// Renders the formats books can be converted to on top of the normal interface
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the highlighted format)
+pub fn render_convert_popup(frame: &mut Frame, state: &TuiState) {
+    let theme = &state.settings.theme;
+
+    let area = centered_in_rect(60, 40, frame.size());
+    frame.render_widget(Clear, area);
+
+    // Format list on top, help line at the bottom
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    let items: Vec<ListItem> = Format::TARGETS
+        .iter()
+        .enumerate()
+        .map(|(i, format)| {
+            let style = if i == state.convert_index {
+                Style::default()
+                    .fg(theme.selected)
+                    .add_modifier(Modifier::BOLD)
+                    .bg(theme.popup_selected_bg)
+            } else {
+                Style::default().fg(theme.text).bg(theme.popup_bg)
+            };
+            ListItem::new(format!("{:<6} {}", format.label(), format.description())).style(style)
+        })
+        .collect();
+
+    let targets = if state.marked.is_empty() { 1 } else { state.marked.len() };
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" CONVERT {} BOOKS TO ", targets))
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, chunks[0]);
+
+    let help = Paragraph::new("↑↓: select | Enter: convert (written next to the EPUB) | Esc: close")
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(help, chunks[1]);
+}
+
+/// Renders the QR code of a shared book's URL on top of the normal interface
+///
+/// The code is drawn black on white whatever the theme: phones only read
+/// dark modules on a light background.
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the share)
+pub fn render_share_popup(frame: &mut Frame, state: &TuiState) {
+    let Some(share) = &state.share else {
+        return;
+    };
+    let theme = &state.settings.theme;
+    let qr = share.qr.as_ref().map(|qr| qr.lines()).unwrap_or_default();
+
+    // Sized to the code: it can't be squeezed
+    let outer = frame.size();
+    let qr_width = qr.first().map(|line| line.chars().count()).unwrap_or(0) as u16;
+    let width = (qr_width + 4).max(share.url.len() as u16 + 4).max(50).min(outer.width);
+    let height = (qr.len() as u16 + 9).min(outer.height);
+    let area = Rect::new(
+        outer.x + (outer.width - width) / 2,
+        outer.y + (outer.height - height) / 2,
+        width,
+        height,
+    );
+    frame.render_widget(Clear, area);
+
+    // Code on top, details and keys at the bottom
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(4), Constraint::Length(3)])
+        .split(area);
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(" SHARING {} ", share.title.to_uppercase()))
+        .style(Style::default().bg(theme.popup_bg).fg(theme.text));
+    let inner = block.inner(chunks[0]);
+    frame.render_widget(block, chunks[0]);
+    if qr.is_empty() {
+        let text = Paragraph::new("The URL is too long for a QR code - type it in instead.")
+            .style(Style::default().fg(theme.muted));
+        frame.render_widget(text, inner);
+    } else {
+        let code_area = Rect::new(
+            inner.x + inner.width.saturating_sub(qr_width) / 2,
+            inner.y,
+            qr_width.min(inner.width),
+            inner.height,
+        );
+        let code = Paragraph::new(qr.join("\n")).style(Style::default().fg(Color::Black).bg(Color::White));
+        frame.render_widget(code, code_area);
+    }
+
+    let remaining = share.remaining().as_secs();
+    let details = Paragraph::new(format!(
+        "{}\nDownloaded {} times - stops in {}:{:02}",
+        share.url,
+        share.downloads(),
+        remaining / 60,
+        remaining % 60
+    ))
+    .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+    .block(Block::default().borders(Borders::ALL).title(" Scan with the phone, or open "));
+    frame.render_widget(details, chunks[1]);
+
+    let help = Paragraph::new("Esc/Enter: stop sharing")
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(help, chunks[2]);
+}
+
+/// Renders the transfer list on top of the normal interface
+///
+/// One line per transfer, with a progress bar:
+/// `↓ [#######-------]  48%  1.2 MB/2.5 MB  340.0 KB/s  running  Dune.epub from 192.168.1.20`
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the transfer queue)
+pub fn render_transfers_popup(frame: &mut Frame, state: &TuiState) {
+    let theme = &state.settings.theme;
+    let transfers = state.transfers.list();
+
+    let area = centered_in_rect(90, 60, frame.size());
+    frame.render_widget(Clear, area);
+
+    // Transfer list on top, help line at the bottom
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    let items: Vec<ListItem> = if transfers.is_empty() {
+        vec![ListItem::new("No transfers yet. Downloads from peers and remote folders show up here.")
+            .style(Style::default().fg(theme.muted))]
+    } else {
+        transfers
+            .iter()
+            .enumerate()
+            .map(|(i, transfer)| {
+                let arrow = match transfer.direction {
+                    TransferDirection::Download => '↓',
+                    TransferDirection::Upload => '↑',
+                    TransferDirection::Local => '⟳',
+                };
+                let (bar, percent) = match transfer.ratio() {
+                    Some(ratio) => {
+                        let filled = (ratio * PROGRESS_WIDTH as f64).round() as usize;
+                        (
+                            format!("{}{}", "#".repeat(filled), "-".repeat(PROGRESS_WIDTH - filled)),
+                            format!("{:>3}%", (ratio * 100.0) as u32),
+                        )
+                    }
+                    None => ("?".repeat(PROGRESS_WIDTH), "  ?%".to_string()),
+                };
+                let size = match transfer.total {
+                    // Conversions count in percent, not bytes
+                    _ if transfer.direction == TransferDirection::Local => String::new(),
+                    Some(total) => format!("{}/{}", human_bytes(transfer.done as f64), human_bytes(total as f64)),
+                    None => human_bytes(transfer.done as f64),
+                };
+                let speed = if transfer.state == TransferState::Running && transfer.direction != TransferDirection::Local {
+                    format!("{}/s", human_bytes(transfer.speed))
+                } else {
+                    String::new()
+                };
+                let mut text = format!(
+                    "{} [{}] {} {:>19} {:>12}  {:<9} {}",
+                    arrow,
+                    bar,
+                    percent,
+                    size,
+                    speed,
+                    transfer.state.label(),
+                    transfer.label
+                );
+                if let TransferState::Failed(reason) = &transfer.state {
+                    text.push_str(&format!(" ({})", reason));
+                }
+
+                let style = if i == state.transfers_screen.selected_index {
+                    Style::default()
+                        .fg(theme.selected)
+                        .add_modifier(Modifier::BOLD)
+                        .bg(theme.popup_selected_bg)
+                } else if transfer.state.is_finished() {
+                    Style::default().fg(theme.muted).bg(theme.popup_bg)
+                } else {
+                    Style::default().fg(theme.text).bg(theme.popup_bg)
+                };
+                ListItem::new(text).style(style)
+            })
+            .collect()
+    };
+
+    let active = transfers.iter().filter(|t| !t.state.is_finished()).count();
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" TRANSFERS - {} active ", active))
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, chunks[0]);
+
+    let help = Paragraph::new("↑↓: select | Space/p: pause/resume | c: cancel | r: retry | C: clear finished | Esc: close")
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(help, chunks[1]);
+}
+
+/// Renders the library health report on top of the normal interface
+///
+/// One line per book with a problem: `missing      ~/Books/Dune.epub`
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the report)
+pub fn render_health_popup(frame: &mut Frame, state: &TuiState) {
+    let theme = &state.settings.theme;
+    let screen = &state.health_screen;
+
+    let area = centered_in_rect(90, 60, frame.size());
+    frame.render_widget(Clear, area);
+
+    // Issue list on top, input/help line at the bottom
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    let items: Vec<ListItem> = if screen.issues.is_empty() {
+        vec![ListItem::new("All book files are healthy.").style(Style::default().fg(theme.muted))]
+    } else {
+        screen
+            .issues
+            .iter()
+            .enumerate()
+            .map(|(i, issue)| {
+                let style = if i == screen.selected_index {
+                    Style::default()
+                        .fg(theme.selected)
+                        .add_modifier(Modifier::BOLD)
+                        .bg(theme.popup_selected_bg)
+                } else {
+                    Style::default().fg(theme.text).bg(theme.popup_bg)
+                };
+                ListItem::new(format!("{:<18} {}", issue.problem.label(), issue.path.display()))
+                    .style(style)
+            })
+            .collect()
+    };
+
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" LIBRARY HEALTH ({} problems) ", screen.issues.len()))
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, chunks[0]);
+
+    // Input line while relocating, key help otherwise
+    let (title, text) = match &screen.relocate_input {
+        Some(input) => (" New path of the file (Enter: relocate, Esc: cancel) ", format!("{}_", input)),
+        None => (
+            " Keys ",
+            "↑↓: select | l: relocate | h: find moved files by content | d: remove entry | s: rescan folders | Esc: close".to_string(),
+        ),
+    };
+    let input = Paragraph::new(text)
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(input, chunks[1]);
+}
+
+/// Renders the fonts embedded in the selected book on top of the normal
+/// interface, a line per font: `  371.7 KB  TrueType  DejaVu Serif  OEBPS/fonts/DejaVuSerif.ttf`
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the font list)
+pub fn render_fonts_popup(frame: &mut Frame, state: &TuiState) {
+    let Some(list) = &state.font_list else {
+        return;
+    };
+    let theme = &state.settings.theme;
+
+    let area = centered_in_rect(90, 60, frame.size());
+    frame.render_widget(Clear, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    let muted = Style::default().fg(theme.muted);
+    let (items, title): (Vec<ListItem>, String) = match &list.fonts {
+        Ok(fonts) if fonts.is_empty() => (vec![ListItem::new("The book has no embedded fonts.").style(muted)], " FONTS ".to_string()),
+        Ok(fonts) => {
+            let total: u64 = fonts.iter().map(|font| font.size).sum();
+            let items = fonts
+                .iter()
+                .map(|font| {
+                    ListItem::new(format!(
+                        "{:>10}  {:<8}  {:<30}  {}",
+                        human_bytes(font.size as f64),
+                        font.kind(),
+                        font.family.as_deref().unwrap_or("-"),
+                        font.path
+                    ))
+                    .style(Style::default().fg(theme.text))
+                })
+                .collect();
+            (items, format!(" FONTS ({}, {}) ", fonts.len(), human_bytes(total as f64)))
+        }
+        Err(e) => (vec![ListItem::new(format!("Cannot read the fonts: {}", e)).style(muted)], " FONTS ".to_string()),
+    };
+
+    let fonts = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(fonts, chunks[0]);
+
+    let keys = Paragraph::new("s: strip them | u: keep only the characters the book uses | Esc: close (the book is backed up first)")
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(keys, chunks[1]);
+}
+
+/// Renders the controls of the narration playing on top of the normal
+/// interface: the book, then `Track 3/12  4:05 / 12:30`
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the player)
+pub fn render_listening_popup(frame: &mut Frame, state: &TuiState) {
+    let Some(listening) = &state.listening else {
+        return;
+    };
+    let theme = &state.settings.theme;
+
+    let area = centered_in_rect(70, 30, frame.size());
+    frame.render_widget(Clear, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    let book = state.books.iter().find(|book| book.path == listening.player.book);
+    let position = match listening.status {
+        Some(status) => status.summary(listening.player.tracks),
+        None => "Starting...".to_string(),
+    };
+    let lines = vec![
+        Line::from(Span::styled(
+            book.map(|book| book.display_title().to_string()).unwrap_or_default(),
+            Style::default().fg(theme.header).add_modifier(Modifier::BOLD),
+        )),
+        Line::from(book.map(|book| book.display_authors()).unwrap_or_default()),
+        Line::from(""),
+        Line::from(Span::styled(position, Style::default().fg(theme.accent))),
+    ];
+    let body = Paragraph::new(lines).wrap(Wrap { trim: true }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" LISTENING ")
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(body, chunks[0]);
+
+    let keys = Paragraph::new("Space: pause | ←→: 30 s back/forward | < >: previous/next file | s: stop | Esc: close")
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(keys, chunks[1]);
+}
+
+/// Renders the library's statistics on top of the normal interface: the
+/// same table `funkhunt stats` prints
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the statistics)
+pub fn render_statistics_popup(frame: &mut Frame, state: &TuiState) {
+    let Some(stats) = &state.statistics else {
+        return;
+    };
+    let theme = &state.settings.theme;
+
+    let area = centered_in_rect(80, 70, frame.size());
+    frame.render_widget(Clear, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    // Headings stand out from the rows
+    let lines: Vec<Line> = stats
+        .lines()
+        .into_iter()
+        .map(|line| match line.as_str() {
+            "Largest files" => Line::from(Span::styled(line, Style::default().fg(theme.header).add_modifier(Modifier::BOLD))),
+            _ if line.starts_with("Format ") => Line::from(Span::styled(line, Style::default().fg(theme.header).add_modifier(Modifier::BOLD))),
+            _ => Line::from(line),
+        })
+        .collect();
+    let body = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" STATISTICS ")
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(body, chunks[0]);
+
+    let keys = Paragraph::new("Esc: close")
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(keys, chunks[1]);
+}
+
+/// Renders the suspected duplicates on top of the normal interface: the
+/// list of pairs, or the selected pair side by side
+///
+/// The comparison has a row per field (`Size   812 KB   790 KB`), then the
+/// two tables of contents lined up; the rows where the books differ are
+/// highlighted.
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the duplicates screen)
+pub fn render_duplicates_popup(frame: &mut Frame, state: &TuiState) {
+    let theme = &state.settings.theme;
+    let screen = &state.duplicates_screen;
+
+    let area = centered_in_rect(90, 60, frame.size());
+    frame.render_widget(Clear, area);
+
+    // Pairs or comparison on top, key help at the bottom
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    let help = match &screen.comparison {
+        Some(comparison) => {
+            // Label column, then half of the rest for each book
+            let column = (chunks[0].width.saturating_sub(2 + 14) / 2).max(1) as usize;
+            let cell = |text: &str| {
+                let text: String = text.chars().take(column - 1).collect();
+                format!("{:<width$} ", text, width = column - 1)
+            };
+            let lines: Vec<Line> = comparison
+                .rows
+                .iter()
+                .skip(screen.scroll)
+                .map(|row| {
+                    let style = if row.differs {
+                        Style::default().fg(theme.accent)
+                    } else {
+                        Style::default().fg(theme.text)
+                    };
+                    Line::from(vec![
+                        Span::styled(format!("{:<13} ", row.label), Style::default().fg(theme.muted)),
+                        Span::styled(cell(&row.left), style),
+                        Span::styled(cell(&row.right), style),
+                    ])
+                })
+                .collect();
+
+            let table = Paragraph::new(lines).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(" COMPARISON (1: left book, 2: right book) ")
+                    .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+            );
+            frame.render_widget(table, chunks[0]);
+            "↑↓: scroll | 1/2: keep the left/right book (the other leaves the library) | Esc: back"
+        }
+        None => {
+            let items: Vec<ListItem> = if screen.pairs.is_empty() {
+                vec![ListItem::new("No books look like duplicates.").style(Style::default().fg(theme.muted))]
+            } else {
+                screen
+                    .pairs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, pair)| {
+                        let style = if i == screen.selected_index {
+                            Style::default()
+                                .fg(theme.selected)
+                                .add_modifier(Modifier::BOLD)
+                                .bg(theme.popup_selected_bg)
+                        } else {
+                            Style::default().fg(theme.text).bg(theme.popup_bg)
+                        };
+                        let name = |path: &std::path::Path| path.file_name().unwrap_or_default().to_string_lossy().to_string();
+                        ListItem::new(format!("{}: {} / {}", pair.title, name(&pair.left), name(&pair.right))).style(style)
+                    })
+                    .collect()
+            };
+
+            let list = List::new(items).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!(" SUSPECTED DUPLICATES ({} pairs) ", screen.pairs.len()))
+                    .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+            );
+            frame.render_widget(list, chunks[0]);
+            "↑↓: select | Enter: compare side by side | Esc: close"
+        }
+    };
+
+    let keys = Paragraph::new(help)
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(keys, chunks[1]);
+}
+
+/// Renders the metadata changes found online on top of the normal interface
+///
+/// One line per changed field: `[x] Publisher: Allen & Unwin -> HarperCollins`
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the review)
+pub fn render_metadata_review_popup(frame: &mut Frame, state: &TuiState) {
+    let Some(review) = &state.metadata_review else {
+        return;
+    };
+    let theme = &state.settings.theme;
+
+    let area = centered_in_rect(90, 60, frame.size());
+    frame.render_widget(Clear, area);
+
+    let items: Vec<ListItem> = review
+        .changes
+        .iter()
+        .enumerate()
+        .map(|(i, change)| {
+            let old = if change.old.is_empty() { "(none)" } else { change.old.as_str() };
+            let text = format!(
+                "[{}] {}: {} -> {}",
+                if change.accepted { 'x' } else { ' ' },
+                change.field.label(),
+                old,
+                change.new
+            );
+
+            let style = if i == review.selected_index {
+                Style::default()
+                    .fg(theme.selected)
+                    .add_modifier(Modifier::BOLD)
+                    .bg(theme.popup_selected_bg)
+            } else {
+                Style::default().fg(theme.text).bg(theme.popup_bg)
+            };
+            ListItem::new(text).style(style)
+        })
+        .collect();
+
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(
+                " FROM {} (Space: tick/untick, Enter: apply, Esc: discard) ",
+                review.source.to_uppercase()
+            ))
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, area);
+}
+
+/// Renders the bulk edit form on top of the normal interface
+///
+/// One line per field; empty fields are shown as "(unchanged)".
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the form)
+pub fn render_bulk_edit_popup(frame: &mut Frame, state: &TuiState) {
+    let theme = &state.settings.theme;
+    let form = &state.bulk_edit;
+
+    let area = centered_in_rect(70, 40, frame.size());
+    frame.render_widget(Clear, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    let items: Vec<ListItem> = BulkField::ALL
+        .iter()
+        .map(|&field| {
+            let selected = field == form.field;
+            let value = match field {
+                BulkField::AddTags => form.add_tags.clone(),
+                BulkField::Authors => form.authors.clone(),
+                BulkField::Series => form.series.clone(),
+                BulkField::Status => match form.status {
+                    None => String::new(),
+                    Some(None) => "none".to_string(),
+                    Some(Some(status)) => status.label().to_string(),
+                },
+            };
+            let value = match (value.is_empty(), selected && field != BulkField::Status) {
+                (_, true) => format!("{}_", value),
+                (true, false) => "(unchanged)".to_string(),
+                (false, false) => value,
+            };
+
+            let style = if selected {
+                Style::default()
+                    .fg(theme.selected)
+                    .add_modifier(Modifier::BOLD)
+                    .bg(theme.popup_selected_bg)
+            } else {
+                Style::default().fg(theme.text).bg(theme.popup_bg)
+            };
+            ListItem::new(format!("{:<9} {}", field.label(), value)).style(style)
+        })
+        .collect();
+
+    let count = state.target_indices().len();
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" BULK EDIT ({} books) ", count))
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, chunks[0]);
+
+    let help = Paragraph::new(
+        "↑↓: field | ←→: status | tags comma-separated, authors ';'-separated, series '-' removes | Enter: apply | Esc: cancel",
+    )
+    .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+    .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(help, chunks[1]);
+}
+
+/// Renders the metadata form of a book on top of the normal interface
+///
+/// One line per field, the cursor's with a `_`; what's wrong with a field
+/// that didn't validate is shown under the fields.
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the form)
+pub fn render_metadata_form_popup(frame: &mut Frame, state: &TuiState) {
+    let Some(form) = &state.metadata_form else {
+        return;
+    };
+    let theme = &state.settings.theme;
+
+    let area = centered_in_rect(70, 50, frame.size());
+    frame.render_widget(Clear, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    let mut items: Vec<ListItem> = MetadataField::ALL
+        .iter()
+        .map(|&field| {
+            let selected = field == form.field;
+            let value = match field {
+                MetadataField::Title => &form.title,
+                MetadataField::Authors => &form.authors,
+                MetadataField::Series => &form.series,
+                MetadataField::SeriesIndex => &form.series_index,
+                MetadataField::Tags => &form.tags,
+                MetadataField::Language => &form.language,
+            };
+            let style = if selected {
+                Style::default()
+                    .fg(theme.selected)
+                    .add_modifier(Modifier::BOLD)
+                    .bg(theme.popup_selected_bg)
+            } else {
+                Style::default().fg(theme.text).bg(theme.popup_bg)
+            };
+            let cursor = if selected { "_" } else { "" };
+            ListItem::new(format!("{:<9} {}{}", field.label(), value, cursor)).style(style)
+        })
+        .collect();
+    if let Some(error) = &form.error {
+        items.push(ListItem::new(""));
+        items.push(ListItem::new(format!("{} - {}", form.field.label(), error)).style(Style::default().fg(theme.accent)));
+    }
+
+    let name = form.path.file_name().unwrap_or_default().to_string_lossy();
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" EDIT METADATA ({}) ", name))
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, chunks[0]);
+
+    let help = Paragraph::new("Tab/↑↓: field | authors ';'-separated, tags comma-separated | Enter: save | Esc: cancel")
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(" Keys "));
+    frame.render_widget(help, chunks[1]);
+}
+
+/// Renders the author merge screen on top of the normal interface
+///
+/// One line per author with all its spellings and their book counts;
+/// the spelling chosen as canonical is shown in brackets:
+/// `[J. R. R. Tolkien (5)]  Tolkien, J.R.R. (3)`
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the author screen state)
+pub fn render_author_popup(frame: &mut Frame, state: &TuiState) {
+    let theme = &state.settings.theme;
+    let screen = &state.author_screen;
+
+    let area = centered_in_rect(80, 60, frame.size());
+    frame.render_widget(Clear, area);
+
+    let items: Vec<ListItem> = screen
+        .groups
+        .iter()
+        .enumerate()
+        .map(|(i, group)| {
+            let selected = i == screen.selected_index;
+            let text = group
+                .spellings
+                .iter()
+                .enumerate()
+                .map(|(j, (spelling, count))| {
+                    // The current choice is only marked on the selected line
+                    if selected && j == screen.choice {
+                        format!("[{} ({})]", spelling, count)
+                    } else {
+                        format!(" {} ({}) ", spelling, count)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            let style = if selected {
+                Style::default()
+                    .fg(theme.selected)
+                    .add_modifier(Modifier::BOLD)
+                    .bg(theme.popup_selected_bg)
+            } else {
+                Style::default().fg(theme.text).bg(theme.popup_bg)
+            };
+            ListItem::new(text).style(style)
+        })
+        .collect();
+
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" MERGE AUTHORS (←→: choose spelling, Enter: merge, Esc: close) ")
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, area);
+}
+
+/// Renders the log viewer popup on top of the normal interface
+///
+/// Shows the most recent log messages (newest at the bottom), scrolled up
+/// by `state.log_scroll` lines. Warnings and errors are highlighted.
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the log buffer)
+pub fn render_log_popup(frame: &mut Frame, state: &TuiState) {
+    let theme = &state.settings.theme;
+
+    // Large dialog: 90% width, 80% height of the screen
+    let area = centered_in_rect(90, 80, frame.size());
+    frame.render_widget(Clear, area);
+
+    // Visible lines = inner height (minus the 2 border rows)
+    let height = area.height.saturating_sub(2) as usize;
+    let lines = state.log.lines();
+    let end = lines.len().saturating_sub(state.log_scroll);
+    let start = end.saturating_sub(height);
+
+    let items: Vec<ListItem> = if lines.is_empty() {
+        vec![ListItem::new("(no log messages yet)").style(Style::default().fg(theme.muted))]
+    } else {
+        lines[start..end]
+            .iter()
+            .map(|line| {
+                // Color by severity so problems stand out
+                let color = if line.contains(" ERROR ") || line.contains(" WARN ") {
+                    theme.selected
+                } else {
+                    theme.text
+                };
+                ListItem::new(line.as_str()).style(Style::default().fg(color))
+            })
+            .collect()
+    };
+
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" LOG (↑↓/PgUp/PgDn: scroll, Esc: close) ")
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, area);
+}
+
+/// Renders the folder settings screen on top of the normal interface
+///
+/// Each scan root is listed with its flags and lists:
+/// `[RO] [watch] [json] [opf] ~/Books  exclude: *.tmp  tags: fiction`
+/// When a text field is being edited, an input line is shown at the bottom.
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the folder screen state)
+pub fn render_folder_settings_popup(frame: &mut Frame, state: &TuiState) {
+    let theme = &state.settings.theme;
+    let screen = &state.folder_screen;
+
+    let area = centered_in_rect(90, 60, frame.size());
+    frame.render_widget(Clear, area);
+
+    // Folder list on top, input/help line at the bottom
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    let items: Vec<ListItem> = if screen.folders.is_empty() {
+        vec![ListItem::new("No folders in this library. Press 'a' to add one.")
+            .style(Style::default().fg(theme.muted))]
+    } else {
+        screen
+            .folders
+            .iter()
+            .enumerate()
+            .map(|(i, (path, settings))| {
+                let mut text = format!(
+                    "{} {} {} {} {}",
+                    if settings.read_only { "[RO]" } else { "[rw]" },
+                    if settings.auto_watch { "[watch]" } else { "[     ]" },
+                    if settings.sidecars { "[json]" } else { "[    ]" },
+                    if settings.write_metadata { "[opf]" } else { "[   ]" },
+                    path.display()
+                );
+                if !settings.exclude.is_empty() {
+                    text.push_str(&format!("  exclude: {}", settings.exclude.join(", ")));
+                }
+                if !settings.default_tags.is_empty() {
+                    text.push_str(&format!("  tags: {}", settings.default_tags.join(", ")));
+                }
+
+                let style = if i == screen.selected_index {
+                    Style::default()
+                        .fg(theme.selected)
+                        .add_modifier(Modifier::BOLD)
+                        .bg(theme.popup_selected_bg)
+                } else {
+                    Style::default().fg(theme.text).bg(theme.popup_bg)
+                };
+                ListItem::new(text).style(style)
+            })
+            .collect()
+    };
+
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" FOLDERS ")
+            .style(Style::default().bg(theme.popup_bg).fg(theme.text)),
+    );
+    frame.render_widget(list, chunks[0]);
+
+    // Input line while editing, key help otherwise
+    let (title, text) = match screen.editing {
+        Some(FolderField::Exclude) => (" Exclude patterns (comma-separated, Enter: save, Esc: cancel) ", format!("{}_", screen.input)),
+        Some(FolderField::DefaultTags) => (" Default tags (comma-separated, Enter: save, Esc: cancel) ", format!("{}_", screen.input)),
+        None => (
+            " Keys ",
+            "↑↓: select | r: read-only | w: auto-watch | j: sidecar files | m: write metadata into EPUBs | e: exclude patterns | t: default tags | Esc: close".to_string(),
+        ),
+    };
+    let input = Paragraph::new(text)
+        .style(Style::default().fg(theme.accent).bg(theme.popup_bg))
+        .block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(input, chunks[1]);
+}
+
 /// Centers a rectangle within another rectangle using percentage sizing
 ///
 /// This helper function is used to create centered modal dialogs.
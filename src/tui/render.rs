@@ -2,38 +2,73 @@
 // Terminal initialization, restoration, and top-level rendering
 
 use crossterm::{
+    event::{DisableBracketedPaste, EnableBracketedPaste},
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     ExecutableCommand,
 };
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     Frame, Terminal,
 };
 use std::io::{self, stdout};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use super::components;
 use super::popup;
-use super::state::TuiState;
+use super::state::{TuiState, UiMode};
+use crate::graphics::Placement;
+
+/// Whether the terminal is currently in TUI mode (raw mode + alternate screen)
+/// Makes restore() safe to call more than once (guard, panic hook, normal exit)
+static TUI_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Restores the terminal when dropped
+///
+/// Returned by init(). Keep it alive for as long as the TUI runs: when
+/// main() returns early with an error (the ? operator) or unwinds from a
+/// panic, dropping the guard puts the terminal back to normal *before*
+/// the error is printed.
+pub struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        // Errors can't be reported from drop - best effort
+        let _ = restore();
+    }
+}
 
 /// Initializes the terminal in TUI mode
 ///
 /// This function:
-/// 1. Enables "raw mode" - terminal captures each keypress immediately without waiting for Enter
-/// 2. Enters the "alternate screen" - saves the current terminal content and uses a fresh buffer
+/// 1. Installs a panic hook that restores the terminal before the panic message is printed
+/// 2. Enables "raw mode" - terminal captures each keypress immediately without waiting for Enter
+/// 3. Enters the "alternate screen" - saves the current terminal content and uses a fresh buffer
+/// 4. Enables bracketed paste - pasted text arrives as one event, not as keys
 ///
 /// # Returns
-/// A Terminal object that we can use to draw frames, or an error if initialization fails
-pub fn init() -> io::Result<Terminal<CrosstermBackend<std::io::Stdout>>> {
+/// A Terminal object that we can use to draw frames plus the guard that restores
+/// the terminal, or an error if initialization fails
+pub fn init() -> io::Result<(Terminal<CrosstermBackend<std::io::Stdout>>, TerminalGuard)> {
+    install_panic_hook();
+
     // Enable raw mode - keys are processed immediately, not line-buffered
     enable_raw_mode()?;
+    TUI_ACTIVE.store(true, Ordering::SeqCst);
+
+    // From here on, any early return drops the guard and restores the terminal
+    let guard = TerminalGuard;
 
     // Enter alternate screen - like vim, we use a separate buffer
     // When we exit, the user's original terminal content will be restored
     stdout().execute(EnterAlternateScreen)?;
 
+    // Pasting a path must not run the commands of its letters
+    stdout().execute(EnableBracketedPaste)?;
+
     // Create and return a Terminal with crossterm backend using stdout
-    Terminal::new(CrosstermBackend::new(stdout()))
+    let terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+    Ok((terminal, guard))
 }
 
 /// Restores the terminal to its original state
@@ -41,31 +76,301 @@ pub fn init() -> io::Result<Terminal<CrosstermBackend<std::io::Stdout>>> {
 /// This function:
 /// 1. Disables raw mode - returns terminal to normal line-buffered mode
 /// 2. Leaves alternate screen - restores the user's original terminal content
+/// 3. Disables bracketed paste
 ///
 /// Should always be called before exiting the application!
+/// Does nothing if the terminal was already restored.
 pub fn restore() -> io::Result<()> {
+    // Only the first call does the work
+    if !TUI_ACTIVE.swap(false, Ordering::SeqCst) {
+        return Ok(());
+    }
+
     // Disable raw mode - return to normal terminal behavior
     disable_raw_mode()?;
 
     // Leave alternate screen - show the original terminal content again
     stdout().execute(LeaveAlternateScreen)?;
 
+    stdout().execute(DisableBracketedPaste)?;
+
     Ok(())
 }
 
+/// Chains a panic hook that restores the terminal, then runs the previous hook
+///
+/// Without this, a panic message is printed into the alternate screen (and lost)
+/// and the shell is left in raw mode.
+fn install_panic_hook() {
+    let previous_hook = std::panic::take_hook();
+
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = restore();
+        tracing::error!(panic = %info, "application panicked");
+        previous_hook(info);
+    }));
+}
+
+/// Pictures to draw over what render() draws: those of the page of the
+/// reader, unless a popup is open over it
+///
+/// # Arguments
+/// * `state` - Current application state
+/// * `area` - Size of the screen
+pub fn picture_placements(state: &TuiState, area: Rect) -> Vec<Placement> {
+    match &state.reader {
+        Some(reader)
+            if state.mode == UiMode::Reading
+                && reader.contents.is_none()
+                && reader.lookup.is_none()
+                && reader.bookmarks.is_none() =>
+        {
+            components::reader_pictures(reader, area)
+        }
+        _ => Vec::new(),
+    }
+}
+
 /// Main render function - draws the appropriate interface based on current mode
 ///
 /// # Arguments
 /// * `frame` - The frame buffer to draw on (provided by ratatui's terminal.draw())
 /// * `state` - Current application state (determines what we render)
+#[cfg_attr(feature = "profiling", tracing::instrument(level = "trace", name = "render", skip_all))]
 pub fn render(frame: &mut Frame, state: &TuiState) {
     // Check current mode and render accordingly
-    if state.mode == crate::tui::state::UiMode::AddingFolder {
+    match state.mode {
         // Show file browser popup over the normal interface
-        popup::render_add_folder_popup(frame, state);
-    } else {
+        UiMode::AddingFolder => popup::render_add_folder_popup(frame, state),
+
+        // Show the profile picker on top of the normal interface
+        UiMode::PickingProfile => {
+            render_normal_interface(frame, state);
+            popup::render_profile_popup(frame, state);
+        }
+
+        // Show the log viewer on top of the normal interface
+        UiMode::ViewingLog => {
+            render_normal_interface(frame, state);
+            popup::render_log_popup(frame, state);
+        }
+
+        // Show the folder settings screen on top of the normal interface
+        UiMode::FolderSettings => {
+            render_normal_interface(frame, state);
+            popup::render_folder_settings_popup(frame, state);
+        }
+
+        // Show the collection picker on top of the normal interface
+        UiMode::PickingCollection => {
+            render_normal_interface(frame, state);
+            popup::render_collection_popup(frame, state);
+        }
+
+        // Show the filter prompt over the footer
+        UiMode::Filtering => {
+            render_normal_interface(frame, state);
+            popup::render_filter_prompt(frame, state);
+        }
+
+        // Show the author merge screen on top of the normal interface
+        UiMode::MergingAuthors => {
+            render_normal_interface(frame, state);
+            popup::render_author_popup(frame, state);
+        }
+
+        // Show the bulk edit form on top of the normal interface
+        UiMode::BulkEditing => {
+            render_normal_interface(frame, state);
+            popup::render_bulk_edit_popup(frame, state);
+        }
+
+        // Show the selected book's metadata form on top of the normal interface
+        UiMode::EditingMetadata => {
+            render_normal_interface(frame, state);
+            popup::render_metadata_form_popup(frame, state);
+        }
+
+        // Show the metadata found online on top of the normal interface
+        UiMode::ReviewingMetadata => {
+            render_normal_interface(frame, state);
+            popup::render_metadata_review_popup(frame, state);
+        }
+
+        // Show the health report on top of the normal interface
+        UiMode::HealthReport => {
+            render_normal_interface(frame, state);
+            popup::render_health_popup(frame, state);
+        }
+
+        // Show the library's totals on top of the normal interface
+        UiMode::Statistics => {
+            render_normal_interface(frame, state);
+            popup::render_statistics_popup(frame, state);
+        }
+
+        // Show the selected book's fonts on top of the normal interface
+        UiMode::Fonts => {
+            render_normal_interface(frame, state);
+            popup::render_fonts_popup(frame, state);
+        }
+
+        // Show the controls of the narration playing on top of the normal interface
+        UiMode::Listening => {
+            render_normal_interface(frame, state);
+            popup::render_listening_popup(frame, state);
+        }
+
+        // Show the suspected duplicates (or two side by side) on top of the normal interface
+        UiMode::Duplicates => {
+            render_normal_interface(frame, state);
+            popup::render_duplicates_popup(frame, state);
+        }
+
+        // Show the planned file moves on top of the normal interface
+        UiMode::ReviewingMoves => {
+            render_normal_interface(frame, state);
+            popup::render_move_review_popup(frame, state);
+        }
+
+        // Show the library merge on top of the normal interface
+        UiMode::MergingLibrary => {
+            render_normal_interface(frame, state);
+            popup::render_library_merge_popup(frame, state);
+        }
+
+        // Show the peers on top of the normal interface
+        UiMode::Peers => {
+            render_normal_interface(frame, state);
+            popup::render_peers_popup(frame, state);
+        }
+
+        // Show a peer's books in place of ours
+        UiMode::BrowsingPeer => render_peer_interface(frame, state),
+
+        // Ask about books sent by a peer on top of the normal interface
+        UiMode::ReviewingOffer => {
+            render_normal_interface(frame, state);
+            popup::render_offer_popup(frame, state);
+        }
+
+        // Ask to compare the PIN of a pairing on top of the normal interface
+        UiMode::Pairing => {
+            render_normal_interface(frame, state);
+            popup::render_pairing_popup(frame, state);
+        }
+
+        // Show the share policy of a paired peer on top of the normal interface
+        UiMode::EditingPolicy => {
+            render_normal_interface(frame, state);
+            popup::render_policy_popup(frame, state);
+        }
+
+        // Ask whether a peer may download a book on top of the normal interface
+        UiMode::ApprovingAccess => {
+            render_normal_interface(frame, state);
+            popup::render_access_popup(frame, state);
+        }
+
+        // Show the QR code of a shared book on top of the normal interface
+        UiMode::Sharing => {
+            render_normal_interface(frame, state);
+            popup::render_share_popup(frame, state);
+        }
+
+        // Show the Calibre library on top of the normal interface
+        UiMode::BrowsingCalibre => {
+            render_normal_interface(frame, state);
+            popup::render_calibre_popup(frame, state);
+        }
+
+        // Show the new books of the feeds on top of the normal interface
+        UiMode::BrowsingFeeds => {
+            render_normal_interface(frame, state);
+            popup::render_feeds_popup(frame, state);
+        }
+
+        // Show the book being read in place of the book list
+        // (with its table of contents, a dictionary lookup or its bookmarks on
+        // top when open)
+        UiMode::Reading => match &state.reader {
+            Some(reader) => {
+                components::render_reader(frame, state, reader, frame.size());
+                if reader.contents.is_some() {
+                    popup::render_contents_popup(frame, state, reader);
+                }
+                if reader.lookup.is_some() {
+                    popup::render_lookup_popup(frame, state, reader);
+                }
+                if reader.bookmarks.is_some() {
+                    popup::render_bookmarks_popup(frame, state, reader);
+                }
+            }
+            None => render_normal_interface(frame, state),
+        },
+
+        // Show the text search prompt over the footer
+        UiMode::SearchingText => {
+            render_normal_interface(frame, state);
+            popup::render_text_search_prompt(frame, state);
+        }
+
+        // Show the chapters that matched on top of the normal interface
+        UiMode::TextSearchResults => {
+            render_normal_interface(frame, state);
+            popup::render_text_search_popup(frame, state);
+        }
+
+        // Show the email prompt over the footer
+        UiMode::Emailing => {
+            render_normal_interface(frame, state);
+            popup::render_email_prompt(frame, state);
+        }
+
+        // Show the cover prompt over the footer
+        UiMode::SettingCover => {
+            render_normal_interface(frame, state);
+            popup::render_cover_prompt(frame, state);
+        }
+
+        // Show the quotes on top of the normal interface
+        UiMode::Quotes => {
+            render_normal_interface(frame, state);
+            popup::render_quotes_popup(frame, state);
+        }
+
+        // Show the sentences of the description on top of the normal interface
+        UiMode::QuotingDescription => {
+            render_normal_interface(frame, state);
+            popup::render_description_quote_popup(frame, state);
+        }
+
+        // Show the anthology's title prompt over the footer
+        UiMode::Joining => {
+            render_normal_interface(frame, state);
+            popup::render_join_prompt(frame, state);
+        }
+
+        // Show the formats to convert to on top of the normal interface
+        UiMode::Converting => {
+            render_normal_interface(frame, state);
+            popup::render_convert_popup(frame, state);
+        }
+
+        // Show the e-readers on top of the normal interface
+        UiMode::Devices => {
+            render_normal_interface(frame, state);
+            popup::render_devices_popup(frame, state);
+        }
+
+        // Show the transfers on top of the normal interface
+        UiMode::Transfers => {
+            render_normal_interface(frame, state);
+            popup::render_transfers_popup(frame, state);
+        }
+
         // Show normal book list interface
-        render_normal_interface(frame, state);
+        UiMode::Normal => render_normal_interface(frame, state),
     }
 }
 
@@ -113,3 +418,29 @@ fn render_normal_interface(frame: &mut Frame, state: &TuiState) {
     components::render_book_details(frame, state, body_chunks[1]);
     components::render_footer(frame, state, main_chunks[2]);
 }
+
+/// Renders the library of a peer with the layout of the normal interface:
+/// its books on the left, the selected one's details on the right
+///
+/// # Arguments
+/// * `frame` - The frame buffer to draw on
+/// * `state` - Current application state (contains the peer's catalog)
+fn render_peer_interface(frame: &mut Frame, state: &TuiState) {
+    let Some(browser) = &state.peer_browser else {
+        return render_normal_interface(frame, state);
+    };
+
+    let main_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(0), Constraint::Length(3)])
+        .split(frame.size());
+    let body_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(main_chunks[1]);
+
+    components::render_peer_header(frame, state, browser, main_chunks[0]);
+    components::render_peer_book_list(frame, state, browser, body_chunks[0]);
+    components::render_peer_book_details(frame, state, browser, body_chunks[1]);
+    components::render_peer_footer(frame, state, main_chunks[2]);
+}
@@ -2,8 +2,51 @@
 // Application state management - the "heart" of the TUI
 // This module contains all mutable state that changes as the user interacts with the app
 
-use crate::book::Book;
-use std::path::PathBuf;
+use crate::audio::{Player, Status};
+use crate::authors::AuthorGroup;
+use crate::calibre::{CalibreBook, Library};
+use crate::convert::Format;
+use crate::device::Device;
+use crate::dictionary::Definition;
+use crate::feeds::FeedEntry;
+use crate::fulltext::Hit;
+use crate::graphics::Protocol;
+use crate::discovery::Peer;
+use crate::duplicates::{Comparison, Pair};
+use crate::fonts::{Action as FontAction, Font};
+use crate::peer::{AccessRequest, Catalog, CatalogEntry, IncomingOffer, SyncPush};
+use crate::book::{Book, ListeningPosition, Metadata, ReadingPosition, ReadingStatus};
+use crate::logging::LogBuffer;
+use crate::parse_queue::ParseQueue;
+use crate::tui::worker::Workers;
+use crate::merge::{Conflict, MergePlan, Update};
+use crate::filter::Filter;
+use crate::query::BookIndex;
+use crate::health::Issue;
+use crate::journal::{Journal, Operation};
+use crate::organize::Move;
+use crate::providers::Change;
+use crate::stats::LibraryStats;
+use crate::quotes::Quote;
+use crate::reader::{Block, Document, Line, TocEntry, Typography};
+use crate::profile::{FolderSettings, Shelf, SmartCollection, RECENT_DAYS};
+use crate::settings::Settings;
+use crate::share::BookShare;
+use crate::sort::SortOrder;
+use crate::sync::PendingSync;
+use crate::transfer::Transfers;
+use crate::trust::{Pairing, Secret, SharePolicy, TrustedPeer};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Characters of text shown by the preview of a book (about two pages)
+const PREVIEW_CHARS: usize = 3000;
+
+/// How often the player playing a narration is asked where it is
+const LISTENING_POLL: Duration = Duration::from_secs(1);
 
 /// Main state of the terminal interface
 /// This struct holds everything the UI needs to render and respond to user actions
@@ -25,6 +68,1312 @@ pub struct TuiState {
 
     /// File browser state (for the "add folder" popup)
     pub browser: FileBrowser,
+
+    /// Name of the library profile currently open (shown in header)
+    pub profile_name: String,
+
+    /// Profile picker state (for the "switch library" popup)
+    pub profile_picker: ProfilePicker,
+
+    /// User settings from config.toml (theme, key bindings, viewer)
+    pub settings: Settings,
+
+    /// Short message shown in the footer (e.g. "Config reloaded")
+    pub status_message: Option<String>,
+
+    /// Recent log lines (for the log viewer popup)
+    pub log: LogBuffer,
+
+    /// How many lines the log viewer is scrolled up from the newest line
+    pub log_scroll: usize,
+
+    /// Folder settings screen state (scan roots and their settings)
+    pub folder_screen: FolderScreen,
+
+    /// Whether books were edited since the library was last saved
+    /// (set by edits, cleared by the main loop's autosave)
+    pub dirty: bool,
+
+    /// Indices (into `books`) of the books shown in the list, in display order
+    /// `selected_index` points into this list, not into `books`.
+    /// Rebuilt by `refresh_view()` whenever books or filters change.
+    pub view: Vec<usize>,
+
+    /// Books changed in a way that may change `view`, which is rebuilt
+    /// once before it's used next (see `invalidate_view`)
+    view_stale: bool,
+
+    /// Names of the collections (shelves) of this library
+    pub collections: Vec<String>,
+
+    /// Smart collections (saved filters) of this library
+    pub smart_collections: Vec<SmartCollection>,
+
+    /// Part of the library shown in the book list (all, a collection, a smart collection)
+    pub shelf: Shelf,
+
+    /// Parsed filter of the shown smart collection (None = not showing one)
+    pub shelf_filter: Option<Filter>,
+
+    /// Filter typed with the '/' prompt (kept as text so it can be edited again)
+    pub filter_text: String,
+
+    /// Parsed form of `filter_text`
+    pub filter: Filter,
+
+    /// What the filters and orders compare of every book (see query.rs),
+    /// kept up to date by `refresh_view`
+    pub index: BookIndex,
+
+    /// Text typed into the filter prompt while it's open
+    pub filter_input: String,
+
+    /// Show only starred books
+    pub starred_only: bool,
+
+    /// Show only books with this reading status (None = any status)
+    pub status_filter: Option<ReadingStatus>,
+
+    /// Order of the book list
+    pub sort: SortOrder,
+
+    /// Show archived books too (they're hidden by default)
+    pub show_archived: bool,
+
+    /// Collection picker state (for the "collections" popup)
+    pub collection_picker: CollectionPicker,
+
+    /// Author merge screen state
+    pub author_screen: AuthorScreen,
+
+    /// Paths of the books marked for bulk operations
+    /// (paths survive rescans and re-sorting, positions don't)
+    pub marked: BTreeSet<PathBuf>,
+
+    /// Bulk edit form state
+    pub bulk_edit: BulkEdit,
+
+    /// Metadata form of the selected book while it's open
+    pub metadata_form: Option<MetadataForm>,
+
+    /// Operations of this session that can be undone and redone
+    pub journal: Journal,
+
+    /// Books whose title, authors or series were edited (or an edit undone)
+    /// since the main loop last wrote them into their EPUB files
+    pub metadata_edits: BTreeSet<PathBuf>,
+
+    /// Metadata found online, waiting to be reviewed
+    pub metadata_review: Option<MetadataReview>,
+
+    /// Health report screen state
+    pub health_screen: HealthScreen,
+
+    /// The library's totals while the statistics screen is open
+    pub statistics: Option<LibraryStats>,
+
+    /// Suspected duplicates screen state
+    pub duplicates_screen: DuplicatesScreen,
+
+    /// File moves waiting to be confirmed
+    pub move_review: Option<MoveReview>,
+
+    /// Another library waiting to be merged into this one
+    pub library_merge: Option<LibraryMerge>,
+
+    /// Other FunkHunt instances found on the local network (and the ones
+    /// remembered from earlier sessions)
+    pub peers: Vec<Peer>,
+
+    /// Peers screen state
+    pub peers_screen: PeersScreen,
+
+    /// The catalog of the peer being browsed
+    pub peer_browser: Option<PeerBrowser>,
+
+    /// Books peers would like to send, oldest first (the first one is
+    /// shown when the book list is idle)
+    pub incoming_offers: Vec<IncomingOffer>,
+
+    /// Peers we paired with - the only ones let in when pairing is required
+    pub trusted: Vec<TrustedPeer>,
+
+    /// Pairings waiting for the user to compare the PINs, oldest first
+    pub pairings: Vec<Pairing>,
+
+    /// The share policy being edited (None = screen not open)
+    pub policy_editor: Option<PolicyEditor>,
+
+    /// Downloads of paired peers waiting for the user's approval, oldest first
+    pub access_requests: Vec<AccessRequest>,
+
+    /// The main loop's channel: keys, and the results of the work done in
+    /// the background
+    pub workers: Workers,
+
+    /// Books whose metadata is read in the background (placeholders until then)
+    pub parsing: ParseQueue,
+
+    /// Ticks since the app started (see worker::TICK), for animations
+    pub ticks: usize,
+
+    /// Downloads and uploads of this session (shared with the main loop
+    /// and the peer server)
+    pub transfers: Transfers,
+
+    /// Transfer list screen state
+    pub transfers_screen: TransfersScreen,
+
+    /// The book being shared with a phone (its server stops when dropped)
+    pub share: Option<BookShare>,
+
+    /// E-reader list state
+    pub devices_screen: DevicesScreen,
+
+    /// Address typed into the email prompt while it's open
+    pub email_input: String,
+
+    /// Format highlighted in the convert popup (index into Format::TARGETS)
+    pub convert_index: usize,
+
+    /// Image file or URL typed into the cover prompt while it's open
+    pub cover_input: String,
+
+    /// Fonts of the selected book while they're listed
+    pub font_list: Option<FontList>,
+
+    /// The narration playing in the background (None = nothing playing)
+    pub listening: Option<Listening>,
+
+    /// Title typed into the anthology prompt while it's open
+    pub join_input: String,
+
+    /// The Calibre library being browsed (None = screen not open)
+    pub calibre_browser: Option<CalibreBrowser>,
+
+    /// The entries of the subscribed feeds (None = screen not open)
+    pub feed_browser: Option<FeedBrowser>,
+
+    /// The book open in the built-in reader (None = reader not open)
+    pub reader: Option<BookReader>,
+
+    /// Whether the details pane shows the first pages of the selected book
+    pub previewing: bool,
+
+    /// The first pages shown (None = none read yet)
+    pub preview: Option<Preview>,
+
+    /// Quotes screen state
+    pub quotes_screen: QuotesScreen,
+
+    /// Sentences of a book's description being picked as a quote (None = picker not open)
+    pub description_quote: Option<DescriptionQuote>,
+
+    /// Words typed into the text search prompt (kept for the next search)
+    pub text_search_input: String,
+
+    /// The hits of the last text search (None = results not shown)
+    pub text_search: Option<TextSearch>,
+
+    /// How the built-in reader lays text out (saved when changed)
+    pub typography: Typography,
+
+    /// How the built-in reader draws pictures ([reader] images; None = alt text only)
+    pub graphics: Option<Protocol>,
+}
+
+/// The share policy of a paired peer, while it's edited
+pub struct PolicyEditor {
+    /// The peer's instance id
+    pub peer_id: String,
+
+    /// The peer's name, for the title
+    pub peer: String,
+
+    /// The policy as edited so far
+    pub policy: SharePolicy,
+
+    /// Index of the selected row (0-based): whole library, ask first, then
+    /// one row per collection
+    pub selected_index: usize,
+}
+
+/// State of the Calibre library screen
+pub struct CalibreBrowser {
+    /// The books of the Calibre library
+    pub library: Library,
+
+    /// Ids of the Calibre books that seem to be in our library already
+    /// (same title and first author)
+    pub owned: BTreeSet<u64>,
+
+    /// Ids of the books marked for download
+    pub marked: BTreeSet<u64>,
+
+    /// Index of the selected book (0-based)
+    pub selected_index: usize,
+}
+
+/// State of the "New from feeds" screen
+pub struct FeedBrowser {
+    /// The entries of every feed, new ones first, then newest first
+    pub entries: Vec<FeedEntry>,
+
+    /// Ids of the entries not seen before this screen opened
+    pub new: BTreeSet<String>,
+
+    /// Ids of the entries that seem to be in our library already (same
+    /// title and first author)
+    pub owned: BTreeSet<String>,
+
+    /// Ids of the entries marked for download
+    pub marked: BTreeSet<String>,
+
+    /// Index of the selected entry (0-based)
+    pub selected_index: usize,
+}
+
+/// A book open in the built-in reader
+pub struct BookReader {
+    /// Title of the book, for the header
+    pub title: String,
+
+    /// Its file (the book's key in the library, for its bookmarks)
+    pub path: PathBuf,
+
+    /// Its text
+    pub document: Document,
+
+    /// The text wrapped to `width` columns
+    pub lines: Vec<Line>,
+
+    /// Columns the lines were wrapped to
+    pub width: usize,
+
+    /// Typography the lines were laid out with
+    pub typography: Typography,
+
+    /// How pictures are drawn (None = alt text only)
+    pub graphics: Option<Protocol>,
+
+    /// Lines shown at once
+    pub page_height: usize,
+
+    /// Index of the first line shown
+    pub top: usize,
+
+    /// Entry selected in the table of contents (None = contents not shown)
+    pub contents: Option<usize>,
+
+    /// Word selected for a dictionary lookup: index of its line, and of the
+    /// word in the line (None = no word selected)
+    pub word: Option<(usize, usize)>,
+
+    /// The selected word starts or ends a passage to highlight (rather
+    /// than being looked up)
+    pub highlighting: bool,
+
+    /// First word of the passage being highlighted: line and word, as
+    /// `word` (None = not chosen yet)
+    pub highlight_start: Option<(usize, usize)>,
+
+    /// The passage being highlighted goes to the quotes (see quotes.rs)
+    /// rather than to the book's highlights
+    pub quoting: bool,
+
+    /// What the dictionaries say of the selected word (None = not shown)
+    pub lookup: Option<Lookup>,
+
+    /// Label typed for a bookmark of the page (None = not adding one)
+    pub bookmark_label: Option<String>,
+
+    /// Bookmark selected in the bookmark list (None = list not shown)
+    pub bookmarks: Option<usize>,
+}
+
+/// Entries of the dictionaries for a word of the book
+pub struct Lookup {
+    /// The word looked up
+    pub word: String,
+
+    pub definitions: Vec<Definition>,
+
+    /// Lines of the entries scrolled past
+    pub scroll: usize,
+}
+
+/// Chapters of the library whose text matched a search
+pub struct TextSearch {
+    /// What was searched
+    pub query: String,
+
+    /// The matching chapters, best first
+    pub hits: Vec<Hit>,
+
+    /// Title of the book of each hit
+    pub titles: Vec<String>,
+
+    /// Books indexed and to index when the search ran, if the index was
+    /// being updated (books not indexed yet can't match)
+    pub indexing: Option<(usize, usize)>,
+
+    /// Index of the selected hit (0-based)
+    pub selected_index: usize,
+}
+
+/// State of the e-reader list
+pub struct DevicesScreen {
+    /// The e-readers found when the list was opened (or rescanned)
+    pub devices: Vec<Device>,
+
+    /// Index of the selected device (0-based)
+    pub selected_index: usize,
+}
+
+/// State of the transfer list screen
+pub struct TransfersScreen {
+    /// Index of the selected transfer (0-based)
+    pub selected_index: usize,
+}
+
+/// State of the peers screen
+pub struct PeersScreen {
+    /// Index of the selected peer (0-based)
+    pub selected_index: usize,
+}
+
+/// A peer's shared books, fetched when the peer was selected
+pub struct PeerBrowser {
+    /// Name of the peer, e.g. "Living room"
+    pub peer: String,
+
+    /// Where the books are downloaded from
+    pub address: SocketAddr,
+
+    /// Our pair secret with the peer (None if we didn't pair)
+    pub secret: Option<Secret>,
+
+    /// What the peer shares
+    pub catalog: Catalog,
+
+    /// Ids of the peer's books we have already (same content, or else same
+    /// file name)
+    pub owned: BTreeSet<usize>,
+
+    /// Ids of the books marked for download
+    pub marked: BTreeSet<usize>,
+
+    /// Index of the selected book (0-based)
+    pub selected_index: usize,
+}
+
+impl PeerBrowser {
+    /// The selected book of the peer
+    pub fn selected(&self) -> Option<&CatalogEntry> {
+        self.catalog.books.get(self.selected_index)
+    }
+}
+
+/// A library merge shown for review, with a choice for each conflict
+pub struct LibraryMerge {
+    /// The merged file, for messages
+    pub source: String,
+
+    /// What the merge changes
+    pub plan: MergePlan,
+
+    /// Index of the selected conflict, counted over all books (0-based)
+    pub selected_index: usize,
+
+    /// The rest of the sync, when the merge is one side of a two-way sync
+    pub sync: Option<PendingSync>,
+}
+
+impl BookReader {
+    /// Opens a book at its beginning, laid out for the widest column until
+    /// the first fit()
+    pub fn new(title: String, path: PathBuf, document: Document, typography: Typography, graphics: Option<Protocol>) -> Self {
+        Self {
+            title,
+            path,
+            lines: document.layout(typography.width, &typography, graphics),
+            width: typography.width,
+            typography,
+            graphics,
+            document,
+            page_height: 1,
+            top: 0,
+            contents: None,
+            word: None,
+            highlighting: false,
+            highlight_start: None,
+            quoting: false,
+            lookup: None,
+            bookmark_label: None,
+            bookmarks: None,
+        }
+    }
+
+    /// Lays the text out for a terminal of the given size and a
+    /// typography, keeping the paragraph at the top of the page there
+    ///
+    /// The text gets the whole terminal but a header and a footer line,
+    /// in a column as wide as the typography allows between its margins.
+    pub fn fit(&mut self, columns: u16, rows: u16, typography: Typography) {
+        self.page_height = (rows as usize).saturating_sub(2).max(1);
+        let width = typography.column(columns as usize);
+        if width != self.width || typography != self.typography {
+            // Lines change: the selected words would be others
+            self.stop_selecting();
+            let anchor = self.lines.get(self.top).map(|line| (line.chapter, line.block));
+            self.lines = self.document.layout(width, &typography, self.graphics);
+            self.width = width;
+            self.typography = typography;
+            self.top = anchor
+                .and_then(|anchor| self.lines.iter().position(|line| (line.chapter, line.block) >= anchor))
+                .unwrap_or(0);
+        }
+        self.scroll(0);
+    }
+
+    /// Moves the page by a number of lines (negative = back), without
+    /// going past the beginning or the end of the book
+    pub fn scroll(&mut self, lines: isize) {
+        let last = self.lines.len().saturating_sub(self.page_height);
+        self.top = self.top.saturating_add_signed(lines).min(last);
+    }
+
+    /// Selects the first word of the page for a dictionary lookup
+    pub fn select_word(&mut self) {
+        let page = self.top..(self.top + self.page_height).min(self.lines.len());
+        self.word = page
+            .into_iter()
+            .find(|&line| !self.lines[line].words().is_empty())
+            .map(|line| (line, 0));
+    }
+
+    /// Selects the next word (or the previous one), going to the next (or
+    /// previous) line at the end of a line
+    pub fn move_word(&mut self, forward: bool) {
+        let Some((line, word)) = self.word else {
+            return;
+        };
+        let count = self.lines[line].words().len();
+        self.word = if forward && word + 1 < count {
+            Some((line, word + 1))
+        } else if !forward && word > 0 {
+            Some((line, word - 1))
+        } else if forward {
+            ((line + 1)..self.lines.len())
+                .find(|&next| !self.lines[next].words().is_empty())
+                .map(|next| (next, 0))
+        } else {
+            (0..line)
+                .rev()
+                .find_map(|previous| Some((previous, self.lines[previous].words().len().checked_sub(1)?)))
+        }
+        .or(self.word);
+        self.show_word();
+    }
+
+    /// Selects the word below (or above) the selected one: the nearest
+    /// word of the next line that has words
+    pub fn move_word_line(&mut self, down: bool) {
+        let Some((line, word)) = self.word else {
+            return;
+        };
+        let column = self.lines[line].words()[word].start;
+        let mut others: Box<dyn Iterator<Item = usize>> = if down {
+            Box::new((line + 1)..self.lines.len())
+        } else {
+            Box::new((0..line).rev())
+        };
+        if let Some(other) = others.find(|&other| !self.lines[other].words().is_empty()) {
+            let words = self.lines[other].words();
+            let nearest = (0..words.len()).min_by_key(|&w| words[w].start.abs_diff(column)).unwrap_or(0);
+            self.word = Some((other, nearest));
+        }
+        self.show_word();
+    }
+
+    /// The selected word
+    pub fn selected_word(&self) -> Option<String> {
+        let (line, word) = self.word?;
+        let line = self.lines.get(line)?;
+        let range = line.words().get(word)?.clone();
+        Some(line.spans.iter().flat_map(|span| span.text.chars()).skip(range.start).take(range.len()).collect())
+    }
+
+    /// Characters of a line that are selected: the selected word, or the
+    /// part of the line in the passage being highlighted
+    pub fn selected_range(&self, line: usize) -> Option<Range<usize>> {
+        let (last_line, last_word) = self.word?;
+        let words = |line: usize| self.lines[line].words();
+        let Some((first_line, first_word)) = self.highlight_start else {
+            return (line == last_line).then(|| words(line).get(last_word).cloned()).flatten();
+        };
+
+        // The passage, whichever way it was selected
+        let ((from_line, from_word), (to_line, to_word)) = if (first_line, first_word) <= (last_line, last_word) {
+            ((first_line, first_word), (last_line, last_word))
+        } else {
+            ((last_line, last_word), (first_line, first_word))
+        };
+        if line < from_line || line > to_line {
+            return None;
+        }
+        let start = if line == from_line { words(line).get(from_word)?.start } else { 0 };
+        let end = if line == to_line {
+            words(line).get(to_word)?.end
+        } else {
+            self.lines[line].spans.iter().map(|span| span.text.chars().count()).sum()
+        };
+        Some(start..end)
+    }
+
+    /// Text of the passage being highlighted: its lines joined with
+    /// spaces, its paragraphs with newlines
+    pub fn selected_text(&self) -> String {
+        let (Some((first, _)), Some((last, _))) = (self.highlight_start, self.word) else {
+            return String::new();
+        };
+        let mut text = String::new();
+        let mut block = None;
+        for line in first.min(last)..=first.max(last) {
+            let Some(range) = self.selected_range(line) else {
+                continue;
+            };
+            let spans = &self.lines[line].spans;
+            let part: String = spans.iter().flat_map(|span| span.text.chars()).skip(range.start).take(range.len()).collect();
+            let part = part.trim();
+            if part.is_empty() {
+                continue;
+            }
+            let this_block = (self.lines[line].chapter, self.lines[line].block);
+            match block {
+                None => {}
+                Some(previous) if previous == this_block => text.push(' '),
+                Some(_) => text.push('\n'),
+            }
+            text.push_str(part);
+            block = Some(this_block);
+        }
+        text
+    }
+
+    /// Stops selecting words (for a lookup or a highlight)
+    pub fn stop_selecting(&mut self) {
+        self.word = None;
+        self.highlighting = false;
+        self.highlight_start = None;
+        self.quoting = false;
+    }
+
+    /// Scrolls so the selected word is on the page
+    fn show_word(&mut self) {
+        if let Some((line, _)) = self.word {
+            if line < self.top {
+                self.top = line;
+            } else if line >= self.top + self.page_height {
+                self.top = line + 1 - self.page_height;
+            }
+        }
+    }
+
+    /// Index of the first line of a paragraph
+    fn line_at(&self, chapter: usize, block: usize) -> usize {
+        self.lines.partition_point(|line| (line.chapter, line.block) < (chapter, block))
+    }
+
+    /// Index of the line an entry of the table of contents points to
+    fn line_of(&self, entry: usize) -> usize {
+        let entry = &self.document.contents[entry];
+        self.line_at(entry.chapter, entry.block)
+    }
+
+    /// Chapter and paragraph at the top of the page (what a bookmark keeps)
+    pub fn position(&self) -> (usize, usize) {
+        self.lines.get(self.top).map_or((0, 0), |line| (line.chapter, line.block))
+    }
+
+    /// Shows a paragraph at the top of the page (or as near as the end of
+    /// the book allows)
+    pub fn go_to(&mut self, chapter: usize, block: usize) {
+        self.top = self.line_at(chapter, block);
+        self.scroll(0);
+    }
+
+    /// Entry of the table of contents a paragraph is in (None = before the first)
+    pub fn entry_at(&self, chapter: usize, block: usize) -> Option<&TocEntry> {
+        self.document
+            .contents
+            .iter()
+            .rev()
+            .find(|entry| (entry.chapter, entry.block) <= (chapter, block))
+    }
+
+    /// Entry of the table of contents the page is in (None = before the first)
+    pub fn current_entry(&self) -> Option<usize> {
+        (0..self.document.contents.len()).rev().find(|&entry| self.line_of(entry) <= self.top)
+    }
+
+    /// Shows the beginning of an entry of the table of contents
+    pub fn jump_to(&mut self, entry: usize) {
+        if entry < self.document.contents.len() {
+            self.top = self.line_of(entry);
+            self.scroll(0);
+        }
+    }
+
+    /// Shows the first paragraph of a chapter with one of some words in it
+    /// (the chapter's beginning if none has)
+    ///
+    /// # Arguments
+    /// * `chapter` - Index of the chapter
+    /// * `words` - The words, folded (fulltext::query_words)
+    pub fn show_words(&mut self, chapter: usize, words: &[String]) {
+        let Some(found) = self.document.chapters.get(chapter) else {
+            return;
+        };
+        let block = found
+            .blocks
+            .iter()
+            .position(|block| {
+                let text = crate::fold::fold(&block.text());
+                words.iter().any(|word| text.contains(word.as_str()))
+            })
+            .unwrap_or(0);
+        self.go_to(chapter, block);
+    }
+
+    /// Goes to the next entry of the table of contents
+    pub fn next_chapter(&mut self) {
+        let next = (0..self.document.contents.len()).find(|&entry| self.line_of(entry) > self.top);
+        if let Some(entry) = next {
+            self.jump_to(entry);
+        }
+    }
+
+    /// Goes to the beginning of the entry of the table of contents the page
+    /// is in, or to the previous entry when already there
+    pub fn previous_chapter(&mut self) {
+        let previous = (0..self.document.contents.len()).rev().find(|&entry| self.line_of(entry) < self.top);
+        match previous {
+            Some(entry) => self.jump_to(entry),
+            None => self.top = 0,
+        }
+    }
+
+    /// Title of the entry of the table of contents shown, else the first
+    /// heading of the chapter shown
+    pub fn chapter_title(&self) -> Option<&str> {
+        if let Some(entry) = self.current_entry() {
+            return Some(&self.document.contents[entry].title);
+        }
+        let line = self.lines.get(self.top)?;
+        self.document.chapters.get(line.chapter)?.title.as_deref()
+    }
+
+    /// How far into the book the end of the page is, in percent
+    pub fn progress(&self) -> usize {
+        match self.lines.len() {
+            0 => 0,
+            total => ((self.top + self.page_height).min(total) * 100) / total,
+        }
+    }
+}
+
+impl LibraryMerge {
+    /// All conflicts with the title of their book, in display order
+    pub fn conflicts(&self) -> impl Iterator<Item = (&str, &Conflict)> {
+        self.plan
+            .updates
+            .iter()
+            .flat_map(|update| update.conflicts.iter().map(move |c| (update.title.as_str(), c)))
+    }
+
+    /// The book of the selected conflict, with the position of that
+    /// conflict among the book's conflicts
+    pub fn selected_update(&self) -> Option<(&Update, usize)> {
+        let mut first = 0;
+        for update in &self.plan.updates {
+            if self.selected_index < first + update.conflicts.len() {
+                return Some((update, self.selected_index - first));
+            }
+            first += update.conflicts.len();
+        }
+        None
+    }
+
+    /// The selected conflict, to change its choice
+    pub fn selected_mut(&mut self) -> Option<&mut Conflict> {
+        self.plan
+            .updates
+            .iter_mut()
+            .flat_map(|update| update.conflicts.iter_mut())
+            .nth(self.selected_index)
+    }
+
+    /// Chooses the same side for every conflict
+    pub fn choose_all(&mut self, take_theirs: bool) {
+        for update in &mut self.plan.updates {
+            for conflict in &mut update.conflicts {
+                conflict.take_theirs = take_theirs;
+            }
+        }
+    }
+}
+
+/// Planned file moves, shown for review before anything is touched
+pub struct MoveReview {
+    /// What the moves are for, e.g. "Organize library"
+    pub title: String,
+
+    /// The planned moves (with the reason when one will be skipped)
+    pub moves: Vec<Move>,
+
+    /// Index of the selected move (0-based)
+    pub selected_index: usize,
+}
+
+/// State of the health report screen
+pub struct HealthScreen {
+    /// Books with a problem, found when the screen opened (or after a rescan)
+    pub issues: Vec<Issue>,
+
+    /// Index of the selected issue (0-based)
+    pub selected_index: usize,
+
+    /// New path being typed to relocate the selected book (None = not relocating)
+    pub relocate_input: Option<String>,
+}
+
+impl HealthScreen {
+    /// Creates an empty report (the check runs when the screen opens)
+    pub fn new() -> Self {
+        Self {
+            issues: Vec::new(),
+            selected_index: 0,
+            relocate_input: None,
+        }
+    }
+
+    /// Removes the selected issue, keeping the cursor in range
+    pub fn resolve_selected(&mut self) {
+        if self.selected_index < self.issues.len() {
+            self.issues.remove(self.selected_index);
+        }
+        if self.selected_index >= self.issues.len() {
+            self.selected_index = self.issues.len().saturating_sub(1);
+        }
+    }
+}
+
+/// The fonts embedded in a book
+pub struct FontList {
+    /// The book
+    pub path: PathBuf,
+
+    /// Its fonts, or why they can't be read
+    pub fonts: Result<Vec<Font>, String>,
+}
+
+/// The narration of a book, playing
+pub struct Listening {
+    pub player: Player,
+
+    /// What the player said last (None = not asked yet)
+    pub status: Option<Status>,
+
+    /// When it was asked (None = to ask at once)
+    polled: Option<Instant>,
+}
+
+/// State of the suspected duplicates screen
+#[derive(Default)]
+pub struct DuplicatesScreen {
+    /// Pairs of books that look like the same work, found when the screen opened
+    pub pairs: Vec<Pair>,
+
+    /// Index of the selected pair (0-based)
+    pub selected_index: usize,
+
+    /// The selected pair side by side (None = the list is shown)
+    pub comparison: Option<Comparison>,
+
+    /// First row of the comparison shown
+    pub scroll: usize,
+}
+
+/// The first pages of the selected book, shown in the details pane
+pub struct Preview {
+    /// File of the book they're from
+    pub path: PathBuf,
+
+    /// Its first blocks, up to PREVIEW_CHARS characters (or why there are none)
+    pub blocks: Result<Vec<Block>, String>,
+}
+
+/// State of the quotes screen
+#[derive(Default)]
+pub struct QuotesScreen {
+    /// The quotes, read from their file when the screen opens
+    pub quotes: Vec<Quote>,
+
+    /// Index of the selected quote (0-based)
+    pub selected_index: usize,
+}
+
+/// A passage of a book's description being picked, sentence by sentence
+pub struct DescriptionQuote {
+    /// Title of the book
+    pub title: String,
+
+    pub authors: Vec<String>,
+
+    /// The sentences of the description
+    pub sentences: Vec<String>,
+
+    /// Index of the selected sentence (0-based)
+    pub selected_index: usize,
+
+    /// First sentence of the passage (None = not chosen yet)
+    pub start: Option<usize>,
+}
+
+impl DescriptionQuote {
+    /// The passage from the first sentence chosen to the selected one
+    /// (whichever comes first), or the selected sentence alone
+    pub fn passage(&self) -> std::ops::RangeInclusive<usize> {
+        let start = self.start.unwrap_or(self.selected_index);
+        start.min(self.selected_index)..=start.max(self.selected_index)
+    }
+}
+
+/// Metadata changes proposed by an online provider, shown for review
+pub struct MetadataReview {
+    /// Path of the book that was looked up
+    pub path: PathBuf,
+
+    /// Name of the provider, e.g. "Open Library"
+    pub source: &'static str,
+
+    /// Everything the provider found
+    pub proposed: Metadata,
+
+    /// Differences with the book's metadata (each can be unticked)
+    pub changes: Vec<Change>,
+
+    /// Index of the selected change (0-based)
+    pub selected_index: usize,
+}
+
+/// State of the bulk edit form
+///
+/// Empty fields leave the books alone, so only what was typed is changed.
+pub struct BulkEdit {
+    /// Field the cursor is on
+    pub field: BulkField,
+
+    /// Comma-separated tags to add
+    pub add_tags: String,
+
+    /// New reading status (None = leave alone, Some(None) = clear the status)
+    pub status: Option<Option<ReadingStatus>>,
+
+    /// Authors separated by ';' (the names may contain commas)
+    pub authors: String,
+
+    /// Series name ("-" takes the books out of their series)
+    pub series: String,
+}
+
+/// Fields of the bulk edit form, top to bottom
+#[derive(PartialEq, Clone, Copy)]
+pub enum BulkField {
+    /// Tags to add
+    AddTags,
+
+    /// Reading status to set
+    Status,
+
+    /// Authors to set
+    Authors,
+
+    /// Series to assign
+    Series,
+}
+
+impl BulkField {
+    /// All fields in form order
+    pub const ALL: [BulkField; 4] = [
+        BulkField::AddTags,
+        BulkField::Status,
+        BulkField::Authors,
+        BulkField::Series,
+    ];
+
+    /// Label shown in front of the field
+    pub fn label(self) -> &'static str {
+        match self {
+            BulkField::AddTags => "Add tags",
+            BulkField::Status => "Status",
+            BulkField::Authors => "Authors",
+            BulkField::Series => "Series",
+        }
+    }
+}
+
+impl BulkEdit {
+    /// Creates an empty form (changes nothing)
+    pub fn new() -> Self {
+        Self {
+            field: BulkField::AddTags,
+            add_tags: String::new(),
+            status: None,
+            authors: String::new(),
+            series: String::new(),
+        }
+    }
+
+    /// Moves the cursor to the next (or previous) field, wrapping around
+    pub fn move_field(&mut self, forward: bool) {
+        let count = BulkField::ALL.len();
+        let current = BulkField::ALL.iter().position(|&f| f == self.field).unwrap_or(0);
+        let next = if forward { current + 1 } else { current + count - 1 };
+        self.field = BulkField::ALL[next % count];
+    }
+
+    /// Cycles the status choice: leave alone -> none -> to read -> ... -> abandoned
+    pub fn cycle_status(&mut self) {
+        self.status = match self.status {
+            None => Some(None),
+            Some(Some(ReadingStatus::Abandoned)) => None,
+            Some(status) => Some(ReadingStatus::cycle(status)),
+        };
+    }
+
+    /// The text field the cursor is on (None for the status field)
+    pub fn input_mut(&mut self) -> Option<&mut String> {
+        match self.field {
+            BulkField::AddTags => Some(&mut self.add_tags),
+            BulkField::Authors => Some(&mut self.authors),
+            BulkField::Series => Some(&mut self.series),
+            BulkField::Status => None,
+        }
+    }
+
+    /// Applies the form to one book
+    ///
+    /// # Returns
+    /// Whether the book changed
+    fn apply(&self, book: &mut Book) -> bool {
+        let before = (book.user.clone(), book.meta.series.clone());
+
+        for tag in self.add_tags.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            if !book.user.tags.iter().any(|t| t == tag) {
+                book.user.tags.push(tag.to_string());
+            }
+        }
+        if let Some(status) = self.status {
+            book.user.status = status;
+        }
+        let authors: Vec<String> = self
+            .authors
+            .split(';')
+            .map(str::trim)
+            .filter(|a| !a.is_empty())
+            .map(String::from)
+            .collect();
+        if !authors.is_empty() {
+            book.user.authors = authors;
+        }
+        match self.series.trim() {
+            "" => {}
+            "-" => {
+                book.meta.series = None;
+                book.meta.series_index = None;
+            }
+            series => book.meta.series = Some(series.to_string()),
+        }
+
+        (book.user.clone(), book.meta.series.clone()) != before
+    }
+}
+
+/// State of the metadata form of one book
+///
+/// The fields start with what the book shows; only valid values are saved.
+pub struct MetadataForm {
+    /// The book
+    pub path: PathBuf,
+
+    /// Field the cursor is on
+    pub field: MetadataField,
+
+    pub title: String,
+
+    /// Authors separated by ';' (the names may contain commas)
+    pub authors: String,
+
+    /// Series name (empty = no series)
+    pub series: String,
+
+    /// Number in the series, e.g. "2" or "1.5"
+    pub series_index: String,
+
+    /// Comma-separated tags
+    pub tags: String,
+
+    /// Language code, e.g. "en" or "pt-BR" (empty = the declared or detected one)
+    pub language: String,
+
+    /// What's wrong with the field the cursor was moved to (None = nothing)
+    pub error: Option<String>,
+}
+
+/// Fields of the metadata form, top to bottom
+#[derive(PartialEq, Clone, Copy)]
+pub enum MetadataField {
+    Title,
+    Authors,
+    Series,
+    SeriesIndex,
+    Tags,
+    Language,
+}
+
+impl MetadataField {
+    /// All fields in form order
+    pub const ALL: [MetadataField; 6] = [
+        MetadataField::Title,
+        MetadataField::Authors,
+        MetadataField::Series,
+        MetadataField::SeriesIndex,
+        MetadataField::Tags,
+        MetadataField::Language,
+    ];
+
+    /// Label shown in front of the field
+    pub fn label(self) -> &'static str {
+        match self {
+            MetadataField::Title => "Title",
+            MetadataField::Authors => "Authors",
+            MetadataField::Series => "Series",
+            MetadataField::SeriesIndex => "Number",
+            MetadataField::Tags => "Tags",
+            MetadataField::Language => "Language",
+        }
+    }
+}
+
+/// What a valid metadata form sets
+struct MetadataValues {
+    title: String,
+    authors: Vec<String>,
+    series: Option<String>,
+    series_index: Option<f32>,
+    tags: Vec<String>,
+    language: Option<String>,
+}
+
+impl MetadataForm {
+    /// Creates the form of a book, filled with what the book shows
+    pub fn new(book: &Book) -> Self {
+        Self {
+            path: book.path.clone(),
+            field: MetadataField::Title,
+            title: book.meta.title.clone().unwrap_or_default(),
+            authors: book.authors().join("; "),
+            series: book.meta.series.clone().unwrap_or_default(),
+            series_index: book.meta.series_index.map(|index| index.to_string()).unwrap_or_default(),
+            tags: book.user.tags.join(", "),
+            language: book.language().unwrap_or_default().to_string(),
+            error: None,
+        }
+    }
+
+    /// Moves the cursor to the next (or previous) field, wrapping around
+    pub fn move_field(&mut self, forward: bool) {
+        let count = MetadataField::ALL.len();
+        let current = MetadataField::ALL.iter().position(|&f| f == self.field).unwrap_or(0);
+        let next = if forward { current + 1 } else { current + count - 1 };
+        self.field = MetadataField::ALL[next % count];
+    }
+
+    /// The text field the cursor is on
+    pub fn input_mut(&mut self) -> &mut String {
+        match self.field {
+            MetadataField::Title => &mut self.title,
+            MetadataField::Authors => &mut self.authors,
+            MetadataField::Series => &mut self.series,
+            MetadataField::SeriesIndex => &mut self.series_index,
+            MetadataField::Tags => &mut self.tags,
+            MetadataField::Language => &mut self.language,
+        }
+    }
+
+    /// Checks every field
+    ///
+    /// # Returns
+    /// What the form sets, or the first invalid field and what's wrong with it
+    fn validate(&self) -> Result<MetadataValues, (MetadataField, String)> {
+        let title = self.title.trim();
+        if title.is_empty() {
+            return Err((MetadataField::Title, "A book needs a title".to_string()));
+        }
+
+        let authors: Vec<String> = self
+            .authors
+            .split(';')
+            .map(str::trim)
+            .filter(|a| !a.is_empty())
+            .map(String::from)
+            .collect();
+        if authors.is_empty() {
+            return Err((MetadataField::Authors, "A book needs an author (several are separated by ';')".to_string()));
+        }
+
+        let series = Some(self.series.trim()).filter(|s| !s.is_empty()).map(String::from);
+        let series_index = match self.series_index.trim() {
+            "" => None,
+            _ if series.is_none() => {
+                return Err((MetadataField::SeriesIndex, "A number needs a series".to_string()));
+            }
+            number => match number.parse::<f32>() {
+                Ok(index) if index >= 0.0 && index.is_finite() => Some(index),
+                _ => return Err((MetadataField::SeriesIndex, format!("'{}' is not a number in a series", number))),
+            },
+        };
+
+        let mut tags: Vec<String> = Vec::new();
+        for tag in self.tags.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            if !tags.iter().any(|t| t == tag) {
+                tags.push(tag.to_string());
+            }
+        }
+
+        let language = match self.language.trim() {
+            "" => None,
+            // An English name ("German") is written as its code
+            name if !name.contains('-') && crate::language::is_known(name) => Some(crate::language::primary(name)),
+            code if is_language_tag(code) => Some(code.to_string()),
+            other => {
+                return Err((MetadataField::Language, format!("'{}' is not a language code (e.g. en, pt-BR)", other)));
+            }
+        };
+
+        Ok(MetadataValues {
+            title: title.to_string(),
+            authors,
+            series,
+            series_index,
+            tags,
+            language,
+        })
+    }
+
+    /// Puts the form's values into a book (call validate first)
+    fn apply(values: MetadataValues, book: &mut Book) {
+        book.meta.title = Some(values.title);
+        if values.authors != book.authors() {
+            // As merging spellings does: the file's own authors are kept
+            book.user.authors = if values.authors == book.meta.authors { Vec::new() } else { values.authors };
+        }
+        book.meta.series = values.series;
+        book.meta.series_index = values.series_index;
+        book.user.tags = values.tags;
+        if values.language.as_deref() != book.language() {
+            book.user.language = values.language;
+        }
+    }
+}
+
+/// Whether a text has the shape of a BCP 47 language tag: a code of two or
+/// three letters, then subtags of up to eight letters or digits ("pt-BR",
+/// "zh-Hant")
+fn is_language_tag(text: &str) -> bool {
+    let mut subtags = text.split('-');
+    let code = subtags.next().unwrap_or_default();
+    (2..=3).contains(&code.len())
+        && code.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// State of the author merge screen
+pub struct AuthorScreen {
+    /// Authors with several spellings, found when the screen opened
+    pub groups: Vec<AuthorGroup>,
+
+    /// Index of the selected group (0-based)
+    pub selected_index: usize,
+
+    /// Index of the spelling chosen as canonical in the selected group
+    pub choice: usize,
+}
+
+/// State of the collection picker popup
+pub struct CollectionPicker {
+    /// What picking a collection does
+    pub purpose: CollectionPurpose,
+
+    /// Index of the selected entry (0-based)
+    pub selected_index: usize,
+
+    /// Name being typed for a new collection (None = not creating one)
+    pub input: Option<String>,
+
+    /// Whether the name being typed is for a smart collection
+    /// (saving the current filter and sort order)
+    pub input_smart: bool,
+}
+
+/// Why the collection picker was opened
+#[derive(PartialEq, Clone, Copy)]
+pub enum CollectionPurpose {
+    /// Choose which collection the book list shows
+    /// ("All books" is the first entry, smart collections come last)
+    Show,
+
+    /// Choose a collection to add the selected book to
+    AddBook,
+}
+
+/// State of the folder settings screen
+pub struct FolderScreen {
+    /// Scan roots of the open profile with their settings (edited in place)
+    pub folders: Vec<(PathBuf, FolderSettings)>,
+
+    /// Index of the selected folder (0-based)
+    pub selected_index: usize,
+
+    /// Field being edited with the text input, if any
+    pub editing: Option<FolderField>,
+
+    /// Text typed into the input line
+    pub input: String,
+
+    /// Whether anything changed since the screen was opened
+    pub dirty: bool,
+}
+
+/// Text fields of a folder that are edited through the input line
+#[derive(PartialEq, Clone, Copy)]
+pub enum FolderField {
+    /// Comma-separated exclude patterns
+    Exclude,
+
+    /// Comma-separated default tags
+    DefaultTags,
+}
+
+/// List of library profiles shown in the "switch library" popup
+pub struct ProfilePicker {
+    /// Names of all known profiles
+    pub names: Vec<String>,
+
+    /// Index of the currently selected profile (0-based)
+    pub selected_index: usize,
 }
 
 /// Simple file browser for navigating directories
@@ -64,6 +1413,115 @@ pub enum UiMode {
 
     /// Adding folder mode: showing file browser popup
     AddingFolder,
+
+    /// Picking profile mode: showing the library profile picker popup
+    PickingProfile,
+
+    /// Viewing log mode: showing recent log messages
+    ViewingLog,
+
+    /// Folder settings mode: editing the settings of the scan roots
+    FolderSettings,
+
+    /// Picking collection mode: choosing a collection to show or add a book to
+    PickingCollection,
+
+    /// Filtering mode: typing a filter expression at the '/' prompt
+    Filtering,
+
+    /// Merging authors mode: reviewing proposed author merges
+    MergingAuthors,
+
+    /// Bulk editing mode: a form applied to all marked books
+    BulkEditing,
+
+    /// Editing metadata mode: the metadata form of the selected book
+    EditingMetadata,
+
+    /// Reviewing metadata mode: accepting changes found online
+    ReviewingMetadata,
+
+    /// Health report mode: books with missing or broken files
+    HealthReport,
+
+    /// Statistics mode: the library's totals
+    Statistics,
+
+    /// Duplicates mode: books that look like the same work, compared side by side
+    Duplicates,
+
+    /// Reviewing moves mode: old -> new paths before files are moved
+    ReviewingMoves,
+
+    /// Merging library mode: choosing between conflicting user data of
+    /// another library
+    MergingLibrary,
+
+    /// Peers mode: other FunkHunt instances on the network
+    Peers,
+
+    /// Browsing peer mode: the books shared by a peer
+    BrowsingPeer,
+
+    /// Reviewing offer mode: accepting or declining books sent by a peer
+    ReviewingOffer,
+
+    /// Pairing mode: comparing the PIN of a pairing with the other screen
+    Pairing,
+
+    /// Editing policy mode: what a paired peer may get from the library
+    EditingPolicy,
+
+    /// Approving access mode: a paired peer asks to download a book
+    ApprovingAccess,
+
+    /// Sharing mode: the QR code of a shared book's URL
+    Sharing,
+
+    /// Devices mode: e-readers plugged in over USB
+    Devices,
+
+    /// Emailing mode: typing the address books are sent to
+    Emailing,
+
+    /// Converting mode: choosing the format to convert books to
+    Converting,
+
+    /// Setting cover mode: typing where the selected book's new cover comes from
+    SettingCover,
+
+    /// Fonts mode: the fonts embedded in the selected book, to strip or subset
+    Fonts,
+
+    /// Joining mode: typing the title of the anthology the marked books are merged into
+    Joining,
+
+    /// Browsing Calibre mode: the books of a Calibre content server
+    BrowsingCalibre,
+
+    /// Browsing feeds mode: the new books of the subscribed feeds
+    BrowsingFeeds,
+
+    /// Reading mode: a book open in the built-in reader
+    Reading,
+
+    /// Listening mode: the controls of the narration playing
+    Listening,
+
+    /// Searching text mode: typing the words to find in the books' text
+    SearchingText,
+
+    /// Text search results mode: the chapters that matched
+    TextSearchResults,
+
+    /// Transfers mode: downloads and uploads with their progress
+    Transfers,
+
+    /// Quotes mode: the passages captured from books
+    Quotes,
+
+    /// Quoting description mode: picking sentences of a book's description as a quote
+    QuotingDescription,
 }
 
 /// Actions that the UI can request the main loop to perform
@@ -72,6 +1530,126 @@ pub enum UiMode {
 pub enum AppAction {
     /// User selected a folder to add - main loop should scan it for books
     AddFolder(PathBuf),
+
+    /// User picked another library profile - main loop should save the
+    /// current library and open the named one
+    SwitchProfile(String),
+
+    /// User changed folder settings - main loop should save them and
+    /// rescan the folders so excludes and default tags take effect
+    SaveFolderSettings(Vec<(PathBuf, FolderSettings)>),
+
+    /// Collections or smart collections were created or deleted - main
+    /// loop should store the new lists in the profile settings
+    SaveCollections(Vec<String>, Vec<SmartCollection>),
+
+    /// Look the selected book up online - main loop does the request
+    FetchMetadata,
+
+    /// Rescan every folder of the library (from the health report)
+    RescanLibrary,
+
+    /// Search the folders for moved files by content hash (from the health report)
+    FindMovedBooks,
+
+    /// Files were moved (or an operation undone) - main loop should save
+    /// the library right away
+    SaveNow,
+
+    /// Author spellings were merged - main loop should remember the
+    /// aliases (spelling -> canonical name) in the profile settings
+    SaveAuthorAliases(BTreeMap<String, String>),
+
+    /// Fetch the catalog of a peer and show it
+    BrowsePeer(Peer),
+
+    /// Forget a remembered peer (by its id)
+    ForgetPeer(String),
+
+    /// Ask a peer to pair (main loop shows the PIN to compare)
+    PairWithPeer(Peer),
+
+    /// The PINs of a pairing matched - main loop should add the peer to
+    /// the trusted peers
+    TrustPeer(Pairing),
+
+    /// Stop trusting a paired peer (by its instance id)
+    RevokePeer(String),
+
+    /// Store the share policy of a paired peer (by its instance id)
+    SetSharePolicy(String, SharePolicy),
+
+    /// Let a peer download a book, or not
+    AnswerAccess(AccessRequest, bool),
+
+    /// Offer the marked books (or the selected one) to a peer
+    SendToPeer(Peer),
+
+    /// Queue the downloads of books of the peer being browsed
+    DownloadFromPeer(Vec<CatalogEntry>),
+
+    /// Queue the downloads of the books of an accepted offer
+    AcceptOffer(IncomingOffer),
+
+    /// Queue the download of a book of a remote folder (by its path) and
+    /// open the copy once it's done
+    OpenRemote(PathBuf),
+
+    /// Sync the library with a peer's (two-way)
+    SyncWithPeer(Peer),
+
+    /// Serve the selected book on a temporary URL and show it as a QR code
+    ShareBook,
+
+    /// Copy the marked books (or the selected one) to an e-reader
+    SendToDevice(Device),
+
+    /// Email the marked books (or the selected one) to an address
+    EmailBooks(String),
+
+    /// Convert the marked books (or the selected one) to a format
+    ConvertBooks(Format),
+
+    /// Make the marked books (or the selected one) smaller
+    OptimizeBooks,
+
+    /// Embed a new cover into the selected book (image file, URL, or "" for
+    /// Open Library's cover of its ISBN)
+    SetCover(String),
+
+    /// Strip or subset the fonts of the listed book (see fonts.rs)
+    SlimFonts(FontAction),
+
+    /// Play the narration of the selected book (see audio.rs)
+    Listen,
+
+    /// Split the selected book into a book per top-level entry of its contents
+    SplitBook,
+
+    /// Merge the marked books into an anthology with this title
+    JoinBooks(String),
+
+    /// Search the text of the books (full-text index) and show the hits
+    SearchText(String),
+
+    /// Fetch the book list of the Calibre server and show it
+    BrowseCalibre,
+
+    /// Download books of the Calibre server into the library
+    DownloadFromCalibre(Vec<CalibreBook>),
+
+    /// Add the marked books (or the selected one) to the Calibre library
+    PushToCalibre,
+
+    /// Fetch the subscribed feeds and show their entries
+    BrowseFeeds,
+
+    /// Download the EPUBs of feed entries into the library
+    DownloadFromFeeds(Vec<FeedEntry>),
+
+    /// Our side of a sync was applied - main loop copies the books and
+    /// sends the settled user data to the partner
+    FinishSync(PendingSync),
 }
 
 impl FileBrowser {
@@ -191,24 +1769,493 @@ impl FileBrowser {
     }
 }
 
+impl ProfilePicker {
+    /// Creates an empty profile picker (names are loaded when the popup opens)
+    pub fn new() -> Self {
+        Self {
+            names: Vec::new(),
+            selected_index: 0,
+        }
+    }
+
+    /// Reloads the list of profiles and pre-selects the current one
+    ///
+    /// # Arguments
+    /// * `current` - Name of the profile currently open
+    pub fn load(&mut self, current: &str) {
+        self.names = crate::profile::list_profiles();
+
+        // Start with the cursor on the open profile (or the first one)
+        self.selected_index = self
+            .names
+            .iter()
+            .position(|name| name == current)
+            .unwrap_or(0);
+    }
+
+    /// Moves the selection cursor up by one entry
+    pub fn move_up(&mut self) {
+        if self.selected_index > 0 {
+            self.selected_index -= 1;
+        }
+    }
+
+    /// Moves the selection cursor down by one entry
+    pub fn move_down(&mut self) {
+        if self.selected_index < self.names.len().saturating_sub(1) {
+            self.selected_index += 1;
+        }
+    }
+
+    /// Gets the name of the selected profile (if any)
+    pub fn selected_name(&self) -> Option<&String> {
+        self.names.get(self.selected_index)
+    }
+}
+
+impl AuthorScreen {
+    /// Creates an empty author screen (groups are found when it opens)
+    pub fn new() -> Self {
+        Self {
+            groups: Vec::new(),
+            selected_index: 0,
+            choice: 0,
+        }
+    }
+}
+
+impl CollectionPicker {
+    /// Creates a closed collection picker
+    pub fn new() -> Self {
+        Self {
+            purpose: CollectionPurpose::Show,
+            selected_index: 0,
+            input: None,
+            input_smart: false,
+        }
+    }
+}
+
+impl FolderScreen {
+    /// Creates an empty folder screen (folders are filled in by the main loop)
+    pub fn new() -> Self {
+        Self {
+            folders: Vec::new(),
+            selected_index: 0,
+            editing: None,
+            input: String::new(),
+            dirty: false,
+        }
+    }
+
+    /// Moves the selection cursor up by one folder
+    pub fn move_up(&mut self) {
+        if self.selected_index > 0 {
+            self.selected_index -= 1;
+        }
+    }
+
+    /// Moves the selection cursor down by one folder
+    pub fn move_down(&mut self) {
+        if self.selected_index < self.folders.len().saturating_sub(1) {
+            self.selected_index += 1;
+        }
+    }
+
+    /// Gets the settings of the selected folder for editing (if any)
+    pub fn selected_mut(&mut self) -> Option<&mut FolderSettings> {
+        self.folders
+            .get_mut(self.selected_index)
+            .map(|(_, settings)| settings)
+    }
+
+    /// Starts editing a text field, pre-filling the input with its current value
+    pub fn start_editing(&mut self, field: FolderField) {
+        let Some(settings) = self.selected_mut() else {
+            return;
+        };
+        let current = match field {
+            FolderField::Exclude => settings.exclude.join(", "),
+            FolderField::DefaultTags => settings.default_tags.join(", "),
+        };
+
+        self.input = current;
+        self.editing = Some(field);
+    }
+
+    /// Stores the input line into the field being edited
+    pub fn commit_editing(&mut self) {
+        let Some(field) = self.editing.take() else {
+            return;
+        };
+
+        // "a, b ,c" -> ["a", "b", "c"]
+        let values: Vec<String> = self
+            .input
+            .split(',')
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .collect();
+
+        if let Some(settings) = self.selected_mut() {
+            match field {
+                FolderField::Exclude => settings.exclude = values,
+                FolderField::DefaultTags => settings.default_tags = values,
+            }
+        }
+
+        self.input.clear();
+        self.dirty = true;
+    }
+}
+
 impl TuiState {
     /// Creates a new TUI state with initial data
     ///
     /// # Arguments
     /// * `books` - Initial list of books to display
     /// * `scan_paths` - Paths that were scanned to find these books
+    /// * `profile_name` - Name of the library profile that is open
+    /// * `settings` - User settings loaded from the config file
     ///
     /// # Returns
     /// A fully initialized TuiState ready to use
-    pub fn new(books: Vec<Book>, scan_paths: Vec<String>) -> Self {
-        Self {
+    pub fn new(
+        books: Vec<Book>,
+        scan_paths: Vec<String>,
+        profile_name: String,
+        settings: Settings,
+    ) -> Self {
+        let workers = Workers::new();
+        let parsing = ParseQueue::new(workers.sender());
+        let mut state = Self {
             books,
             selected_index: 0, // Start with first book selected
             should_quit: false, // Don't quit yet!
             scan_paths,
             mode: UiMode::Normal, // Start in normal mode
             browser: FileBrowser::new(), // Initialize file browser
+            profile_name,
+            profile_picker: ProfilePicker::new(),
+            settings,
+            status_message: None,
+            log: LogBuffer::default(),
+            log_scroll: 0,
+            folder_screen: FolderScreen::new(),
+            dirty: false,
+            view: Vec::new(),
+            view_stale: false,
+            collections: Vec::new(),
+            smart_collections: Vec::new(),
+            shelf: Shelf::All,
+            shelf_filter: None,
+            filter_text: String::new(),
+            filter: Filter::default(),
+            index: BookIndex::new(),
+            filter_input: String::new(),
+            starred_only: false,
+            status_filter: None,
+            sort: SortOrder::default(),
+            show_archived: false,
+            collection_picker: CollectionPicker::new(),
+            author_screen: AuthorScreen::new(),
+            marked: BTreeSet::new(),
+            bulk_edit: BulkEdit::new(),
+            metadata_form: None,
+            journal: Journal::default(),
+            metadata_edits: BTreeSet::new(),
+            metadata_review: None,
+            font_list: None,
+            listening: None,
+            health_screen: HealthScreen::new(),
+            statistics: None,
+            duplicates_screen: DuplicatesScreen::default(),
+            move_review: None,
+            library_merge: None,
+            peers: Vec::new(),
+            peers_screen: PeersScreen { selected_index: 0 },
+            peer_browser: None,
+            incoming_offers: Vec::new(),
+            trusted: Vec::new(),
+            pairings: Vec::new(),
+            policy_editor: None,
+            access_requests: Vec::new(),
+            workers,
+            parsing,
+            ticks: 0,
+            transfers: Transfers::new(),
+            transfers_screen: TransfersScreen { selected_index: 0 },
+            share: None,
+            email_input: String::new(),
+            cover_input: String::new(),
+            join_input: String::new(),
+            convert_index: 0,
+            calibre_browser: None,
+            feed_browser: None,
+            reader: None,
+            previewing: false,
+            preview: None,
+            quotes_screen: QuotesScreen::default(),
+            description_quote: None,
+            typography: Typography::default(),
+            graphics: None,
+            text_search_input: String::new(),
+            text_search: None,
+            devices_screen: DevicesScreen {
+                devices: Vec::new(),
+                selected_index: 0,
+            },
+        };
+        state.refresh_view();
+        state
+    }
+
+    /// Rebuilds the list of shown books after books, filters or order changed
+    ///
+    /// The selected book stays selected if it's still shown; otherwise the
+    /// selection is kept inside the list.
+    #[cfg_attr(feature = "profiling", tracing::instrument(level = "trace", name = "refresh view", skip_all))]
+    pub fn refresh_view(&mut self) {
+        self.view_stale = false;
+        self.index.update(&self.books);
+        self.refilter();
+    }
+
+    /// Rebuilds the list of shown books after only filters or order changed
+    /// (e.g. as a filter is typed): the books aren't looked at for changes,
+    /// the index answers as it is
+    pub fn refilter(&mut self) {
+        let selected_path = self.selected_book().map(|book| book.path.clone());
+
+        // Hide archived books - unless asked to show them, or a filter is about them
+        let show_archived = self.show_archived
+            || self.filter.mentions_archived()
+            || self.shelf_filter.as_ref().is_some_and(Filter::mentions_archived);
+
+        // The books of the typed filter (found with the index), then those
+        // of the shown shelf that pass the starred and reading status filters
+        let books = &self.books;
+        let index = &self.index;
+        let shelf = &self.shelf;
+        let shelf_filter = self.shelf_filter.as_ref();
+        let starred_only = self.starred_only;
+        let status_filter = self.status_filter;
+        let recent_since = crate::book::unix_now().saturating_sub(RECENT_DAYS * 86_400);
+        self.view = index
+            .matching(&self.filter, books)
+            .into_iter()
+            .map(|position| (position, &books[position]))
+            .filter(|(_, book)| show_archived || !book.user.archived)
+            .filter(|&(position, book)| match shelf {
+                Shelf::All => true,
+                Shelf::Collection(name) => book.user.collections.contains(name),
+                // Smart collections are evaluated every time, so they stay up to date
+                Shelf::Smart(_) => match shelf_filter {
+                    Some(f) => index.matches(f, position, book),
+                    None => true,
+                },
+                Shelf::RecentlyAdded => book.added.is_some_and(|added| added >= recent_since),
+                Shelf::RecentlyOpened => book.opened.is_some(),
+                Shelf::Series(name) => book.meta.series.as_ref() == Some(name),
+            })
+            .filter(|(_, book)| !starred_only || book.user.starred)
+            .filter(|(_, book)| status_filter.is_none() || book.user.status == status_filter)
+            .map(|(position, _)| position)
+            .collect();
+
+        // Stable sort: books that compare equal keep their library order
+        let sort = self.sort;
+        self.view.sort_by(|&a, &b| index.compare(sort, a, b, books));
+
+        if let Some(path) = selected_path {
+            self.select_path(&path);
+        }
+        if self.selected_index >= self.view.len() {
+            self.selected_index = self.view.len().saturating_sub(1);
+        }
+    }
+
+    /// Marks the list of shown books out of date without rebuilding it:
+    /// for changes that come in bursts (e.g. the books read in the
+    /// background), so the list is filtered and sorted once for all of them
+    pub fn invalidate_view(&mut self) {
+        self.view_stale = true;
+    }
+
+    /// Rebuilds the list of shown books if it's out of date (the main loop
+    /// calls it before handling keys and before drawing)
+    pub fn update_view(&mut self) {
+        if self.view_stale {
+            self.refresh_view();
+        }
+    }
+
+    /// Whether something on the screen changes by itself - the spinner of
+    /// the work in the background, transfers, the narration's position, a
+    /// share's countdown - so the main loop draws it on every tick
+    pub fn animating(&self) -> bool {
+        self.workers.running() > 0
+            || self.parsing.pending_count() > 0
+            || self.transfers.active_count() > 0
+            || (self.mode == UiMode::Listening && self.listening.is_some())
+            || (self.mode == UiMode::Sharing && self.share.is_some())
+    }
+
+    /// The next book to read in the shown series: the first one in reading
+    /// order that isn't finished or abandoned
+    ///
+    /// # Returns
+    /// Index into `books`, or None when no series is shown or it was all read
+    pub fn next_in_series(&self) -> Option<usize> {
+        if !matches!(self.shelf, Shelf::Series(_)) {
+            return None;
+        }
+        self.view.iter().copied().find(|&index| {
+            !matches!(
+                self.books[index].user.status,
+                Some(ReadingStatus::Finished) | Some(ReadingStatus::Abandoned)
+            )
+        })
+    }
+
+    /// Entries of the collection picker for its current purpose
+    ///
+    /// Books can only be added to regular collections, so "All books" and
+    /// the smart collections are only offered when choosing what to show.
+    pub fn collection_entries(&self) -> Vec<Shelf> {
+        let regular = self.collections.iter().cloned().map(Shelf::Collection);
+        match self.collection_picker.purpose {
+            CollectionPurpose::Show => [Shelf::All, Shelf::RecentlyAdded, Shelf::RecentlyOpened]
+                .into_iter()
+                .chain(regular)
+                .chain(
+                    self.smart_collections
+                        .iter()
+                        .map(|smart| Shelf::Smart(smart.name.clone())),
+                )
+                .collect(),
+            CollectionPurpose::AddBook => regular.collect(),
+        }
+    }
+
+    /// Shows a shelf in the book list, starting from its first book
+    ///
+    /// A smart collection also brings its sort order, the recent views sort
+    /// newest first and a series view sorts in reading order. A shelf that no longer exists (or a smart collection
+    /// with a broken filter) shows all books.
+    pub fn show_shelf(&mut self, shelf: Shelf) {
+        self.shelf_filter = None;
+        self.shelf = match shelf {
+            Shelf::Collection(name) if !self.collections.contains(&name) => Shelf::All,
+            Shelf::Smart(name) => {
+                let smart = self.smart_collections.iter().find(|s| s.name == name);
+                match smart.map(|s| (Filter::parse(&s.query), s.sort)) {
+                    Some((Ok(filter), sort)) => {
+                        self.shelf_filter = Some(filter);
+                        self.sort = sort;
+                        Shelf::Smart(name)
+                    }
+                    Some((Err(e), _)) => {
+                        self.status_message = Some(format!("Smart collection '{}': {}", name, e));
+                        Shelf::All
+                    }
+                    None => Shelf::All,
+                }
+            }
+            Shelf::RecentlyAdded => {
+                self.sort = SortOrder::Added;
+                Shelf::RecentlyAdded
+            }
+            Shelf::RecentlyOpened => {
+                self.sort = SortOrder::Opened;
+                Shelf::RecentlyOpened
+            }
+            Shelf::Series(name) => {
+                self.sort = SortOrder::Series;
+                Shelf::Series(name)
+            }
+            shelf => shelf,
+        };
+        self.selected_index = 0;
+        self.refresh_view();
+    }
+
+    /// Name of the shown collection, smart collection or view (None = all books)
+    pub fn shelf_name(&self) -> Option<&str> {
+        match &self.shelf {
+            Shelf::All => None,
+            Shelf::Collection(name) | Shelf::Smart(name) | Shelf::Series(name) => Some(name),
+            Shelf::RecentlyAdded => Some("Recently added"),
+            Shelf::RecentlyOpened => Some("Recently opened"),
+        }
+    }
+
+    /// The filters in effect, written as one filter expression
+    ///
+    /// Used to save them as a smart collection: the typed filter plus the
+    /// shown collection and the starred / reading status toggles.
+    pub fn current_query(&self) -> String {
+        let mut terms = Vec::new();
+        match &self.shelf {
+            Shelf::All => {}
+            Shelf::Collection(name) => terms.push(format!("collection:\"{}\"", name)),
+            Shelf::RecentlyAdded => terms.push(format!("added:<{}d", RECENT_DAYS)),
+            Shelf::RecentlyOpened => terms.push("opened:any".to_string()),
+            Shelf::Series(name) => terms.push(format!("series:\"{}\"", name)),
+            Shelf::Smart(name) => {
+                if let Some(smart) = self.smart_collections.iter().find(|s| s.name == *name) {
+                    terms.push(smart.query.clone());
+                }
+            }
+        }
+        if self.starred_only {
+            terms.push("starred".to_string());
+        }
+        if let Some(status) = self.status_filter {
+            terms.push(format!("status:{}", status.key()));
+        }
+        if !self.filter_text.trim().is_empty() {
+            terms.push(self.filter_text.trim().to_string());
+        }
+        terms.join(" ")
+    }
+
+    /// Reads the first pages of the selected book for the details pane,
+    /// when they're shown and it isn't the book they were read from (the
+    /// main loop calls it before drawing)
+    pub fn load_preview(&mut self) {
+        if !self.previewing {
+            return;
+        }
+        let Some(book) = self.selected_book() else {
+            self.preview = None;
+            return;
+        };
+        if self.preview.as_ref().is_some_and(|preview| preview.path == book.path) {
+            return;
         }
+
+        let blocks = if crate::remote::is_remote(&book.path) {
+            Err("The book is on a server: download it to see its first pages".to_string())
+        } else {
+            Document::open(&book.path).map_err(|e| format!("Cannot read the text: {}", e)).map(|document| {
+                let mut length = 0;
+                document
+                    .chapters
+                    .into_iter()
+                    .flat_map(|chapter| chapter.blocks)
+                    .take_while(|block| {
+                        let more = length < PREVIEW_CHARS;
+                        length += block.text().chars().count();
+                        more
+                    })
+                    .collect()
+            })
+        };
+        self.preview = Some(Preview {
+            path: book.path.clone(),
+            blocks,
+        });
     }
 
     /// Gets a reference to the currently selected book (if any)
@@ -217,7 +2264,438 @@ impl TuiState {
     /// Some(&Book) if a valid book is selected, None if list is empty or index invalid
     pub fn selected_book(&self) -> Option<&Book> {
         // get() returns Option<&T> - safe indexing that returns None if out of bounds
-        self.books.get(self.selected_index)
+        // The ? operator returns None early if nothing is shown at that position
+        let index = *self.view.get(self.selected_index)?;
+        self.books.get(index)
+    }
+
+    /// Gets a mutable reference to the currently selected book (if any)
+    pub fn selected_book_mut(&mut self) -> Option<&mut Book> {
+        let index = *self.view.get(self.selected_index)?;
+        self.books.get_mut(index)
+    }
+
+    /// Marks or unmarks the selected book for bulk operations
+    pub fn toggle_mark(&mut self) {
+        if let Some(path) = self.selected_book().map(|book| book.path.clone()) {
+            if !self.marked.remove(&path) {
+                self.marked.insert(path);
+            }
+        }
+    }
+
+    /// Books a bulk operation works on: the marked books, or the selected
+    /// book when none are marked
+    ///
+    /// # Returns
+    /// Indices into `books`
+    pub fn target_indices(&self) -> Vec<usize> {
+        if self.marked.is_empty() {
+            return self.view.get(self.selected_index).copied().into_iter().collect();
+        }
+        (0..self.books.len())
+            .filter(|&i| self.marked.contains(&self.books[i].path))
+            .collect()
+    }
+
+    /// Applies the bulk edit form to the target books in one go
+    ///
+    /// The edit is recorded in the journal as one operation, so the whole
+    /// edit can be undone at once.
+    ///
+    /// # Returns
+    /// How many books changed
+    pub fn apply_bulk_edit(&mut self) -> usize {
+        let mut before = Vec::new();
+        let mut after = Vec::new();
+        for index in self.target_indices() {
+            let original = self.books[index].clone();
+            if self.bulk_edit.apply(&mut self.books[index]) {
+                before.push(original);
+                after.push(self.books[index].clone());
+            }
+        }
+
+        let changed = before.len();
+        if changed > 0 {
+            self.record_edit(format!("bulk edit of {} books", changed), before, after);
+            self.dirty = true;
+            self.refresh_view();
+        }
+        changed
+    }
+
+    /// Saves the metadata form into its book if every field is valid
+    /// (recorded in the journal, so it can be undone)
+    ///
+    /// # Returns
+    /// Whether the form was saved; if not, the cursor is on the invalid
+    /// field and the form says what's wrong with it
+    pub fn save_metadata_form(&mut self) -> bool {
+        let Some(form) = self.metadata_form.as_mut() else {
+            return false;
+        };
+        let values = match form.validate() {
+            Ok(values) => values,
+            Err((field, error)) => {
+                form.field = field;
+                form.error = Some(error);
+                return false;
+            }
+        };
+        let Some(form) = self.metadata_form.take() else {
+            return false;
+        };
+
+        let description = format!("edit of {}", values.title);
+        self.edit_book(&form.path, &description, |book| MetadataForm::apply(values, book));
+        true
+    }
+
+    /// Applies a reviewed library merge and records it in the journal
+    ///
+    /// Added books can't be undone (they'd have to be removed by hand), the
+    /// user data changes can.
+    ///
+    /// # Returns
+    /// How many books were updated and added
+    pub fn apply_library_merge(&mut self, merge: LibraryMerge) -> (usize, usize) {
+        let paths: Vec<PathBuf> = merge.plan.updates.iter().map(|u| u.path.clone()).collect();
+        let before: Vec<Book> = self.books.iter().filter(|b| paths.contains(&b.path)).cloned().collect();
+
+        let (updated, added) = crate::merge::apply(&mut self.books, merge.plan);
+
+        let after: Vec<Book> = before
+            .iter()
+            .filter_map(|old| self.books.iter().find(|b| b.path == old.path).cloned())
+            .collect();
+        if updated > 0 {
+            self.record_edit(format!("merge of {}", merge.source), before, after);
+        }
+        if updated + added > 0 {
+            self.dirty = true;
+            self.refresh_view();
+        }
+        (updated, added)
+    }
+
+    /// Our pairing with a peer, if we paired with it
+    pub fn trusted_peer(&self, peer: &Peer) -> Option<&TrustedPeer> {
+        let instance = peer.instance.as_deref()?;
+        self.trusted.iter().find(|trusted| trusted.id == instance)
+    }
+
+    /// Applies the user data a syncing peer settled and records it in the journal
+    ///
+    /// # Returns
+    /// How many books were updated
+    pub fn apply_sync_push(&mut self, push: SyncPush) -> usize {
+        let mut before = Vec::new();
+        let mut after = Vec::new();
+        for pushed in push.books {
+            let Some(book) = self.books.iter_mut().find(|b| b.path == pushed.path) else {
+                continue;
+            };
+            // The metadata of a copy not read over there is no edit
+            let meta = if pushed.is_placeholder() { book.meta.clone() } else { pushed.meta };
+            if book.user == pushed.user && book.meta == meta {
+                continue;
+            }
+            before.push(book.clone());
+            book.user = pushed.user;
+            book.meta = meta;
+            // Their stamp, so the change isn't taken for one of ours
+            book.modified = pushed.modified;
+            after.push(book.clone());
+        }
+
+        let updated = after.len();
+        if updated > 0 {
+            self.record_edit(format!("sync with {}", push.from), before, after);
+            self.dirty = true;
+            self.refresh_view();
+        }
+        updated
+    }
+
+    /// Edits one book (by path) and records the edit in the journal
+    ///
+    /// # Arguments
+    /// * `path` - Path of the book
+    /// * `description` - What the edit is, for undo messages
+    /// * `edit` - Changes the book
+    ///
+    /// # Returns
+    /// Whether the book was found
+    pub fn edit_book(&mut self, path: &Path, description: &str, edit: impl FnOnce(&mut Book)) -> bool {
+        let Some(book) = self.books.iter_mut().find(|b| b.path == path) else {
+            return false;
+        };
+        let before = book.clone();
+        edit(book);
+        let after = book.clone();
+
+        self.record_edit(description.to_string(), vec![before], vec![after]);
+        self.dirty = true;
+        self.refresh_view();
+        true
+    }
+
+    /// Records an edit of books in the journal, noting the books whose
+    /// title, authors or series it changed (and touching them in the index)
+    fn record_edit(&mut self, description: String, before: Vec<Book>, after: Vec<Book>) {
+        for (old, new) in before.iter().zip(&after) {
+            if old.written_metadata() != new.written_metadata() {
+                self.metadata_edits.insert(new.path.clone());
+            }
+            self.index.touch(&new.path);
+        }
+        self.journal.record(Operation::Edit { description, before, after });
+    }
+
+    /// Removes books from the library (their files are left alone),
+    /// recording the removal in the journal
+    pub fn remove_books(&mut self, paths: &[PathBuf], description: String) {
+        let removed: Vec<(usize, Book)> = self
+            .books
+            .iter()
+            .enumerate()
+            .filter(|(_, book)| paths.contains(&book.path))
+            .map(|(index, book)| (index, book.clone()))
+            .collect();
+        if removed.is_empty() {
+            return;
+        }
+
+        self.books.retain(|book| !paths.contains(&book.path));
+        for path in paths {
+            self.marked.remove(path);
+        }
+        self.journal.record(Operation::Remove {
+            description,
+            books: removed,
+        });
+        self.dirty = true;
+        self.refresh_view();
+    }
+
+    /// Points books to the files they were moved to, recording the moves
+    /// in the journal
+    ///
+    /// # Arguments
+    /// * `moves` - Moves that were done
+    /// * `description` - What the moves were, for undo messages
+    pub fn apply_moves(&mut self, moves: Vec<Move>, description: String) {
+        for moved in &moves {
+            crate::organize::repoint(&mut self.books, &moved.from, &moved.to);
+            if self.marked.remove(&moved.from) {
+                self.marked.insert(moved.to.clone());
+            }
+        }
+        self.journal.record(Operation::Move { description, moves });
+        self.dirty = true;
+        self.refresh_view();
+    }
+
+    /// Undoes (or redoes) the newest operation of the journal
+    ///
+    /// # Returns
+    /// A message saying what happened, for the footer
+    pub fn undo_redo(&mut self, redo: bool) -> String {
+        // Compared after, for the metadata edits undone or redone
+        let before: HashMap<PathBuf, Metadata> = self.books.iter().map(|book| (book.path.clone(), book.written_metadata())).collect();
+        let result = if redo {
+            self.journal.redo(&mut self.books)
+        } else {
+            self.journal.undo(&mut self.books)
+        };
+        let verb = if redo { "Redid" } else { "Undid" };
+
+        match result {
+            None => format!("Nothing to {}", if redo { "redo" } else { "undo" }),
+            Some(Ok(description)) => {
+                tracing::info!(operation = %description, redo, "journal operation reverted");
+                for book in &self.books {
+                    if before.get(&book.path).is_some_and(|meta| *meta != book.written_metadata()) {
+                        self.metadata_edits.insert(book.path.clone());
+                    }
+                }
+                // Any book of the operation may be back as it was
+                self.index.touch_all();
+                self.dirty = true;
+                self.refresh_view();
+                format!("{} {}", verb, description)
+            }
+            Some(Err(e)) => {
+                tracing::error!(error = %e, redo, "cannot undo/redo");
+                format!("Cannot {}: {}", if redo { "redo" } else { "undo" }, e)
+            }
+        }
+    }
+
+    /// The book read last in the built-in reader, to continue (None if none
+    /// was, or the ones that were are on a server now)
+    pub fn continue_reading_book(&self) -> Option<usize> {
+        self.books
+            .iter()
+            .enumerate()
+            .filter(|(_, book)| !crate::remote::is_remote(&book.path))
+            .filter_map(|(index, book)| Some((index, book.user.position.as_ref()?.read_at)))
+            .max_by_key(|&(_, read_at)| read_at)
+            .map(|(index, _)| index)
+    }
+
+    /// Shows the controls of a player just started, and keeps its book's
+    /// listening position from now on
+    pub fn start_listening(&mut self, player: Player) {
+        self.listening = Some(Listening {
+            player,
+            status: None,
+            polled: None,
+        });
+        self.poll_listening();
+        self.mode = UiMode::Listening;
+    }
+
+    /// Puts the books on the screen first in the parse queue: the selected
+    /// book, then the top of the list (the main loop calls it before drawing)
+    ///
+    /// # Arguments
+    /// * `rows` - Height of the terminal (at most as many books are shown)
+    pub fn prioritize_parsing(&mut self, rows: u16) {
+        if self.parsing.pending_count() == 0 {
+            return;
+        }
+        let shown: Vec<PathBuf> = self
+            .selected_book()
+            .into_iter()
+            .chain(self.view.iter().take(rows as usize).map(|&index| &self.books[index]))
+            .filter(|book| self.parsing.is_pending(&book.path))
+            .map(|book| book.path.clone())
+            .collect();
+        self.parsing.prioritize(shown);
+    }
+
+    /// Fills in the placeholders whose metadata was read in the background
+    ///
+    /// # Arguments
+    /// * `read` - The books read (AppEvent::Parsed)
+    /// * `aliases` - The library's merged author spellings, for the authors read
+    #[cfg_attr(feature = "profiling", tracing::instrument(level = "trace", name = "fill in", skip_all))]
+    pub fn fill_in(&mut self, read: Vec<Book>, aliases: &BTreeMap<String, String>) {
+        let read: Vec<Book> = read.into_iter().filter(|book| self.parsing.finish(&book.path)).collect();
+        if read.is_empty() {
+            return;
+        }
+        let positions: HashMap<&Path, usize> = self.books.iter().enumerate().map(|(index, book)| (book.path.as_path(), index)).collect();
+        let positions: Vec<(usize, Book)> = read
+            .into_iter()
+            .filter_map(|parsed| Some((*positions.get(parsed.path.as_path())?, parsed)))
+            .collect();
+        for (index, parsed) in positions {
+            // Left alone if it was edited (or read again) in the meantime
+            let book = &mut self.books[index];
+            if !book.is_placeholder() {
+                continue;
+            }
+            book.meta = parsed.meta;
+            book.detected_language = parsed.detected_language;
+            book.readability = parsed.readability;
+            crate::authors::apply_aliases(std::slice::from_mut(book), aliases);
+            self.index.touch(&book.path);
+        }
+        self.dirty = true;
+        // Their titles and authors may move them in the list (or into a filter)
+        self.invalidate_view();
+    }
+
+    /// Asks the player where it is (at most every LISTENING_POLL; the main
+    /// loop calls it before drawing) and keeps that as the book's listening
+    /// position; forgets the player when it has stopped
+    pub fn poll_listening(&mut self) {
+        let Some(listening) = self.listening.as_mut() else {
+            return;
+        };
+        if listening.polled.is_some_and(|polled| polled.elapsed() < LISTENING_POLL) {
+            return;
+        }
+        listening.polled = Some(Instant::now());
+
+        if !listening.player.is_running() {
+            // The end of the last file, or the player closed by hand
+            let title = self.books.iter().find(|book| book.path == listening.player.book).map(|book| book.display_title().to_string());
+            self.status_message = Some(format!("Stopped listening to {}", title.unwrap_or_default()));
+            self.listening = None;
+            if self.mode == UiMode::Listening {
+                self.mode = UiMode::Normal;
+            }
+            return;
+        }
+        let Ok(status) = listening.player.status() else {
+            return;
+        };
+        listening.status = Some(status);
+
+        let (track, seconds, tracks) = (status.track, status.seconds as u32, listening.player.tracks);
+        let Some(book) = self.books.iter_mut().find(|book| book.path == listening.player.book) else {
+            return;
+        };
+        if book.user.listening.as_ref().is_some_and(|at| (at.track, at.seconds) == (track, seconds)) {
+            return;
+        }
+        book.user.listening = Some(ListeningPosition {
+            track,
+            seconds,
+            tracks,
+            listened_at: crate::book::unix_now(),
+        });
+        self.dirty = true;
+    }
+
+    /// Asks the player where it is without waiting for the next poll
+    pub fn poll_listening_now(&mut self) {
+        if let Some(listening) = self.listening.as_mut() {
+            listening.polled = None;
+        }
+        self.poll_listening();
+    }
+
+    /// Stops the narration playing, keeping where it was
+    pub fn stop_listening(&mut self) {
+        self.poll_listening_now();
+        if let Some(listening) = self.listening.take() {
+            listening.player.stop();
+        }
+    }
+
+    /// Keeps the page the reader shows as its book's reading position
+    /// (only marks the library dirty when the page changed)
+    pub fn remember_reading_position(&mut self) {
+        let Some(reader) = &self.reader else {
+            return;
+        };
+        let (chapter, block) = reader.position();
+        let percent = reader.progress() as u8;
+        let Some(book) = self.books.iter_mut().find(|book| book.path == reader.path) else {
+            return;
+        };
+        if book.user.position.as_ref().is_some_and(|p| (p.chapter, p.block, p.percent) == (chapter, block, percent)) {
+            return;
+        }
+        book.user.position = Some(ReadingPosition {
+            chapter,
+            block,
+            percent,
+            read_at: crate::book::unix_now(),
+        });
+        self.dirty = true;
+    }
+
+    /// Selects the book with the given path (keeps the selection if it isn't shown)
+    pub fn select_path(&mut self, path: &Path) {
+        if let Some(position) = self.view.iter().position(|&i| self.books[i].path == path) {
+            self.selected_index = position;
+        }
     }
 
     /// Moves the book selection cursor up by one
@@ -231,8 +2709,8 @@ impl TuiState {
     /// Moves the book selection cursor down by one
     /// Does nothing if already at the bottom of the list
     pub fn move_selection_down(&mut self) {
-        // saturating_sub(1) returns 0 if books is empty, preventing underflow
-        if self.selected_index < self.books.len().saturating_sub(1) {
+        // saturating_sub(1) returns 0 if the list is empty, preventing underflow
+        if self.selected_index < self.view.len().saturating_sub(1) {
             self.selected_index += 1;
         }
     }
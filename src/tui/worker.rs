@@ -0,0 +1,258 @@
+// src/tui/worker.rs
+// Work the TUI does in the background, and the events it reports with
+//
+// Whatever can take long - scanning folders, hashing files, asking a
+// server or a peer, reading packages (see parse_queue.rs) - runs on a
+// thread of its own, which sends its result back as an AppEvent. The
+// terminal's events - keys, resizes, pasted text - come down the same
+// channel (an input thread reads them), and so does a tick every TICK for
+// what's done periodically (watchers, autosave, the spinner). The main loop
+// waits on that one queue and the interface keeps answering while the work
+// goes on: the results are applied to the state as they arrive.
+
+use crate::book::{Book, Metadata};
+use crate::calibre::Library;
+use crate::discovery::Peer;
+use crate::feeds::FeedEntry;
+use crate::health::Relocation;
+use crate::peer::Catalog;
+use crate::sync::Partner;
+use crate::trust::{Pairing, Secret};
+use crossterm::event::{Event, KeyEvent};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How often the main loop gets a Tick
+pub const TICK: Duration = Duration::from_millis(200);
+
+/// Something the main loop has to handle
+pub enum AppEvent {
+    /// A key was pressed
+    Key(KeyEvent),
+
+    /// The terminal was resized (columns, rows)
+    Resize(u16, u16),
+
+    /// Text was pasted into the terminal (bracketed paste)
+    Paste(String),
+
+    /// TICK went by
+    Tick,
+
+    /// The package of a placeholder book was read (see parse_queue.rs)
+    Parsed(Box<Book>),
+
+    /// Folders were scanned
+    Scanned(Scan),
+
+    /// Files with the content of missing books were found (by hash)
+    MovesFound(Vec<Relocation>),
+
+    /// The providers answered about a book
+    MetadataLookedUp(Box<Lookup>),
+
+    /// A peer answered with its catalog (or didn't)
+    PeerCatalog(Box<PeerReply>),
+
+    /// The Calibre server listed its books
+    CalibreListed(Result<Library, String>),
+
+    /// The subscribed feeds were fetched (the entries of those that
+    /// answered, and what went wrong with the others)
+    FeedsFetched { entries: Vec<FeedEntry>, failed: Vec<String> },
+
+    /// Another library was read, to merge or sync with it
+    LibraryRead(Box<OtherLibrary>),
+
+    /// The peer side of a sync was done (offer sent, user data pushed)
+    SyncSent(Box<SyncSent>),
+
+    /// A peer answered a request to pair (with the PIN to compare)
+    PairingStarted { peer: String, result: Result<Pairing, String> },
+
+    /// A peer answered an offer of some books
+    OfferSent { peer: String, books: usize, result: Result<(), String> },
+
+    /// A new cover was fetched and embedded into a book (or wasn't)
+    CoverSet { path: PathBuf, title: String, result: Result<(), String> },
+}
+
+/// A book looked up online
+pub struct Lookup {
+    /// The book looked up
+    pub path: PathBuf,
+
+    /// Its metadata when it was looked up, to compare the answer with
+    pub meta: Metadata,
+
+    /// What providers::lookup answered
+    pub result: Result<Option<(&'static str, Metadata)>, String>,
+}
+
+/// A peer's catalog, asked for to browse it
+pub struct PeerReply {
+    pub peer: Peer,
+    pub address: SocketAddr,
+
+    /// The pair key the catalog was asked with, for the downloads
+    pub secret: Option<Secret>,
+
+    /// What peer::fetch_catalog answered
+    pub result: Result<Catalog, String>,
+}
+
+/// Another library, read to compare it with the open one
+pub struct OtherLibrary {
+    pub reason: ReadReason,
+
+    /// Its books
+    pub result: Result<Vec<Book>, String>,
+
+    /// Hashes of our books computed meanwhile (path, hash), which the
+    /// books are matched by
+    pub hashes: Vec<(PathBuf, String)>,
+}
+
+/// Why another library was read
+pub enum ReadReason {
+    /// To review a merge of it (`tui --merge`)
+    Merge(PathBuf),
+
+    /// To sync with it
+    Sync(Partner),
+}
+
+/// What the peer side of a sync did, for the summary
+pub struct SyncSent {
+    pub partner: Partner,
+
+    /// When our side was stamped (see database::stamp_changes): the time
+    /// the sync is remembered with
+    pub time: u64,
+
+    /// Books queued for download, books offered, books with user data pushed
+    pub received: usize,
+    pub sent: usize,
+    pub pushed: usize,
+
+    /// What went wrong
+    pub problems: Vec<String>,
+}
+
+/// Why folders were scanned, i.e. what's done with the books found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanReason {
+    /// A watched folder's files changed
+    Watched,
+
+    /// Folder settings (excludes, default tags) were saved
+    FolderSettings,
+
+    /// A folder was added: its books become the library, if it has any
+    NewFolder,
+
+    /// The health report asked for it: the books are read at once (the
+    /// check needs their metadata), then the files are checked
+    Health,
+}
+
+/// Folders scanned, each with its books
+pub struct Scan {
+    pub reason: ScanReason,
+
+    /// Each folder, with the books found (None = a server that can't be
+    /// reached: its books are kept)
+    pub roots: Vec<(PathBuf, Option<Vec<Book>>)>,
+}
+
+/// The channel of the main loop, and the threads sending to it
+pub struct Workers {
+    sender: Sender<AppEvent>,
+    events: Receiver<AppEvent>,
+
+    /// Threads started with `spawn` that haven't sent their result yet
+    running: Arc<AtomicUsize>,
+}
+
+impl Workers {
+    /// A channel with no thread sending to it yet
+    pub fn new() -> Self {
+        let (sender, events) = channel();
+        Self {
+            sender,
+            events,
+            running: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Where threads send their events (e.g. the parse queue's)
+    pub fn sender(&self) -> Sender<AppEvent> {
+        self.sender.clone()
+    }
+
+    /// Starts the thread reading the terminal's events (once the terminal
+    /// is in raw mode; it reads until the app quits) and the one ticking
+    pub fn read_input(&self) {
+        let sender = self.sender();
+        std::thread::spawn(move || loop {
+            let event = match crossterm::event::read() {
+                Ok(Event::Key(key)) => AppEvent::Key(key),
+                Ok(Event::Resize(columns, rows)) => AppEvent::Resize(columns, rows),
+                Ok(Event::Paste(text)) => AppEvent::Paste(text),
+                Ok(_) => continue,
+                Err(e) => {
+                    tracing::error!(error = %e, "cannot read the terminal's input");
+                    return;
+                }
+            };
+            if sender.send(event).is_err() {
+                return;
+            }
+        });
+
+        let sender = self.sender();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(TICK);
+            if sender.send(AppEvent::Tick).is_err() {
+                return;
+            }
+        });
+    }
+
+    /// Runs some work on a thread of its own, its result sent to the main loop
+    pub fn spawn(&self, work: impl FnOnce() -> AppEvent + Send + 'static) {
+        let sender = self.sender();
+        let running = self.running.clone();
+        running.fetch_add(1, Ordering::Relaxed);
+        std::thread::spawn(move || {
+            let event = work();
+            running.fetch_sub(1, Ordering::Relaxed);
+            let _ = sender.send(event);
+        });
+    }
+
+    /// How many threads started with `spawn` are still working
+    pub fn running(&self) -> usize {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// Waits for the next event (there's one every TICK at the latest),
+    /// then takes the ones already waiting behind it too, so a burst is
+    /// handled in one pass
+    pub fn wait(&self) -> Vec<AppEvent> {
+        let Ok(first) = self.events.recv() else {
+            return Vec::new();
+        };
+        std::iter::once(first).chain(self.events.try_iter()).collect()
+    }
+}
+
+impl Default for Workers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,132 @@
+// src/userdata.rs
+// Portable export/import of user data (tags, ratings...) keyed by content hash,
+// so curation survives moving files between folders or machines
+
+use crate::book::{Book, UserData};
+use crate::import::ImportSummary;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Value of the `format` field of a user-data file
+pub const USERDATA_FORMAT: &str = "funkhunt-userdata";
+
+/// Version of the user-data layout
+pub const USERDATA_VERSION: u32 = 1;
+
+/// Top-level document of a user-data file
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserDataExport {
+    /// Always USERDATA_FORMAT
+    pub format: String,
+
+    /// Layout version (USERDATA_VERSION at the time of writing)
+    pub version: u32,
+
+    /// When the export was made (seconds since the Unix epoch)
+    pub exported_at: u64,
+
+    /// One entry per book the user has touched
+    pub entries: Vec<UserDataEntry>,
+}
+
+/// User data of one book, identified by the hash of its content
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserDataEntry {
+    /// SHA-256 of the book file - the key used when importing
+    pub hash: String,
+
+    /// Filename at export time (only a hint for humans reading the file)
+    pub name: String,
+
+    /// Everything the user added to the book
+    pub user: UserData,
+}
+
+/// Exports the user data of every book that has some
+///
+/// Hashes are computed (and cached on the books) as needed, so this may
+/// take a while on a large library the first time.
+///
+/// # Arguments
+/// * `books` - The books of the library
+/// * `dest` - File to write the JSON document to
+///
+/// # Returns
+/// The number of exported entries
+pub fn export(books: &mut [Book], dest: &Path) -> io::Result<usize> {
+    let mut entries = Vec::new();
+
+    for book in books.iter_mut().filter(|b| !b.user.is_empty()) {
+        // Books whose file can't be read have no hash and can't be exported
+        if let Some(hash) = book.ensure_hash().map(str::to_string) {
+            entries.push(UserDataEntry {
+                hash,
+                name: book.name.clone(),
+                user: book.user.clone(),
+            });
+        }
+    }
+
+    let count = entries.len();
+    let document = UserDataExport {
+        format: USERDATA_FORMAT.to_string(),
+        version: USERDATA_VERSION,
+        exported_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        entries,
+    };
+
+    let json = serde_json::to_string_pretty(&document).map_err(io::Error::other)?;
+    std::fs::write(dest, json)?;
+
+    Ok(count)
+}
+
+/// Reattaches exported user data to the books of a library
+///
+/// Each library book is hashed and, if an entry with the same hash exists,
+/// its user data is replaced by the imported one.
+///
+/// # Arguments
+/// * `source` - A file written by `export`
+/// * `books` - The books of the library (modified in place)
+///
+/// # Returns
+/// Summary with `updated` (books that got data) and `unmatched` (entries without a book)
+pub fn import(source: &Path, books: &mut [Book]) -> io::Result<ImportSummary> {
+    let text = std::fs::read_to_string(source)?;
+    let document: UserDataExport = serde_json::from_str(&text).map_err(io::Error::other)?;
+
+    if document.format != USERDATA_FORMAT {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not a FunkHunt user-data file", source.display()),
+        ));
+    }
+
+    // Index entries by hash for quick lookup
+    let mut by_hash: HashMap<String, UserData> = document
+        .entries
+        .into_iter()
+        .map(|entry| (entry.hash, entry.user))
+        .collect();
+
+    let mut summary = ImportSummary::default();
+    for book in books.iter_mut() {
+        let user = book.ensure_hash().and_then(|hash| by_hash.remove(hash));
+        if let Some(user) = user {
+            book.user = user;
+            summary.updated += 1;
+        }
+    }
+
+    // Whatever is left had no matching file in this library
+    summary.unmatched = by_hash.len();
+
+    Ok(summary)
+}
@@ -0,0 +1,101 @@
+// src/watcher.rs
+// Watches auto-watch scan roots for file changes, so they can be rescanned automatically
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant};
+
+/// How long a folder must stay quiet before it's rescanned
+/// (copying a big file produces many events - wait until it's done)
+const QUIET_PERIOD: Duration = Duration::from_secs(1);
+
+/// Watches a set of scan roots and reports which ones changed
+pub struct FolderWatcher {
+    /// The OS watcher - kept alive for as long as we want events
+    /// None if no roots are watched (or the watcher couldn't be created)
+    _watcher: Option<RecommendedWatcher>,
+
+    /// Paths touched by file system events, sent from the watcher thread
+    events: Receiver<PathBuf>,
+
+    /// Watched roots
+    roots: Vec<PathBuf>,
+
+    /// Roots with unprocessed changes, and when their last event arrived
+    pending: HashMap<PathBuf, Instant>,
+}
+
+impl FolderWatcher {
+    /// Starts watching the given roots (recursively)
+    ///
+    /// Roots that can't be watched are logged and skipped.
+    pub fn new(roots: Vec<PathBuf>) -> Self {
+        let (sender, events) = channel();
+
+        let watcher = if roots.is_empty() {
+            None
+        } else {
+            // The callback runs on the watcher's own thread - just forward the paths
+            let handler = move |result: notify::Result<notify::Event>| match result {
+                Ok(event) => {
+                    for path in event.paths {
+                        let _ = sender.send(path);
+                    }
+                }
+                Err(e) => tracing::warn!(error = %e, "file watcher error"),
+            };
+
+            match notify::recommended_watcher(handler) {
+                Ok(mut watcher) => {
+                    for root in &roots {
+                        match watcher.watch(root, RecursiveMode::Recursive) {
+                            Ok(()) => tracing::info!(path = %root.display(), "watching folder"),
+                            Err(e) => tracing::warn!(path = %root.display(), error = %e, "cannot watch folder"),
+                        }
+                    }
+                    Some(watcher)
+                }
+                Err(e) => {
+                    tracing::warn!(error = %e, "cannot create file watcher");
+                    None
+                }
+            }
+        };
+
+        Self {
+            _watcher: watcher,
+            events,
+            roots,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Collects new events and returns the roots that changed and have been quiet since
+    ///
+    /// Call regularly (e.g. on every loop iteration); never blocks.
+    pub fn ready_roots(&mut self) -> Vec<PathBuf> {
+        let now = Instant::now();
+
+        // Drain the channel, remembering which root each event belongs to
+        while let Ok(path) = self.events.try_recv() {
+            if let Some(root) = self.roots.iter().find(|root| path.starts_with(root)) {
+                self.pending.insert(root.clone(), now);
+            }
+        }
+
+        // Hand out the roots whose last event is old enough
+        let ready: Vec<PathBuf> = self
+            .pending
+            .iter()
+            .filter(|(_, last_event)| now.duration_since(**last_event) >= QUIET_PERIOD)
+            .map(|(root, _)| root.clone())
+            .collect();
+        for root in &ready {
+            self.pending.remove(root);
+        }
+
+        ready
+    }
+}
@@ -0,0 +1,238 @@
+// src/webhooks.rs
+// Webhooks - library events POSTed as JSON to the URLs of [webhooks],
+// e.g. a Home Assistant automation or a Discord channel
+//
+// Events: a book was added, removed, finished (its status became Finished)
+// or received from a peer. Added, removed and finished are found by
+// comparing the library with what it was the last time it was checked: on
+// every pass of the main loop when the number of books (or of finished
+// ones) changed, which costs a count, and in full at every autosave. Books
+// received from peers are reported by the code that adds them (as
+// "received", not "added").
+//
+// Each event is one POST with a body like
+//   {"event": "finished", "library": "default", "title": "Dune",
+//    "authors": ["Frank Herbert"], "path": "/home/me/Books/dune.epub",
+//    "peer": null, "time": 1760000000,
+//    "content": "Finished reading Dune - Frank Herbert"}
+// where "content" is the message Discord shows. Requests are sent from a
+// thread of their own, so a slow server never blocks the interface.
+
+use crate::book::{Book, ReadingStatus};
+use crate::settings::WebhookSettings;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How long to wait for a webhook's server
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most events sent for a single check (a big rescan is summed up instead)
+const MAX_EVENTS: usize = 20;
+
+/// Something that happened to a book of the library
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LibraryEvent {
+    /// Found by a scan (or imported)
+    Added,
+
+    /// Its file is gone
+    Removed,
+
+    /// Its reading status became Finished
+    Finished,
+
+    /// Downloaded from a peer
+    Received,
+}
+
+impl LibraryEvent {
+    /// Name in [webhooks] events and in the payload, e.g. "finished"
+    pub fn key(self) -> &'static str {
+        match self {
+            LibraryEvent::Added => "added",
+            LibraryEvent::Removed => "removed",
+            LibraryEvent::Finished => "finished",
+            LibraryEvent::Received => "received",
+        }
+    }
+
+    /// Message for chat services, e.g. "Finished reading Dune - Frank Herbert"
+    fn message(self, book: &str, peer: Option<&str>) -> String {
+        match (self, peer) {
+            (LibraryEvent::Added, _) => format!("Added {}", book),
+            (LibraryEvent::Removed, _) => format!("Removed {}", book),
+            (LibraryEvent::Finished, _) => format!("Finished reading {}", book),
+            (LibraryEvent::Received, Some(peer)) => format!("Received {} from {}", book, peer),
+            (LibraryEvent::Received, None) => format!("Received {}", book),
+        }
+    }
+}
+
+/// Body of a webhook request
+#[derive(Debug, Clone, Serialize)]
+struct Payload {
+    event: &'static str,
+    library: String,
+    title: String,
+    authors: Vec<String>,
+    path: PathBuf,
+
+    /// Who sent the book (received events only)
+    peer: Option<String>,
+
+    /// When it happened (seconds since 1970)
+    time: u64,
+
+    /// Human-readable message (what Discord shows)
+    content: String,
+}
+
+/// Sends the webhooks of a library
+pub struct Webhooks {
+    /// The [webhooks] settings
+    settings: WebhookSettings,
+
+    /// Name of the library profile, sent with every event
+    library: String,
+
+    /// Books as of the last check: path -> whether it was finished
+    known: HashMap<PathBuf, bool>,
+
+    /// Book count and finished count as of the last check (a change means
+    /// the library must be compared again)
+    counts: (usize, usize),
+}
+
+impl Webhooks {
+    /// Starts watching a library (its current books raise no events)
+    ///
+    /// # Arguments
+    /// * `settings` - The [webhooks] settings
+    /// * `library` - Name of the library profile
+    /// * `books` - The books of the library
+    pub fn new(settings: &WebhookSettings, library: &str, books: &[Book]) -> Self {
+        Self {
+            settings: settings.clone(),
+            library: library.to_string(),
+            known: books.iter().map(|book| (book.path.clone(), is_finished(book))).collect(),
+            counts: counts(books),
+        }
+    }
+
+    /// Uses new settings (after config.toml changed)
+    pub fn configure(&mut self, settings: &WebhookSettings) {
+        self.settings = settings.clone();
+    }
+
+    /// Sends the events of what changed since the last check, if the
+    /// number of books or of finished ones changed (only counts otherwise)
+    pub fn check(&mut self, books: &[Book]) {
+        if counts(books) != self.counts {
+            self.compare(books);
+        }
+    }
+
+    /// Sends the events of what changed since the last check, comparing
+    /// every book (catches a book added while another was removed)
+    pub fn compare(&mut self, books: &[Book]) {
+        self.counts = counts(books);
+
+        let mut events = Vec::new();
+        let mut current = HashMap::with_capacity(books.len());
+        for book in books {
+            let finished = is_finished(book);
+            match self.known.get(&book.path) {
+                None => events.push(self.payload(LibraryEvent::Added, book, None)),
+                Some(false) if finished => events.push(self.payload(LibraryEvent::Finished, book, None)),
+                _ => {}
+            }
+            current.insert(book.path.clone(), finished);
+        }
+        for path in self.known.keys().filter(|path| !current.contains_key(*path)) {
+            let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            events.push(Payload {
+                event: LibraryEvent::Removed.key(),
+                library: self.library.clone(),
+                title: name.clone(),
+                authors: Vec::new(),
+                path: path.clone(),
+                peer: None,
+                time: crate::book::unix_now(),
+                content: LibraryEvent::Removed.message(&name, None),
+            });
+        }
+        self.known = current;
+        self.send(events);
+    }
+
+    /// Reports a book received from a peer (it isn't reported as added)
+    ///
+    /// # Arguments
+    /// * `book` - The book, as added to the library
+    /// * `peer` - Who sent it (name or address)
+    pub fn received(&mut self, book: &Book, peer: &str) {
+        self.known.insert(book.path.clone(), is_finished(book));
+        let payload = self.payload(LibraryEvent::Received, book, Some(peer));
+        self.send(vec![payload]);
+    }
+
+    /// Describes an event of a book
+    fn payload(&self, event: LibraryEvent, book: &Book, peer: Option<&str>) -> Payload {
+        let name = match book.authors() {
+            [] => book.display_title().to_string(),
+            authors => format!("{} - {}", book.display_title(), authors.join(", ")),
+        };
+        Payload {
+            event: event.key(),
+            library: self.library.clone(),
+            title: book.display_title().to_string(),
+            authors: book.authors().to_vec(),
+            path: book.path.clone(),
+            peer: peer.map(str::to_string),
+            time: crate::book::unix_now(),
+            content: event.message(&name, peer),
+        }
+    }
+
+    /// POSTs events to every URL, from a thread of their own
+    fn send(&self, mut events: Vec<Payload>) {
+        let wanted = &self.settings.events;
+        events.retain(|event| wanted.is_empty() || wanted.iter().any(|w| w == event.event));
+        if events.is_empty() || self.settings.urls.is_empty() {
+            return;
+        }
+
+        // A whole folder appearing is one message, not hundreds
+        if events.len() > MAX_EVENTS {
+            let mut summary = events[0].clone();
+            summary.content = format!("{} ({} more changes)", summary.content, events.len() - 1);
+            tracing::info!(events = events.len(), "webhook events summed up");
+            events = vec![summary];
+        }
+
+        let urls = self.settings.urls.clone();
+        std::thread::spawn(move || {
+            let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+            for event in &events {
+                for url in &urls {
+                    match agent.post(url).send_json(event) {
+                        Ok(_) => tracing::debug!(url = %url, event = event.event, "webhook sent"),
+                        Err(e) => tracing::warn!(url = %url, event = event.event, error = %e, "webhook failed"),
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Whether a book's status is Finished
+fn is_finished(book: &Book) -> bool {
+    book.user.status == Some(ReadingStatus::Finished)
+}
+
+/// Number of books, and of finished ones
+fn counts(books: &[Book]) -> (usize, usize) {
+    (books.len(), books.iter().filter(|book| is_finished(book)).count())
+}